@@ -0,0 +1,72 @@
+//! Live count of the objects of each type, with a progress observer
+//!
+//! Collect a domain:
+//!
+//! ```text
+//! cargo run --example progress -- contoso.local dc01.contoso.local user Password123
+//! ```
+//!
+//! Or parse a `--dump` file again, without the DC:
+//!
+//! ```text
+//! cargo run --example progress -- contoso.local --from-dump contoso.jsonl
+//! ```
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use rusthound_ce::{set_progress_observer, Collector, Event, ProgressObserver};
+
+/// Count of each object type, printed on one line of stderr as it changes.
+#[derive(Default)]
+struct LiveCount {
+    counts: Mutex<BTreeMap<String, usize>>,
+}
+
+impl ProgressObserver for LiveCount {
+    fn on_phase(&self, phase: &str) {
+        eprintln!("\n[{phase}]");
+    }
+
+    fn on_object_parsed(&self, kind: &str, count: usize) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.insert(kind.to_string(), count);
+        let line: Vec<String> = counts.iter().map(|(kind, count)| format!("{kind}: {count}")).collect();
+        eprint!("\r{}", line.join("  "));
+        let _ = std::io::stderr().flush();
+    }
+
+    fn on_event(&self, event: &Event) {
+        if let Event::QueryFinished { naming_context, entries } = event {
+            eprintln!("{entries} entries in {naming_context}");
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let usage = "usage: progress <domain> <dc> <username> <password> | progress <domain> --from-dump <file>";
+    let domain = args.first().ok_or(usage)?;
+    set_progress_observer(Arc::new(LiveCount::default()));
+
+    let results = match args.get(1).map(String::as_str) {
+        Some("--from-dump") => {
+            let dump = args.get(2).ok_or(usage)?;
+            Collector::builder(domain).build().process_dump(dump).await?
+        }
+        Some(dc) => {
+            let (username, password) = args.get(2).zip(args.get(3)).ok_or(usage)?;
+            Collector::builder(domain)
+                .ldapfqdn(dc)
+                .credentials(username, password)
+                .build()
+                .collect()
+                .await?
+        }
+        None => return Err(usage.into()),
+    };
+    eprintln!("\n{} users, {} groups, {} computers", results.users.len(), results.groups.len(), results.computers.len());
+    Ok(())
+}
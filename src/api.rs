@@ -1,19 +1,42 @@
-use std::{collections::HashMap, error::Error};
+use std::{collections::HashMap, error::Error, path::PathBuf, time::Duration};
 
 use indicatif::ProgressBar;
 use ldap3::SearchEntry;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
 
 use crate::{
-    args::Options, banner::progress_bar, enums::{get_type, Type, PARSER_MOD_RE1, PARSER_MOD_RE2}, json::{
-        checker::check_all_result,
+    args::Options, banner::progress_bar, config::Config, events::{emit, object_parsed, Event}, enums::{get_type, load_well_known_overrides, Type, PARSER_MOD_RE1, PARSER_MOD_RE2}, json::{
+        checker::{acefilter::check_ace_filter, baseline::check_acl_baseline, check_all_result},
     }, 
     objects::{
-        aiaca::AIACA, certtemplate::CertTemplate, common::parse_unknown, computer::Computer, container::Container, domain::Domain, enterpriseca::EnterpriseCA, fsp::Fsp, gpo::Gpo, group::Group, inssuancepolicie::IssuancePolicie, ntauthstore::NtAuthStore, ou::Ou, rootca::RootCA, trust::Trust, user::User
+        aiaca::AIACA, certtemplate::CertTemplate, common::{parse_unknown, HostResolver, LdapObject}, computer::Computer, container::Container, domain::Domain, enterpriseca::EnterpriseCA, fsp::Fsp, gpo::Gpo, group::Group, inssuancepolicie::IssuancePolicie, ntauthstore::NtAuthStore, ou::Ou, rootca::RootCA, trust::Trust, user::User
     }, 
     storage::{EntrySource}
 };
+use crate::confignc::{add_collection_warning, take_collection_warnings};
+use crate::deadline::{is_deadline_reached, take_skipped};
+use crate::coverage::{enable_coverage, is_coverage_enabled, record_entry};
+use crate::provenance::{enable_provenance, Source};
+use crate::interrupt::is_interrupted;
+use crate::ldap::{connect_global_catalog, BindState};
+use crate::utils::format::dn_to_domain;
+use crate::referral::referral_entry_domain;
+use crate::registry::collect_ca_registry;
+use crate::forest::forest_entry_domain;
+use crate::incremental::seed_mappings;
+use crate::deleted::{is_deleted_objects_container, prepare_deleted_entry};
+use crate::dns::{backfill_computers, is_dns_node, DnsRecords};
+use crate::rootdse::{take_root_dse, RootDse};
+use crate::methods::CollectionMethod;
+use crate::objecttypes::is_parsing_skipped;
+use crate::ranged::merge_ranges;
+use crate::resolver::{resolve_ca_hosts, resolve_foreign_principals, GC_BATCH_SIZE, GC_CACHE_FILE, GC_QUERY_INTERVAL};
+use crate::enums::explain::{disable_explain_edges, enable_explain_edges, finish_object, is_explain_enabled, start_object, write_explain_report};
 
+/// All objects parsed for one domain.
 #[derive(Default)]
+#[non_exhaustive]
 pub struct ADResults {
     pub users: Vec<User>,
     pub groups: Vec<Group>,
@@ -32,18 +55,43 @@ pub struct ADResults {
     pub issuancepolicies: Vec<IssuancePolicie>,
 
     pub mappings: DomainMappings,
+    /// Phase reached when the run was interrupted, the output files are marked as partial
+    pub partial: Option<String>,
+    /// ACE filter applied after the checker, recorded in the output meta
+    pub ace_filter: Option<String>,
+    /// Warnings about the collection completeness, shown in the summary and the output meta
+    pub warnings: Vec<String>,
+    /// Collection categories skipped or cut by the deadline, shown in the output meta
+    pub skipped: Vec<String>,
+    /// Domain controllers used for the LDAP collection, the fallback DCs when the first one failed
+    pub domain_controllers: Vec<String>,
+    /// Authentication of the LDAP bind, recorded in the output meta
+    pub auth_mechanism: Option<String>,
+    /// rootDSE of the DC bound, recorded in the output meta
+    pub root_dse: Option<RootDse>,
+    /// Collection methods, the fields of the methods turned off are emptied in the output
+    pub methods: CollectionMethod,
+    /// Object types left out by `--skip` or `--only`, written as empty files with `--empty-skipped`
+    pub empty_types: Vec<String>,
+    /// A and AAAA records of the AD-integrated DNS zones, with `--dns`
+    pub dns_records: DnsRecords,
 }
 
 #[derive(Default)]
+#[non_exhaustive]
 pub struct DomainMappings {
     /// DN to SID
     pub dn_sid: HashMap<String, String>,
     ///  DN to Type
     pub sid_type: HashMap<String, String>,
-    /// FQDN to SID
-    pub fqdn_sid: HashMap<String, String>,
+    /// Host names (FQDN, short name, SPN host) to computer SID
+    pub host_resolver: HostResolver,
     /// fqdn to an ip address
     pub fqdn_ip: HashMap<String, String>,
+    /// Schema class name to defaultSecurityDescriptor (SDDL)
+    pub default_sd: HashMap<String, String>,
+    /// DN of a deleted object to the DN of its last known parent
+    pub last_known_parent: HashMap<String, String>,
 }
 
 impl ADResults {
@@ -52,40 +100,210 @@ impl ADResults {
     }
 }
 
+/// Function to parse and check all LDAP entries from memory or from the disk cache.
+/// The `bind` of the LDAP search is used for the Global Catalog and the CA registry, then dropped with its password.
 pub async fn prepare_results_from_source<S: EntrySource>(
     source: S,
     options: &Options,
+    bind: BindState,
     total_objects: Option<usize>,
 ) -> Result<ADResults, Box<dyn std::error::Error>> {
-    let mut ad_results = parse_result_type_from_source(options, source, total_objects)?;
-
-    // Functions to replace and add missing values
-    check_all_result(
-        options,
-        &mut ad_results.users,
-        &mut ad_results.groups,
-        &mut ad_results.computers,
-        &mut ad_results.ous,
-        &mut ad_results.domains,
-        &mut ad_results.gpos,
-        &mut ad_results.fsps,
-        &mut ad_results.containers,
-        &mut ad_results.trusts,
-        &mut ad_results.ntauthstores,
-        &mut ad_results.aiacas,
-        &mut ad_results.rootcas,
-        &mut ad_results.enterprisecas,
-        &mut ad_results.certtemplates,
-        &mut ad_results.issuancepolicies,
-        &ad_results.mappings.dn_sid,
-        &ad_results.mappings.sid_type,
-        &ad_results.mappings.fqdn_sid,
-        &ad_results.mappings.fqdn_ip,
-    )?;
+    let mut ad_results = parse_results_from_source(options, source, total_objects)?;
+    if options.anonymous {
+        let visible = visible_objects(&ad_results);
+        if visible.is_empty() {
+            log::warn!("No object visible anonymously, the DC may refuse anonymous searches");
+        } else {
+            let summary: Vec<String> = visible.iter().map(|(name, count)| format!("{count} {name}")).collect();
+            log::info!("Objects visible anonymously: {}", summary.join(", "));
+        }
+    }
+    // Before the checker so the resolved types are used for the ACEs and the members
+    // --adcs-only: the principals of the ACEs, one SID per query, and the CA hosts instead of the users and computers collection
+    if (options.resolve_foreign || options.adcs_only) && !is_interrupted() && !is_deadline_reached() {
+        match connect_global_catalog(options, &bind).await {
+            Ok(mut catalog) => {
+                if options.adcs_only {
+                    resolve_ca_hosts(&mut catalog, &mut ad_results).await;
+                }
+                let cache_path = PathBuf::from(".rusthound-cache").join(&options.domain).join(GC_CACHE_FILE);
+                let interval = GC_QUERY_INTERVAL.max(Duration::from_millis(options.throttle));
+                let batch_size = if options.adcs_only { 1 } else { GC_BATCH_SIZE };
+                resolve_foreign_principals(catalog, cache_path, interval, batch_size, &mut ad_results).await?;
+            }
+            Err(err) => add_collection_warning(&format!("Global Catalog not reachable, foreign principals not resolved: {err}")),
+        }
+    }
+    // EnrollmentAgentRights, EditFlags and RoleSeparationEnabled with the password of the bind
+    if !ad_results.enterprisecas.is_empty() && !is_interrupted() && !is_deadline_reached() {
+        collect_ca_registry(options, &bind, &mut ad_results.enterprisecas).await;
+    }
+    ad_results.domain_controllers = bind.domain_controllers().to_vec();
+    ad_results.auth_mechanism = bind.auth_mechanism().map(|mechanism| mechanism.to_string());
+    // Last use of the password of the bind
+    drop(bind);
+    check_results(options, &mut ad_results)?;
+    ad_results.warnings = take_collection_warnings();
+    ad_results.root_dse = take_root_dse();
+    // Deadline reached during the LDAP search: partial output
+    ad_results.skipped = take_skipped();
+    if !ad_results.skipped.is_empty() && ad_results.partial.is_none() {
+        ad_results.partial = Some("ldap_search".to_string());
+    }
+    Ok(ad_results)
+}
 
+/// Function to parse all LDAP entries into typed objects, without resolving the relations between them.
+pub fn parse_results_from_source(
+    options: &Options,
+    source: impl EntrySource,
+    total_objects: Option<usize>,
+) -> Result<ADResults, Box<dyn Error>> {
+    // Renamed or localized builtin groups
+    if let Some(path) = &options.well_known_overrides {
+        load_well_known_overrides(path)?;
+    }
+    if let Some(target) = &options.explain_edges {
+        enable_explain_edges(target);
+    }
+    if options.coverage_report {
+        enable_coverage();
+    }
+    if options.provenance {
+        enable_provenance(collection_source(options));
+    }
+    emit(Event::phase_started("parsing"));
+    let ad_results = parse_result_type_from_source(options, source, total_objects)?;
+    emit(Event::phase_finished("parsing"));
+    if options.explain_edges.is_some() {
+        write_explain_report(&options.path, &options.domain)?;
+        disable_explain_edges();
+    }
     Ok(ad_results)
 }
 
+/// Function to count the parsed objects of each type, the types without any object are left out.
+pub fn visible_objects(ad_results: &ADResults) -> Vec<(&'static str, usize)> {
+    object_counts(ad_results)
+        .into_iter()
+        .filter(|(_, count)| *count > 0)
+        .collect()
+}
+
+/// Function to count the parsed objects of each type, in the order of the output files.
+pub fn object_counts(ad_results: &ADResults) -> Vec<(&'static str, usize)> {
+    vec![
+        ("users", ad_results.users.len()),
+        ("groups", ad_results.groups.len()),
+        ("computers", ad_results.computers.len()),
+        ("ous", ad_results.ous.len()),
+        ("domains", ad_results.domains.len()),
+        ("gpos", ad_results.gpos.len()),
+        ("fsps", ad_results.fsps.len()),
+        ("containers", ad_results.containers.len()),
+        ("trusts", ad_results.trusts.len()),
+        ("ntauthstores", ad_results.ntauthstores.len()),
+        ("aiacas", ad_results.aiacas.len()),
+        ("rootcas", ad_results.rootcas.len()),
+        ("enterprisecas", ad_results.enterprisecas.len()),
+        ("certtemplates", ad_results.certtemplates.len()),
+        ("issuancepolicies", ad_results.issuancepolicies.len()),
+    ]
+}
+
+/// Function to remove the objects of the types left out by `--skip` or `--only`, parsed from a class shared with a
+/// type collected, or added by the checker.
+pub fn remove_excluded_types(options: &Options, ad_results: &mut ADResults) {
+    for name in &options.excluded_types {
+        match name.as_str() {
+            "users" => ad_results.users.clear(),
+            "groups" => ad_results.groups.clear(),
+            "computers" => ad_results.computers.clear(),
+            "ous" => ad_results.ous.clear(),
+            "domains" => ad_results.domains.clear(),
+            "gpos" => ad_results.gpos.clear(),
+            "fsps" => ad_results.fsps.clear(),
+            "containers" => ad_results.containers.clear(),
+            "trusts" => ad_results.trusts.clear(),
+            "ntauthstores" => ad_results.ntauthstores.clear(),
+            "aiacas" => ad_results.aiacas.clear(),
+            "rootcas" => ad_results.rootcas.clear(),
+            "enterprisecas" => ad_results.enterprisecas.clear(),
+            "certtemplates" => ad_results.certtemplates.clear(),
+            "issuancepolicies" => ad_results.issuancepolicies.clear(),
+            _ => {}
+        }
+    }
+    if options.empty_skipped {
+        ad_results.empty_types = options.excluded_types.to_owned();
+    }
+}
+
+/// Function to get the source of this collection: the DC requested and the current time.
+fn collection_source(options: &Options) -> Source {
+    let dc = if options.ldapfqdn != "not set" {
+        options.ldapfqdn.to_owned()
+    } else {
+        options.ip.to_owned().unwrap_or_else(|| options.domain.to_owned())
+    };
+    Source {
+        dc: dc.to_uppercase(),
+        module: "ldap".to_string(),
+        timestamp: chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+    }
+}
+
+/// Function to replace and add missing values once all objects are parsed.
+pub fn check_results(
+    options: &Options,
+    ad_results: &mut ADResults,
+) -> Result<(), Box<dyn Error>> {
+    emit(Event::phase_started("checker"));
+    // Compare with the default ACLs before the checker changes any ACE
+    if options.acl_baseline {
+        let domain_sid = ad_results.domains.first()
+            .map(|domain| domain.get_object_identifier().to_owned())
+            .unwrap_or_default();
+        check_acl_baseline(
+            &ad_results.mappings.default_sd,
+            &domain_sid,
+            &options.domain,
+            &mut ad_results.users,
+            &mut ad_results.computers,
+            &mut ad_results.groups,
+            &mut ad_results.ous,
+            &mut ad_results.domains,
+            &mut ad_results.gpos,
+            &mut ad_results.containers,
+        )?;
+    }
+    // --dns: the host names of the computers without dNSHostName and their addresses, before the SPN targets are resolved
+    if options.dns {
+        let named = backfill_computers(
+            &ad_results.dns_records,
+            &mut ad_results.computers,
+            &mut ad_results.mappings.host_resolver,
+            &mut ad_results.mappings.fqdn_ip,
+        );
+        log::info!("{} DNS host names, {named} computers named from the DNS zones", ad_results.dns_records.len());
+    }
+    // The FQDNs of the requested domain resolve from the short names, even without any dNSHostName
+    ad_results.mappings.host_resolver.add_domain(&options.domain);
+    // --cache-state: the objects of the previous runs which did not change
+    if options.cache_state.is_some() {
+        seed_mappings(&mut ad_results.mappings.dn_sid, &mut ad_results.mappings.sid_type);
+    }
+    check_all_result(options, ad_results)?;
+    // Once all edges are known, the descriptors parsing warnings are already logged
+    if let Some(spec) = &options.ace_filter {
+        check_ace_filter(spec, ad_results)?;
+    }
+    // Once the checker used them for the references and the default groups and users
+    remove_excluded_types(options, ad_results);
+    emit(Event::phase_finished("checker"));
+    Ok(())
+}
+
 // for `total_objects`, the total number of objects may not be known if the ldap query was never run
 // (e.g run was resumed from cached results)
 pub fn parse_result_type_from_source(
@@ -93,132 +311,103 @@ pub fn parse_result_type_from_source(
     source: impl EntrySource,
     total_objects: Option<usize>,
 ) -> Result<ADResults, Box<dyn Error>> {
-    let mut results = ADResults::default();
-    // Domain name
+    let mut results = ADResults { methods: common_args.collection_method, ..ADResults::default() };
+    // Domain name, the domain of each entry with the Global Catalog
     let domain = &common_args.domain;
+    // Custom properties from the --config file
+    let config = &common_args.config;
 
     // Needed for progress bar stats
     let pb = ProgressBar::new(1);
     let mut count = 0;
     let total = total_objects;
-    let mut domain_sid: String = "DOMAIN_SID".to_owned();
+    // Domain name to domain SID, one domain without the Global Catalog
+    let mut domain_sids: HashMap<String, String> = HashMap::new();
 
     log::info!("Starting the LDAP objects parsing...");
 
     let output_dir = format!(".rusthound-cache/{domain}");
     std::fs::create_dir_all(&output_dir)?;
 
-    let dn_sid = &mut results.mappings.dn_sid;
-    let sid_type = &mut results.mappings.sid_type;
-    let fqdn_sid = &mut results.mappings.fqdn_sid;
-    let fqdn_ip = &mut results.mappings.fqdn_ip;
-
-    for entry in source.into_entry_iter() {
-        let entry: SearchEntry = entry?.into();
-        // Start parsing with Type matching
-        let atype = get_type(&entry).unwrap_or(Type::Unknown);
-        match atype {
-            Type::User => {
-                let mut user: User = User::new();
-                user.parse(entry, domain, dn_sid, sid_type, &domain_sid)?;
-                results.users.push(user);
-            }
-            Type::Group => {
-                let mut group = Group::new();
-                group.parse(entry, domain, dn_sid, sid_type, &domain_sid)?;
-                results.groups.push(group);
-            }
-            Type::Computer => {
-                let mut computer = Computer::new();
-                computer.parse(
-                    entry,
-                    domain,
-                    dn_sid,
-                    sid_type,
-                    fqdn_sid,
-                    fqdn_ip,
-                    &domain_sid,
-                )?;
-                results.computers.push(computer);
-            }
-            Type::Ou => {
-                let mut ou = Ou::new();
-                ou.parse(entry, domain, dn_sid, sid_type, &domain_sid)?;
-                results.ous.push(ou);
-            }
-            Type::Domain => {
-                let mut domain_object = Domain::new();
-                let domain_sid_from_domain =
-                    domain_object.parse(entry, domain, dn_sid, sid_type)?;
-                domain_sid = domain_sid_from_domain;
-                results.domains.push(domain_object);
-            }
-            Type::Gpo => {
-                let mut gpo = Gpo::new();
-                gpo.parse(entry, domain, dn_sid, sid_type, &domain_sid)?;
-                results.gpos.push(gpo);
-            }
-            Type::ForeignSecurityPrincipal => {
-                let mut security_principal = Fsp::new();
-                security_principal.parse(entry, domain, dn_sid, sid_type)?;
-                results.fsps.push(security_principal);
-            }
-            Type::Container => {
-                if PARSER_MOD_RE1.is_match(&entry.dn.to_uppercase())
-                    || PARSER_MOD_RE2.is_match(&entry.dn.to_uppercase())
-                {
-                    //trace!("Container not to add: {}",&cloneresult.dn.to_uppercase());
-                    continue;
-                }
+    // One thread with --explain-edges: the explanations keep the LDAP order
+    let pool = match common_args.parse_threads == 1 || is_explain_enabled() {
+        true => None,
+        false => Some(ThreadPoolBuilder::new().num_threads(common_args.parse_threads).build()?),
+    };
 
-                //trace!("Container: {}",&entry.dn.to_uppercase());
-                let mut container = Container::new();
-                container.parse(entry, domain, dn_sid, sid_type, &domain_sid)?;
-                results.containers.push(container);
-            }
-            Type::Trust => {
-                let mut trust = Trust::new();
-                trust.parse(entry, domain)?;
-                results.trusts.push(trust);
-            }
-            Type::NtAutStore => {
-                let mut nt_auth_store = NtAuthStore::new();
-                nt_auth_store.parse(entry, domain, dn_sid, sid_type, &domain_sid)?;
-                results.ntauthstores.push(nt_auth_store);
+    let mut entries = source.into_entry_iter().peekable();
+    while entries.peek().is_some() {
+        let batch = entries.by_ref()
+            .take(PARSE_BATCH_SIZE)
+            .map(|entry| entry.map(SearchEntry::from))
+            .collect::<Result<Vec<SearchEntry>, Box<dyn Error>>>()?;
+        let read = batch.len();
+        // The domain objects first, in the LDAP order: the SID of their domain for the next entries
+        let mut pending = Vec::with_capacity(read);
+        for entry in batch {
+            // --dns: the records of the node, not an object of the graph
+            if common_args.dns && is_dns_node(&entry) {
+                results.dns_records.add_node(&entry);
+                continue
             }
-            Type::AIACA => {
-                let mut aiaca = AIACA::new();
-                aiaca.parse(entry, domain, dn_sid, sid_type, &domain_sid)?;
-                results.aiacas.push(aiaca);
+            // Global Catalog: the domain of the object from its DN, not from -d
+            // Referrals: the child domain of the object from its DN
+            // Forest and trusts: the domain of the object from its DN, the DNS zones in the domain holding them
+            let entry_domain = if common_args.gc {
+                dn_to_domain(&entry.dn)
+            } else if common_args.forest || common_args.follow_trusts.is_some() {
+                forest_entry_domain(&entry.dn)
+            } else if common_args.follow_referrals {
+                referral_entry_domain(&entry.dn, domain)
+            } else {
+                domain.to_owned()
+            };
+            let domain_sid = domain_sids.get(&entry_domain.to_uppercase())
+                .map_or("DOMAIN_SID", String::as_str)
+                .to_owned();
+            let atype = get_type(&entry).unwrap_or(Type::Unknown);
+            if is_coverage_enabled() {
+                record_entry(&format!("{atype:?}"), &entry);
             }
-            Type::RootCA => {
-                let mut root_ca = RootCA::new();
-                root_ca.parse(entry, domain, dn_sid, sid_type, &domain_sid)?;
-                results.rootcas.push(root_ca);
+            // The container of the deleted objects, not an object of the graph, and the types left out
+            if is_deleted_objects_container(&entry.dn) || is_parsing_skipped(&atype, &common_args.excluded_types) {
+                continue
             }
-            Type::EnterpriseCA => {
-                let mut enterprise_ca = EnterpriseCA::new();
-                enterprise_ca.parse(entry, domain, dn_sid, sid_type, &domain_sid)?;
-                results.enterprisecas.push(enterprise_ca);
+            if matches!(atype, Type::Container)
+                && (PARSER_MOD_RE1.is_match(&entry.dn.to_uppercase()) || PARSER_MOD_RE2.is_match(&entry.dn.to_uppercase()))
+            {
+                continue
             }
-            Type::CertTemplate => {
-                let mut cert_template = CertTemplate::new();
-                cert_template.parse(entry, domain, dn_sid, sid_type, &domain_sid)?;
-                results.certtemplates.push(cert_template);
-            }
-            Type::IssuancePolicie => {
-                let mut issuance_policie = IssuancePolicie::new();
-                issuance_policie.parse(entry, domain, dn_sid, sid_type, &domain_sid)?;
-                results.issuancepolicies.push(issuance_policie);
-            }
-            Type::Unknown => {
-                let _unknown = parse_unknown(entry, domain);
+            let entry = PendingEntry { entry, atype, domain: entry_domain, domain_sid };
+            if matches!(entry.atype, Type::Domain) {
+                let domain_key = entry.domain.to_uppercase();
+                let parsed = parse_pending_entry(entry, config)?;
+                if let ParsedObject::Domain(_, domain_sid) = &parsed.object {
+                    domain_sids.insert(domain_key, domain_sid.to_owned());
+                }
+                merge_parsed_entry(&mut results, parsed);
+            } else {
+                pending.push(entry);
             }
         }
+        // The others are independent, their mappings are merged in the LDAP order
+        let parsed = match &pool {
+            Some(pool) => pool.install(|| {
+                pending.into_par_iter()
+                    .map(|entry| parse_pending_entry(entry, config).map_err(|err| err.to_string()))
+                    .collect::<Result<Vec<ParsedEntry>, String>>()
+            })?,
+            None => pending.into_iter()
+                .map(|entry| parse_pending_entry(entry, config))
+                .collect::<Result<Vec<ParsedEntry>, Box<dyn Error>>>()?,
+        };
+        for parsed in parsed {
+            merge_parsed_entry(&mut results, parsed);
+        }
         // Manage progress bar
         // Pourcentage (%) = 100 x Valeur partielle/Valeur totale
         if let Some(total) = total {
-            count += 1;
+            count += read;
             let pourcentage = 100 * count / total;
             progress_bar(
                 pb.to_owned(),
@@ -229,8 +418,276 @@ pub fn parse_result_type_from_source(
         }
     }
 
+    // Root CAs parsed before their domain object (ADCS collected first): DomainSID of the RootCAFor edge
+    for root_ca in results.rootcas.iter_mut().filter(|root_ca| root_ca.domain_sid() == "DOMAIN_SID") {
+        if let Some(domain_sid) = domain_sids.get(root_ca.domain()) {
+            root_ca.set_domain_sid(domain_sid);
+        }
+    }
+    // Same for the NTAuthCertificates object: DomainSID of the NTAuthStoreFor edge
+    for nt_auth_store in results.ntauthstores.iter_mut().filter(|store| store.domain_sid() == "DOMAIN_SID") {
+        if let Some(domain_sid) = domain_sids.get(nt_auth_store.domain()) {
+            nt_auth_store.set_domain_sid(domain_sid);
+        }
+    }
+
     pb.finish_and_clear();
     log::info!("Parsing LDAP objects finished!");
     Ok(results)
 }
 
+/// LDAP entries read before parsing them, the mappings of a batch are merged before the next one.
+const PARSE_BATCH_SIZE: usize = 10_000;
+
+/// Entry to parse with its domain and the SID of its domain when it was read.
+struct PendingEntry {
+    entry: SearchEntry,
+    atype: Type,
+    domain: String,
+    domain_sid: String,
+}
+
+/// Object parsed from one LDAP entry.
+enum ParsedObject {
+    User(User),
+    Group(Group),
+    Computer(Computer),
+    Ou(Ou),
+    /// With the SID of the domain.
+    Domain(Domain, String),
+    Gpo(Gpo),
+    Fsp(Fsp),
+    Container(Container),
+    Trust(Trust),
+    NtAuthStore(NtAuthStore),
+    Aiaca(AIACA),
+    RootCa(RootCA),
+    EnterpriseCa(EnterpriseCA),
+    CertTemplate(CertTemplate),
+    IssuancePolicie(IssuancePolicie),
+    /// Default security descriptor of an object class.
+    DefaultSd(String, String),
+    Skipped,
+}
+
+/// Object parsed from one LDAP entry and the mappings it adds, own to the entry so it is parsed on any thread.
+struct ParsedEntry {
+    object: ParsedObject,
+    dn_sid: HashMap<String, String>,
+    sid_type: HashMap<String, String>,
+    host_resolver: HostResolver,
+    fqdn_ip: HashMap<String, String>,
+    /// DN of a deleted object and DN of its last known parent.
+    last_known_parent: Option<(String, String)>,
+}
+
+/// Function to parse one LDAP entry into its typed object.
+fn parse_pending_entry(pending: PendingEntry, config: &Config) -> Result<ParsedEntry, Box<dyn Error>> {
+    let PendingEntry { mut entry, atype, domain, domain_sid } = pending;
+    // Deleted object: its names without the DEL suffix, contained by its last known parent
+    let last_known_parent = prepare_deleted_entry(&mut entry).map(|parent| (entry.dn.to_uppercase(), parent.to_uppercase()));
+    // Ranges not completed, from a lost connection or an older cache: the values received
+    merge_ranges(&mut entry);
+    let domain = domain.as_str();
+    let domain_sid = domain_sid.as_str();
+    let mut dn_sid = HashMap::new();
+    let mut sid_type = HashMap::new();
+    let mut host_resolver = HostResolver::new();
+    let mut fqdn_ip = HashMap::new();
+    if is_explain_enabled() {
+        start_object(&entry, &format!("{atype:?}"), domain);
+    }
+    let object = match atype {
+        Type::User => {
+            let mut user: User = User::new();
+            let custom = config.custom_values("user", &entry);
+            user.parse(entry, domain, &mut dn_sid, &mut sid_type, domain_sid)?;
+            user.set_custom_properties(custom);
+            ParsedObject::User(user)
+        }
+        Type::Group => {
+            let mut group = Group::new();
+            let custom = config.custom_values("group", &entry);
+            group.parse(entry, domain, &mut dn_sid, &mut sid_type, domain_sid)?;
+            group.set_custom_properties(custom);
+            ParsedObject::Group(group)
+        }
+        Type::Computer => {
+            let mut computer = Computer::new();
+            let custom = config.custom_values("computer", &entry);
+            computer.parse(
+                entry,
+                domain,
+                &mut dn_sid,
+                &mut sid_type,
+                &mut host_resolver,
+                &mut fqdn_ip,
+                domain_sid,
+            )?;
+            computer.set_custom_properties(custom);
+            ParsedObject::Computer(computer)
+        }
+        Type::Ou => {
+            let mut ou = Ou::new();
+            let custom = config.custom_values("ou", &entry);
+            ou.parse(entry, domain, &mut dn_sid, &mut sid_type, domain_sid)?;
+            ou.set_custom_properties(custom);
+            ParsedObject::Ou(ou)
+        }
+        Type::Domain => {
+            let mut domain_object = Domain::new();
+            let custom = config.custom_values("domain", &entry);
+            let domain_sid_from_domain = domain_object.parse(entry, domain, &mut dn_sid, &mut sid_type)?;
+            domain_object.set_custom_properties(custom);
+            ParsedObject::Domain(domain_object, domain_sid_from_domain)
+        }
+        Type::Gpo => {
+            let mut gpo = Gpo::new();
+            let custom = config.custom_values("gpo", &entry);
+            gpo.parse(entry, domain, &mut dn_sid, &mut sid_type, domain_sid)?;
+            gpo.set_custom_properties(custom);
+            ParsedObject::Gpo(gpo)
+        }
+        Type::ForeignSecurityPrincipal => {
+            let mut security_principal = Fsp::new();
+            security_principal.parse(entry, domain, &mut dn_sid, &mut sid_type)?;
+            ParsedObject::Fsp(security_principal)
+        }
+        Type::Container => {
+            let mut container = Container::new();
+            let custom = config.custom_values("container", &entry);
+            container.parse(entry, domain, &mut dn_sid, &mut sid_type, domain_sid)?;
+            container.set_custom_properties(custom);
+            ParsedObject::Container(container)
+        }
+        Type::Trust => {
+            let mut trust = Trust::new();
+            trust.parse(entry, domain)?;
+            ParsedObject::Trust(trust)
+        }
+        Type::NtAutStore => {
+            let mut nt_auth_store = NtAuthStore::new();
+            nt_auth_store.parse(entry, domain, &mut dn_sid, &mut sid_type, domain_sid)?;
+            ParsedObject::NtAuthStore(nt_auth_store)
+        }
+        Type::AIACA => {
+            let mut aiaca = AIACA::new();
+            aiaca.parse(entry, domain, &mut dn_sid, &mut sid_type, domain_sid)?;
+            ParsedObject::Aiaca(aiaca)
+        }
+        Type::RootCA => {
+            let mut root_ca = RootCA::new();
+            root_ca.parse(entry, domain, &mut dn_sid, &mut sid_type, domain_sid)?;
+            ParsedObject::RootCa(root_ca)
+        }
+        Type::EnterpriseCA => {
+            let mut enterprise_ca = EnterpriseCA::new();
+            enterprise_ca.parse(entry, domain, &mut dn_sid, &mut sid_type, domain_sid)?;
+            ParsedObject::EnterpriseCa(enterprise_ca)
+        }
+        Type::CertTemplate => {
+            let mut cert_template = CertTemplate::new();
+            cert_template.parse(entry, domain, &mut dn_sid, &mut sid_type, domain_sid)?;
+            ParsedObject::CertTemplate(cert_template)
+        }
+        Type::IssuancePolicie => {
+            let mut issuance_policie = IssuancePolicie::new();
+            issuance_policie.parse(entry, domain, &mut dn_sid, &mut sid_type, domain_sid)?;
+            ParsedObject::IssuancePolicie(issuance_policie)
+        }
+        Type::ClassSchema => {
+            // Default security descriptor for each object class, used by the ACL baseline
+            match (
+                entry.attrs.get("lDAPDisplayName").and_then(|v| v.first()),
+                entry.attrs.get("defaultSecurityDescriptor").and_then(|v| v.first()),
+            ) {
+                (Some(name), Some(sddl)) => ParsedObject::DefaultSd(name.to_owned(), sddl.to_owned()),
+                _ => ParsedObject::Skipped,
+            }
+        }
+        Type::Unknown => {
+            let _unknown = parse_unknown(entry, domain);
+            ParsedObject::Skipped
+        }
+    };
+    finish_object();
+    Ok(ParsedEntry { object, dn_sid, sid_type, host_resolver, fqdn_ip, last_known_parent })
+}
+
+/// Function to add one parsed object and its mappings to the results.
+fn merge_parsed_entry(results: &mut ADResults, parsed: ParsedEntry) {
+    let mappings = &mut results.mappings;
+    mappings.dn_sid.extend(parsed.dn_sid);
+    // The type of a foreign security principal is guessed from its SID: the object of its own domain, collected
+    // with --forest or --gc, keeps its type whatever the LDAP order
+    if matches!(parsed.object, ParsedObject::Fsp(_)) {
+        for (sid, object_type) in parsed.sid_type {
+            mappings.sid_type.entry(sid).or_insert(object_type);
+        }
+    } else {
+        mappings.sid_type.extend(parsed.sid_type);
+    }
+    mappings.host_resolver.merge(parsed.host_resolver);
+    mappings.fqdn_ip.extend(parsed.fqdn_ip);
+    mappings.last_known_parent.extend(parsed.last_known_parent);
+    // Type and count of the object for the progress observer
+    let (kind, count) = match parsed.object {
+        ParsedObject::User(user) => { results.users.push(user); ("users", results.users.len()) }
+        ParsedObject::Group(group) => { results.groups.push(group); ("groups", results.groups.len()) }
+        ParsedObject::Computer(computer) => { results.computers.push(computer); ("computers", results.computers.len()) }
+        ParsedObject::Ou(ou) => { results.ous.push(ou); ("ous", results.ous.len()) }
+        ParsedObject::Domain(domain, _) => { results.domains.push(domain); ("domains", results.domains.len()) }
+        ParsedObject::Gpo(gpo) => { results.gpos.push(gpo); ("gpos", results.gpos.len()) }
+        ParsedObject::Fsp(fsp) => { results.fsps.push(fsp); ("fsps", results.fsps.len()) }
+        ParsedObject::Container(container) => { results.containers.push(container); ("containers", results.containers.len()) }
+        ParsedObject::Trust(trust) => { results.trusts.push(trust); ("trusts", results.trusts.len()) }
+        ParsedObject::NtAuthStore(nt_auth_store) => { results.ntauthstores.push(nt_auth_store); ("ntauthstores", results.ntauthstores.len()) }
+        ParsedObject::Aiaca(aiaca) => { results.aiacas.push(aiaca); ("aiacas", results.aiacas.len()) }
+        ParsedObject::RootCa(root_ca) => { results.rootcas.push(root_ca); ("rootcas", results.rootcas.len()) }
+        ParsedObject::EnterpriseCa(enterprise_ca) => { results.enterprisecas.push(enterprise_ca); ("enterprisecas", results.enterprisecas.len()) }
+        ParsedObject::CertTemplate(cert_template) => { results.certtemplates.push(cert_template); ("certtemplates", results.certtemplates.len()) }
+        ParsedObject::IssuancePolicie(issuance_policie) => { results.issuancepolicies.push(issuance_policie); ("issuancepolicies", results.issuancepolicies.len()) }
+        ParsedObject::DefaultSd(name, sddl) => {
+            mappings.default_sd.insert(name, sddl);
+            return
+        }
+        ParsedObject::Skipped => return,
+    };
+    object_parsed(kind, count);
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use ldap3::SearchEntry;
+
+    use crate::api::{visible_objects, ADResults};
+    use crate::objects::common::LdapObject;
+    use crate::objects::user::User;
+
+    #[test]
+    pub fn test_anonymous_object_without_security_descriptor() {
+        // Anonymous search: nTSecurityDescriptor not readable
+        let entry = SearchEntry {
+            dn: "CN=John Doe,CN=Users,DC=CONTOSO,DC=LOCAL".to_string(),
+            attrs: HashMap::from([
+                ("sAMAccountName".to_string(), vec!["jdoe".to_string()]),
+                ("objectClass".to_string(), vec!["top".to_string(), "person".to_string(), "user".to_string()]),
+            ]),
+            bin_attrs: HashMap::new(),
+        };
+        let mut user = User::new();
+        user.parse(entry, "CONTOSO.LOCAL", &mut HashMap::new(), &mut HashMap::new(), "S-1-5-21-1-2-3").unwrap();
+        assert!(user.get_aces().is_empty());
+        assert!(!user.get_is_acl_protected());
+        let json = serde_json::to_value(&user).unwrap();
+        assert_eq!(json["Aces"], serde_json::json!([]));
+        assert_eq!(json["IsACLProtected"], serde_json::json!(false));
+
+        let mut ad_results = ADResults::new();
+        ad_results.users.push(user);
+        assert_eq!(visible_objects(&ad_results), vec![("users", 1)]);
+        assert!(visible_objects(&ADResults::new()).is_empty());
+    }
+}
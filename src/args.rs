@@ -9,34 +9,258 @@ use crate::utils::exec::run;
 #[cfg(feature = "noargs")]
 use regex::Regex;
 
+use crate::config::Config;
+use crate::credentials::{BindSecret, Credentials, Secret};
+use crate::verify::VerifyOptions;
+use crate::utils::format::parse_host_port;
+use crate::paging::DEFAULT_PAGE_SIZE;
+use crate::ldap::{parse_sd_flags, DEFAULT_SD_FLAGS};
+use crate::filters::{object_filter, parse_ldap_filter};
+use crate::methods::{parse_collection_methods, CollectionMethod};
+use crate::objecttypes::{adcs_excluded_types, excluded_types, parse_object_types};
+#[cfg(not(feature = "noargs"))]
+use crate::buildinfo::build_info;
+#[cfg(not(feature = "noargs"))]
+use crate::deadline::{collection_order, parse_duration, parse_priority};
+#[cfg(not(feature = "noargs"))]
+use crate::utils::ntlm::parse_nt_hash;
+#[cfg(not(feature = "noargs"))]
+use crate::credentials::{merge_credentials, resolve_password, validate_credentials, PASSWORD_ENV};
+#[cfg(not(feature = "noargs"))]
+use crate::json::checker::acefilter::AceFilter;
+#[cfg(not(feature = "noargs"))]
+use crate::profile::{effective_config, get_profile, PROFILES};
+#[cfg(not(feature = "noargs"))]
+use clap::parser::ValueSource;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
 #[derive(Clone, Debug)]
+#[non_exhaustive]
 pub struct Options {
     pub domain: String,
     pub username: Option<String>,
-    pub password: Option<String>,
+    pub password: Option<Secret>,
+    pub nt_hash: Option<Secret>,
+    pub bind_dn: Option<String>,
+    pub module_credentials: BTreeMap<String, Credentials>,
+    pub pfx: Option<String>,
+    pub pfx_password: Option<String>,
     pub ldapfqdn: String,
+    pub fallback_dcs: Vec<String>,
     pub ip: Option<String>,
     pub port: Option<u16>,
     pub name_server: String,
     pub path: String,
     pub collection_method: CollectionMethod,
     pub ldaps: bool,
+    pub starttls: bool,
+    pub ca_cert: Option<String>,
+    pub insecure: bool,
+    pub no_hostname_verify: bool,
+    pub no_channel_binding: bool,
+    pub proxy: Option<String>,
+    pub proxy_dns: bool,
+    pub samba: bool,
+    pub gc: bool,
+    pub follow_referrals: bool,
+    /// Collect the domains of the forest found in the trusts, each from a DC of its own.
+    pub forest: bool,
+    /// Collect the domains of all the trusts up to this depth, `--follow-trusts`.
+    pub follow_trusts: Option<u8>,
     pub dns_tcp: bool,
     pub fqdn_resolver: bool,
+    pub honeypot: bool,
+    pub honeypot_config: Option<String>,
+    pub acl_baseline: bool,
+    pub resolve_foreign: bool,
+    pub coverage_report: bool,
+    pub provenance: bool,
     pub kerberos: bool,
+    pub krb5_conf: Option<String>,
+    pub ccache: Option<String>,
+    /// Realm of the `-u` account for the Kerberos bind, the domain collected when not set.
+    pub realm: Option<String>,
+    pub kerberos_only: bool,
+    pub anonymous: bool,
     pub zip: bool,
+    pub ndjson: bool,
+    pub reports: bool,
+    pub include_disabled: bool,
     pub verbose: log::LevelFilter,
     pub ldap_filter: String,
+    /// Filters of the objects of one class, the other objects are still collected.
+    pub user_filter: Option<String>,
+    pub computer_filter: Option<String>,
+    pub group_filter: Option<String>,
+    /// Subtrees of the domain partition collected instead of the whole domain, `--search-base`.
+    pub search_base: Vec<String>,
+    /// Object types left out of the collection, from `--skip` or `--only`.
+    pub excluded_types: Vec<String>,
+    /// Write an empty file for each type left out instead of no file, `--empty-skipped`.
+    pub empty_skipped: bool,
+    /// Only the PKI container of the configuration partition and the domain object, `--adcs-only`.
+    pub adcs_only: bool,
+    /// Collect the deleted objects of the AD Recycle Bin, `--deleted-objects`.
+    pub deleted_objects: bool,
+    /// Collect the A and AAAA records of the AD-integrated DNS zones, `--dns`.
+    pub dns: bool,
+    pub all_attributes: bool,
+    pub well_known_overrides: Option<String>,
+    pub config: Config,
+    pub explain_edges: Option<String>,
+    pub ace_filter: Option<String>,
+    pub profile: Option<String>,
+    pub throttle: u64,
+    pub jitter: u8,
+    pub page_size: i32,
+    pub sd_flags: u32,
+    pub retries: u32,
+    pub retry_delay: u64,
+    pub retry_max_delay: u64,
+    pub connect_timeout: u64,
+    pub search_timeout: u64,
+    pub deadline: Option<Duration>,
+    pub collection_priority: Vec<String>,
+    pub verify: Option<VerifyOptions>,
+    pub check_update: bool,
+    pub progress_json: Option<String>,
+    pub bh_url: Option<String>,
+    pub bh_token_id: Option<String>,
+    pub bh_token_key: Option<String>,
 
     pub cache: bool,
     pub cache_buffer_size: usize,
     pub resume: bool,
+    /// JSON lines file the raw LDAP entries are written to, parsed again with `from_dump`.
+    pub dump: Option<String>,
+    /// Dump parsed instead of the LDAP collection.
+    pub from_dump: Option<String>,
+    /// USNs of the previous collection, only the objects changed since are collected, `--cache-state`.
+    pub cache_state: Option<String>,
+    /// Count the objects of each type without writing any file, `--count-only`.
+    pub count_only: bool,
+    /// Threads parsing the LDAP entries, 0 for all the cores and 1 for the sequential parsing.
+    pub parse_threads: usize,
 }
 
-#[derive(Clone, Debug)]
-pub enum CollectionMethod {
-    All,
-    DCOnly,
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            domain: String::from(""),
+            username: None,
+            password: None,
+            nt_hash: None,
+            bind_dn: None,
+            module_credentials: BTreeMap::new(),
+            pfx: None,
+            pfx_password: None,
+            ldapfqdn: String::from("not set"),
+            fallback_dcs: Vec::new(),
+            ip: None,
+            port: None,
+            name_server: String::from("not set"),
+            path: String::from("./"),
+            collection_method: CollectionMethod::ALL,
+            ldaps: false,
+            starttls: false,
+            ca_cert: None,
+            insecure: false,
+            no_hostname_verify: false,
+            no_channel_binding: false,
+            proxy: None,
+            proxy_dns: false,
+            samba: false,
+            gc: false,
+            follow_referrals: false,
+            forest: false,
+            follow_trusts: None,
+            dns_tcp: false,
+            fqdn_resolver: false,
+            honeypot: false,
+            honeypot_config: None,
+            acl_baseline: false,
+            resolve_foreign: false,
+            coverage_report: false,
+            provenance: false,
+            kerberos: false,
+            krb5_conf: None,
+            ccache: None,
+            realm: None,
+            kerberos_only: false,
+            anonymous: false,
+            zip: false,
+            ndjson: false,
+            reports: false,
+            include_disabled: false,
+            verbose: log::LevelFilter::Info,
+            ldap_filter: String::from("(objectClass=*)"),
+            user_filter: None,
+            computer_filter: None,
+            group_filter: None,
+            search_base: Vec::new(),
+            excluded_types: Vec::new(),
+            empty_skipped: false,
+            adcs_only: false,
+            deleted_objects: false,
+            dns: false,
+            all_attributes: false,
+            well_known_overrides: None,
+            config: Config::default(),
+            explain_edges: None,
+            ace_filter: None,
+            profile: None,
+            throttle: 0,
+            jitter: 0,
+            page_size: DEFAULT_PAGE_SIZE,
+            sd_flags: DEFAULT_SD_FLAGS,
+            retries: 3,
+            retry_delay: 5,
+            retry_max_delay: 60,
+            connect_timeout: 30,
+            search_timeout: 300,
+            deadline: None,
+            collection_priority: Vec::new(),
+            verify: None,
+            check_update: false,
+            progress_json: None,
+            bh_url: None,
+            bh_token_id: None,
+            bh_token_key: None,
+            cache: false,
+            cache_buffer_size: 1000,
+            resume: false,
+            dump: None,
+            from_dump: None,
+            cache_state: None,
+            count_only: false,
+            parse_threads: 0,
+        }
+    }
+}
+
+impl Options {
+    /// Secret of the `-u` bind: the NT hash of `--nt-hash`, or the password.
+    pub fn bind_secret(&self) -> Option<BindSecret> {
+        match &self.nt_hash {
+            Some(nt_hash) => Some(BindSecret::NtHash(nt_hash.to_owned())),
+            None => self.password.clone().map(BindSecret::Password),
+        }
+    }
+
+    /// Credential set of a module, `None` when it binds with the primary credentials.
+    pub fn credentials_for(&self, module: &str) -> Option<&Credentials> {
+        self.module_credentials.get(module)
+    }
+
+    /// Filter of the collection: `--ldap-filter` AND-ed with the filter of each class.
+    pub fn object_filter(&self) -> String {
+        object_filter(&self.ldap_filter, &[
+            ("user", self.user_filter.as_deref()),
+            ("computer", self.computer_filter.as_deref()),
+            ("group", self.group_filter.as_deref()),
+        ])
+    }
 }
 
 // Current RustHound version
@@ -47,46 +271,111 @@ fn cli() -> Command {
     // Return Command args
     Command::new("rusthound-ce")
     .version(RUSTHOUND_VERSION)
+    // --version is handled in extract_args for --json
+    .disable_version_flag(true)
     .about("Active Directory data collector for BloodHound Community Edition.\ng0h4n <https://twitter.com/g0h4n_0>")
     .arg(Arg::new("v")
         .short('v')
         .help("Set the level of verbosity")
         .action(ArgAction::Count),
     )
+    .arg(Arg::new("version")
+        .short('V')
+        .long("version")
+        .help("Print version")
+        .action(ArgAction::SetTrue)
+    )
     .next_help_heading("REQUIRED VALUES")
     .arg(Arg::new("domain")
         .short('d')
         .long("domain")
             .help("Domain name like: DOMAIN.LOCAL")
-            .required(true)
+            .required_unless_present_any(["print-config", "version", "check-update"])
             .value_parser(value_parser!(String))
     )
     .next_help_heading("OPTIONAL VALUES")
     .arg(Arg::new("ldapusername")
         .short('u')
         .long("ldapusername")
-        .help("LDAP username, like: user@domain.local")
+        .help("LDAP username, like: user@domain.local, DOMAIN\\user or user")
         .required(false)
         .value_parser(value_parser!(String))
     )
     .arg(Arg::new("ldappassword")
         .short('p')
         .long("ldappassword")
-        .help("LDAP password")
+        .help("LDAP password, prompted if omitted (or set RUSTHOUND_PASSWORD)")
         .required(false)
         .value_parser(value_parser!(String))
     )
+    .arg(Arg::new("password-file")
+        .long("password-file")
+        .value_name("FILE")
+        .help("Read the LDAP password from the first line of a file instead of -p")
+        .required(false)
+        .conflicts_with("ldappassword")
+        .value_parser(value_parser!(String))
+    )
+    .arg(Arg::new("nt-hash")
+        .long("nt-hash")
+        .value_name("NTHASH")
+        .help("NT hash of the -u account, bound with NTLM instead of a password (pass-the-hash): 32 hex characters or LMHASH:NTHASH")
+        .required(false)
+        .requires("ldapusername")
+        .conflicts_with_all(["ldappassword", "password-file", "bind-dn", "kerberos", "pfx"])
+        .value_parser(|value: &str| parse_nt_hash(value).map(|_| value.to_owned()))
+    )
+    .arg(Arg::new("bind-dn")
+        .long("bind-dn")
+        .value_name("DN")
+        .help("DN of the simple bind used as given, like: CN=svc_ldap,OU=Service,DC=domain,DC=local")
+        .required(false)
+        .conflicts_with_all(["kerberos", "pfx"])
+        .value_parser(value_parser!(String))
+    )
+    .arg(Arg::new("pfx")
+        .long("pfx")
+        .value_name("FILE")
+        .help("PKCS#12 client certificate to authenticate over LDAPS or StartTLS instead of -u/-p")
+        .required(false)
+        .conflicts_with_all(["ldappassword", "password-file", "kerberos"])
+        .value_parser(value_parser!(String))
+    )
+    .arg(Arg::new("pfx-pass")
+        .long("pfx-pass")
+        .value_name("PASSWORD")
+        .help("Password of the PKCS#12 file, empty by default")
+        .required(false)
+        .requires("pfx")
+        .value_parser(value_parser!(String))
+    )
+    .arg(Arg::new("module-credentials")
+        .long("module-credentials")
+        .value_name("module=username[:password]")
+        .help("Credentials of a module binding on its own instead of -u/-p, password prompted if omitted [modules: gc, forest-root]")
+        .required(false)
+        .action(ArgAction::Append)
+        .value_parser(value_parser!(String))
+    )
     .arg(Arg::new("ldapfqdn")
         .short('f')
         .long("ldapfqdn")
-        .help("Domain Controller FQDN like: DC01.DOMAIN.LOCAL or just DC01")
+        .help("Domain Controller FQDN like: DC01.DOMAIN.LOCAL, DC01 or DC01:636, an address like [2001:db8::10]:636, several DCs like DC01,DC02 are used in order when one fails")
         .required(false)
         .value_parser(value_parser!(String))
     )
+    .arg(Arg::new("dc")
+        .long("dc")
+        .value_name("FQDN")
+        .help("Fallback Domain Controller used when the previous ones fail, can be repeated")
+        .required(false)
+        .action(ArgAction::Append)
+        .value_parser(value_parser!(String))
+    )
     .arg(Arg::new("ldapip")
         .short('i')
         .long("ldapip")
-        .help("Domain Controller IP address like: 192.168.1.10")
+        .help("Domain Controller IP address like: 192.168.1.10 or 2001:db8::10")
         .required(false)
         .value_parser(value_parser!(String))
     )
@@ -111,24 +400,248 @@ fn cli() -> Command {
         .required(false)
         .value_parser(value_parser!(String))
     )
+    .arg(Arg::new("well-known-overrides")
+        .long("well-known-overrides")
+        .help("JSON file to extend or adjust the well-known SIDs mapping (renamed or localized builtin groups)")
+        .required(false)
+        .value_parser(value_parser!(String))
+    )
+    .arg(Arg::new("config")
+        .long("config")
+        .value_name("FILE")
+        .help("TOML configuration file (custom_properties copying extra LDAP attributes in the output)")
+        .required(false)
+        .value_parser(value_parser!(String))
+    )
+    .arg(Arg::new("profile")
+        .long("profile")
+        .help("Collection profile setting several options at once, explicit flags keep their value")
+        .required(false)
+        .value_parser(PROFILES.iter().map(|profile| profile.name).collect::<Vec<&str>>())
+    )
+    .arg(Arg::new("throttle")
+        .long("throttle")
+        .value_name("ms")
+        .help("Wait this many milliseconds between two LDAP pages and between two LDAP queries")
+        .required(false)
+        .value_parser(value_parser!(u64))
+    )
+    .arg(Arg::new("jitter")
+        .long("jitter")
+        .value_name("pct")
+        .help("Randomize each --throttle wait by up to this percentage, more or less")
+        .required(false)
+        .value_parser(value_parser!(u8).range(0..=100))
+    )
+    .arg(Arg::new("page-size")
+        .long("page-size")
+        .value_name("N")
+        .help("Entries per LDAP page, lowered automatically when the DC answers sizeLimitExceeded or adminLimitExceeded")
+        .required(false)
+        .default_value("1000")
+        .value_parser(value_parser!(i32).range(1..))
+    )
+    .arg(Arg::new("sd-flags")
+        .long("sd-flags")
+        .value_name("flags")
+        .help("Parts of nTSecurityDescriptor requested with the LDAP_SERVER_SD_FLAGS control: 1 owner, 2 group, 4 DACL, 8 SACL (needs SeSecurityPrivilege)")
+        .required(false)
+        .default_value("0x7")
+        .value_parser(parse_sd_flags)
+    )
+    .arg(Arg::new("retries")
+        .long("retries")
+        .value_name("N")
+        .help("Retry up to N times when the connection to the DC drops or the DC is busy during a search, the entries already received are kept")
+        .required(false)
+        .default_value("3")
+        .value_parser(value_parser!(u32))
+    )
+    .arg(Arg::new("retry-delay")
+        .long("retry-delay")
+        .value_name("seconds")
+        .help("Wait this many seconds before the first retry, twice as long before each next one")
+        .required(false)
+        .default_value("5")
+        .value_parser(value_parser!(u64))
+    )
+    .arg(Arg::new("retry-max-delay")
+        .long("retry-max-delay")
+        .value_name("seconds")
+        .help("Never wait more than this many seconds before a retry")
+        .required(false)
+        .default_value("60")
+        .value_parser(value_parser!(u64))
+    )
+    .arg(Arg::new("connect-timeout")
+        .long("connect-timeout")
+        .value_name("seconds")
+        .help("Give up the connection, the TLS handshake or the bind to a DC after this many seconds")
+        .required(false)
+        .default_value("30")
+        .value_parser(value_parser!(u64).range(1..))
+    )
+    .arg(Arg::new("search-timeout")
+        .long("search-timeout")
+        .value_name("seconds")
+        .help("Give up a search when a page is not received after this many seconds, then reconnect like a lost connection")
+        .required(false)
+        .default_value("300")
+        .value_parser(value_parser!(u64).range(1..))
+    )
+    .arg(Arg::new("deadline")
+        .long("deadline")
+        .value_name("duration")
+        .help("Time box of the run like 30m or 1h30m: the most valuable objects are requested first, partial output once the deadline approaches")
+        .required(false)
+        .value_parser(parse_duration)
+    )
+    .arg(Arg::new("collection-priority")
+        .long("collection-priority")
+        .value_name("categories")
+        .help("Comma separated collection order with --deadline [default: domain,trusts,groups,users,adcs,computers,containers,gpos,others]")
+        .required(false)
+        .value_parser(|s: &str| collection_order(&parse_priority(s)).map(|_| s.to_string()).map_err(|e| e.to_string()))
+    )
+    .arg(Arg::new("explain-edges")
+        .long("explain-edges")
+        .value_name("principal-or-object")
+        .help("Explain which ACE made or suppressed each edge of an object or principal (SID, GUID, name or DN) in <domain>_explain_edges.txt")
+        .required(false)
+        .value_parser(value_parser!(String))
+    )
+    .arg(Arg::new("ace-filter")
+        .long("ace-filter")
+        .value_name("filter")
+        .help("Comma separated ACE output filter: high-value (keep only the control, ADCS, delegation and DCSync edges), EdgeName to keep, -EdgeName to drop, no-default-large-groups")
+        .required(false)
+        .value_parser(|s: &str| AceFilter::parse(s).map(|_| s.to_string()).map_err(|e| e.to_string()))
+    )
+    .arg(Arg::new("progress-json")
+        .long("progress-json")
+        .help("Emit NDJSON progress events (phase_started, phase_finished, query_finished, warning, file_written, finished) to stdout [-], a file or named pipe, or a unix socket [unix:<path>]. Logs are written to stderr")
+        .required(false)
+        .value_name("TARGET")
+        .value_parser(value_parser!(String))
+        .num_args(0..=1)
+        .default_missing_value("-")
+    )
+    .arg(Arg::new("bh-url")
+        .long("bh-url")
+        .help("BloodHound CE URL to upload the JSON files like: https://bloodhound.domain.local")
+        .required(false)
+        .requires_all(["bh-token-id", "bh-token-key"])
+        .value_parser(value_parser!(String))
+    )
+    .arg(Arg::new("bh-token-id")
+        .long("bh-token-id")
+        .help("BloodHound CE API token ID used for the upload")
+        .required(false)
+        .value_parser(value_parser!(String))
+    )
+    .arg(Arg::new("bh-token-key")
+        .long("bh-token-key")
+        .help("BloodHound CE API token key used for the upload")
+        .required(false)
+        .value_parser(value_parser!(String))
+    )
     .next_help_heading("OPTIONAL FLAGS")
     .arg(Arg::new("collectionmethod")
         .short('c')
-        .long("collectionmethod")
-        .help("Which information to collect. Supported: All (LDAP,SMB,HTTP requests), DCOnly (no computer connections, only LDAP requests). (default: All)")
+        .long("collection-methods")
+        .visible_alias("collectionmethod")
+        .help("SharpHound collection methods, comma separated like DCOnly or ACL,ObjectProps,Container, -ACL removes ACL from All. Only the LDAP ones and CARegistry (CA host registry over SMB, with the password of the bind) are collected: Group, Trusts, ACL, Container, ObjectProps, SPNTargets, CertServices, CARegistry (default: All)")
         .required(false)
-        .value_name("COLLECTIONMETHOD")
-        .value_parser(["All", "DCOnly"])
+        .value_name("METHODS")
+        .value_parser(parse_collection_methods)
         .num_args(0..=1)
         .default_missing_value("All")
     )
     .arg(Arg::new("ldap-filter")
         .long("ldap-filter")
-        .help("Use custom ldap-filter default is : (objectClass=*)")
+        .help("Custom LDAP filter AND-ed with every query, default is : (objectClass=*). The objects left out are missing from the graph with all their edges")
         .required(false)
-        .value_parser(value_parser!(String))
+        .value_parser(parse_ldap_filter)
         .default_missing_value("(objectClass=*)")
     )
+    .arg(Arg::new("user-filter")
+        .long("user-filter")
+        .value_name("FILTER")
+        .help("LDAP filter of the users only, like (!(userAccountControl:1.2.840.113556.1.4.803:=2)) for the enabled ones. The users left out are missing from the graph with all their edges")
+        .required(false)
+        .value_parser(parse_ldap_filter)
+    )
+    .arg(Arg::new("computer-filter")
+        .long("computer-filter")
+        .value_name("FILTER")
+        .help("LDAP filter of the computers only. The computers left out are missing from the graph with all their edges")
+        .required(false)
+        .value_parser(parse_ldap_filter)
+    )
+    .arg(Arg::new("group-filter")
+        .long("group-filter")
+        .value_name("FILTER")
+        .help("LDAP filter of the groups only. The groups left out are missing from the graph with all their edges, their members are not resolved")
+        .required(false)
+        .value_parser(parse_ldap_filter)
+    )
+    .arg(Arg::new("search-base")
+        .long("search-base")
+        .value_name("DN")
+        .help("Collect only the objects under this DN of the domain partition, like OU=Workstations,DC=corp,DC=local, can be repeated. The configuration partition is still collected")
+        .required(false)
+        .action(ArgAction::Append)
+        .value_parser(value_parser!(String))
+        .conflicts_with_all(["gc", "follow-referrals"])
+    )
+    .arg(Arg::new("skip")
+        .long("skip")
+        .value_name("types")
+        .help("Comma separated object types not collected nor written, like users,gpos: users, groups, computers, ous, domains, gpos, fsps, containers, trusts, ntauthstores, aiacas, rootcas, enterprisecas, certtemplates, issuancepolicies")
+        .required(false)
+        .value_parser(parse_object_types)
+    )
+    .arg(Arg::new("only")
+        .long("only")
+        .value_name("types")
+        .help("Comma separated object types collected and written, like computers,enterprisecas, the references to the other objects are not resolved")
+        .required(false)
+        .value_parser(parse_object_types)
+        .conflicts_with("skip")
+    )
+    .arg(Arg::new("empty-skipped")
+        .long("empty-skipped")
+        .help("Write an empty file for each object type left out by --skip or --only instead of no file")
+        .required(false)
+        .action(ArgAction::SetTrue)
+    )
+    .arg(Arg::new("adcs-only")
+        .long("adcs-only")
+        .help("Refresh the AD CS objects only: the Public Key Services container of the configuration partition and the domain object are requested, the type of the principals of their ACEs and the computer of each CA dNSHostName are requested on the Global Catalog one at a time")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .conflicts_with_all(["gc", "search-base", "skip", "only", "forest", "follow-trusts", "deleted-objects"])
+    )
+    .arg(Arg::new("deleted-objects")
+        .long("deleted-objects")
+        .help("Collect the deleted objects of CN=Deleted Objects (AD Recycle Bin) with IsDeleted, contained by their last known parent. Listing them needs an account allowed to, like a domain admin")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .conflicts_with("gc")
+    )
+    .arg(Arg::new("dns")
+        .long("dns")
+        .help("Collect the A and AAAA records of the AD-integrated DNS zones (DomainDnsZones, ForestDnsZones), written in <domain>_dns.json, and name the computers without dNSHostName from them")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .conflicts_with_all(["gc", "adcs-only"])
+    )
+    .arg(Arg::new("all-attributes")
+        .long("all-attributes")
+        .help("Request all the attributes (*) instead of the ones read by the parsers, slower on big domains")
+        .required(false)
+        .action(ArgAction::SetTrue)
+    )
     .arg(Arg::new("ldaps")
         .long("ldaps")
         .help("Force LDAPS using for request like: ldaps://DOMAIN.LOCAL/")
@@ -136,6 +649,99 @@ fn cli() -> Command {
         .action(ArgAction::SetTrue)
         .global(false)
     )
+    .arg(Arg::new("starttls")
+        .long("starttls")
+        .help("Upgrade the LDAP connection with StartTLS before the bind, when the port 636 is blocked")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .conflicts_with("ldaps")
+        .global(false)
+    )
+    .arg(Arg::new("ca-cert")
+        .long("ca-cert")
+        .value_name("PEM")
+        .help("PEM file of the enterprise root CA trusted for the DC certificate, in addition to the system store")
+        .required(false)
+        .value_parser(value_parser!(String))
+    )
+    .arg(Arg::new("insecure")
+        .long("insecure")
+        .help("Don't validate the DC certificate with LDAPS or StartTLS (not recommended)")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .conflicts_with("ca-cert")
+        .global(false)
+    )
+    .arg(Arg::new("no-hostname-verify")
+        .long("no-hostname-verify")
+        .help("Don't check the DC name in its certificate, for DCs with a certificate for the NetBIOS name only")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .global(false)
+    )
+    .arg(Arg::new("no-channel-binding")
+        .long("no-channel-binding")
+        .help("Bind with -u/-p over LDAPS or StartTLS with a simple bind, not NTLM with the channel binding token, for DCs refusing NTLM")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .global(false)
+    )
+    .arg(Arg::new("proxy")
+        .long("proxy")
+        .value_name("URL")
+        .help("Tunnel the LDAP connections through a SOCKS5 proxy like socks5://[user:password@]127.0.0.1:1080")
+        .required(false)
+        .value_parser(value_parser!(String))
+    )
+    .arg(Arg::new("proxy-dns")
+        .long("proxy-dns")
+        .help("Resolve the DC name with the SOCKS5 proxy instead of locally, like socks5h://")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .requires("proxy")
+        .global(false)
+    )
+    .arg(Arg::new("samba")
+        .long("samba")
+        .help("Samba AD compatibility mode, auto-detected from the rootDSE vendorName")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .global(false)
+    )
+    .arg(Arg::new("gc")
+        .long("gc")
+        .help("Collect all the domains of the forest from the Global Catalog (3268, 3269 with --ldaps), the attributes not replicated to the GC from each domain")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .global(false)
+    )
+    .arg(Arg::new("forest")
+        .long("forest")
+        .help("Collect the other domains of the forest found in the trusts, each from a DC of its domain with the same credentials")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .conflicts_with_all(["gc", "follow-referrals", "search-base"])
+        .global(false)
+    )
+    .arg(Arg::new("follow-trusts")
+        .long("follow-trusts")
+        .value_name("DEPTH")
+        .help("Collect the domains of all the enabled trusts (forest and external ones too), each from a DC of its domain with the same credentials, up to DEPTH trusts away. All the domains are in the same output [default: 3]")
+        .required(false)
+        .num_args(0..=1)
+        .default_missing_value("3")
+        .value_parser(value_parser!(u8).range(1..))
+        .conflicts_with_all(["gc", "follow-referrals", "search-base", "forest"])
+        .global(false)
+    )
+    .arg(Arg::new("follow-referrals")
+        .long("follow-referrals")
+        .help("Follow the LDAP referrals to the child domains with the same credentials, 3 referrals deep")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .conflicts_with("gc")
+        .global(false)
+    )
     .arg(Arg::new("kerberos")
         .short('k')
         .long("kerberos")
@@ -144,6 +750,44 @@ fn cli() -> Command {
         .action(ArgAction::SetTrue)
         .global(false)
     )
+    .arg(Arg::new("krb5-conf")
+        .long("krb5-conf")
+        .value_name("FILE")
+        .help("krb5.conf used for the Kerberos authentication instead of KRB5_CONFIG or /etc/krb5.conf")
+        .required(false)
+        .requires("kerberos")
+        .value_parser(value_parser!(String))
+    )
+    .arg(Arg::new("ccache")
+        .long("ccache")
+        .value_name("FILE")
+        .help("Kerberos credential cache used instead of KRB5CCNAME, without it -u/-p request a TGT with kinit")
+        .required(false)
+        .requires("kerberos")
+        .value_parser(value_parser!(String))
+    )
+    .arg(Arg::new("realm")
+        .long("realm")
+        .value_name("REALM")
+        .help("Kerberos realm of the -u account when it is not the domain collected (cross-realm trust), like FABRIKAM.LOCAL")
+        .required(false)
+        .requires("kerberos")
+        .value_parser(value_parser!(String))
+    )
+    .arg(Arg::new("kerberos-only")
+        .long("kerberos-only")
+        .help("No password bind with -u/-p when the Kerberos authentication fails (clock skew, no SPN, no ticket)")
+        .required(false)
+        .requires("kerberos")
+        .action(ArgAction::SetTrue)
+    )
+    .arg(Arg::new("anonymous")
+        .long("anonymous")
+        .help("Anonymous simple bind, only the objects readable without authentication are collected")
+        .required(false)
+        .conflicts_with_all(["ldapusername", "ldappassword", "password-file", "bind-dn", "kerberos", "pfx"])
+        .action(ArgAction::SetTrue)
+    )
     .arg(Arg::new("dns-tcp")
         .long("dns-tcp")
         .help("Use TCP instead of UDP for DNS queries")
@@ -154,7 +798,28 @@ fn cli() -> Command {
     .arg(Arg::new("zip")
         .long("zip")
         .short('z')
-        .help("Compress the JSON files into a zip archive")
+        .help("Compress the JSON files into one <timestamp>_<domain>.zip archive like SharpHound")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .global(false)
+    )
+    .arg(Arg::new("ndjson")
+        .long("ndjson")
+        .help("Also write all objects in one NDJSON file (one object per line)")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .global(false)
+    )
+    .arg(Arg::new("reports")
+        .long("reports")
+        .help("Also write the analysis reports as JSON in the output directory (tier zero ACL exposure, roastable users)")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .global(false)
+    )
+    .arg(Arg::new("include-disabled")
+        .long("include-disabled")
+        .help("Keep the disabled users and computers in the delegation edges and the analysis reports")
         .required(false)
         .action(ArgAction::SetTrue)
         .global(false)
@@ -172,12 +837,69 @@ fn cli() -> Command {
         .value_parser(value_parser!(usize))
         .default_value("1000")
     )
+    .arg(Arg::new("parse-threads")
+        .long("parse-threads")
+        .value_name("N")
+        .help("Threads parsing the LDAP entries, 1 to parse them one at a time [default: all the cores]")
+        .required(false)
+        .value_parser(value_parser!(usize))
+    )
     .arg(Arg::new("resume")
         .long("resume")
         .help("Resume the collection from the last saved state")
         .required(false)
         .action(ArgAction::SetTrue)
     )
+    .arg(Arg::new("dump")
+        .long("dump")
+        .value_name("FILE")
+        .help("Also write the raw LDAP entries to a JSON lines file, parsed again offline with --from-dump")
+        .required(false)
+        .value_parser(value_parser!(String))
+        .conflicts_with_all(["resume", "from-dump"])
+    )
+    .arg(Arg::new("from-dump")
+        .long("from-dump")
+        .value_name("FILE")
+        .help("Parse the LDAP entries of a --dump file instead of requesting the DC")
+        .required(false)
+        .value_parser(value_parser!(String))
+        .conflicts_with("resume")
+    )
+    .arg(Arg::new("cache-state")
+        .long("cache-state")
+        .value_name("FILE")
+        .help("Incremental collection: only the objects changed since the USNs of this file are collected, the USNs of the DC are recorded in it at the end of the run. A full collection when the file doesn't exist or was recorded on another DC")
+        .required(false)
+        .value_parser(value_parser!(String))
+        .conflicts_with_all(["gc", "from-dump"])
+    )
+    .arg(Arg::new("count-only")
+        .long("count-only")
+        .help("Request and parse the objects, print their count by type then exit: no module is run and no file is written")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .conflicts_with_all(["resume", "from-dump", "cache-state"])
+    )
+    .arg(Arg::new("print-config")
+        .long("print-config")
+        .help("Print the effective collection options (profile and flags) then exit")
+        .required(false)
+        .action(ArgAction::SetTrue)
+    )
+    .arg(Arg::new("json")
+        .long("json")
+        .help("With --version, print the build metadata (version, commit, date, features) as JSON")
+        .required(false)
+        .requires("version")
+        .action(ArgAction::SetTrue)
+    )
+    .arg(Arg::new("check-update")
+        .long("check-update")
+        .help("Compare the version with the latest GitHub release then exit, nothing is downloaded")
+        .required(false)
+        .action(ArgAction::SetTrue)
+    )
     .next_help_heading("OPTIONAL MODULES")
     .arg(Arg::new("fqdn-resolver")
         .long("fqdn-resolver")
@@ -186,6 +908,93 @@ fn cli() -> Command {
         .action(ArgAction::SetTrue)
         .global(false)
     )
+    .arg(Arg::new("honeypot")
+        .long("honeypot")
+        .help("Use honeypot module to score users and computers on deception indicators (honeypotscore property)")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .global(false)
+    )
+    .arg(Arg::new("honeypot-config")
+        .long("honeypot-config")
+        .help("JSON file to tune the honeypot indicators weights, enables the honeypot module")
+        .required(false)
+        .value_parser(value_parser!(String))
+    )
+    .arg(Arg::new("acl-baseline")
+        .long("acl-baseline")
+        .help("Compare ACEs with the schema default security descriptors (isdefault and acedeviation properties)")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .global(false)
+    )
+    .arg(Arg::new("resolve-foreign")
+        .long("resolve-foreign")
+        .help("Resolve the foreign principals (ACEs and members not collected) with the Global Catalog, cached in .rusthound-cache")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .global(false)
+    )
+    .arg(Arg::new("coverage-report")
+        .long("coverage-report")
+        .help("Compare the requested attributes and the edges produced with SharpHound CE in <domain>_coverage.txt")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .global(false)
+    )
+    .arg(Arg::new("provenance")
+        .long("provenance")
+        .help("Record the source (DC, module, time) of each property group of each object in <domain>_provenance.json")
+        .required(false)
+        .action(ArgAction::SetTrue)
+        .global(false)
+    )
+    .subcommand(Command::new("verify")
+        .about("Re-check the findings of a previous collection against the live AD, read-only")
+        .arg(Arg::new("findings")
+            .long("findings")
+            .value_name("FILE")
+            .help("Findings file, one '<object identifier> <Edge> [<principal SID>]' or '<object identifier> <property>[=<value>]' per line")
+            .required(true)
+            .value_parser(value_parser!(String))
+        )
+        .arg(Arg::new("previous")
+            .long("previous")
+            .value_name("DIR")
+            .help("Directory with the json files of the previous collection")
+            .required(true)
+            .value_parser(value_parser!(String))
+        )
+    )
+}
+
+/// Function to get the domain controllers of `-f DC01,DC02` and `--dc`, in order and without duplicates, and the
+/// port given like `DC01:636` or `[2001:db8::10]:636` (the same one for all of them).
+/// A FQDN must be in the collected domain, the objects of two domains are never mixed.
+pub fn parse_domain_controllers<'a>(
+    values: impl IntoIterator<Item = &'a str>,
+    domain: &str,
+) -> Result<(Vec<String>, Option<u16>), String> {
+    let suffix = format!(".{}", domain.to_lowercase());
+    let mut domain_controllers: Vec<String> = Vec::new();
+    let mut port: Option<u16> = None;
+    for value in values.into_iter().flat_map(|value| value.split(',')).map(str::trim).filter(|dc| !dc.is_empty()) {
+        let (dc, dc_port) = parse_host_port(value)?;
+        if let Some(dc_port) = dc_port {
+            if port.is_some_and(|port| port != dc_port) {
+                return Err(format!("{value}: all the domain controllers use the same port"))
+            }
+            port = Some(dc_port);
+        }
+        let is_fqdn = dc.contains('.') && dc.parse::<std::net::IpAddr>().is_err();
+        if is_fqdn && !domain.is_empty() && !dc.to_lowercase().ends_with(&suffix) {
+            return Err(format!("{dc} is not a domain controller of {domain}"))
+        }
+        if !domain_controllers.iter().any(|known| known.eq_ignore_ascii_case(&dc)) {
+            domain_controllers.push(dc);
+        }
+    }
+    Ok((domain_controllers, port))
 }
 
 #[cfg(not(feature = "noargs"))]
@@ -195,25 +1004,54 @@ pub fn extract_args() -> Options {
     // Get arguments
     let matches = cli().get_matches();
 
+    if matches.get_flag("version") {
+        let build = build_info();
+        match matches.get_flag("json") {
+            true => println!("{}", serde_json::to_string_pretty(&build).unwrap_or_default()),
+            false => println!("rusthound-ce {}", build.summary()),
+        }
+        std::process::exit(0);
+    }
+
     // Now get values
     let d = matches
         .get_one::<String>("domain")
         .map(|s| s.as_str())
-        .unwrap();
+        .unwrap_or("");
     let username = matches
         .get_one::<String>("ldapusername")
         .map(|s| s.to_owned());
-    let password = matches
-        .get_one::<String>("ldappassword")
-        .map(|s| s.to_owned());
-    let f = matches
+    let password = resolve_password(
+        matches.get_one::<String>("ldappassword").map(|s| s.as_str()),
+        matches.get_one::<String>("password-file").map(|s| s.as_str()),
+        std::env::var(PASSWORD_ENV).ok(),
+    ).unwrap_or_else(|err| cli().error(clap::error::ErrorKind::InvalidValue, err).exit());
+    let nt_hash = matches.get_one::<String>("nt-hash").map(|nt_hash| Secret::from(nt_hash.as_str()));
+    let bind_dn = matches.get_one::<String>("bind-dn").cloned();
+    let pfx = matches.get_one::<String>("pfx").cloned();
+    let pfx_password = matches.get_one::<String>("pfx-pass").cloned();
+    let domain_controllers = matches
         .get_one::<String>("ldapfqdn")
-        .map(|s| s.as_str())
-        .unwrap_or("not set");
+        .into_iter()
+        .chain(matches.get_many::<String>("dc").unwrap_or_default())
+        .map(|s| s.as_str());
+    let (mut domain_controllers, dc_port) = parse_domain_controllers(domain_controllers, d).unwrap_or_else(|err| {
+        cli().error(clap::error::ErrorKind::InvalidValue, err).exit()
+    });
+    let f = match domain_controllers.is_empty() {
+        true => String::from("not set"),
+        false => domain_controllers.remove(0),
+    };
+    let fallback_dcs = domain_controllers;
     let ip = matches.get_one::<String>("ldapip").cloned();    
+    // An address with -f is the one of the connection, no FQDN for the certificate and the Kerberos SPN
+    let (f, ip) = match (f.parse::<std::net::IpAddr>(), ip) {
+        (Ok(_), None) => (String::from("not set"), Some(f)),
+        (_, ip) => (f, ip),
+    };
     let port = match matches.get_one::<String>("ldapport") {
         Some(val) => val.parse::<u16>().ok(),
-        None => None,
+        None => dc_port,
     };
     let n = matches
         .get_one::<String>("name-server")
@@ -227,6 +1065,13 @@ pub fn extract_args() -> Options {
         .get_one::<bool>("ldaps")
         .map(|s| s.to_owned())
         .unwrap_or(false);
+    let starttls = matches.get_flag("starttls");
+    let ca_cert = matches.get_one::<String>("ca-cert").cloned();
+    let insecure = matches.get_flag("insecure");
+    let no_hostname_verify = matches.get_flag("no-hostname-verify");
+    let no_channel_binding = matches.get_flag("no-channel-binding");
+    let proxy = matches.get_one::<String>("proxy").cloned();
+    let proxy_dns = matches.get_flag("proxy-dns");
     let dns_tcp = matches
         .get_one::<bool>("dns-tcp")
         .map(|s| s.to_owned())
@@ -239,21 +1084,88 @@ pub fn extract_args() -> Options {
         .get_one::<bool>("fqdn-resolver")
         .map(|s| s.to_owned())
         .unwrap_or(false);
+    let honeypot_config = matches.get_one::<String>("honeypot-config").cloned();
+    let honeypot = matches.get_flag("honeypot") || honeypot_config.is_some();
+    let acl_baseline = matches.get_flag("acl-baseline");
+    let resolve_foreign = matches.get_flag("resolve-foreign");
+    let coverage_report = matches.get_flag("coverage-report");
+    let provenance = matches.get_flag("provenance");
+    let samba = matches.get_flag("samba");
+    let gc = matches.get_flag("gc");
+    let follow_referrals = matches.get_flag("follow-referrals");
+    let forest = matches.get_flag("forest");
+    let follow_trusts = matches.get_one::<u8>("follow-trusts").copied();
     let kerberos = matches
         .get_one::<bool>("kerberos")
         .map(|s| s.to_owned())
         .unwrap_or(false);
+    let krb5_conf = matches.get_one::<String>("krb5-conf").cloned();
+    let ccache = matches.get_one::<String>("ccache").cloned();
+    let realm = matches.get_one::<String>("realm").cloned();
+    let kerberos_only = matches.get_flag("kerberos-only");
+    // The service principal ldap/<FQDN> is never built from an address
+    if kerberos {
+        let mut targets = ip.iter().filter(|_| f == "not set").chain(&fallback_dcs);
+        if let Some(address) = targets.find(|dc| dc.parse::<std::net::IpAddr>().is_ok()) {
+            cli().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("Kerberos needs the FQDN of the DC for its service principal, not {address}: use -f <FQDN> -i {address}"),
+            ).exit()
+        }
+    }
+    let anonymous = matches.get_flag("anonymous");
     let v = match matches.get_count("v") {
         0 => log::LevelFilter::Info,
         1 => log::LevelFilter::Debug,
         _ => log::LevelFilter::Trace,
     };
-    let collection_method = match matches.get_one::<String>("collectionmethod").map(|s| s.as_str()).unwrap_or("All") {
-        "All"       => CollectionMethod::All,
-        "DCOnly"    => CollectionMethod::DCOnly,
-         _          => CollectionMethod::All,
-    };
+    let collection_method = matches.get_one::<CollectionMethod>("collectionmethod").copied().unwrap_or_default();
     let ldap_filter = matches.get_one::<String>("ldap-filter").map(|s| s.as_str()).unwrap_or("(objectClass=*)");
+    let user_filter = matches.get_one::<String>("user-filter").cloned();
+    let computer_filter = matches.get_one::<String>("computer-filter").cloned();
+    let group_filter = matches.get_one::<String>("group-filter").cloned();
+    let search_base: Vec<String> = matches.get_many::<String>("search-base").unwrap_or_default().cloned().collect();
+    let adcs_only = matches.get_flag("adcs-only");
+    let excluded_types = match adcs_only {
+        true => adcs_excluded_types(),
+        false => excluded_types(
+            &matches.get_one::<Vec<String>>("skip").cloned().unwrap_or_default(),
+            &matches.get_one::<Vec<String>>("only").cloned().unwrap_or_default(),
+        ),
+    };
+    let empty_skipped = matches.get_flag("empty-skipped");
+    let deleted_objects = matches.get_flag("deleted-objects");
+    let dns = matches.get_flag("dns");
+    let all_attributes = matches.get_flag("all-attributes");
+    let well_known_overrides = matches.get_one::<String>("well-known-overrides").cloned();
+    // Invalid configuration stops RustHound-CE before any LDAP request
+    let config = match matches.get_one::<String>("config") {
+        Some(path) => Config::from_file(path).unwrap_or_else(|err| {
+            cli().error(clap::error::ErrorKind::InvalidValue, format!("invalid configuration {err}")).exit()
+        }),
+        None => Config::default(),
+    };
+    // Each set is checked before any LDAP request, the command line wins over the configuration file
+    let cli_credentials = matches.get_many::<String>("module-credentials")
+        .into_iter()
+        .flatten()
+        .map(|value| Credentials::parse(value))
+        .collect::<Result<Vec<(String, Credentials)>, _>>();
+    let module_credentials = cli_credentials
+        .and_then(|cli| merge_credentials(&config.credentials, cli))
+        .and_then(|sets| validate_credentials(&sets, kerberos).map(|_| sets))
+        .unwrap_or_else(|err| {
+            cli().error(clap::error::ErrorKind::InvalidValue, format!("invalid module credentials: {err}")).exit()
+        });
+    let explain_edges = matches.get_one::<String>("explain-edges").cloned();
+    let ace_filter = matches.get_one::<String>("ace-filter").cloned();
+    let progress_json = matches.get_one::<String>("progress-json").cloned();
+    let ndjson = matches.get_flag("ndjson");
+    let reports = matches.get_flag("reports");
+    let include_disabled = matches.get_flag("include-disabled");
+    let bh_url = matches.get_one::<String>("bh-url").cloned();
+    let bh_token_id = matches.get_one::<String>("bh-token-id").cloned();
+    let bh_token_key = matches.get_one::<String>("bh-token-key").cloned();
 
     let cache = matches.get_flag("cache");
     let cache_buffer_size = matches
@@ -261,29 +1173,137 @@ pub fn extract_args() -> Options {
         .copied()
         .unwrap_or(1000);
     let resume = matches.get_flag("resume");
+    let dump = matches.get_one::<String>("dump").cloned();
+    let from_dump = matches.get_one::<String>("from-dump").cloned();
+    let cache_state = matches.get_one::<String>("cache-state").cloned();
+    let count_only = matches.get_flag("count-only");
+    let parse_threads = matches.get_one::<usize>("parse-threads").copied().unwrap_or(0);
+    let throttle = matches.get_one::<u64>("throttle").copied().unwrap_or(0);
+    let jitter = matches.get_one::<u8>("jitter").copied().unwrap_or(0);
+    let page_size = matches.get_one::<i32>("page-size").copied().unwrap_or(DEFAULT_PAGE_SIZE);
+    let sd_flags = matches.get_one::<u32>("sd-flags").copied().unwrap_or(DEFAULT_SD_FLAGS);
+    let retries = matches.get_one::<u32>("retries").copied().unwrap_or(3);
+    let retry_delay = matches.get_one::<u64>("retry-delay").copied().unwrap_or(5);
+    let retry_max_delay = matches.get_one::<u64>("retry-max-delay").copied().unwrap_or(60);
+    let connect_timeout = matches.get_one::<u64>("connect-timeout").copied().unwrap_or(30);
+    let search_timeout = matches.get_one::<u64>("search-timeout").copied().unwrap_or(300);
+    let deadline = matches.get_one::<Duration>("deadline").copied();
+    let collection_priority = match matches.get_one::<String>("collection-priority") {
+        Some(priority) => parse_priority(priority),
+        None => config.collection_priority.to_owned(),
+    };
+    let check_update = matches.get_flag("check-update");
+    let verify = matches.subcommand_matches("verify").map(|verify| VerifyOptions {
+        findings: verify.get_one::<String>("findings").cloned().unwrap_or_default(),
+        previous: verify.get_one::<String>("previous").cloned().unwrap_or_default(),
+    });
 
-    // Return all
-    Options {
+    let mut options = Options {
         domain: d.to_string(),
         username,
         password,
-        ldapfqdn: f.to_string(),
+        nt_hash,
+        bind_dn,
+        module_credentials,
+        pfx,
+        pfx_password,
+        ldapfqdn: f,
+        fallback_dcs,
         ip,
         port,
         name_server: n.to_string(),
         path: path.to_string(),
         collection_method,
         ldaps,
+        starttls,
+        ca_cert,
+        insecure,
+        no_hostname_verify,
+        no_channel_binding,
+        proxy,
+        proxy_dns,
+        samba,
+        gc,
+        follow_referrals,
+        forest,
+        follow_trusts,
         dns_tcp,
         fqdn_resolver,
+        honeypot,
+        honeypot_config,
+        acl_baseline,
+        resolve_foreign,
+        coverage_report,
+        provenance,
         kerberos,
+        krb5_conf,
+        ccache,
+        realm,
+        kerberos_only,
+        anonymous,
         zip: z,
+        ndjson,
+        reports,
+        include_disabled,
         verbose: v,
         ldap_filter: ldap_filter.to_string(),
+        user_filter,
+        computer_filter,
+        group_filter,
+        search_base,
+        excluded_types,
+        empty_skipped,
+        adcs_only,
+        deleted_objects,
+        dns,
+        all_attributes,
+        well_known_overrides,
+        config,
+        explain_edges,
+        ace_filter,
+        profile: None,
+        throttle,
+        jitter,
+        page_size,
+        sd_flags,
+        retries,
+        retry_delay,
+        retry_max_delay,
+        connect_timeout,
+        search_timeout,
+        deadline,
+        collection_priority,
+        verify,
+        check_update,
+        progress_json,
+        bh_url,
+        bh_token_id,
+        bh_token_key,
         cache,
         cache_buffer_size,
         resume,
+        dump,
+        from_dump,
+        cache_state,
+        count_only,
+        parse_threads,
+    };
+
+    // Profile values for the options not given on the command line
+    if let Some(profile) = matches.get_one::<String>("profile").and_then(|name| get_profile(name)) {
+        let explicit: Vec<&str> = matches.ids()
+            .map(|id| id.as_str())
+            .filter(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+            .collect();
+        profile.apply(&mut options, &explicit).unwrap_or_else(|err| {
+            cli().error(clap::error::ErrorKind::InvalidValue, format!("invalid profile {err}")).exit()
+        });
+    }
+    if matches.get_flag("print-config") {
+        print!("{}", effective_config(&options));
+        std::process::exit(0);
     }
+    options
 }
 
 #[cfg(feature = "noargs")]
@@ -329,21 +1349,112 @@ pub fn auto_args() -> Options {
         domain: domain.to_string(),
         username: "not set".to_string(),
         password: "not set".to_string(),
+        nt_hash: None,
+        bind_dn: None,
+        module_credentials: BTreeMap::new(),
+        pfx: None,
+        pfx_password: None,
         ldapfqdn: fqdn.to_string(),
+        fallback_dcs: Vec::new(),
         ip: None, 
         port: port,
         name_server: "127.0.0.1".to_string(),
         path: "./output".to_string(),
-        collection_method: CollectionMethod::All,
+        collection_method: CollectionMethod::ALL,
         ldaps: ldaps,
+        starttls: false,
+        ca_cert: None,
+        insecure: false,
+        no_hostname_verify: false,
+        no_channel_binding: false,
+        proxy: None,
+        proxy_dns: false,
+        samba: false,
+        gc: false,
+        follow_referrals: false,
+        forest: false,
+        follow_trusts: None,
         dns_tcp: false,
         fqdn_resolver: false,
+        honeypot: false,
+        honeypot_config: None,
+        acl_baseline: false,
+        resolve_foreign: false,
+        coverage_report: false,
+        provenance: false,
         kerberos: true,
+        krb5_conf: None,
+        ccache: None,
+        realm: None,
+        kerberos_only: false,
+        anonymous: false,
         zip: true,
+        ndjson: false,
+        reports: false,
+        include_disabled: false,
         verbose: log::LevelFilter::Info,
         ldap_filter: "(objectClass=*)".to_string(),
+        user_filter: None,
+        computer_filter: None,
+        group_filter: None,
+        search_base: Vec::new(),
+        excluded_types: Vec::new(),
+        empty_skipped: false,
+        adcs_only: false,
+        deleted_objects: false,
+        dns: false,
+        all_attributes: false,
+        well_known_overrides: None,
+        config: Config::default(),
+        explain_edges: None,
+        ace_filter: None,
+        profile: None,
+        throttle: 0,
+        jitter: 0,
+        page_size: DEFAULT_PAGE_SIZE,
+        sd_flags: DEFAULT_SD_FLAGS,
+        retries: 3,
+        retry_delay: 5,
+        retry_max_delay: 60,
+        connect_timeout: 30,
+        search_timeout: 300,
+        deadline: None,
+        collection_priority: Vec::new(),
+        verify: None,
+        check_update: false,
+        progress_json: None,
+        bh_url: None,
+        bh_token_id: None,
+        bh_token_key: None,
         cache: false,
         cache_buffer_size: 1000,
         resume: false,
+        dump: None,
+        from_dump: None,
+        cache_state: None,
+        count_only: false,
+        parse_threads: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::args::*;
+
+    #[test]
+    pub fn test_parse_domain_controllers() {
+        let (dcs, port) = parse_domain_controllers(["DC01.contoso.local,dc02", "dc02", "192.168.1.10"], "CONTOSO.LOCAL").unwrap();
+        assert_eq!(dcs, vec!["DC01.contoso.local", "dc02", "192.168.1.10"]);
+        assert_eq!(port, None);
+        assert!(parse_domain_controllers([], "contoso.local").unwrap().0.is_empty());
+        assert!(parse_domain_controllers(["dc01.contoso.local", "dc01.fabrikam.local"], "contoso.local").is_err());
+
+        // Ports and IPv6 addresses
+        let (dcs, port) = parse_domain_controllers(["[2001:db8::10]:636,dc02.contoso.local:636", "2001:db8::11"], "contoso.local").unwrap();
+        assert_eq!(dcs, vec!["2001:db8::10", "dc02.contoso.local", "2001:db8::11"]);
+        assert_eq!(port, Some(636));
+        assert!(parse_domain_controllers(["dc01:636", "192.168.1.10:389"], "contoso.local").is_err());
+        assert!(parse_domain_controllers(["[dc01.contoso.local]:636"], "contoso.local").is_err());
     }
 }
@@ -0,0 +1,163 @@
+//! LDAP attributes requested by the collection
+//!
+//! The searches request the attributes read by the parsers instead of `*`, the lists below for each object class,
+//! unless `--all-attributes`. An attribute newly read by a parser must be added to the list of its class:
+//! `test_parsed_attributes` checks the match arms of every parser against these lists.
+
+/// Read for every object: its type, identifiers, ACEs and deletion state, with the parent of a deleted object.
+pub const COMMON_ATTRIBUTES: &[&str] = &["objectClass", "objectSid", "objectGUID", "nTSecurityDescriptor", "isDeleted", "lastKnownParent"];
+
+pub const USER_ATTRIBUTES: &[&str] = &[
+    "sAMAccountName", "description", "mail", "title", "userPassword", "unixUserPassword", "unicodePwd", "displayName",
+    "adminCount", "homeDirectory", "scriptPath", "userAccountControl", "msDS-AllowedToDelegateTo", "logonCount",
+    "lastLogon", "lastLogonTimestamp", "pwdLastSet", "whenCreated", "servicePrincipalName", "primaryGroupID",
+    "msDS-SupportedEncryptionTypes", "sIDHistory", "msDS-GroupMSAMembership", "msDS-ManagedPasswordInterval",
+    "userCertificate",
+];
+
+pub const COMPUTER_ATTRIBUTES: &[&str] = &[
+    "sAMAccountName", "name", "dNSHostName", "msDS-AdditionalDnsHostName", "description", "operatingSystem",
+    "userAccountControl", "msDS-AllowedToDelegateTo", "msDS-AllowedToActOnBehalfOfOtherIdentity", "lastLogon",
+    "lastLogonTimestamp", "pwdLastSet", "whenCreated", "servicePrincipalName", "primaryGroupID",
+    "msDS-SupportedEncryptionTypes", "ms-Mcs-AdmPwd", "ms-Mcs-AdmPwdExpirationTime", "msLAPS-Password",
+    "msLAPS-EncryptedPassword", "msLAPS-PasswordExpirationTime",
+];
+
+pub const GROUP_ATTRIBUTES: &[&str] = &["sAMAccountName", "name", "description", "adminCount", "member", "whenCreated"];
+
+pub const DOMAIN_ATTRIBUTES: &[&str] = &[
+    "distinguishedName", "gPLink", "isCriticalSystemObject", "msDS-Behavior-Version", "ms-DS-MachineAccountQuota",
+    "minPwdLength", "pwdProperties", "pwdHistoryLength", "lockoutThreshold", "minPwdAge", "maxPwdAge",
+    "lockoutDuration", "lockOutObservationWindow", "msDS-ExpirePasswordsOnSmartCardOnlyAccounts", "whenCreated",
+];
+
+pub const OU_ATTRIBUTES: &[&str] = &["name", "description", "gPLink", "gPOptions", "whenCreated"];
+
+pub const GPO_ATTRIBUTES: &[&str] = &["displayName", "description", "gPCFileSysPath", "whenCreated"];
+
+pub const CONTAINER_ATTRIBUTES: &[&str] = &["name", "description", "whenCreated"];
+
+pub const FSP_ATTRIBUTES: &[&str] = &["name", "whenCreated"];
+
+pub const TRUST_ATTRIBUTES: &[&str] = &["name", "securityIdentifier", "trustAttributes", "trustDirection"];
+
+pub const ROOT_CA_ATTRIBUTES: &[&str] = &["name", "description", "cACertificate", "whenCreated"];
+
+pub const NTAUTH_STORE_ATTRIBUTES: &[&str] = &["name", "description", "cACertificate", "whenCreated"];
+
+pub const AIA_CA_ATTRIBUTES: &[&str] = &["name", "description", "cACertificate", "crossCertificatePair", "whenCreated"];
+
+pub const ENTERPRISE_CA_ATTRIBUTES: &[&str] = &[
+    "name", "description", "cACertificate", "certificateTemplates", "dNSHostName", "flags", "whenCreated",
+];
+
+pub const CERT_TEMPLATE_ATTRIBUTES: &[&str] = &[
+    "name", "description", "displayName", "msPKI-Cert-Template-OID", "msPKI-Certificate-Name-Flag",
+    "msPKI-Enrollment-Flag", "msPKI-Private-Key-Flag", "msPKI-RA-Signature", "msPKI-RA-Policies",
+    "msPKI-Certificate-Application-Policy", "msPKI-RA-Application-Policies", "msPKI-Template-Schema-Version",
+    "pKIExtendedKeyUsage", "pKIExpirationPeriod", "pKIOverlapPeriod", "whenCreated",
+];
+
+/// `flags` tells an issuance policy from the other OIDs.
+pub const ISSUANCE_POLICY_ATTRIBUTES: &[&str] = &["displayName", "description", "msPKI-Cert-Template-OID", "flags", "whenCreated"];
+
+/// Default security descriptor of each object class, for the ACL baseline.
+pub const CLASS_SCHEMA_ATTRIBUTES: &[&str] = &["lDAPDisplayName", "defaultSecurityDescriptor"];
+
+/// Attributes read for each object type.
+pub const CLASS_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("User", USER_ATTRIBUTES),
+    ("Computer", COMPUTER_ATTRIBUTES),
+    ("Group", GROUP_ATTRIBUTES),
+    ("Domain", DOMAIN_ATTRIBUTES),
+    ("Ou", OU_ATTRIBUTES),
+    ("Gpo", GPO_ATTRIBUTES),
+    ("Container", CONTAINER_ATTRIBUTES),
+    ("ForeignSecurityPrincipal", FSP_ATTRIBUTES),
+    ("Trust", TRUST_ATTRIBUTES),
+    ("RootCA", ROOT_CA_ATTRIBUTES),
+    ("NtAutStore", NTAUTH_STORE_ATTRIBUTES),
+    ("AIACA", AIA_CA_ATTRIBUTES),
+    ("EnterpriseCA", ENTERPRISE_CA_ATTRIBUTES),
+    ("CertTemplate", CERT_TEMPLATE_ATTRIBUTES),
+    ("IssuancePolicie", ISSUANCE_POLICY_ATTRIBUTES),
+    ("ClassSchema", CLASS_SCHEMA_ATTRIBUTES),
+];
+
+/// Function to get the attributes of the searches: the ones read by the parsers, once each, or `*` with `--all-attributes`.
+/// One search returns every object class, it requests the attributes of all of them. Without `security_descriptor`
+/// (no `ACL` collection method) `nTSecurityDescriptor` is not requested.
+pub fn search_attributes(all_attributes: bool, security_descriptor: bool) -> Vec<&'static str> {
+    let mut attributes: Vec<&'static str> = Vec::new();
+    let listed: Vec<&'static str> = match all_attributes {
+        true => vec!["*", "nTSecurityDescriptor"],
+        false => COMMON_ATTRIBUTES.iter().chain(CLASS_ATTRIBUTES.iter().flat_map(|(_, attributes)| attributes.iter())).copied().collect(),
+    };
+    for attribute in listed {
+        if !security_descriptor && attribute.eq_ignore_ascii_case("nTSecurityDescriptor") {
+            continue
+        }
+        if !attributes.iter().any(|a| a.eq_ignore_ascii_case(attribute)) {
+            attributes.push(attribute);
+        }
+    }
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+
+    use regex::Regex;
+
+    use crate::attributes::*;
+
+    /// Parser of each object type.
+    const PARSERS: &[(&str, &str)] = &[
+        ("User", include_str!("objects/user.rs")),
+        ("Computer", include_str!("objects/computer.rs")),
+        ("Group", include_str!("objects/group.rs")),
+        ("Domain", include_str!("objects/domain.rs")),
+        ("Ou", include_str!("objects/ou.rs")),
+        ("Gpo", include_str!("objects/gpo.rs")),
+        ("Container", include_str!("objects/container.rs")),
+        ("ForeignSecurityPrincipal", include_str!("objects/fsp.rs")),
+        ("Trust", include_str!("objects/trust.rs")),
+        ("RootCA", include_str!("objects/rootca.rs")),
+        ("NtAutStore", include_str!("objects/ntauthstore.rs")),
+        ("AIACA", include_str!("objects/aiaca.rs")),
+        ("EnterpriseCA", include_str!("objects/enterpriseca.rs")),
+        ("CertTemplate", include_str!("objects/certtemplate.rs")),
+        ("IssuancePolicie", include_str!("objects/inssuancepolicie.rs")),
+    ];
+
+    #[test]
+    pub fn test_parsed_attributes() {
+        // Match arms on the attribute names, before the tests of the file
+        let arm = Regex::new(r#"(?m)^\s*((?:"[A-Za-z0-9-]+"\s*\|?\s*)+)=>"#).unwrap();
+        let name = Regex::new(r#""([A-Za-z0-9-]+)""#).unwrap();
+        for (class, source) in PARSERS {
+            let listed = CLASS_ATTRIBUTES.iter().find(|(c, _)| c == class).unwrap().1;
+            let code = source.split("#[cfg(test)]").next().unwrap();
+            let parsed: Vec<&str> = arm.captures_iter(code)
+                .flat_map(|arm| name.captures_iter(arm.get(1).unwrap().as_str()).map(|n| n.get(1).unwrap().as_str()).collect::<Vec<&str>>())
+                .collect();
+            assert!(!parsed.is_empty(), "no attribute parsed for {class}");
+            for attribute in parsed {
+                assert!(
+                    COMMON_ATTRIBUTES.iter().chain(listed.iter()).any(|a| a.eq_ignore_ascii_case(attribute)),
+                    "{attribute} read by the {class} parser but not requested, add it to the {class} attributes"
+                );
+            }
+        }
+
+        let attributes = search_attributes(false, true);
+        assert!(!attributes.contains(&"*"));
+        assert!(attributes.contains(&"nTSecurityDescriptor") && attributes.contains(&"msPKI-Certificate-Name-Flag"));
+        // Once each
+        assert_eq!(attributes.iter().filter(|a| **a == "whenCreated").count(), 1);
+        assert_eq!(search_attributes(true, true), ["*", "nTSecurityDescriptor"]);
+        // No ACL collection method
+        assert!(!search_attributes(false, false).contains(&"nTSecurityDescriptor"));
+        assert_eq!(search_attributes(true, false), ["*"]);
+    }
+}
@@ -30,6 +30,27 @@ pub fn print_end_banner() {
     );
 }
 
+/// Count of the objects of each type with `--count-only`, and their total.
+pub fn print_object_counts(domain: &str, counts: &[(&str, usize)]) {
+    println!("\n{}", format!("Objects of {}:", domain.to_uppercase()).bold());
+    for (object_type, count) in counts {
+        println!("  {object_type:<18}{count:>8}");
+    }
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+    println!("  {:<18}{:>8}", "total".bold(), total.to_string().bold());
+}
+
+/// Warnings about the collection completeness, before the end banner.
+pub fn print_warnings_summary(warnings: &[String]) {
+    if warnings.is_empty() {
+        return
+    }
+    println!("\n{}", "The collection may be incomplete:".bold().yellow());
+    for warning in warnings {
+        println!("  - {}", warning.yellow());
+    }
+}
+
 /// Progress Bar used in RustHound-CE.
 pub fn progress_bar(
 	pb: ProgressBar,
@@ -0,0 +1,195 @@
+//! Build metadata and the opt-in update check
+//!
+//! The crate version, the git commit, the build date and the enabled cargo features are embedded at
+//! compile time by `build.rs`. They are shown by `--version` (`--version --json` for automation) and
+//! recorded in the `build` meta of every output file, so a dataset can be traced back to its build.
+//!
+//! `--check-update` compares the version with the latest GitHub release tag and reports it. It is the only
+//! network request not made to the target domain, it is never made without the flag and nothing is downloaded.
+use std::error::Error;
+
+use chrono::DateTime;
+use colored::Colorize;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::args::RUSTHOUND_VERSION;
+
+/// Latest release of the upstream repository.
+pub const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/g0h4n/RustHound-CE/releases/latest";
+
+/// Cargo features of the crate, with their state in this build.
+const FEATURES: &[(&str, bool)] = &[
+    ("default", cfg!(feature = "default")),
+    ("noargs", cfg!(feature = "noargs")),
+    ("nogssapi", cfg!(feature = "nogssapi")),
+];
+
+/// Metadata of the build that produced a dataset.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BuildInfo {
+    pub version: String,
+    /// Short git commit, `unknown` when built outside of a git checkout.
+    pub commit: String,
+    /// UTC build date, `SOURCE_DATE_EPOCH` for reproducible builds.
+    pub date: String,
+    pub features: Vec<String>,
+}
+
+impl BuildInfo {
+    /// One line summary like `2.4.0 (1ca941d4e2f0, 2026-10-16)`.
+    pub fn summary(&self) -> String {
+        format!("{} ({}, {})", self.version, self.commit, self.date.split('T').next().unwrap_or_default())
+    }
+}
+
+/// Function to get the metadata of the current build.
+pub fn build_info() -> BuildInfo {
+    let timestamp = env!("RUSTHOUND_BUILD_TIMESTAMP").parse::<i64>().unwrap_or_default();
+    BuildInfo {
+        version: RUSTHOUND_VERSION.to_string(),
+        commit: env!("RUSTHOUND_GIT_COMMIT").to_string(),
+        date: DateTime::from_timestamp(timestamp, 0)
+            .map(|date| date.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+            .unwrap_or_default(),
+        features: FEATURES.iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| name.to_string())
+            .collect(),
+    }
+}
+
+/// Source of the latest release tag.
+pub trait ReleaseSource {
+    /// Tag and page of the latest release.
+    fn latest_release(&self) -> Result<(String, String), Box<dyn Error>>;
+}
+
+/// GitHub releases API over HTTPS.
+pub struct GithubReleases;
+
+impl ReleaseSource for GithubReleases {
+    fn latest_release(&self) -> Result<(String, String), Box<dyn Error>> {
+        debug!("Requesting {LATEST_RELEASE_URL}");
+        let content = ureq::get(LATEST_RELEASE_URL)
+            .timeout(std::time::Duration::from_secs(10))
+            .set("User-Agent", &format!("rusthound-ce/{RUSTHOUND_VERSION}"))
+            .set("Accept", "application/vnd.github+json")
+            .call()?
+            .into_string()?;
+        let release: serde_json::Value = serde_json::from_str(&content)?;
+        let tag = release["tag_name"].as_str().ok_or("no tag_name in the latest release")?;
+        let url = release["html_url"].as_str().unwrap_or_default();
+        Ok((tag.to_string(), url.to_string()))
+    }
+}
+
+/// Result of the update check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateStatus {
+    pub current: String,
+    pub latest: String,
+    /// Release page.
+    pub url: String,
+    /// The latest release is newer than this build.
+    pub update_available: bool,
+}
+
+/// Function to parse a version or a tag like `v2.4.0`.
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    version.trim().trim_start_matches('v')
+        // Pre-release and build suffixes are not compared
+        .split(['-', '+']).next()?
+        .split('.')
+        .map(|number| number.parse::<u64>().ok())
+        .collect()
+}
+
+/// Function to compare the current version with the latest release, only when enabled.
+pub fn check_update<S: ReleaseSource>(enabled: bool, source: &S) -> Result<Option<UpdateStatus>, Box<dyn Error>> {
+    if !enabled {
+        return Ok(None)
+    }
+    let (latest, url) = source.latest_release()?;
+    let update_available = match (parse_version(&latest), parse_version(RUSTHOUND_VERSION)) {
+        (Some(latest), Some(current)) => latest > current,
+        _ => return Err(format!("can't compare the release tag '{latest}' with {RUSTHOUND_VERSION}").into()),
+    };
+    Ok(Some(UpdateStatus { current: RUSTHOUND_VERSION.to_string(), latest, url, update_available }))
+}
+
+/// Function to show the update check result.
+pub fn print_update_status(status: &UpdateStatus) {
+    if status.update_available {
+        warn!(
+            "RustHound-CE {} is available (current {}): {}",
+            status.latest.bold().yellow(),
+            status.current,
+            status.url
+        );
+    } else {
+        info!("RustHound-CE {} is up to date (latest release {})", status.current.bold().green(), status.latest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::cell::Cell;
+    use std::error::Error;
+    use serde_json::json;
+
+    use crate::args::RUSTHOUND_VERSION;
+    use crate::buildinfo::*;
+
+    /// Counts the requests instead of calling GitHub.
+    struct FakeReleases {
+        tag: &'static str,
+        calls: Cell<usize>,
+    }
+
+    impl ReleaseSource for FakeReleases {
+        fn latest_release(&self) -> Result<(String, String), Box<dyn Error>> {
+            self.calls.set(self.calls.get() + 1);
+            Ok((self.tag.to_string(), format!("https://github.com/g0h4n/RustHound-CE/releases/tag/{}", self.tag)))
+        }
+    }
+
+    #[test]
+    pub fn test_build_info_json() {
+        let info = build_info();
+        let value = serde_json::to_value(&info).unwrap();
+        let keys: Vec<&String> = value.as_object().unwrap().keys().collect();
+        assert_eq!(keys, vec!["version", "commit", "date", "features"]);
+        assert_eq!(value["version"], json!(RUSTHOUND_VERSION));
+        assert!(!info.commit.is_empty());
+        // 2026-10-16T09:30:00Z
+        assert_eq!(info.date.len(), 20);
+        assert!(info.date.ends_with('Z'));
+        assert_eq!(info.features.contains(&"nogssapi".to_string()), cfg!(feature = "nogssapi"));
+        assert!(info.summary().starts_with(&format!("{RUSTHOUND_VERSION} (")));
+        assert_eq!(serde_json::from_value::<BuildInfo>(value).unwrap(), info);
+    }
+
+    #[test]
+    pub fn test_check_update_opt_in() {
+        let source = FakeReleases { tag: "v999.0.0", calls: Cell::new(0) };
+        // No flag: no request
+        assert_eq!(check_update(false, &source).unwrap(), None);
+        assert_eq!(source.calls.get(), 0);
+
+        let status = check_update(true, &source).unwrap().unwrap();
+        assert_eq!(source.calls.get(), 1);
+        assert!(status.update_available);
+        assert_eq!(status.latest, "v999.0.0");
+
+        let current = FakeReleases { tag: "v0.1.0", calls: Cell::new(0) };
+        assert!(!check_update(true, &current).unwrap().unwrap().update_available);
+        let invalid = FakeReleases { tag: "nightly", calls: Cell::new(0) };
+        assert!(check_update(true, &invalid).is_err());
+
+        assert_eq!(parse_version("v2.4.0"), Some(vec![2, 4, 0]));
+        assert_eq!(parse_version("2.10.1-beta+1"), Some(vec![2, 10, 1]));
+        assert!(parse_version("v2.10.0") > parse_version("v2.4.0"));
+    }
+}
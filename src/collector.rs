@@ -0,0 +1,803 @@
+//! Library API to embed RustHound-CE in other Rust tools.
+//!
+//! The [`Collector`] runs the same phases as the CLI binary, each one can be called on its own
+//! so the objects can be post-processed before serialization:
+//!
+//! 1. [`Collector::search`] requests all the naming contexts and stores the raw LDAP entries,
+//! 2. [`Collector::parse`] turns the entries into typed objects (`Vec<User>`, `Vec<EnterpriseCA>`, ...),
+//! 3. [`Collector::check`] resolves the relations between objects (SIDs, hosts, default groups...),
+//! 4. [`Collector::write`] makes the BloodHound json files or the zip archive.
+//!
+//! ```no_run
+//! use rusthound_ce::Collector;
+//! use rusthound_ce::objects::common::LdapObject;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let collector = Collector::builder("contoso.local")
+//!     .ldapfqdn("dc01.contoso.local")
+//!     .credentials("user", "Password123")
+//!     .ldaps(true)
+//!     .output("/tmp/bloodhound")
+//!     .build();
+//!
+//! let mut results = collector.collect().await?;
+//! results.users.retain(|user| !user.get_object_identifier().is_empty());
+//! collector.write(results)?;
+//! # Ok(())
+//! # }
+//! ```
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use colored::Colorize;
+use log::{debug, info, warn};
+
+use crate::coverage::{enable_coverage, write_coverage_report};
+use crate::dns::write_dns_records;
+use crate::provenance::write_provenance;
+use crate::api::{check_results, object_counts, parse_results_from_source, prepare_results_from_source, remove_excluded_types, ADResults};
+use crate::args::Options;
+use crate::config::Config;
+use crate::credentials::{validate_credentials, Credentials, Secret};
+use crate::confignc::add_collection_warning;
+use crate::deadline::{collection_order, is_deadline_reached, start_deadline, CollectionCategory};
+use crate::events::{emit, Event};
+use crate::filters::parse_ldap_filter;
+use crate::forest::{ForestDomains, TrustRecorder, TrustedDomain};
+use crate::rootdse::{record_root_dse, take_root_dse};
+use crate::incremental::{load_usn_state, write_usn_state};
+use crate::interrupt::{interrupted_phase, is_interrupted};
+use crate::json::checker::honeypot::{check_honeypots, HoneypotConfig};
+use crate::json::checker::roastable::{print_roastable_accounts, roastable_accounts, write_roastable_accounts};
+use crate::json::checker::tierzero::{print_tier_zero_exposure, tier_zero_exposure, write_tier_zero_exposure};
+use crate::json::maker::{make_result, make_result_with_sinks, sink::OutputSink};
+use crate::ldap::{connect_object_reader, discover_domain_controllers, ldap_search, BindState, LdapSearchEntry, LdapSettings};
+use crate::methods::CollectionMethod;
+use crate::objecttypes::{adcs_excluded_types, excluded_types};
+use crate::profile::Profile;
+use crate::storage::{DumpReader, DumpWriter, EntrySource, Storage};
+use crate::utils::krb5::{check_credentials, fallback_reason, host_realm, request_tgt, service_principal, use_ccache, use_krb5_config, user_principal, KerberosPrincipals};
+use crate::verify::{load_previous, parse_findings, print_verifications, verify_findings, write_verifications, Verification};
+
+/// All objects collected for one domain.
+pub type CollectionResult = ADResults;
+
+/// Collector running the RustHound-CE phases with the given options.
+#[derive(Clone, Debug)]
+pub struct Collector {
+    options: Options,
+    /// Binds of the last search, taken by the next [`Collector::process`] or [`Collector::count`].
+    bind: Arc<Mutex<BindState>>,
+}
+
+impl Collector {
+    /// Start a new collector builder for the given domain.
+    pub fn builder(domain: &str) -> CollectorBuilder {
+        CollectorBuilder::new(domain)
+    }
+
+    /// Create a collector from already parsed options (used by the CLI binary).
+    pub fn from_options(options: Options) -> Self {
+        Self { options, bind: Arc::default() }
+    }
+
+    // Immutable access.
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Request all the naming contexts and store the raw LDAP entries, return the number of entries.
+    /// With `--dump` the entries are also written to the dump file.
+    pub async fn search<S: Storage<LdapSearchEntry>>(&self, storage: &mut S) -> Result<usize, Box<dyn Error>> {
+        match &self.options.dump {
+            Some(path) => {
+                info!("Writing the LDAP entries to {}", path.bold());
+                self.search_to(&mut DumpWriter::create(path, &self.options.domain, storage)?).await
+            }
+            None => self.search_to(storage).await,
+        }
+    }
+
+    async fn search_to<S: Storage<LdapSearchEntry>>(&self, storage: &mut S) -> Result<usize, Box<dyn Error>> {
+        validate_credentials(&self.options.module_credentials, self.options.kerberos)?;
+        // Invalid filters stop the collection before any LDAP request
+        for filter in [Some(&self.options.ldap_filter), self.options.user_filter.as_ref(), self.options.computer_filter.as_ref(), self.options.group_filter.as_ref()].into_iter().flatten() {
+            parse_ldap_filter(filter)?;
+        }
+        let settings = self.load_connection_settings()?;
+        // Prioritized categories only with a deadline
+        let order = match self.options.deadline {
+            Some(deadline) => {
+                start_deadline(deadline);
+                Some(collection_order(&self.options.collection_priority)?)
+            }
+            None => None,
+        };
+        if self.options.coverage_report {
+            enable_coverage();
+        }
+        if let Some(path) = &self.options.cache_state {
+            load_usn_state(path)?;
+        }
+        emit(Event::phase_started("ldap_search"));
+        let total = match self.options.forest || self.options.follow_trusts.is_some() {
+            true => self.search_forest(&settings, order.as_deref(), storage).await?,
+            false => self.search_domain(&settings, order.as_deref(), true, storage).await?,
+        };
+        emit(Event::phase_finished("ldap_search"));
+        *self.bind.lock().unwrap() = settings.take_bind_state();
+        Ok(total)
+    }
+
+    /// Collect the domain of `-d`, then each domain of the forest (or each trusted domain with `--follow-trusts`)
+    /// found in the trusts from a DC of its own. A domain not reachable is left out with a collection warning.
+    async fn search_forest<S: Storage<LdapSearchEntry>>(
+        &self,
+        settings: &LdapSettings,
+        order: Option<&[&CollectionCategory]>,
+        storage: &mut S,
+    ) -> Result<usize, Box<dyn Error>> {
+        let mut forest = match self.options.follow_trusts {
+            Some(max_depth) => ForestDomains::following_trusts(&self.options.domain, max_depth),
+            None => ForestDomains::new(&self.options.domain),
+        };
+        let mut total = self.search_domain(settings, order, true, &mut TrustRecorder::new(&mut forest, storage)).await?;
+        // The DC of -d in the output meta, not the one of another forest
+        let root_dse = take_root_dse();
+        while let Some(TrustedDomain { domain, depth, other_forest }) = forest.next_domain() {
+            if is_interrupted() || is_deadline_reached() {
+                add_collection_warning(&format!("Domain {domain} found in the trusts not collected before the end of the collection"));
+                continue
+            }
+            match other_forest {
+                true => info!("Collecting {} of another forest, {depth} trusts away", domain.bold().green()),
+                false => info!("Collecting {} of the forest", domain.bold().green()),
+            }
+            let collector = Collector::from_options(Options {
+                domain: domain.to_lowercase(),
+                ldapfqdn: "not set".to_string(),
+                ip: None,
+                fallback_dcs: Vec::new(),
+                // The account of -d
+                realm: Some(self.options.realm.to_owned().unwrap_or_else(|| self.options.domain.to_owned())),
+                ..self.options.to_owned()
+            });
+            match collector.search_domain(settings, order, other_forest, &mut TrustRecorder::new(&mut forest, storage)).await {
+                Ok(entries) => total += entries,
+                Err(err) => add_collection_warning(&format!("Domain {domain} found in the trusts not collected: {err}")),
+            }
+        }
+        if let Some(root_dse) = root_dse {
+            record_root_dse(root_dse);
+        }
+        info!("Domains collected: {}", forest.collected().join(", ").bold());
+        Ok(total)
+    }
+
+    /// Request the naming contexts of the domain, the configuration and schema ones with `forest_wide`.
+    async fn search_domain<S: Storage<LdapSearchEntry>>(
+        &self,
+        settings: &LdapSettings,
+        order: Option<&[&CollectionCategory]>,
+        forest_wide: bool,
+        storage: &mut S,
+    ) -> Result<usize, Box<dyn Error>> {
+        // Only the domain name: the DCs of the DNS SRV records, then the domain name when all of them fail
+        let discovered = match self.options.ldapfqdn == "not set" && self.options.ip.is_none() {
+            true => discover_domain_controllers(&self.options.domain, self.options.gc, self.options.dns_tcp, &self.options.name_server).await,
+            false => Vec::new(),
+        };
+        // The IPv6 address of an IPv6-only DC, its name stays the one of the certificate and the Kerberos SPN
+        let discovered_options;
+        let options = match discovered.split_first() {
+            Some((first, others)) => {
+                let mut fallback_dcs: Vec<String> = others.iter().map(|dc| dc.fqdn.to_owned()).collect();
+                fallback_dcs.push(self.options.domain.to_owned());
+                discovered_options = Options {
+                    ip: Some(first.address()),
+                    ldapfqdn: first.fqdn.to_owned(),
+                    fallback_dcs,
+                    ..self.options.to_owned()
+                };
+                &discovered_options
+            }
+            None => &self.options,
+        };
+        ldap_search(options, settings, forest_wide, order, storage).await
+    }
+
+    /// Parse raw LDAP entries into typed objects, without resolving the relations between them.
+    pub fn parse<S: EntrySource>(&self, source: S, total_objects: Option<usize>) -> Result<CollectionResult, Box<dyn Error>> {
+        parse_results_from_source(&self.options, source, total_objects)
+    }
+
+    /// Replace and add missing values once all the objects are parsed.
+    pub fn check(&self, results: &mut CollectionResult) -> Result<(), Box<dyn Error>> {
+        check_results(&self.options, results)
+    }
+
+    /// Parse raw LDAP entries and count the objects of each type, nothing is checked or written.
+    pub fn count<S: EntrySource>(&self, source: S, total_objects: Option<usize>) -> Result<Vec<(&'static str, usize)>, Box<dyn Error>> {
+        // The password of the search isn't used
+        drop(self.take_bind_state());
+        let mut results = self.parse(source, total_objects)?;
+        remove_excluded_types(&self.options, &mut results);
+        Ok(object_counts(&results))
+    }
+
+    /// Parse and check raw LDAP entries, from memory or from the disk cache.
+    pub async fn process<S: EntrySource>(&self, source: S, total_objects: Option<usize>) -> Result<CollectionResult, Box<dyn Error>> {
+        prepare_results_from_source(source, &self.options, self.take_bind_state(), total_objects).await
+    }
+
+    /// Take the binds of the last search, none when the entries come from the cache or a dump.
+    fn take_bind_state(&self) -> BindState {
+        std::mem::take(&mut *self.bind.lock().unwrap())
+    }
+
+    /// Parse and check the raw LDAP entries of a `--dump` file, without the DC.
+    pub async fn process_dump(&self, path: &str) -> Result<CollectionResult, Box<dyn Error>> {
+        let dump = DumpReader::from_path(path)?;
+        if !dump.domain().eq_ignore_ascii_case(&self.options.domain) {
+            return Err(format!("{path} is a dump of {}, not of {}", dump.domain(), self.options.domain).into())
+        }
+        self.process(dump, None).await
+    }
+
+    /// Run the LDAP search in memory, then parse and check all objects.
+    /// If the run was interrupted or the deadline reached the results are marked as partial.
+    pub async fn collect(&self) -> Result<CollectionResult, Box<dyn Error>> {
+        let mut entries: Vec<LdapSearchEntry> = Vec::new();
+        let total = self.search(&mut entries).await?;
+        let mut results = self.process(entries, Some(total)).await?;
+        if let Some(phase) = interrupted_phase() {
+            results.partial = Some(phase);
+        }
+        Ok(results)
+    }
+
+    /// Run the analysis passes: tier zero ACL exposure, roastable users, and the ones enabled in the options (honeypot scores).
+    /// Call it after the modules so DNS resolutions are known.
+    pub fn analyze(&self, results: &mut CollectionResult) -> Result<(), Box<dyn Error>> {
+        if self.options.honeypot {
+            let config = match &self.options.honeypot_config {
+                Some(path) => HoneypotConfig::from_file(path)?,
+                None => HoneypotConfig::default(),
+            };
+            check_honeypots(&config, &mut results.users, &mut results.computers, &results.mappings.fqdn_ip)?;
+        }
+        let exposures = tier_zero_exposure(results, self.options.include_disabled);
+        print_tier_zero_exposure(&exposures);
+        let roastable = roastable_accounts(results, self.options.include_disabled);
+        print_roastable_accounts(&roastable);
+        if self.options.reports {
+            write_tier_zero_exposure(&exposures, &self.options.path, &self.options.domain)?;
+            write_roastable_accounts(&roastable, &self.options.path, &self.options.domain)?;
+        }
+        Ok(())
+    }
+
+    /// Load the `--krb5-conf` file and the Kerberos credentials, then the settings of the LDAP connections of this collector:
+    /// the `--proxy`, the validation of the DC certificate, the timeouts, the SD flags and the `--pfx` client certificate.
+    fn load_connection_settings(&self) -> Result<LdapSettings, Box<dyn Error>> {
+        if let Some(krb5_conf) = &self.options.krb5_conf {
+            use_krb5_config(krb5_conf)?;
+        }
+        if self.options.kerberos {
+            match self.load_kerberos_credentials() {
+                // Clock skew or no ticket: the password bind is tried after the Kerberos one
+                Err(err) if self.password_fallback() && fallback_reason(&err.to_string()).is_some() => {
+                    warn!("Kerberos credentials not ready ({err}), -u/-p are used if the Kerberos bind fails");
+                }
+                res => res?,
+            }
+        }
+        let settings = LdapSettings::from_options(&self.options)?;
+        if self.options.insecure {
+            warn!("{}", "--insecure: the DC certificate is not validated, anyone in the middle can read the LDAP bind and the collected data!".bold().red());
+        } else if self.options.no_hostname_verify {
+            warn!("--no-hostname-verify: the DC name is not checked in its certificate");
+        }
+        if self.options.no_channel_binding && (self.options.ldaps || self.options.starttls) {
+            warn!("--no-channel-binding: simple bind over TLS, refused by the DCs enforcing LDAP channel binding");
+        }
+        Ok(settings)
+    }
+
+    /// Select the Kerberos credentials of the GSSAPI bind: the `--ccache` file, a TGT requested with `-u/-p`,
+    /// or the KRB5CCNAME cache.
+    fn load_kerberos_credentials(&self) -> Result<(), Box<dyn Error>> {
+        let principals = self.kerberos_principals();
+        match (&self.options.ccache, &principals.client, &self.options.password) {
+            (Some(ccache), _, _) => {
+                let principal = use_ccache(ccache)?;
+                let realm = self.options.realm.as_deref().map(str::to_uppercase);
+                if let Some(realm) = realm.filter(|realm| !principal.to_uppercase().ends_with(&format!("@{realm}"))) {
+                    warn!("The ticket of {ccache} is for {principal}, not an account of the realm {realm} of --realm");
+                }
+            }
+            (None, Some(principal), Some(password)) => {
+                let ccache = request_tgt(principal, password)?;
+                info!("TGT of {} requested in {ccache}", principal.bold());
+            }
+            _ => {}
+        }
+        match &principals.service_realm {
+            Some(realm) => debug!("Kerberos service principal {}@{realm}", principals.service),
+            None => debug!("Kerberos service principal {}", principals.service),
+        }
+        check_credentials()
+    }
+
+    /// Principals of the Kerberos bind: `-u` in the realm of `--realm` (the domain collected by default) and
+    /// `ldap/<DC FQDN>`, in the realm of the DC given by the `--krb5-conf`.
+    pub fn kerberos_principals(&self) -> KerberosPrincipals {
+        let service_realm = self.options.krb5_conf.as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|krb5_conf| host_realm(&krb5_conf, &self.options.ldapfqdn));
+        KerberosPrincipals {
+            client: self.options.username.as_deref()
+                .map(|username| user_principal(username, &self.options.domain, self.options.realm.as_deref())),
+            service: service_principal(&self.options.ldapfqdn),
+            service_realm,
+        }
+    }
+
+    /// Password bind with `-u/-p` when the Kerberos authentication fails, not with `--kerberos-only`.
+    fn password_fallback(&self) -> bool {
+        !self.options.kerberos_only && self.options.username.is_some() && self.options.password.is_some()
+    }
+
+    /// Make the json files (or the zip archive) in the output directory, and `<domain>_dns.json` with `--dns`.
+    /// With `--cache-state` the USNs of the collection are recorded once the output is written.
+    pub fn write(&self, results: CollectionResult) -> Result<(), Box<dyn Error>> {
+        let mappings = self.options.cache_state.as_ref()
+            .map(|path| (path, results.mappings.dn_sid.to_owned(), results.mappings.sid_type.to_owned()));
+        if self.options.dns {
+            write_dns_records(&results.dns_records, &self.options.path, &self.options.domain)?;
+        }
+        make_result(&self.options, results)?;
+        if let Some((path, dn_sid, sid_type)) = mappings {
+            write_usn_state(path, &dn_sid, &sid_type)?;
+        }
+        if self.options.coverage_report {
+            write_coverage_report(&self.options.path, &self.options.domain)?;
+        }
+        if self.options.provenance {
+            write_provenance(&self.options.path, &self.options.domain)?;
+        }
+        Ok(())
+    }
+
+    /// Re-check the findings of a previous collection with targeted LDAP reads, no output file is changed.
+    /// The verifications are shown as a table and written in `<domain>_verify.json`.
+    pub async fn verify(&self, findings: &str, previous: &str) -> Result<Vec<Verification>, Box<dyn Error>> {
+        let findings = parse_findings(&std::fs::read_to_string(findings)?)?;
+        let previous = load_previous(previous)?;
+        info!("Verifying {} findings of {} previous objects", findings.len(), previous.len());
+        let settings = self.load_connection_settings()?;
+        let reader = connect_object_reader(&self.options, &settings).await?;
+        let verifications = verify_findings(reader, &self.options, &previous, &findings).await?;
+        print_verifications(&verifications);
+        write_verifications(&verifications, &self.options.path, &self.options.domain)?;
+        Ok(verifications)
+    }
+
+    /// Write all objects in custom output sinks instead of the ones selected by the options.
+    pub fn write_to(&self, results: CollectionResult, sinks: Vec<Box<dyn OutputSink>>) -> Result<(), Box<dyn Error>> {
+        make_result_with_sinks(results, sinks)
+    }
+}
+
+/// Builder for [`Collector`], every value not set keeps the CLI default.
+#[derive(Clone, Debug)]
+pub struct CollectorBuilder {
+    options: Options,
+}
+
+impl CollectorBuilder {
+    /// New builder for the given domain.
+    pub fn new(domain: &str) -> Self {
+        Self {
+            options: Options {
+                domain: domain.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Domain controller FQDN.
+    pub fn ldapfqdn(mut self, ldapfqdn: &str) -> Self {
+        self.options.ldapfqdn = ldapfqdn.to_string();
+        self
+    }
+    /// Fallback domain controllers, used in order when the previous DC fails.
+    pub fn fallback_dcs(mut self, dcs: &[&str]) -> Self {
+        self.options.fallback_dcs = dcs.iter().map(|dc| dc.to_string()).collect();
+        self
+    }
+    /// Domain controller IP address.
+    pub fn ip(mut self, ip: &str) -> Self {
+        self.options.ip = Some(ip.to_string());
+        self
+    }
+    /// LDAP port, default is 389 or 636 with LDAPS.
+    pub fn port(mut self, port: u16) -> Self {
+        self.options.port = Some(port);
+        self
+    }
+    /// Use LDAPS.
+    pub fn ldaps(mut self, ldaps: bool) -> Self {
+        self.options.ldaps = ldaps;
+        self
+    }
+    /// Upgrade the LDAP connection with StartTLS before the bind.
+    pub fn starttls(mut self, starttls: bool) -> Self {
+        self.options.starttls = starttls;
+        self
+    }
+    /// Validation of the DC certificate with LDAPS or StartTLS: an extra trusted root CA (PEM file),
+    /// no validation at all with `insecure`, and the check of the DC name in the certificate.
+    pub fn tls_validation(mut self, ca_cert: Option<&str>, insecure: bool, verify_hostname: bool) -> Self {
+        self.options.ca_cert = ca_cert.map(str::to_string);
+        self.options.insecure = insecure;
+        self.options.no_hostname_verify = !verify_hostname;
+        self
+    }
+    /// Bind with the password over TLS with NTLM and the channel binding token (default), or with a simple bind.
+    pub fn channel_binding(mut self, channel_binding: bool) -> Self {
+        self.options.no_channel_binding = !channel_binding;
+        self
+    }
+    /// SOCKS5 proxy URL for the LDAP connections, the DC name is resolved by the proxy with `remote_dns`.
+    pub fn proxy(mut self, url: &str, remote_dns: bool) -> Self {
+        self.options.proxy = Some(url.to_string());
+        self.options.proxy_dns = remote_dns;
+        self
+    }
+    /// Username and password for the LDAP bind.
+    pub fn credentials(mut self, username: &str, password: &str) -> Self {
+        self.options.username = Some(username.to_string());
+        self.options.password = Some(Secret::from(password));
+        self
+    }
+    /// Username and NT hash of an NTLM bind instead of the password (pass-the-hash), 32 hex characters.
+    pub fn nt_hash_credentials(mut self, username: &str, nt_hash: &str) -> Self {
+        self.options.username = Some(username.to_string());
+        self.options.password = None;
+        self.options.nt_hash = Some(Secret::from(nt_hash));
+        self
+    }
+    /// DN of the simple bind, used as given instead of the username.
+    pub fn bind_dn(mut self, bind_dn: &str) -> Self {
+        self.options.bind_dn = Some(bind_dn.to_string());
+        self
+    }
+    /// PKCS#12 client certificate authenticating over LDAPS or StartTLS instead of the credentials.
+    pub fn client_certificate(mut self, pfx: &str, password: Option<&str>) -> Self {
+        self.options.pfx = Some(pfx.to_string());
+        self.options.pfx_password = password.map(str::to_string);
+        self
+    }
+    /// Credentials of a module binding on its own, see [`crate::credentials`].
+    /// Without password it is prompted on first use.
+    pub fn module_credentials(mut self, module: &str, username: &str, password: Option<&str>) -> Self {
+        self.options.module_credentials.insert(module.to_lowercase(), Credentials::new(username, password));
+        self
+    }
+    /// Samba AD compatibility mode, enabled anyway when the rootDSE vendorName is Samba.
+    pub fn samba(mut self, samba: bool) -> Self {
+        self.options.samba = samba;
+        self
+    }
+    /// Collect all the domains of the forest from the Global Catalog.
+    pub fn gc(mut self, gc: bool) -> Self {
+        self.options.gc = gc;
+        self
+    }
+    /// Follow the LDAP referrals to the child domains.
+    pub fn follow_referrals(mut self, follow_referrals: bool) -> Self {
+        self.options.follow_referrals = follow_referrals;
+        self
+    }
+    /// Collect the other domains of the forest found in the trusts.
+    pub fn forest(mut self, forest: bool) -> Self {
+        self.options.forest = forest;
+        self
+    }
+    /// Collect the domains found in all the trusts, up to `max_depth` trusts away from the domain.
+    pub fn follow_trusts(mut self, max_depth: u8) -> Self {
+        self.options.follow_trusts = Some(max_depth);
+        self
+    }
+    /// Use Kerberos authentication from the KRB5CCNAME ticket.
+    pub fn kerberos(mut self, kerberos: bool) -> Self {
+        self.options.kerberos = kerberos;
+        self
+    }
+    /// Anonymous simple bind, only the objects readable without authentication are collected.
+    pub fn anonymous(mut self, anonymous: bool) -> Self {
+        self.options.anonymous = anonymous;
+        self
+    }
+    /// Kerberos credential cache used by the Kerberos bind instead of `KRB5CCNAME`.
+    pub fn ccache(mut self, path: &str) -> Self {
+        self.options.ccache = Some(path.to_string());
+        self
+    }
+    /// No password bind when the Kerberos authentication fails.
+    pub fn kerberos_only(mut self, kerberos_only: bool) -> Self {
+        self.options.kerberos_only = kerberos_only;
+        self
+    }
+    /// krb5.conf used by the Kerberos bind instead of `KRB5_CONFIG` or `/etc/krb5.conf`.
+    pub fn krb5_conf(mut self, path: &str) -> Self {
+        self.options.krb5_conf = Some(path.to_string());
+        self
+    }
+    /// Kerberos realm of the account when it is not the domain collected (cross-realm trust).
+    pub fn realm(mut self, realm: &str) -> Self {
+        self.options.realm = Some(realm.to_string());
+        self
+    }
+    /// LDAP filter used for each naming context, default is `(objectClass=*)`.
+    pub fn ldap_filter(mut self, ldap_filter: &str) -> Self {
+        self.options.ldap_filter = ldap_filter.to_string();
+        self
+    }
+    /// LDAP filter of the users only, the other objects are still collected.
+    pub fn user_filter(mut self, filter: &str) -> Self {
+        self.options.user_filter = Some(filter.to_string());
+        self
+    }
+    /// LDAP filter of the computers only.
+    pub fn computer_filter(mut self, filter: &str) -> Self {
+        self.options.computer_filter = Some(filter.to_string());
+        self
+    }
+    /// LDAP filter of the groups only.
+    pub fn group_filter(mut self, filter: &str) -> Self {
+        self.options.group_filter = Some(filter.to_string());
+        self
+    }
+    /// Collect only the objects under this DN of the domain partition, can be called for several subtrees.
+    pub fn search_base(mut self, dn: &str) -> Self {
+        self.options.search_base.push(dn.to_string());
+        self
+    }
+    /// Object types not collected nor written, like `["users", "gpos"]`.
+    pub fn skip(mut self, types: &[&str]) -> Self {
+        let skip: Vec<String> = types.iter().map(|name| name.to_lowercase()).collect();
+        self.options.excluded_types = excluded_types(&skip, &[]);
+        self
+    }
+    /// Object types collected and written, like `["computers", "enterprisecas"]`.
+    pub fn only(mut self, types: &[&str]) -> Self {
+        let only: Vec<String> = types.iter().map(|name| name.to_lowercase()).collect();
+        self.options.excluded_types = excluded_types(&[], &only);
+        self
+    }
+    /// Refresh the AD CS objects only, the principals of their ACEs are resolved on the Global Catalog.
+    pub fn adcs_only(mut self, adcs_only: bool) -> Self {
+        self.options.adcs_only = adcs_only;
+        self.options.excluded_types = match adcs_only {
+            true => adcs_excluded_types(),
+            false => Vec::new(),
+        };
+        self
+    }
+    /// Write an empty file for each type left out by [`CollectorBuilder::skip`] or [`CollectorBuilder::only`].
+    pub fn empty_skipped(mut self, empty_skipped: bool) -> Self {
+        self.options.empty_skipped = empty_skipped;
+        self
+    }
+    /// Collect the deleted objects of the AD Recycle Bin.
+    pub fn deleted_objects(mut self, deleted_objects: bool) -> Self {
+        self.options.deleted_objects = deleted_objects;
+        self
+    }
+    /// Collect the A and AAAA records of the AD-integrated DNS zones, written in `<domain>_dns.json`.
+    pub fn dns(mut self, dns: bool) -> Self {
+        self.options.dns = dns;
+        self
+    }
+    /// Incremental collection: only the objects changed since the USNs of the state file, recorded in it by [`Collector::write`].
+    pub fn cache_state(mut self, path: &str) -> Self {
+        self.options.cache_state = Some(path.to_string());
+        self
+    }
+    /// Request all the attributes (`*`) instead of the ones read by the parsers.
+    pub fn all_attributes(mut self, all_attributes: bool) -> Self {
+        self.options.all_attributes = all_attributes;
+        self
+    }
+    /// SharpHound collection methods, like `CollectionMethod::DC_ONLY`: only the LDAP ones are collected.
+    pub fn collection_method(mut self, collection_method: CollectionMethod) -> Self {
+        self.options.collection_method = collection_method;
+        self
+    }
+    /// Output directory for the json files.
+    pub fn output(mut self, path: &str) -> Self {
+        self.options.path = path.to_string();
+        self
+    }
+    /// Compress the json files into a zip archive.
+    pub fn zip(mut self, zip: bool) -> Self {
+        self.options.zip = zip;
+        self
+    }
+    /// Also write all objects in one NDJSON file.
+    pub fn ndjson(mut self, ndjson: bool) -> Self {
+        self.options.ndjson = ndjson;
+        self
+    }
+    /// Upload the json files to BloodHound CE with an API token.
+    pub fn upload(mut self, url: &str, token_id: &str, token_key: &str) -> Self {
+        self.options.bh_url = Some(url.to_string());
+        self.options.bh_token_id = Some(token_id.to_string());
+        self.options.bh_token_key = Some(token_key.to_string());
+        self
+    }
+    /// Score users and computers on deception indicators.
+    pub fn honeypot(mut self, honeypot: bool) -> Self {
+        self.options.honeypot = honeypot;
+        self
+    }
+    /// Mark the ACEs not matching the schema default security descriptors.
+    pub fn acl_baseline(mut self, acl_baseline: bool) -> Self {
+        self.options.acl_baseline = acl_baseline;
+        self
+    }
+    /// Configuration loaded from a TOML file, see [`crate::config`].
+    pub fn config(mut self, config: Config) -> Self {
+        self.options.config = config;
+        self
+    }
+    /// Explain the ACEs making or suppressing the edges of one object or principal.
+    pub fn explain_edges(mut self, target: &str) -> Self {
+        self.options.explain_edges = Some(target.to_string());
+        self
+    }
+    /// Filter the ACEs written in the output, see [`crate::json::checker::acefilter::AceFilter`].
+    pub fn ace_filter(mut self, filter: &str) -> Self {
+        self.options.ace_filter = Some(filter.to_string());
+        self
+    }
+    /// Resolve the foreign principals with the Global Catalog, see [`crate::resolver`].
+    pub fn resolve_foreign(mut self, resolve_foreign: bool) -> Self {
+        self.options.resolve_foreign = resolve_foreign;
+        self
+    }
+    /// Also write the analysis reports as JSON, see [`crate::json::checker::tierzero`] and [`crate::json::checker::roastable`].
+    pub fn reports(mut self, reports: bool) -> Self {
+        self.options.reports = reports;
+        self
+    }
+    /// Keep the disabled accounts in the delegation edges and the analysis reports.
+    pub fn include_disabled(mut self, include_disabled: bool) -> Self {
+        self.options.include_disabled = include_disabled;
+        self
+    }
+    /// Also write the raw LDAP entries of [`Collector::search`] to a dump file.
+    pub fn dump(mut self, path: &str) -> Self {
+        self.options.dump = Some(path.to_owned());
+        self
+    }
+    /// Threads parsing the LDAP entries, 0 for all the cores and 1 for the sequential parsing.
+    pub fn parse_threads(mut self, parse_threads: usize) -> Self {
+        self.options.parse_threads = parse_threads;
+        self
+    }
+    /// Compare the collection with SharpHound CE in `<domain>_coverage.txt`, see [`crate::coverage`].
+    pub fn coverage_report(mut self, coverage_report: bool) -> Self {
+        self.options.coverage_report = coverage_report;
+        self
+    }
+    /// Record the source of each property group in `<domain>_provenance.json`, see [`crate::provenance`].
+    pub fn provenance(mut self, provenance: bool) -> Self {
+        self.options.provenance = provenance;
+        self
+    }
+    /// Apply a collection profile, the setters called after it keep their value.
+    pub fn profile(mut self, profile: &Profile) -> Self {
+        // Only the built-in profiles, their settings are always valid
+        profile.apply(&mut self.options, &[]).ok();
+        self
+    }
+    /// Time box of the run, the most valuable objects are requested first, see [`crate::deadline`].
+    pub fn deadline(mut self, deadline: std::time::Duration) -> Self {
+        self.options.deadline = Some(deadline);
+        self
+    }
+    /// Collection order with a deadline, like `["users", "groups"]`.
+    pub fn collection_priority(mut self, priority: &[&str]) -> Self {
+        self.options.collection_priority = priority.iter().map(|category| category.to_string()).collect();
+        self
+    }
+    /// Wait this many milliseconds between two LDAP pages and between two LDAP queries.
+    pub fn throttle(mut self, throttle: u64) -> Self {
+        self.options.throttle = throttle;
+        self
+    }
+    /// Randomize each throttle wait by up to this percentage.
+    pub fn jitter(mut self, jitter: u8) -> Self {
+        self.options.jitter = jitter.min(100);
+        self
+    }
+    /// Entries per LDAP page, lowered when the DC refuses the pages.
+    pub fn page_size(mut self, page_size: i32) -> Self {
+        self.options.page_size = page_size;
+        self
+    }
+    /// Parts of nTSecurityDescriptor requested: 1 owner, 2 group, 4 DACL, 8 SACL.
+    pub fn sd_flags(mut self, sd_flags: u32) -> Self {
+        self.options.sd_flags = sd_flags;
+        self
+    }
+    /// Retries when the connection to the DC drops or the DC is busy during a search, and the seconds to wait before
+    /// the first one, doubled for each next one.
+    pub fn retries(mut self, retries: u32, delay: u64) -> Self {
+        self.options.retries = retries;
+        self.options.retry_delay = delay;
+        self
+    }
+    /// Longest wait before a retry, in seconds.
+    pub fn retry_max_delay(mut self, max_delay: u64) -> Self {
+        self.options.retry_max_delay = max_delay;
+        self
+    }
+    /// Seconds to wait for the connection and the bind to a DC, and for each page of a search.
+    pub fn timeouts(mut self, connect: u64, search: u64) -> Self {
+        self.options.connect_timeout = connect;
+        self.options.search_timeout = search;
+        self
+    }
+    /// JSON file to extend or adjust the well-known SIDs mapping.
+    pub fn well_known_overrides(mut self, path: &str) -> Self {
+        self.options.well_known_overrides = Some(path.to_string());
+        self
+    }
+
+    /// Build the collector.
+    pub fn build(self) -> Collector {
+        Collector::from_options(self.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::collector::Collector;
+
+    #[test]
+    pub fn test_collector_builder() {
+        let collector = Collector::builder("contoso.local")
+            .ldapfqdn("dc01.contoso.local")
+            .credentials("user", "Password123")
+            .ldaps(true)
+            .output("/tmp/bloodhound")
+            .build();
+
+        let options = collector.options();
+        assert_eq!(options.domain, "contoso.local");
+        assert_eq!(options.ldapfqdn, "dc01.contoso.local");
+        assert_eq!(options.username.as_deref(), Some("user"));
+        assert!(options.ldaps);
+        assert_eq!(options.path, "/tmp/bloodhound");
+        assert_eq!(options.ldap_filter, "(objectClass=*)");
+        assert!(!options.zip);
+        assert_eq!(options.credentials_for("gc"), None);
+    }
+
+    #[test]
+    pub fn test_collector_module_credentials() {
+        let collector = Collector::builder("contoso.local")
+            .credentials("user", "Password123")
+            .module_credentials("GC", "reader@contoso.local", Some("Reader123"))
+            .build();
+        let set = collector.options().credentials_for("gc").unwrap();
+        assert_eq!(set.username, "reader@contoso.local");
+        assert_eq!(set.password.as_deref(), Some("Reader123"));
+        assert_eq!(collector.options().username.as_deref(), Some("user"));
+    }
+}
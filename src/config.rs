@@ -0,0 +1,322 @@
+//! TOML configuration file loaded with `--config`
+//!
+//! Custom properties copy any LDAP attribute (asset tags, cost centers, custom schema extensions)
+//! into the output properties of one object class:
+//!
+//! ```toml
+//! [custom_properties.user]
+//! attribute = "extensionAttribute5"
+//! property = "costcenter"
+//! type = "string"
+//!
+//! [[custom_properties.computer]]
+//! attribute = "msDS-cloudExtensionAttribute1"
+//! property = "assettag"
+//! type = "int"
+//!
+//! [[custom_properties.computer]]
+//! attribute = "thumbnailPhoto"
+//! property = "photohash"
+//! type = "sha1"
+//! ```
+//!
+//! Object classes are `user`, `computer`, `group`, `ou`, `domain`, `gpo` and `container`.
+//! Types are `string`, `int`, `bool`, `filetime` (FILETIME or generalized time to epoch, -1 for never) and, for binary attributes, `sha1` and `base64`.
+//!
+//! `collection_priority = ["users", "groups", "adcs"]` sets the collection order with `--deadline`, see [`crate::deadline`].
+//!
+//! The `credentials` sections set the accounts of the modules binding on their own, see [`crate::credentials`].
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ldap3::SearchEntry;
+use log::warn;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::credentials::Credentials;
+use crate::deadline::collection_order;
+use crate::utils::crypto::calculate_sha1;
+use crate::utils::date::string_to_epoch;
+
+/// Object classes accepting custom properties.
+pub const CUSTOM_PROPERTY_CLASSES: &[&str] = &["user", "computer", "group", "ou", "domain", "gpo", "container"];
+
+/// Content of the `--config` TOML file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Custom properties per object class.
+    pub custom_properties: HashMap<String, CustomProperties>,
+    /// Credential sets per module.
+    pub credentials: BTreeMap<String, Credentials>,
+    /// Collection order with a deadline.
+    pub collection_priority: Vec<String>,
+}
+
+/// One or many custom properties for an object class.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum CustomProperties {
+    One(CustomProperty),
+    Many(Vec<CustomProperty>),
+}
+
+impl CustomProperties {
+    // Immutable access.
+    pub fn as_slice(&self) -> &[CustomProperty] {
+        match self {
+            CustomProperties::One(property) => std::slice::from_ref(property),
+            CustomProperties::Many(properties) => properties,
+        }
+    }
+}
+
+/// LDAP attribute copied in the output properties.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomProperty {
+    /// LDAP attribute name.
+    pub attribute: String,
+    /// Property name in the BloodHound json files.
+    pub property: String,
+    /// Conversion applied to the attribute value.
+    #[serde(rename = "type", default)]
+    pub kind: CustomPropertyType,
+}
+
+/// Conversion of a custom property value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CustomPropertyType {
+    #[default]
+    String,
+    Int,
+    Bool,
+    Filetime,
+    Sha1,
+    Base64,
+}
+
+impl Config {
+    /// Load and validate the configuration from a TOML file.
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)?;
+        Self::from_toml(&content).map_err(|err| format!("{path}: {err}").into())
+    }
+
+    /// Load and validate the configuration from a TOML string.
+    pub fn from_toml(content: &str) -> Result<Self, Box<dyn Error>> {
+        let config: Config = toml::from_str(content)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check the object classes, property names, credential sets and collection categories.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        collection_order(&self.collection_priority)?;
+        for (module, credentials) in &self.credentials {
+            credentials.validate(module)?;
+        }
+        for (class, properties) in &self.custom_properties {
+            if !CUSTOM_PROPERTY_CLASSES.contains(&class.as_str()) {
+                return Err(format!(
+                    "unknown object class '{class}' in custom_properties, expected one of: {}",
+                    CUSTOM_PROPERTY_CLASSES.join(", ")
+                ).into())
+            }
+            for property in properties.as_slice() {
+                if property.attribute.is_empty() || property.property.is_empty() {
+                    return Err(format!("custom_properties.{class}: attribute and property can't be empty").into())
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Custom attributes to request in addition to the default ones.
+    pub fn custom_attributes(&self) -> Vec<String> {
+        let mut attributes: Vec<String> = self.custom_properties.values()
+            .flat_map(|properties| properties.as_slice().iter().map(|p| p.attribute.to_owned()))
+            .collect();
+        attributes.sort();
+        attributes.dedup();
+        attributes
+    }
+
+    /// Custom property values for one LDAP entry of the given object class.
+    pub fn custom_values(&self, class: &str, entry: &SearchEntry) -> Map<String, Value> {
+        let mut values = Map::new();
+        let Some(properties) = self.custom_properties.get(class) else {
+            return values
+        };
+        for property in properties.as_slice() {
+            match convert_custom_property(property, entry) {
+                Ok(Some(value)) => {
+                    values.insert(property.property.to_owned(), value);
+                }
+                Ok(None) => {}
+                Err(err) => warn!("Custom property {} for {}: {err}", property.property, entry.dn),
+            }
+        }
+        values
+    }
+}
+
+/// Function to convert the attribute value of one entry, `None` if the entry doesn't have this attribute.
+pub fn convert_custom_property(property: &CustomProperty, entry: &SearchEntry) -> Result<Option<Value>, Box<dyn Error>> {
+    // LDAP attribute names are case insensitive
+    let text = entry.attrs.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(&property.attribute))
+        .map(|(_, values)| values);
+    let binary = entry.bin_attrs.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(&property.attribute))
+        .map(|(_, values)| values);
+
+    let values: Vec<Value> = match property.kind {
+        CustomPropertyType::Sha1 | CustomPropertyType::Base64 => {
+            let raw: Vec<&[u8]> = match (binary, text) {
+                (Some(values), _) => values.iter().map(|v| v.as_slice()).collect(),
+                (None, Some(values)) => values.iter().map(|v| v.as_bytes()).collect(),
+                (None, None) => return Ok(None),
+            };
+            raw.into_iter().map(|v| match property.kind {
+                CustomPropertyType::Sha1 => Value::String(calculate_sha1(v)),
+                _ => Value::String(STANDARD.encode(v)),
+            }).collect()
+        }
+        _ => {
+            let Some(values) = text else {
+                return Ok(None)
+            };
+            let mut converted = Vec::new();
+            for value in values {
+                converted.push(convert_text(property.kind, value)?);
+            }
+            converted
+        }
+    };
+
+    Ok(match values.len() {
+        0 => None,
+        1 => values.into_iter().next(),
+        _ => Some(Value::Array(values)),
+    })
+}
+
+/// Function to convert one text value.
+fn convert_text(kind: CustomPropertyType, value: &str) -> Result<Value, Box<dyn Error>> {
+    Ok(match kind {
+        CustomPropertyType::Int => Value::from(value.trim().parse::<i64>()?),
+        CustomPropertyType::Bool => match value.trim().to_uppercase().as_str() {
+            "TRUE" | "1" => Value::Bool(true),
+            "FALSE" | "0" => Value::Bool(false),
+            _ => return Err(format!("'{value}' is not a boolean").into()),
+        },
+        // FILETIME or generalized time, -1 for never
+        CustomPropertyType::Filetime => Value::from(string_to_epoch(value)?),
+        _ => Value::String(value.to_owned()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use ldap3::SearchEntry;
+    use serde_json::json;
+    use crate::config::*;
+
+    fn entry() -> SearchEntry {
+        SearchEntry {
+            dn: "CN=JDOE,CN=USERS,DC=CONTOSO,DC=LOCAL".to_string(),
+            attrs: HashMap::from([
+                ("extensionAttribute5".to_string(), vec!["CC-042".to_string()]),
+                ("employeeNumber".to_string(), vec!["1337".to_string()]),
+                ("msDS-cloudExtensionAttribute1".to_string(), vec!["TRUE".to_string()]),
+                ("accountExpires".to_string(), vec!["133500000000000000".to_string()]),
+                ("whenChanged".to_string(), vec!["20240101120000.0Z".to_string()]),
+                ("otherMailbox".to_string(), vec!["a".to_string(), "b".to_string()]),
+            ]),
+            bin_attrs: HashMap::from([
+                ("thumbnailPhoto".to_string(), vec![vec![0xff, 0xd8, 0xff]]),
+            ]),
+        }
+    }
+
+    #[test]
+    pub fn test_custom_property_conversions() {
+        let config = Config::from_toml(r#"
+            [[custom_properties.user]]
+            attribute = "extensionattribute5"
+            property = "costcenter"
+            type = "string"
+
+            [[custom_properties.user]]
+            attribute = "employeeNumber"
+            property = "employeenumber"
+            type = "int"
+
+            [[custom_properties.user]]
+            attribute = "msDS-cloudExtensionAttribute1"
+            property = "managed"
+            type = "bool"
+
+            [[custom_properties.user]]
+            attribute = "accountExpires"
+            property = "expires"
+            type = "filetime"
+
+            [[custom_properties.user]]
+            attribute = "whenChanged"
+            property = "changed"
+            type = "filetime"
+
+            [[custom_properties.user]]
+            attribute = "thumbnailPhoto"
+            property = "photohash"
+            type = "sha1"
+
+            [[custom_properties.user]]
+            attribute = "thumbnailPhoto"
+            property = "photo"
+            type = "base64"
+
+            [[custom_properties.user]]
+            attribute = "otherMailbox"
+            property = "mailboxes"
+
+            [[custom_properties.user]]
+            attribute = "departmentNumber"
+            property = "department"
+        "#).unwrap();
+
+        let values = config.custom_values("user", &entry());
+        assert_eq!(values["costcenter"], json!("CC-042"));
+        assert_eq!(values["employeenumber"], json!(1337));
+        assert_eq!(values["managed"], json!(true));
+        assert_eq!(values["expires"], json!(1705526400));
+        assert_eq!(values["changed"], json!(1704110400));
+        assert_eq!(values["photohash"], json!("A3FF16385BEA1D45349EA11CE23E4F337B7D4DD5"));
+        assert_eq!(values["photo"], json!("/9j/"));
+        assert_eq!(values["mailboxes"], json!(["a", "b"]));
+        assert!(values.get("department").is_none());
+        assert!(config.custom_values("computer", &entry()).is_empty());
+        assert!(config.custom_attributes().contains(&"thumbnailPhoto".to_string()));
+    }
+
+    #[test]
+    pub fn test_config_validation() {
+        let single = Config::from_toml("[custom_properties.computer]\nattribute = \"info\"\nproperty = \"assettag\"\n").unwrap();
+        assert_eq!(single.custom_properties["computer"].as_slice()[0].kind, CustomPropertyType::String);
+
+        // Unknown type
+        assert!(Config::from_toml("[custom_properties.user]\nattribute = \"info\"\nproperty = \"x\"\ntype = \"float\"\n").is_err());
+        // Unknown object class
+        let err = Config::from_toml("[custom_properties.printer]\nattribute = \"info\"\nproperty = \"x\"\n").unwrap_err();
+        assert!(err.to_string().contains("unknown object class 'printer'"));
+        // Unknown section
+        assert!(Config::from_toml("[custom_property.user]\nattribute = \"info\"\nproperty = \"x\"\n").is_err());
+    }
+}
@@ -0,0 +1,282 @@
+//! Configuration naming context checks
+//!
+//! A RODC or a DC with a stale replica of the configuration NC can return no `pKIEnrollmentService`
+//! object even if the forest has an ADCS PKI, which looks like "no PKI here" in BloodHound.
+//!
+//! The rootDSE tells if the configuration NC is hosted and synchronized on the target, the PKI objects
+//! collected (certificate templates, CA certificates) hint if enrollment services must exist.
+//! When they are missing, the `CN=Public Key Services` container is requested again on a writable DC
+//! of the forest root domain found with the `_ldap._tcp.dc._msdcs.<forest root>` SRV record.
+//! Every problem is recorded as a collection warning, shown in the summary and in the output meta.
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::sync::Mutex;
+
+use colored::Colorize;
+use ldap3::SearchEntry;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+
+/// supportedCapabilities OID only set by read-only domain controllers (LDAP_CAP_ACTIVE_DIRECTORY_PARTIAL_SECRETS_OID).
+const RODC_CAPABILITY: &str = "1.2.840.113556.1.4.1920";
+
+static COLLECTION_WARNINGS: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Function to record a warning about the collection completeness, also logged.
+pub fn add_collection_warning(message: &str) {
+    warn!("{}", message.bold().yellow());
+    COLLECTION_WARNINGS.lock().unwrap().push(message.to_owned());
+}
+
+/// Function to get and clear the collection warnings.
+pub fn take_collection_warnings() -> Vec<String> {
+    std::mem::take(&mut *COLLECTION_WARNINGS.lock().unwrap())
+}
+
+/// Configuration NC state on the target DC.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigNcStatus {
+    /// configurationNamingContext from the rootDSE.
+    pub naming_context: String,
+    /// Forest root domain DNS name, from the rootDSE rootDomainNamingContext.
+    pub forest_root: String,
+    /// Target DC host name.
+    pub dc: String,
+    /// The configuration NC is in the namingContexts of the target.
+    pub hosted: bool,
+    /// rootDSE isSynchronized, the DC finished its initial replication.
+    pub synchronized: bool,
+    /// The target is a read-only domain controller.
+    pub rodc: bool,
+    /// pKIEnrollmentService objects collected.
+    pub enrollment_services: usize,
+    /// Certificate templates and CA certificates objects collected.
+    pub pki_hints: usize,
+}
+
+impl ConfigNcStatus {
+    /// Read the configuration NC state from the rootDSE attributes.
+    pub fn from_root_dse(root_dse: &HashMap<String, Vec<String>>) -> Self {
+        let first = |name: &str| root_dse.get(name).and_then(|v| v.first()).cloned().unwrap_or_default();
+        let naming_context = first("configurationNamingContext");
+        let forest_root = first("rootDomainNamingContext")
+            .split(',')
+            .filter_map(|part| part.strip_prefix("DC=").or_else(|| part.strip_prefix("dc=")))
+            .collect::<Vec<&str>>()
+            .join(".");
+        ConfigNcStatus {
+            hosted: root_dse.get("namingContexts")
+                .is_some_and(|ncs| ncs.iter().any(|nc| nc.eq_ignore_ascii_case(&naming_context))),
+            // Not returned by every directory, only FALSE is a stale replica
+            synchronized: !first("isSynchronized").eq_ignore_ascii_case("FALSE"),
+            rodc: root_dse.get("supportedCapabilities").is_some_and(|caps| caps.iter().any(|c| c == RODC_CAPABILITY)),
+            dc: first("dnsHostName"),
+            naming_context,
+            forest_root,
+            ..Default::default()
+        }
+    }
+
+    /// Count the PKI objects of one configuration NC entry.
+    pub fn observe(&mut self, entry: &SearchEntry) {
+        let Some(classes) = entry.attrs.get("objectClass") else { return };
+        for class in classes {
+            match class.to_lowercase().as_str() {
+                "pkienrollmentservice" => self.enrollment_services += 1,
+                "pkicertificatetemplate" | "certificationauthority" => self.pki_hints += 1,
+                _ => {}
+            }
+        }
+    }
+
+    /// Reason to request the configuration NC again on a writable forest root DC, if any.
+    pub fn fallback_reason(&self) -> Option<String> {
+        if self.naming_context.is_empty() {
+            return None
+        }
+        if !self.hosted {
+            return Some(format!("Configuration NC {} not hosted by {}", self.naming_context, self.dc))
+        }
+        if self.enrollment_services > 0 {
+            return None
+        }
+        if self.pki_hints > 0 {
+            return Some(format!(
+                "No pKIEnrollmentService found on {} while {} certificate templates or CA certificates exist",
+                self.dc, self.pki_hints
+            ))
+        }
+        if self.rodc || !self.synchronized {
+            return Some(format!(
+                "No pKIEnrollmentService found on {}, a {} may not have a complete configuration NC",
+                self.dc,
+                if self.rodc { "read-only DC" } else { "not synchronized DC" }
+            ))
+        }
+        None
+    }
+
+    /// Base of the configuration NC request on the fallback DC.
+    pub fn public_key_services(&self) -> String {
+        format!("CN=Public Key Services,CN=Services,{}", self.naming_context)
+    }
+}
+
+/// LDAP operations used by the configuration NC fallback.
+pub(crate) trait ConfigNcFallback {
+    /// Writable DCs of the forest root domain.
+    async fn writable_dcs(&mut self, forest_root: &str) -> Vec<String>;
+    /// Request all entries under `base` on one DC.
+    async fn search(&mut self, dc: &str, base: &str) -> Result<Vec<SearchEntry>, Box<dyn Error>>;
+}
+
+/// Entries and warnings of the configuration NC fallback.
+#[derive(Debug, Default)]
+pub(crate) struct ConfigNcRetry {
+    /// New entries not already collected on the target DC.
+    pub entries: Vec<SearchEntry>,
+    /// Warnings about the configuration NC completeness.
+    pub warnings: Vec<String>,
+}
+
+/// Function to request the PKI objects on a writable forest root DC when the target configuration NC looks incomplete.
+/// `collected` are the uppercase DNs already collected in the configuration NC.
+pub(crate) async fn retry_config_nc<F: ConfigNcFallback>(
+    status: &ConfigNcStatus,
+    collected: &HashSet<String>,
+    fallback: &mut F,
+) -> ConfigNcRetry {
+    let mut retry = ConfigNcRetry::default();
+    let reason = match status.fallback_reason() {
+        Some(reason) => reason,
+        None => return retry,
+    };
+    retry.warnings.push(reason);
+
+    let base = status.public_key_services();
+    for dc in fallback.writable_dcs(&status.forest_root).await {
+        if dc.eq_ignore_ascii_case(&status.dc) {
+            continue
+        }
+        info!("Requesting {} on {}", base.bold(), dc.bold().green());
+        let entries = match fallback.search(&dc, &base).await {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!("Configuration NC request failed on {}: {err}", dc.bold().red());
+                continue
+            }
+        };
+        let mut found = ConfigNcStatus::default();
+        entries.iter().for_each(|entry| found.observe(entry));
+        retry.entries = entries.into_iter()
+            .filter(|entry| !collected.contains(&entry.dn.to_uppercase()))
+            .collect();
+        if found.enrollment_services == 0 {
+            retry.warnings.push(format!("No pKIEnrollmentService found on the writable DC {dc} either"));
+        } else {
+            info!("{} pKIEnrollmentService found on {}", found.enrollment_services.to_string().bold(), dc.bold().green());
+        }
+        return retry
+    }
+    retry.warnings.push(format!(
+        "No writable DC of the forest root {} answered, ADCS data may be incomplete",
+        status.forest_root
+    ));
+    retry
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::{HashMap, HashSet};
+    use std::error::Error;
+    use ldap3::SearchEntry;
+
+    use crate::confignc::*;
+
+    const CONFIG_NC: &str = "CN=Configuration,DC=CONTOSO,DC=LOCAL";
+
+    fn entry(dn: &str, class: &str) -> SearchEntry {
+        SearchEntry {
+            dn: format!("{dn},{CONFIG_NC}"),
+            attrs: HashMap::from([("objectClass".to_string(), vec!["top".to_string(), class.to_string()])]),
+            bin_attrs: HashMap::new(),
+        }
+    }
+
+    /// Fallback DC returning the enrollment service missing on the RODC.
+    #[derive(Default)]
+    struct FakeFallback {
+        dcs: Vec<String>,
+        attempts: Vec<(String, String)>,
+    }
+
+    impl ConfigNcFallback for FakeFallback {
+        async fn writable_dcs(&mut self, _forest_root: &str) -> Vec<String> {
+            self.dcs.to_owned()
+        }
+        async fn search(&mut self, dc: &str, base: &str) -> Result<Vec<SearchEntry>, Box<dyn Error>> {
+            self.attempts.push((dc.to_owned(), base.to_owned()));
+            Ok(vec![
+                entry("CN=User,CN=Certificate Templates,CN=Public Key Services,CN=Services", "pKICertificateTemplate"),
+                entry("CN=CONTOSO-CA,CN=Enrollment Services,CN=Public Key Services,CN=Services", "pKIEnrollmentService"),
+            ])
+        }
+    }
+
+    fn rodc_status() -> ConfigNcStatus {
+        let root_dse = HashMap::from([
+            ("configurationNamingContext".to_string(), vec![CONFIG_NC.to_string()]),
+            ("rootDomainNamingContext".to_string(), vec!["DC=CONTOSO,DC=LOCAL".to_string()]),
+            ("namingContexts".to_string(), vec!["DC=CHILD,DC=CONTOSO,DC=LOCAL".to_string(), CONFIG_NC.to_string()]),
+            ("supportedCapabilities".to_string(), vec!["1.2.840.113556.1.4.800".to_string(), "1.2.840.113556.1.4.1920".to_string()]),
+            ("isSynchronized".to_string(), vec!["TRUE".to_string()]),
+            ("dnsHostName".to_string(), vec!["rodc01.child.contoso.local".to_string()]),
+        ]);
+        ConfigNcStatus::from_root_dse(&root_dse)
+    }
+
+    #[test]
+    pub fn test_config_nc_status() {
+        let mut status = rodc_status();
+        assert!(status.hosted && status.synchronized && status.rodc);
+        assert_eq!(status.forest_root, "CONTOSO.LOCAL");
+        assert!(status.fallback_reason().is_some());
+
+        status.observe(&entry("CN=CONTOSO-CA,CN=Enrollment Services,CN=Public Key Services,CN=Services", "pKIEnrollmentService"));
+        assert_eq!(status.fallback_reason(), None);
+
+        // Writable and synchronized DC without any PKI object: no ADCS
+        let status = ConfigNcStatus { rodc: false, ..rodc_status() };
+        assert_eq!(status.fallback_reason(), None);
+    }
+
+    #[tokio::test]
+    async fn test_empty_config_nc_fallback() {
+        let mut status = ConfigNcStatus { rodc: false, ..rodc_status() };
+        let template = entry("CN=User,CN=Certificate Templates,CN=Public Key Services,CN=Services", "pKICertificateTemplate");
+        status.observe(&template);
+        let collected = HashSet::from([template.dn.to_uppercase()]);
+
+        let mut fallback = FakeFallback {
+            dcs: vec!["rodc01.child.contoso.local".to_string(), "dc01.contoso.local".to_string()],
+            ..Default::default()
+        };
+        let retry = retry_config_nc(&status, &collected, &mut fallback).await;
+        assert_eq!(retry.warnings.len(), 1);
+        assert!(retry.warnings[0].contains("No pKIEnrollmentService found on rodc01.child.contoso.local"));
+        assert_eq!(fallback.attempts, vec![(
+            "dc01.contoso.local".to_string(),
+            format!("CN=Public Key Services,CN=Services,{CONFIG_NC}"),
+        )]);
+        // Only the enrollment service is new
+        assert_eq!(retry.entries.len(), 1);
+        assert!(retry.entries[0].dn.starts_with("CN=CONTOSO-CA"));
+
+        // No writable DC found: only the warnings
+        let retry = retry_config_nc(&status, &collected, &mut FakeFallback::default()).await;
+        assert!(retry.entries.is_empty());
+        assert_eq!(retry.warnings.len(), 2);
+        assert!(retry.warnings[1].contains("No writable DC of the forest root CONTOSO.LOCAL answered"));
+    }
+}
@@ -0,0 +1,270 @@
+//! LDAP query coverage compared with SharpHound CE
+//!
+//! With `--coverage-report` the LDAP search records the requested attributes, the parser the
+//! attributes found for each object type and the output makers the edge kinds and collection
+//! methods written. Once the json files are made they are compared with the embedded
+//! SharpHound CE manifest (`src/coverage/sharphound.toml`) in `<domain>_coverage.txt`: what is
+//! not collected and the RustHound-CE only extras.
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use ldap3::SearchEntry;
+use log::info;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// SharpHound CE collection behavior, also the parity roadmap.
+pub const SHARPHOUND_MANIFEST: &str = include_str!("coverage/sharphound.toml");
+
+/// Object relations written as json properties, and the edge kind they make.
+const RELATION_EDGES: &[(&str, &str)] = &[
+    ("Members", "MemberOf"),
+    ("ChildObjects", "Contains"),
+    ("Links", "GPLink"),
+    ("AllowedToDelegate", "AllowedToDelegate"),
+    ("AllowedToAct", "AllowedToAct"),
+    ("HasSIDHistory", "HasSIDHistory"),
+    ("DumpSMSAPassword", "DumpSMSAPassword"),
+    ("Trusts", "TrustedBy"),
+];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static COVERAGE: Lazy<Mutex<Coverage>> = Lazy::new(|| Mutex::new(Coverage::default()));
+
+/// SharpHound CE manifest.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Manifest {
+    /// Edge kinds.
+    pub edges: Vec<String>,
+    /// LDAP attributes read for each RustHound-CE object type.
+    pub classes: BTreeMap<String, Vec<String>>,
+    /// Collection methods not based on LDAP.
+    #[serde(default)]
+    pub methods: Vec<ManifestMethod>,
+}
+
+/// One collection method of the manifest.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ManifestMethod {
+    pub name: String,
+    /// Json property holding the method results.
+    pub key: String,
+    pub description: String,
+}
+
+impl Manifest {
+    /// Load the embedded SharpHound CE manifest.
+    pub fn sharphound() -> Result<Self, Box<dyn Error>> {
+        Ok(toml::from_str(SHARPHOUND_MANIFEST)?)
+    }
+}
+
+/// What was requested and written during the collection.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    /// Attributes requested in the LDAP search.
+    pub requested: BTreeSet<String>,
+    /// Lowercase attributes found for each object type.
+    pub attributes: BTreeMap<String, BTreeSet<String>>,
+    /// Edge kinds written.
+    pub edges: BTreeSet<String>,
+    /// Json properties of the collection methods with collected results.
+    pub methods: BTreeSet<String>,
+}
+
+/// Function to start the coverage bookkeeping, the records already made are kept.
+pub fn enable_coverage() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Function to check if the coverage bookkeeping is enabled.
+pub fn is_coverage_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Function to stop the coverage bookkeeping and get the records.
+pub fn take_coverage() -> Coverage {
+    ENABLED.store(false, Ordering::SeqCst);
+    std::mem::take(&mut *COVERAGE.lock().unwrap())
+}
+
+/// Function to record the attributes requested in the LDAP search.
+pub fn record_requested(attributes: &[&str]) {
+    if !is_coverage_enabled() {
+        return
+    }
+    COVERAGE.lock().unwrap().requested.extend(attributes.iter().map(|a| a.to_string()));
+}
+
+/// Function to record the attributes of one LDAP entry.
+pub fn record_entry(entry_type: &str, entry: &SearchEntry) {
+    if !is_coverage_enabled() {
+        return
+    }
+    let mut coverage = COVERAGE.lock().unwrap();
+    coverage.attributes.entry(entry_type.to_owned()).or_default()
+        .extend(entry.attrs.keys().chain(entry.bin_attrs.keys()).map(|a| a.to_lowercase()));
+}
+
+/// Function to record the edge kinds and collection methods of one output object.
+pub fn record_object(object: &Value) {
+    if !is_coverage_enabled() {
+        return
+    }
+    let mut coverage = COVERAGE.lock().unwrap();
+    coverage.record_object(object);
+}
+
+impl Coverage {
+    fn record_object(&mut self, object: &Value) {
+        for ace in object["Aces"].as_array().into_iter().flatten() {
+            if let Some(right) = ace["RightName"].as_str() {
+                self.edges.insert(right.to_owned());
+            }
+        }
+        for target in object["SPNTargets"].as_array().into_iter().flatten() {
+            if let Some(service) = target["Service"].as_str() {
+                self.edges.insert(service.to_owned());
+            }
+        }
+        for (key, edge) in RELATION_EDGES {
+            if object[key].as_array().is_some_and(|values| !values.is_empty()) {
+                self.edges.insert(edge.to_string());
+            }
+        }
+        // Collection methods results: {"Collected": true, "Results": [...]} or a list of them
+        if let Some(properties) = object.as_object() {
+            for (key, value) in properties {
+                let collected = |v: &Value| v["Collected"].as_bool() == Some(true);
+                if collected(value) || value.as_array().is_some_and(|values| values.iter().any(collected)) {
+                    self.methods.insert(key.to_owned());
+                }
+            }
+        }
+    }
+
+    /// Compare with the manifest and make the text report.
+    pub fn report(&self, manifest: &Manifest) -> String {
+        let mut missing_methods = Vec::new();
+        for method in &manifest.methods {
+            if !self.methods.contains(&method.key) {
+                missing_methods.push(format!("{} ({})", method.name, method.description));
+            }
+        }
+        let missing_edges: Vec<&String> = manifest.edges.iter().filter(|e| !self.edges.contains(*e)).collect();
+        let extra_edges: Vec<&String> = self.edges.iter().filter(|e| !manifest.edges.contains(e)).collect();
+
+        let mut report = String::from("RustHound-CE coverage compared with SharpHound CE\n\n");
+        report += &format!("Requested attributes: {}\n\n", join(self.requested.iter()));
+
+        report += "Not collected:\n";
+        for method in &missing_methods {
+            report += &format!("  - {method}\n");
+        }
+        if !missing_edges.is_empty() {
+            report += &format!("  - edges: {}\n", join(missing_edges.iter()));
+        }
+        for (class, attributes) in &manifest.classes {
+            let Some(found) = self.attributes.get(class) else {
+                report += &format!("  - {class}: no object collected\n");
+                continue
+            };
+            let missing: Vec<&String> = attributes.iter().filter(|a| !found.contains(&a.to_lowercase())).collect();
+            if !missing.is_empty() {
+                report += &format!("  - {class} attributes: {}\n", join(missing.iter()));
+            }
+        }
+
+        report += "\nRustHound-CE only:\n";
+        if !extra_edges.is_empty() {
+            report += &format!("  - edges: {}\n", join(extra_edges.iter()));
+        }
+        let extra_requested: Vec<&String> = self.requested.iter()
+            .filter(|a| *a != "*" && !manifest.classes.values().flatten().any(|m| m.eq_ignore_ascii_case(a)))
+            .collect();
+        if !extra_requested.is_empty() {
+            report += &format!("  - attributes: {}\n", join(extra_requested.iter()));
+        }
+        report
+    }
+}
+
+fn join<T: AsRef<str>>(values: impl Iterator<Item = T>) -> String {
+    values.map(|v| v.as_ref().to_owned()).collect::<Vec<String>>().join(", ")
+}
+
+/// Function to compare the recorded coverage with SharpHound CE in `{path}/{domain}_coverage.txt`.
+pub fn write_coverage_report(path: &str, domain: &str) -> Result<Option<String>, Box<dyn Error>> {
+    if !is_coverage_enabled() {
+        return Ok(None)
+    }
+    let report = take_coverage().report(&Manifest::sharphound()?);
+    std::fs::create_dir_all(path)?;
+    let file = format!("{}/{}_coverage.txt", path.trim_end_matches('/'), domain.to_lowercase());
+    std::fs::write(&file, &report)?;
+    info!("Coverage report written to {file}");
+    Ok(Some(file))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use ldap3::SearchEntry;
+    use serde_json::json;
+
+    use crate::coverage::*;
+
+    #[test]
+    pub fn test_sharphound_manifest() {
+        let manifest = Manifest::sharphound().unwrap();
+        assert!(manifest.edges.iter().any(|e| e == "GenericAll"));
+        assert!(manifest.classes["User"].iter().any(|a| a == "userPassword"));
+        assert!(manifest.methods.iter().any(|m| m.name == "Sessions"));
+    }
+
+    #[test]
+    pub fn test_coverage_report() {
+        let manifest: Manifest = toml::from_str(r#"
+            edges = ["GenericAll", "MemberOf", "AdminTo"]
+            [classes]
+            User = ["sAMAccountName", "userPassword"]
+            Gpo = ["gPCFileSysPath"]
+            [[methods]]
+            name = "Sessions"
+            key = "Sessions"
+            description = "NetSessionEnum"
+            [[methods]]
+            name = "LocalGroups"
+            key = "LocalGroups"
+            description = "SAMR"
+        "#).unwrap();
+
+        let mut coverage = Coverage::default();
+        coverage.requested.extend(["*".to_string(), "nTSecurityDescriptor".to_string(), "msDS-ReplAttributeMetaData".to_string()]);
+        let entry = SearchEntry {
+            dn: "CN=JDOE,CN=USERS,DC=CONTOSO,DC=LOCAL".to_string(),
+            attrs: HashMap::from([("sAMAccountName".to_string(), vec!["jdoe".to_string()])]),
+            bin_attrs: HashMap::new(),
+        };
+        coverage.attributes.entry("User".to_string()).or_default()
+            .extend(entry.attrs.keys().map(|a| a.to_lowercase()));
+        coverage.record_object(&json!({
+            "Aces": [{"RightName": "GenericAll"}, {"RightName": "WriteGPLink"}],
+            "Members": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1105"}],
+            "LocalGroups": [{"Collected": false, "Results": []}],
+            "Sessions": {"Collected": true, "Results": []},
+        }));
+
+        let report = coverage.report(&manifest);
+        assert!(report.contains("  - LocalGroups (SAMR)\n"));
+        assert!(!report.contains("Sessions (NetSessionEnum)"));
+        assert!(report.contains("  - edges: AdminTo\n"));
+        assert!(report.contains("  - User attributes: userPassword\n"));
+        assert!(report.contains("  - Gpo: no object collected\n"));
+        assert!(report.contains("RustHound-CE only:\n  - edges: WriteGPLink\n  - attributes: msDS-ReplAttributeMetaData, nTSecurityDescriptor\n"));
+    }
+}
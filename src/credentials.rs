@@ -0,0 +1,372 @@
+//! Credential sets of the modules binding on their own
+//!
+//! The main LDAP collection binds with `-u`/`-p` (the primary credentials). A module with its own bind can
+//! use another account, like a low privileged account for the domain and a forest account for the Global
+//! Catalog. Sets are given with `--module-credentials <module>=<username>[:<password>]` or in the
+//! `--config` file:
+//!
+//! ```toml
+//! [credentials.gc]
+//! username = "reader@corp.local"
+//! password = "Password123"
+//! ```
+//!
+//! - `--module-credentials` takes precedence over the configuration file, a module without set uses the primary credentials
+//! - a set without password prompts for it on first use, like the primary credentials
+//! - each set is validated on its own before any LDAP request
+//! - passwords are never logged (`Debug` shows `<redacted>`) and are zeroed in memory when the set is dropped
+//!
+//! The primary password is read, in order, from `-p`, `--password-file` (first line) and the `RUSTHOUND_PASSWORD`
+//! environment variable, otherwise it is prompted without echo when the bind needs it. It is held in a [`Secret`].
+//! With `--nt-hash` the NT hash of the account replaces the password ([`BindSecret`]), for NTLM binds only.
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::ops::Deref;
+
+use serde::Deserialize;
+
+use crate::utils::ntlm::{nt_hash, parse_nt_hash};
+
+/// Modules accepting their own credential set, and what they bind to.
+pub const CREDENTIAL_MODULES: &[(&str, &str)] = &[
+    ("gc", "Global Catalog lookups of --resolve-foreign"),
+    ("forest-root", "PKI objects retry on a writable forest root DC"),
+];
+
+/// Shown instead of the passwords.
+pub const REDACTED: &str = "<redacted>";
+
+/// Environment variable of the primary password, keeps it out of the shell history and the process list.
+pub const PASSWORD_ENV: &str = "RUSTHOUND_PASSWORD";
+
+/// Password zeroed in memory when dropped, `<redacted>` in `Debug`.
+#[derive(Clone, Default, PartialEq)]
+pub struct Secret(String);
+
+impl Secret {
+    /// New secret, taking the ownership of the buffer so no copy is left behind.
+    pub fn new(secret: String) -> Self {
+        Self(secret)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(secret: &str) -> Self {
+        Self(secret.to_string())
+    }
+}
+
+impl Deref for Secret {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        zeroize(&mut self.0);
+    }
+}
+
+/// Secret of a password bind: the password, or the NT hash of `--nt-hash` (pass-the-hash, NTLM bind only).
+#[derive(Debug, Clone, PartialEq)]
+pub enum BindSecret {
+    Password(Secret),
+    /// Hexadecimal NT hash, checked with [`parse_nt_hash`].
+    NtHash(Secret),
+}
+
+impl Default for BindSecret {
+    fn default() -> Self {
+        BindSecret::Password(Secret::default())
+    }
+}
+
+impl BindSecret {
+    /// Function to get the NT hash of the account, computed from the password.
+    pub fn nt_hash(&self) -> Result<[u8; 16], String> {
+        match self {
+            BindSecret::Password(password) => Ok(nt_hash(password)),
+            BindSecret::NtHash(hash) => parse_nt_hash(hash),
+        }
+    }
+}
+
+/// Username and optional password of one module.
+#[derive(Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Credentials {
+    pub username: String,
+    /// Prompted on first use when not set.
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+impl Credentials {
+    /// New credential set.
+    pub fn new(username: &str, password: Option<&str>) -> Self {
+        Self {
+            username: username.to_string(),
+            password: password.map(|password| password.to_string()),
+        }
+    }
+
+    /// Parse `<module>=<username>[:<password>]`, the password can contain `:`.
+    pub fn parse(value: &str) -> Result<(String, Self), Box<dyn Error>> {
+        let Some((module, set)) = value.split_once('=') else {
+            return Err(format!("'{}' is not <module>=<username>[:<password>]", redact_value(value)).into())
+        };
+        let credentials = match set.split_once(':') {
+            Some((username, password)) => Self::new(username, Some(password)),
+            None => Self::new(set, None),
+        };
+        let module = module.trim().to_lowercase();
+        credentials.validate(&module)?;
+        Ok((module, credentials))
+    }
+
+    /// Check the module name and the set values.
+    pub fn validate(&self, module: &str) -> Result<(), Box<dyn Error>> {
+        if !CREDENTIAL_MODULES.iter().any(|(name, _)| *name == module) {
+            return Err(format!(
+                "unknown module '{module}' for credentials, expected one of: {}",
+                CREDENTIAL_MODULES.iter().map(|(name, _)| *name).collect::<Vec<&str>>().join(", ")
+            ).into())
+        }
+        if self.username.trim().is_empty() {
+            return Err(format!("credentials for {module}: username can't be empty").into())
+        }
+        if self.username.contains(char::is_whitespace) {
+            return Err(format!("credentials for {module}: username '{}' can't contain whitespace", self.username).into())
+        }
+        if self.password.as_deref() == Some("") {
+            return Err(format!("credentials for {module}: password can't be empty, omit it to be prompted").into())
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .finish()
+    }
+}
+
+impl Drop for Credentials {
+    fn drop(&mut self) {
+        if let Some(password) = self.password.as_mut() {
+            zeroize(password);
+        }
+    }
+}
+
+/// Function to overwrite a secret in memory before its buffer is released.
+pub fn zeroize(secret: &mut String) {
+    let mut bytes = std::mem::take(secret).into_bytes();
+    bytes.fill(0);
+    // Keep the writes, the buffer is freed just after
+    std::hint::black_box(&bytes);
+}
+
+/// Function to get the primary password: `-p`, then the first line of `--password-file`, then the
+/// `RUSTHOUND_PASSWORD` value. None when nothing is set, the password is prompted by the bind.
+pub fn resolve_password(
+    cli: Option<&str>,
+    file: Option<&str>,
+    env: Option<String>,
+) -> Result<Option<Secret>, Box<dyn Error>> {
+    if let Some(password) = cli {
+        return Ok(Some(Secret::from(password)))
+    }
+    if let Some(path) = file {
+        return read_password_file(path).map(Some)
+    }
+    Ok(env.filter(|password| !password.is_empty()).map(Secret::new))
+}
+
+/// Function to read the password of `--password-file`: its first line without the line ending.
+pub fn read_password_file(path: &str) -> Result<Secret, Box<dyn Error>> {
+    // The whole content is zeroed too when dropped
+    let content = Secret::new(
+        std::fs::read_to_string(path).map_err(|err| format!("password file {path}: {err}"))?
+    );
+    let password = Secret::from(content.lines().next().unwrap_or_default());
+    if password.is_empty() {
+        return Err(format!("password file {path}: the first line is empty").into())
+    }
+    Ok(password)
+}
+
+/// Function to prompt for a password without echo, on the Unix tty or the Windows console.
+pub fn prompt_password(prompt: &str) -> Result<Secret, Box<dyn Error>> {
+    Ok(Secret::new(rpassword::prompt_password(prompt)?))
+}
+
+/// Function to hide the password of a `<module>=<username>:<password>` value in the errors.
+fn redact_value(value: &str) -> String {
+    match value.split_once(':') {
+        Some((before, _)) => format!("{before}:{REDACTED}"),
+        None => value.to_string(),
+    }
+}
+
+/// Function to merge the configuration file sets with the command line ones, the command line wins.
+/// A module given twice on the command line is an error.
+pub fn merge_credentials(
+    config: &BTreeMap<String, Credentials>,
+    cli: Vec<(String, Credentials)>,
+) -> Result<BTreeMap<String, Credentials>, Box<dyn Error>> {
+    let mut sets = config.to_owned();
+    let mut seen = Vec::new();
+    for (module, credentials) in cli {
+        if seen.contains(&module) {
+            return Err(format!("credentials for {module} given twice").into())
+        }
+        seen.push(module.to_owned());
+        sets.insert(module, credentials);
+    }
+    Ok(sets)
+}
+
+/// Function to check every set on its own before the collection, the first invalid one is the error.
+pub fn validate_credentials(sets: &BTreeMap<String, Credentials>, kerberos: bool) -> Result<(), Box<dyn Error>> {
+    for (module, credentials) in sets {
+        credentials.validate(module)?;
+        if kerberos {
+            return Err(format!("credentials for {module}: module credentials need a simple bind, not Kerberos").into())
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::BTreeMap;
+
+    use crate::args::Options;
+    use crate::config::Config;
+    use crate::credentials::*;
+
+    #[test]
+    pub fn test_parse_module_credentials() {
+        let (module, credentials) = Credentials::parse("gc=reader@corp.local:Pass:word=1").unwrap();
+        assert_eq!(module, "gc");
+        assert_eq!(credentials, Credentials::new("reader@corp.local", Some("Pass:word=1")));
+
+        // Prompted password
+        let (module, credentials) = Credentials::parse("Forest-Root=CORP\\admin").unwrap();
+        assert_eq!(module, "forest-root");
+        assert_eq!(credentials.password, None);
+
+        let err = Credentials::parse("adcs-registry=user2@corp:secret").unwrap_err();
+        assert!(err.to_string().contains("unknown module 'adcs-registry'"));
+        assert!(Credentials::parse("gc=:secret").is_err());
+        assert!(Credentials::parse("gc=user:").is_err());
+        // The password never shows in the errors
+        let err = Credentials::parse("gc reader:secret").unwrap_err();
+        assert!(!err.to_string().contains("secret"));
+    }
+
+    #[test]
+    pub fn test_credentials_redaction() {
+        let credentials = Credentials::new("reader@corp.local", Some("secret"));
+        let debug = format!("{credentials:?}");
+        assert!(debug.contains("reader@corp.local"));
+        assert!(debug.contains(REDACTED));
+        assert!(!debug.contains("secret"));
+
+        let mut options = Options::default();
+        options.module_credentials.insert("gc".to_string(), credentials);
+        assert!(!format!("{options:?}").contains("secret"));
+
+        let mut secret = String::from("secret");
+        zeroize(&mut secret);
+        assert!(secret.is_empty());
+
+        // Primary password
+        let options = Options { password: Some(Secret::from("secret")), ..Default::default() };
+        assert!(!format!("{options:?}").contains("secret"));
+    }
+
+    #[test]
+    pub fn test_password_sources() {
+        let path = std::env::temp_dir().join(format!("rusthound-password-{}", std::process::id()));
+        std::fs::write(&path, "Pass word:1\r\nsecond line\n").unwrap();
+        let file = path.to_str();
+
+        let password = resolve_password(Some("from-cli"), file, Some("from-env".to_string())).unwrap();
+        assert_eq!(password.as_deref(), Some("from-cli"));
+        let password = resolve_password(None, file, Some("from-env".to_string())).unwrap();
+        assert_eq!(password.as_deref(), Some("Pass word:1"));
+        let password = resolve_password(None, None, Some("from-env".to_string())).unwrap();
+        assert_eq!(password.as_deref(), Some("from-env"));
+        // Empty variable: prompted
+        assert_eq!(resolve_password(None, None, Some(String::new())).unwrap(), None);
+
+        std::fs::write(&path, "\n").unwrap();
+        assert!(read_password_file(path.to_str().unwrap()).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(read_password_file(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    pub fn test_credentials_precedence() {
+        let config = Config::from_toml(r#"
+            [credentials.gc]
+            username = "config@corp.local"
+            password = "from-config"
+
+            [credentials.forest-root]
+            username = "admin@corp.local"
+        "#).unwrap();
+        assert!(!format!("{config:?}").contains("from-config"));
+
+        let cli = vec![Credentials::parse("gc=cli@corp.local:from-cli").unwrap()];
+        let sets = merge_credentials(&config.credentials, cli).unwrap();
+        assert_eq!(sets["gc"], Credentials::new("cli@corp.local", Some("from-cli")));
+        assert_eq!(sets["forest-root"], Credentials::new("admin@corp.local", None));
+
+        let twice = vec![
+            Credentials::parse("gc=a@corp.local:x").unwrap(),
+            Credentials::parse("gc=b@corp.local:y").unwrap(),
+        ];
+        assert!(merge_credentials(&BTreeMap::new(), twice).is_err());
+
+        // Unknown module in the configuration file
+        assert!(Config::from_toml("[credentials.smb]\nusername = \"user\"\n").is_err());
+        assert!(Config::from_toml("[credentials.gc]\nusername = \"user\"\npasword = \"x\"\n").is_err());
+    }
+
+    #[test]
+    pub fn test_credentials_fallback() {
+        let mut options = Options {
+            username: Some("primary@corp.local".to_string()),
+            password: Some(Secret::from("primary")),
+            ..Default::default()
+        };
+        // No set: the module binds with the primary credentials
+        assert_eq!(options.credentials_for("gc"), None);
+
+        options.module_credentials.insert("gc".to_string(), Credentials::new("reader@corp.local", Some("secret")));
+        assert_eq!(options.credentials_for("gc"), Some(&Credentials::new("reader@corp.local", Some("secret"))));
+        assert_eq!(options.credentials_for("forest-root"), None);
+
+        assert!(validate_credentials(&options.module_credentials, false).is_ok());
+        assert!(validate_credentials(&options.module_credentials, true).is_err());
+        options.module_credentials.insert("gc".to_string(), Credentials::new(" ", None));
+        assert!(validate_credentials(&options.module_credentials, false).is_err());
+    }
+}
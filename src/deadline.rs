@@ -0,0 +1,370 @@
+//! Time-boxed collection with `--deadline`
+//!
+//! With a deadline the LDAP search of each naming context is split by object category and the most
+//! valuable ones are requested first, by default:
+//!
+//! `domain`, `trusts`, `groups` (and foreign security principals), `users`, `adcs`, `computers`,
+//! `containers` (containers, OUs), `gpos`, then `others` (everything else: schema, DNS...).
+//!
+//! The order is set with `--collection-priority`, a profile or `collection_priority` in the `--config`
+//! file, the categories not listed keep the default order after the listed ones. The category filters
+//! never overlap so each object is requested once.
+//!
+//! One tenth of the deadline is kept for the checker and the output: once the cutoff is reached no new
+//! query is started, the current one stops after its page like an interruption, the network modules are
+//! skipped and the output is written with `"partial": true` and the `skipped` categories in its meta.
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+use log::warn;
+use once_cell::sync::Lazy;
+
+/// Part of the deadline kept for the checker and the output.
+pub const DEADLINE_RESERVE_DIVISOR: u32 = 10;
+
+static CUTOFF: Lazy<Mutex<Option<Instant>>> = Lazy::new(|| Mutex::new(None));
+static SKIPPED: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Objects requested by one query of a prioritized collection.
+#[derive(Debug, PartialEq)]
+pub struct CollectionCategory {
+    /// Name used in the priority list and the skipped list.
+    pub name: &'static str,
+    /// LDAP filter, `others` gets everything the other categories don't.
+    pub filter: &'static str,
+}
+
+/// All categories in the default priority order.
+pub const COLLECTION_CATEGORIES: &[CollectionCategory] = &[
+    CollectionCategory { name: "domain", filter: "(objectClass=domain)" },
+    CollectionCategory { name: "trusts", filter: "(objectClass=trustedDomain)" },
+    CollectionCategory { name: "groups", filter: "(|(objectClass=group)(objectClass=foreignSecurityPrincipal))" },
+    CollectionCategory { name: "users", filter: "(sAMAccountType=805306368)" },
+    CollectionCategory {
+        name: "adcs",
+        filter: "(|(objectClass=pKIEnrollmentService)(objectClass=pKICertificateTemplate)(objectClass=certificationAuthority)(objectClass=msPKI-Enterprise-Oid))",
+    },
+    CollectionCategory { name: "computers", filter: "(sAMAccountType=805306369)" },
+    CollectionCategory {
+        name: "containers",
+        filter: "(|(&(objectClass=container)(!(objectClass=groupPolicyContainer)))(objectClass=organizationalUnit)(objectClass=builtinDomain))",
+    },
+    CollectionCategory { name: "gpos", filter: "(objectClass=groupPolicyContainer)" },
+    CollectionCategory { name: "others", filter: "(objectClass=*)" },
+];
+
+/// Function to get the collection order from a priority list, the categories not listed keep the default order.
+pub fn collection_order(priority: &[String]) -> Result<Vec<&'static CollectionCategory>, Box<dyn Error>> {
+    let mut order: Vec<&'static CollectionCategory> = Vec::new();
+    for name in priority {
+        let Some(category) = COLLECTION_CATEGORIES.iter().find(|c| c.name.eq_ignore_ascii_case(name.trim())) else {
+            return Err(format!(
+                "unknown collection category '{name}', expected one of: {}",
+                COLLECTION_CATEGORIES.iter().map(|c| c.name).collect::<Vec<&str>>().join(", ")
+            ).into())
+        };
+        if order.contains(&category) {
+            return Err(format!("collection category '{name}' given twice").into())
+        }
+        order.push(category);
+    }
+    for category in COLLECTION_CATEGORIES {
+        if !order.contains(&category) {
+            order.push(category);
+        }
+    }
+    Ok(order)
+}
+
+/// Function to split a comma separated priority list like `users,groups,adcs`.
+pub fn parse_priority(value: &str) -> Vec<String> {
+    value.split(',').map(|name| name.trim().to_lowercase()).filter(|name| !name.is_empty()).collect()
+}
+
+/// Function to make the LDAP filter of each category, without the objects of the categories requested before.
+/// The custom `--ldap-filter` restricts every category.
+pub fn category_filters(order: &[&CollectionCategory], ldapfilter: &str) -> Vec<(&'static str, String)> {
+    let others = || -> String {
+        let all: String = COLLECTION_CATEGORIES.iter().filter(|c| c.name != "others").map(|c| c.filter).collect();
+        format!("(!(|{all}))")
+    };
+    let mut previous = String::new();
+    order.iter()
+        .map(|category| {
+            let filter = match (category.name, previous.is_empty()) {
+                ("others", _) => others(),
+                (_, true) => category.filter.to_owned(),
+                (_, false) => format!("(&{}(!(|{previous})))", category.filter),
+            };
+            if category.name != "others" {
+                previous += category.filter;
+            }
+            let filter = match ldapfilter {
+                "" | "(objectClass=*)" => filter,
+                custom => format!("(&{custom}{filter})"),
+            };
+            (category.name, filter)
+        })
+        .collect()
+}
+
+/// Function to start the deadline, the cutoff keeps one tenth of it for the checker and the output.
+pub fn start_deadline(deadline: Duration) {
+    let cutoff = Instant::now() + deadline - deadline / DEADLINE_RESERVE_DIVISOR;
+    *CUTOFF.lock().unwrap() = Some(cutoff);
+}
+
+/// Function to get the instant after which no new query is started.
+pub fn deadline_cutoff() -> Option<Instant> {
+    *CUTOFF.lock().unwrap()
+}
+
+/// Function to check if the deadline cutoff is reached.
+pub fn is_deadline_reached() -> bool {
+    deadline_cutoff().is_some_and(|cutoff| Instant::now() >= cutoff)
+}
+
+/// Function to record the categories skipped or cut by the deadline.
+pub fn record_skipped(skipped: &[String]) {
+    if skipped.is_empty() {
+        return
+    }
+    warn!("Deadline reached, not collected: {}", skipped.join(", ").bold().yellow());
+    SKIPPED.lock().unwrap().extend(skipped.iter().cloned());
+}
+
+/// Function to get and clear the categories skipped by the deadline.
+pub fn take_skipped() -> Vec<String> {
+    std::mem::take(&mut *SKIPPED.lock().unwrap())
+}
+
+/// Function to clear the deadline state, useful when RustHound-CE is used as a library.
+pub fn reset_deadline() {
+    *CUTOFF.lock().unwrap() = None;
+    SKIPPED.lock().unwrap().clear();
+}
+
+/// Queries of one prioritized collection: each category on each naming context.
+#[derive(Debug)]
+pub struct QueryPlan {
+    queries: Vec<(&'static str, String, String)>,
+    next: usize,
+    cutoff: Option<Instant>,
+    /// Categories with at least one query cut or not started.
+    incomplete: Vec<&'static str>,
+    /// Categories with at least one query finished or cut.
+    started: Vec<&'static str>,
+}
+
+impl QueryPlan {
+    /// New plan, the queries are not started after the cutoff.
+    pub fn new(naming_contexts: &[String], order: &[&CollectionCategory], ldapfilter: &str, cutoff: Option<Instant>) -> Self {
+        let mut queries = Vec::new();
+        for (name, filter) in category_filters(order, ldapfilter) {
+            for naming_context in naming_contexts {
+                queries.push((name, naming_context.to_owned(), filter.to_owned()));
+            }
+        }
+        Self { queries, next: 0, cutoff, incomplete: Vec::new(), started: Vec::new() }
+    }
+
+    /// Plan without deadline: one query per naming context with the `--ldap-filter`, no category.
+    pub fn single(naming_contexts: &[String], ldapfilter: &str) -> Self {
+        let queries = naming_contexts.iter()
+            .map(|naming_context| ("", naming_context.to_owned(), ldapfilter.to_owned()))
+            .collect();
+        Self { queries, next: 0, cutoff: None, incomplete: Vec::new(), started: Vec::new() }
+    }
+
+    /// Add a query before the others, like the domain object of a `--search-base` collection.
+    pub fn prepend(&mut self, category: &'static str, naming_context: &str, filter: &str) {
+        self.queries.insert(self.next, (category, naming_context.to_owned(), filter.to_owned()));
+    }
+
+    /// Number of queries of the plan.
+    pub fn query_count(&self) -> usize {
+        self.queries.len()
+    }
+
+    /// Check if the cutoff is reached.
+    pub fn is_expired(&self) -> bool {
+        self.cutoff.is_some_and(|cutoff| Instant::now() >= cutoff)
+    }
+
+    /// Next query as `(category, naming context, filter)`, `None` when all are done or the cutoff is reached.
+    pub fn next_query(&mut self) -> Option<(&'static str, String, String)> {
+        if self.is_expired() {
+            for (category, _, _) in &self.queries[self.next..] {
+                if !self.incomplete.contains(category) {
+                    self.incomplete.push(category);
+                }
+            }
+            self.next = self.queries.len();
+            return None
+        }
+        let query = self.queries.get(self.next).cloned();
+        self.next += 1;
+        query
+    }
+
+    /// Record the end of a query, `complete` is false when it stopped before its last page.
+    pub fn finish(&mut self, category: &'static str, complete: bool) {
+        if !self.started.contains(&category) {
+            self.started.push(category);
+        }
+        if !complete && !self.incomplete.contains(&category) {
+            self.incomplete.push(category);
+        }
+    }
+
+    /// Categories not collected, `name (partial)` when some of their objects were.
+    pub fn skipped(&self) -> Vec<String> {
+        self.incomplete.iter()
+            .map(|category| match self.started.contains(category) {
+                true => format!("{category} (partial)"),
+                false => category.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Function to parse a duration like `30m`, `1h30m`, `90s` or `45` (seconds).
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Ok(Duration::from_secs(seconds))
+    }
+    let mut total = 0;
+    let mut number = String::new();
+    for c in value.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'h' | 'm' | 's' if !number.is_empty() => {
+                let n: u64 = number.parse().map_err(|_| format!("invalid duration '{value}'"))?;
+                total += n * match c { 'h' => 3600, 'm' => 60, _ => 1 };
+                number.clear();
+            }
+            _ => return Err(format!("invalid duration '{value}', expected like 30m, 1h30m or 90s")),
+        }
+    }
+    if !number.is_empty() || total == 0 {
+        return Err(format!("invalid duration '{value}', expected like 30m, 1h30m or 90s"))
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// Function to show a duration like `1h30m`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let mut text = String::new();
+    for (value, unit) in [(seconds / 3600, "h"), (seconds / 60 % 60, "m"), (seconds % 60, "s")] {
+        if value > 0 {
+            text += &format!("{value}{unit}");
+        }
+    }
+    if text.is_empty() { "0s".to_string() } else { text }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::{Duration, Instant};
+
+    use crate::args::Options;
+    use crate::deadline::*;
+    use crate::test_support::{entry, parse_entries, write_output};
+
+    #[test]
+    pub fn test_parse_duration() {
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("45"), Ok(Duration::from_secs(45)));
+        assert!(parse_duration("30x").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("1h30").is_err());
+        assert_eq!(format_duration(Duration::from_secs(5400)), "1h30m");
+        assert_eq!(format_duration(Duration::from_secs(90)), "1m30s");
+    }
+
+    #[test]
+    pub fn test_collection_order() {
+        let names = |priority: &[&str]| -> Vec<&str> {
+            let priority: Vec<String> = priority.iter().map(|p| p.to_string()).collect();
+            collection_order(&priority).unwrap().iter().map(|c| c.name).collect()
+        };
+        assert_eq!(names(&[]), vec!["domain", "trusts", "groups", "users", "adcs", "computers", "containers", "gpos", "others"]);
+        assert_eq!(names(&["ADCS", "users"]), vec!["adcs", "users", "domain", "trusts", "groups", "computers", "containers", "gpos", "others"]);
+        assert_eq!(parse_priority(" adcs, Users,,"), vec!["adcs", "users"]);
+        assert!(collection_order(&["sessions".to_string()]).is_err());
+        assert!(collection_order(&["users".to_string(), "users".to_string()]).is_err());
+    }
+
+    #[test]
+    pub fn test_category_filters() {
+        let order = collection_order(&["users".to_string(), "others".to_string()]).unwrap();
+        let filters = category_filters(&order, "(objectClass=*)");
+        assert_eq!(filters[0], ("users", "(sAMAccountType=805306368)".to_string()));
+        // Everything not in a category, wherever it is in the order
+        assert!(filters[1].1.starts_with("(!(|(objectClass=domain)(objectClass=trustedDomain)"));
+        assert_eq!(filters[2], ("domain", "(&(objectClass=domain)(!(|(sAMAccountType=805306368))))".to_string()));
+
+        let custom = category_filters(&order, "(description=*)");
+        assert_eq!(custom[0], ("users", "(&(description=*)(sAMAccountType=805306368))".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_short_deadline_partial_output() {
+        let naming_contexts = vec!["DC=CONTOSO,DC=LOCAL".to_string()];
+        let order = collection_order(&[]).unwrap();
+        let mut plan = QueryPlan::new(&naming_contexts, &order, "(objectClass=*)", Some(Instant::now() + Duration::from_secs(3600)));
+
+        // domain, trusts and groups requested, the deadline is reached during the users query
+        let mut requested = Vec::new();
+        while let Some((category, _, _)) = plan.next_query() {
+            requested.push(category);
+            if category == "users" {
+                plan.cutoff = Some(Instant::now());
+            }
+            plan.finish(category, !plan.is_expired());
+        }
+        assert_eq!(requested, vec!["domain", "trusts", "groups", "users"]);
+        let skipped = plan.skipped();
+        assert_eq!(skipped, vec!["users (partial)", "adcs", "computers", "containers", "gpos", "others"]);
+
+        let entries = vec![
+            entry("DC=CONTOSO,DC=LOCAL", &[("objectClass", "top|domain|domainDNS"), ("name", "contoso")]),
+            entry("CN=HELPDESK,CN=USERS,DC=CONTOSO,DC=LOCAL", &[
+                ("objectClass", "top|group"),
+                ("sAMAccountName", "helpdesk"),
+                ("sAMAccountType", "268435456"),
+            ]),
+            entry("CN=JDOE,CN=USERS,DC=CONTOSO,DC=LOCAL", &[
+                ("objectClass", "top|person|organizationalPerson|user"),
+                ("sAMAccountName", "jdoe"),
+                ("sAMAccountType", "805306368"),
+            ]),
+        ];
+        let options = Options {
+            domain: "contoso.local".to_string(),
+            deadline: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let mut results = parse_entries(entries, &options).await;
+        results.skipped = skipped.to_owned();
+        results.partial = Some("ldap_search".to_string());
+
+        let mut types = Vec::new();
+        for (_, json) in write_output(&options, results) {
+            assert_eq!(json["meta"]["partial"], true);
+            assert_eq!(json["meta"]["phase"], "ldap_search");
+            assert_eq!(json["meta"]["skipped"], serde_json::json!(skipped));
+            assert_eq!(json["meta"]["count"], json["data"].as_array().unwrap().len());
+            types.push(json["meta"]["type"].as_str().unwrap().to_string());
+        }
+        types.sort();
+        assert_eq!(types, vec!["domains", "groups", "users"]);
+    }
+}
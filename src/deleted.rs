@@ -0,0 +1,96 @@
+//! Deleted objects of the AD Recycle Bin
+//!
+//! With `--deleted-objects` the `CN=Deleted Objects` container of the domain is requested with the Show Deleted
+//! Objects control, the tombstones and the recycled-bin objects are collected with `IsDeleted` set, a recently
+//! deleted privileged account can still be restored with its group memberships and ACEs.
+//!
+//! The DC renames a deleted object: `CN=John Doe\0ADEL:<objectGUID>,CN=Deleted Objects,DC=contoso,DC=local`, with
+//! `John Doe\nDEL:<objectGUID>` in its `name` and `cn`. The names are written without the `DEL:` suffix and the
+//! object is contained by its `lastKnownParent`.
+use ldap3::controls::RawControl;
+use ldap3::SearchEntry;
+
+/// LDAP_SERVER_SHOW_DELETED_OID: the deleted objects are returned by the DC.
+pub const SHOW_DELETED_OID: &str = "1.2.840.113556.1.4.417";
+
+/// First RDN of the container of the deleted objects, under the domain partition.
+pub const DELETED_OBJECTS_RDN: &str = "CN=Deleted Objects";
+
+/// Function to get the Show Deleted Objects control, without value.
+pub fn show_deleted_control() -> RawControl {
+    RawControl { ctype: SHOW_DELETED_OID.to_string(), crit: true, val: None }
+}
+
+/// Function to get the DN of the container of the deleted objects of a naming context.
+pub fn deleted_objects_dn(naming_context: &str) -> String {
+    format!("{DELETED_OBJECTS_RDN},{naming_context}")
+}
+
+/// Function to check if a DN is the container of the deleted objects or one of the objects in it.
+pub fn is_under_deleted_objects(dn: &str) -> bool {
+    dn.split(',').any(|rdn| rdn.trim().eq_ignore_ascii_case(DELETED_OBJECTS_RDN))
+}
+
+/// Function to check if a DN is the container of the deleted objects itself, it is not written.
+pub fn is_deleted_objects_container(dn: &str) -> bool {
+    dn.split(',').next().is_some_and(|rdn| rdn.trim().eq_ignore_ascii_case(DELETED_OBJECTS_RDN))
+}
+
+/// Function to remove the `DEL:<objectGUID>` suffix of the name of a deleted object, escaped in a DN (`\0ADEL:`) or
+/// after a new line in its `name` and `cn`.
+pub fn strip_deleted_suffix(name: &str) -> &str {
+    ["\\0ADEL:", "\\0aDEL:", "\nDEL:"].iter()
+        .find_map(|suffix| name.split_once(suffix))
+        .map_or(name, |(name, _)| name)
+}
+
+/// Function to remove the `DEL:` suffix from the names of a deleted entry, return its last known parent.
+pub fn prepare_deleted_entry(entry: &mut SearchEntry) -> Option<String> {
+    if !entry.attrs.contains_key("isDeleted") {
+        return None
+    }
+    for attribute in ["name", "cn"] {
+        if let Some(values) = entry.attrs.get_mut(attribute) {
+            for value in values.iter_mut() {
+                *value = strip_deleted_suffix(value).to_string();
+            }
+        }
+    }
+    entry.attrs.get("lastKnownParent").and_then(|values| values.first()).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+
+    use crate::deleted::*;
+
+    #[test]
+    pub fn test_deleted_entry() {
+        let dn = "CN=John Doe\\0ADEL:5e9ac1f6-2b0b-4d25-8f45-2d1a0b3f3c11,CN=Deleted Objects,DC=contoso,DC=local";
+        assert_eq!(strip_deleted_suffix("John Doe\nDEL:5e9ac1f6-2b0b-4d25-8f45-2d1a0b3f3c11"), "John Doe");
+        assert_eq!(strip_deleted_suffix(dn), "CN=John Doe");
+        assert_eq!(strip_deleted_suffix("CN=John Doe,CN=Users,DC=contoso,DC=local"), "CN=John Doe,CN=Users,DC=contoso,DC=local");
+        assert_eq!(deleted_objects_dn("DC=contoso,DC=local"), "CN=Deleted Objects,DC=contoso,DC=local");
+        assert!(is_under_deleted_objects(dn) && is_under_deleted_objects("CN=Deleted Objects,DC=contoso,DC=local"));
+        assert!(!is_under_deleted_objects("CN=Users,DC=contoso,DC=local"));
+        assert!(is_deleted_objects_container("CN=DELETED OBJECTS,DC=CONTOSO,DC=LOCAL") && !is_deleted_objects_container(dn));
+
+        let mut entry = SearchEntry {
+            dn: dn.to_string(),
+            attrs: HashMap::from([
+                ("name".to_string(), vec!["John Doe\nDEL:5e9ac1f6-2b0b-4d25-8f45-2d1a0b3f3c11".to_string()]),
+                ("sAMAccountName".to_string(), vec!["jdoe".to_string()]),
+                ("isDeleted".to_string(), vec!["TRUE".to_string()]),
+                ("lastKnownParent".to_string(), vec!["OU=Admins,DC=contoso,DC=local".to_string()]),
+            ]),
+            bin_attrs: HashMap::new(),
+        };
+        assert_eq!(prepare_deleted_entry(&mut entry).as_deref(), Some("OU=Admins,DC=contoso,DC=local"));
+        assert_eq!(entry.attrs["name"], ["John Doe"]);
+        // Live object: left as is
+        entry.attrs.remove("isDeleted");
+        assert!(prepare_deleted_entry(&mut entry).is_none());
+    }
+}
@@ -0,0 +1,352 @@
+//! Records of the AD-integrated DNS zones, `--dns`
+//!
+//! The zones stored in Active Directory hold one `dnsNode` object per name under `CN=MicrosoftDNS`, in the
+//! `DomainDnsZones` and `ForestDnsZones` partitions (or `CN=System` of the domain for the legacy zones):
+//!
+//! ```text
+//! DC=ws01,DC=contoso.local,CN=MicrosoftDNS,DC=DomainDnsZones,DC=contoso,DC=local    WS01.CONTOSO.LOCAL
+//! DC=@,DC=contoso.local,CN=MicrosoftDNS,DC=DomainDnsZones,DC=contoso,DC=local       CONTOSO.LOCAL
+//! ```
+//!
+//! Each value of its `dnsRecord` attribute is one record ([MS-DNSP] 2.3.2.2): a 24 bytes header with the type and
+//! the TTL, then the data. The A and AAAA records are kept, the tombstoned nodes are left out. The host names and
+//! their addresses are written in `<domain>_dns.json`, they fill the IP address of the computers and name the ones
+//! without `dNSHostName`.
+//!
+//! [MS-DNSP]: https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-dnsp
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use ldap3::SearchEntry;
+use log::{debug, info};
+
+use crate::error::RustHoundError;
+use crate::objects::common::{HostResolver, LdapObject};
+use crate::objects::computer::Computer;
+
+/// Attributes of the DNS nodes requested with `--dns`.
+pub const DNS_NODE_ATTRIBUTES: &[&str] = &["dnsRecord", "dNSTombstoned"];
+
+/// Container of the zones in each DNS partition.
+pub const MICROSOFT_DNS_RDN: &str = "CN=MicrosoftDNS";
+
+/// Record types of the addresses.
+pub const DNS_TYPE_A: u16 = 0x0001;
+pub const DNS_TYPE_AAAA: u16 = 0x001c;
+
+/// Size of the header of a dnsRecord value.
+const DNS_RECORD_HEADER: usize = 24;
+
+/// One value of the `dnsRecord` attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnsRecord {
+    pub record_type: u16,
+    /// Seconds, big endian in the record.
+    pub ttl: u32,
+    /// Hours since 1601 of the last refresh, 0 for a static record.
+    pub timestamp: u32,
+    pub data: Vec<u8>,
+}
+
+impl DnsRecord {
+    /// Function to parse one `dnsRecord` value.
+    pub fn parse(raw: &[u8]) -> Result<Self, RustHoundError> {
+        if raw.len() < DNS_RECORD_HEADER {
+            return Err(RustHoundError::InvalidValue(format!("dnsRecord of {} bytes, shorter than its header", raw.len())))
+        }
+        let length = u16::from_le_bytes([raw[0], raw[1]]) as usize;
+        let version = raw[4];
+        if version != 5 {
+            return Err(RustHoundError::InvalidValue(format!("dnsRecord version {version}, expected 5")))
+        }
+        let Some(data) = raw.get(DNS_RECORD_HEADER..DNS_RECORD_HEADER + length) else {
+            return Err(RustHoundError::InvalidValue(format!("dnsRecord data of {length} bytes, {} left", raw.len() - DNS_RECORD_HEADER)))
+        };
+        Ok(DnsRecord {
+            record_type: u16::from_le_bytes([raw[2], raw[3]]),
+            ttl: u32::from_be_bytes([raw[12], raw[13], raw[14], raw[15]]),
+            timestamp: u32::from_le_bytes([raw[20], raw[21], raw[22], raw[23]]),
+            data: data.to_vec(),
+        })
+    }
+
+    /// Function to get the address of an A or AAAA record.
+    pub fn address(&self) -> Option<IpAddr> {
+        match (self.record_type, self.data.len()) {
+            (DNS_TYPE_A, 4) => Some(IpAddr::V4(Ipv4Addr::new(self.data[0], self.data[1], self.data[2], self.data[3]))),
+            (DNS_TYPE_AAAA, 16) => Some(IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(self.data.as_slice()).ok()?))),
+            _ => None,
+        }
+    }
+}
+
+/// Function to get the host name of a DNS node from its DN, none for a DN out of a zone or a root hints node.
+pub fn dns_node_name(dn: &str) -> Option<String> {
+    let rdns: Vec<&str> = dn.split(',').map(str::trim).collect();
+    let container = rdns.iter().position(|rdn| rdn.eq_ignore_ascii_case(MICROSOFT_DNS_RDN))?;
+    if container < 2 {
+        return None
+    }
+    let value = |rdn: &str| rdn.get(..3).filter(|prefix| prefix.eq_ignore_ascii_case("DC=")).map(|_| rdn[3..].to_owned());
+    let (node, zone) = (value(rdns[0])?, value(rdns[container - 1])?);
+    // Root hints and the internal zones of the DNS server
+    if zone.eq_ignore_ascii_case("RootDNSServers") || zone.starts_with("..") {
+        return None
+    }
+    Some(match node.as_str() {
+        "@" => zone.to_uppercase(),
+        node => format!("{node}.{zone}").to_uppercase(),
+    })
+}
+
+/// Function to check if an entry is a DNS node.
+pub fn is_dns_node(entry: &SearchEntry) -> bool {
+    entry.attrs.get("objectClass").is_some_and(|classes| classes.iter().any(|class| class.eq_ignore_ascii_case("dnsNode")))
+}
+
+/// Function to check if a naming context is a DNS application partition.
+pub fn is_dns_partition(naming_context: &str) -> bool {
+    naming_context.split(',').next().is_some_and(|rdn| {
+        let rdn = rdn.trim();
+        rdn.eq_ignore_ascii_case("DC=DomainDnsZones") || rdn.eq_ignore_ascii_case("DC=ForestDnsZones")
+    })
+}
+
+/// Host names of the DNS zones and their addresses, IPv4 first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DnsRecords {
+    hosts: BTreeMap<String, Vec<IpAddr>>,
+}
+
+impl DnsRecords {
+    /// Add the A and AAAA records of a DNS node, returns the addresses added.
+    pub fn add_node(&mut self, entry: &SearchEntry) -> usize {
+        let tombstoned = entry.attrs.get("dNSTombstoned").is_some_and(|values| values.iter().any(|v| v.eq_ignore_ascii_case("TRUE")));
+        let (Some(name), false) = (dns_node_name(&entry.dn), tombstoned) else {
+            return 0
+        };
+        let mut added = 0;
+        for raw in entry.bin_attrs.get("dnsRecord").into_iter().flatten() {
+            match DnsRecord::parse(raw) {
+                Ok(record) => {
+                    if let Some(address) = record.address() {
+                        self.insert(&name, address);
+                        added += 1;
+                    }
+                }
+                Err(err) => debug!("Invalid record of the DNS node {}: {err}", entry.dn),
+            }
+        }
+        added
+    }
+
+    /// Register one address of a host name.
+    pub fn insert(&mut self, host: &str, address: IpAddr) {
+        let addresses = self.hosts.entry(host.to_uppercase()).or_default();
+        if !addresses.contains(&address) {
+            addresses.push(address);
+            addresses.sort();
+        }
+    }
+
+    /// Addresses of a host name.
+    pub fn addresses(&self, host: &str) -> &[IpAddr] {
+        self.hosts.get(&host.to_uppercase()).map_or(&[], Vec::as_slice)
+    }
+
+    /// Host names with this short name, in every zone.
+    pub fn hosts_named(&self, short: &str) -> Vec<&str> {
+        let short = short.to_uppercase();
+        self.hosts.keys()
+            .filter(|host| host.split('.').next() == Some(short.as_str()) && host.contains('.'))
+            .map(String::as_str)
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.hosts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+}
+
+/// This function names the computers without `dNSHostName` from the DNS zones, and sets the IP address of every
+/// computer with a record. A short name in several zones is only used when one of them is the zone of the domain.
+pub fn backfill_computers(
+    records: &DnsRecords,
+    computers: &mut [Computer],
+    host_resolver: &mut HostResolver,
+    fqdn_ip: &mut HashMap<String, String>,
+) -> usize {
+    let mut named = 0;
+    for computer in computers.iter_mut() {
+        if computer.properties().dnshostname().is_none() {
+            let short = computer.properties().samaccountname().trim_end_matches('$').to_uppercase();
+            let hosts = records.hosts_named(&short);
+            let domain_host = format!("{short}.{}", computer.properties().domain());
+            let host = match hosts.as_slice() {
+                [host] => Some(host.to_string()),
+                hosts => hosts.iter().find(|host| **host == domain_host).map(|host| host.to_string()),
+            };
+            if let Some(host) = host.filter(|_| !short.is_empty()) {
+                debug!("{} named {host} from the DNS zones", computer.properties().name());
+                fqdn_ip.remove(computer.properties().name());
+                host_resolver.insert(&host, computer.get_object_identifier());
+                computer.properties_mut().set_dnshostname(&host);
+                named += 1;
+            }
+        }
+        let address = records.addresses(computer.properties().name()).first().map(IpAddr::to_string);
+        let ip = fqdn_ip.entry(computer.properties().name().to_owned()).or_default();
+        if let Some(address) = address {
+            *ip = address;
+        }
+    }
+    named
+}
+
+/// Function to write the host names of the DNS zones and their addresses in `<domain>_dns.json`.
+pub fn write_dns_records(records: &DnsRecords, path: &str, domain: &str) -> Result<String, Box<dyn Error>> {
+    std::fs::create_dir_all(path)?;
+    let file = format!("{}/{}_dns.json", path.trim_end_matches('/'), domain.to_lowercase());
+    let hosts: BTreeMap<&String, Vec<String>> = records.hosts.iter()
+        .map(|(host, addresses)| (host, addresses.iter().map(IpAddr::to_string).collect()))
+        .collect();
+    std::fs::write(&file, serde_json::to_string_pretty(&hosts)?)?;
+    info!("{} DNS host names written to {file}", records.len());
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use std::net::IpAddr;
+
+    use ldap3::SearchEntry;
+
+    use crate::dns::*;
+
+    /// dnsRecord value of a version 5 record.
+    fn raw_record(record_type: u16, ttl: u32, data: &[u8]) -> Vec<u8> {
+        let mut raw = Vec::new();
+        raw.extend((data.len() as u16).to_le_bytes());
+        raw.extend(record_type.to_le_bytes());
+        raw.extend([5, 0xf0, 0, 0]);
+        raw.extend(42u32.to_le_bytes());
+        raw.extend(ttl.to_be_bytes());
+        raw.extend([0; 4]);
+        raw.extend(3_700_000u32.to_le_bytes());
+        raw.extend(data);
+        raw
+    }
+
+    fn dns_node(dn: &str, records: Vec<Vec<u8>>) -> SearchEntry {
+        SearchEntry {
+            dn: dn.to_string(),
+            attrs: HashMap::from([("objectClass".to_string(), vec!["top".to_string(), "dnsNode".to_string()])]),
+            bin_attrs: HashMap::from([("dnsRecord".to_string(), records)]),
+        }
+    }
+
+    #[test]
+    pub fn test_dns_record() {
+        let record = DnsRecord::parse(&raw_record(DNS_TYPE_A, 1200, &[10, 0, 0, 21])).unwrap();
+        assert_eq!((record.record_type, record.ttl, record.timestamp), (DNS_TYPE_A, 1200, 3_700_000));
+        assert_eq!(record.address(), Some("10.0.0.21".parse::<IpAddr>().unwrap()));
+
+        let ipv6: IpAddr = "fd00::21".parse().unwrap();
+        let IpAddr::V6(octets) = ipv6 else { unreachable!() };
+        let record = DnsRecord::parse(&raw_record(DNS_TYPE_AAAA, 3600, &octets.octets())).unwrap();
+        assert_eq!(record.address(), Some(ipv6));
+        // CNAME: no address
+        assert_eq!(DnsRecord::parse(&raw_record(0x0005, 3600, &[3, b'w', b'w', b'w', 0])).unwrap().address(), None);
+
+        assert!(DnsRecord::parse(&[0; 10]).is_err());
+        let mut truncated = raw_record(DNS_TYPE_A, 1200, &[10, 0, 0, 21]);
+        truncated.pop();
+        assert!(matches!(DnsRecord::parse(&truncated), Err(RustHoundError::InvalidValue(_))));
+        let mut version = raw_record(DNS_TYPE_A, 1200, &[10, 0, 0, 21]);
+        version[4] = 4;
+        assert!(DnsRecord::parse(&version).is_err());
+    }
+
+    #[test]
+    pub fn test_dns_node_name() {
+        assert_eq!(
+            dns_node_name("DC=ws01,DC=contoso.local,CN=MicrosoftDNS,DC=DomainDnsZones,DC=contoso,DC=local").as_deref(),
+            Some("WS01.CONTOSO.LOCAL")
+        );
+        assert_eq!(dns_node_name("DC=@,DC=contoso.local,CN=MicrosoftDNS,CN=System,DC=contoso,DC=local").as_deref(), Some("CONTOSO.LOCAL"));
+        assert_eq!(dns_node_name("DC=a.root-servers.net,DC=RootDNSServers,CN=MicrosoftDNS,CN=System,DC=contoso,DC=local"), None);
+        assert_eq!(dns_node_name("DC=contoso.local,CN=MicrosoftDNS,DC=DomainDnsZones,DC=contoso,DC=local"), None);
+        assert!(is_dns_partition("DC=ForestDnsZones,DC=contoso,DC=local") && !is_dns_partition("DC=contoso,DC=local"));
+    }
+
+    #[test]
+    pub fn test_dns_records() {
+        let zone = "DC=contoso.local,CN=MicrosoftDNS,DC=DomainDnsZones,DC=contoso,DC=local";
+        let mut records = DnsRecords::default();
+        let node = dns_node(&format!("DC=ws01,{zone}"), vec![
+            raw_record(DNS_TYPE_AAAA, 1200, &"fd00::21".parse::<std::net::Ipv6Addr>().unwrap().octets()),
+            raw_record(DNS_TYPE_A, 1200, &[10, 0, 0, 21]),
+            vec![1, 2, 3],
+        ]);
+        assert!(is_dns_node(&node));
+        assert_eq!(records.add_node(&node), 2);
+        // IPv4 first
+        assert_eq!(records.addresses("ws01.contoso.local")[0].to_string(), "10.0.0.21");
+
+        let mut tombstoned = dns_node(&format!("DC=old,{zone}"), vec![raw_record(DNS_TYPE_A, 1200, &[10, 0, 0, 99])]);
+        tombstoned.attrs.insert("dNSTombstoned".to_string(), vec!["TRUE".to_string()]);
+        assert_eq!(records.add_node(&tombstoned), 0);
+        records.insert("WS01.LAB.CONTOSO.LOCAL", "10.1.0.21".parse().unwrap());
+        assert_eq!(records.hosts_named("ws01"), ["WS01.CONTOSO.LOCAL", "WS01.LAB.CONTOSO.LOCAL"]);
+        assert_eq!(records.len(), 2);
+    }
+
+    fn computer(name: &str, rid: u32, dnshostname: Option<&str>, fqdn_ip: &mut HashMap<String, String>, host_resolver: &mut HostResolver) -> Computer {
+        let mut attrs = HashMap::from([
+            ("name".to_string(), vec![name.to_string()]),
+            ("sAMAccountName".to_string(), vec![format!("{name}$")]),
+        ]);
+        if let Some(dnshostname) = dnshostname {
+            attrs.insert("dNSHostName".to_string(), vec![dnshostname.to_string()]);
+        }
+        let sid = crate::enums::sddl::sid_to_bytes(&format!("S-1-5-21-1-2-3-{rid}")).unwrap();
+        let entry = SearchEntry {
+            dn: format!("CN={name},OU=Lab,DC=contoso,DC=local"),
+            attrs,
+            bin_attrs: HashMap::from([("objectSid".to_string(), vec![sid])]),
+        };
+        let mut computer = Computer::new();
+        computer.parse(entry, "contoso.local", &mut HashMap::new(), &mut HashMap::new(), host_resolver, fqdn_ip, "S-1-5-21-1-2-3").unwrap();
+        computer
+    }
+
+    #[test]
+    pub fn test_backfill_computers() {
+        let mut records = DnsRecords::default();
+        records.insert("DC01.CONTOSO.LOCAL", "10.0.0.10".parse().unwrap());
+        records.insert("WS02.LAB.CONTOSO.LOCAL", "10.1.0.22".parse().unwrap());
+        let (mut fqdn_ip, mut host_resolver) = (HashMap::new(), HostResolver::new());
+        let mut computers = vec![
+            computer("DC01", 1000, Some("dc01.contoso.local"), &mut fqdn_ip, &mut host_resolver),
+            computer("WS02", 1105, None, &mut fqdn_ip, &mut host_resolver),
+            computer("WS03", 1106, None, &mut fqdn_ip, &mut host_resolver),
+        ];
+
+        // WS02 named from its only zone, WS03 unknown of the DNS keeps its name
+        assert_eq!(backfill_computers(&records, &mut computers, &mut host_resolver, &mut fqdn_ip), 1);
+        assert_eq!(computers[1].properties().dnshostname().as_deref(), Some("WS02.LAB.CONTOSO.LOCAL"));
+        assert_eq!(computers[1].properties().name(), "WS02.LAB.CONTOSO.LOCAL");
+        assert_eq!(host_resolver.resolve("ws02.lab.contoso.local").map(String::as_str), Some("S-1-5-21-1-2-3-1105"));
+        assert_eq!(fqdn_ip["WS02.LAB.CONTOSO.LOCAL"], "10.1.0.22");
+        assert_eq!(fqdn_ip["DC01.CONTOSO.LOCAL"], "10.0.0.10");
+        assert_eq!(computers[2].properties().name(), "WS03.CONTOSO.LOCAL");
+        assert!(computers[2].properties().dnshostname().is_none());
+    }
+}
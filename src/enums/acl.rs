@@ -1,16 +1,15 @@
 extern crate lazy_static;
 
 use lazy_static::lazy_static;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::{enums::decode_guid_le, objects::{
-    common::{AceTemplate, LdapObject}, user::User
-}};
+use crate::{enums::decode_guid_le, objects::common::{AceTemplate, LdapObject}};
 use crate::enums::constants::*;
 use crate::enums::secdesc::*;
-use crate::enums::sid::{bin_to_string, sid_maker};
+use crate::enums::sid::{sid_maker, is_admin_sid};
+use crate::enums::explain::AceTracer;
 use bitflags::bitflags;
-use log::{error, trace};
+use log::{error, trace, warn};
 
 /// This function allows to parse the attribut nTSecurityDescriptor from secdesc.rs
 /// <http://www.selfadsi.org/deep-inside/ad-security-descriptors.htm#SecurityDescriptorStructure>
@@ -23,10 +22,16 @@ pub fn parse_ntsecuritydescriptor<T: LdapObject>(
     domain: &str,
 ) -> Vec<AceTemplate> {
     let mut relations_dacl: Vec<AceTemplate> = Vec::new();
-    let relations_sacl: Vec<AceTemplate> = Vec::new();
     let mut owner_sid: String = "".to_string();
 
-    let secdesc: SecurityDescriptor = SecurityDescriptor::parse(nt).unwrap().1;
+    // Truncated or non Windows encoded descriptor (Samba), keep the object without ACEs
+    let secdesc: SecurityDescriptor = match SecurityDescriptor::parse(nt) {
+        Ok(res) => res.1,
+        Err(err) => {
+            warn!("Invalid nTSecurityDescriptor for {entry_type} {}: {err}", object.get_object_identifier());
+            return relations_dacl
+        }
+    };
     trace!("SECURITY-DESCRIPTOR: {:?}", secdesc);
 
     // Check for ACL protected for Bloodhound4.1+
@@ -44,44 +49,34 @@ pub fn parse_ntsecuritydescriptor<T: LdapObject>(
 
     if secdesc.offset_owner as usize != 0 
     {
-        owner_sid = sid_maker(LdapSid::parse(&nt[secdesc.offset_owner as usize..]).unwrap().1,domain);
+        match nt.get(secdesc.offset_owner as usize..).map(LdapSid::parse) {
+            Some(Ok(res)) => owner_sid = sid_maker(res.1, domain),
+            _ => warn!("Invalid owner SID in nTSecurityDescriptor for {entry_type} {}", object.get_object_identifier()),
+        }
         trace!("OWNER-SID: {:?}", owner_sid);
     }
 
     if secdesc.offset_group as usize != 0 
     {
-        let group_sid = sid_maker(LdapSid::parse(&nt[secdesc.offset_group as usize..]).unwrap().1,domain);
-        trace!("GROUP-SID: {:?}", group_sid);
+        if let Some(Ok(res)) = nt.get(secdesc.offset_group as usize..).map(LdapSid::parse) {
+            let group_sid = sid_maker(res.1, domain);
+            trace!("GROUP-SID: {:?}", group_sid);
+        }
     }
 
+    // The SACL is only returned with the SeSecurityPrivilege (or by Samba), it never makes edges
     if secdesc.offset_sacl as usize != 0 
     {
-        let res = Acl::parse(&nt[secdesc.offset_sacl as usize..]);
-        match res {
-            Ok(_res) => {
-                let sacl = _res.1;
-                trace!("SACL: {:?}", sacl);
-                //let aces = sacl.data;
-                /*ace_maker(
-                    object,
-                    domain,
-                    &mut relations_sacl,
-                    &owner_sid,
-                    aces,
-                    &entry_type,
-                    result_attrs,
-                    result_bin,
-                );*/
-                trace!("RESULT: {:?}", relations_sacl);
-            }
-            Err(err) => error!("Error. Reason: {err}"),
+        match nt.get(secdesc.offset_sacl as usize..).map(Acl::parse) {
+            Some(Ok(res)) => trace!("SACL: {:?}", res.1),
+            _ => trace!("Invalid SACL for {entry_type} {}, ignored", object.get_object_identifier()),
         }
-        return relations_sacl;
     }
 
     if secdesc.offset_dacl as usize != 0 
     {
-        let res = Acl::parse(&nt[secdesc.offset_dacl as usize..]);    
+        let res = nt.get(secdesc.offset_dacl as usize..).ok_or("DACL offset out of bounds".to_string())
+            .and_then(|dacl| Acl::parse(dacl).map_err(|err| err.to_string()));
         match res {
             Ok(_res) => {
                 let dacl = _res.1;
@@ -96,18 +91,40 @@ pub fn parse_ntsecuritydescriptor<T: LdapObject>(
                     entry_type,
                     result_attrs,
                     result_bin,
+                    // First ACE right after the ACL header
+                    secdesc.offset_dacl as usize + 8,
                 );
                 trace!("RESULT: {:?}", relations_dacl);
             }
-            Err(err) => error!("Error. Reason: {err}"),
+            Err(err) => warn!("Invalid DACL for {entry_type} {}: {err}", object.get_object_identifier()),
         }
         return relations_dacl;
     }
     relations_dacl
 }
 
+/// This function removes the duplicate ACEs of a descriptor, like the copies of an ACE left by the inheritance flattening,
+/// keeping the first one. Two ACEs are duplicates with the same principal SID, right, ACE type and object type.
+pub fn dedup_aces(aces: Vec<AceTemplate>) -> Vec<AceTemplate> {
+    let total = aces.len();
+    let mut seen: HashSet<(String, String, Option<u8>, Option<u128>)> = HashSet::new();
+    let aces: Vec<AceTemplate> = aces.into_iter()
+        .filter(|ace| seen.insert((
+            ace.principal_sid().to_owned(),
+            ace.right_name().to_owned(),
+            *ace.ace_type(),
+            *ace.ace_object_type(),
+        )))
+        .collect();
+    if aces.len() < total {
+        trace!("{} duplicate ACEs removed", total - aces.len());
+    }
+    aces
+}
+
 /// Parse ace in acl and get correct values (thanks fox-it for bloodhound.py works)
 /// <https://github.com/fox-it/BloodHound.py/blob/master/bloodhound/enumeration/acls.py>
+#[allow(clippy::too_many_arguments)]
 fn ace_maker<T: LdapObject>(
     object: &mut T,
     domain: &str,
@@ -117,12 +134,16 @@ fn ace_maker<T: LdapObject>(
     entry_type: &str,
     _result_attrs: &HashMap<String, Vec<String>>,
     _result_bin: &HashMap<String, Vec<Vec<u8>>>,
+    dacl_offset: usize,
 ) {
+    // --explain-edges observer, no-op when unused
+    let mut tracer = AceTracer::new(dacl_offset);
     // trace!("ACL/ACE FOR ENTRY: {:?}",object.properties().name);
     // Ignore Creator Owner or Local System
     const IGNORE_SIDS: &[&str] = &["S-1-3-0", "S-1-5-18", "S-1-5-10"];
     //, "S-1-1-0".to_string(), "S-1-5-10".to_string(), "S-1-5-11".to_string()];
-    if IGNORE_SIDS.iter().any(|i| !osid.contains(i)) {
+    tracer.owner(osid, !IGNORE_SIDS.iter().any(|i| osid.contains(i)));
+    if !osid.is_empty() && !IGNORE_SIDS.iter().any(|i| osid.contains(i)) {
         relations.push(AceTemplate::new(
             osid.to_owned(),
             "Base".to_string(),
@@ -133,10 +154,16 @@ fn ace_maker<T: LdapObject>(
         );
     }
 
+    // Edges made by the previous ACE: first edge, ACE type and object type
+    let mut source: Option<(usize, u8, Option<u128>)> = None;
     for ace in aces {
+        set_ace_source(relations, source.take());
+        source = Some((relations.len(), ace.ace_type, AceFormat::get_object_type(&ace.data)));
+        tracer.ace(&ace, domain, relations);
         if ace.ace_type != 0x05 && ace.ace_type != 0x00
         {
             trace!("Don't care about acetype {:?}", ace.ace_type);
+            tracer.suppress("deny ACE, only allowed ACEs make edges");
             continue;
         }
 
@@ -146,6 +173,7 @@ fn ace_maker<T: LdapObject>(
         // Check if sid is in the ignored list
         if IGNORE_SIDS.iter().any(|i| sid.contains(i))
         {
+            tracer.suppress("principal ignored (Creator Owner, Local System or Principal Self)");
             continue;
         }
 
@@ -167,6 +195,7 @@ fn ace_maker<T: LdapObject>(
                 && (ace.ace_flags & INHERIT_ONLY_ACE == INHERIT_ONLY_ACE) 
             {
                 // ACE is set on this object, but only inherited, so not applicable to us
+                tracer.suppress("inherit-only ACE, it only applies to the child objects");
                 continue;
             }
 
@@ -182,13 +211,17 @@ fn ace_maker<T: LdapObject>(
                 // https://github.com/fox-it/BloodHound.py/blob/645082e3462c93f31b571db945cde1fd7b837fb9/bloodhound/enumeration/acls.py#L85
                 let ace_guid = decode_guid_le(&inherited_object_type.to_le_bytes().to_vec()).to_lowercase();
                 if !(ace_applies(&ace_guid, entry_type)) {
+                    tracer.suppress(&format!("inherited object type doesn't apply to {entry_type} objects"));
                     continue;
                 }
             }
 
             let mask = match AceFormat::get_mask(&ace.data) {
                 Some(mask) => mask,
-                None => continue,
+                None => {
+                    tracer.suppress("no access mask");
+                    continue
+                }
             };
             trace!("ACE MASK for ACETYPE 0x05: {:?}", mask);
 
@@ -203,6 +236,7 @@ fn ace_maker<T: LdapObject>(
             {
                 trace!("ACE MASK contain: GENERIC_ALL or WRITE_DACL or WRITE_OWNER or GENERIC_WRITE");
                 if &flags & ACE_OBJECT_TYPE_PRESENT == ACE_OBJECT_TYPE_PRESENT && !ace_applies(&ace_guid, entry_type) {
+                    tracer.suppress(&format!("object type doesn't apply to {entry_type} objects"));
                     continue;
                 }
                 if (MaskFlags::GENERIC_ALL.bits() | mask) == mask 
@@ -267,7 +301,7 @@ fn ace_maker<T: LdapObject>(
             // https://github.com/fox-it/BloodHound.py/blob/645082e3462c93f31b571db945cde1fd7b837fb9/bloodhound/enumeration/acls.py#L126
             if (MaskFlags::ADS_RIGHT_DS_WRITE_PROP.bits() | mask) == mask {
 
-                if ((entry_type == "User") || (entry_type == "Group") || (entry_type == "Computer"))
+                if ["User","Group","Computer","Gpo"].contains(&entry_type)
                     && !(&flags & ACE_OBJECT_TYPE_PRESENT == ACE_OBJECT_TYPE_PRESENT)
                 {
                     relations.push(AceTemplate::new(
@@ -324,7 +358,8 @@ fn ace_maker<T: LdapObject>(
                         "".to_string(),
                     ));
                 }
-                if entry_type == "OU" && can_write_property(&ace, WRITE_GPLINK)
+                // WriteGPLink is valid on every object which can hold a gPLink attribute
+                if ["OU","Domain"].contains(&entry_type) && can_write_property(&ace, WRITE_GPLINK)
                 {
                     relations.push(AceTemplate::new(
                         sid.to_owned(),
@@ -502,7 +537,10 @@ fn ace_maker<T: LdapObject>(
 
             let mask = match AceFormat::get_mask(&ace.data) {
                 Some(mask) => mask,
-                None => continue,
+                None => {
+                    tracer.suppress("no access mask");
+                    continue
+                }
             };
             trace!("ACE MASK for ACETYPE 0x00: {:?}", mask);
 
@@ -575,9 +613,7 @@ fn ace_maker<T: LdapObject>(
             // Self add, also possible ad ACCESS_ALLOWED_ACE
             // Thanks to bh-py: <https://github.com/dirkjanm/BloodHound.py/blob/d47e765fd3d0356e2e4b48d0d9a0841525194c64/bloodhound/enumeration/acls.py#L221C1-L225C97>
             if (MaskFlags::ADS_RIGHT_DS_SELF.bits() & mask) == mask 
-                && sid != "S-1-5-32-544"
-                && !sid.ends_with("-512")
-                && !sid.ends_with("-519")
+                && !is_admin_sid(&sid)
             {
 
                 if entry_type == "Group" {
@@ -623,6 +659,17 @@ fn ace_maker<T: LdapObject>(
             }
         }
     }
+    set_ace_source(relations, source);
+    tracer.finish(relations);
+}
+
+/// Function to record the ACE of the edges it made, from the first one.
+fn set_ace_source(relations: &mut [AceTemplate], source: Option<(usize, u8, Option<u128>)>) {
+    if let Some((first_edge, ace_type, object_type)) = source {
+        for relation in &mut relations[first_edge..] {
+            relation.set_ace_source(ace_type, object_type);
+        }
+    }
 }
 
 /// Checks if the access is sufficient to write to a specific property.
@@ -659,7 +706,7 @@ fn can_write_property(
     trace!("AceFormat::get_object_type {}",decode_guid_le(&typea.to_le_bytes().as_ref()));
     trace!("bin_property_guid_string {}", bin_property.to_uppercase());
 
-    if bin_to_string(&typea.to_le_bytes().as_ref()) == bin_property.to_uppercase()
+    if decode_guid_le(&typea.to_le_bytes().as_ref()) == bin_property.to_uppercase()
     {
         trace!("MATCHED AceFormat::get_object_type with bin_property!");
         return true;
@@ -713,21 +760,78 @@ fn ace_applies(ace_guid: &String, entry_type: &str) -> bool {
     // Checks if an ACE applies to this object (based on object classes).
     // Note that this function assumes you already verified that InheritedObjectType is set (via the flag).
     // If this is not set, the ACE applies to all object types.
+    let class_name = entry_type_to_class_name(entry_type);
     trace!("ACE GUID: {}", &ace_guid);
-    trace!("OBJECTTYPE_GUID_HASHMAP: {}",OBJECTTYPE_GUID_HASHMAP.get(entry_type).unwrap_or(&String::from("GUID-NOT-FOUND")));
-    ace_guid == OBJECTTYPE_GUID_HASHMAP.get(entry_type).unwrap_or(&String::from("GUID-NOT-FOUND"))
+    trace!("OBJECTTYPE_GUID_HASHMAP: {}",OBJECTTYPE_GUID_HASHMAP.get(class_name).unwrap_or(&String::from("GUID-NOT-FOUND")));
+    if ace_guid == OBJECTTYPE_GUID_HASHMAP.get(class_name).unwrap_or(&String::from("GUID-NOT-FOUND")) {
+        return true
+    }
+    // Computer class inherits from user class, ACEs scoped on users apply to computers too.
+    entry_type == "Computer"
+        && ace_guid == OBJECTTYPE_GUID_HASHMAP.get("user").unwrap_or(&String::from("GUID-NOT-FOUND"))
 }
 
-/// Function to parse GMSA DACL which states which users (or groups) can read the password
-pub fn parse_gmsa(processed_aces: &[AceTemplate], user: &mut User) {
-    for ace in processed_aces {
-        if ace.right_name() == "Owner"  { // || ace.right_name() == "Owns" {
-            continue;
+/// Get the schema name of a known object type GUID, used to explain the ACEs.
+pub fn object_type_name(guid: &str) -> Option<&'static str> {
+    OBJECTTYPE_GUID_HASHMAP.iter()
+        .filter(|(_, value)| value.eq_ignore_ascii_case(guid))
+        .map(|(name, _)| name.as_str())
+        .min()
+}
+
+/// Get the schema class name (OBJECTTYPE_GUID_HASHMAP key) for one RustHound entry type.
+fn entry_type_to_class_name(entry_type: &str) -> &str {
+    match entry_type {
+        "User" => "user",
+        "Computer" => "computer",
+        "Group" => "group",
+        "OU" => "organizational-unit",
+        "Domain" => "domain-dns",
+        "Gpo" => "group-policy-container",
+        "Container" => "container",
+        "CertTemplate" => "pki-certificate-template",
+        "EnterpriseCA" => "pki-enrollment-service",
+        "RootCA" | "AIACA" | "NtAuthStore" => "certification-authority",
+        _ => entry_type,
+    }
+}
+
+/// Function to parse the msDS-GroupMSAMembership descriptor of a gMSA or a dMSA, which states which users (or groups)
+/// can read the password: every principal of an allowed ACE, whatever its mask. The owner of the descriptor can't.
+pub fn parse_gmsa(nt: &[u8], domain: &str) -> Vec<AceTemplate> {
+    let mut relations: Vec<AceTemplate> = Vec::new();
+    let secdesc: SecurityDescriptor = match SecurityDescriptor::parse(nt) {
+        Ok(res) => res.1,
+        Err(err) => {
+            warn!("Invalid msDS-GroupMSAMembership descriptor: {err}");
+            return relations
+        }
+    };
+    if secdesc.offset_dacl as usize == 0 {
+        return relations
+    }
+    let res = nt.get(secdesc.offset_dacl as usize..).ok_or("DACL offset out of bounds".to_string())
+        .and_then(|dacl| Acl::parse(dacl).map_err(|err| err.to_string()));
+    match res {
+        Ok(res) => {
+            for ace in res.1.data {
+                if ace.ace_type != 0x00 && ace.ace_type != 0x05 {
+                    continue
+                }
+                if let Some(sid) = AceFormat::get_sid(ace.data.to_owned()) {
+                    relations.push(AceTemplate::new(
+                        sid_maker(sid, domain),
+                        "Base".to_string(),
+                        "ReadGMSAPassword".to_string(),
+                        false,
+                        "".to_string(),
+                    ));
+                }
+            }
         }
-        let mut ace = ace.clone();
-        *ace.right_name_mut() = "ReadGMSAPassword".to_string();
-        user.aces_mut().push(ace);
+        Err(err) => warn!("Invalid msDS-GroupMSAMembership DACL: {err}"),
     }
+    relations
 }
 
 /// Function to get relations for CASecurity from LDAP attribute.
@@ -741,12 +845,6 @@ pub fn parse_ca_security(
     // If changes are made on the CA server, registry and the AD object is updated.
     // If changes are made directly on the AD object, the CA server registry is not updated.
     // For RustHound, we need to use AD object DACL because we dont have RPC to read registry yet.
-    let blacklist_sid = [
-        // <https://learn.microsoft.com/fr-fr/windows-server/identity/ad-ds/manage/understand-security-identifiers>
-        "-544", // Administrators
-        "-519", // Enterprise Administrators
-        "-512", // Domain Admins
-    ];
     let mut relations:  Vec<AceTemplate> = Vec::new();
     // Hosting Computer local administrator group is the owner.
     relations.push(AceTemplate::new(
@@ -756,8 +854,14 @@ pub fn parse_ca_security(
         false,
         "".to_string(),
     ));
-    let secdesc: SecurityDescriptor = SecurityDescriptor::parse(nt).unwrap().1;
-    if secdesc.offset_dacl as usize != 0 
+    let secdesc: SecurityDescriptor = match SecurityDescriptor::parse(nt) {
+        Ok(res) => res.1,
+        Err(err) => {
+            warn!("Invalid CASecurity descriptor: {err}");
+            return relations
+        }
+    };
+    if secdesc.offset_dacl as usize != 0 && (secdesc.offset_dacl as usize) < nt.len()
     {
         let res = Acl::parse(&nt[secdesc.offset_dacl as usize..]);    
         match res {
@@ -765,7 +869,10 @@ pub fn parse_ca_security(
                 let dacl = _res.1;
                 let aces = dacl.data;
                 for ace in aces {
-                    let sid = sid_maker(AceFormat::get_sid(ace.data.to_owned()).unwrap(), domain);
+                    let sid = match AceFormat::get_sid(ace.data.to_owned()) {
+                        Some(sid) => sid_maker(sid, domain),
+                        None => continue,
+                    };
                     let mask = match AceFormat::get_mask(&ace.data) {
                         Some(mask) => mask,
                         None => continue,
@@ -784,7 +891,7 @@ pub fn parse_ca_security(
                         if (MaskFlags::MANAGE_CERTIFICATES.bits() | mask) == mask
                         {
                             // trace!("SID: {:?}\nMASK: ManageCertificates",&sid);
-                            if !blacklist_sid.iter().any(|blacklisted| sid.ends_with(blacklisted)) {
+                            if !is_admin_sid(&sid) {
                                 // HostingComputer SID, need to add -544 for LocalGroup
                                 relations.push(AceTemplate::new(
                                     sid.to_owned() + "-544",
@@ -806,7 +913,7 @@ pub fn parse_ca_security(
                         if (MaskFlags::MANAGE_CA.bits() | mask) == mask
                         {
                             // trace!("SID: {:?}\nMASK: ManageCA",&sid);
-                            if !blacklist_sid.iter().any(|blacklisted| sid.ends_with(blacklisted)) {
+                            if !is_admin_sid(&sid) {
                                 // HostingComputer SID, need to add -544 for LocalGroup
                                 relations.push(AceTemplate::new(
                                     sid.to_owned() + "-544",
@@ -2680,3 +2787,218 @@ lazy_static! {
         values.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect::<HashMap<String, String>>()
     };
 }
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use crate::enums::acl::{dedup_aces, parse_ntsecuritydescriptor};
+    use crate::enums::constants::*;
+    use crate::enums::explain::{disable_explain_edges, enable_explain_edges, explain_report, finish_object, start_object};
+    use crate::objects::{common::AceTemplate, domain::Domain, gpo::Gpo, group::Group, ou::Ou};
+
+    /// Build a S-1-5-21-1-2-3-<rid> SID as raw bytes.
+    fn raw_sid(rid: u32) -> Vec<u8> {
+        let mut sid = vec![1, 5, 0, 0, 0, 0, 0, 5];
+        for sub in [21, 1, 2, 3, rid] {
+            sid.extend_from_slice(&u32::to_le_bytes(sub));
+        }
+        sid
+    }
+
+    /// Convert a GUID string to its raw little-endian representation.
+    fn raw_guid(guid: &str) -> Vec<u8> {
+        let value = u128::from_str_radix(&guid.replace('-', ""), 16).unwrap();
+        let mut raw = value.to_be_bytes().to_vec();
+        raw[0..4].reverse();
+        raw[4..6].reverse();
+        raw[6..8].reverse();
+        raw
+    }
+
+    /// Build an ACCESS_ALLOWED_OBJECT_ACE.
+    fn object_ace(ace_flags: u8, mask: u32, object_type: Option<&str>, inherited_object_type: Option<&str>, rid: u32) -> Vec<u8> {
+        let mut data = u32::to_le_bytes(mask).to_vec();
+        let flags = object_type.map_or(0, |_| ACE_OBJECT_TYPE_PRESENT)
+            | inherited_object_type.map_or(0, |_| ACE_INHERITED_OBJECT_TYPE_PRESENT);
+        data.extend_from_slice(&u32::to_le_bytes(flags));
+        if let Some(guid) = object_type {
+            data.extend(raw_guid(guid));
+        }
+        if let Some(guid) = inherited_object_type {
+            data.extend(raw_guid(guid));
+        }
+        data.extend(raw_sid(rid));
+        let mut ace = vec![ACCESS_ALLOWED_OBJECT_ACE_TYPE, ace_flags];
+        ace.extend_from_slice(&u16::to_le_bytes(data.len() as u16 + 4));
+        ace.extend(data);
+        ace
+    }
+
+    /// Build a self-relative security descriptor with one owner and one DACL.
+    fn security_descriptor(owner_rid: u32, aces: Vec<Vec<u8>>) -> Vec<u8> {
+        let owner = raw_sid(owner_rid);
+        let aces_len: usize = aces.iter().map(|a| a.len()).sum();
+        let mut nt = vec![1, 0, 0x04, 0x80];
+        nt.extend_from_slice(&u32::to_le_bytes(20));
+        nt.extend_from_slice(&u32::to_le_bytes(0));
+        nt.extend_from_slice(&u32::to_le_bytes(0));
+        nt.extend_from_slice(&u32::to_le_bytes(20 + owner.len() as u32));
+        nt.extend(owner);
+        nt.extend([4, 0]);
+        nt.extend_from_slice(&u16::to_le_bytes(8 + aces_len as u16));
+        nt.extend_from_slice(&u16::to_le_bytes(aces.len() as u16));
+        nt.extend([0, 0]);
+        for ace in aces {
+            nt.extend(ace);
+        }
+        nt
+    }
+
+    fn edges(aces: &[crate::objects::common::AceTemplate]) -> Vec<(String, String)> {
+        aces.iter().map(|a| (a.principal_sid().to_owned(), a.right_name().to_owned())).collect()
+    }
+
+    #[test]
+    pub fn test_delegated_gplink_group() {
+        // Group RID 1107 can write gPLink on an OU and on the domain head.
+        let nt = security_descriptor(512, vec![
+            object_ace(0, 0x20, Some(WRITE_GPLINK), None, 1107),
+        ]);
+        let attrs = HashMap::new();
+        let bins = HashMap::new();
+
+        let mut ou = Ou::new();
+        let aces = parse_ntsecuritydescriptor(&mut ou, &nt, "OU", &attrs, &bins, "contoso.local");
+        assert_eq!(edges(&aces), vec![
+            ("S-1-5-21-1-2-3-512".to_string(), "Owns".to_string()),
+            ("S-1-5-21-1-2-3-1107".to_string(), "WriteGPLink".to_string()),
+        ]);
+
+        let mut domain = Domain::new();
+        let aces = parse_ntsecuritydescriptor(&mut domain, &nt, "Domain", &attrs, &bins, "contoso.local");
+        assert_eq!(edges(&aces), vec![
+            ("S-1-5-21-1-2-3-512".to_string(), "Owns".to_string()),
+            ("S-1-5-21-1-2-3-1107".to_string(), "WriteGPLink".to_string()),
+        ]);
+    }
+
+    #[test]
+    pub fn test_gpo_non_admin_owner() {
+        // User RID 1106 owns the GPO and group RID 1108 get GenericWrite
+        // inherited from CN=Policies,CN=System and scoped to groupPolicyContainer.
+        let nt = security_descriptor(1106, vec![
+            object_ace(INHERITED_ACE, 0x00020028, None, Some("f30e3bc2-9ff0-11d1-b603-0000f80367c1"), 1108),
+            // Scoped to user objects, must not apply on GPO.
+            object_ace(INHERITED_ACE, 0x00020028, None, Some("bf967aba-0de6-11d0-a285-00aa003049e2"), 1109),
+        ]);
+        let attrs = HashMap::new();
+        let bins = HashMap::new();
+
+        let mut gpo = Gpo::new();
+        let aces = parse_ntsecuritydescriptor(&mut gpo, &nt, "Gpo", &attrs, &bins, "contoso.local");
+        assert_eq!(edges(&aces), vec![
+            ("S-1-5-21-1-2-3-1106".to_string(), "Owns".to_string()),
+            ("S-1-5-21-1-2-3-1108".to_string(), "GenericWrite".to_string()),
+        ]);
+        assert!(*aces[1].is_inherited());
+    }
+
+    #[test]
+    pub fn test_explain_edges() {
+        // GenericAll for RID 1105, inherit-only ACE for RID 1106 that only applies to child objects
+        let nt = security_descriptor(512, vec![
+            object_ace(0, 0x000F01FF, None, None, 1105),
+            object_ace(CONTAINER_INHERIT_ACE | INHERIT_ONLY_ACE, 0x000F01FF, None, None, 1106),
+        ]);
+        let dn = "CN=EXPLAINED,CN=USERS,DC=CONTOSO,DC=LOCAL";
+        let entry = ldap3::SearchEntry { dn: dn.to_string(), attrs: HashMap::new(), bin_attrs: HashMap::new() };
+
+        enable_explain_edges(dn);
+        start_object(&entry, "Group", "contoso.local");
+        let mut group = Group::new();
+        let aces = parse_ntsecuritydescriptor(&mut group, &nt, "Group", &HashMap::new(), &HashMap::new(), "contoso.local");
+        finish_object();
+        let report = explain_report();
+        disable_explain_edges();
+
+        assert_eq!(edges(&aces), vec![
+            ("S-1-5-21-1-2-3-512".to_string(), "Owns".to_string()),
+            ("S-1-5-21-1-2-3-1105".to_string(), "GenericAll".to_string()),
+        ]);
+        assert!(report.contains(&format!("{dn} (Group)\n  owner S-1-5-21-1-2-3-512 -> Owns")));
+        assert!(report.contains(concat!(
+            "  ACE #0 at offset 0x38: ACCESS_ALLOWED_OBJECT flags=0x00\n",
+            "    principal: S-1-5-21-1-2-3-1105\n",
+            "    mask: 0x000f01ff (GENERIC_ALL)\n",
+            "    -> GenericAll",
+        )), "{report}");
+        assert!(report.contains(concat!(
+            "  ACE #1 at offset 0x60: ACCESS_ALLOWED_OBJECT flags=0x0a (CONTAINER_INHERIT|INHERIT_ONLY)\n",
+            "    principal: S-1-5-21-1-2-3-1106\n",
+            "    mask: 0x000f01ff (GENERIC_ALL)\n",
+            "    suppressed: inherit-only ACE, it only applies to the child objects",
+        )), "{report}");
+    }
+
+    #[test]
+    pub fn test_dedup_aces() {
+        // GenericAll and GenericWrite granted twice to the same principals, GenericAll inherited from the parent too
+        let nt = security_descriptor(512, vec![
+            object_ace(0, 0x000F01FF, None, None, 1105),
+            object_ace(0, 0x00020028, None, None, 1106),
+            object_ace(0, 0x000F01FF, None, None, 1105),
+            object_ace(INHERITED_ACE, 0x000F01FF, None, None, 1105),
+            object_ace(0, 0x00020028, None, None, 1106),
+            // AddMember from a write of all the properties and from a write of the member property
+            object_ace(0, 0x00000020, None, None, 1107),
+            object_ace(0, 0x00000020, Some("bf9679c0-0de6-11d0-a285-00aa003049e2"), None, 1107),
+        ]);
+        let mut group = Group::new();
+        let aces = parse_ntsecuritydescriptor(&mut group, &nt, "Group", &HashMap::new(), &HashMap::new(), "contoso.local");
+        assert_eq!(aces.len(), 9);
+
+        // First occurrences kept in their order, the inherited copy included
+        let aces = dedup_aces(aces);
+        assert_eq!(aces.len(), 6);
+        assert_eq!(edges(&aces), vec![
+            ("S-1-5-21-1-2-3-512".to_string(), "Owns".to_string()),
+            ("S-1-5-21-1-2-3-1105".to_string(), "GenericAll".to_string()),
+            ("S-1-5-21-1-2-3-1106".to_string(), "GenericWrite".to_string()),
+            ("S-1-5-21-1-2-3-1107".to_string(), "GenericWrite".to_string()),
+            ("S-1-5-21-1-2-3-1107".to_string(), "AddMember".to_string()),
+            ("S-1-5-21-1-2-3-1107".to_string(), "AddMember".to_string()),
+        ]);
+        assert!(!*aces[1].is_inherited());
+        // Same principal, right and ACE type, another object type
+        assert_eq!(*aces[4].ace_object_type(), None);
+        assert!(aces[5].ace_object_type().is_some());
+    }
+
+    #[test]
+    pub fn test_inherited_aces() {
+        // Explicit GenericAll for RID 1105, GenericWrite for RID 1106 inherited from the parent
+        let nt = security_descriptor(512, vec![
+            object_ace(0, 0x000F01FF, None, None, 1105),
+            object_ace(INHERITED_ACE, 0x00020028, None, None, 1106),
+        ]);
+        let mut group = Group::new();
+        let aces = parse_ntsecuritydescriptor(&mut group, &nt, "Group", &HashMap::new(), &HashMap::new(), "contoso.local");
+        assert_eq!(edges(&aces[1..]), vec![
+            ("S-1-5-21-1-2-3-1105".to_string(), "GenericAll".to_string()),
+            ("S-1-5-21-1-2-3-1106".to_string(), "GenericWrite".to_string()),
+        ]);
+        let inherited: Vec<bool> = aces.iter().map(|ace| *ace.is_inherited()).collect();
+        assert_eq!(inherited, [false, false, true]);
+        assert_eq!(serde_json::to_value(&aces[2]).unwrap()["IsInherited"], true);
+
+        // ACE of an output without IsInherited
+        let ace: AceTemplate = serde_json::from_value(serde_json::json!({
+            "PrincipalSID": "S-1-5-21-1-2-3-1105",
+            "PrincipalType": "Group",
+            "RightName": "GenericAll",
+            "InheritanceHash": "",
+        })).unwrap();
+        assert!(!*ace.is_inherited());
+    }
+}
@@ -1,10 +1,12 @@
 use bitflags::bitflags;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 
+use crate::enums::secdesc::{LdapSid, SecurityDescriptor};
+use crate::enums::sid::sid_maker;
 use crate::objects::{
     certtemplate::CertTemplate,
-    enterpriseca::EnterpriseCA, common::Member,
+    enterpriseca::{EnterpriseCA, EnrollmentAgentRestriction}, common::Member,
 };
 
 bitflags! {
@@ -317,37 +319,271 @@ pub fn get_pki_private_flags(value: u64) -> String
     flags.join(", ")
 }
 
-/// Function to replace displayname by SID in enabled cert templates.
+bitflags! {
+    struct CaFlag: u64 {
+        const NO_TEMPLATE_SUPPORT = 0x00000001;
+        const SUPPORTS_NT_AUTHENTICATION = 0x00000002;
+        const CA_SUPPORTS_MANUAL_AUTHENTICATION = 0x00000004;
+        const CA_SERVERTYPE_ADVANCED = 0x00000008;
+    }
+}
+
+/// Get the CA flags from "flags" LDAP attribut of a pKIEnrollmentService object.
+/// The editing flags (EDITF_*) are in the CA registry, not in this attribute.
+/// MS: <https://learn.microsoft.com/en-us/windows/win32/api/certca/nf-certca-cagetcaflags>
+pub fn get_ca_flags(value: u64) -> String
+{
+    let mut flags: Vec<String> = Vec::new();
+
+    if (CaFlag::NO_TEMPLATE_SUPPORT.bits() | value) == value
+    {
+        flags.push("NO_TEMPLATE_SUPPORT".to_string());
+    }
+    if (CaFlag::SUPPORTS_NT_AUTHENTICATION.bits() | value) == value
+    {
+        flags.push("SUPPORTS_NT_AUTHENTICATION".to_string());
+    }
+    if (CaFlag::CA_SUPPORTS_MANUAL_AUTHENTICATION.bits() | value) == value
+    {
+        flags.push("CA_SUPPORTS_MANUAL_AUTHENTICATION".to_string());
+    }
+    if (CaFlag::CA_SERVERTYPE_ADVANCED.bits() | value) == value
+    {
+        flags.push("CA_SERVERTYPE_ADVANCED".to_string());
+    }
+    flags.join(", ")
+}
+
+/// EditFlags bit letting the requester set the subject alternative name of any request (ESC6).
+pub const EDITF_ATTRIBUTESUBJECTALTNAME2: u32 = 0x00040000;
+
+/// Check EDITF_ATTRIBUTESUBJECTALTNAME2 in the EditFlags value of the CA policy module registry key
+/// (`CertSvc\Configuration\<CAName>\PolicyModules\CertificateAuthority_MicrosoftDefault.Policy`).
+/// MS: <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-wcce/c8f3c3bf-8ea6-4cdb-955a-23aa3e0b8a1d>
+pub fn is_user_specifies_san_enabled(edit_flags: u32) -> bool {
+    edit_flags & EDITF_ATTRIBUTESUBJECTALTNAME2 != 0
+}
+
+/// Callback ACEs of the EnrollmentAgentRights descriptor.
+const ACCESS_ALLOWED_CALLBACK_ACE_TYPE: u8 = 0x09;
+const ACCESS_DENIED_CALLBACK_ACE_TYPE: u8 = 0x0A;
+
+/// Function to parse the EnrollmentAgentRights security descriptor of the CA registry.
+/// Each callback ACE is an enrollment agent, its application data the number of target SIDs, the
+/// targets and the optional template name (UTF-16, all templates without it).
+pub fn parse_enrollment_agent_rights(nt: &[u8], domain: &str) -> Result<Vec<EnrollmentAgentRestriction>, String> {
+    let secdesc = SecurityDescriptor::parse(nt).map_err(|err| format!("invalid security descriptor: {err}"))?.1;
+    let mut restrictions = Vec::new();
+    let offset = secdesc.offset_dacl as usize;
+    if offset == 0 {
+        return Ok(restrictions)
+    }
+    let acl = nt.get(offset..offset + 8).ok_or("DACL out of the security descriptor")?;
+    let ace_count = u16::from_le_bytes([acl[4], acl[5]]);
+    let mut position = offset + 8;
+    for _ in 0..ace_count {
+        let header = nt.get(position..position + 4).ok_or("truncated DACL")?;
+        let ace_size = u16::from_le_bytes([header[2], header[3]]) as usize;
+        let ace = nt.get(position + 4..position + ace_size.max(4)).ok_or("truncated ACE")?;
+        position += ace_size.max(4);
+        let access_type = match header[0] {
+            ACCESS_ALLOWED_CALLBACK_ACE_TYPE => "AccessAllowedCallback",
+            ACCESS_DENIED_CALLBACK_ACE_TYPE => "AccessDeniedCallback",
+            _ => continue,
+        };
+        // Access mask then the agent SID
+        let (data, agent) = LdapSid::parse(ace.get(4..).ok_or("truncated ACE")?)
+            .map_err(|err| format!("invalid enrollment agent SID: {err}"))?;
+        let (mut data, target_count) = match data {
+            [a, b, c, d, rest @ ..] => (rest, u32::from_le_bytes([*a, *b, *c, *d])),
+            _ => return Err("truncated enrollment agent targets".to_string()),
+        };
+        let mut targets = Vec::new();
+        for _ in 0..target_count {
+            let (rest, target) = LdapSid::parse(data).map_err(|err| format!("invalid enrollment agent target: {err}"))?;
+            targets.push(sid_maker(target, domain));
+            data = rest;
+        }
+        let template = String::from_utf16_lossy(
+            &data.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect::<Vec<u16>>()
+        ).trim_end_matches('\0').to_string();
+        restrictions.push(EnrollmentAgentRestriction::new(
+            access_type,
+            sid_maker(agent, domain),
+            targets,
+            (!template.is_empty()).then_some(template),
+        ));
+    }
+    Ok(restrictions)
+}
+
+/// Function to replace the names of the templates published by the CAs (certificateTemplates) by the objectGUID of
+/// the collected templates, the names not collected are kept in `unresolvedpublishedtemplates`.
 pub fn templates_enabled_change_displayname_to_sid(
     vec_certtemplates: &mut [CertTemplate],
     vec_enterprisecas: &mut [EnterpriseCA],
 ) -> Result<(), Box<dyn Error>> {
-    let mut name_sid: HashMap<String, String> = HashMap::new();
-    for certtemplate in vec_certtemplates {
-        name_sid.insert(
-            certtemplate.properties().name().to_owned(),
-            certtemplate.object_identifier().to_owned(),
-         );
-    }
-    // println!("{:?}",&name_sid);
+    // Template cn to objectGUID, the node name of a template is `CN@DOMAIN`
+    let name_guid: HashMap<String, String> = vec_certtemplates.iter()
+        .map(|certtemplate| {
+            let name = certtemplate.properties().name();
+            let cn = name.rsplit_once('@').map_or(name.as_str(), |(cn, _)| cn);
+            (cn.to_uppercase(), certtemplate.object_identifier().to_owned())
+        })
+        .collect();
+    let guids: HashSet<&String> = name_guid.values().collect();
 
     for enterprise_ca in vec_enterprisecas {
-        let templates = enterprise_ca.enabled_cert_templates();
         let mut enabled_cert_templates: Vec<Member> = Vec::new();
-        for template in templates {
-            let mut member = Member::new();
-            // println!("{:?}",&template.object_identifier());
-            if let Some(value) = name_sid.keys()
-            .find(|&key| key.contains(&template.object_identifier().to_uppercase()))
-            .and_then(|key| name_sid.get(key))
-            {
-                *member.object_identifier_mut() = value.to_owned();
-                *member.object_type_mut() = template.object_type().to_owned();
-                enabled_cert_templates.push(member);
+        let mut unresolved: Vec<String> = Vec::new();
+        for template in enterprise_ca.enabled_cert_templates() {
+            let name = template.object_identifier();
+            // Already resolved
+            let guid = if guids.contains(name) {
+                Some(name)
+            } else {
+                name_guid.get(&name.to_uppercase())
+            };
+            match guid {
+                Some(guid) => {
+                    let mut member = Member::new();
+                    *member.object_identifier_mut() = guid.to_owned();
+                    *member.object_type_mut() = template.object_type().to_owned();
+                    enabled_cert_templates.push(member);
+                }
+                None => unresolved.push(name.to_owned()),
             }
         }
         // Fixe values in enterprise CA
         *enterprise_ca.enabled_cert_templates_mut() = enabled_cert_templates;
+        *enterprise_ca.unresolved_published_templates_mut() = unresolved;
+
+        // Enrollment agent restriction templates, the name is kept when the template is not found
+        for restriction in enterprise_ca.enrollment_agent_restrictions_mut() {
+            if let Some(template) = restriction.template_mut() {
+                if let Some(guid) = name_guid.get(&template.object_identifier().to_uppercase()) {
+                    *template.object_identifier_mut() = guid.to_owned();
+                }
+            }
+        }
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::enums::adcs::*;
+    use crate::enums::sddl::sid_to_bytes;
+    use crate::objects::common::LdapObject;
+
+    #[test]
+    pub fn test_get_ca_flags() {
+        // Enterprise CA: flags = 10
+        assert_eq!(get_ca_flags(10), "SUPPORTS_NT_AUTHENTICATION, CA_SERVERTYPE_ADVANCED");
+        assert_eq!(get_ca_flags(1), "NO_TEMPLATE_SUPPORT");
+        assert_eq!(get_ca_flags(0), "");
+        // Unknown bits are ignored
+        assert_eq!(get_ca_flags(0x100 | 0x4), "CA_SUPPORTS_MANUAL_AUTHENTICATION");
+    }
+
+    #[test]
+    pub fn test_is_user_specifies_san_enabled() {
+        // Default EditFlags of an Enterprise CA
+        assert!(!is_user_specifies_san_enabled(0x0011014E));
+        // certutil -setreg policy\EditFlags +EDITF_ATTRIBUTESUBJECTALTNAME2
+        assert!(is_user_specifies_san_enabled(0x0015014E));
+        assert!(is_user_specifies_san_enabled(EDITF_ATTRIBUTESUBJECTALTNAME2));
+        assert!(!is_user_specifies_san_enabled(0));
+    }
+
+    /// Callback ACE: access mask, agent SID, target count, target SIDs and template name.
+    fn callback_ace(agent: &str, targets: &[&str], template: &str) -> Vec<u8> {
+        let mut body = 0x0000_0100u32.to_le_bytes().to_vec();
+        body.extend(sid_to_bytes(agent).unwrap());
+        body.extend((targets.len() as u32).to_le_bytes());
+        for target in targets {
+            body.extend(sid_to_bytes(target).unwrap());
+        }
+        if !template.is_empty() {
+            body.extend(template.encode_utf16().chain([0]).flat_map(u16::to_le_bytes));
+        }
+        let mut ace = vec![ACCESS_ALLOWED_CALLBACK_ACE_TYPE, 0];
+        ace.extend(((body.len() + 4) as u16).to_le_bytes());
+        ace.extend(body);
+        ace
+    }
+
+    #[test]
+    pub fn test_parse_enrollment_agent_rights() {
+        let domain_sid = "S-1-5-21-1004336348-1177238915-682003330";
+        let aces = [
+            callback_ace(&format!("{domain_sid}-1105"), &[&format!("{domain_sid}-513"), &format!("{domain_sid}-1106")], "User"),
+            callback_ace("S-1-5-11", &["S-1-1-0"], ""),
+        ];
+        // Self-relative descriptor with only a DACL
+        let mut sd = vec![0x01, 0x00, 0x04, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 20, 0, 0, 0];
+        let acl_size = 8 + aces.iter().map(Vec::len).sum::<usize>();
+        sd.extend([0x04, 0x00]);
+        sd.extend((acl_size as u16).to_le_bytes());
+        sd.extend([2, 0, 0, 0]);
+        sd.extend(aces.concat());
+
+        let restrictions = parse_enrollment_agent_rights(&sd, "contoso.local").unwrap();
+        let json = serde_json::to_value(&restrictions).unwrap();
+        assert_eq!(json, serde_json::json!([
+            {
+                "AccessType": "AccessAllowedCallback",
+                "Agent": {"ObjectIdentifier": format!("{domain_sid}-1105"), "ObjectType": "Base"},
+                "Targets": [
+                    {"ObjectIdentifier": format!("{domain_sid}-513"), "ObjectType": "Base"},
+                    {"ObjectIdentifier": format!("{domain_sid}-1106"), "ObjectType": "Base"},
+                ],
+                "Template": {"ObjectIdentifier": "User", "ObjectType": "CertTemplate"},
+                "AllTemplates": false,
+            },
+            {
+                "AccessType": "AccessAllowedCallback",
+                "Agent": {"ObjectIdentifier": "CONTOSO.LOCAL-S-1-5-11", "ObjectType": "Base"},
+                "Targets": [{"ObjectIdentifier": "CONTOSO.LOCAL-S-1-1-0", "ObjectType": "Base"}],
+                "Template": null,
+                "AllTemplates": true,
+            },
+        ]));
+
+        // Truncated ACE
+        assert!(parse_enrollment_agent_rights(&sd[..sd.len() - 10], "contoso.local").is_err());
+    }
+
+    #[test]
+    pub fn test_templates_enabled_change_displayname_to_sid() {
+        let entry = ldap3::SearchEntry {
+            dn: "CN=User,CN=Certificate Templates,CN=Public Key Services,CN=Services,CN=Configuration,DC=CONTOSO,DC=LOCAL".to_string(),
+            attrs: HashMap::from([("name".to_string(), vec!["User".to_string()])]),
+            bin_attrs: HashMap::from([
+                ("objectGUID".to_string(), vec![vec![0x64, 0x7D, 0x1A, 0x7E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]]),
+            ]),
+        };
+        let mut certtemplate = CertTemplate::new();
+        certtemplate.parse(entry, "contoso.local", &mut HashMap::new(), &mut HashMap::new(), "S-1-5-21-1-2-3").unwrap();
+        let mut certtemplates = vec![certtemplate];
+        // SmartcardUser contains User but is not collected
+        let mut enterprisecas = vec![
+            EnterpriseCA::builder("5A6B6E0C-6A8B-4E3E-9B2D-1C2D3E4F5A6B")
+                .domain("CONTOSO.LOCAL", "S-1-5-21-1-2-3")
+                .caname("contoso-DC01-CA")
+                .enabled_cert_templates(&["User", "SmartcardUser"])
+                .build(),
+        ];
+        templates_enabled_change_displayname_to_sid(&mut certtemplates, &mut enterprisecas).unwrap();
+        let json = enterprisecas[0].to_json();
+        assert_eq!(json["EnabledCertTemplates"], serde_json::json!([
+            {"ObjectIdentifier": "7E1A7D64-0000-0000-0000-000000000001", "ObjectType": "CertTemplate"},
+        ]));
+        assert_eq!(json["Properties"]["unresolvedpublishedtemplates"], serde_json::json!(["SmartcardUser"]));
+
+        // Resolved twice: the objectGUID is kept
+        templates_enabled_change_displayname_to_sid(&mut certtemplates, &mut enterprisecas).unwrap();
+        assert_eq!(enterprisecas[0].enabled_cert_templates().len(), 1);
+    }
+}
@@ -3,7 +3,7 @@ pub const ACCESS_DENIED_ACE_TYPE: u8 = 0x01;
 pub const ACCESS_ALLOWED_OBJECT_ACE_TYPE: u8 = 0x05;
 pub const ACCESS_DENIED_OBJECT_ACE_TYPE: u8 = 0x06;
 
-pub const CONTAINER_INHERIT_ACE: u8 = 0x01;
+pub const CONTAINER_INHERIT_ACE: u8 = 0x02;
 pub const FAILED_ACCESS_ACE_FLAG: u8 = 0x80;
 pub const INHERIT_ONLY_ACE: u8 = 0x08;
 pub const INHERITED_ACE: u8 = 0x10;
@@ -0,0 +1,359 @@
+//! ACE provenance for `--explain-edges`
+//!
+//! While the nTSecurityDescriptor of an object is parsed, each DACL ACE is recorded with its
+//! offset in the raw security descriptor, its decoded mask and object types, and the mapping
+//! decision: the edges it produced or the reason it was suppressed.
+//!
+//! Only the objects matching the target (SID, GUID, name or distinguished name) and the ACEs
+//! whose principal SID is the target are kept in the explanation file:
+//!
+//! ```text
+//! CN=HELPDESK,OU=GROUPS,DC=CONTOSO,DC=LOCAL (Group)
+//!   owner S-1-5-21-1004336348-1177238915-682003330-512 -> Owns
+//!   ACE #0 at offset 0x30: ACCESS_ALLOWED flags=0x02 (CONTAINER_INHERIT)
+//!     principal: S-1-5-21-1004336348-1177238915-682003330-1105
+//!     mask: 0x000f01ff (GENERIC_ALL)
+//!     -> GenericAll
+//! ```
+//!
+//! When the option is not set, the ACE mapping code only checks one atomic flag per security descriptor.
+use std::cell::RefCell;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use ldap3::SearchEntry;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+
+use crate::enums::acl::object_type_name;
+use crate::enums::constants::*;
+use crate::enums::secdesc::{Ace, AceFormat, LdapSid};
+use crate::enums::sid::{decode_guid_le, sid_maker};
+use crate::objects::common::AceTemplate;
+
+/// Maximum number of objects written in the explanation file, the next ones are only counted.
+pub const EXPLAIN_MAX_OBJECTS: usize = 10_000;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TARGET: Lazy<Mutex<String>> = Lazy::new(|| Mutex::new(String::new()));
+static REPORT: Lazy<Mutex<ExplainReport>> = Lazy::new(|| Mutex::new(ExplainReport::default()));
+
+thread_local! {
+    /// Object being parsed on this thread.
+    static CURRENT: RefCell<Option<ObjectTrace>> = const { RefCell::new(None) };
+}
+
+#[derive(Default)]
+struct ExplainReport {
+    objects: Vec<String>,
+    dropped: usize,
+}
+
+/// Object being parsed, with the values the target is compared to.
+struct ObjectTrace {
+    title: String,
+    labels: Vec<String>,
+    owner: Option<String>,
+    aces: Vec<AceExplanation>,
+}
+
+/// Mapping decision for one DACL ACE.
+#[derive(Debug, Clone, Default)]
+pub struct AceExplanation {
+    pub index: usize,
+    pub offset: usize,
+    pub ace_type: u8,
+    pub ace_flags: u8,
+    pub mask: Option<u32>,
+    pub principal: String,
+    pub object_type: Option<String>,
+    pub inherited_object_type: Option<String>,
+    pub size: usize,
+    pub edges: Vec<String>,
+    pub suppressed: Option<String>,
+    first_edge: usize,
+}
+
+/// Function to start recording the ACE mapping decisions for objects or principals matching `target`.
+pub fn enable_explain_edges(target: &str) {
+    *TARGET.lock().unwrap() = target.trim().to_uppercase();
+    *REPORT.lock().unwrap() = ExplainReport::default();
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Function to stop recording the ACE mapping decisions.
+pub fn disable_explain_edges() {
+    ENABLED.store(false, Ordering::SeqCst);
+}
+
+/// Function to check if `--explain-edges` is set.
+pub fn is_explain_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Function to start tracing one LDAP entry before it is parsed.
+pub fn start_object(entry: &SearchEntry, entry_type: &str, domain: &str) {
+    if !is_explain_enabled() {
+        return
+    }
+    let mut labels = vec![entry.dn.to_uppercase()];
+    for attribute in ["sAMAccountName", "name", "displayName", "dNSHostName"] {
+        if let Some(value) = entry.attrs.get(attribute).and_then(|v| v.first()) {
+            labels.push(value.to_uppercase());
+            labels.push(format!("{}@{}", value, domain).to_uppercase());
+        }
+    }
+    if let Some(Ok((_, sid))) = entry.bin_attrs.get("objectSid").and_then(|v| v.first()).map(|v| LdapSid::parse(v)) {
+        labels.push(sid_maker(sid, domain));
+    }
+    if let Some(guid) = entry.bin_attrs.get("objectGUID").and_then(|v| v.first()).filter(|v| v.len() == 16) {
+        labels.push(decode_guid_le(guid));
+    }
+    CURRENT.with(|current| {
+        *current.borrow_mut() = Some(ObjectTrace {
+            title: format!("{} ({})", entry.dn.to_uppercase(), entry_type),
+            labels,
+            owner: None,
+            aces: Vec::new(),
+        })
+    });
+}
+
+/// Function to stop tracing the current object and keep its explanation if it matches the target.
+pub fn finish_object() {
+    if !is_explain_enabled() {
+        return
+    }
+    let Some(trace) = CURRENT.with(|current| current.borrow_mut().take()) else {
+        return
+    };
+    let target = TARGET.lock().unwrap().to_owned();
+    let aces: Vec<&AceExplanation> = if trace.labels.contains(&target) {
+        trace.aces.iter().collect()
+    } else {
+        trace.aces.iter().filter(|ace| principal_matches(&ace.principal, &target)).collect()
+    };
+    if aces.is_empty() {
+        return
+    }
+
+    let mut report = REPORT.lock().unwrap();
+    if report.objects.len() >= EXPLAIN_MAX_OBJECTS {
+        if report.dropped == 0 {
+            warn!("More than {EXPLAIN_MAX_OBJECTS} objects match --explain-edges, the next ones are not explained");
+        }
+        report.dropped += 1;
+        return
+    }
+    let mut text = trace.title.to_owned();
+    if let Some(owner) = &trace.owner {
+        text.push_str(&format!("\n  {owner}"));
+    }
+    for ace in aces {
+        text.push('\n');
+        text.push_str(&ace.to_string());
+    }
+    report.objects.push(text);
+}
+
+/// Function to get the explanation text of all the matching objects.
+pub fn explain_report() -> String {
+    let report = REPORT.lock().unwrap();
+    let mut text = format!("Edges explanation for {}\n", TARGET.lock().unwrap());
+    for object in &report.objects {
+        text.push('\n');
+        text.push_str(object);
+        text.push('\n');
+    }
+    if report.dropped > 0 {
+        text.push_str(&format!("\n{} more objects not explained (limit {EXPLAIN_MAX_OBJECTS})\n", report.dropped));
+    }
+    text
+}
+
+/// Function to write the explanation file in the output directory, return its path.
+pub fn write_explain_report(path: &str, domain: &str) -> Result<Option<String>, Box<dyn Error>> {
+    if !is_explain_enabled() {
+        return Ok(None)
+    }
+    std::fs::create_dir_all(path)?;
+    let file = format!("{}/{}_explain_edges.txt", path.trim_end_matches('/'), domain.to_lowercase());
+    std::fs::write(&file, explain_report())?;
+    info!("Edges explanation written to {file}");
+    Ok(Some(file))
+}
+
+/// Well-known SIDs are prefixed by the domain name (`CONTOSO.LOCAL-S-1-5-32-544`).
+fn principal_matches(principal: &str, target: &str) -> bool {
+    let principal = principal.to_uppercase();
+    principal == target || (target.starts_with("S-") && principal.ends_with(&format!("-{target}")))
+}
+
+/// Tracer used by the ACE mapping code, does nothing if no object is traced on this thread.
+pub(crate) struct AceTracer {
+    aces: Option<Vec<AceExplanation>>,
+    dacl_offset: usize,
+}
+
+impl AceTracer {
+    /// Start tracing the DACL of the current object, `dacl_offset` is the offset of its first ACE.
+    pub fn new(dacl_offset: usize) -> Self {
+        let traced = is_explain_enabled() && CURRENT.with(|current| current.borrow().is_some());
+        Self { aces: traced.then(Vec::new), dacl_offset }
+    }
+
+    /// Record the owner and if the Owns edge is made.
+    pub fn owner(&mut self, owner_sid: &str, emitted: bool) {
+        if self.aces.is_none() || owner_sid.is_empty() {
+            return
+        }
+        let text = match emitted {
+            true => format!("owner {owner_sid} -> Owns"),
+            false => format!("owner {owner_sid} ignored (Creator Owner, Local System or Principal Self)"),
+        };
+        CURRENT.with(|current| {
+            if let Some(trace) = current.borrow_mut().as_mut() {
+                trace.owner = Some(text);
+            }
+        });
+    }
+
+    /// Record the edges made by the previous ACE and start explaining the next one.
+    pub fn ace(&mut self, ace: &Ace, domain: &str, relations: &[AceTemplate]) {
+        let Some(aces) = self.aces.as_mut() else {
+            return
+        };
+        let offset = match aces.last() {
+            Some(previous) => previous.offset + previous.size,
+            None => self.dacl_offset,
+        };
+        close_previous(aces, relations);
+        let guid = |value: Option<u128>| value.map(|v| {
+            let guid = decode_guid_le(&v.to_le_bytes()).to_lowercase();
+            match object_type_name(&guid) {
+                Some(name) => format!("{guid} ({name})"),
+                None => guid,
+            }
+        });
+        aces.push(AceExplanation {
+            index: aces.len(),
+            offset,
+            ace_type: ace.ace_type,
+            ace_flags: ace.ace_flags,
+            size: ace.ace_size as usize,
+            mask: AceFormat::get_mask(&ace.data),
+            principal: AceFormat::get_sid(ace.data.to_owned()).map(|sid| sid_maker(sid, domain)).unwrap_or_default(),
+            object_type: guid(AceFormat::get_object_type(&ace.data)),
+            inherited_object_type: guid(AceFormat::get_inherited_object_type(&ace.data)),
+            first_edge: relations.len(),
+            ..Default::default()
+        });
+    }
+
+    /// Record why the current ACE doesn't make any edge.
+    pub fn suppress(&mut self, reason: &str) {
+        if let Some(ace) = self.aces.as_mut().and_then(|aces| aces.last_mut()) {
+            ace.suppressed = Some(reason.to_owned());
+        }
+    }
+
+    /// Record the edges made by the last ACE and attach the explanations to the current object.
+    pub fn finish(self, relations: &[AceTemplate]) {
+        let Some(mut aces) = self.aces else {
+            return
+        };
+        close_previous(&mut aces, relations);
+        CURRENT.with(|current| {
+            if let Some(trace) = current.borrow_mut().as_mut() {
+                trace.aces.extend(aces);
+            }
+        });
+    }
+}
+
+/// Access rights names, the generic ones first so their bits are not listed twice.
+const MASK_NAMES: &[(u32, &str)] = &[
+    (0x000F01FF, "GENERIC_ALL"),
+    (0x00020028, "GENERIC_WRITE"),
+    (0x00020094, "GENERIC_READ"),
+    (0x00080000, "WRITE_OWNER"),
+    (0x00040000, "WRITE_DACL"),
+    (0x00020000, "READ_CONTROL"),
+    (0x00010000, "DELETE"),
+    (0x00000100, "CONTROL_ACCESS"),
+    (0x00000080, "LIST_OBJECT"),
+    (0x00000040, "DELETE_TREE"),
+    (0x00000020, "WRITE_PROP"),
+    (0x00000010, "READ_PROP"),
+    (0x00000008, "SELF"),
+    (0x00000004, "LIST_CHILDREN"),
+    (0x00000002, "DELETE_CHILD"),
+    (0x00000001, "CREATE_CHILD"),
+];
+
+/// Function to decode an access mask like `GENERIC_WRITE|CONTROL_ACCESS`.
+pub fn mask_to_string(mask: u32) -> String {
+    let mut remaining = mask;
+    let mut names = Vec::new();
+    for (bits, name) in MASK_NAMES {
+        if remaining & bits == *bits {
+            names.push(name.to_string());
+            remaining &= !bits;
+        }
+    }
+    if remaining != 0 {
+        names.push(format!("{remaining:#x}"));
+    }
+    names.join("|")
+}
+
+fn close_previous(aces: &mut [AceExplanation], relations: &[AceTemplate]) {
+    if let Some(previous) = aces.last_mut() {
+        previous.edges = relations.iter()
+            .skip(previous.first_edge)
+            .map(|ace| ace.right_name().to_owned())
+            .collect();
+    }
+}
+
+impl std::fmt::Display for AceExplanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ace_type = match self.ace_type {
+            ACCESS_ALLOWED_ACE_TYPE => "ACCESS_ALLOWED",
+            ACCESS_DENIED_ACE_TYPE => "ACCESS_DENIED",
+            ACCESS_ALLOWED_OBJECT_ACE_TYPE => "ACCESS_ALLOWED_OBJECT",
+            ACCESS_DENIED_OBJECT_ACE_TYPE => "ACCESS_DENIED_OBJECT",
+            _ => "UNKNOWN",
+        };
+        let flags: Vec<&str> = [
+            (OBJECT_INHERIT_ACE, "OBJECT_INHERIT"),
+            (CONTAINER_INHERIT_ACE, "CONTAINER_INHERIT"),
+            (NO_PROPAGATE_INHERIT_ACE, "NO_PROPAGATE_INHERIT"),
+            (INHERIT_ONLY_ACE, "INHERIT_ONLY"),
+            (INHERITED_ACE, "INHERITED"),
+        ].iter().filter(|(flag, _)| self.ace_flags & flag == *flag).map(|(_, name)| *name).collect();
+        let flags = match flags.is_empty() {
+            true => String::new(),
+            false => format!(" ({})", flags.join("|")),
+        };
+        writeln!(f, "  ACE #{} at offset {:#x}: {} flags={:#04x}{}", self.index, self.offset, ace_type, self.ace_flags, flags)?;
+        writeln!(f, "    principal: {}", self.principal)?;
+        if let Some(mask) = self.mask {
+            writeln!(f, "    mask: {:#010x} ({})", mask, mask_to_string(mask))?;
+        }
+        if let Some(object_type) = &self.object_type {
+            writeln!(f, "    object type: {object_type}")?;
+        }
+        if let Some(inherited_object_type) = &self.inherited_object_type {
+            writeln!(f, "    inherited object type: {inherited_object_type}")?;
+        }
+        if !self.edges.is_empty() {
+            write!(f, "    -> {}", self.edges.join(", "))
+        } else if let Some(reason) = &self.suppressed {
+            write!(f, "    suppressed: {reason}")
+        } else {
+            write!(f, "    no edge: no right mapped for this object type")
+        }
+    }
+}
@@ -1,9 +1,9 @@
-use std::error::Error;
+use crate::error::RustHoundError;
 use crate::objects::common::Link;
 use crate::enums::regex::{GPLINK_RE1,GPLINK_RE2};
 
 /// Function to parse gplink and push it in json format
-pub fn parse_gplink(all_link: String) -> Result<Vec<Link>, Box<dyn Error>> {
+pub fn parse_gplink(all_link: String) -> Result<Vec<Link>, RustHoundError> {
    let mut gplinks: Vec<Link> = Vec::new();
 
    let mut cpaths: Vec<String> = Vec::new();
@@ -2,6 +2,7 @@ use ldap3::SearchEntry;
 //use log::trace;
 
 /// Enum to get ldap object type.
+#[derive(Debug)]
 pub enum Type {
     User,
     Computer,
@@ -18,6 +19,7 @@ pub enum Type {
     AIACA,
     CertTemplate,
     IssuancePolicie,
+    ClassSchema,
     Unknown
 }
 
@@ -37,7 +39,8 @@ pub fn get_type(result: &SearchEntry) -> std::result::Result<Type, Type> {
                 && !contains(vals, "group") => {
                 return Ok(Type::User);
             }
-            _ if contains(vals, "msDS-GroupManagedServiceAccount") => {
+            _ if contains(vals, "msDS-GroupManagedServiceAccount")
+                || contains(vals, "msDS-DelegatedManagedServiceAccount") => {
                 return Ok(Type::User);
             }
             _ if contains(vals, "group") => {
@@ -94,6 +97,9 @@ pub fn get_type(result: &SearchEntry) -> std::result::Result<Type, Type> {
                     }
                 }
             }
+            _ if contains(vals, "classSchema") => {
+                return Ok(Type::ClassSchema);
+            }
             _ => {}
         }
     }
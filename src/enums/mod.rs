@@ -33,4 +33,6 @@ pub mod gplink;
 pub mod constants;
 pub mod trusts;
 pub mod adcs;
-pub mod regex;
\ No newline at end of file
+pub mod regex;
+pub mod sddl;
+pub mod explain;
\ No newline at end of file
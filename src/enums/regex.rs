@@ -19,4 +19,7 @@ pub static OBJECT_SID_RE1: Lazy<Regex> = Lazy::new(|| Regex::new(r"^S-[0-9]{1}-[
 pub static SID_PART1_RE1: Lazy<Regex> = Lazy::new(|| Regex::new(r"S-.*-").unwrap());
 
 // Use by ./RustHound-CE/src/enums/sid.rs
-pub static IS_SID_RE1: Lazy<Regex> = Lazy::new(|| Regex::new(r".*S-1-5.*").unwrap());
\ No newline at end of file
+pub static IS_SID_RE1: Lazy<Regex> = Lazy::new(|| Regex::new(r".*S-1-5.*").unwrap());
+// Use by ./RustHound-CE/src/json/maker/identifiers.rs
+pub static CANONICAL_SID_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?:\S+-)?S-1-[0-9]+(?:-[0-9]+)*$").unwrap());
+pub static CANONICAL_GUID_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[0-9A-F]{8}-[0-9A-F]{4}-[0-9A-F]{4}-[0-9A-F]{4}-[0-9A-F]{12}$").unwrap());
@@ -0,0 +1,283 @@
+//! Convert SDDL strings (like the schema `defaultSecurityDescriptor`) to self-relative security descriptors.
+//! <https://learn.microsoft.com/en-us/windows/win32/secauthz/security-descriptor-string-format>
+use crate::error::RustHoundError;
+
+use crate::enums::constants::*;
+
+/// SDDL SID aliases, domain relative aliases start with `-`.
+const SID_ALIASES: &[(&str, &str)] = &[
+    ("AN", "S-1-5-7"),
+    ("AO", "S-1-5-32-548"),
+    ("AU", "S-1-5-11"),
+    ("BA", "S-1-5-32-544"),
+    ("BG", "S-1-5-32-546"),
+    ("BO", "S-1-5-32-551"),
+    ("BU", "S-1-5-32-545"),
+    ("CD", "S-1-5-32-574"),
+    ("CG", "S-1-3-1"),
+    ("CO", "S-1-3-0"),
+    ("ED", "S-1-5-9"),
+    ("ER", "S-1-5-32-573"),
+    ("IU", "S-1-5-4"),
+    ("LS", "S-1-5-19"),
+    ("NS", "S-1-5-20"),
+    ("NU", "S-1-5-2"),
+    ("OW", "S-1-3-4"),
+    ("PO", "S-1-5-32-550"),
+    ("PS", "S-1-5-10"),
+    ("PU", "S-1-5-32-547"),
+    ("RC", "S-1-5-12"),
+    ("RD", "S-1-5-32-555"),
+    ("RE", "S-1-5-32-552"),
+    ("RU", "S-1-5-32-554"),
+    ("SO", "S-1-5-32-549"),
+    ("SU", "S-1-5-6"),
+    ("SY", "S-1-5-18"),
+    ("WD", "S-1-1-0"),
+    ("CA", "-517"),
+    ("CN", "-522"),
+    ("DA", "-512"),
+    ("DC", "-515"),
+    ("DD", "-516"),
+    ("DG", "-514"),
+    ("DU", "-513"),
+    ("EA", "-519"),
+    ("EK", "-527"),
+    ("KA", "-526"),
+    ("LA", "-500"),
+    ("LG", "-501"),
+    ("PA", "-520"),
+    ("RO", "-498"),
+    ("RS", "-553"),
+    ("SA", "-518"),
+    ("AP", "-525"),
+];
+
+/// SDDL access rights.
+const RIGHTS: &[(&str, u32)] = &[
+    ("GA", 0x10000000),
+    ("GR", 0x80000000),
+    ("GW", 0x40000000),
+    ("GX", 0x20000000),
+    ("RC", 0x00020000),
+    ("SD", 0x00010000),
+    ("WD", 0x00040000),
+    ("WO", 0x00080000),
+    ("RP", 0x00000010),
+    ("WP", 0x00000020),
+    ("CC", 0x00000001),
+    ("DC", 0x00000002),
+    ("LC", 0x00000004),
+    ("SW", 0x00000008),
+    ("LO", 0x00000080),
+    ("DT", 0x00000040),
+    ("CR", 0x00000100),
+];
+
+/// SDDL ACE flags.
+const ACE_FLAGS: &[(&str, u8)] = &[
+    ("OI", OBJECT_INHERIT_ACE),
+    ("CI", CONTAINER_INHERIT_ACE),
+    ("NP", NO_PROPAGATE_INHERIT_ACE),
+    ("IO", INHERIT_ONLY_ACE),
+    ("ID", INHERITED_ACE),
+    ("SA", 0x40),
+    ("FA", FAILED_ACCESS_ACE_FLAG),
+];
+
+/// Function to resolve one SDDL SID (alias or string SID) with the domain SID.
+pub fn sddl_sid(value: &str, domain_sid: &str) -> Option<String> {
+    if value.starts_with("S-") {
+        return Some(value.to_string())
+    }
+    SID_ALIASES.iter()
+        .find(|(alias, _)| *alias == value)
+        .map(|(_, sid)| {
+            if sid.starts_with('-') {
+                format!("{domain_sid}{sid}")
+            } else {
+                sid.to_string()
+            }
+        })
+}
+
+/// Function to encode a string SID like S-1-5-21-1-2-3-512.
+pub fn sid_to_bytes(sid: &str) -> Option<Vec<u8>> {
+    let parts: Vec<&str> = sid.split('-').collect();
+    if parts.len() < 3 || parts[0] != "S" {
+        return None
+    }
+    let revision = parts[1].parse::<u8>().ok()?;
+    let authority = parts[2].parse::<u64>().ok()?;
+    let sub_authorities: Vec<u32> = parts[3..].iter().map(|p| p.parse::<u32>()).collect::<Result<_, _>>().ok()?;
+    let mut raw = vec![revision, sub_authorities.len() as u8];
+    raw.extend_from_slice(&authority.to_be_bytes()[2..]);
+    for sub in sub_authorities {
+        raw.extend_from_slice(&sub.to_le_bytes());
+    }
+    Some(raw)
+}
+
+/// Function to encode a GUID string in its little-endian representation.
+pub fn guid_to_bytes(guid: &str) -> Option<Vec<u8>> {
+    let value = u128::from_str_radix(&guid.replace('-', ""), 16).ok()?;
+    let mut raw = value.to_be_bytes().to_vec();
+    raw[0..4].reverse();
+    raw[4..6].reverse();
+    raw[6..8].reverse();
+    Some(raw)
+}
+
+fn parse_rights(value: &str) -> Option<u32> {
+    if let Some(hex) = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        return u32::from_str_radix(hex, 16).ok()
+    }
+    let mut mask = 0;
+    for i in (0..value.len()).step_by(2) {
+        let code = value.get(i..i + 2)?;
+        mask |= RIGHTS.iter().find(|(right, _)| *right == code)?.1;
+    }
+    Some(mask)
+}
+
+fn parse_ace_flags(value: &str) -> Option<u8> {
+    let mut flags = 0;
+    for i in (0..value.len()).step_by(2) {
+        let code = value.get(i..i + 2)?;
+        flags |= ACE_FLAGS.iter().find(|(flag, _)| *flag == code)?.1;
+    }
+    Some(flags)
+}
+
+/// Function to encode one SDDL ACE string, without the parentheses.
+fn encode_ace(ace: &str, domain_sid: &str) -> Result<Vec<u8>, RustHoundError> {
+    let fields: Vec<&str> = ace.split(';').collect();
+    if fields.len() < 6 {
+        return Err(RustHoundError::SecurityDescriptorParse(format!("Invalid SDDL ACE: {ace}")))
+    }
+    let ace_type = match fields[0] {
+        "A" => ACCESS_ALLOWED_ACE_TYPE,
+        "D" => ACCESS_DENIED_ACE_TYPE,
+        "OA" => ACCESS_ALLOWED_OBJECT_ACE_TYPE,
+        "OD" => ACCESS_DENIED_OBJECT_ACE_TYPE,
+        other => return Err(RustHoundError::SecurityDescriptorParse(format!("Unsupported SDDL ACE type: {other}"))),
+    };
+    let ace_flags = parse_ace_flags(fields[1]).ok_or_else(|| RustHoundError::SecurityDescriptorParse(format!("Invalid SDDL ACE flags: {}", fields[1])))?;
+    let mask = parse_rights(fields[2]).ok_or_else(|| RustHoundError::SecurityDescriptorParse(format!("Invalid SDDL ACE rights: {}", fields[2])))?;
+    let sid = sddl_sid(fields[5], domain_sid)
+        .and_then(|sid| sid_to_bytes(&sid))
+        .ok_or_else(|| RustHoundError::SecurityDescriptorParse(format!("Invalid SDDL ACE SID: {}", fields[5])))?;
+
+    let mut data = mask.to_le_bytes().to_vec();
+    if ace_type == ACCESS_ALLOWED_OBJECT_ACE_TYPE || ace_type == ACCESS_DENIED_OBJECT_ACE_TYPE {
+        let object_type = if fields[3].is_empty() { None } else { guid_to_bytes(fields[3]) };
+        let inherited_object_type = if fields[4].is_empty() { None } else { guid_to_bytes(fields[4]) };
+        let flags = object_type.as_ref().map_or(0, |_| ACE_OBJECT_TYPE_PRESENT)
+            | inherited_object_type.as_ref().map_or(0, |_| ACE_INHERITED_OBJECT_TYPE_PRESENT);
+        data.extend_from_slice(&flags.to_le_bytes());
+        data.extend(object_type.unwrap_or_default());
+        data.extend(inherited_object_type.unwrap_or_default());
+    }
+    data.extend(sid);
+
+    let mut raw = vec![ace_type, ace_flags];
+    raw.extend_from_slice(&(data.len() as u16 + 4).to_le_bytes());
+    raw.extend(data);
+    Ok(raw)
+}
+
+/// Function to convert a SDDL string to a self-relative security descriptor (owner and DACL only).
+///
+/// With `inherited` the DACL only keeps the container inheritable ACEs, flagged as inherited:
+/// what a child object gets from a parent with this security descriptor.
+pub fn sddl_to_security_descriptor(sddl: &str, domain_sid: &str, inherited: bool) -> Result<Vec<u8>, RustHoundError> {
+    let mut owner: Option<Vec<u8>> = None;
+    let mut dacl_protected = false;
+    let mut aces: Vec<Vec<u8>> = Vec::new();
+
+    // Split the SDDL in its components: O:, G:, D:, S:
+    let bytes = sddl.as_bytes();
+    let mut marks: Vec<usize> = Vec::new();
+    let mut depth = 0;
+    for (i, c) in bytes.iter().enumerate() {
+        match c {
+            b'(' => depth += 1,
+            b')' => depth -= 1,
+            b'O' | b'G' | b'D' | b'S' if depth == 0 && bytes.get(i + 1) == Some(&b':') => marks.push(i),
+            _ => {}
+        }
+    }
+    let components: Vec<(char, &str)> = marks.iter().enumerate()
+        .map(|(n, &i)| (bytes[i] as char, &sddl[i + 2..*marks.get(n + 1).unwrap_or(&sddl.len())]))
+        .collect();
+
+    for (component, value) in components {
+        match component {
+            'O' if !inherited => {
+                owner = sddl_sid(value, domain_sid).and_then(|sid| sid_to_bytes(&sid));
+            }
+            'D' => {
+                let (flags, rest) = value.split_at(value.find('(').unwrap_or(value.len()));
+                dacl_protected = flags.contains('P');
+                for ace in rest.split(')').filter(|a| a.starts_with('(')) {
+                    let mut raw = encode_ace(&ace[1..], domain_sid)?;
+                    if inherited {
+                        if raw[1] & CONTAINER_INHERIT_ACE != CONTAINER_INHERIT_ACE {
+                            continue
+                        }
+                        raw[1] = (raw[1] | INHERITED_ACE) & !INHERIT_ONLY_ACE;
+                    }
+                    aces.push(raw);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // SE_SELF_RELATIVE | SE_DACL_PRESENT (| SE_DACL_PROTECTED)
+    let control: u16 = 0x8004 | if dacl_protected { 0x1000 } else { 0 };
+    let owner = owner.unwrap_or_default();
+    let aces_len: usize = aces.iter().map(|a| a.len()).sum();
+    let offset_owner = if owner.is_empty() { 0 } else { 20 };
+    let offset_dacl = 20 + owner.len() as u32;
+
+    let mut nt = vec![1, 0];
+    nt.extend_from_slice(&control.to_le_bytes());
+    nt.extend_from_slice(&(offset_owner as u32).to_le_bytes());
+    nt.extend_from_slice(&0u32.to_le_bytes());
+    nt.extend_from_slice(&0u32.to_le_bytes());
+    nt.extend_from_slice(&offset_dacl.to_le_bytes());
+    nt.extend(owner);
+    nt.extend([4, 0]);
+    nt.extend_from_slice(&(8 + aces_len as u16).to_le_bytes());
+    nt.extend_from_slice(&(aces.len() as u16).to_le_bytes());
+    nt.extend([0, 0]);
+    for ace in aces {
+        nt.extend(ace);
+    }
+    Ok(nt)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::enums::sddl::*;
+    use crate::enums::secdesc::{LdapSid, SecurityDescriptor};
+    use crate::enums::sid::sid_maker;
+
+    #[test]
+    pub fn test_sddl_to_security_descriptor() {
+        let sddl = "O:DAG:DAD:P(A;;RPWPCRCCDCLCLORCWOWDSDDTSW;;;DA)(OA;CI;RP;4c164200-20c0-11d0-a768-00aa006e0529;bf967aba-0de6-11d0-a285-00aa003049e2;RU)(A;;RC;;;AU)";
+        let nt = sddl_to_security_descriptor(sddl, "S-1-5-21-1-2-3", false).unwrap();
+        let secdesc = SecurityDescriptor::parse(&nt).unwrap().1;
+        assert_eq!(secdesc.control & 0x1000, 0x1000);
+        let owner = sid_maker(LdapSid::parse(&nt[secdesc.offset_owner as usize..]).unwrap().1, "contoso.local");
+        assert_eq!(owner, "S-1-5-21-1-2-3-512");
+
+        // Only the container inheritable ACE is kept for children
+        let nt = sddl_to_security_descriptor(sddl, "S-1-5-21-1-2-3", true).unwrap();
+        let secdesc = SecurityDescriptor::parse(&nt).unwrap().1;
+        assert_eq!(secdesc.offset_owner, 0);
+        assert_eq!(nt[secdesc.offset_dacl as usize + 4], 1);
+    }
+}
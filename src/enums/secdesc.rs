@@ -146,6 +146,10 @@ impl Ace {
         let (i, ace_type) = le_u8(i)?;
         let (i, ace_flags) = le_u8(i)?;
         let (i, ace_size) = le_u16(i)?;
+        // Malformed ACE smaller than its header
+        if ace_size < 4 {
+            return Err(nom7::Err::Error(nom7::error::Error::new(i, nom7::error::ErrorKind::LengthValue)))
+        }
         let (i, data) = take(ace_size as usize - 4)(i)?;
         let (_j,ace_data_formatted) = AceFormat::parse(data, ace_type)?;
 
@@ -188,7 +192,8 @@ impl AceFormat {
             Ok((i, data))
         }
         else {
-            panic!("Error during ACE data parsing to AceFormat!")
+            // Audit, callback or mandatory label ACEs (Samba may return them), never mapped to edges
+            Ok((i, AceFormat::Empty))
         }
     }
     
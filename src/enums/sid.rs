@@ -1,5 +1,8 @@
 use std::error::Error;
-use log::{trace,error};
+use std::sync::RwLock;
+use log::{trace,error,info};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use crate::enums::{secdesc::LdapSid, regex::IS_SID_RE1};
 
 /// Function to check if string is SID
@@ -103,4 +106,203 @@ pub fn decode_guid_le(raw_guid: &[u8]) -> String {
     );
 
     str_guid
-}
\ No newline at end of file
+}
+/// Well-known SID definition.
+/// The `sid` is a full SID like "S-1-5-32-544" or a domain relative RID like "-512".
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct WellKnownSid {
+    pub sid: String,
+    pub name: String,
+    #[serde(rename = "type", default = "default_well_known_type")]
+    pub object_type: String,
+    /// Tier zero principal, flagged as highvalue in BloodHound.
+    #[serde(default)]
+    pub highvalue: bool,
+    /// Administrative principal, skipped when looking for delegated rights (CA hosting computer, AddSelf...).
+    #[serde(default)]
+    pub admin: bool,
+    /// Localized names, only used to guess the RID of a foreign object known by its DN.
+    #[serde(default)]
+    pub localized_names: Vec<String>,
+}
+
+fn default_well_known_type() -> String {
+    "Group".to_string()
+}
+
+impl WellKnownSid {
+    fn new(sid: &str, name: &str, object_type: &str, highvalue: bool, admin: bool, localized_names: &[&str]) -> Self {
+        Self {
+            sid: sid.to_string(),
+            name: name.to_string(),
+            object_type: object_type.to_string(),
+            highvalue,
+            admin,
+            localized_names: localized_names.iter().map(|n| n.to_string()).collect(),
+        }
+    }
+}
+
+/// Default well-known SIDs table.
+/// <https://learn.microsoft.com/en-us/windows-server/identity/ad-ds/manage/understand-security-identifiers>
+fn default_well_known_sids() -> Vec<WellKnownSid> {
+    vec![
+        WellKnownSid::new("S-1-1-0", "EVERYONE", "Group", false, false, &["TOUT LE MONDE"]),
+        WellKnownSid::new("S-1-3-0", "CREATOR OWNER", "User", false, false, &[]),
+        WellKnownSid::new("S-1-5-4", "INTERACTIVE", "Group", false, false, &[]),
+        WellKnownSid::new("S-1-5-9", "ENTERPRISE DOMAIN CONTROLLERS", "Group", false, false, &[]),
+        WellKnownSid::new("S-1-5-10", "PRINCIPAL SELF", "User", false, false, &[]),
+        WellKnownSid::new("S-1-5-11", "AUTHENTICATED USERS", "Group", false, false, &["UTILISATEURS AUTHENTIFIÉS"]),
+        WellKnownSid::new("S-1-5-15", "THIS ORGANIZATION", "Group", false, false, &[]),
+        WellKnownSid::new("S-1-5-18", "LOCAL SYSTEM", "User", false, false, &[]),
+        WellKnownSid::new("S-1-5-20", "NT AUTHORITY", "User", false, false, &[]),
+        WellKnownSid::new("S-1-5-32-544", "ADMINISTRATORS", "Group", true, true, &["ADMINISTRATEURS"]),
+        WellKnownSid::new("S-1-5-32-548", "ACCOUNT OPERATORS", "Group", true, false, &["OPÉRATEURS DE COMPTE"]),
+        WellKnownSid::new("S-1-5-32-549", "SERVER OPERATORS", "Group", true, false, &["OPÉRATEURS DE SERVEUR"]),
+        WellKnownSid::new("S-1-5-32-550", "PRINT OPERATORS", "Group", true, false, &["OPÉRATEURS D'IMPRESSION"]),
+        WellKnownSid::new("S-1-5-32-551", "BACKUP OPERATORS", "Group", true, false, &["OPÉRATEURS DE SAUVEGARDE"]),
+        WellKnownSid::new("S-1-5-32-554", "PRE-WINDOWS 2000 COMPATIBLE ACCESS", "Group", false, false, &[]),
+        WellKnownSid::new("S-1-5-32-557", "INCOMING FOREST TRUST BUILDERS", "Group", false, false, &[]),
+        WellKnownSid::new("S-1-5-32-560", "WINDOWS AUTHORIZATION ACCESS GROUP", "Group", false, false, &[]),
+        WellKnownSid::new("S-1-5-32-561", "TERMINAL SERVER LICENSE SERVERS", "Group", false, false, &[]),
+        WellKnownSid::new("-500", "ADMINISTRATOR", "User", false, false, &["ADMINISTRATEUR"]),
+        WellKnownSid::new("-512", "DOMAIN ADMINS", "Group", true, true, &["ADMINISTRATEURS DU DOMAINE"]),
+        WellKnownSid::new("-513", "DOMAIN USERS", "Group", false, false, &["UTILISATEURS DU DOMAINE"]),
+        WellKnownSid::new("-514", "DOMAIN GUESTS", "Group", false, false, &["INVITES DE DOMAINE"]),
+        WellKnownSid::new("-515", "DOMAIN COMPUTERS", "Group", false, false, &["ORDINATEURS DE DOMAINE"]),
+        WellKnownSid::new("-516", "DOMAIN CONTROLLERS", "Group", true, false, &["CONTRÔLEURS DE DOMAINE"]),
+        WellKnownSid::new("-517", "CERT PUBLISHERS", "Group", false, false, &["EDITEURS DE CERTIFICATS"]),
+        WellKnownSid::new("-518", "SCHEMA ADMINS", "Group", false, false, &["ADMINISTRATEURS DU SCHEMA"]),
+        WellKnownSid::new("-519", "ENTERPRISE ADMINS", "Group", true, true, &["ADMINISTRATEURS DE L'ENTREPRISE"]),
+        WellKnownSid::new("-520", "GROUP POLICY CREATOR OWNERS", "Group", true, false, &["PROPRIÉTAIRES CRÉATEURS DE LA STRATÉGIE DE GROUPE"]),
+    ]
+}
+
+static WELL_KNOWN_SIDS: Lazy<RwLock<Vec<WellKnownSid>>> = Lazy::new(|| RwLock::new(default_well_known_sids()));
+
+/// Function to load a JSON file to extend or adjust the well-known SIDs table.
+/// Entries with an existing `sid` replace the default one, others are added.
+pub fn load_well_known_overrides(path: &str) -> Result<(), Box<dyn Error>> {
+    let content = std::fs::read_to_string(path)?;
+    let overrides: Vec<WellKnownSid> = serde_json::from_str(&content)?;
+    info!("{} well-known SIDs loaded from {}", overrides.len(), path);
+    apply_well_known_overrides(overrides);
+    Ok(())
+}
+
+/// Function to extend or adjust the well-known SIDs table.
+pub fn apply_well_known_overrides(overrides: Vec<WellKnownSid>) {
+    merge_well_known_overrides(&mut WELL_KNOWN_SIDS.write().unwrap(), overrides);
+}
+
+/// Function to replace or add the overrides in one well-known SIDs table.
+fn merge_well_known_overrides(table: &mut Vec<WellKnownSid>, overrides: Vec<WellKnownSid>) {
+    for mut entry in overrides {
+        entry.sid = entry.sid.to_uppercase();
+        match table.iter_mut().find(|known| known.sid == entry.sid) {
+            Some(known) => *known = entry,
+            None => table.push(entry),
+        }
+    }
+}
+
+/// Function to get the key used in the well-known SIDs table from a SID.
+/// "DOMAIN.LOCAL-S-1-5-32-544" gives "S-1-5-32-544" and "S-1-5-21-X-Y-Z-512" gives "-512".
+pub fn well_known_key(sid: &str) -> String {
+    let sid = sid.to_uppercase();
+    let sid = match sid.find("S-1-") {
+        Some(index) => &sid[index..],
+        None => return sid,
+    };
+    let parts: Vec<&str> = sid.split('-').collect();
+    if parts.len() == 8 && parts[3] == "21" {
+        format!("-{}", parts[7])
+    } else {
+        sid.to_string()
+    }
+}
+
+/// Function to get the well-known definition for one SID.
+pub fn get_well_known_sid(sid: &str) -> Option<WellKnownSid> {
+    find_well_known_sid(&WELL_KNOWN_SIDS.read().unwrap(), sid).cloned()
+}
+
+/// Function to find the definition of one SID in a well-known SIDs table.
+fn find_well_known_sid<'a>(table: &'a [WellKnownSid], sid: &str) -> Option<&'a WellKnownSid> {
+    let key = well_known_key(sid);
+    table.iter().find(|known| known.sid == key)
+}
+
+/// Function to get the name for one well-known SID.
+pub fn get_well_known_name(sid: &str) -> Option<String> {
+    get_well_known_sid(sid).map(|known| known.name)
+}
+
+/// Function to check if one SID is a well-known administrative principal.
+pub fn is_admin_sid(sid: &str) -> bool {
+    get_well_known_sid(sid).is_some_and(|known| known.admin)
+}
+
+/// Function to check if one SID is a well-known highvalue principal.
+pub fn is_highvalue_sid(sid: &str) -> bool {
+    get_well_known_sid(sid).is_some_and(|known| known.highvalue)
+}
+
+/// Function to get the domain relative RID ("-512") from a localized name found in a DN.
+pub fn get_rid_from_localized_name(object_identifier: &str) -> Option<String> {
+    rid_from_localized_name(&WELL_KNOWN_SIDS.read().unwrap(), object_identifier)
+}
+
+/// Function to get the domain relative RID from a localized name in one well-known SIDs table.
+fn rid_from_localized_name(table: &[WellKnownSid], object_identifier: &str) -> Option<String> {
+    let object_identifier = object_identifier.to_uppercase();
+    table.iter()
+        .filter(|known| known.sid.starts_with('-'))
+        .find(|known| {
+            std::iter::once(&known.name)
+                .chain(known.localized_names.iter())
+                .any(|name| object_identifier.contains(&format!("CN={},", name.to_uppercase())))
+        })
+        .map(|known| known.sid.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::enums::sid::*;
+
+    #[test]
+    pub fn test_well_known_sid() {
+        assert!(is_admin_sid("CONTOSO.LOCAL-S-1-5-32-544"));
+        assert!(is_admin_sid("S-1-5-21-1004336348-1177238915-682003330-512"));
+        assert!(!is_admin_sid("S-1-5-21-1004336348-1177238915-682003330-1512"));
+        assert!(!is_admin_sid("S-1-5-21-1004336348-1177238915-682003330-11512"));
+        assert!(is_highvalue_sid("S-1-5-21-1004336348-1177238915-682003330-516"));
+        assert!(!is_highvalue_sid("S-1-5-21-1004336348-1177238915-682003330-513"));
+        assert_eq!(get_well_known_name("CONTOSO.LOCAL-S-1-1-0"), Some("EVERYONE".to_string()));
+    }
+
+    #[test]
+    pub fn test_renamed_builtin_groups() {
+        // Renamed groups keep their SID, results are the same than in a default-named domain.
+        // The overrides go in a local table, the global one is shared by the other tests.
+        let mut table = default_well_known_sids();
+        let default_named = rid_from_localized_name(&table, "CN=DOMAIN ADMINS,CN=USERS,DC=CONTOSO,DC=LOCAL");
+        merge_well_known_overrides(&mut table, vec![WellKnownSid {
+            sid: "-512".to_string(),
+            name: "DOMAIN ADMINS".to_string(),
+            object_type: "Group".to_string(),
+            highvalue: true,
+            admin: true,
+            localized_names: vec!["TIER0 ADMINS".to_string()],
+        }]);
+        let renamed = rid_from_localized_name(&table, "CN=TIER0 ADMINS,CN=USERS,DC=CONTOSO,DC=LOCAL");
+        assert_eq!(default_named, Some("-512".to_string()));
+        assert_eq!(renamed, default_named);
+        let known = find_well_known_sid(&table, "S-1-5-21-1004336348-1177238915-682003330-512").unwrap();
+        assert!(known.admin && known.highvalue);
+        assert_eq!(table.len(), default_well_known_sids().len());
+        // The global table is untouched
+        assert_eq!(get_rid_from_localized_name("CN=TIER0 ADMINS,CN=USERS,DC=CONTOSO,DC=LOCAL"), None);
+    }
+}
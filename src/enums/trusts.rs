@@ -19,6 +19,11 @@ bitflags! {
    }
 }
 
+/// Check a trust is within the forest: parent, child or tree root domain.
+pub fn is_within_forest(trustflag: u32) -> bool {
+   Flags::from_bits_truncate(trustflag).contains(Flags::WITHIN_FOREST)
+}
+
 /// Get the trust flags from "trustDomain".
 pub fn get_trust_flag(trustflag: u32, object: &mut Trust)
 {
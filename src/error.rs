@@ -0,0 +1,82 @@
+//! Errors of the parsing of the LDAP entries
+//!
+//! The parsers of the objects, the SDDL encoder and the date helpers return a [`RustHoundError`], the caller can
+//! tell an LDAP failure from an unreadable attribute with `matches!`:
+//!
+//! ```
+//! use rusthound_ce::RustHoundError;
+//! use rusthound_ce::utils::date::string_to_epoch;
+//!
+//! let err = string_to_epoch("2024-13-01").unwrap_err();
+//! assert!(matches!(err, RustHoundError::DateParse { .. }));
+//! ```
+//!
+//! The error of the library it wraps is kept as its [`source`](std::error::Error::source).
+use std::error::Error;
+use std::fmt;
+
+/// Error of the parsing of an LDAP entry.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RustHoundError {
+    /// LDAP request or response refused by the DC or the connection.
+    Ldap(ldap3::LdapError),
+    /// Security descriptor or SDDL string which can not be read.
+    SecurityDescriptorParse(String),
+    /// Certificate which can not be read.
+    CertificateParse(String),
+    /// Generalized time attribute which can not be read, like `whenCreated`.
+    DateParse { value: String, source: chrono::ParseError },
+    /// Attribute value of an unexpected format.
+    InvalidValue(String),
+}
+
+impl fmt::Display for RustHoundError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RustHoundError::Ldap(err) => write!(f, "LDAP error: {err}"),
+            RustHoundError::SecurityDescriptorParse(msg) => write!(f, "invalid security descriptor: {msg}"),
+            RustHoundError::CertificateParse(msg) => write!(f, "invalid certificate: {msg}"),
+            RustHoundError::DateParse { value, source } => write!(f, "invalid date '{value}': {source}"),
+            RustHoundError::InvalidValue(msg) => write!(f, "invalid value: {msg}"),
+        }
+    }
+}
+
+impl Error for RustHoundError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RustHoundError::Ldap(err) => Some(err),
+            RustHoundError::DateParse { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<ldap3::LdapError> for RustHoundError {
+    fn from(err: ldap3::LdapError) -> Self {
+        RustHoundError::Ldap(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::error::*;
+
+    #[test]
+    pub fn test_rusthound_error() {
+        let err = crate::utils::date::string_to_epoch("20241301000000.0Z").unwrap_err();
+        assert!(matches!(&err, RustHoundError::DateParse { value, .. } if value == "20241301000000.0Z"));
+        assert!(err.source().is_some());
+        assert!(err.to_string().starts_with("invalid date '20241301000000.0Z'"));
+
+        let err = crate::enums::sddl::sddl_to_security_descriptor("O:DAD:(X;;GA;;;DA)", "S-1-5-21-1-2-3", false).unwrap_err();
+        assert!(matches!(err, RustHoundError::SecurityDescriptorParse(_)));
+        assert!(err.source().is_none());
+
+        // Still usable with the Box<dyn Error> of the callers
+        let boxed: Box<dyn Error + Send + Sync> = RustHoundError::InvalidValue("pKIExpirationPeriod".to_string()).into();
+        assert_eq!(boxed.to_string(), "invalid value: pKIExpirationPeriod");
+    }
+}
@@ -0,0 +1,340 @@
+//! Machine-readable progress events
+//!
+//! With `--progress-json` RustHound-CE writes one JSON object per line (NDJSON) to stdout,
+//! a named pipe / file path or a unix socket (`unix:/path/to/socket`).
+//! Human-readable logs stay on stderr so both streams never interleave.
+//!
+//! Every event has the same envelope:
+//!
+//! ```json
+//! {"version":1,"timestamp":1700000000,"event":"phase_started","phase":"ldap_search"}
+//! ```
+//!
+//! | event            | fields                                   |
+//! |------------------|------------------------------------------|
+//! | `phase_started`  | `phase`                                  |
+//! | `phase_finished` | `phase`                                  |
+//! | `query_finished` | `naming_context`, `entries`              |
+//! | `warning`        | `level` (`WARN` or `ERROR`), `message`   |
+//! | `file_written`   | `path`, `sha1`                           |
+//! | `finished`       |                                          |
+//!
+//! Phases are `ldap_search`, `parsing`, `checker`, `modules` and `output`.
+//!
+//! When RustHound-CE is used as a library, a [`ProgressObserver`] set with [`set_progress_observer`] is called with
+//! the same events, and with the count of each object type as the entries are parsed, without `--progress-json` nor
+//! the `log` facade.
+use log::{Level, Log, Metadata, Record};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Current events schema version
+pub const EVENTS_SCHEMA_VERSION: u8 = 1;
+
+/// Progress events emitted with `--progress-json`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Event {
+    PhaseStarted { phase: String },
+    PhaseFinished { phase: String },
+    QueryFinished { naming_context: String, entries: u64 },
+    Warning { level: String, message: String },
+    FileWritten { path: String, sha1: String },
+    Finished,
+}
+
+impl Event {
+    pub fn phase_started(phase: &str) -> Self {
+        Event::PhaseStarted { phase: phase.to_string() }
+    }
+    pub fn phase_finished(phase: &str) -> Self {
+        Event::PhaseFinished { phase: phase.to_string() }
+    }
+}
+
+#[derive(Serialize)]
+struct EventLine<'a> {
+    version: u8,
+    timestamp: i64,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Observer of the collection progress, to render progress bars in a GUI or TUI.
+/// Every method does nothing by default, they are called from the thread of the collection.
+pub trait ProgressObserver: Send + Sync {
+    /// A phase started: `ldap_search`, `parsing`, `checker`, `modules` or `output`.
+    fn on_phase(&self, _phase: &str) {}
+
+    /// One more object parsed, `count` objects of this type so far.
+    /// Types are the ones of the json files: `users`, `groups`, `computers`, `ous`, `domains`, `gpos`, `fsps`,
+    /// `containers`, `trusts`, `ntauthstores`, `aiacas`, `rootcas`, `enterprisecas`, `certtemplates` and `issuancepolicies`.
+    fn on_object_parsed(&self, _kind: &str, _count: usize) {}
+
+    /// Every event, the ones written with `--progress-json`.
+    fn on_event(&self, _event: &Event) {}
+}
+
+type EventWriter = Option<Box<dyn Write + Send>>;
+
+#[cfg(not(test))]
+static EVENT_WRITER: Lazy<Mutex<EventWriter>> = Lazy::new(|| Mutex::new(None));
+// One writer per test thread, so a test only reads the events it emitted
+#[cfg(test)]
+thread_local! {
+    static EVENT_WRITER: std::cell::RefCell<EventWriter> = const { std::cell::RefCell::new(None) };
+}
+static PROGRESS_OBSERVER: Lazy<Mutex<Option<Arc<dyn ProgressObserver>>>> = Lazy::new(|| Mutex::new(None));
+static CURRENT_PHASE: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+/// Function to open the events output from the `--progress-json` value.
+/// `-` or `stdout` for standard output, `unix:<path>` for a unix socket, any other value is a file or named pipe.
+pub fn init_progress_events(target: &str) -> Result<(), Box<dyn Error>> {
+    let writer: Box<dyn Write + Send> = match target {
+        "-" | "stdout" => Box::new(std::io::stdout()),
+        #[cfg(unix)]
+        _ if target.starts_with("unix:") => {
+            Box::new(std::os::unix::net::UnixStream::connect(&target[5..])?)
+        }
+        _ => Box::new(OpenOptions::new().create(true).append(true).open(target)?),
+    };
+    set_event_writer(writer);
+    Ok(())
+}
+
+/// Function to run a closure with the events writer.
+#[cfg(not(test))]
+fn with_event_writer<R>(f: impl FnOnce(&mut EventWriter) -> R) -> R {
+    f(&mut EVENT_WRITER.lock().unwrap())
+}
+
+#[cfg(test)]
+fn with_event_writer<R>(f: impl FnOnce(&mut EventWriter) -> R) -> R {
+    EVENT_WRITER.with(|writer| f(&mut writer.borrow_mut()))
+}
+
+/// Function to set where events are written, useful when RustHound-CE is used as a library.
+pub fn set_event_writer(writer: Box<dyn Write + Send>) {
+    with_event_writer(|current| *current = Some(writer));
+}
+
+/// Function to set the observer of the collection progress, replacing the previous one.
+pub fn set_progress_observer(observer: Arc<dyn ProgressObserver>) {
+    *PROGRESS_OBSERVER.lock().unwrap() = Some(observer);
+}
+
+/// Function to remove the observer of the collection progress.
+pub fn clear_progress_observer() {
+    *PROGRESS_OBSERVER.lock().unwrap() = None;
+}
+
+/// Function to get the observer, out of the lock so it can emit or log itself.
+fn progress_observer() -> Option<Arc<dyn ProgressObserver>> {
+    PROGRESS_OBSERVER.lock().unwrap().clone()
+}
+
+/// Function to report one more object parsed to the observer, if any.
+pub(crate) fn object_parsed(kind: &str, count: usize) {
+    if let Some(observer) = progress_observer() {
+        observer.on_object_parsed(kind, count);
+    }
+}
+
+/// Function to check if progress events are enabled.
+pub fn events_enabled() -> bool {
+    with_event_writer(|writer| writer.is_some())
+}
+
+/// Function to get the last phase started, even if progress events are not enabled.
+pub fn current_phase() -> Option<String> {
+    CURRENT_PHASE.lock().unwrap().clone()
+}
+
+/// Function to emit one event, do nothing if progress events are not enabled and no observer is set.
+pub fn emit(event: Event) {
+    if let Event::PhaseStarted { phase } = &event {
+        *CURRENT_PHASE.lock().unwrap() = Some(phase.to_owned());
+    }
+    if let Some(observer) = progress_observer() {
+        if let Event::PhaseStarted { phase } = &event {
+            observer.on_phase(phase);
+        }
+        observer.on_event(&event);
+    }
+    with_event_writer(|writer| {
+        if let Some(writer) = writer.as_mut() {
+            let line = EventLine {
+                version: EVENTS_SCHEMA_VERSION,
+                timestamp: chrono::Utc::now().timestamp(),
+                event: &event,
+            };
+            if let Ok(json) = serde_json::to_string(&line) {
+                let _ = writeln!(writer, "{json}");
+                let _ = writer.flush();
+            }
+        }
+    });
+}
+
+/// Logger forwarding records to env_logger and emitting warnings and errors as events.
+pub struct EventLogger {
+    inner: env_logger::Logger,
+}
+
+impl EventLogger {
+    /// Install the logger built from the env_logger builder.
+    pub fn init(mut builder: env_logger::Builder) -> Result<(), log::SetLoggerError> {
+        let inner = builder.build();
+        let max_level = inner.filter();
+        log::set_boxed_logger(Box::new(EventLogger { inner }))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+}
+
+impl Log for EventLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return
+        }
+        if record.level() <= Level::Warn {
+            emit(Event::Warning {
+                level: record.level().to_string(),
+                message: record.args().to_string(),
+            });
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use crate::api::visible_objects;
+    use crate::args::Options;
+    use crate::events::*;
+    use crate::json::maker::make_result;
+    use crate::test_support::{entry_with_sid, parse_entries};
+
+    const DOMAIN_SID: &str = "S-1-5-21-4000000001-4000000002-4000000003";
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Remove the writer of the test thread, the harness can reuse it with `--test-threads=1`.
+    struct WriterGuard;
+
+    impl Drop for WriterGuard {
+        fn drop(&mut self) {
+            with_event_writer(|writer| *writer = None);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_progress_events_sequence() {
+        let buffer = SharedBuffer::default();
+        set_event_writer(Box::new(buffer.clone()));
+        let _guard = WriterGuard;
+        // Warnings of the collection as events, as with --progress-json
+        let mut builder = env_logger::Builder::new();
+        builder.filter_level(log::LevelFilter::Warn).is_test(true);
+        EventLogger::init(builder).ok();
+
+        let path = std::env::temp_dir().join("rusthound-ce-events-test");
+        let options = Options {
+            domain: "contoso.local".to_string(),
+            path: path.display().to_string(),
+            progress_json: Some("-".to_string()),
+            ..Default::default()
+        };
+        // Synthetic LDAP search: the entries of each naming context, the domain without msDS-Behavior-Version
+        let user = "top|person|organizationalPerson|user";
+        let naming_contexts = vec![
+            ("DC=CONTOSO,DC=LOCAL", vec![
+                entry_with_sid("DC=CONTOSO,DC=LOCAL", DOMAIN_SID, &[("objectClass", "top|domain|domainDNS"), ("name", "contoso")]),
+                entry_with_sid("CN=DC01,OU=Domain Controllers,DC=CONTOSO,DC=LOCAL", &format!("{DOMAIN_SID}-1000"), &[
+                    ("objectClass", "top|person|organizationalPerson|user|computer"), ("sAMAccountName", "DC01$"),
+                    ("dNSHostName", "dc01.contoso.local"), ("userAccountControl", "532480"), ("primaryGroupID", "516"),
+                ]),
+                entry_with_sid("CN=alice,CN=Users,DC=CONTOSO,DC=LOCAL", &format!("{DOMAIN_SID}-1104"), &[
+                    ("objectClass", user), ("sAMAccountName", "alice"), ("userAccountControl", "512"), ("primaryGroupID", "513"),
+                ]),
+            ]),
+            ("CN=Configuration,DC=CONTOSO,DC=LOCAL", Vec::new()),
+        ];
+        emit(Event::phase_started("ldap_search"));
+        let mut entries = Vec::new();
+        for (naming_context, found) in naming_contexts {
+            emit(Event::QueryFinished { naming_context: naming_context.to_string(), entries: found.len() as u64 });
+            entries.extend(found);
+        }
+        emit(Event::phase_finished("ldap_search"));
+
+        let results = parse_entries(entries, &options).await;
+        let written: Vec<&str> = visible_objects(&results).into_iter().map(|(name, _)| name).collect();
+        make_result(&options, results).unwrap();
+        emit(Event::Finished);
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().to_vec()).unwrap();
+        let events: Vec<serde_json::Value> = output.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert!(events.iter().all(|e| e["version"] == 1 && e["timestamp"].is_i64()));
+        let sequence: Vec<String> = events.iter().map(|e| match e["event"].as_str().unwrap() {
+            "phase_started" | "phase_finished" => format!("{} {}", e["event"].as_str().unwrap(), e["phase"].as_str().unwrap()),
+            "query_finished" => format!("query_finished {} {}", e["naming_context"].as_str().unwrap(), e["entries"]),
+            "warning" => format!("warning {}", e["level"].as_str().unwrap()),
+            "file_written" => {
+                let path = e["path"].as_str().unwrap();
+                assert!(path.contains("_contoso-local_"), "{path}");
+                assert_eq!(e["sha1"].as_str().unwrap().len(), 40);
+                format!("file_written {}", &path[path.rfind('_').unwrap() + 1..])
+            }
+            event => event.to_string(),
+        }).collect();
+
+        let mut expected = vec![
+            "phase_started ldap_search".to_string(),
+            "query_finished DC=CONTOSO,DC=LOCAL 3".to_string(),
+            "query_finished CN=Configuration,DC=CONTOSO,DC=LOCAL 0".to_string(),
+            "phase_finished ldap_search".to_string(),
+            "phase_started parsing".to_string(),
+            "warning WARN".to_string(),
+            "phase_finished parsing".to_string(),
+            "phase_started checker".to_string(),
+            "phase_finished checker".to_string(),
+            "phase_started output".to_string(),
+        ];
+        expected.extend(written.iter().map(|name| format!("file_written {name}.json")));
+        expected.extend(["phase_finished output".to_string(), "finished".to_string()]);
+        assert_eq!(sequence, expected);
+        // Default groups added by the checker, written with the objects of the search
+        assert_eq!(written, vec!["users", "groups", "computers", "domains"]);
+        let warning = events.iter().find(|e| e["event"] == "warning").unwrap();
+        assert!(warning["message"].as_str().unwrap().contains("functional level unknown"));
+
+        std::fs::remove_dir_all(path).ok();
+    }
+}
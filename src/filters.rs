@@ -0,0 +1,85 @@
+//! Custom LDAP filters of the collection
+//!
+//! `--ldap-filter` is AND-ed with the filter of every query. `--user-filter`, `--computer-filter` and `--group-filter`
+//! only restrict the objects of their class, the objects of the other classes are still collected:
+//!
+//! ```text
+//! --user-filter '(!(userAccountControl:1.2.840.113556.1.4.803:=2))'
+//! (|(!(sAMAccountType=805306368))(!(userAccountControl:1.2.840.113556.1.4.803:=2)))
+//! ```
+//!
+//! The filters are checked before the first LDAP request, their syntax when the arguments are read, then by the DC
+//! on the domain object. The objects left out are missing from the graph with all their edges, the members of a
+//! group or the ACEs pointing to them are not resolved.
+use ldap3::parse_filter;
+
+/// Filter of the objects of each class, by the name of its option.
+pub const CLASS_FILTERS: &[(&str, &str)] = &[
+    ("user", "(sAMAccountType=805306368)"),
+    ("computer", "(sAMAccountType=805306369)"),
+    ("group", "(objectClass=group)"),
+];
+
+/// Filter of every object, `--ldap-filter` when not given.
+pub const DEFAULT_LDAP_FILTER: &str = "(objectClass=*)";
+
+/// Function to check the syntax of an LDAP filter, the one without parentheses is wrapped in parentheses.
+pub fn parse_ldap_filter(value: &str) -> Result<String, String> {
+    let filter = match value.trim() {
+        filter if filter.starts_with('(') => filter.to_string(),
+        filter => format!("({filter})"),
+    };
+    parse_filter(&filter).map_err(|_| format!("invalid LDAP filter '{value}'"))?;
+    Ok(filter)
+}
+
+/// Function to get the filter of the collection: `--ldap-filter` AND-ed with the filter of each class, which only
+/// restricts the objects of its class.
+pub fn object_filter(ldapfilter: &str, class_filters: &[(&str, Option<&str>)]) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    if !matches!(ldapfilter, "" | DEFAULT_LDAP_FILTER) {
+        parts.push(ldapfilter.to_owned());
+    }
+    for (class, filter) in class_filters {
+        let (Some(filter), Some((_, objects))) = (filter, CLASS_FILTERS.iter().find(|(name, _)| name == class)) else {
+            continue
+        };
+        parts.push(format!("(|(!{objects}){filter})"));
+    }
+    match parts.len() {
+        0 => ldapfilter.to_owned(),
+        1 => parts.remove(0),
+        _ => format!("(&{})", parts.concat()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::filters::*;
+
+    #[test]
+    pub fn test_object_filter() {
+        let enabled = "(!(userAccountControl:1.2.840.113556.1.4.803:=2))";
+        assert_eq!(parse_ldap_filter(enabled).unwrap(), enabled);
+        assert_eq!(parse_ldap_filter(" objectClass=user ").unwrap(), "(objectClass=user)");
+        assert!(parse_ldap_filter("(&(objectClass=user)").unwrap_err().contains("invalid LDAP filter"));
+        assert!(parse_ldap_filter("(cn=a)(cn=b)").is_err());
+
+        // No custom filter
+        assert_eq!(object_filter(DEFAULT_LDAP_FILTER, &[("user", None), ("computer", None), ("group", None)]), DEFAULT_LDAP_FILTER);
+        // The custom one alone
+        let ou = "(!(distinguishedName=*OU=Lab,DC=contoso,DC=local))";
+        assert_eq!(object_filter(ou, &[("user", None)]), ou);
+        // Only the users are restricted
+        assert_eq!(
+            object_filter(DEFAULT_LDAP_FILTER, &[("user", Some(enabled))]),
+            format!("(|(!(sAMAccountType=805306368)){enabled})")
+        );
+        assert_eq!(
+            object_filter(ou, &[("user", Some(enabled)), ("computer", Some(enabled)), ("group", None)]),
+            format!("(&{ou}(|(!(sAMAccountType=805306368)){enabled})(|(!(sAMAccountType=805306369)){enabled}))")
+        );
+        assert!(parse_ldap_filter(&object_filter(ou, &[("group", Some("(adminCount=1)"))])).is_ok());
+    }
+}
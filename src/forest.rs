@@ -0,0 +1,212 @@
+//! Collection of the domains of the forest, or of all the trusted domains, in one run
+//!
+//! With `--forest` the trusts of the collected domain are read as its entries are stored: the target of each trust
+//! within the forest (`TRUST_ATTRIBUTE_WITHIN_FOREST`, the parent, child and tree root domains) is then collected
+//! from a DC of its own domain with the same bind, and its own trusts lead to the next domains, each domain once.
+//!
+//! With `--follow-trusts [DEPTH]` the target of every enabled trust (external and forest trusts too) is collected the
+//! same way, up to `DEPTH` trusts away from `-d`. The bind of `-d` must be accepted by the trusting domain (Kerberos
+//! cross-realm or NTLM), a domain not reachable is left out with a collection warning.
+//!
+//! The configuration and schema partitions are the same on every DC of the forest, they are only requested from the
+//! DC of `-d` and from the first domain collected in each other forest. The entries of all the domains go to the same
+//! storage, each object is parsed with the domain of its DN so the SIDs of every domain resolve through the same
+//! `dn_sid` and `sid_type` maps and all the domains are written in the same output.
+use std::collections::VecDeque;
+use std::error::Error;
+
+use crate::enums::trusts::is_within_forest;
+use crate::ldap::LdapSearchEntry;
+use crate::storage::Storage;
+use crate::utils::format::dn_to_domain;
+
+/// Domain found in a trust, collected from a DC of its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrustedDomain {
+    pub domain: String,
+    /// Trusts between `-d` and the domain, 1 for the trusts of `-d`.
+    pub depth: u8,
+    /// Target of a trust out of the forest: its configuration and schema partitions are collected too.
+    pub other_forest: bool,
+}
+
+/// Domains of the forest, the ones collected and the ones found in their trusts.
+#[derive(Debug, Default)]
+pub struct ForestDomains {
+    collected: Vec<String>,
+    pending: VecDeque<TrustedDomain>,
+    /// Every trust up to this depth with `--follow-trusts`, the trusts within the forest only without it.
+    max_depth: Option<u8>,
+    /// Depth of the domain being collected.
+    depth: u8,
+}
+
+impl ForestDomains {
+    /// Domains of the forest of `domain`, collected first.
+    pub fn new(domain: &str) -> Self {
+        Self { collected: vec![domain.to_uppercase()], ..Default::default() }
+    }
+
+    /// Domains trusted by `domain` and by the next ones, up to `max_depth` trusts away.
+    pub fn following_trusts(domain: &str, max_depth: u8) -> Self {
+        Self { max_depth: Some(max_depth), ..Self::new(domain) }
+    }
+
+    /// Record the target of a trust followed, once: within the forest, or any enabled one up to the max depth.
+    pub fn add_trust(&mut self, entry: &LdapSearchEntry) {
+        let first = |name: &str| entry.attrs.get(name).and_then(|values| values.first());
+        let is_trust = entry.attrs.get("objectClass")
+            .is_some_and(|classes| classes.iter().any(|class| class.eq_ignore_ascii_case("trustedDomain")));
+        let within_forest = first("trustAttributes")
+            .and_then(|value| value.parse::<u32>().ok())
+            .is_some_and(is_within_forest);
+        // TRUST_DIRECTION_DISABLED
+        let disabled = first("trustDirection").is_some_and(|direction| direction == "0");
+        let followed = match self.max_depth {
+            Some(max_depth) => self.depth < max_depth && !disabled,
+            None => within_forest,
+        };
+        let Some(target) = first("name").map(|name| name.to_uppercase()) else {
+            return
+        };
+        let known = self.collected.contains(&target) || self.pending.iter().any(|pending| pending.domain == target);
+        if is_trust && followed && !known {
+            self.pending.push_back(TrustedDomain { domain: target, depth: self.depth + 1, other_forest: !within_forest });
+        }
+    }
+
+    /// Next domain to collect, none once all the domains found are collected.
+    pub fn next_domain(&mut self) -> Option<TrustedDomain> {
+        let trusted = self.pending.pop_front()?;
+        self.collected.push(trusted.domain.to_owned());
+        self.depth = trusted.depth;
+        Some(trusted)
+    }
+
+    /// Domains collected or being collected, `-d` first.
+    pub fn collected(&self) -> &[String] {
+        &self.collected
+    }
+}
+
+/// Storage recording the trusts of the entries before the storage of the collection.
+pub struct TrustRecorder<'a, S> {
+    forest: &'a mut ForestDomains,
+    storage: &'a mut S,
+}
+
+impl<'a, S: Storage<LdapSearchEntry>> TrustRecorder<'a, S> {
+    pub fn new(forest: &'a mut ForestDomains, storage: &'a mut S) -> Self {
+        Self { forest, storage }
+    }
+}
+
+impl<S: Storage<LdapSearchEntry>> Storage<LdapSearchEntry> for TrustRecorder<'_, S> {
+    fn buffer_mut(&mut self) -> &mut Vec<LdapSearchEntry> {
+        self.storage.buffer_mut()
+    }
+
+    fn add(&mut self, item: LdapSearchEntry) -> Result<(), Box<dyn Error>> {
+        self.forest.add_trust(&item);
+        self.storage.add(item)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.storage.flush()
+    }
+}
+
+/// Function to get the domain of an entry of a forest collection: the domain of its DN, the one holding the
+/// DNS application partition for `DC=DomainDnsZones` and `DC=ForestDnsZones`.
+pub fn forest_entry_domain(dn: &str) -> String {
+    let rdns: Vec<&str> = dn.split(',').map(str::trim).collect();
+    let partition = rdns.iter()
+        .rposition(|rdn| rdn.eq_ignore_ascii_case("DC=DomainDnsZones") || rdn.eq_ignore_ascii_case("DC=ForestDnsZones"));
+    match partition {
+        Some(index) => dn_to_domain(&rdns[index + 1..].join(",")),
+        None => dn_to_domain(dn),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+
+    use crate::forest::*;
+
+    fn trust(name: &str, trust_attributes: u32) -> LdapSearchEntry {
+        LdapSearchEntry {
+            dn: format!("CN={name},CN=System,DC=contoso,DC=local"),
+            attrs: HashMap::from([
+                ("objectClass".to_string(), vec!["top".to_string(), "leaf".to_string(), "trustedDomain".to_string()]),
+                ("name".to_string(), vec![name.to_string()]),
+                ("trustAttributes".to_string(), vec![trust_attributes.to_string()]),
+            ]),
+            bin_attrs: HashMap::new(),
+        }
+    }
+
+    #[test]
+    pub fn test_forest_domains() {
+        let mut forest = ForestDomains::new("contoso.local");
+        let mut entries: Vec<LdapSearchEntry> = Vec::new();
+        let mut recorder = TrustRecorder::new(&mut forest, &mut entries);
+        // Child domain and tree root within the forest, external and forest trusts left out
+        recorder.add(trust("child.contoso.local", 0x20)).unwrap();
+        recorder.add(trust("fabrikam.local", 0x20)).unwrap();
+        recorder.add(trust("partner.local", 0x4)).unwrap();
+        recorder.add(trust("other-forest.local", 0x8)).unwrap();
+        recorder.add(trust("child.contoso.local", 0x20)).unwrap();
+        assert_eq!(entries.len(), 5);
+
+        let name = |trusted: Option<TrustedDomain>| trusted.map(|trusted| trusted.domain);
+        assert_eq!(name(forest.next_domain()).as_deref(), Some("CHILD.CONTOSO.LOCAL"));
+        // Trusts of the child domain: its parent is already collected, its own child is not
+        forest.add_trust(&trust("contoso.local", 0x20));
+        forest.add_trust(&trust("sub.child.contoso.local", 0x20));
+        assert_eq!(name(forest.next_domain()).as_deref(), Some("FABRIKAM.LOCAL"));
+        assert_eq!(name(forest.next_domain()).as_deref(), Some("SUB.CHILD.CONTOSO.LOCAL"));
+        assert!(forest.next_domain().is_none());
+        assert_eq!(forest.collected(), ["CONTOSO.LOCAL", "CHILD.CONTOSO.LOCAL", "FABRIKAM.LOCAL", "SUB.CHILD.CONTOSO.LOCAL"]);
+    }
+
+    #[test]
+    pub fn test_following_trusts() {
+        let mut domains = ForestDomains::following_trusts("contoso.local", 2);
+        // Child domain, forest trust, external trust: all followed but the disabled one
+        domains.add_trust(&trust("child.contoso.local", 0x20));
+        domains.add_trust(&trust("fabrikam.local", 0x8));
+        domains.add_trust(&trust("partner.local", 0x4));
+        let mut disabled = trust("old.local", 0x4);
+        disabled.attrs.insert("trustDirection".to_string(), vec!["0".to_string()]);
+        domains.add_trust(&disabled);
+
+        assert_eq!(
+            domains.next_domain(),
+            Some(TrustedDomain { domain: "CHILD.CONTOSO.LOCAL".to_string(), depth: 1, other_forest: false })
+        );
+        assert_eq!(
+            domains.next_domain(),
+            Some(TrustedDomain { domain: "FABRIKAM.LOCAL".to_string(), depth: 1, other_forest: true })
+        );
+        // Trusts of fabrikam.local: the loop back to contoso.local is left out, its child is 2 trusts away
+        domains.add_trust(&trust("contoso.local", 0x8));
+        domains.add_trust(&trust("emea.fabrikam.local", 0x20));
+        assert_eq!(domains.next_domain().map(|trusted| trusted.domain).as_deref(), Some("PARTNER.LOCAL"));
+        let emea = domains.next_domain().unwrap();
+        assert_eq!((emea.domain.as_str(), emea.depth, emea.other_forest), ("EMEA.FABRIKAM.LOCAL", 2, false));
+        // Max depth reached
+        domains.add_trust(&trust("apac.fabrikam.local", 0x20));
+        assert!(domains.next_domain().is_none());
+        assert_eq!(domains.collected().len(), 5);
+    }
+
+    #[test]
+    pub fn test_forest_entry_domain() {
+        assert_eq!(forest_entry_domain("CN=Alice,CN=Users,DC=child,DC=contoso,DC=local"), "CHILD.CONTOSO.LOCAL");
+        assert_eq!(forest_entry_domain("CN=ESC1,CN=Certificate Templates,CN=Public Key Services,CN=Services,CN=Configuration,DC=contoso,DC=local"), "CONTOSO.LOCAL");
+        assert_eq!(forest_entry_domain("DC=ws01,DC=child.contoso.local,CN=MicrosoftDNS,DC=DomainDnsZones,DC=child,DC=contoso,DC=local"), "CHILD.CONTOSO.LOCAL");
+        assert_eq!(forest_entry_domain("DC=_msdcs.contoso.local,CN=MicrosoftDNS,DC=ForestDnsZones,DC=contoso,DC=local"), "CONTOSO.LOCAL");
+    }
+}
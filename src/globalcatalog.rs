@@ -0,0 +1,166 @@
+//! Global Catalog collection of a whole forest
+//!
+//! With `--gc` one search on the Global Catalog (3268, or 3269 over TLS) returns the objects of every domain of the
+//! forest, with the attributes of the partial attribute set only. The attributes not replicated to the GC are then
+//! requested from a DC of each domain and merged in the GC entries by DN.
+use ldap3::SearchEntry;
+use std::collections::HashMap;
+
+use crate::utils::format::dn_to_domain;
+
+/// Global Catalog port.
+pub const GC_PORT: u16 = 3268;
+/// Global Catalog port over TLS.
+pub const GC_TLS_PORT: u16 = 3269;
+
+/// Attributes parsed by RustHound-CE and not in the default partial attribute set, requested from each domain.
+pub const GC_SUPPLEMENT_ATTRIBUTES: &[&str] = &[
+    // Users and computers
+    "adminCount",
+    "lastLogon",
+    "lastLogonTimestamp",
+    "logonCount",
+    "pwdLastSet",
+    "homeDirectory",
+    "scriptPath",
+    "userPassword",
+    "unixUserPassword",
+    "unicodePwd",
+    "msSFU30Password",
+    "msDS-SupportedEncryptionTypes",
+    "msDS-AllowedToDelegateTo",
+    "msDS-AllowedToActOnBehalfOfOtherIdentity",
+    "msDS-GroupMSAMembership",
+    "msDS-AdditionalDnsHostName",
+    "ms-Mcs-AdmPwd",
+    "ms-Mcs-AdmPwdExpirationTime",
+    "msLAPS-Password",
+    "msLAPS-EncryptedPassword",
+    "msLAPS-PasswordExpirationTime",
+    // Members of the global and domain local groups
+    "member",
+    // Domains and GPOs
+    "gPLink",
+    "gPOptions",
+    "gPCFileSysPath",
+    "ms-DS-MachineAccountQuota",
+    "msDS-Behavior-Version",
+    "msDS-ExpirePasswordsOnSmartCardOnlyAccounts",
+    "lockoutDuration",
+    "lockoutThreshold",
+    "lockOutObservationWindow",
+    "maxPwdAge",
+    "minPwdAge",
+    "minPwdLength",
+    "pwdHistoryLength",
+    "pwdProperties",
+    // AD CS
+    "cACertificate",
+    "certificateTemplates",
+    "crossCertificatePair",
+    "flags",
+    "msPKI-Cert-Template-OID",
+    "msPKI-Certificate-Application-Policy",
+    "msPKI-Certificate-Name-Flag",
+    "msPKI-Enrollment-Flag",
+    "msPKI-Private-Key-Flag",
+    "msPKI-RA-Application-Policies",
+    "msPKI-RA-Policies",
+    "msPKI-RA-Signature",
+    "msPKI-Template-Schema-Version",
+    "pKIExpirationPeriod",
+    "pKIExtendedKeyUsage",
+    "pKIOverlapPeriod",
+    // Schema
+    "defaultSecurityDescriptor",
+];
+
+/// Function to get the Global Catalog port.
+pub fn gc_port(ldaps: bool) -> u16 {
+    if ldaps { GC_TLS_PORT } else { GC_PORT }
+}
+
+/// Function to get the naming contexts to complete with the domain of the DC to ask:
+/// each domain of the forest, the configuration and the schema on the DC of `domain`.
+pub fn supplement_naming_contexts(
+    entries: &[SearchEntry],
+    root_dse: &HashMap<String, Vec<String>>,
+    domain: &str,
+) -> Vec<(String, String)> {
+    let mut naming_contexts: Vec<(String, String)> = entries.iter()
+        .filter(|entry| entry.attrs.get("objectClass")
+            .is_some_and(|classes| classes.iter().any(|class| class.eq_ignore_ascii_case("domainDNS"))))
+        .map(|entry| (entry.dn.to_owned(), dn_to_domain(&entry.dn)))
+        .collect();
+    naming_contexts.sort();
+    for attribute in ["configurationNamingContext", "schemaNamingContext"] {
+        if let Some(naming_context) = root_dse.get(attribute).and_then(|v| v.first()) {
+            naming_contexts.push((naming_context.to_owned(), domain.to_uppercase()));
+        }
+    }
+    naming_contexts
+}
+
+/// Function to get the naming context of a DN, the most specific one: a child domain is below its parent DN.
+pub fn naming_context_of<'a>(dn: &str, naming_contexts: &'a [String]) -> Option<&'a str> {
+    let dn = dn.to_uppercase();
+    naming_contexts.iter()
+        .filter(|nc| {
+            let nc = nc.to_uppercase();
+            dn == nc || dn.ends_with(&format!(",{nc}"))
+        })
+        .max_by_key(|nc| nc.len())
+        .map(String::as_str)
+}
+
+/// Function to merge the attributes requested from a DC in a Global Catalog entry, the DC values replace the GC ones.
+pub fn merge_entry(target: &mut SearchEntry, supplement: SearchEntry) {
+    target.attrs.extend(supplement.attrs);
+    target.bin_attrs.extend(supplement.bin_attrs);
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::globalcatalog::*;
+    use crate::test_support::search_entry;
+
+    fn entry(dn: &str, attrs: &[(&str, &str)]) -> SearchEntry {
+        search_entry(dn, attrs, HashMap::new())
+    }
+
+    #[test]
+    pub fn test_supplement_naming_contexts() {
+        let entries = vec![
+            entry("DC=contoso,DC=local", &[("objectClass", "domainDNS")]),
+            entry("DC=child,DC=contoso,DC=local", &[("objectClass", "domainDNS")]),
+            entry("CN=alice,CN=Users,DC=child,DC=contoso,DC=local", &[("objectClass", "user")]),
+        ];
+        let root_dse = HashMap::from([
+            ("configurationNamingContext".to_string(), vec!["CN=Configuration,DC=contoso,DC=local".to_string()]),
+        ]);
+        let naming_contexts = supplement_naming_contexts(&entries, &root_dse, "contoso.local");
+        assert_eq!(naming_contexts, vec![
+            ("DC=child,DC=contoso,DC=local".to_string(), "CHILD.CONTOSO.LOCAL".to_string()),
+            ("DC=contoso,DC=local".to_string(), "CONTOSO.LOCAL".to_string()),
+            ("CN=Configuration,DC=contoso,DC=local".to_string(), "CONTOSO.LOCAL".to_string()),
+        ]);
+
+        let ncs: Vec<String> = naming_contexts.into_iter().map(|(nc, _)| nc).collect();
+        assert_eq!(naming_context_of("CN=alice,CN=Users,DC=child,DC=contoso,DC=local", &ncs), Some("DC=child,DC=contoso,DC=local"));
+        assert_eq!(naming_context_of("cn=bob,cn=users,dc=contoso,dc=local", &ncs), Some("DC=contoso,DC=local"));
+        assert_eq!(naming_context_of("CN=PKI,CN=Configuration,DC=contoso,DC=local", &ncs), Some("CN=Configuration,DC=contoso,DC=local"));
+        assert_eq!(naming_context_of("DC=fabrikam,DC=local", &ncs), None);
+    }
+
+    #[test]
+    pub fn test_merge_entry() {
+        let mut target = entry("CN=group,DC=contoso,DC=local", &[("name", "group"), ("member", "")]);
+        let mut supplement = entry("CN=group,DC=contoso,DC=local", &[("member", "CN=alice,DC=contoso,DC=local")]);
+        supplement.bin_attrs.insert("nTSecurityDescriptor".to_string(), vec![vec![1, 0]]);
+        merge_entry(&mut target, supplement);
+        assert_eq!(target.attrs["name"], vec!["group"]);
+        assert_eq!(target.attrs["member"], vec!["CN=alice,DC=contoso,DC=local"]);
+        assert!(target.bin_attrs.contains_key("nTSecurityDescriptor"));
+    }
+}
@@ -0,0 +1,288 @@
+//! Incremental collection with the USNs of the DC
+//!
+//! With `--cache-state <FILE>` the `highestCommittedUSN` of the DC is read in its rootDSE before the first query,
+//! and recorded in the state file for each search base fully collected once the output is written. The next runs
+//! add `(uSNChanged>=N)` to the filter of these bases: only the objects changed since are requested and written,
+//! the zip only holds the delta, merged by BloodHound CE on upload. The domain object is requested anyway, for the
+//! domain SID and its properties.
+//!
+//! ```json
+//! {
+//!   "searchbases": [
+//!     {"base": "DC=contoso,DC=local", "dc": "CN=NTDS Settings,CN=DC01,CN=Servers,...", "usn": 128463, "filter": "(objectClass=*)"}
+//!   ],
+//!   "dn_sid": {"CN=ALICE,CN=USERS,DC=CONTOSO,DC=LOCAL": "S-1-5-21-1004336348-1177238915-682003330-1104"},
+//!   "sid_type": {"S-1-5-21-1004336348-1177238915-682003330-1104": "User"}
+//! }
+//! ```
+//!
+//! The USNs are counters of one DC (`dsServiceName`): a base recorded on another DC, or with another LDAP filter, is
+//! collected again in full with a warning, like a DC switched during the run. The DNs and SIDs of the previous runs
+//! resolve the members and the containers of the changed objects which did not change. The child domains of
+//! `--follow-referrals` are always collected in full.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::path::Path;
+use std::sync::Mutex;
+
+use colored::Colorize;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+/// State loaded with `--cache-state`, none without it.
+static USN_STATE: Lazy<Mutex<Option<LoadedState>>> = Lazy::new(|| Mutex::new(None));
+
+/// State of the previous runs, with the search bases collected by this run.
+#[derive(Debug, Default)]
+struct LoadedState {
+    state: UsnState,
+    collected: Vec<SearchBaseUsn>,
+}
+
+/// USN of the DC before the collection of a search base.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SearchBaseUsn {
+    pub base: String,
+    /// `dsServiceName` of the DC, its `dnsHostName` when not readable.
+    pub dc: String,
+    pub usn: u64,
+    /// LDAP filter of the collection, the objects left out by another one are not in the delta.
+    pub filter: String,
+}
+
+/// Content of the `--cache-state` file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct UsnState {
+    pub searchbases: Vec<SearchBaseUsn>,
+    /// DN to SID of the objects of the previous runs.
+    #[serde(default)]
+    pub dn_sid: BTreeMap<String, String>,
+    /// SID to type of the objects of the previous runs.
+    #[serde(default)]
+    pub sid_type: BTreeMap<String, String>,
+}
+
+impl UsnState {
+    /// Record of a search base, the first one when the base is recorded twice.
+    pub fn search_base(&self, base: &str) -> Option<&SearchBaseUsn> {
+        self.searchbases.iter().find(|record| record.base.eq_ignore_ascii_case(base))
+    }
+
+    /// Records of the search bases collected again replaced, the mappings of the collection added.
+    pub fn merge(&mut self, collected: Vec<SearchBaseUsn>, dn_sid: &HashMap<String, String>, sid_type: &HashMap<String, String>) {
+        self.searchbases.retain(|record| !collected.iter().any(|new| new.base.eq_ignore_ascii_case(&record.base)));
+        self.searchbases.extend(collected);
+        self.dn_sid.extend(dn_sid.iter().map(|(dn, sid)| (dn.to_owned(), sid.to_owned())));
+        self.sid_type.extend(sid_type.iter().map(|(sid, object_type)| (sid.to_owned(), object_type.to_owned())));
+    }
+}
+
+/// Function to load the `--cache-state` file, a full collection when it doesn't exist yet.
+pub fn load_usn_state(path: &str) -> Result<(), Box<dyn Error>> {
+    let state = match Path::new(path).exists() {
+        true => serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|err| format!("Invalid state file {path}: {err}"))?,
+        false => {
+            info!("No state in {}, full collection", path.bold());
+            UsnState::default()
+        }
+    };
+    *USN_STATE.lock().unwrap() = Some(LoadedState { state, collected: Vec::new() });
+    Ok(())
+}
+
+/// Function to add the DNs and SIDs of the previous runs missing from the mappings of the collection.
+pub fn seed_mappings(dn_sid: &mut HashMap<String, String>, sid_type: &mut HashMap<String, String>) {
+    let guard = USN_STATE.lock().unwrap();
+    let Some(LoadedState { state, .. }) = guard.as_ref() else {
+        return
+    };
+    for (dn, sid) in &state.dn_sid {
+        dn_sid.entry(dn.to_owned()).or_insert_with(|| sid.to_owned());
+    }
+    for (sid, object_type) in &state.sid_type {
+        sid_type.entry(sid.to_owned()).or_insert_with(|| object_type.to_owned());
+    }
+}
+
+/// Function to write the `--cache-state` file with the USNs of the search bases collected and the mappings.
+pub fn write_usn_state(path: &str, dn_sid: &HashMap<String, String>, sid_type: &HashMap<String, String>) -> Result<(), Box<dyn Error>> {
+    let mut guard = USN_STATE.lock().unwrap();
+    let Some(LoadedState { state, collected }) = guard.as_mut() else {
+        return Ok(())
+    };
+    let count = collected.len();
+    state.merge(std::mem::take(collected), dn_sid, sid_type);
+    std::fs::write(path, serde_json::to_string(state)?)?;
+    info!("USN of {count} search bases recorded in {}", path.bold());
+    Ok(())
+}
+
+/// Delta of the search bases of one DC, all of them in full without `--cache-state`.
+#[derive(Debug, Default)]
+pub struct DeltaSearch {
+    dc: String,
+    /// `highestCommittedUSN` of the DC before the first query.
+    usn: Option<u64>,
+    filter: String,
+    /// Search bases of the collection, the other queries (like the domain object of `--search-base`) aren't recorded.
+    bases: HashSet<String>,
+    /// Search bases with their USN recorded on this DC.
+    since: HashMap<String, u64>,
+    completed: Vec<String>,
+    failed: HashSet<String>,
+}
+
+impl DeltaSearch {
+    /// Delta of the search bases from the rootDSE of the DC, a warning for each base recorded on another DC.
+    pub fn new(root_dse: &HashMap<String, Vec<String>>, ldapfilter: &str, bases: &[String]) -> Self {
+        let guard = USN_STATE.lock().unwrap();
+        let Some(LoadedState { state, .. }) = guard.as_ref() else {
+            return Self::default()
+        };
+        let first = |name: &str| root_dse.get(name).and_then(|v| v.first()).cloned().unwrap_or_default();
+        let dc = match first("dsServiceName") {
+            dc if dc.is_empty() => first("dnsHostName").to_uppercase(),
+            dc => dc,
+        };
+        let usn = first("highestCommittedUSN").parse().ok();
+        if usn.is_none() {
+            warn!("No highestCommittedUSN in the rootDSE of the DC, no USN recorded for the next run");
+        }
+        let mut since = HashMap::new();
+        for base in bases {
+            match state.search_base(base) {
+                None => info!("No USN recorded for {}, full collection", base.bold()),
+                Some(record) if !record.dc.eq_ignore_ascii_case(&dc) => warn!(
+                    "USN of {} recorded on {}, not on {dc}: the USNs are per DC, full collection",
+                    base.bold().yellow(),
+                    record.dc
+                ),
+                Some(record) if record.filter != ldapfilter => warn!(
+                    "USN of {} recorded with the LDAP filter {}, full collection",
+                    base.bold().yellow(),
+                    record.filter
+                ),
+                Some(record) => {
+                    info!("Only the objects of {} changed since USN {}", base.bold().green(), record.usn);
+                    since.insert(base.to_uppercase(), record.usn);
+                }
+            }
+        }
+        let bases = bases.iter().map(|base| base.to_uppercase()).collect();
+        Self { dc, usn, filter: ldapfilter.to_owned(), bases, since, ..Default::default() }
+    }
+
+    /// Filter of a query: the objects changed since the USN of the base, with the domain object.
+    pub fn filter(&self, base: &str, filter: &str) -> String {
+        match self.since.get(&base.to_uppercase()) {
+            Some(usn) => format!("(&{filter}(|(uSNChanged>={usn})(objectClass=domain)))"),
+            None => filter.to_owned(),
+        }
+    }
+
+    /// Result of a query, a base is recorded once all its queries are complete.
+    pub fn finish_query(&mut self, base: &str, complete: bool) {
+        match complete {
+            true if self.bases.contains(&base.to_uppercase()) => self.completed.push(base.to_owned()),
+            true => {}
+            false => {
+                self.failed.insert(base.to_uppercase());
+            }
+        }
+    }
+
+    /// Another DC answers the queries: no more delta and nothing recorded.
+    pub fn dc_changed(&mut self) {
+        if self.usn.take().is_some() {
+            warn!("Domain controller switched, the next queries are full collections and no USN is recorded");
+        }
+        self.since.clear();
+    }
+
+    /// Record the USN of the bases completed, none after a partial collection.
+    pub fn finish(self, complete: bool) {
+        let (Some(usn), true) = (self.usn, complete) else {
+            return
+        };
+        let mut guard = USN_STATE.lock().unwrap();
+        let Some(LoadedState { collected, .. }) = guard.as_mut() else {
+            return
+        };
+        for base in self.completed {
+            if !self.failed.contains(&base.to_uppercase()) && !collected.iter().any(|record| record.base.eq_ignore_ascii_case(&base)) {
+                collected.push(SearchBaseUsn { base, dc: self.dc.to_owned(), usn, filter: self.filter.to_owned() });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::incremental::*;
+
+    #[test]
+    pub fn test_usn_state() {
+        let dc01 = "CN=NTDS Settings,CN=DC01,CN=Servers,CN=Default-First-Site-Name,CN=Sites,CN=Configuration,DC=contoso,DC=local";
+        let record = |base: &str, dc: &str, usn: u64, filter: &str| SearchBaseUsn {
+            base: base.to_string(), dc: dc.to_string(), usn, filter: filter.to_string(),
+        };
+        let mut state = UsnState {
+            searchbases: vec![
+                record("DC=contoso,DC=local", dc01, 1000, "(objectClass=*)"),
+                record("CN=Configuration,DC=contoso,DC=local", "CN=NTDS Settings,CN=DC02", 900, "(objectClass=*)"),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(state.search_base("dc=CONTOSO,dc=local").map(|record| record.usn), Some(1000));
+
+        // Collected again: replaced, the mappings added
+        state.merge(
+            vec![record("DC=CONTOSO,DC=LOCAL", dc01, 1500, "(objectClass=*)")],
+            &HashMap::from([("CN=ALICE,CN=USERS,DC=CONTOSO,DC=LOCAL".to_string(), "S-1-5-21-1-2-3-1104".to_string())]),
+            &HashMap::from([("S-1-5-21-1-2-3-1104".to_string(), "User".to_string())]),
+        );
+        assert_eq!(state.searchbases.len(), 2);
+        assert_eq!(state.search_base("DC=contoso,DC=local").map(|record| record.usn), Some(1500));
+        assert_eq!(state.sid_type["S-1-5-21-1-2-3-1104"], "User");
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(serde_json::from_str::<UsnState>(&json).unwrap(), state);
+    }
+
+    #[test]
+    pub fn test_delta_search() {
+        // Without --cache-state: full collection
+        let delta = DeltaSearch::default();
+        assert_eq!(delta.filter("DC=contoso,DC=local", "(objectClass=*)"), "(objectClass=*)");
+
+        let mut delta = DeltaSearch {
+            dc: "CN=NTDS Settings,CN=DC01".to_string(),
+            usn: Some(2000),
+            filter: "(objectClass=*)".to_string(),
+            bases: HashSet::from(["DC=CONTOSO,DC=LOCAL".to_string(), "CN=CONFIGURATION,DC=CONTOSO,DC=LOCAL".to_string()]),
+            since: HashMap::from([("DC=CONTOSO,DC=LOCAL".to_string(), 1000)]),
+            ..Default::default()
+        };
+        assert_eq!(
+            delta.filter("DC=contoso,DC=local", "(objectClass=*)"),
+            "(&(objectClass=*)(|(uSNChanged>=1000)(objectClass=domain)))"
+        );
+        assert_eq!(delta.filter("CN=Configuration,DC=contoso,DC=local", "(objectClass=*)"), "(objectClass=*)");
+        assert!(crate::filters::parse_ldap_filter(&delta.filter("DC=contoso,DC=local", "(objectClass=*)")).is_ok());
+
+        // A base with a failed query is not recorded, nor a query out of the search bases
+        delta.finish_query("DC=contoso,DC=local", true);
+        delta.finish_query("OU=Lab,DC=contoso,DC=local", true);
+        delta.finish_query("CN=Configuration,DC=contoso,DC=local", true);
+        delta.finish_query("CN=Configuration,DC=contoso,DC=local", false);
+        assert_eq!(delta.completed.len(), 2);
+        assert!(delta.failed.contains("CN=CONFIGURATION,DC=CONTOSO,DC=LOCAL"));
+
+        // Switched DC: full collection, no USN
+        delta.dc_changed();
+        assert!(delta.usn.is_none());
+        assert_eq!(delta.filter("DC=contoso,DC=local", "(objectClass=*)"), "(objectClass=*)");
+    }
+}
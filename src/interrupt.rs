@@ -0,0 +1,162 @@
+//! Graceful interruption on SIGINT / SIGTERM
+//!
+//! The first signal stops issuing new LDAP queries once the current page is received,
+//! the checker then runs on the objects already collected and the output files are written
+//! with `"partial": true` and the phase reached in their meta. A second signal aborts immediately.
+//!
+//! | exit code | meaning                                                     |
+//! |-----------|-------------------------------------------------------------|
+//! | `0`       | success                                                     |
+//! | `1`       | error                                                       |
+//! | `3`       | interrupted or `--deadline` reached, partial output written |
+//! | `130`     | aborted by a second signal, no output                       |
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use log::{error, warn};
+
+use crate::events::current_phase;
+
+/// Exit code when the run was interrupted but the partial output was written.
+pub const EXIT_PARTIAL_OUTPUT: i32 = 3;
+/// Exit code when a second signal aborted the run.
+pub const EXIT_ABORTED: i32 = 130;
+
+/// Interruption state: the signals received and the phase running at the first one.
+#[derive(Debug, Default)]
+pub struct Interruption {
+    interrupted: AtomicBool,
+    phase: Mutex<Option<String>>,
+}
+
+impl Interruption {
+    pub const fn new() -> Self {
+        Self { interrupted: AtomicBool::new(false), phase: Mutex::new(None) }
+    }
+
+    /// Check if a signal was received.
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+
+    /// Phase running when the first signal was received.
+    pub fn phase(&self) -> Option<String> {
+        self.phase.lock().unwrap().clone()
+    }
+
+    /// Handle one signal, return `true` if it is the second one and the run must abort.
+    pub fn interrupt(&self) -> bool {
+        if self.interrupted.swap(true, Ordering::SeqCst) {
+            return true
+        }
+        *self.phase.lock().unwrap() = Some(current_phase().unwrap_or_else(|| "startup".to_string()));
+        false
+    }
+
+    /// Clear the signals received.
+    pub fn reset(&self) {
+        self.interrupted.store(false, Ordering::SeqCst);
+        *self.phase.lock().unwrap() = None;
+    }
+}
+
+/// Interruption of the process, set by the signal handlers.
+static INTERRUPTION: Interruption = Interruption::new();
+
+/// Function to check if the run was interrupted.
+pub fn is_interrupted() -> bool {
+    INTERRUPTION.is_interrupted()
+}
+
+/// Function to get the phase running when the first signal was received.
+pub fn interrupted_phase() -> Option<String> {
+    INTERRUPTION.phase()
+}
+
+/// Function to handle one signal, return `true` if it is the second one and the run must abort.
+pub fn interrupt() -> bool {
+    INTERRUPTION.interrupt()
+}
+
+/// Function to clear the interruption state, useful when RustHound-CE is used as a library.
+pub fn reset_interrupt() {
+    INTERRUPTION.reset()
+}
+
+/// Function to install the ctrl-c and SIGTERM (Unix) handlers, must be called inside the tokio runtime.
+pub fn install_signal_handlers() {
+    tokio::spawn(async {
+        loop {
+            if wait_for_signal().await.is_err() {
+                error!("Unable to listen for interruption signals");
+                return
+            }
+            if interrupt() {
+                error!("Second interruption, aborting without output");
+                std::process::exit(EXIT_ABORTED);
+            }
+            warn!("Interrupted during {}, finishing the current LDAP page and writing partial output (interrupt again to abort)",
+                interrupted_phase().unwrap_or_default());
+        }
+    });
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> std::io::Result<()> {
+    let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => res,
+        _ = sigterm.recv() => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> std::io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::args::Options;
+    use crate::interrupt::*;
+    use crate::test_support::{entry, parse_entries, write_output};
+
+    #[tokio::test]
+    async fn test_partial_output_after_interrupt() {
+        // Signal received during the LDAP search: only some entries were collected. A state of the test, not the
+        // one of the process read by the other tests
+        let interruption = Interruption::new();
+        assert!(!interruption.interrupt());
+        assert!(interruption.is_interrupted());
+        let entries = vec![
+            entry("DC=CONTOSO,DC=LOCAL", &[("objectClass", "top|domain|domainDNS"), ("name", "contoso")]),
+            entry("CN=JDOE,CN=USERS,DC=CONTOSO,DC=LOCAL", &[
+                ("objectClass", "top|person|organizationalPerson|user"),
+                ("sAMAccountName", "jdoe"),
+                ("sAMAccountType", "805306368"),
+            ]),
+        ];
+
+        let options = Options { domain: "contoso.local".to_string(), ..Default::default() };
+        let mut results = parse_entries(entries, &options).await;
+        results.partial = interruption.phase();
+        assert!(results.partial.is_some());
+        let files = write_output(&options, results);
+
+        // Second signal aborts
+        assert!(interruption.interrupt());
+        interruption.reset();
+        assert!(!interruption.is_interrupted() && interruption.phase().is_none());
+
+        for (_, json) in &files {
+            assert_eq!(json["meta"]["partial"], true);
+            assert!(json["meta"]["phase"].is_string());
+            assert_eq!(json["meta"]["count"], json["data"].as_array().unwrap().len());
+        }
+        // Domain and user collected, default groups added by the checker
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names.len(), 3);
+        assert!(names[0].ends_with("_domains.json") && names[1].ends_with("_groups.json") && names[2].ends_with("_users.json"));
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashSet;
+use std::error::Error;
+
+use colored::Colorize;
+use log::{debug, info};
+
+use crate::api::ADResults;
+use crate::objects::common::{AceTemplate, LdapObject};
+
+/// Edges kept by the `high-value` preset: object control, ADCS, delegation, gMSA password and DCSync edges.
+pub const HIGH_VALUE_EDGES: &[&str] = &[
+    "GenericAll",
+    "WriteDacl",
+    "WriteOwner",
+    "Owns",
+    "AddMember",
+    "Enroll",
+    "AutoEnroll",
+    "ManageCA",
+    "ManageCertificates",
+    "AddAllowedToAct",
+    "WriteAccountRestrictions",
+    "AddKeyCredentialLink",
+    "ReadGMSAPassword",
+    "GetChanges",
+    "GetChangesAll",
+    "GetChangesInFilteredSet",
+];
+
+/// Well-known groups holding (nearly) every account: Everyone, Authenticated Users, Users
+/// and Pre-Windows 2000 Compatible Access.
+const LARGE_GROUP_SIDS: &[&str] = &["S-1-1-0", "S-1-5-11", "S-1-5-32-545", "S-1-5-32-554"];
+
+/// Domain Users and Domain Computers.
+const LARGE_GROUP_RIDS: &[&str] = &["-513", "-515"];
+
+/// ACE output filter, parsed from the `--ace-filter` comma separated list:
+///
+/// - `high-value` keeps only the [`HIGH_VALUE_EDGES`],
+/// - `EdgeName` keeps this edge type (added to the preset if any),
+/// - `-EdgeName` drops this edge type,
+/// - `no-default-large-groups` drops the default ACEs granted to Everyone, Authenticated Users, Domain Users...
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AceFilter {
+    /// Edge types kept, all of them when empty.
+    pub keep: HashSet<String>,
+    /// Edge types dropped.
+    pub drop: HashSet<String>,
+    /// Drop the default ACEs granted to very large well-known groups.
+    pub drop_default_large_groups: bool,
+}
+
+impl AceFilter {
+    /// Parse the `--ace-filter` value.
+    pub fn parse(spec: &str) -> Result<Self, Box<dyn Error>> {
+        let mut filter = AceFilter::default();
+        for token in spec.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            match token {
+                "high-value" => filter.keep.extend(HIGH_VALUE_EDGES.iter().map(|e| e.to_string())),
+                "no-default-large-groups" => filter.drop_default_large_groups = true,
+                _ => match token.strip_prefix('-') {
+                    Some(edge) if !edge.is_empty() => { filter.drop.insert(edge.to_string()); }
+                    Some(_) => return Err(format!("invalid ACE filter '{token}'").into()),
+                    None => { filter.keep.insert(token.to_string()); }
+                },
+            }
+        }
+        if filter == AceFilter::default() {
+            return Err(format!("empty ACE filter '{spec}'").into())
+        }
+        Ok(filter)
+    }
+
+    /// Check if one ACE is kept in the output.
+    ///
+    /// An ACE is default when the ACL baseline marked it so, or when it is inherited
+    /// if the baseline comparison was not run.
+    pub fn keep_ace(&self, ace: &AceTemplate) -> bool {
+        let right = ace.right_name();
+        if self.drop.contains(right) || (!self.keep.is_empty() && !self.keep.contains(right)) {
+            return false
+        }
+        if self.drop_default_large_groups
+            && is_large_group(ace.principal_sid())
+            && ace.is_default().unwrap_or(*ace.is_inherited())
+        {
+            return false
+        }
+        true
+    }
+}
+
+/// Check if the principal is a well-known group holding (nearly) every account.
+fn is_large_group(sid: &str) -> bool {
+    // Well-known SIDs are prefixed with the domain name: CONTOSO.LOCAL-S-1-5-11
+    LARGE_GROUP_SIDS.iter().any(|s| sid == *s || sid.ends_with(&format!("-{s}")))
+        || (sid.starts_with("S-1-5-21-") && LARGE_GROUP_RIDS.iter().any(|rid| sid.ends_with(rid)))
+}
+
+/// Function to remove the ACEs not kept by the filter, return the number of ACEs removed.
+pub fn filter_aces<T: LdapObject>(objects: &mut [T], filter: &AceFilter) -> usize {
+    let mut dropped = 0;
+    for object in objects.iter_mut() {
+        let aces = object.get_aces_mut();
+        let count = aces.len();
+        aces.retain(|ace| filter.keep_ace(ace));
+        dropped += count - aces.len();
+    }
+    dropped
+}
+
+/// Function to filter the ACEs of all objects once the checker is done.
+/// The filter is recorded in the results so each output meta shows it.
+pub fn check_ace_filter(spec: &str, results: &mut ADResults) -> Result<usize, Box<dyn Error>> {
+    let filter = AceFilter::parse(spec)?;
+    debug!("ACE filter: {:?}", filter);
+
+    let mut dropped = 0;
+    dropped += filter_aces(&mut results.users, &filter);
+    dropped += filter_aces(&mut results.groups, &filter);
+    dropped += filter_aces(&mut results.computers, &filter);
+    dropped += filter_aces(&mut results.ous, &filter);
+    dropped += filter_aces(&mut results.domains, &filter);
+    dropped += filter_aces(&mut results.gpos, &filter);
+    dropped += filter_aces(&mut results.containers, &filter);
+    dropped += filter_aces(&mut results.ntauthstores, &filter);
+    dropped += filter_aces(&mut results.aiacas, &filter);
+    dropped += filter_aces(&mut results.rootcas, &filter);
+    dropped += filter_aces(&mut results.enterprisecas, &filter);
+    dropped += filter_aces(&mut results.certtemplates, &filter);
+    dropped += filter_aces(&mut results.issuancepolicies, &filter);
+    info!("{} ACEs removed by the ACE filter {}", dropped.to_string().bold(), spec.bold().yellow());
+
+    results.ace_filter = Some(spec.to_owned());
+    Ok(dropped)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::json::checker::acefilter::*;
+    use crate::objects::{common::{AceTemplate, LdapObject}, user::User};
+
+    const DOMAIN_SID: &str = "S-1-5-21-1-2-3";
+
+    fn ace(sid: &str, right: &str, inherited: bool) -> AceTemplate {
+        AceTemplate::new(sid.to_string(), "Group".to_string(), right.to_string(), inherited, "".to_string())
+    }
+
+    fn user() -> User {
+        let mut user = User::new();
+        user.set_aces(vec![
+            ace(&format!("{DOMAIN_SID}-512"), "Owns", false),
+            ace(&format!("{DOMAIN_SID}-512"), "GenericAll", false),
+            ace(&format!("{DOMAIN_SID}-1105"), "ForceChangePassword", false),
+            ace(&format!("{DOMAIN_SID}-513"), "GenericWrite", true),
+            ace(&format!("{DOMAIN_SID}-513"), "WriteDacl", false),
+            ace("CONTOSO.LOCAL-S-1-5-11", "WriteDacl", true),
+        ]);
+        user
+    }
+
+    #[test]
+    pub fn test_parse_ace_filter() {
+        let filter = AceFilter::parse("high-value, -Owns,ForceChangePassword").unwrap();
+        assert!(filter.keep.contains("GenericAll") && filter.keep.contains("ForceChangePassword"));
+        assert!(filter.drop.contains("Owns"));
+        assert!(!filter.drop_default_large_groups);
+        assert!(AceFilter::parse("no-default-large-groups").unwrap().keep.is_empty());
+        assert!(AceFilter::parse("").is_err());
+        assert!(AceFilter::parse("-").is_err());
+    }
+
+    #[test]
+    pub fn test_filter_aces() {
+        let mut users = vec![user()];
+        assert_eq!(filter_aces(&mut users, &AceFilter::parse("high-value").unwrap()), 2);
+        assert_eq!(users[0].get_aces().len(), 4);
+
+        let mut users = vec![user()];
+        assert_eq!(filter_aces(&mut users, &AceFilter::parse("-Owns,-GenericAll").unwrap()), 2);
+
+        // Inherited ACEs of Domain Users and Authenticated Users are dropped, not the explicit one
+        let mut users = vec![user()];
+        assert_eq!(filter_aces(&mut users, &AceFilter::parse("no-default-large-groups").unwrap()), 2);
+        assert!(users[0].get_aces().iter().any(|ace| ace.right_name() == "WriteDacl" && ace.principal_sid().ends_with("-513")));
+
+        // The ACL baseline mark wins over the inherited flag
+        let mut users = vec![user()];
+        *users[0].get_aces_mut()[4].is_default_mut() = Some(true);
+        *users[0].get_aces_mut()[5].is_default_mut() = Some(false);
+        assert_eq!(filter_aces(&mut users, &AceFilter::parse("no-default-large-groups").unwrap()), 2);
+        assert!(users[0].get_aces().iter().any(|ace| ace.principal_sid() == "CONTOSO.LOCAL-S-1-5-11"));
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+use log::{debug, info, warn};
+
+use crate::enums::acl::parse_ntsecuritydescriptor;
+use crate::enums::sddl::sddl_to_security_descriptor;
+use crate::enums::sid::well_known_key;
+use crate::objects::{
+    common::LdapObject,
+    computer::Computer,
+    container::Container,
+    domain::Domain,
+    gpo::Gpo,
+    group::Group,
+    ou::Ou,
+    user::User,
+};
+
+/// ACEs found in the default security descriptors: principal SID, right name and inherited flag.
+pub type AclBaseline = HashSet<(String, String, bool)>;
+
+/// RustHound-CE entry type to schema class name.
+const ENTRY_CLASSES: &[(&str, &str)] = &[
+    ("User", "user"),
+    ("Computer", "computer"),
+    ("Group", "group"),
+    ("OU", "organizationalUnit"),
+    ("Domain", "domainDNS"),
+    ("Gpo", "groupPolicyContainer"),
+    ("Container", "container"),
+];
+
+/// Classes whose inheritable ACEs are propagated to the objects below them.
+const PARENT_CLASSES: &[&str] = &["domainDNS", "organizationalUnit", "container"];
+
+/// Owners set by default on new objects: Domain Admins, Enterprise Admins and Administrators, as well-known keys.
+const DEFAULT_OWNERS: &[&str] = &["-512", "-519", "S-1-5-32-544"];
+
+/// Function to build the default ACEs for one entry type from the schema default security descriptors.
+///
+/// The baseline is the class default DACL plus the inheritable ACEs of the default
+/// parent classes (inheritance expansion), parsed the same way as the collected objects.
+pub fn make_acl_baseline(
+    entry_type: &str,
+    default_sd: &HashMap<String, String>,
+    domain_sid: &str,
+    domain: &str,
+) -> Result<AclBaseline, Box<dyn Error>> {
+    let mut baseline = AclBaseline::new();
+    let class = match ENTRY_CLASSES.iter().find(|(t, _)| *t == entry_type) {
+        Some((_, class)) => *class,
+        None => return Ok(baseline),
+    };
+
+    let mut descriptors: Vec<Vec<u8>> = Vec::new();
+    if let Some(sddl) = default_sd.get(class) {
+        descriptors.push(sddl_to_security_descriptor(sddl, domain_sid, false)?);
+    }
+    for parent in PARENT_CLASSES {
+        if let Some(sddl) = default_sd.get(*parent) {
+            descriptors.push(sddl_to_security_descriptor(sddl, domain_sid, true)?);
+        }
+    }
+
+    let empty_attrs = HashMap::new();
+    let empty_bin = HashMap::new();
+    for nt in descriptors {
+        let aces = parse_ntsecuritydescriptor(&mut Container::new(), &nt, entry_type, &empty_attrs, &empty_bin, domain);
+        for ace in aces {
+            baseline.insert((ace.principal_sid().to_owned(), ace.right_name().to_owned(), *ace.is_inherited()));
+        }
+    }
+    Ok(baseline)
+}
+
+/// Function to set `isdefault` on each ACE and the `acedeviation` count on each object.
+/// Return the total number of ACEs not found in the baseline.
+pub fn mark_default_aces<T: LdapObject>(objects: &mut [T], baseline: &AclBaseline) -> u32 {
+    let mut total = 0;
+    for object in objects.iter_mut() {
+        let mut count = 0;
+        for ace in object.get_aces_mut().iter_mut() {
+            let is_default = if ace.right_name() == "Owns" {
+                DEFAULT_OWNERS.contains(&well_known_key(ace.principal_sid()).as_str())
+            } else {
+                baseline.contains(&(ace.principal_sid().to_owned(), ace.right_name().to_owned(), *ace.is_inherited()))
+            };
+            *ace.is_default_mut() = Some(is_default);
+            if !is_default {
+                count += 1;
+            }
+        }
+        object.set_ace_deviation(count);
+        total += count;
+    }
+    total
+}
+
+/// Function to compare the ACEs of the main AD objects against the schema default security descriptors.
+#[allow(clippy::too_many_arguments)]
+pub fn check_acl_baseline(
+    default_sd:     &HashMap<String, String>,
+    domain_sid:     &str,
+    domain:         &str,
+    vec_users:      &mut [User],
+    vec_computers:  &mut [Computer],
+    vec_groups:     &mut [Group],
+    vec_ous:        &mut [Ou],
+    vec_domains:    &mut [Domain],
+    vec_gpos:       &mut [Gpo],
+    vec_containers: &mut [Container],
+) -> Result<(), Box<dyn Error>> {
+    debug!("ACL baseline comparison started");
+    if default_sd.is_empty() {
+        warn!("No defaultSecurityDescriptor found in the schema, ACL baseline comparison skipped");
+        return Ok(())
+    }
+
+    let mut total = 0;
+    total += mark_default_aces(vec_users, &make_acl_baseline("User", default_sd, domain_sid, domain)?);
+    total += mark_default_aces(vec_computers, &make_acl_baseline("Computer", default_sd, domain_sid, domain)?);
+    total += mark_default_aces(vec_groups, &make_acl_baseline("Group", default_sd, domain_sid, domain)?);
+    total += mark_default_aces(vec_ous, &make_acl_baseline("OU", default_sd, domain_sid, domain)?);
+    total += mark_default_aces(vec_domains, &make_acl_baseline("Domain", default_sd, domain_sid, domain)?);
+    total += mark_default_aces(vec_gpos, &make_acl_baseline("Gpo", default_sd, domain_sid, domain)?);
+    total += mark_default_aces(vec_containers, &make_acl_baseline("Container", default_sd, domain_sid, domain)?);
+    info!("{} ACEs deviate from the schema default security descriptors", total);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use crate::enums::acl::parse_ntsecuritydescriptor;
+    use crate::enums::sddl::sddl_to_security_descriptor;
+    use crate::json::checker::baseline::*;
+    use crate::objects::{common::LdapObject, ou::Ou, user::User};
+
+    const DOMAIN: &str = "CONTOSO.LOCAL";
+    const DOMAIN_SID: &str = "S-1-5-21-1-2-3";
+    const USER_SD: &str = "D:(A;;RPWPCRCCDCLCLORCWOWDSDDTSW;;;DA)(A;;RPWPCRCCDCLCLORCWOWDSDDTSW;;;AO)(A;;RPLCLORC;;;PS)(OA;;CR;ab721a53-1e2f-11d0-9819-00aa0040529b;;PS)(A;;RC;;;AU)";
+    const OU_SD: &str = "D:(A;;RPWPCRCCDCLCLORCWOWDSDDTSW;;;DA)(A;;RPLCLORC;;;AU)(OA;;CCDC;bf967aba-0de6-11d0-a285-00aa003049e2;;AO)";
+    const DOMAIN_DNS_SD: &str = "D:(A;;RP;;;WD)(A;;RPWPCRCCDCLCLORCWOWDSDDTSW;;;DA)(A;CI;RPWPCRCCDCLCLORCWOWDSDDTSW;;;EA)";
+
+    fn default_sd() -> HashMap<String, String> {
+        HashMap::from([
+            ("user".to_string(), USER_SD.to_string()),
+            ("organizationalUnit".to_string(), OU_SD.to_string()),
+            ("domainDNS".to_string(), DOMAIN_DNS_SD.to_string()),
+        ])
+    }
+
+    fn parse_aces<T: LdapObject>(object: &mut T, sddl: &str, entry_type: &str) {
+        let nt = sddl_to_security_descriptor(sddl, DOMAIN_SID, false).unwrap();
+        let aces = parse_ntsecuritydescriptor(object, &nt, entry_type, &HashMap::new(), &HashMap::new(), DOMAIN);
+        object.set_aces(aces);
+    }
+
+    #[test]
+    pub fn test_default_installed_user() {
+        // Class default plus the ACE inherited from the domain head
+        let sddl = format!("O:DA{USER_SD}(A;CIID;RPWPCRCCDCLCLORCWOWDSDDTSW;;;EA)");
+        let mut users = vec![User::new()];
+        parse_aces(&mut users[0], &sddl, "User");
+        assert!(users[0].get_aces().iter().any(|ace| *ace.is_inherited()));
+
+        let baseline = make_acl_baseline("User", &default_sd(), DOMAIN_SID, DOMAIN).unwrap();
+        assert_eq!(mark_default_aces(&mut users, &baseline), 0);
+        assert!(users[0].get_aces().iter().all(|ace| *ace.is_default() == Some(true)));
+        assert_eq!(serde_json::to_value(&users[0]).unwrap()["Properties"]["acedeviation"], 0);
+    }
+
+    #[test]
+    pub fn test_delegated_ou() {
+        // GenericAll delegated to a helpdesk group on the OU
+        let sddl = format!("O:DA{OU_SD}(A;CI;CCDCLCSWRPWPDTLOCRSDRCWDWO;;;{DOMAIN_SID}-1105)(A;CIID;RPWPCRCCDCLCLORCWOWDSDDTSW;;;EA)");
+        let mut ous = vec![Ou::new()];
+        parse_aces(&mut ous[0], &sddl, "OU");
+
+        let baseline = make_acl_baseline("OU", &default_sd(), DOMAIN_SID, DOMAIN).unwrap();
+        assert_eq!(mark_default_aces(&mut ous, &baseline), 1);
+        let deviations: Vec<_> = ous[0].get_aces().iter().filter(|ace| *ace.is_default() == Some(false)).collect();
+        assert_eq!(deviations.len(), 1);
+        assert_eq!(deviations[0].principal_sid(), &format!("{DOMAIN_SID}-1105"));
+        assert_eq!(deviations[0].right_name(), "GenericAll");
+        assert_eq!(serde_json::to_value(&ous[0]).unwrap()["Properties"]["acedeviation"], 1);
+    }
+
+    #[test]
+    pub fn test_default_owner_exact_rid() {
+        let baseline = make_acl_baseline("User", &default_sd(), DOMAIN_SID, DOMAIN).unwrap();
+        let owner_is_default = |owner: &str| {
+            let mut users = vec![User::new()];
+            parse_aces(&mut users[0], &format!("O:{owner}{USER_SD}"), "User");
+            mark_default_aces(&mut users, &baseline);
+            let owns = users[0].get_aces().iter().find(|ace| ace.right_name() == "Owns").cloned().unwrap();
+            *owns.is_default()
+        };
+        assert_eq!(owner_is_default("EA"), Some(true));
+        assert_eq!(owner_is_default("BA"), Some(true));
+        // Same suffix as Domain Admins and Enterprise Admins, not the same RID
+        assert_eq!(owner_is_default(&format!("{DOMAIN_SID}-11512")), Some(false));
+        assert_eq!(owner_is_default(&format!("{DOMAIN_SID}-2519")), Some(false));
+    }
+}
@@ -11,14 +11,22 @@ use crate::objects::{
     ou::Ou,
     domain::Domain,
     trust::Trust,
-    common::{Member, GPOChange, LdapObject}
+    enterpriseca::EnterpriseCA,
+    common::{Member, GPOChange, LdapObject, HostResolver}
 };
-//use log::{info,debug,trace};
+use log::debug;
 use crate::ldap::prepare_ldap_dc;
 use crate::utils::format::domain_to_dc;
+use crate::deleted::strip_deleted_suffix;
 use crate::enums::regex::COMMON_RE1;
+use crate::enums::sid::{get_well_known_name, get_rid_from_localized_name, is_highvalue_sid};
 use indicatif::ProgressBar;
 
+/// Function to get the name of one well-known principal like "EVERYONE@DOMAIN.LOCAL"
+fn get_well_known_group_name(sid: &str, domain: &str) -> String {
+    format!("{}@{}", get_well_known_name(sid).unwrap_or_else(|| sid.to_string()), domain.to_uppercase())
+}
+
 /// Function to add default groups
 /// <https://github.com/fox-it/BloodHound.py/blob/645082e3462c93f31b571db945cde1fd7b837fb9/bloodhound/enumeration/memberships.py#L411>
 pub fn add_default_groups(
@@ -34,9 +42,7 @@ pub fn add_default_groups(
     let mut edc_group = Group::new();
     let mut sid = domain.to_uppercase();
     sid.push_str("-S-1-5-9");
-
-    let mut name = "ENTERPRISE DOMAIN CONTROLLERS@".to_owned();
-    name.push_str(&domain.to_uppercase());
+    let name = get_well_known_group_name("S-1-5-9", &domain);
 
     let mut vec_members: Vec<Member> = Vec::new();
     for computer in vec_computers {
@@ -68,20 +74,18 @@ pub fn add_default_groups(
     let mut account_operators_group = Group::new();
     sid = domain.to_uppercase();
     sid.push_str("-S-1-5-32-548");
-    let mut name = "ACCOUNT OPERATORS@".to_owned();
-    name.push_str(&domain.to_uppercase());
+    let name = get_well_known_group_name("S-1-5-32-548", &domain);
     
+    *account_operators_group.properties_mut().highvalue_mut() = is_highvalue_sid(&sid);
     *account_operators_group.object_identifier_mut() = sid;
     *account_operators_group.properties_mut().name_mut() = name;
-    *account_operators_group.properties_mut().highvalue_mut() = true;
     vec_groups.push(account_operators_group);
 
     // WINDOWS AUTHORIZATION ACCESS GROUP
     let mut waag_group = Group::new();
     sid = domain.to_uppercase();
     sid.push_str("-S-1-5-32-560");
-    let mut name = "WINDOWS AUTHORIZATION ACCESS GROUP@".to_owned();
-    name.push_str(&domain.to_uppercase());
+    let name = get_well_known_group_name("S-1-5-32-560", &domain);
     *waag_group.object_identifier_mut() = sid;
     *waag_group.properties_mut().name_mut() = name;
     vec_groups.push(waag_group);
@@ -90,8 +94,7 @@ pub fn add_default_groups(
     let mut everyone_group = Group::new();
     sid = domain.to_uppercase();
     sid.push_str("-S-1-1-0");
-    let mut name = "EVERYONE@".to_owned();
-    name.push_str(&domain.to_uppercase());
+    let name = get_well_known_group_name("S-1-1-0", &domain);
 
     let mut vec_everyone_members: Vec<Member> = Vec::new();
     let mut member_id = domain_sid.to_owned();
@@ -115,8 +118,7 @@ pub fn add_default_groups(
     let mut auth_users_group = Group::new();
     sid = domain.to_uppercase();
     sid.push_str("-S-1-5-11");
-    let mut name = "AUTHENTICATED USERS@".to_owned();
-    name.push_str(&domain.to_uppercase());
+    let name = get_well_known_group_name("S-1-5-11", &domain);
 
     let mut vec_auth_users_members: Vec<Member> = Vec::new();
     member_id = domain_sid.to_owned();
@@ -140,20 +142,18 @@ pub fn add_default_groups(
     let mut administrators_group = Group::new();
     sid = domain.to_uppercase();
     sid.push_str("-S-1-5-32-544");
-    let mut name = "ADMINISTRATORS@".to_owned();
-    name.push_str(&domain.to_uppercase());
+    let name = get_well_known_group_name("S-1-5-32-544", &domain);
 
+    *administrators_group.properties_mut().highvalue_mut() = is_highvalue_sid(&sid);
     *administrators_group.object_identifier_mut() = sid;
     *administrators_group.properties_mut().name_mut() = name;
-    *administrators_group.properties_mut().highvalue_mut() = true;
     vec_groups.push(administrators_group);
 
     // PRE-WINDOWS 2000 COMPATIBLE ACCESS
     let mut pw2000ca_group = Group::new();
     sid = domain.to_uppercase();
     sid.push_str("-S-1-5-32-554");
-    let mut name = "PRE-WINDOWS 2000 COMPATIBLE ACCESS@".to_owned();
-    name.push_str(&domain.to_uppercase());
+    let name = get_well_known_group_name("S-1-5-32-554", &domain);
             
     *pw2000ca_group.object_identifier_mut() = sid;
     *pw2000ca_group.properties_mut().name_mut() = name;
@@ -163,8 +163,7 @@ pub fn add_default_groups(
     let mut interactive_group = Group::new();
     sid = domain.to_uppercase();
     sid.push_str("-S-1-5-4");
-    let mut name = "INTERACTIVE@".to_owned();
-    name.push_str(&domain.to_uppercase());
+    let name = get_well_known_group_name("S-1-5-4", &domain);
 
     *interactive_group.object_identifier_mut() = sid;
     *interactive_group.properties_mut().name_mut() = name;
@@ -174,20 +173,18 @@ pub fn add_default_groups(
     let mut print_operators_group = Group::new();
     sid = domain.to_uppercase();
     sid.push_str("-S-1-5-32-550");
-    let mut name = "PRINT OPERATORS@".to_owned();
-    name.push_str(&domain.to_uppercase());
+    let name = get_well_known_group_name("S-1-5-32-550", &domain);
             
+    *print_operators_group.properties_mut().highvalue_mut() = is_highvalue_sid(&sid);
     *print_operators_group.object_identifier_mut() = sid;
     *print_operators_group.properties_mut().name_mut() = name;
-    *print_operators_group.properties_mut().highvalue_mut() = true;
     vec_groups.push(print_operators_group); 
 
     // TERMINAL SERVER LICENSE SERVERS
     let mut tsls_group = Group::new();
     sid = domain.to_uppercase();
     sid.push_str("-S-1-5-32-561");
-    let mut name = "TERMINAL SERVER LICENSE SERVERS@".to_owned();
-    name.push_str(&domain.to_uppercase());
+    let name = get_well_known_group_name("S-1-5-32-561", &domain);
             
     *tsls_group.object_identifier_mut() = sid;
     *tsls_group.properties_mut().name_mut() = name;
@@ -197,8 +194,7 @@ pub fn add_default_groups(
     let mut iftb_group = Group::new();
     sid = domain.to_uppercase();
     sid.push_str("-S-1-5-32-557");
-    let mut name = "INCOMING FOREST TRUST BUILDERS@".to_owned();
-    name.push_str(&domain.to_uppercase());
+    let name = get_well_known_group_name("S-1-5-32-557", &domain);
             
     *iftb_group.object_identifier_mut() = sid;
     *iftb_group.properties_mut().name_mut() = name;
@@ -208,8 +204,7 @@ pub fn add_default_groups(
     let mut this_organization_group = Group::new();
     sid = domain.to_uppercase();
     sid.push_str("-S-1-5-15");
-    let mut name = "THIS ORGANIZATION@".to_owned();
-    name.push_str(&domain.to_uppercase());
+    let name = get_well_known_group_name("S-1-5-15", &domain);
             
     *this_organization_group.object_identifier_mut() = sid;
     *this_organization_group.properties_mut().name_mut() = name;
@@ -227,11 +222,15 @@ pub fn add_default_users(
     let mut ntauthority_user = User::new();
     let mut sid = domain.to_uppercase();
     sid.push_str("-S-1-5-20");
-    let mut name = "NT AUTHORITY@".to_owned();
-    name.push_str(&domain.to_uppercase());
+    let name = get_well_known_group_name("S-1-5-20", &domain);
     *ntauthority_user.properties_mut().name_mut() = name;
     *ntauthority_user.object_identifier_mut() = sid;
-    *ntauthority_user.properties_mut().domainsid_mut() = vec_users[0].properties().domainsid().to_string();
+    // Domain SID of a user of this domain, several domains with the Global Catalog
+    // No user at all with a --search-base without any
+    let user = vec_users.iter()
+        .find(|user| user.properties().domain().eq_ignore_ascii_case(&domain))
+        .or(vec_users.first());
+    *ntauthority_user.properties_mut().domainsid_mut() = user.map(|user| user.properties().domainsid().to_string()).unwrap_or_default();
     vec_users.push(ntauthority_user);
     Ok(())
 }
@@ -248,6 +247,9 @@ pub fn add_childobjects_members<T: LdapObject>(
 
     // Precompute "null" to avoid repeated allocations
     let null: String = "NULL".to_string();
+    // Sorted by DN so the child objects order is stable between runs
+    let mut sorted_dn_sid: Vec<(&String, &String)> = dn_sid.iter().collect();
+    sorted_dn_sid.sort();
 
     // Iterate over the objects
     for (count, object) in vec_replaced.iter_mut().enumerate() {
@@ -258,17 +260,18 @@ pub fn add_childobjects_members<T: LdapObject>(
 
         // Get the SID, DN, and name of the current object
         let sid = object.get_object_identifier().to_uppercase();
-        let dn = dn_sid
+        let dn = sorted_dn_sid
             .iter()
+            .copied()
             .find(|(_, v)| **v == sid)
             .map(|(k, _)| k)
             .unwrap_or(&null);
         let name = get_name_from_full_distinguishedname(dn);
-        let _otype = sid_type.get(&sid).unwrap();
 
         // Filter direct members from dn_sid
-        let direct_members: Vec<Member> = dn_sid
+        let direct_members: Vec<Member> = sorted_dn_sid
             .iter()
+            .copied()
             .filter_map(|(dn_object, value_sid)| {
                 let dn_object_upper = dn_object.to_uppercase();
 
@@ -311,6 +314,9 @@ pub fn add_childobjects_members_for_ou(
 
     // Cache common values to avoid repeated allocations
     let null = "NULL".to_string();
+    // Sorted by DN so the child objects order is stable between runs
+    let mut sorted_dn_sid: Vec<(&String, &String)> = dn_sid.iter().collect();
+    sorted_dn_sid.sort();
 
     for (count, object) in vec_replaced.iter_mut().enumerate() {
         // Update progress bar periodically
@@ -335,7 +341,7 @@ pub fn add_childobjects_members_for_ou(
         }
 
         // Process all dn_sid entries
-        for (dn_object, value_sid) in dn_sid {
+        for &(dn_object, value_sid) in &sorted_dn_sid {
             let dn_object_upper = dn_object.to_uppercase();
 
             // Parse the "first" component of the DN
@@ -436,10 +442,15 @@ pub fn add_affected_computers(
     sid_type: &HashMap<String, String>,
 ) -> Result<(), Box<dyn Error>> {
     // Filter only "Computer" SIDs and map them to Member objects
-    let vec_affected_computers: Vec<Member> = sid_type
+    let mut computer_sids: Vec<&String> = sid_type
         .iter()
         .filter(|&(_, obj_type)| obj_type == "Computer")
-        .map(|(sid, _)| {
+        .map(|(sid, _)| sid)
+        .collect();
+    computer_sids.sort();
+    let vec_affected_computers: Vec<Member> = computer_sids
+        .into_iter()
+        .map(|sid| {
             let mut member = Member::new();
             *member.object_type_mut() = "Computer".to_string();
             *member.object_identifier_mut() = sid.clone();
@@ -456,17 +467,18 @@ pub fn add_affected_computers(
     Ok(())
 }
 
-/// This function pushes computer SIDs into GPO changes for each OU
+/// This function pushes computer SIDs into GPO changes for each OU, nested OUs included
 pub fn add_affected_computers_for_ou(
     vec_ous: &mut [Ou],
     dn_sid: &HashMap<String, String>,
     sid_type: &HashMap<String, String>,
 ) -> Result<(), Box<dyn Error>> {
     // Filter all computers DN:SID in advance
-    let dn_sid_filtered: Vec<(&String, &String)> = dn_sid
+    let mut dn_sid_filtered: Vec<(&String, &String)> = dn_sid
         .iter()
         .filter(|(_, sid)| sid_type.get(*sid).map(|t| t == "Computer").unwrap_or(false))
         .collect();
+    dn_sid_filtered.sort();
 
     // Map each OU's identifier to its DN
     let ou_dn_map: HashMap<String, String> = vec_ous
@@ -484,17 +496,14 @@ pub fn add_affected_computers_for_ou(
         })
         .collect();
 
-    // For each OU, add affected computers
+    // For each OU, add affected computers: the GPOs linked to an OU apply to its whole subtree
     for ou in vec_ous.iter_mut() {
         if let Some(ou_dn) = ou_dn_map.get(ou.get_object_identifier()) {
+            let suffix = format!(",{}", ou_dn.to_uppercase());
             let vec_affected_computers: Vec<Member> = dn_sid_filtered
                 .iter()
                 .filter_map(|(dn, sid)| {
-                    if get_contained_by_name_from_distinguishedname(
-                        &get_cn_object_name_from_full_distinguishedname(dn),
-                        dn,
-                    ) == *ou_dn
-                    {
+                    if dn.to_uppercase().ends_with(&suffix) {
                         let mut member = Member::new();
                         *member.object_identifier_mut() = sid.to_string();
                         *member.object_type_mut() = "Computer".to_string();
@@ -518,7 +527,7 @@ pub fn add_affected_computers_for_ou(
 pub fn replace_fqdn_by_sid<T: LdapObject>(
     object_type: Type,
     vec_src: &mut [T],
-    fqdn_sid: &HashMap<String, String>,
+    host_resolver: &HostResolver,
 ) -> Result<(), Box<dyn Error>> {
     // Progress bar setup
     let total = vec_src.len();
@@ -535,16 +544,16 @@ pub fn replace_fqdn_by_sid<T: LdapObject>(
 
                 // Process SPNTargets
                 for target in obj.get_spntargets_mut().iter_mut() {
-                    let sid = fqdn_sid
-                        .get(target.computer_sid())
+                    let sid = host_resolver
+                        .resolve(target.computer_sid())
                         .unwrap_or_else(|| target.computer_sid());
                     *target.computer_sid_mut() = sid.to_string();
                 }
 
                 // Process AllowedToDelegate
                 for target in obj.get_allowed_to_delegate_mut().iter_mut() {
-                    let sid = fqdn_sid
-                        .get(target.object_identifier())
+                    let sid = host_resolver
+                        .resolve(target.object_identifier())
                         .unwrap_or_else(|| target.object_identifier());
                     *target.object_identifier_mut() = sid.to_string();
                 }
@@ -559,8 +568,8 @@ pub fn replace_fqdn_by_sid<T: LdapObject>(
 
                 // Process AllowedToDelegate
                 for delegate in obj.get_allowed_to_delegate_mut().iter_mut() {
-                    let sid = fqdn_sid
-                        .get(delegate.object_identifier())
+                    let sid = host_resolver
+                        .resolve(delegate.object_identifier())
                         .unwrap_or_else(|| delegate.object_identifier());
                     *delegate.object_identifier_mut() = sid.to_string();
                 }
@@ -573,6 +582,51 @@ pub fn replace_fqdn_by_sid<T: LdapObject>(
     Ok(())
 }
 
+/// Function to drop the delegation and SPN target edges of the disabled users and computers.
+/// Their nodes, ACEs and raw `userAccountControl` properties (`unconstraineddelegation`, `trustedtoauth`...) are kept.
+/// Return the number of accounts with dropped edges.
+pub fn remove_disabled_delegation(
+    vec_users: &mut [User],
+    vec_computers: &mut [Computer],
+) -> Result<usize, Box<dyn Error>> {
+    let mut count = 0;
+    for user in vec_users.iter_mut().filter(|user| !user.properties().enabled()) {
+        if !user.get_allowed_to_delegate().is_empty() || !user.get_spntargets().is_empty() || *user.unconstrained_delegation() {
+            count += 1;
+        }
+        user.set_allowed_to_delegate(Vec::new());
+        user.set_spntargets(Vec::new());
+        *user.unconstrained_delegation_mut() = false;
+    }
+    for computer in vec_computers.iter_mut().filter(|computer| !computer.properties().enabled()) {
+        if !computer.get_allowed_to_delegate().is_empty() || *computer.unconstrained_delegation() {
+            count += 1;
+        }
+        computer.set_allowed_to_delegate(Vec::new());
+        *computer.unconstrained_delegation_mut() = false;
+    }
+    Ok(count)
+}
+
+/// This function replaces the EnterpriseCA HostingComputer by the computer SID found from its dNSHostName,
+/// the principal with ManageCertificates is kept when the host is not a collected computer.
+pub fn resolve_hosting_computer(
+    vec_enterprisecas: &mut [EnterpriseCA],
+    host_resolver: &HostResolver,
+) -> Result<(), Box<dyn Error>> {
+    for enterpriseca in vec_enterprisecas.iter_mut() {
+        let Some(sid) = host_resolver.resolve(enterpriseca.dnshostname()).cloned() else {
+            continue
+        };
+        if &sid != enterpriseca.hosting_computer() {
+            debug!("HostingComputer of {} is {sid} from its dNSHostName, not {}", enterpriseca.dnshostname(), enterpriseca.hosting_computer());
+            enterpriseca.set_hosting_computer(&sid);
+        }
+        *enterpriseca.dnshostname_sid_mut() = Some(sid);
+    }
+    Ok(())
+}
+
 /// This function checks and replaces object names by SIDs in group members v2
 pub fn replace_sid_members(
     vec_groups: &mut [Group],
@@ -653,31 +707,9 @@ fn get_id_from_objectidentifier(
     object_identifier: &str
 ) -> Result<String, Box<dyn Error>> {
 
-    // Static mapping of group names to RIDs
-    const NAME_TO_RID: [(&str, &str); 16] = [
-        ("DOMAIN ADMINS", "-512"),
-        ("ADMINISTRATEURS DU DOMAINE", "-512"),
-        ("DOMAIN USERS", "-513"),
-        ("UTILISATEURS DU DOMAINE", "-513"),
-        ("DOMAIN GUESTS", "-514"),
-        ("INVITES DE DOMAINE", "-514"),
-        ("DOMAIN COMPUTERS", "-515"),
-        ("ORDINATEURS DE DOMAINE", "-515"),
-        ("DOMAIN CONTROLLERS", "-516"),
-        ("CONTRÔLEURS DE DOMAINE", "-516"),
-        ("CERT PUBLISHERS", "-517"),
-        ("EDITEURS DE CERTIFICATS", "-517"),
-        ("SCHEMA ADMINS", "-518"),
-        ("ADMINISTRATEURS DU SCHEMA", "-518"),
-        ("ENTERPRISE ADMINS", "-519"),
-        ("ADMINISTRATEURS DE L'ENTREPRISE", "-519"),
-    ];
-
-    // Iterate over the static array to find a match
-    for (name, rid) in NAME_TO_RID.iter() {
-        if object_identifier.contains(name) {
-            return Ok(rid.to_string())
-        }
+    // Well-known names (and localized names) to RIDs
+    if let Some(rid) = get_rid_from_localized_name(object_identifier) {
+        return Ok(rid)
     }
 
     // Default case if no match is found
@@ -690,9 +722,14 @@ pub fn add_trustdomain(
     vec_trusts: &mut [Trust]
 ) -> Result<(), Box<dyn Error>> {
     if !&vec_trusts[0].target_domain_sid().to_string().contains("SID") {
-        let mut trusts: Vec<Trust> = Vec::new();
-        for trust in vec_trusts {
-            trusts.push(trust.to_owned());
+        // Domains collected in the same run (--forest) keep their own node
+        let collected: Vec<String> = vec_domains.iter().map(|domain| domain.get_object_identifier().to_owned()).collect();
+        let mut added: Vec<String> = Vec::new();
+        for trust in vec_trusts.iter() {
+            if collected.contains(trust.target_domain_sid()) || added.contains(trust.target_domain_sid()) {
+                continue
+            }
+            added.push(trust.target_domain_sid().to_owned());
             let mut new_domain = Domain::new();
             *new_domain.object_identifier_mut() = trust.target_domain_sid().to_string();
             *new_domain.properties_mut().name_mut() = trust.target_domain_name().to_string();
@@ -701,7 +738,14 @@ pub fn add_trustdomain(
             *new_domain.properties_mut().highvalue_mut() = true;
             vec_domains.push(new_domain);
         }
-        *vec_domains[0].trusts_mut() = trusts.to_owned();
+        // Each trust on the domain holding it, the first domain for a trust of an unknown domain
+        for trust in vec_trusts.iter() {
+            let holder = vec_domains.iter()
+                .position(|domain| domain.properties().name().eq_ignore_ascii_case(trust.domain()))
+                .filter(|index| collected.len() > *index)
+                .unwrap_or(0);
+            vec_domains[holder].trusts_mut().push(trust.to_owned());
+        }
     }
     Ok(())
 }
@@ -777,10 +821,12 @@ pub fn add_type_for_allowtedtoact(
 }
 
 /// This function pushes user SID into ChildObjects for Ou v2
+/// A deleted object is contained by its last known parent.
 pub fn add_contained_by_for<T: LdapObject>(
     vec_replaced: &mut [T],
     dn_sid: &HashMap<String, String>, 
     sid_type: &HashMap<String, String>,
+    last_known_parent: &HashMap<String, String>,
 ) -> Result<(), Box<dyn Error>> {
     // Progress bar setup
     let total = vec_replaced.len();
@@ -804,8 +850,13 @@ pub fn add_contained_by_for<T: LdapObject>(
 
             if otype != "Domain" {
                 // Extract CN name and contained-by name
-                let cn_name = get_cn_object_name_from_full_distinguishedname(dn);
-                let contained_by_name = get_contained_by_name_from_distinguishedname(&cn_name, dn);
+                let contained_by_name = match last_known_parent.get(dn) {
+                    Some(parent) => parent.to_owned(),
+                    None => {
+                        let cn_name = get_cn_object_name_from_full_distinguishedname(dn);
+                        get_contained_by_name_from_distinguishedname(&cn_name, dn)
+                    }
+                };
 
                 // Check if the contained-by name exists in dn_sid
                 if let Some(sid_contained_by) = dn_sid.get(&contained_by_name) {
@@ -833,7 +884,8 @@ pub fn get_name_from_full_distinguishedname(dn_object: &str) -> String {
     let vec1 = split1.collect::<Vec<&str>>();
     let split2 = vec1[0].split("=");
     let vec2 = split2.collect::<Vec<&str>>();
-    let name = vec2[1].to_owned();
+    // Deleted object: CN=G0H4N\0ADEL:<guid>,CN=DELETED OBJECTS,DC=ESSOS,DC=LOCAL
+    let name = strip_deleted_suffix(vec2[1]).to_owned();
     // name = G0H4N
     name
 }
@@ -867,11 +919,18 @@ fn get_contained_by_name_from_distinguishedname(cn_name: &str, dn_object: &str)
 #[cfg(test)]
 mod tests {
     
+    use std::collections::HashMap;
+
     use crate::json::checker::common::{
+        add_affected_computers_for_ou,
+        resolve_hosting_computer,
         get_name_from_full_distinguishedname,
         get_cn_object_name_from_full_distinguishedname,
         get_contained_by_name_from_distinguishedname
     };
+    use crate::objects::common::{HostResolver, LdapObject};
+    use crate::objects::enterpriseca::EnterpriseCA;
+    use crate::objects::ou::Ou;
     
     #[test]
     #[rustfmt::skip]
@@ -883,6 +942,9 @@ mod tests {
         println!("dn_object: {:?}",dn_object);
         println!("cn_name: {:?}",cn_name);
         assert_eq!(cn_name, "G0H4N".to_string());
+        // Deleted object
+        let dn_object = "CN=G0H4N\\0ADEL:5E9AC1F6-2B0B-4D25-8F45-2D1A0B3F3C11,CN=DELETED OBJECTS,DC=ESSOS,DC=LOCAL";
+        assert_eq!(get_name_from_full_distinguishedname(dn_object), "G0H4N");
     }
 
     #[test]
@@ -909,4 +971,58 @@ mod tests {
         println!("contained_by_dn: {:?}",contained_by_dn);
         assert_eq!(contained_by_dn, "CN=USERS,DC=ESSOS,DC=LOCAL".to_string());
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_affected_computers_for_nested_ous() {
+        let dn_sid: HashMap<String, String> = [
+            ("OU=SERVERS,DC=ESSOS,DC=LOCAL", "OU-GUID-1"),
+            ("OU=SQL,OU=SERVERS,DC=ESSOS,DC=LOCAL", "OU-GUID-2"),
+            ("CN=SRV01,OU=SERVERS,DC=ESSOS,DC=LOCAL", "S-1-5-21-1-2-3-1001"),
+            ("CN=SQL01,OU=SQL,OU=SERVERS,DC=ESSOS,DC=LOCAL", "S-1-5-21-1-2-3-1002"),
+            ("CN=WS01,CN=COMPUTERS,DC=ESSOS,DC=LOCAL", "S-1-5-21-1-2-3-1003"),
+            ("CN=SQLADMIN,OU=SQL,OU=SERVERS,DC=ESSOS,DC=LOCAL", "S-1-5-21-1-2-3-1104"),
+        ].iter().map(|(dn, sid)| (dn.to_string(), sid.to_string())).collect();
+        let sid_type: HashMap<String, String> = [
+            ("OU-GUID-1", "OU"),
+            ("OU-GUID-2", "OU"),
+            ("S-1-5-21-1-2-3-1001", "Computer"),
+            ("S-1-5-21-1-2-3-1002", "Computer"),
+            ("S-1-5-21-1-2-3-1003", "Computer"),
+            ("S-1-5-21-1-2-3-1104", "User"),
+        ].iter().map(|(sid, otype)| (sid.to_string(), otype.to_string())).collect();
+
+        let mut ous = vec![Ou::new(), Ou::new()];
+        *ous[0].object_identifier_mut() = "OU-GUID-1".to_string();
+        *ous[1].object_identifier_mut() = "OU-GUID-2".to_string();
+        add_affected_computers_for_ou(&mut ous, &dn_sid, &sid_type).unwrap();
+
+        let affected = |ou: &Ou| -> Vec<String> {
+            ou.to_json()["GPOChanges"]["AffectedComputers"].as_array().unwrap().iter()
+                .map(|member| member["ObjectIdentifier"].as_str().unwrap().to_string())
+                .collect()
+        };
+        // The parent OU GPOs also apply to the nested OU computers
+        assert_eq!(affected(&ous[0]), vec!["S-1-5-21-1-2-3-1002", "S-1-5-21-1-2-3-1001"]);
+        assert_eq!(affected(&ous[1]), vec!["S-1-5-21-1-2-3-1002"]);
+    }
+
+    #[test]
+    pub fn test_resolve_hosting_computer() {
+        let mut host_resolver = HostResolver::new();
+        host_resolver.add_computer("S-1-5-21-1-2-3-1001", Some("CA01.ESSOS.LOCAL"), Some("CA01$"), &[], &[]);
+
+        // The ManageCertificates heuristic picked another principal
+        let mut cas = vec![
+            EnterpriseCA::builder("CA-GUID-1").dnshostname("ca01.essos.local").hosting_computer("S-1-5-21-1-2-3-1104").build(),
+            EnterpriseCA::builder("CA-GUID-2").dnshostname("ca02.essos.local").hosting_computer("S-1-5-21-1-2-3-1104").build(),
+        ];
+        resolve_hosting_computer(&mut cas, &host_resolver).unwrap();
+
+        assert_eq!(cas[0].hosting_computer(), "S-1-5-21-1-2-3-1001");
+        assert_eq!(cas[0].dnshostname_sid().as_deref(), Some("S-1-5-21-1-2-3-1001"));
+        assert_eq!(cas[0].to_json()["Properties"]["dnshostnamesid"], "S-1-5-21-1-2-3-1001");
+        // Host not collected: the heuristic is kept
+        assert_eq!(cas[1].hosting_computer(), "S-1-5-21-1-2-3-1104");
+        assert!(cas[1].dnshostname_sid().is_none());
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use colored::Colorize;
+use log::{debug, info, warn};
+use regex::RegexBuilder;
+use serde::{Deserialize, Serialize};
+
+use crate::objects::{computer::Computer, user::User};
+
+const DAY: i64 = 86400;
+
+/// Honeypot analysis configuration, loaded from the `--honeypot-config` JSON file.
+/// Every missing value keeps its default.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HoneypotConfig {
+    /// Weight for each indicator, `0` disables it.
+    pub weights: HashMap<String, u32>,
+    /// Case insensitive regex matching deception tools default descriptions.
+    pub description_patterns: Vec<String>,
+    /// Account created before this many days is old.
+    pub old_account_days: i64,
+    /// Password set since less than this many days is recent.
+    pub recent_password_days: i64,
+    /// Number of objects listed in the summary.
+    pub top: usize,
+}
+
+impl Default for HoneypotConfig {
+    fn default() -> HoneypotConfig {
+        HoneypotConfig {
+            weights: INDICATORS.iter().map(|i| (i.id.to_string(), i.weight)).collect(),
+            description_patterns: vec![
+                "honey ?(pot|token|user|account)".to_string(),
+                "decoy".to_string(),
+                "canary ?token".to_string(),
+                "deploy-?deception".to_string(),
+                "do not (use|touch|delete)".to_string(),
+            ],
+            old_account_days: 365,
+            recent_password_days: 90,
+            top: 10,
+        }
+    }
+}
+
+impl HoneypotConfig {
+    /// Load the configuration from a JSON file.
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)?;
+        let mut config: HoneypotConfig = serde_json::from_str(&content)?;
+        // Indicators not set in the file keep their default weight
+        for indicator in INDICATORS {
+            config.weights.entry(indicator.id.to_string()).or_insert(indicator.weight);
+        }
+        Ok(config)
+    }
+}
+
+/// Values used by the indicators, extracted from users and computers.
+#[derive(Debug, Clone, Default)]
+pub struct HoneypotFacts {
+    pub whencreated: i64,
+    pub lastlogon: i64,
+    pub lastlogontimestamp: i64,
+    pub pwdlastset: i64,
+    pub logoncount: i64,
+    pub admincount: bool,
+    pub hasspn: bool,
+    pub description: Option<String>,
+    pub is_computer: bool,
+    pub has_dns_record: bool,
+}
+
+impl HoneypotFacts {
+    fn never_logged_on(&self) -> bool {
+        self.lastlogon <= 0 && self.lastlogontimestamp <= 0 && self.logoncount <= 0
+    }
+}
+
+/// Deception indicator: id used in the configuration, default weight and check.
+pub struct Indicator {
+    pub id: &'static str,
+    pub weight: u32,
+    pub check: fn(&HoneypotFacts, &HoneypotConfig, &[regex::Regex], i64) -> bool,
+}
+
+/// All deception indicators.
+pub const INDICATORS: &[Indicator] = &[
+    // Old account never used but with a password recently changed
+    Indicator {
+        id: "never_logged_on_recent_password",
+        weight: 30,
+        check: |facts, config, _, now| {
+            !facts.is_computer
+                && facts.never_logged_on()
+                && facts.whencreated > 0
+                && facts.whencreated < now - config.old_account_days * DAY
+                && facts.pwdlastset > now - config.recent_password_days * DAY
+        },
+    },
+    // Privileged account never used
+    Indicator {
+        id: "never_logged_on_admin",
+        weight: 30,
+        check: |facts, _, _, _| !facts.is_computer && facts.admincount && facts.never_logged_on(),
+    },
+    // Kerberoastable privileged account never used
+    Indicator {
+        id: "spn_admincount_no_logon",
+        weight: 40,
+        check: |facts, _, _, _| !facts.is_computer && facts.hasspn && facts.admincount && facts.logoncount <= 0,
+    },
+    // Computer resolved by DNS which never authenticated to the domain
+    Indicator {
+        id: "computer_dns_without_logon",
+        weight: 30,
+        check: |facts, _, _, _| facts.is_computer && facts.has_dns_record && facts.lastlogontimestamp <= 0,
+    },
+    // Description left by a deception tool
+    Indicator {
+        id: "deception_description",
+        weight: 40,
+        check: |facts, _, patterns, _| {
+            facts.description.as_ref().is_some_and(|d| patterns.iter().any(|p| p.is_match(d)))
+        },
+    },
+];
+
+/// Function to compute the honeypot score from 0 to 100.
+pub fn honeypot_score(facts: &HoneypotFacts, config: &HoneypotConfig, patterns: &[regex::Regex], now: i64) -> u32 {
+    let score: u32 = INDICATORS
+        .iter()
+        .filter(|indicator| (indicator.check)(facts, config, patterns, now))
+        .map(|indicator| config.weights.get(indicator.id).copied().unwrap_or(indicator.weight))
+        .sum();
+    score.min(100)
+}
+
+/// Function to add the `honeypotscore` property on users and computers, and list the top scoring objects.
+pub fn check_honeypots(
+    config: &HoneypotConfig,
+    vec_users: &mut [User],
+    vec_computers: &mut [Computer],
+    fqdn_ip: &HashMap<String, String>,
+) -> Result<(), Box<dyn Error>> {
+    debug!("Honeypot analysis started");
+    let mut patterns = Vec::new();
+    for pattern in &config.description_patterns {
+        patterns.push(RegexBuilder::new(pattern).case_insensitive(true).build()?);
+    }
+    let now = chrono::Utc::now().timestamp();
+    let mut scores: Vec<(u32, String)> = Vec::new();
+
+    for user in vec_users.iter_mut() {
+        let facts = user.properties().honeypot_facts();
+        let score = honeypot_score(&facts, config, &patterns, now);
+        *user.properties_mut().honeypotscore_mut() = Some(score);
+        scores.push((score, user.properties().name().to_string()));
+    }
+    for computer in vec_computers.iter_mut() {
+        let mut facts = computer.properties().honeypot_facts();
+        facts.has_dns_record = fqdn_ip.get(computer.properties().name()).is_some_and(|ip| !ip.is_empty());
+        let score = honeypot_score(&facts, config, &patterns, now);
+        *computer.properties_mut().honeypotscore_mut() = Some(score);
+        scores.push((score, computer.properties().name().to_string()));
+    }
+
+    scores.retain(|(score, _)| *score > 0);
+    scores.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    if scores.is_empty() {
+        info!("No honeypot indicator found");
+    }
+    for (score, name) in scores.iter().take(config.top) {
+        warn!("Possible honeypot: {} (score {})", name.bold().red(), score);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::json::checker::honeypot::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    #[test]
+    pub fn test_never_logged_on_admin() {
+        let config = HoneypotConfig::default();
+        let facts = HoneypotFacts {
+            whencreated: NOW - 1000 * DAY,
+            pwdlastset: NOW - 10 * DAY,
+            admincount: true,
+            hasspn: true,
+            ..Default::default()
+        };
+        // 30 (recent password) + 30 (admin) + 40 (spn), capped to 100
+        assert_eq!(honeypot_score(&facts, &config, &[], NOW), 100);
+
+        // Same account used every day
+        let used = HoneypotFacts {
+            lastlogontimestamp: NOW - DAY,
+            logoncount: 250,
+            ..facts.clone()
+        };
+        assert_eq!(honeypot_score(&used, &config, &[], NOW), 0);
+
+        // Weights are tuned from the configuration
+        let mut config = HoneypotConfig::default();
+        config.weights.insert("spn_admincount_no_logon".to_string(), 0);
+        config.weights.insert("never_logged_on_recent_password".to_string(), 0);
+        assert_eq!(honeypot_score(&facts, &config, &[], NOW), 30);
+    }
+
+    #[test]
+    pub fn test_deception_description() {
+        let config = HoneypotConfig::default();
+        let patterns: Vec<regex::Regex> = config.description_patterns.iter()
+            .map(|p| RegexBuilder::new(p).case_insensitive(true).build().unwrap())
+            .collect();
+        let facts = HoneypotFacts {
+            description: Some("Created by Deploy-Deception".to_string()),
+            lastlogontimestamp: NOW,
+            logoncount: 1,
+            ..Default::default()
+        };
+        assert_eq!(honeypot_score(&facts, &config, &patterns, NOW), 40);
+
+        let computer = HoneypotFacts {
+            is_computer: true,
+            has_dns_record: true,
+            ..Default::default()
+        };
+        assert_eq!(honeypot_score(&computer, &config, &patterns, NOW), 30);
+    }
+}
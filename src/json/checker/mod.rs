@@ -1,64 +1,70 @@
-use std::collections::HashMap;
 use std::error::Error;
 
 use log::{info,debug};
+use crate::api::{ADResults, DomainMappings};
 use crate::args::Options;
 use crate::enums::{ldaptype::*, templates_enabled_change_displayname_to_sid};
-use crate::objects::{
-    user::User,
-    computer::Computer,
-    group::Group,
-    ou::Ou,
-    container::Container,
-    gpo::Gpo,
-    domain::Domain,
-    fsp::Fsp,
-    trust::Trust,
-    ntauthstore::NtAuthStore,
-    aiaca::AIACA,
-    rootca::RootCA,
-    enterpriseca::EnterpriseCA,
-    certtemplate::CertTemplate,
-    inssuancepolicie::IssuancePolicie,
-};
+use crate::objects::computer::Computer;
+pub mod acefilter;
+pub mod baseline;
 pub mod common;
+pub mod honeypot;
+pub mod roastable;
+pub mod tierzero;
 
 /// Functions to replace and add missing values
 pub fn check_all_result(
     common_args:             &Options,
-    vec_users:               &mut Vec<User>,
-    vec_groups:              &mut Vec<Group>,
-    vec_computers:           &mut [Computer],
-    vec_ous:                 &mut [Ou],
-    vec_domains:             &mut Vec<Domain>,
-    vec_gpos:                &mut [Gpo],
-    _vec_fsps:               &mut [Fsp],
-    vec_containers:          &mut [Container],
-    vec_trusts:              &mut [Trust],
-    vec_ntauthstores:        &mut [NtAuthStore],
-    vec_aiacas:              &mut [AIACA],
-    vec_rootcas:             &mut [RootCA],
-    vec_enterprisecas:       &mut [EnterpriseCA],
-    vec_certtemplates:       &mut [CertTemplate],
-    vec_issuancepolicies:    &mut [IssuancePolicie],
-    dn_sid:                  &HashMap<String, String>,
-    sid_type:                &HashMap<String, String>,
-    fqdn_sid:                &HashMap<String, String>,
-    _fqdn_ip:                &HashMap<String, String>,
+    results:                 &mut ADResults,
 ) -> Result<(), Box<dyn Error>> {
+    let ADResults {
+        users: vec_users,
+        groups: vec_groups,
+        computers: vec_computers,
+        ous: vec_ous,
+        domains: vec_domains,
+        gpos: vec_gpos,
+        containers: vec_containers,
+        trusts: vec_trusts,
+        ntauthstores: vec_ntauthstores,
+        aiacas: vec_aiacas,
+        rootcas: vec_rootcas,
+        enterprisecas: vec_enterprisecas,
+        certtemplates: vec_certtemplates,
+        issuancepolicies: vec_issuancepolicies,
+        mappings,
+        ..
+    } = results;
+    let DomainMappings { dn_sid, sid_type, host_resolver, last_known_parent, .. } = mappings;
     let domain = &common_args.domain;
     info!("Starting checker to replace some values...");
     
     debug!("Replace SID with checker.rs started");
-    common::replace_fqdn_by_sid(Type::User, vec_users, fqdn_sid)?;
-    common::replace_fqdn_by_sid(Type::Computer, vec_computers, fqdn_sid)?;
+    common::replace_fqdn_by_sid(Type::User, vec_users, host_resolver)?;
+    common::replace_fqdn_by_sid(Type::Computer, vec_computers, host_resolver)?;
+    common::resolve_hosting_computer(vec_enterprisecas, host_resolver)?;
     templates_enabled_change_displayname_to_sid(vec_certtemplates, vec_enterprisecas)?;
     common::replace_sid_members(vec_groups, dn_sid, sid_type, vec_trusts)?;
     debug!("Replace SID finished!");
 
     debug!("Adding defaults groups and default users");
-    common::add_default_groups(vec_groups, &vec_computers, domain.to_owned())?;
-    common::add_default_users(vec_users, domain.to_owned())?;
+    if common_args.gc || common_args.forest {
+        // Global Catalog or --forest: the default groups and users of each domain of the forest
+        let mut domains: Vec<String> = vec_computers.iter().map(|computer| computer.properties().domain().to_owned()).collect();
+        domains.sort();
+        domains.dedup();
+        for domain in domains {
+            let computers: Vec<Computer> = vec_computers.iter()
+                .filter(|computer| computer.properties().domain() == &domain)
+                .cloned()
+                .collect();
+            common::add_default_groups(vec_groups, &computers, domain.to_owned())?;
+            common::add_default_users(vec_users, domain)?;
+        }
+    } else {
+        common::add_default_groups(vec_groups, &vec_computers, domain.to_owned())?;
+        common::add_default_users(vec_users, domain.to_owned())?;
+    }
     debug!("Defaults groups and default users added!");
 
     debug!("Adding PrincipalType for ACEs started");
@@ -86,18 +92,18 @@ pub fn check_all_result(
     debug!("ChildObject members added!");
 
     debug!("Adding ContainedBy value started");
-    common::add_contained_by_for(vec_users, dn_sid, sid_type)?;
-    common::add_contained_by_for(vec_groups, dn_sid, sid_type)?;
-    common::add_contained_by_for(vec_computers, dn_sid, sid_type)?;
-    common::add_contained_by_for(vec_gpos, dn_sid, sid_type)?;
-    common::add_contained_by_for(vec_ous, dn_sid, sid_type)?;
-    common::add_contained_by_for(vec_containers, dn_sid, sid_type)?;
-    common::add_contained_by_for(vec_ntauthstores, dn_sid, sid_type)?;
-    common::add_contained_by_for(vec_aiacas, dn_sid, sid_type)?;
-    common::add_contained_by_for(vec_rootcas, dn_sid, sid_type)?;
-    common::add_contained_by_for(vec_enterprisecas, dn_sid, sid_type)?;
-    common::add_contained_by_for(vec_certtemplates, dn_sid, sid_type)?;
-    common::add_contained_by_for(vec_issuancepolicies, dn_sid, sid_type)?;
+    common::add_contained_by_for(vec_users, dn_sid, sid_type, last_known_parent)?;
+    common::add_contained_by_for(vec_groups, dn_sid, sid_type, last_known_parent)?;
+    common::add_contained_by_for(vec_computers, dn_sid, sid_type, last_known_parent)?;
+    common::add_contained_by_for(vec_gpos, dn_sid, sid_type, last_known_parent)?;
+    common::add_contained_by_for(vec_ous, dn_sid, sid_type, last_known_parent)?;
+    common::add_contained_by_for(vec_containers, dn_sid, sid_type, last_known_parent)?;
+    common::add_contained_by_for(vec_ntauthstores, dn_sid, sid_type, last_known_parent)?;
+    common::add_contained_by_for(vec_aiacas, dn_sid, sid_type, last_known_parent)?;
+    common::add_contained_by_for(vec_rootcas, dn_sid, sid_type, last_known_parent)?;
+    common::add_contained_by_for(vec_enterprisecas, dn_sid, sid_type, last_known_parent)?;
+    common::add_contained_by_for(vec_certtemplates, dn_sid, sid_type, last_known_parent)?;
+    common::add_contained_by_for(vec_issuancepolicies, dn_sid, sid_type, last_known_parent)?;
 
     debug!("ContainedBy value added!");
 
@@ -116,6 +122,12 @@ pub fn check_all_result(
         common::add_trustdomain(vec_domains, vec_trusts)?;
         debug!("Trust domain relation added!");
     }
+
+    // Disabled accounts keep their node and ACEs, not their delegation edges
+    if !common_args.include_disabled {
+        let count = common::remove_disabled_delegation(vec_users, vec_computers)?;
+        debug!("Delegation edges of {count} disabled accounts removed");
+    }
     info!("Checking and replacing some values finished!");
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,195 @@
+//! Roastable accounts report
+//!
+//! List the Kerberoastable users (with a service principal name, except krbtgt) and the AS-REP roastable
+//! users (`dontreqpreauth`) of each domain, the privileged ones (`admincount`) first.
+//!
+//! The KDC refuses the tickets of a disabled account, so the disabled users are only counted, unless
+//! `--include-disabled` is set. They are still in the output files with `enabled: false`.
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use colored::Colorize;
+use log::info;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::api::ADResults;
+use crate::objects::common::LdapObject;
+
+/// One roastable user.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RoastableAccount {
+    pub object_identifier: String,
+    pub name: String,
+    pub enabled: bool,
+    pub admincount: bool,
+    /// Kerberoastable: a TGS can be requested for one of its service principal names.
+    pub kerberoastable: bool,
+    /// AS-REP roastable: no Kerberos pre-authentication.
+    pub asreproastable: bool,
+    pub serviceprincipalnames: Vec<String>,
+}
+
+/// Roastable users of one domain.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RoastableReport {
+    pub domain: String,
+    /// Privileged accounts first, then by name.
+    pub accounts: Vec<RoastableAccount>,
+    /// Disabled roastable users left out of `accounts`.
+    pub excluded_disabled: usize,
+}
+
+/// Function to list the roastable users of each domain, without the disabled ones unless `include_disabled`.
+pub fn roastable_accounts(results: &ADResults, include_disabled: bool) -> Vec<RoastableReport> {
+    let mut domains: BTreeMap<String, RoastableReport> = BTreeMap::new();
+    for user in &results.users {
+        let json = user.to_json();
+        let properties = &json["Properties"];
+        let flag = |name: &str| properties[name].as_bool().unwrap_or(false);
+        let is_krbtgt = properties["samaccountname"].as_str().is_some_and(|name| name.eq_ignore_ascii_case("krbtgt"))
+            || user.get_object_identifier().ends_with("-502");
+        let kerberoastable = flag("hasspn") && !is_krbtgt;
+        let asreproastable = flag("dontreqpreauth");
+        if !kerberoastable && !asreproastable {
+            continue
+        }
+        let domain = properties["domain"].as_str().unwrap_or_default().to_uppercase();
+        let report = domains.entry(domain.to_owned()).or_insert_with(|| RoastableReport {
+            domain,
+            accounts: Vec::new(),
+            excluded_disabled: 0,
+        });
+        let enabled = flag("enabled");
+        if !enabled && !include_disabled {
+            report.excluded_disabled += 1;
+            continue
+        }
+        report.accounts.push(RoastableAccount {
+            object_identifier: user.get_object_identifier().to_owned(),
+            name: properties["name"].as_str().unwrap_or_default().to_owned(),
+            enabled,
+            admincount: flag("admincount"),
+            kerberoastable,
+            asreproastable,
+            serviceprincipalnames: properties["serviceprincipalnames"].as_array().into_iter().flatten()
+                .filter_map(Value::as_str)
+                .map(str::to_owned)
+                .collect(),
+        });
+    }
+    domains.into_values()
+        .map(|mut report| {
+            report.accounts.sort_by(|a, b| b.admincount.cmp(&a.admincount).then_with(|| a.name.cmp(&b.name)));
+            report
+        })
+        .collect()
+}
+
+/// Function to show the roastable users of each domain.
+pub fn print_roastable_accounts(reports: &[RoastableReport]) {
+    for report in reports {
+        info!(
+            "Roastable users for {}: {} ({} disabled not shown)",
+            report.domain.bold(),
+            report.accounts.len().to_string().bold().red(),
+            report.excluded_disabled
+        );
+        for account in &report.accounts {
+            let kind = match (account.kerberoastable, account.asreproastable) {
+                (true, true) => "Kerberoast, AS-REP roast",
+                (true, false) => "Kerberoast",
+                _ => "AS-REP roast",
+            };
+            let admin = if account.admincount { " admincount" } else { "" };
+            let disabled = if account.enabled { "" } else { " disabled" };
+            info!("  {}{admin}{disabled}: {kind}", account.name.bold());
+        }
+    }
+}
+
+/// Function to write the roastable users in `{path}/{domain}_roastable.json`.
+pub fn write_roastable_accounts(reports: &[RoastableReport], path: &str, domain: &str) -> Result<String, Box<dyn Error>> {
+    std::fs::create_dir_all(path)?;
+    let file = format!("{}/{}_roastable.json", path.trim_end_matches('/'), domain.to_lowercase());
+    std::fs::write(&file, serde_json::to_string_pretty(reports)?)?;
+    info!("Roastable users report written to {file}");
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::args::Options;
+    use crate::json::checker::roastable::*;
+    use crate::ldap::LdapSearchEntry;
+    use crate::test_support::{entry_with_sid, parse_entries};
+
+    const DOMAIN_SID: &str = "S-1-5-21-4000000001-4000000002-4000000003";
+
+    fn entries() -> Vec<LdapSearchEntry> {
+        let user = "top|person|organizationalPerson|user";
+        vec![
+            entry_with_sid("DC=CONTOSO,DC=LOCAL", DOMAIN_SID, &[("objectClass", "top|domain|domainDNS"), ("name", "contoso")]),
+            entry_with_sid("CN=krbtgt,CN=Users,DC=CONTOSO,DC=LOCAL", &format!("{DOMAIN_SID}-502"), &[
+                ("objectClass", user), ("sAMAccountName", "krbtgt"), ("userAccountControl", "514"),
+                ("servicePrincipalName", "kadmin/changepw"),
+            ]),
+            // Disabled (514) kerberoastable admin with a constrained delegation
+            entry_with_sid("CN=svc_old,CN=Users,DC=CONTOSO,DC=LOCAL", &format!("{DOMAIN_SID}-1104"), &[
+                ("objectClass", user), ("sAMAccountName", "svc_old"), ("userAccountControl", "514"), ("adminCount", "1"),
+                ("servicePrincipalName", "MSSQLSvc/sql01.contoso.local:1433"),
+                ("msDS-AllowedToDelegateTo", "cifs/dc01.contoso.local"),
+            ]),
+            entry_with_sid("CN=svc_sql,CN=Users,DC=CONTOSO,DC=LOCAL", &format!("{DOMAIN_SID}-1105"), &[
+                ("objectClass", user), ("sAMAccountName", "svc_sql"), ("userAccountControl", "512"),
+                ("servicePrincipalName", "MSSQLSvc/sql02.contoso.local:1433"),
+            ]),
+            // DontReqPreauth (4194304) and normal account
+            entry_with_sid("CN=bob,CN=Users,DC=CONTOSO,DC=LOCAL", &format!("{DOMAIN_SID}-1106"), &[
+                ("objectClass", user), ("sAMAccountName", "bob"), ("userAccountControl", "4194816"),
+            ]),
+        ]
+    }
+
+    fn user(results: &ADResults, rid: &str) -> serde_json::Value {
+        let sid = format!("{DOMAIN_SID}-{rid}");
+        results.users.iter().find(|user| user.get_object_identifier() == &sid).map(|user| user.to_json()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_disabled_kerberoastable_admin() {
+        let options = Options { domain: "contoso.local".to_string(), ..Default::default() };
+        let results = parse_entries(entries(), &options).await;
+
+        // Still a node, flagged as disabled with its raw properties
+        let svc_old = user(&results, "1104");
+        assert_eq!(svc_old["Properties"]["enabled"], false);
+        assert_eq!(svc_old["Properties"]["hasspn"], true);
+        assert_eq!(svc_old["Properties"]["admincount"], true);
+        assert_eq!(svc_old["AllowedToDelegate"], serde_json::json!([]));
+        assert_eq!(user(&results, "1105")["Properties"]["enabled"], true);
+
+        let reports = roastable_accounts(&results, false);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].domain, "CONTOSO.LOCAL");
+        let names: Vec<&str> = reports[0].accounts.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["BOB@CONTOSO.LOCAL", "SVC_SQL@CONTOSO.LOCAL"]);
+        assert!(reports[0].accounts[0].asreproastable && !reports[0].accounts[0].kerberoastable);
+        // krbtgt is not counted
+        assert_eq!(reports[0].excluded_disabled, 1);
+
+        let reports = roastable_accounts(&results, true);
+        assert_eq!(reports[0].accounts[0].name, "SVC_OLD@CONTOSO.LOCAL");
+        assert!(!reports[0].accounts[0].enabled);
+        assert_eq!(reports[0].excluded_disabled, 0);
+    }
+
+    #[tokio::test]
+    async fn test_include_disabled_keeps_delegation() {
+        let options = Options { domain: "contoso.local".to_string(), include_disabled: true, ..Default::default() };
+        let results = parse_entries(entries(), &options).await;
+        let delegation = &user(&results, "1104")["AllowedToDelegate"];
+        assert_eq!(delegation.as_array().map(Vec::len), Some(1));
+    }
+}
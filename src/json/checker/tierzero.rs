@@ -0,0 +1,211 @@
+//! Tier zero ACL exposure
+//!
+//! Count the distinct non-privileged principals holding a dangerous right (GenericAll, WriteDacl,
+//! WriteOwner, AddMember, AllExtendedRights) over a tier zero object, per domain.
+//!
+//! - tier zero principals: the highvalue users, groups and computers, and all their nested members
+//! - tier zero objects: the tier zero principals, the domain heads and the ADCS objects
+//! - a group holding a right gives it to all its nested members, each one is counted with the group in `via`
+//! - disabled users and computers are not counted, unless `--include-disabled` is set
+//!
+//! Run once the checker added the high-value marks and the ACE principal types.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+
+use colored::Colorize;
+use log::info;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::api::ADResults;
+use crate::enums::sid::{get_well_known_name, is_highvalue_sid};
+use crate::objects::common::LdapObject;
+
+/// Rights giving the control of the target object.
+pub const DANGEROUS_RIGHTS: &[&str] = &["GenericAll", "WriteDacl", "WriteOwner", "AddMember", "AllExtendedRights"];
+
+/// Offenders shown on the console for each domain.
+const CONSOLE_TOP: usize = 10;
+
+/// One non-privileged principal controlling tier zero objects.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TierZeroOffender {
+    pub object_identifier: String,
+    pub name: String,
+    pub object_type: String,
+    /// Names of the tier zero objects reachable with a dangerous right.
+    pub reachable: Vec<String>,
+    /// Dangerous rights held.
+    pub rights: Vec<String>,
+    /// Groups giving the rights, empty for the rights held directly.
+    pub via: Vec<String>,
+}
+
+/// Tier zero exposure of one domain.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TierZeroExposure {
+    pub domain: String,
+    /// Distinct non-privileged principals with a dangerous right over a tier zero object.
+    pub exposed_principals: usize,
+    pub tier_zero_objects: usize,
+    /// Ranked by number of reachable objects, then by name.
+    pub offenders: Vec<TierZeroOffender>,
+}
+
+/// Object fields used by the pass, from the output json.
+#[derive(Debug, Default)]
+struct Node {
+    name: String,
+    object_type: String,
+    domain: String,
+    highvalue: bool,
+    enabled: bool,
+    members: Vec<String>,
+    aces: Vec<(String, String)>,
+}
+
+fn add_nodes<T: LdapObject>(nodes: &mut BTreeMap<String, Node>, objects: &[T], object_type: &str) {
+    for object in objects {
+        let json = object.to_json();
+        let text = |value: &Value| value.as_str().unwrap_or_default().to_owned();
+        let name = text(&json["Properties"]["name"]);
+        // The default groups added by the checker only have a NAME@DOMAIN name
+        let domain = match text(&json["Properties"]["domain"]) {
+            domain if domain.is_empty() => name.rsplit_once('@').map(|(_, domain)| domain.to_owned()).unwrap_or_default(),
+            domain => domain,
+        };
+        let node = Node {
+            name,
+            object_type: object_type.to_owned(),
+            domain: domain.to_uppercase(),
+            highvalue: json["Properties"]["highvalue"].as_bool().unwrap_or(false),
+            // Only users and computers have the property
+            enabled: json["Properties"]["enabled"].as_bool().unwrap_or(true),
+            members: json["Members"].as_array().into_iter().flatten()
+                .map(|member| text(&member["ObjectIdentifier"]))
+                .collect(),
+            aces: json["Aces"].as_array().into_iter().flatten()
+                .map(|ace| (text(&ace["PrincipalSID"]), text(&ace["RightName"])))
+                .collect(),
+        };
+        nodes.insert(object.get_object_identifier().to_owned(), node);
+    }
+}
+
+/// Function to get a group and all its nested members.
+fn nested_members(nodes: &BTreeMap<String, Node>, group: &str) -> BTreeSet<String> {
+    let mut found = BTreeSet::from([group.to_owned()]);
+    let mut pending = vec![group.to_owned()];
+    while let Some(id) = pending.pop() {
+        for member in nodes.get(&id).map(|node| node.members.as_slice()).unwrap_or_default() {
+            if found.insert(member.to_owned()) {
+                pending.push(member.to_owned());
+            }
+        }
+    }
+    found
+}
+
+/// Function to compute the tier zero ACL exposure of each domain, without the disabled offenders unless `include_disabled`.
+pub fn tier_zero_exposure(results: &ADResults, include_disabled: bool) -> Vec<TierZeroExposure> {
+    let mut nodes = BTreeMap::new();
+    add_nodes(&mut nodes, &results.users, "User");
+    add_nodes(&mut nodes, &results.groups, "Group");
+    add_nodes(&mut nodes, &results.computers, "Computer");
+    add_nodes(&mut nodes, &results.domains, "Domain");
+    add_nodes(&mut nodes, &results.enterprisecas, "EnterpriseCA");
+    add_nodes(&mut nodes, &results.rootcas, "RootCA");
+    add_nodes(&mut nodes, &results.aiacas, "AIACA");
+    add_nodes(&mut nodes, &results.ntauthstores, "NTAuthStore");
+    add_nodes(&mut nodes, &results.certtemplates, "CertTemplate");
+
+    // Tier zero principals with their nested members
+    let mut tier_zero = BTreeSet::new();
+    for (id, node) in &nodes {
+        if matches!(node.object_type.as_str(), "User" | "Group" | "Computer") && (node.highvalue || is_highvalue_sid(id)) {
+            tier_zero.extend(nested_members(&nodes, id));
+        }
+    }
+    let is_tier_zero = |id: &str| tier_zero.contains(id) || is_highvalue_sid(id);
+    let name_of = |id: &str| nodes.get(id).map(|node| node.name.to_owned())
+        .filter(|name| !name.is_empty())
+        .or_else(|| get_well_known_name(id))
+        .unwrap_or_else(|| id.to_owned());
+
+    // Domain to offender to (reachable objects, rights, via groups)
+    type Exposure = (BTreeSet<String>, BTreeSet<String>, BTreeSet<String>);
+    let mut domains: BTreeMap<String, (usize, HashMap<String, Exposure>)> = BTreeMap::new();
+    for (id, target) in &nodes {
+        let is_target = is_tier_zero(id) || !matches!(target.object_type.as_str(), "User" | "Group" | "Computer");
+        if !is_target {
+            continue
+        }
+        let (count, offenders) = domains.entry(target.domain.to_owned()).or_default();
+        *count += 1;
+        for (principal, right) in &target.aces {
+            if !DANGEROUS_RIGHTS.contains(&right.as_str()) {
+                continue
+            }
+            for holder in nested_members(&nodes, principal) {
+                let disabled = nodes.get(&holder).is_some_and(|node| !node.enabled);
+                if is_tier_zero(&holder) || (disabled && !include_disabled) {
+                    continue
+                }
+                let (reachable, rights, via) = offenders.entry(holder.to_owned()).or_default();
+                reachable.insert(name_of(id));
+                rights.insert(right.to_owned());
+                if &holder != principal {
+                    via.insert(name_of(principal));
+                }
+            }
+        }
+    }
+
+    domains.into_iter()
+        .map(|(domain, (tier_zero_objects, offenders))| {
+            let mut offenders: Vec<TierZeroOffender> = offenders.into_iter()
+                .map(|(id, (reachable, rights, via))| TierZeroOffender {
+                    name: name_of(&id),
+                    object_type: nodes.get(&id).map(|node| node.object_type.to_owned()).unwrap_or("Base".to_owned()),
+                    object_identifier: id,
+                    reachable: reachable.into_iter().collect(),
+                    rights: rights.into_iter().collect(),
+                    via: via.into_iter().collect(),
+                })
+                .collect();
+            offenders.sort_by(|a, b| b.reachable.len().cmp(&a.reachable.len()).then_with(|| a.name.cmp(&b.name)));
+            TierZeroExposure { domain, exposed_principals: offenders.len(), tier_zero_objects, offenders }
+        })
+        .collect()
+}
+
+/// Function to show the exposure and the top offenders of each domain.
+pub fn print_tier_zero_exposure(exposures: &[TierZeroExposure]) {
+    for exposure in exposures {
+        info!(
+            "Tier zero exposure for {}: {} non-privileged principals control {} tier zero objects",
+            exposure.domain.bold(),
+            exposure.exposed_principals.to_string().bold().red(),
+            exposure.tier_zero_objects
+        );
+        for (rank, offender) in exposure.offenders.iter().take(CONSOLE_TOP).enumerate() {
+            info!(
+                "  #{} {} ({}) {}: {}",
+                rank + 1,
+                offender.name.bold(),
+                offender.object_type,
+                offender.rights.join(", "),
+                offender.reachable.join(", ")
+            );
+        }
+    }
+}
+
+/// Function to write the exposure in `{path}/{domain}_tier_zero_exposure.json`.
+pub fn write_tier_zero_exposure(exposures: &[TierZeroExposure], path: &str, domain: &str) -> Result<String, Box<dyn Error>> {
+    std::fs::create_dir_all(path)?;
+    let file = format!("{}/{}_tier_zero_exposure.json", path.trim_end_matches('/'), domain.to_lowercase());
+    std::fs::write(&file, serde_json::to_string_pretty(exposures)?)?;
+    info!("Tier zero exposure report written to {file}");
+    Ok(file)
+}
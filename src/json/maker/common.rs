@@ -1,105 +1,118 @@
-use serde_json::value::Value;
-
-use std::collections::HashMap;
 use colored::Colorize;
-use log::{info, debug, trace};
-
-use std::fs;
-use std::fs::File;
-use std::io::{Seek, Write};
-use zip::result::ZipResult;
-use zip::write::{SimpleFileOptions, ZipWriter};
+use log::{info, debug, warn};
+use std::error::Error;
 
-extern crate zip;
-use crate::args::{Options, RUSTHOUND_VERSION};
-use crate::objects::common::{FinalJson, Meta, LdapObject};
+use crate::args::RUSTHOUND_VERSION;
+use crate::buildinfo::build_info;
+use crate::coverage::{is_coverage_enabled, record_object};
+use crate::provenance;
+use crate::json::maker::identifiers::canonicalize_identifiers;
+use crate::json::maker::sink::OutputSink;
+use crate::methods::{strip_object, CollectionMethod};
+use crate::objects::common::{Meta, LdapObject};
+use crate::rootdse::RootDse;
 
 /// Current Bloodhound version 4.3+
 pub const BLOODHOUND_VERSION_4: i8 = 6;
 
-// Function to create the .json file.
-pub fn add_file<T: LdapObject>(
-   datetime: &String,
-   name: String,
-   domain_format: &String,
+/// Collection state recorded in the meta of every output file.
+#[derive(Debug, Default)]
+pub struct MetaStatus<'a> {
+   /// Phase reached when the run was interrupted.
+   pub partial: Option<&'a str>,
+   /// ACE filter applied by the checker.
+   pub ace_filter: Option<&'a str>,
+   /// Warnings about the collection completeness.
+   pub warnings: &'a [String],
+   /// Collection categories skipped by the deadline.
+   pub skipped: &'a [String],
+   /// Domain controllers used for the LDAP collection.
+   pub domain_controllers: &'a [String],
+   /// Authentication of the LDAP bind.
+   pub auth_mechanism: Option<&'a str>,
+   /// rootDSE of the DC bound.
+   pub root_dse: Option<&'a RootDse>,
+   /// Collection methods selected.
+   pub methods: CollectionMethod,
+   /// Types written even without object, the ones left out with `--empty-skipped`.
+   pub empty_types: &'a [String],
+}
+
+/// Function to write all objects of one type in each output sink.
+pub fn add_objects<T: LdapObject>(
+   sinks: &mut [Box<dyn OutputSink>],
+   name: &str,
    vec_json: Vec<T>,
-   json_result: &mut HashMap<String, String>,
-   common_args: &Options, 
- ) -> std::io::Result<()>
- {
-  if !vec_json.is_empty() {
-    debug!("Making {}.json",&name);
-  
-    let path = &common_args.path;
-    let zip = common_args.zip;
-    let count = vec_json.len();
-  
-    let mut result: Vec<Value> = Vec::new();
-    for object in vec_json {
-        result.push(object.to_json().to_owned());
-    }
-    // Prepare template and get result in const var
-    let final_json = FinalJson::new(
-        result,
-        Meta::new(
-          000000_i32,
-          name.to_owned(),
-          count as i32,
-          BLOODHOUND_VERSION_4,
-          format!("RustHound-CE v{}",RUSTHOUND_VERSION.to_owned())
-        )
-    );
-  
-    info!("{} {} parsed!", count.to_string().bold(),&name);
-  
-    // result
-    fs::create_dir_all(path)?;
-  
-    // Create json file if isn't zip
-    if ! zip 
-    {
-        let final_path = format!("{}/{}_{}_{}.json",path,datetime,domain_format,name);
-        fs::write(&final_path, serde_json::to_string(&final_json)?)?;
-        info!("{} created!",final_path.bold());
-    }
-    else
-    {
-        json_result.insert(format!("{}_{}_{}.json",datetime,domain_format,name).to_string(),serde_json::to_string(&final_json)?);
-    }
-  }
-  Ok(())
- }
- 
- /// Function to compress the JSON files into a zip archive
- pub fn make_a_zip(
-   datetime: &String,
-   domain: &String,
-   path: &String,
-   json_result: &HashMap<String, String>
- ){
-   let final_path = format!("{}/{}_{}_rusthound-ce.zip",path,datetime,domain);
-   let mut file = File::create(&final_path).expect("Couldn't create file");
-   create_zip_archive(&mut file, json_result).expect("Couldn't create archive");
- 
-   info!("{} created!",&final_path.bold());
- }
- 
- 
- fn create_zip_archive<T: Seek + Write>(zip_filename: &mut T,json_result: &HashMap<String, String>) -> ZipResult<()> {
-   let mut writer = ZipWriter::new(zip_filename);
-   // json file by json file
-   trace!("Making the ZIP file");
- 
-   for file in json_result
-   {
-      let filename = file.0;
-      let content = file.1;
-      trace!("Adding file {}",filename.bold());
-      let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
-      writer.start_file(filename, options)?;
-      writer.write_all(content.as_bytes())?;
+   status: &MetaStatus,
+) -> Result<(), Box<dyn Error>>
+{
+   if vec_json.is_empty() && !status.empty_types.iter().any(|empty| empty == name) {
+      return Ok(())
+   }
+   debug!("Making {}.json",&name);
+
+   let count = vec_json.len();
+   let mut meta = Meta::new(
+      status.methods.collected().bits() as i32,
+      name.to_owned(),
+      count as i32,
+      BLOODHOUND_VERSION_4,
+      format!("RustHound-CE v{}",RUSTHOUND_VERSION.to_owned())
+   );
+   meta.set_build(build_info());
+   if let Some(phase) = status.partial {
+      meta.set_partial(phase);
+   }
+   if let Some(filter) = status.ace_filter {
+      meta.set_ace_filter(filter);
+   }
+   if !status.warnings.is_empty() {
+      meta.set_warnings(status.warnings);
+   }
+   if !status.skipped.is_empty() {
+      meta.set_skipped(status.skipped);
+   }
+   if !status.domain_controllers.is_empty() {
+      meta.set_domain_controllers(status.domain_controllers);
+   }
+   if let Some(mechanism) = status.auth_mechanism {
+      meta.set_auth_mechanism(mechanism);
+   }
+   if let Some(root_dse) = status.root_dse {
+      meta.set_root_dse(root_dse);
+   }
+
+   info!("{} {} parsed!", count.to_string().bold(),&name);
+
+   for sink in sinks.iter_mut() {
+      sink.begin_type(name, &meta)?;
+   }
+   // Invalid identifiers with the DN of their object
+   let mut invalid = Vec::new();
+   for object in &vec_json {
+      let mut json = object.to_json();
+      strip_object(&mut json, status.methods);
+      let errors = canonicalize_identifiers(&mut json);
+      if !errors.is_empty() {
+         let dn = json["Properties"]["distinguishedname"].as_str().unwrap_or(object.get_object_identifier());
+         invalid.push(format!("{dn}: {}", errors.join(", ")));
+      }
+      if is_coverage_enabled() {
+         record_object(&json);
+      }
+      if provenance::is_provenance_enabled() {
+         provenance::record_object(&json);
+      }
+      for sink in sinks.iter_mut() {
+         sink.write_object(&json)?;
+      }
+   }
+   for sink in sinks.iter_mut() {
+      sink.end_type()?;
+   }
+   if !invalid.is_empty() {
+      warn!("{} {} with an identifier neither SID nor GUID, written as is:", invalid.len().to_string().bold().yellow(), &name);
+      invalid.iter().for_each(|line| warn!("  {line}"));
    }
- 
-   writer.finish()?;
    Ok(())
- }
\ No newline at end of file
+}
@@ -0,0 +1,196 @@
+//! Canonical object identifiers
+//!
+//! BloodHound CE matches the nodes on their exact identifier, so `{5b0d...}` and `5B0D...` or a SID
+//! with a stray space are two different nodes. Before writing an object the maker rewrites every
+//! identifier field to its canonical form:
+//!
+//! - GUID: uppercase, without braces (`5B0D5F77-1E5A-4F5B-9C43-31F5B6B7C3A1`)
+//! - SID: uppercase, without whitespace (`S-1-5-21-...`), well-known SIDs keep their domain prefix (`CONTOSO.LOCAL-S-1-5-32-544`)
+//!
+//! Identifiers matching neither pattern are written as is and reported with the DN of their object.
+use serde_json::value::Value;
+
+use crate::enums::regex::{CANONICAL_GUID_RE, CANONICAL_SID_RE};
+
+/// Json properties holding a SID or a GUID, at any depth of an output object.
+pub const IDENTIFIER_KEYS: &[&str] = &[
+   "ObjectIdentifier",
+   "PrincipalSID",
+   "GUID",
+   "UserSID",
+   "ComputerSID",
+   "DomainSID",
+   "PrimaryGroupSID",
+   "TargetDomainSid",
+   "HostingComputer",
+   "domainsid",
+   "sidhistory",
+];
+
+/// Function to get the canonical form of a SID or a GUID: no whitespace, no braces, uppercase.
+pub fn canonicalize_identifier(identifier: &str) -> String {
+   let compact: String = identifier.chars().filter(|c| !c.is_whitespace()).collect();
+   let compact = compact.strip_prefix('{')
+      .and_then(|guid| guid.strip_suffix('}'))
+      .unwrap_or(&compact);
+   compact.to_uppercase()
+}
+
+/// Function to check if an identifier is a canonical SID or GUID.
+pub fn is_canonical_identifier(identifier: &str) -> bool {
+   CANONICAL_SID_RE.is_match(identifier) || CANONICAL_GUID_RE.is_match(identifier)
+}
+
+/// Function to canonicalize all identifiers of one output object.
+/// Return the invalid ones like `PrincipalSID 'value'`, left unchanged.
+pub fn canonicalize_identifiers(object: &mut Value) -> Vec<String> {
+   let mut invalid = Vec::new();
+   canonicalize_value(object, &mut invalid);
+   invalid
+}
+
+fn canonicalize_value(value: &mut Value, invalid: &mut Vec<String>) {
+   match value {
+      Value::Object(properties) => {
+         for (key, value) in properties.iter_mut() {
+            if IDENTIFIER_KEYS.contains(&key.as_str()) {
+               canonicalize_field(key, value, invalid);
+            } else {
+               canonicalize_value(value, invalid);
+            }
+         }
+      }
+      Value::Array(values) => values.iter_mut().for_each(|value| canonicalize_value(value, invalid)),
+      _ => {}
+   }
+}
+
+fn canonicalize_field(key: &str, value: &mut Value, invalid: &mut Vec<String>) {
+   match value {
+      Value::String(identifier) => {
+         // Not resolved, like the domainsid of an unknown domain
+         if identifier.is_empty() {
+            return
+         }
+         let canonical = canonicalize_identifier(identifier);
+         if is_canonical_identifier(&canonical) {
+            *identifier = canonical;
+         } else {
+            invalid.push(format!("{key} '{identifier}'"));
+         }
+      }
+      Value::Array(values) => values.iter_mut().for_each(|value| canonicalize_field(key, value, invalid)),
+      _ => canonicalize_value(value, invalid),
+   }
+}
+
+#[cfg(test)]
+mod tests {
+
+   use serde_json::json;
+
+   use crate::json::maker::identifiers::*;
+
+   /// Small xorshift generator, the same seed always gives the same inputs.
+   struct Generator(u64);
+
+   impl Generator {
+      fn next(&mut self) -> u64 {
+         self.0 ^= self.0 << 13;
+         self.0 ^= self.0 >> 7;
+         self.0 ^= self.0 << 17;
+         self.0
+      }
+      fn below(&mut self, max: u64) -> u64 {
+         self.next() % max
+      }
+      fn sid(&mut self) -> String {
+         let mut sid = format!("S-1-{}", self.below(16));
+         for _ in 0..=self.below(6) {
+            sid += &format!("-{}", self.below(u32::MAX as u64));
+         }
+         sid
+      }
+      fn guid(&mut self) -> String {
+         let hex = |generator: &mut Generator, len: usize| -> String {
+            (0..len).map(|_| format!("{:X}", generator.below(16))).collect()
+         };
+         format!("{}-{}-{}-{}-{}", hex(self, 8), hex(self, 4), hex(self, 4), hex(self, 4), hex(self, 12))
+      }
+      /// Random case, whitespace and braces around a canonical identifier.
+      fn mess(&mut self, canonical: &str, braces: bool) -> String {
+         let mut messy = String::new();
+         for c in canonical.chars() {
+            if self.below(8) == 0 {
+               messy.push([' ', '\t', '\n', '\u{a0}'][self.below(4) as usize]);
+            }
+            messy.push(if self.below(2) == 0 { c.to_ascii_lowercase() } else { c });
+         }
+         if braces && self.below(2) == 0 {
+            messy = format!("{{{messy}}}");
+         }
+         format!("{}{messy}{}", " ".repeat(self.below(3) as usize), " ".repeat(self.below(3) as usize))
+      }
+   }
+
+   #[test]
+   pub fn test_canonicalize_identifier() {
+      assert_eq!(canonicalize_identifier("{5b0d5f77-1e5a-4f5b-9c43-31f5b6b7c3a1}"), "5B0D5F77-1E5A-4F5B-9C43-31F5B6B7C3A1");
+      assert_eq!(canonicalize_identifier(" s-1-5-21-1004336348-1177238915-682003330-512 "), "S-1-5-21-1004336348-1177238915-682003330-512");
+      assert_eq!(canonicalize_identifier("contoso.local-S-1-5-32-544"), "CONTOSO.LOCAL-S-1-5-32-544");
+      assert!(is_canonical_identifier("CONTOSO.LOCAL-S-1-5-32-544"));
+      assert!(!is_canonical_identifier("CN=ADMINISTRATORS,CN=BUILTIN,DC=CONTOSO,DC=LOCAL"));
+      assert!(!is_canonical_identifier("5B0D5F77-1E5A-4F5B-9C43"));
+   }
+
+   #[test]
+   pub fn test_messy_identifiers_property() {
+      let mut generator = Generator(0x05ee_d1d5);
+      for _ in 0..5000 {
+         let (canonical, braces) = if generator.below(2) == 0 {
+            (generator.sid(), false)
+         } else {
+            (generator.guid(), true)
+         };
+         let messy = generator.mess(&canonical, braces);
+         assert_eq!(canonicalize_identifier(&messy), canonical, "{messy:?}");
+         assert!(is_canonical_identifier(&canonical));
+         // Idempotent
+         assert_eq!(canonicalize_identifier(&canonical), canonical);
+      }
+   }
+
+   #[test]
+   pub fn test_canonicalize_object_identifiers() {
+      let mut generator = Generator(42);
+      for _ in 0..500 {
+         let (sid, group, guid) = (generator.sid(), generator.sid(), generator.guid());
+         let mut object = json!({
+            "ObjectIdentifier": generator.mess(&sid, false),
+            "Properties": {"domainsid": generator.mess(&group, false), "sidhistory": [generator.mess(&group, false)]},
+            "Members": [{"ObjectIdentifier": generator.mess(&group, false), "ObjectType": "Group"}],
+            "Aces": [{"PrincipalSID": generator.mess(&group, false), "RightName": "GenericAll"}],
+            "ContainedBy": {"ObjectIdentifier": generator.mess(&guid, true), "ObjectType": "OU"},
+            "Links": [{"GUID": generator.mess(&guid, true), "IsEnforced": false}],
+            "PrimaryGroupSID": "",
+         });
+         assert!(canonicalize_identifiers(&mut object).is_empty());
+         assert_eq!(object, json!({
+            "ObjectIdentifier": sid,
+            "Properties": {"domainsid": group, "sidhistory": [group]},
+            "Members": [{"ObjectIdentifier": group, "ObjectType": "Group"}],
+            "Aces": [{"PrincipalSID": group, "RightName": "GenericAll"}],
+            "ContainedBy": {"ObjectIdentifier": guid, "ObjectType": "OU"},
+            "Links": [{"GUID": guid, "IsEnforced": false}],
+            "PrimaryGroupSID": "",
+         }));
+      }
+
+      let mut object = json!({
+         "ObjectIdentifier": "S-1-5-21-1-2-3-1105",
+         "Aces": [{"PrincipalSID": "CN=JDOE,CN=USERS,DC=CONTOSO,DC=LOCAL"}],
+      });
+      assert_eq!(canonicalize_identifiers(&mut object), vec!["PrincipalSID 'CN=JDOE,CN=USERS,DC=CONTOSO,DC=LOCAL'"]);
+      assert_eq!(object["Aces"][0]["PrincipalSID"], "CN=JDOE,CN=USERS,DC=CONTOSO,DC=LOCAL");
+   }
+}
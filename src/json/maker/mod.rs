@@ -1,136 +1,137 @@
-use std::collections::HashMap;
 use std::error::Error;
 
 extern crate zip;
 use crate::api::ADResults;
 use crate::args::Options;
+use crate::events::{emit, Event};
 use crate::utils::date::return_current_fulldate;
 pub mod common;
+pub mod identifiers;
+pub mod sink;
+
+use sink::{sinks_from_options, OutputSink};
 
 /// This function will create json output and zip output
 pub fn make_result(common_args: &Options, ad_results: ADResults) -> Result<(), Box<dyn Error>> {
    // Format domain name
    let filename = common_args.domain.replace(".", "-").to_lowercase();
 
-   // Hashmap for json files
-   let mut json_result: HashMap<String, String> = HashMap::new();
-
    // Datetime for output file
    let datetime = return_current_fulldate();
 
-   // Add all in json files
-   common::add_file(
-      &datetime,
-      "users".to_string(),
-		&filename,
+   // Sinks selected by the CLI flags (json files or zip, ndjson, BloodHound CE upload)
+   let sinks = sinks_from_options(common_args, &datetime, &filename)?;
+   make_result_with_sinks(ad_results, sinks)
+}
+
+/// This function will write all objects in the given output sinks
+pub fn make_result_with_sinks(ad_results: ADResults, mut sinks: Vec<Box<dyn OutputSink>>) -> Result<(), Box<dyn Error>> {
+   emit(Event::phase_started("output"));
+
+   // Collection state recorded in each meta
+   let status = common::MetaStatus {
+      // Phase reached if the run was interrupted
+      partial: ad_results.partial.as_deref(),
+      // ACE filter applied by the checker
+      ace_filter: ad_results.ace_filter.as_deref(),
+      warnings: &ad_results.warnings,
+      // Categories not collected before the deadline
+      skipped: &ad_results.skipped,
+      // DCs bound, in order when a DC failed
+      domain_controllers: &ad_results.domain_controllers,
+      // Kerberos, or the password bind when Kerberos failed
+      auth_mechanism: ad_results.auth_mechanism.as_deref(),
+      // DC, naming contexts and functional levels
+      root_dse: ad_results.root_dse.as_ref(),
+      // Methods selected, the LDAP ones in the meta
+      methods: ad_results.methods,
+      // Types left out by --skip or --only, written as empty files
+      empty_types: &ad_results.empty_types,
+   };
+
+   // Add all objects in each sink
+   common::add_objects(
+      &mut sinks,
+      "users",
       ad_results.users,
-      &mut json_result,
-      common_args,
+      &status,
    )?;
-   common::add_file(
-      &datetime,
-      "groups".to_string(),
-		&filename,
+   common::add_objects(
+      &mut sinks,
+      "groups",
       ad_results.groups,
-      &mut json_result,
-      common_args,
+      &status,
    )?;
-   common::add_file(
-      &datetime,
-      "computers".to_string(),
-		&filename,
+   common::add_objects(
+      &mut sinks,
+      "computers",
       ad_results.computers,
-      &mut json_result,
-      common_args,
+      &status,
    )?;
-   common::add_file(
-      &datetime,
-      "ous".to_string(),
-		&filename,
+   common::add_objects(
+      &mut sinks,
+      "ous",
       ad_results.ous,
-      &mut json_result,
-      common_args,
+      &status,
    )?;
-   common::add_file(
-      &datetime,
-      "domains".to_string(),
-		&filename,
+   common::add_objects(
+      &mut sinks,
+      "domains",
       ad_results.domains,
-      &mut json_result,
-      common_args,
+      &status,
    )?;
-   common::add_file(
-      &datetime,
-      "gpos".to_string(),
-      &filename,
+   common::add_objects(
+      &mut sinks,
+      "gpos",
       ad_results.gpos,
-      &mut json_result,
-      common_args,
+      &status,
    )?;
-   // }
-   common::add_file(
-      &datetime,
-      "containers".to_string(),
-		&filename,
+   common::add_objects(
+      &mut sinks,
+      "containers",
       ad_results.containers,
-      &mut json_result,
-      common_args,
+      &status,
    )?;
-   common::add_file(
-      &datetime,
-      "ntauthstores".to_string(),
-		&filename,
+   common::add_objects(
+      &mut sinks,
+      "ntauthstores",
       ad_results.ntauthstores,
-      &mut json_result,
-      common_args,
+      &status,
    )?;
-   common::add_file(
-      &datetime,
-      "aiacas".to_string(),
-		&filename,
+   common::add_objects(
+      &mut sinks,
+      "aiacas",
       ad_results.aiacas,
-      &mut json_result,
-      common_args,
+      &status,
    )?;
-   common::add_file(
-      &datetime,
-      "rootcas".to_string(),
-		&filename,
+   common::add_objects(
+      &mut sinks,
+      "rootcas",
       ad_results.rootcas,
-      &mut json_result,
-      common_args,
+      &status,
    )?;
-   common::add_file(
-      &datetime,
-      "enterprisecas".to_string(),
-		&filename,
+   common::add_objects(
+      &mut sinks,
+      "enterprisecas",
       ad_results.enterprisecas,
-      &mut json_result,
-      common_args,
+      &status,
    )?;
-   common::add_file(
-      &datetime,
-      "certtemplates".to_string(),
-		&filename,
+   common::add_objects(
+      &mut sinks,
+      "certtemplates",
       ad_results.certtemplates,
-      &mut json_result,
-      common_args,
+      &status,
    )?;
-   common::add_file(
-      &datetime,
-      "issuancepolicies".to_string(),
-		&filename,
+   common::add_objects(
+      &mut sinks,
+      "issuancepolicies",
       ad_results.issuancepolicies,
-      &mut json_result,
-      common_args,
+      &status,
    )?;
-   // All in zip file
-   if common_args.zip {
-      common::make_a_zip(
-         &datetime,
-         &filename,
-         &common_args.path,
-         &json_result);
+
+   for sink in sinks.iter_mut() {
+      sink.finish()?;
    }
+   emit(Event::phase_finished("output"));
    Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,595 @@
+use serde_json::value::Value;
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use colored::Colorize;
+use log::{info, debug, trace};
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::args::Options;
+use crate::events::{emit, Event};
+use crate::objects::common::Meta;
+use crate::utils::crypto::calculate_sha1;
+
+/// Output sink used by the maker.
+/// For each objects type the maker calls `begin_type`, `write_object` for every object and `end_type`,
+/// then `finish` once all types are written. Objects are streamed so memory stays bounded.
+pub trait OutputSink {
+   /// Start a new objects type like "users" with its meta data.
+   fn begin_type(&mut self, object_type: &str, meta: &Meta) -> Result<(), Box<dyn Error>>;
+   /// Write one object of the current type, as json with canonical identifiers.
+   fn write_object(&mut self, object: &Value) -> Result<(), Box<dyn Error>>;
+   /// Close the current type.
+   fn end_type(&mut self) -> Result<(), Box<dyn Error>> {
+      Ok(())
+   }
+   /// Close the sink once all types are written.
+   fn finish(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+/// Function to build the sinks selected by the CLI flags.
+pub fn sinks_from_options(
+   common_args: &Options,
+   datetime: &str,
+   domain_format: &str,
+) -> Result<Vec<Box<dyn OutputSink>>, Box<dyn Error>> {
+   let mut sinks: Vec<Box<dyn OutputSink>> = Vec::new();
+   if common_args.zip {
+      sinks.push(Box::new(ZipSink::new(&common_args.path, datetime, domain_format)?));
+   } else {
+      sinks.push(Box::new(JsonFileSink::new(&common_args.path, datetime, domain_format)));
+   }
+   if common_args.ndjson {
+      sinks.push(Box::new(NdjsonSink::new(&common_args.path, datetime, domain_format)?));
+   }
+   if let Some(url) = &common_args.bh_url {
+      let (Some(token_id), Some(token_key)) = (&common_args.bh_token_id, &common_args.bh_token_key) else {
+         return Err("--bh-url needs --bh-token-id and --bh-token-key".into())
+      };
+      sinks.push(Box::new(UploadSink::new(BloodHoundClient::new(url, token_id, token_key))));
+   }
+   Ok(sinks)
+}
+
+/// Streamed BloodHound json document: `{"data":[...],"meta":{...}}`
+struct JsonStream {
+   meta: Meta,
+   first: bool,
+}
+
+impl JsonStream {
+   fn begin<W: Write>(writer: &mut W, meta: &Meta) -> Result<Self, Box<dyn Error>> {
+      writer.write_all(b"{\"data\":[")?;
+      Ok(Self { meta: meta.to_owned(), first: true })
+   }
+   fn push<W: Write>(&mut self, writer: &mut W, value: &Value) -> Result<(), Box<dyn Error>> {
+      if !self.first {
+         writer.write_all(b",")?;
+      }
+      self.first = false;
+      serde_json::to_writer(&mut *writer, value)?;
+      Ok(())
+   }
+   fn end<W: Write>(self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+      writer.write_all(b"],\"meta\":")?;
+      serde_json::to_writer(&mut *writer, &self.meta)?;
+      writer.write_all(b"}")?;
+      Ok(())
+   }
+}
+
+/// Function to emit the file_written event for a file on disk.
+fn file_written(path: &str) {
+   if let Ok(content) = fs::read(path) {
+      emit(Event::FileWritten { path: path.to_string(), sha1: calculate_sha1(&content) });
+   }
+}
+
+/// One json file per objects type, default output.
+pub struct JsonFileSink {
+   path: String,
+   datetime: String,
+   domain_format: String,
+   current: Option<(String, BufWriter<File>, JsonStream)>,
+}
+
+impl JsonFileSink {
+   pub fn new(path: &str, datetime: &str, domain_format: &str) -> Self {
+      Self {
+         path: path.to_string(),
+         datetime: datetime.to_string(),
+         domain_format: domain_format.to_string(),
+         current: None,
+      }
+   }
+}
+
+impl OutputSink for JsonFileSink {
+   fn begin_type(&mut self, object_type: &str, meta: &Meta) -> Result<(), Box<dyn Error>> {
+      self.end_type()?;
+      fs::create_dir_all(&self.path)?;
+      let final_path = format!("{}/{}_{}_{}.json", self.path, self.datetime, self.domain_format, object_type);
+      let mut writer = BufWriter::new(File::create(&final_path)?);
+      let stream = JsonStream::begin(&mut writer, meta)?;
+      self.current = Some((final_path, writer, stream));
+      Ok(())
+   }
+   fn write_object(&mut self, object: &Value) -> Result<(), Box<dyn Error>> {
+      if let Some((_, writer, stream)) = self.current.as_mut() {
+         stream.push(writer, object)?;
+      }
+      Ok(())
+   }
+   fn end_type(&mut self) -> Result<(), Box<dyn Error>> {
+      if let Some((final_path, mut writer, stream)) = self.current.take() {
+         stream.end(&mut writer)?;
+         writer.flush()?;
+         info!("{} created!", final_path.bold());
+         file_written(&final_path);
+      }
+      Ok(())
+   }
+   fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+      self.end_type()
+   }
+}
+
+/// All json files compressed into one zip archive named like SharpHound: `<timestamp>_<domain>.zip`.
+/// Each json file is deflated while its objects are written, nothing is kept in memory.
+pub struct ZipSink {
+   final_path: String,
+   datetime: String,
+   domain_format: String,
+   writer: Option<ZipWriter<BufWriter<File>>>,
+   stream: Option<JsonStream>,
+}
+
+impl ZipSink {
+   pub fn new(path: &str, datetime: &str, domain_format: &str) -> Result<Self, Box<dyn Error>> {
+      fs::create_dir_all(path)?;
+      let final_path = format!("{}/{}_{}.zip", path, datetime, domain_format);
+      let writer = ZipWriter::new(BufWriter::new(File::create(&final_path)?));
+      Ok(Self {
+         final_path,
+         datetime: datetime.to_string(),
+         domain_format: domain_format.to_string(),
+         writer: Some(writer),
+         stream: None,
+      })
+   }
+}
+
+impl OutputSink for ZipSink {
+   fn begin_type(&mut self, object_type: &str, meta: &Meta) -> Result<(), Box<dyn Error>> {
+      self.end_type()?;
+      if let Some(writer) = self.writer.as_mut() {
+         let filename = format!("{}_{}_{}.json", self.datetime, self.domain_format, object_type);
+         trace!("Adding file {}", filename.bold());
+         let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+         writer.start_file(filename, options)?;
+         self.stream = Some(JsonStream::begin(writer, meta)?);
+      }
+      Ok(())
+   }
+   fn write_object(&mut self, object: &Value) -> Result<(), Box<dyn Error>> {
+      if let (Some(writer), Some(stream)) = (self.writer.as_mut(), self.stream.as_mut()) {
+         stream.push(writer, object)?;
+      }
+      Ok(())
+   }
+   fn end_type(&mut self) -> Result<(), Box<dyn Error>> {
+      if let (Some(writer), Some(stream)) = (self.writer.as_mut(), self.stream.take()) {
+         stream.end(writer)?;
+      }
+      Ok(())
+   }
+   fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+      self.end_type()?;
+      if let Some(writer) = self.writer.take() {
+         writer.finish()?.flush()?;
+         info!("{} created!", self.final_path.bold());
+         file_written(&self.final_path);
+      }
+      Ok(())
+   }
+}
+
+/// One json object per line: `{"type":"users","meta":{...}}` then `{"type":"users","data":{...}}` for each object.
+pub struct NdjsonSink {
+   writer: Box<dyn Write>,
+   final_path: Option<String>,
+   object_type: String,
+}
+
+impl NdjsonSink {
+   pub fn new(path: &str, datetime: &str, domain_format: &str) -> Result<Self, Box<dyn Error>> {
+      fs::create_dir_all(path)?;
+      let final_path = format!("{}/{}_{}_rusthound-ce.ndjson", path, datetime, domain_format);
+      let writer = BufWriter::new(File::create(&final_path)?);
+      Ok(Self {
+         writer: Box::new(writer),
+         final_path: Some(final_path),
+         object_type: String::new(),
+      })
+   }
+   /// NDJSON sink on any writer, like stdout.
+   pub fn from_writer(writer: Box<dyn Write>) -> Self {
+      Self {
+         writer,
+         final_path: None,
+         object_type: String::new(),
+      }
+   }
+}
+
+impl OutputSink for NdjsonSink {
+   fn begin_type(&mut self, object_type: &str, meta: &Meta) -> Result<(), Box<dyn Error>> {
+      self.object_type = object_type.to_string();
+      let line = serde_json::json!({ "type": object_type, "meta": meta });
+      writeln!(self.writer, "{line}")?;
+      Ok(())
+   }
+   fn write_object(&mut self, object: &Value) -> Result<(), Box<dyn Error>> {
+      let line = serde_json::json!({ "type": &self.object_type, "data": object });
+      writeln!(self.writer, "{line}")?;
+      Ok(())
+   }
+   fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+      self.writer.flush()?;
+      if let Some(final_path) = &self.final_path {
+         info!("{} created!", final_path.bold());
+         file_written(final_path);
+      }
+      Ok(())
+   }
+}
+
+/// BloodHound CE API client signing requests with an API token.
+/// <https://bloodhound.specterops.io/integrations/bloodhound-api/working-with-api>
+#[derive(Debug, Clone)]
+pub struct BloodHoundClient {
+   url: String,
+   token_id: String,
+   token_key: String,
+   agent: ureq::Agent,
+}
+
+impl BloodHoundClient {
+   pub fn new(url: &str, token_id: &str, token_key: &str) -> Self {
+      Self {
+         url: url.trim_end_matches('/').to_string(),
+         token_id: token_id.to_string(),
+         token_key: token_key.to_string(),
+         agent: ureq::Agent::new(),
+      }
+   }
+
+   /// Function to send one signed request, the body is read twice: to sign it and to send it.
+   fn request(&self, method: &str, uri: &str, body: Option<&str>) -> Result<Value, Box<dyn Error>> {
+      let request_date = chrono::Local::now().to_rfc3339();
+      let signature = match body {
+         Some(path) => sign_request(&self.token_key, method, uri, &request_date, File::open(path)?)?,
+         None => sign_request(&self.token_key, method, uri, &request_date, std::io::empty())?,
+      };
+      debug!("BloodHound CE request: {method} {uri}");
+      let request = self.agent.request(method, &format!("{}{}", self.url, uri))
+         .set("User-Agent", &format!("rusthound-ce/{}", crate::args::RUSTHOUND_VERSION))
+         .set("Authorization", &format!("bhesignature {}", self.token_id))
+         .set("RequestDate", &request_date)
+         .set("Signature", &signature)
+         .set("Content-Type", "application/json");
+      let response = match body {
+         Some(path) => {
+            let file = File::open(path)?;
+            let length = file.metadata()?.len();
+            request.set("Content-Length", &length.to_string()).send(file)?
+         }
+         None => request.call()?,
+      };
+      let content = response.into_string()?;
+      if content.is_empty() {
+         return Ok(Value::Null)
+      }
+      Ok(serde_json::from_str(&content).unwrap_or(Value::Null))
+   }
+
+   /// Function to start a new file upload job, return the job id.
+   pub fn start_upload(&self) -> Result<i64, Box<dyn Error>> {
+      let response = self.request("POST", "/api/v2/file-upload/start", None)?;
+      response["data"]["id"].as_i64().ok_or_else(|| "BloodHound CE file upload job id not found".into())
+   }
+
+   /// Function to upload one json file in the current job.
+   pub fn upload_file(&self, job_id: i64, path: &str) -> Result<(), Box<dyn Error>> {
+      self.request("POST", &format!("/api/v2/file-upload/{job_id}"), Some(path))?;
+      Ok(())
+   }
+
+   /// Function to end the file upload job, BloodHound CE starts the ingestion.
+   pub fn end_upload(&self, job_id: i64) -> Result<(), Box<dyn Error>> {
+      self.request("POST", &format!("/api/v2/file-upload/{job_id}/end"), None)?;
+      Ok(())
+   }
+}
+
+/// Function to compute the BloodHound CE request signature.
+/// HMAC-SHA256 chain over the method and URI, the request date truncated to the hour, then the body.
+pub fn sign_request<R: Read>(
+   token_key: &str,
+   method: &str,
+   uri: &str,
+   request_date: &str,
+   mut body: R,
+) -> Result<String, Box<dyn Error>> {
+   let mut digester = Hmac::<Sha256>::new_from_slice(token_key.as_bytes())?;
+   digester.update(format!("{method}{uri}").as_bytes());
+   let mut digester = Hmac::<Sha256>::new_from_slice(&digester.finalize().into_bytes())?;
+   digester.update(request_date.get(..13).unwrap_or(request_date).as_bytes());
+   let mut digester = Hmac::<Sha256>::new_from_slice(&digester.finalize().into_bytes())?;
+   let mut buffer = [0u8; 8192];
+   loop {
+      let read = body.read(&mut buffer)?;
+      if read == 0 {
+         break
+      }
+      digester.update(&buffer[..read]);
+   }
+   Ok(base64::engine::general_purpose::STANDARD.encode(digester.finalize().into_bytes()))
+}
+
+/// Upload each json file to BloodHound CE in one file upload job.
+/// Files are streamed in a temporary directory before the upload.
+pub struct UploadSink {
+   client: BloodHoundClient,
+   job_id: Option<i64>,
+   tmp_dir: std::path::PathBuf,
+   current: Option<(String, BufWriter<File>, JsonStream)>,
+}
+
+impl UploadSink {
+   pub fn new(client: BloodHoundClient) -> Self {
+      let tmp_dir = std::env::temp_dir().join(format!("rusthound-ce-upload-{}", std::process::id()));
+      Self {
+         client,
+         job_id: None,
+         tmp_dir,
+         current: None,
+      }
+   }
+}
+
+impl OutputSink for UploadSink {
+   fn begin_type(&mut self, object_type: &str, meta: &Meta) -> Result<(), Box<dyn Error>> {
+      self.end_type()?;
+      if self.job_id.is_none() {
+         self.job_id = Some(self.client.start_upload()?);
+      }
+      fs::create_dir_all(&self.tmp_dir)?;
+      let tmp_path = self.tmp_dir.join(format!("{object_type}.json")).display().to_string();
+      let mut writer = BufWriter::new(File::create(&tmp_path)?);
+      let stream = JsonStream::begin(&mut writer, meta)?;
+      self.current = Some((tmp_path, writer, stream));
+      Ok(())
+   }
+   fn write_object(&mut self, object: &Value) -> Result<(), Box<dyn Error>> {
+      if let Some((_, writer, stream)) = self.current.as_mut() {
+         stream.push(writer, object)?;
+      }
+      Ok(())
+   }
+   fn end_type(&mut self) -> Result<(), Box<dyn Error>> {
+      if let Some((tmp_path, mut writer, stream)) = self.current.take() {
+         stream.end(&mut writer)?;
+         writer.flush()?;
+         if let Some(job_id) = self.job_id {
+            self.client.upload_file(job_id, &tmp_path)?;
+         }
+         fs::remove_file(&tmp_path)?;
+      }
+      Ok(())
+   }
+   fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+      self.end_type()?;
+      if let Some(job_id) = self.job_id.take() {
+         self.client.end_upload(job_id)?;
+         info!("Files uploaded to {} (job {})", self.client.url.bold(), job_id);
+      }
+      fs::remove_dir_all(&self.tmp_dir).ok();
+      Ok(())
+   }
+}
+
+#[cfg(test)]
+mod tests {
+
+   use std::io::{BufRead, BufReader, Read, Write};
+   use std::net::TcpListener;
+   use std::sync::{Arc, Mutex};
+   use crate::json::maker::make_result_with_sinks;
+   use crate::json::maker::sink::*;
+   use crate::api::ADResults;
+   use crate::objects::user::User;
+   use crate::objects::common::LdapObject;
+
+   #[derive(Clone, Default)]
+   struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+   impl Write for SharedBuffer {
+      fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+         self.0.lock().unwrap().write(buf)
+      }
+      fn flush(&mut self) -> std::io::Result<()> {
+         Ok(())
+      }
+   }
+
+   fn meta(object_type: &str, count: i32) -> Meta {
+      Meta::new(0, object_type.to_string(), count, 6, "RustHound-CE test".to_string())
+   }
+
+   fn write_users<S: OutputSink>(sink: &mut S, count: usize) {
+      sink.begin_type("users", &meta("users", count as i32)).unwrap();
+      for _ in 0..count {
+         sink.write_object(&User::new().to_json()).unwrap();
+      }
+      sink.end_type().unwrap();
+      sink.finish().unwrap();
+   }
+
+   fn test_dir(name: &str) -> String {
+      let path = std::env::temp_dir().join(format!("rusthound-ce-sink-{name}"));
+      std::fs::remove_dir_all(&path).ok();
+      path.display().to_string()
+   }
+
+   #[test]
+   pub fn test_json_file_sink() {
+      let path = test_dir("json");
+      let mut sink = JsonFileSink::new(&path, "20240101000000", "contoso-local");
+      write_users(&mut sink, 2);
+
+      let content = std::fs::read_to_string(format!("{path}/20240101000000_contoso-local_users.json")).unwrap();
+      let json: Value = serde_json::from_str(&content).unwrap();
+      assert_eq!(json["data"].as_array().unwrap().len(), 2);
+      assert_eq!(json["meta"]["type"], "users");
+      assert_eq!(json["meta"]["count"], 2);
+      std::fs::remove_dir_all(path).ok();
+   }
+
+   #[test]
+   pub fn test_zip_sink() {
+      let path = test_dir("zip");
+      let mut sink = ZipSink::new(&path, "20240101000000", "contoso-local").unwrap();
+      for object_type in ["computers", "groups", "enterprisecas"] {
+         sink.begin_type(object_type, &meta(object_type, 0)).unwrap();
+      }
+      write_users(&mut sink, 3);
+
+      // SharpHound naming: <timestamp>_<domain>.zip
+      let file = File::open(format!("{path}/20240101000000_contoso-local.zip")).unwrap();
+      let mut archive = zip::ZipArchive::new(file).unwrap();
+      let mut names: Vec<&str> = archive.file_names().collect();
+      names.sort();
+      assert_eq!(names, [
+         "20240101000000_contoso-local_computers.json",
+         "20240101000000_contoso-local_enterprisecas.json",
+         "20240101000000_contoso-local_groups.json",
+         "20240101000000_contoso-local_users.json",
+      ]);
+      let mut users = archive.by_name("20240101000000_contoso-local_users.json").unwrap();
+      assert_eq!(users.compression(), zip::CompressionMethod::Deflated);
+      let mut content = String::new();
+      users.read_to_string(&mut content).unwrap();
+      let json: Value = serde_json::from_str(&content).unwrap();
+      assert_eq!(json["data"].as_array().unwrap().len(), 3);
+      drop(users);
+      let mut content = String::new();
+      archive.by_name("20240101000000_contoso-local_groups.json").unwrap().read_to_string(&mut content).unwrap();
+      let json: Value = serde_json::from_str(&content).unwrap();
+      assert_eq!(json["meta"]["type"], "groups");
+      assert!(json["data"].as_array().unwrap().is_empty());
+      std::fs::remove_dir_all(path).ok();
+   }
+
+   #[test]
+   pub fn test_ndjson_sink() {
+      let buffer = SharedBuffer::default();
+      let mut sink = NdjsonSink::from_writer(Box::new(buffer.clone()));
+      write_users(&mut sink, 2);
+
+      let output = String::from_utf8(buffer.0.lock().unwrap().to_vec()).unwrap();
+      let lines: Vec<Value> = output.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+      assert_eq!(lines.len(), 3);
+      assert_eq!(lines[0]["meta"]["count"], 2);
+      assert!(lines.iter().all(|l| l["type"] == "users"));
+      assert!(lines[1]["data"]["ObjectIdentifier"].is_string());
+   }
+
+   #[test]
+   pub fn test_sign_request() {
+      // Reference value computed with the python example of the BloodHound CE documentation.
+      let signature = sign_request(
+         "c2VjcmV0",
+         "POST",
+         "/api/v2/file-upload/start",
+         "2024-01-01T12:34:56.000000+00:00",
+         "{}".as_bytes(),
+      ).unwrap();
+      assert_eq!(signature, "9JEEcNI0cHFnNBtnY9A/wTlaruRnkPmwQkvixvZlOZA=");
+   }
+
+   #[test]
+   pub fn test_upload_sink() {
+      // Minimal BloodHound CE API answering the upload job requests.
+      let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+      let url = format!("http://{}", listener.local_addr().unwrap());
+      let server = std::thread::spawn(move || {
+         let mut requests = Vec::new();
+         for stream in listener.incoming().take(3) {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut length = 0;
+            let mut authorization = String::new();
+            loop {
+               let mut header = String::new();
+               reader.read_line(&mut header).unwrap();
+               if header.trim().is_empty() {
+                  break
+               }
+               let lower = header.to_lowercase();
+               if let Some(value) = lower.strip_prefix("content-length:") {
+                  length = value.trim().parse().unwrap();
+               }
+               if lower.starts_with("authorization:") {
+                  authorization = header.trim().to_string();
+               }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            requests.push((request_line.trim().to_string(), authorization, String::from_utf8(body).unwrap()));
+            let response = r#"{"data":{"id":7}}"#;
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", response.len(), response).unwrap();
+         }
+         requests
+      });
+
+      let mut sink = UploadSink::new(BloodHoundClient::new(&url, "token-id", "c2VjcmV0"));
+      write_users(&mut sink, 1);
+
+      let requests = server.join().unwrap();
+      assert_eq!(requests[0].0, "POST /api/v2/file-upload/start HTTP/1.1");
+      assert_eq!(requests[1].0, "POST /api/v2/file-upload/7 HTTP/1.1");
+      assert_eq!(requests[2].0, "POST /api/v2/file-upload/7/end HTTP/1.1");
+      assert!(requests.iter().all(|r| r.1.ends_with("bhesignature token-id")));
+      let json: Value = serde_json::from_str(&requests[1].2).unwrap();
+      assert_eq!(json["meta"]["type"], "users");
+   }
+
+   #[test]
+   pub fn test_two_sinks_simultaneously() {
+      let path = test_dir("two");
+      let buffer = SharedBuffer::default();
+      let sinks: Vec<Box<dyn OutputSink>> = vec![
+         Box::new(JsonFileSink::new(&path, "20240101000000", "contoso-local")),
+         Box::new(NdjsonSink::from_writer(Box::new(buffer.clone()))),
+      ];
+      let mut results = ADResults::new();
+      results.users.push(User::new());
+      results.users.push(User::new());
+      make_result_with_sinks(results, sinks).unwrap();
+
+      let content = std::fs::read_to_string(format!("{path}/20240101000000_contoso-local_users.json")).unwrap();
+      let json: Value = serde_json::from_str(&content).unwrap();
+      assert_eq!(json["data"].as_array().unwrap().len(), 2);
+      let output = String::from_utf8(buffer.0.lock().unwrap().to_vec()).unwrap();
+      assert_eq!(output.lines().count(), 3);
+      std::fs::remove_dir_all(path).ok();
+   }
+}
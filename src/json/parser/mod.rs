@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use std::error::Error;
 use ldap3::SearchEntry;
 use indicatif::ProgressBar;
-use crate::objects::common::parse_unknown;
+use crate::objects::common::{parse_unknown, HostResolver};
 use crate::objects::{
     user::User,
     computer::Computer,
@@ -52,7 +52,7 @@ pub fn parse_result_type(
 
     dn_sid:             &mut HashMap<String, String>,
     sid_type:           &mut HashMap<String, String>,
-    fqdn_sid:           &mut HashMap<String, String>,
+    host_resolver:      &mut HostResolver,
     fqdn_ip:            &mut HashMap<String, String>,
     // adcs_templates: &mut HashMap<String, Vec<String>>,
 ) -> Result<(), Box<dyn Error>> {
@@ -101,7 +101,7 @@ pub fn parse_result_type(
                     domain,
                     dn_sid,
                     sid_type,
-                    fqdn_sid,
+                    host_resolver,
                     fqdn_ip,
                     &domain_sid
                 )?;
@@ -246,7 +246,7 @@ pub fn parse_result_type(
                 )?;
                 vec_issuancepolicies.push(issuance_policie);
             }
-            Type::Unknown => {
+            Type::ClassSchema | Type::Unknown => {
                 let _unknown = parse_unknown(cloneresult, domain);
             }
         }
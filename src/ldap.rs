@@ -7,120 +7,1281 @@
 //! Example in rust
 //!
 //! ```ignore
-//! let search = ldap_search(...)
+//! let settings = LdapSettings::from_options(&options)?;
+//! let total = ldap_search(&options, &settings, true, None, &mut entries).await?;
 //! ```
 
 // use crate::errors::Result;
+use crate::args::Options;
+use crate::attributes::search_attributes;
 use crate::banner::progress_bar;
+use crate::coverage::record_requested;
+use crate::credentials::{prompt_password, BindSecret, Credentials, Secret, REDACTED};
+use crate::deadline::{deadline_cutoff, format_duration, record_skipped, CollectionCategory, QueryPlan};
+use crate::deleted::{deleted_objects_dn, is_under_deleted_objects, show_deleted_control};
+use crate::incremental::DeltaSearch;
+use crate::confignc::{add_collection_warning, retry_config_nc, ConfigNcFallback, ConfigNcStatus};
+use crate::events::{emit, Event};
+use crate::filters::DEFAULT_LDAP_FILTER;
+use crate::globalcatalog::{gc_port, merge_entry, naming_context_of, supplement_naming_contexts, GC_SUPPLEMENT_ATTRIBUTES};
+use crate::interrupt::is_interrupted;
+use crate::methods::{methods_filter, CollectionMethod};
+use crate::objecttypes::types_filter;
+use crate::dns::{is_dns_partition, DNS_NODE_ATTRIBUTES};
+use crate::paging::{page_control, page_cookie, Page, PageSource, PagedSearch};
+use crate::proxy::{proxied_url, split_ldap_url, Proxy};
+use crate::ranged::{complete_ranges, has_ranged_attributes, RangeReader};
+use crate::rootdse::{dedup_search_bases, functional_level_name, is_domain_of, is_under, record_root_dse, RootDse};
+use crate::referral::{Referral, ReferralQueue};
+use crate::resolver::{GlobalCatalog, GC_ATTRIBUTES};
 use crate::storage::Storage;
-use crate::utils::format::domain_to_dc;
+use crate::sicily::{self, LdapStream};
+use crate::utils::crypto::{certificate_host_names, host_name_matches, tls_server_end_point};
+use crate::utils::format::{domain_to_dc, url_host};
+use crate::utils::ntlm::{channel_bindings_hash, ntlm_identity, parse_nt_hash, NtlmAccount};
+use crate::verify::ObjectReader;
 
 use colored::Colorize;
 use indicatif::ProgressBar;
 use ldap3::adapters::{Adapter, EntriesOnly};
 use ldap3::{adapters::PagedResults, controls::RawControl, LdapConnAsync, LdapConnSettings};
+use ldap3::exop::{WhoAmI, WhoAmIResp};
 use ldap3::{Scope, SearchEntry};
-use log::{info, debug, error, trace};
+use log::{info, debug, error, trace, warn};
 use std::io::{self, Write, stdin};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::process;
+use std::sync::{Arc, Mutex};
 
-/// Function to request all AD values.
-#[allow(clippy::too_many_arguments)]
-pub async fn ldap_search<S: Storage<LdapSearchEntry>>(
-    ldaps: bool,
-    ip: Option<&str>,
-    port: Option<u16>,
-    domain: &str,
+/// Smallest page size when the DC refuses the pages with sizeLimitExceeded or adminLimitExceeded.
+const MIN_PAGE_SIZE: i32 = 50;
+
+/// Function to check if a search result is refused for the size of the pages (MaxPageSize, MaxResultSetSize):
+/// sizeLimitExceeded or adminLimitExceeded.
+fn is_limit_exceeded(result: &ldap3::LdapResult) -> bool {
+    matches!(result.rc, 4 | 11)
+}
+
+/// Function to get the page size requested again after a sizeLimitExceeded or adminLimitExceeded result,
+/// none when the page size is already the smallest one.
+fn lower_page_size(page_size: i32) -> Option<i32> {
+    (page_size > MIN_PAGE_SIZE).then(|| (page_size / 2).max(MIN_PAGE_SIZE))
+}
+
+/// Constructed attributes not implemented by Samba AD, never requested in Samba mode.
+pub const SAMBA_UNSUPPORTED_ATTRIBUTES: &[&str] = &[
+    "msDS-ReplAttributeMetaData",
+    "msDS-ReplValueMetaData",
+    "msDS-Approx-Immed-Subordinates",
+    "msDS-LocalEffectiveDeletionTime",
+    "msDS-LocalEffectiveRecycleTime",
+    "msDS-NCReplInboundNeighbors",
+    "msDS-NCReplOutboundNeighbors",
+    "msDS-NCReplCursors",
+];
+
+/// State of the binds of one collection, recorded in its [`LdapSettings`] during the LDAP search and handed to
+/// [`crate::prepare_results_from_source`]. The password is wiped when the state is dropped.
+#[derive(Debug, Default)]
+pub struct BindState {
+    /// Credentials of the main bind, reused by the Global Catalog lookups and the CA registry reads so the
+    /// password is prompted once. None after a Kerberos, certificate or anonymous bind.
+    credentials: Option<(String, BindSecret)>,
+    /// Domain controllers bound during the collection, in order, the next one is used when a DC fails.
+    domain_controllers: Vec<String>,
+    /// Authentication of the main bind.
+    auth_mechanism: Option<AuthMechanism>,
+}
+
+impl BindState {
+    /// Function to get the credentials of the main bind, none after a Kerberos, certificate or anonymous bind.
+    pub fn credentials(&self) -> Option<(&str, &BindSecret)> {
+        self.credentials.as_ref().map(|(username, secret)| (username.as_str(), secret))
+    }
+
+    /// Function to get the domain controllers used for the collection, shown in the output meta.
+    pub fn domain_controllers(&self) -> &[String] {
+        &self.domain_controllers
+    }
+
+    /// Function to get the authentication of the main bind, none when the search didn't bind.
+    pub fn auth_mechanism(&self) -> Option<AuthMechanism> {
+        self.auth_mechanism
+    }
+}
+
+/// Authentication of the main bind, shown in the output meta.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuthMechanism {
+    ClientCertificate,
+    Anonymous,
+    SimpleBind,
+    Ntlm,
+    Kerberos,
+    /// Password bind, NTLM or simple, after a Kerberos bind failed for the given reason.
+    KerberosFallback { ntlm: bool, reason: &'static str },
+}
+
+impl std::fmt::Display for AuthMechanism {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthMechanism::ClientCertificate => write!(f, "Client certificate"),
+            AuthMechanism::Anonymous => write!(f, "Anonymous"),
+            AuthMechanism::SimpleBind => write!(f, "Simple bind"),
+            AuthMechanism::Ntlm => write!(f, "NTLM"),
+            AuthMechanism::Kerberos => write!(f, "Kerberos"),
+            AuthMechanism::KerberosFallback { ntlm, reason } => {
+                write!(f, "{} (Kerberos failed: {reason})", if *ntlm { "NTLM" } else { "Simple bind" })
+            }
+        }
+    }
+}
+
+/// Active Directory error of a bind without the channel binding token required by the DC.
+const CHANNEL_BINDING_ERROR: &str = "80090346";
+
+/// Active Directory error of a simple bind without TLS when the DC requires LDAP signing (strongerAuthRequired).
+const SIGNING_REQUIRED_ERROR: &str = "00002028";
+
+/// Security of an LDAP connection, used to explain a bind refused by a hardened DC.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct ChannelSecurity {
+    /// LDAPS or StartTLS connection.
+    tls: bool,
+    /// Channel binding token computed from the DC certificate, sent with the Kerberos and NTLM binds.
+    channel_binding: bool,
+}
+
+/// Function to check the channel binding token of a TLS connection, sent by ldap3 with the Kerberos bind.
+async fn check_channel_binding(ldap: &mut ldap3::Ldap, tls: bool) -> ChannelSecurity {
+    if !tls {
+        return ChannelSecurity::default()
+    }
+    let certificate = ldap.get_peer_certificate().await.ok().flatten();
+    let token = certificate.as_deref().and_then(tls_server_end_point);
+    if let Some(token) = &token {
+        trace!("LDAPS channel binding token: {token:02X?}");
+    }
+    ChannelSecurity { tls, channel_binding: token.is_some() }
+}
+
+/// Function to explain a bind refused for the channel binding or the LDAP signing enforced by the DC.
+fn bind_error_hint(error: &str, security: ChannelSecurity, mechanism: AuthMechanism) -> Option<&'static str> {
+    if error.contains(CHANNEL_BINDING_ERROR) {
+        let token_sent = matches!(mechanism, AuthMechanism::Kerberos | AuthMechanism::Ntlm);
+        let hint = match (token_sent, security.channel_binding) {
+            (false, _) => "The DC enforces LDAP channel binding, a simple bind can't send the token. Please bind with -u/-p over LDAPS without --no-channel-binding, or use Kerberos over LDAPS '-k --ldaps -f DC01.DOMAIN.LAB'",
+            (true, false) => "The DC enforces LDAP channel binding and no token could be computed from its certificate (unsupported signature algorithm). Please use Kerberos without TLS, the connection is sealed: '-k -f DC01.DOMAIN.LAB', or renew the DC certificate with a SHA-2 signature",
+            (true, true) => "The DC enforces LDAP channel binding and refused the token of the connection, a TLS inspection proxy changes the certificate. Please use Kerberos without TLS, the connection is sealed: '-k -f DC01.DOMAIN.LAB'",
+        };
+        return Some(hint)
+    }
+    if !security.tls && (error.contains(SIGNING_REQUIRED_ERROR) || error.contains("strongerAuthRequired")) {
+        return Some("The DC enforces LDAP signing, a password bind needs TLS. Please use '--ldaps' or '--starttls', or Kerberos '-k -f DC01.DOMAIN.LAB'")
+    }
+    None
+}
+
+/// Function to add the channel binding or LDAP signing explanation to a refused bind.
+fn bind_error(settings: &LdapSettings, err: ldap3::LdapError, security: ChannelSecurity, mechanism: AuthMechanism) -> Box<dyn Error> {
+    if matches!(err, ldap3::LdapError::Timeout { .. }) {
+        return timeout_error(settings, err, "LDAP bind")
+    }
+    match bind_error_hint(&err.to_string(), security, mechanism) {
+        Some(hint) => format!("{err}. {hint}").into(),
+        None => err.into(),
+    }
+}
+
+/// PKCS#12 client certificate of `--pfx`, only sent with the native TLS backend.
+#[cfg(feature = "nogssapi")]
+type ClientCertificate = native_tls::Identity;
+#[cfg(not(feature = "nogssapi"))]
+type ClientCertificate = std::convert::Infallible;
+
+/// Function to load the PKCS#12 client certificate, the LDAP connections then authenticate with it instead of a password.
+fn load_client_certificate(path: &str, password: &str) -> Result<ClientCertificate, Box<dyn Error>> {
+    let pfx = std::fs::read(path).map_err(|err| format!("can't read the PKCS#12 file {path}: {err}"))?;
+    #[cfg(feature = "nogssapi")]
+    {
+        let identity = native_tls::Identity::from_pkcs12(&pfx, password)
+            .map_err(|err| format!("can't load the PKCS#12 file {path}, wrong --pfx-pass? {err}"))?;
+        Ok(identity)
+    }
+    #[cfg(not(feature = "nogssapi"))]
+    {
+        let _ = (pfx, password);
+        Err("Certificate authentication needs the native TLS backend, please build with '--no-default-features --features nogssapi'".into())
+    }
+}
+
+/// Validation of the DC certificate with LDAPS and StartTLS, against the system store by default.
+#[derive(Debug, Clone, Default)]
+struct TlsValidation {
+    /// DER root certificates of `--ca-cert`, trusted in addition to the system store.
+    ca_certificates: Vec<Vec<u8>>,
+    /// No validation at all with `--insecure`.
+    insecure: bool,
+    /// No check of the DC name in its certificate with `--no-hostname-verify`.
+    no_hostname_verify: bool,
+}
+
+/// Function to load the validation of the DC certificate, with the root certificates of the `--ca-cert` file.
+fn load_tls_validation(ca_cert: Option<&str>, insecure: bool, no_hostname_verify: bool) -> Result<TlsValidation, Box<dyn Error>> {
+    let mut ca_certificates = Vec::new();
+    if let Some(path) = ca_cert {
+        let pem = std::fs::read(path).map_err(|err| format!("can't read the CA certificate {path}: {err}"))?;
+        for pem in x509_parser::pem::Pem::iter_from_buffer(&pem) {
+            let pem = pem.map_err(|err| format!("invalid PEM in the CA certificate {path}: {err}"))?;
+            if pem.label == "CERTIFICATE" {
+                ca_certificates.push(pem.contents);
+            }
+        }
+        if ca_certificates.is_empty() {
+            return Err(format!("no PEM certificate in the CA certificate {path}").into())
+        }
+        debug!("{} CA certificates trusted from {path}", ca_certificates.len());
+    }
+    Ok(TlsValidation { ca_certificates, insecure, no_hostname_verify })
+}
+
+/// Settings of all the LDAP connections of a collection, loaded from the options by each [`crate::Collector`]:
+/// the validation of the DC certificate, the `--pfx` client certificate, the `--proxy`, the timeouts and the SD flags.
+#[derive(Clone)]
+pub struct LdapSettings {
+    tls: TlsValidation,
+    client_certificate: Option<ClientCertificate>,
+    proxy: Option<Proxy>,
+    timeouts: LdapTimeouts,
+    /// SD flags requested with nTSecurityDescriptor.
+    sd_flags: u32,
+    /// NTLM password binds with the channel binding token over TLS, the simple bind with `--no-channel-binding`.
+    channel_binding: bool,
+    /// Binds of the collection, shared by the searches of the forest.
+    bind: Arc<Mutex<BindState>>,
+}
+
+impl Default for LdapSettings {
+    fn default() -> Self {
+        Self {
+            tls: TlsValidation::default(),
+            client_certificate: None,
+            proxy: None,
+            timeouts: LdapTimeouts::default(),
+            sd_flags: DEFAULT_SD_FLAGS,
+            channel_binding: true,
+            bind: Arc::default(),
+        }
+    }
+}
+
+impl LdapSettings {
+    /// Function to load the settings of the options: the `--ca-cert` and `--pfx` files are read, the `--proxy` parsed.
+    pub fn from_options(options: &Options) -> Result<Self, Box<dyn Error>> {
+        let client_certificate = match &options.pfx {
+            Some(pfx) => Some(load_client_certificate(pfx, options.pfx_password.as_deref().unwrap_or_default())?),
+            None => None,
+        };
+        if let Some(nt_hash) = &options.nt_hash {
+            parse_nt_hash(nt_hash).map_err(|err| format!("invalid --nt-hash: {err}"))?;
+        }
+        let proxy = match &options.proxy {
+            Some(proxy) => {
+                let proxy = Proxy::parse(proxy, options.proxy_dns)?;
+                debug!("LDAP connections through the SOCKS5 proxy {}:{}", proxy.host, proxy.port);
+                Some(proxy)
+            }
+            None => None,
+        };
+        Ok(Self {
+            tls: load_tls_validation(options.ca_cert.as_deref(), options.insecure, options.no_hostname_verify)?,
+            client_certificate,
+            proxy,
+            timeouts: LdapTimeouts {
+                connect: std::time::Duration::from_secs(options.connect_timeout),
+                search: std::time::Duration::from_secs(options.search_timeout),
+            },
+            sd_flags: options.sd_flags,
+            channel_binding: !options.no_channel_binding,
+            bind: Arc::default(),
+        })
+    }
+
+    /// Function to check if the connections authenticate with a client certificate.
+    fn has_client_certificate(&self) -> bool {
+        self.client_certificate.is_some()
+    }
+
+    /// Function to keep the credentials of the main bind for the Global Catalog and the CA registry.
+    fn set_bind_credentials(&self, username: &str, secret: &BindSecret) {
+        self.bind.lock().unwrap().credentials = Some((username.to_owned(), secret.to_owned()));
+    }
+
+    /// Function to record the authentication of the main bind.
+    fn record_auth_mechanism(&self, mechanism: AuthMechanism) {
+        self.bind.lock().unwrap().auth_mechanism = Some(mechanism);
+    }
+
+    /// Function to record a domain controller used for the collection.
+    fn record_domain_controller(&self, name: &str) {
+        let used = &mut self.bind.lock().unwrap().domain_controllers;
+        if !used.iter().any(|dc| dc.eq_ignore_ascii_case(name)) {
+            used.push(name.to_owned());
+        }
+    }
+
+    /// Function to take the state of the binds once the search is done, the settings keep none of the credentials.
+    pub fn take_bind_state(&self) -> BindState {
+        std::mem::take(&mut *self.bind.lock().unwrap())
+    }
+}
+
+/// Verifier of the DC certificate chain against the trusted roots. The name is checked after the handshake
+/// with the name of the DC, the URL can be the local end of the `--proxy` tunnel.
+#[cfg(not(feature = "nogssapi"))]
+struct ChainVerifier(rustls::client::WebPkiVerifier);
+
+#[cfg(not(feature = "nogssapi"))]
+impl rustls::client::ServerCertVerifier for ChainVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        intermediates: &[rustls::Certificate],
+        server_name: &rustls::ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: std::time::SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        // The chain is verified before the name
+        match self.0.verify_server_cert(end_entity, intermediates, server_name, scts, ocsp_response, now) {
+            Err(rustls::Error::InvalidCertificate(rustls::CertificateError::NotValidForName)) => {
+                Ok(rustls::client::ServerCertVerified::assertion())
+            }
+            result => result,
+        }
+    }
+}
+
+/// Verifier accepting any DC certificate with `--insecure`, for the TLS channels opened without ldap3.
+#[cfg(not(feature = "nogssapi"))]
+struct NoVerification;
+
+#[cfg(not(feature = "nogssapi"))]
+impl rustls::client::ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
+
+/// Function to build the native TLS connector, with the validation of the DC certificate and the client certificate.
+#[cfg(feature = "nogssapi")]
+fn tls_connector(settings: &LdapSettings) -> Result<native_tls::TlsConnector, Box<dyn Error>> {
+    let validation = &settings.tls;
+    let mut builder = native_tls::TlsConnector::builder();
+    builder
+        .danger_accept_invalid_certs(validation.insecure)
+        .danger_accept_invalid_hostnames(true);
+    for certificate in &validation.ca_certificates {
+        builder.add_root_certificate(native_tls::Certificate::from_der(certificate)?);
+    }
+    if let Some(identity) = settings.client_certificate.clone() {
+        builder.identity(identity);
+    }
+    Ok(builder.build()?)
+}
+
+/// Function to build the rustls configuration, with the validation of the DC certificate.
+#[cfg(not(feature = "nogssapi"))]
+fn tls_config(settings: &LdapSettings) -> Arc<rustls::ClientConfig> {
+    let validation = &settings.tls;
+    let verifier: Arc<dyn rustls::client::ServerCertVerifier> = if validation.insecure {
+        Arc::new(NoVerification)
+    } else {
+        let mut roots = rustls::RootCertStore::empty();
+        let system = rustls_native_certs::load_native_certs().unwrap_or_default();
+        roots.add_parsable_certificates(&system.into_iter().map(|cert| cert.0).collect::<Vec<_>>());
+        roots.add_parsable_certificates(&validation.ca_certificates);
+        Arc::new(ChainVerifier(rustls::client::WebPkiVerifier::new(roots, None)))
+    };
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth();
+    Arc::new(config)
+}
+
+/// Function to get the LDAP connection settings, with the validation of the DC certificate and the client certificate.
+fn connection_settings(settings: &LdapSettings, starttls: bool) -> Result<LdapConnSettings, Box<dyn Error>> {
+    let conn_settings = LdapConnSettings::new()
+        .set_conn_timeout(settings.timeouts.connect)
+        .set_no_tls_verify(settings.tls.insecure)
+        .set_starttls(starttls);
+    #[cfg(feature = "nogssapi")]
+    {
+        Ok(conn_settings.set_connector(tls_connector(settings)?))
+    }
+    #[cfg(not(feature = "nogssapi"))]
+    {
+        if settings.tls.insecure {
+            return Ok(conn_settings)
+        }
+        Ok(conn_settings.set_config(tls_config(settings)))
+    }
+}
+
+/// Function to check the DC name in the certificate of a TLS connection, the FQDN given with `-f` or the host of the URL.
+async fn check_certificate_name(settings: &LdapSettings, ldap: &mut ldap3::Ldap, url: &str, server_name: &str) -> Result<(), Box<dyn Error>> {
+    if settings.tls.insecure || settings.tls.no_hostname_verify {
+        return Ok(())
+    }
+    let certificate = ldap.get_peer_certificate().await?.ok_or("no DC certificate in the TLS connection")?;
+    check_certificate_host(settings, &certificate, url, server_name)
+}
+
+/// Function to check the DC name in its DER certificate.
+fn check_certificate_host(settings: &LdapSettings, certificate: &[u8], url: &str, server_name: &str) -> Result<(), Box<dyn Error>> {
+    if settings.tls.insecure || settings.tls.no_hostname_verify {
+        return Ok(())
+    }
+    let host = match server_name.contains("not set") {
+        true => split_ldap_url(url)?.1,
+        false => server_name.to_owned(),
+    };
+    let names = certificate_host_names(certificate);
+    if names.iter().any(|name| host_name_matches(name, &host)) {
+        return Ok(())
+    }
+    Err(format!(
+        "The certificate of {url} was rejected: name mismatch, issued for {} and not for {host}. Use -f with one of these names or --no-hostname-verify",
+        if names.is_empty() { "no name".to_owned() } else { names.join(", ") },
+    ).into())
+}
+
+/// Function to bind with the client certificate of the TLS connection (SASL EXTERNAL), return the bound identity.
+async fn certificate_bind(settings: &LdapSettings, ldap: &mut ldap3::Ldap) -> Result<String, Box<dyn Error>> {
+    ldap.with_timeout(settings.timeouts.connect).sasl_external_bind().await
+        .map_err(|err| timeout_error(settings, err, "LDAP bind"))?
+        .success()
+        .map_err(|err| format!("the DC refused the client certificate and requires an explicit bind: {err}"))?;
+    let (exop, _res) = ldap.extended(WhoAmI).await?.success()?;
+    let identity = exop.parse::<WhoAmIResp>().authzid;
+    // Anonymous: the certificate is not mapped to an account
+    if identity.is_empty() {
+        return Err("the client certificate is not mapped to an account, the DC requires an explicit bind".into())
+    }
+    Ok(identity)
+}
+
+/// Function to close a connection once its searches are done. The DC or a middlebox may have reset it already,
+/// the unbind is then only logged.
+async fn close_connection(settings: &LdapSettings, mut ldap: ldap3::Ldap) {
+    if let Err(err) = ldap.with_timeout(settings.timeouts.connect).unbind().await {
+        debug!("LDAP unbind failed: {err}");
+    }
+}
+
+/// Function to open an LDAP connection, through the `--proxy` when set, the DC certificate is checked for `server_name`.
+async fn ldap_connect(settings: &LdapSettings, url: &str, server_name: &str, starttls: bool) -> Result<ldap3::Ldap, Box<dyn Error>> {
+    let target = proxied_url(url, settings.proxy.as_ref()).await?;
+    let (conn, mut ldap) = LdapConnAsync::with_settings(connection_settings(settings, starttls)?, &target).await
+        .map_err(|err| connection_error(settings, url, starttls, err))?;
+    ldap3::drive!(conn);
+    if url.starts_with("ldaps") || starttls {
+        check_certificate_name(settings, &mut ldap, url, server_name).await?;
+    }
+    Ok(ldap)
+}
+
+/// Domain controller of a connection, bound again on a connection of its own by the NTLM bind.
+#[derive(Debug, Clone, Copy)]
+struct DcTarget<'a> {
+    url: &'a str,
+    /// FQDN given with `-f`, checked in the DC certificate.
+    server_name: &'a str,
+    starttls: bool,
+}
+
+/// Function to open the TLS channel of an NTLM bind, return it with the DER certificate of the DC.
+async fn tls_handshake(
+    settings: &LdapSettings,
+    stream: tokio::net::TcpStream,
+    host: &str,
+) -> Result<(Box<dyn LdapStream>, Vec<u8>), Box<dyn Error>> {
+    #[cfg(feature = "nogssapi")]
+    {
+        let stream = tokio_native_tls::TlsConnector::from(tls_connector(settings)?).connect(host, stream).await?;
+        let certificate = stream.get_ref().peer_certificate()?.ok_or("no DC certificate in the TLS connection")?.to_der()?;
+        Ok((Box::new(stream), certificate))
+    }
+    #[cfg(not(feature = "nogssapi"))]
+    {
+        let server_name = rustls::ServerName::try_from(host).map_err(|_| format!("invalid DC name {host}"))?;
+        let stream = tokio_rustls::TlsConnector::from(tls_config(settings)).connect(server_name, stream).await?;
+        let certificate = stream.get_ref().1.peer_certificates()
+            .and_then(|certificates| certificates.first())
+            .ok_or("no DC certificate in the TLS connection")?
+            .0
+            .clone();
+        Ok((Box::new(stream), certificate))
+    }
+}
+
+/// Function to open a connection bound with NTLM, through the `--proxy` when set. Over LDAPS or StartTLS the
+/// bind carries the channel binding token of the DC certificate. ldap3 has no NTLM bind, the bound connection
+/// is handed over to it on a local socket.
+async fn ntlm_connect(settings: &LdapSettings, target: DcTarget<'_>, account: &NtlmAccount<'_>) -> Result<ldap3::Ldap, Box<dyn Error>> {
+    let DcTarget { url, server_name, starttls } = target;
+    let timeout = settings.timeouts.connect;
+    let timed_out = |operation: &str| format!("{operation} timed out after {}s (--connect-timeout)", timeout.as_secs());
+    let (scheme, host, _) = split_ldap_url(url)?;
+    let (_, address, port) = split_ldap_url(&proxied_url(url, settings.proxy.as_ref()).await?)?;
+    let mut stream = tokio::time::timeout(timeout, tokio::net::TcpStream::connect((address.as_str(), port))).await
+        .map_err(|_| timed_out(&format!("Connection to {url}")))?
+        .map_err(|err| format!("Can't connect to {url}: {err}"))?;
+    let starttls = starttls && scheme == "ldap";
+    if starttls {
+        tokio::time::timeout(timeout, sicily::starttls(&mut stream)).await
+            .map_err(|_| timed_out(&format!("StartTLS with {url}")))?
+            .map_err(|err| match err.downcast::<ldap3::LdapError>() {
+                Ok(err) => starttls_error(url, *err),
+                Err(err) => format!("Can't connect to {url}: {err}"),
+            })?;
+    }
+
+    let (mut stream, certificate): (Box<dyn LdapStream>, _) = if scheme == "ldaps" || starttls {
+        let host = if server_name.contains("not set") { host } else { server_name.to_owned() };
+        let (stream, certificate) = tokio::time::timeout(timeout, tls_handshake(settings, stream, &host)).await
+            .map_err(|_| timed_out(&format!("TLS handshake with {url}")))?
+            .map_err(|err| match certificate_rejection(&format!("{err} {err:?}")) {
+                Some(reason) => format!("The certificate of {url} was rejected: {reason} ({err})").into(),
+                None if starttls => format!("StartTLS accepted by {url} but the TLS handshake failed: {err}").into(),
+                None => err,
+            })?;
+        check_certificate_host(settings, &certificate, url, server_name)?;
+        (stream, Some(certificate))
+    } else {
+        (Box::new(stream), None)
+    };
+    // --nt-hash with --no-channel-binding: NTLM bind without the token
+    let token = certificate.as_deref().filter(|_| settings.channel_binding).and_then(tls_server_end_point);
+    let security = ChannelSecurity { tls: certificate.is_some(), channel_binding: token.is_some() };
+    if security.tls && !security.channel_binding && settings.channel_binding {
+        debug!("No channel binding token for the certificate of {url}, NTLM bind without it");
+    }
+    let channel_bindings = token.map(|token| channel_bindings_hash(&token));
+
+    tokio::time::timeout(timeout, sicily::ntlm_bind(&mut stream, account, channel_bindings.as_ref())).await
+        .map_err(|_| timed_out("LDAP bind"))?
+        .map_err(|err| match bind_error_hint(&err.to_string(), security, AuthMechanism::Ntlm) {
+            Some(hint) => format!("{err}. {hint}").into(),
+            None => err,
+        })?;
+    debug!("NTLM bind to {url}{}", if channel_bindings.is_some() { " with the channel binding token" } else { "" });
+
+    let local = sicily::handover(stream).await?;
+    let (conn, ldap) = LdapConnAsync::with_settings(LdapConnSettings::new().set_conn_timeout(timeout), &local).await?;
+    ldap3::drive!(conn);
+    Ok(ldap)
+}
+
+/// Function to bind a connection with the password or the NT hash of `-u`, return the authentication of the bind.
+/// Over LDAPS or StartTLS the bind is NTLM with the channel binding token, on a new connection replacing `ldap`.
+/// A bind DN, the anonymous bind and `--no-channel-binding` keep the simple bind, the NT hash is always bound with NTLM.
+async fn password_bind(
+    settings: &LdapSettings,
+    ldap: &mut ldap3::Ldap,
+    target: DcTarget<'_>,
+    username: &str,
+    secret: &BindSecret,
+) -> Result<AuthMechanism, Box<dyn Error>> {
+    let tls = target.url.starts_with("ldaps") || target.starttls;
+    let ntlm = match secret {
+        BindSecret::Password(password) => tls && settings.channel_binding && !password.is_empty(),
+        BindSecret::NtHash(_) => true,
+    };
+    let password = match (ntlm_identity(username, "").filter(|_| ntlm), secret) {
+        (Some((user, domain)), _) => {
+            let account = NtlmAccount { user: &user, domain: &domain, nt_hash: secret.nt_hash()? };
+            let bound = ntlm_connect(settings, target, &account).await?;
+            close_connection(settings, std::mem::replace(ldap, bound)).await;
+            return Ok(AuthMechanism::Ntlm)
+        }
+        (None, BindSecret::NtHash(_)) => {
+            return Err(format!("--nt-hash needs the account name of -u for the NTLM bind, not the DN {username}").into())
+        }
+        (None, BindSecret::Password(password)) => password,
+    };
+    let security = check_channel_binding(ldap, tls).await;
+    ldap.with_timeout(settings.timeouts.connect).simple_bind(username, password).await
+        .and_then(|res| res.success())
+        .map_err(|err| bind_error(settings, err, security, AuthMechanism::SimpleBind))?;
+    Ok(AuthMechanism::SimpleBind)
+}
+
+/// Retries when the connection to the DC drops or the DC is busy during a search, with an exponential backoff.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries for each naming context, 0 to stop at the first transient error.
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each next one.
+    pub delay: std::time::Duration,
+    /// Longest wait before a retry.
+    pub max_delay: std::time::Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { retries: 3, delay: std::time::Duration::from_secs(5), max_delay: std::time::Duration::from_secs(60) }
+    }
+}
+
+impl From<&Options> for RetryPolicy {
+    fn from(options: &Options) -> Self {
+        Self {
+            retries: options.retries,
+            delay: std::time::Duration::from_secs(options.retry_delay),
+            max_delay: std::time::Duration::from_secs(options.retry_max_delay),
+        }
+    }
+}
+
+/// LDAP operation requested again by [`RetryPolicy::run`] after a transient error.
+pub(crate) trait Retryable {
+    type Output;
+
+    /// Name of the operation in the warnings.
+    fn describe(&self) -> String;
+
+    /// Request the operation once.
+    async fn attempt(&mut self) -> Result<Self::Output, ldap3::LdapError>;
+}
+
+impl RetryPolicy {
+    /// Function to get the wait before the retry `attempt`, from 1: the delay doubled for each retry, up to the longest wait.
+    pub fn backoff(&self, attempt: u32) -> std::time::Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// Function to run an LDAP operation, requested again after each transient error with the backoff of the retry.
+    /// A fatal error is returned at once, the error of the first attempt once the retries are exhausted.
+    pub(crate) async fn run<R: Retryable>(&self, operation: &mut R) -> Result<R::Output, ldap3::LdapError> {
+        let mut first_error = None;
+        let mut attempts = 0;
+        loop {
+            let err = match operation.attempt().await {
+                Ok(output) => return Ok(output),
+                Err(err) => err,
+            };
+            if !is_transient_error(&err) {
+                return Err(err)
+            }
+            if attempts >= self.retries || is_interrupted() {
+                return Err(first_error.unwrap_or(err))
+            }
+            attempts += 1;
+            let wait = self.backoff(attempts);
+            warn!("{} failed ({err}), retrying in {}s ({attempts}/{})", operation.describe(), wait.as_secs(), self.retries);
+            first_error.get_or_insert(err);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Timeouts of the LDAP connections and searches, `--connect-timeout` and `--search-timeout`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LdapTimeouts {
+    /// Connection, TLS handshake, rootDSE read and bind to a DC.
+    pub connect: std::time::Duration,
+    /// Wait for each page of a search.
+    pub search: std::time::Duration,
+}
+
+impl Default for LdapTimeouts {
+    fn default() -> Self {
+        Self { connect: std::time::Duration::from_secs(30), search: std::time::Duration::from_secs(300) }
+    }
+}
+
+/// Wait between two LDAP pages and between two LDAP queries, `--throttle` and `--jitter`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Throttle {
+    /// Mean wait, no wait when zero.
+    pub delay: std::time::Duration,
+    /// Random variation of each wait, in percent of the mean wait.
+    pub jitter: u8,
+}
+
+impl From<&Options> for Throttle {
+    fn from(options: &Options) -> Self {
+        Self { delay: std::time::Duration::from_millis(options.throttle), jitter: options.jitter }
+    }
+}
+
+impl Throttle {
+    /// Function to draw the next wait, uniformly within the jitter around the mean wait.
+    fn next_delay(&self) -> std::time::Duration {
+        let delay = self.delay.as_millis() as u64;
+        let spread = delay * u64::from(self.jitter.min(100)) / 100;
+        std::time::Duration::from_millis(fastrand::u64(delay - spread..=delay + spread))
+    }
+
+    /// Function to wait before the next page or query, return the time waited.
+    async fn wait(&self) -> std::time::Duration {
+        if self.delay.is_zero() {
+            return std::time::Duration::ZERO
+        }
+        let delay = self.next_delay();
+        tokio::time::sleep(delay).await;
+        delay
+    }
+
+    /// Function to estimate the time added between `queries` queries, and to each 10000 objects received in pages of `page_size`.
+    fn estimate(&self, queries: usize, page_size: i32) -> (std::time::Duration, std::time::Duration) {
+        let between_queries = self.delay * queries.saturating_sub(1) as u32;
+        let per_10000_objects = self.delay * (10_000 / page_size.max(1)) as u32;
+        (between_queries, per_10000_objects)
+    }
+}
+
+/// LDAP_SERVER_SD_FLAGS_OID: the parts of nTSecurityDescriptor returned by the DC.
+/// <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-adts/3888c2b7-35b9-45b7-afeb-b772aa932dd0>
+pub const SD_FLAGS_OID: &str = "1.2.840.113556.1.4.801";
+
+/// Owner, group and DACL: readable by any user, unlike the SACL.
+pub const DEFAULT_SD_FLAGS: u32 = 0x7;
+
+/// SACL bit of the SD flags, needs SeSecurityPrivilege.
+const SACL_SECURITY_INFORMATION: u32 = 0x8;
+
+/// Function to parse `--sd-flags`, decimal or hexadecimal like 0x7: 1 owner, 2 group, 4 DACL, 8 SACL.
+pub fn parse_sd_flags(value: &str) -> Result<u32, String> {
+    let flags = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse::<u32>(),
+    }
+    .map_err(|_| format!("invalid SD flags {value}, expected a number like 0x7"))?;
+    if !(1..=0xF).contains(&flags) {
+        return Err(format!("SD flags {value} out of range, 1 owner, 2 group, 4 DACL and 8 SACL combined from 0x1 to 0xF"))
+    }
+    Ok(flags)
+}
+
+/// Function to get the LDAP_SERVER_SD_FLAGS_OID control: the BER value `SEQUENCE { INTEGER flags }`.
+/// Without it the DC returns the SACL too, and no SD at all to a user without SeSecurityPrivilege.
+fn sd_flags_control(settings: &LdapSettings) -> RawControl {
+    let flags = settings.sd_flags;
+    let mut integer: Vec<u8> = flags.to_be_bytes().into_iter().skip_while(|byte| *byte == 0).collect();
+    // Positive INTEGER: a leading zero when the high bit is set
+    if integer.first().is_none_or(|byte| byte & 0x80 != 0) {
+        integer.insert(0, 0);
+    }
+    let mut val = vec![0x30, integer.len() as u8 + 2, 0x02, integer.len() as u8];
+    val.extend(integer);
+    RawControl {
+        ctype: String::from(SD_FLAGS_OID),
+        crit: true,
+        val: Some(val),
+    }
+}
+
+/// Function to name the connection or the bind which got no answer before the `--connect-timeout`, the other errors are kept.
+fn timeout_error(settings: &LdapSettings, err: ldap3::LdapError, operation: &str) -> Box<dyn Error> {
+    match err {
+        ldap3::LdapError::Timeout { .. } => {
+            format!("{operation} timed out after {}s (--connect-timeout)", settings.timeouts.connect.as_secs()).into()
+        }
+        err => err.into(),
+    }
+}
+
+/// Function to name the search which got no page before the `--search-timeout`, the other errors are kept.
+fn search_timeout_error(settings: &LdapSettings, err: ldap3::LdapError, base: &str) -> Box<dyn Error> {
+    match err {
+        ldap3::LdapError::Timeout { .. } => {
+            format!("LDAP search of {base} timed out after {}s (--search-timeout)", settings.timeouts.search.as_secs()).into()
+        }
+        err => err.into(),
+    }
+}
+
+/// Function to check if an LDAP error is a lost connection, not an error returned by the DC.
+fn is_connection_error(err: &ldap3::LdapError) -> bool {
+    matches!(
+        err,
+        ldap3::LdapError::Io { .. }
+            | ldap3::LdapError::OpSend { .. }
+            | ldap3::LdapError::ResultRecv { .. }
+            | ldap3::LdapError::IdScrubSend { .. }
+            | ldap3::LdapError::Timeout { .. }
+            | ldap3::LdapError::EndOfStream
+    )
+}
+
+/// Function to check if a result returned by the DC is transient: busy (51) or unavailable (52).
+fn is_transient_result(result: &ldap3::LdapResult) -> bool {
+    matches!(result.rc, 51 | 52)
+}
+
+/// Function to check if an LDAP error is worth a retry: a lost connection, or a DC busy or unavailable. The other
+/// errors returned by the DC, like invalidCredentials (49) or insufficientAccessRights (50), are fatal.
+fn is_transient_error(err: &ldap3::LdapError) -> bool {
+    match err {
+        ldap3::LdapError::LdapResult { result } => is_transient_result(result),
+        err => is_connection_error(err),
+    }
+}
+
+/// Function to hash a DN, the paging cookie is bound to the lost connection so the query is requested again.
+fn dn_hash(dn: &str) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    std::hash::Hash::hash(dn, &mut hasher);
+    std::hash::Hasher::finish(&hasher)
+}
+
+/// Function to open a connection to a domain controller, checked to belong to the domain before the bind
+/// when a list of DCs is given (anonymous rootDSE read).
+async fn connect_domain_controller(settings: &LdapSettings, url: &str, ldapfqdn: &str, starttls: bool, domain: Option<&str>) -> Result<ldap3::Ldap, Box<dyn Error>> {
+    let mut ldap = ldap_connect(settings, url, ldapfqdn, starttls).await?;
+    if let Some(domain) = domain {
+        ldap.with_timeout(settings.timeouts.connect);
+        let root_dse = get_root_dse(&mut ldap).await?;
+        let naming_context = root_dse.get("defaultNamingContext")
+            .and_then(|v| v.first())
+            .ok_or("no defaultNamingContext in the rootDSE")?;
+        if !is_domain_of(&root_dse, domain) {
+            return Err(format!("the DC belongs to {naming_context}, not to {domain}").into())
+        }
+    }
+    Ok(ldap)
+}
+
+/// Function to get the name of a domain controller shown to the user, its FQDN or its LDAP url.
+fn domain_controller_name<'a>(url: &'a str, ldapfqdn: &'a str) -> &'a str {
+    if ldapfqdn.contains("not set") { url } else { ldapfqdn }
+}
+
+/// Function to connect again with the same bind as the lost connection, on the same or the next DC.
+async fn reconnect(
+    settings: &LdapSettings,
+    url: &str,
+    args: &LdapArgs,
     ldapfqdn: &str,
-    username: Option<&str>,
-    password: Option<&str>,
+    starttls: bool,
     kerberos: bool,
-    ldapfilter: &str,
+    domain: Option<&str>,
+) -> Result<ldap3::Ldap, Box<dyn Error>> {
+    let ldap = bind_domain_controller(settings, url, args, ldapfqdn, starttls, kerberos, domain).await?;
+    info!("Reconnected to {}", domain_controller_name(url, ldapfqdn).bold().green());
+    settings.record_domain_controller(domain_controller_name(url, ldapfqdn));
+    Ok(ldap)
+}
+
+/// Function to connect to a domain controller with the same bind as the main connection.
+async fn bind_domain_controller(
+    settings: &LdapSettings,
+    url: &str,
+    args: &LdapArgs,
+    ldapfqdn: &str,
+    starttls: bool,
+    kerberos: bool,
+    domain: Option<&str>,
+) -> Result<ldap3::Ldap, Box<dyn Error>> {
+    let mut ldap = connect_domain_controller(settings, url, ldapfqdn, starttls, domain).await?;
+    if settings.has_client_certificate() {
+        certificate_bind(settings, &mut ldap).await?;
+    } else if kerberos {
+        #[cfg(not(feature = "nogssapi"))]
+        {
+            let security = check_channel_binding(&mut ldap, url.starts_with("ldaps") || starttls).await;
+            ldap.with_timeout(settings.timeouts.connect).sasl_gssapi_bind(ldapfqdn).await
+                .and_then(|res| res.success())
+                .map_err(|err| bind_error(settings, err, security, AuthMechanism::Kerberos))?;
+        }
+        #[cfg(feature = "nogssapi")]
+        return Err(format!("Kerberos auth and GSSAPI not compatible with current os, can't reconnect to {ldapfqdn}").into());
+    } else {
+        let target = DcTarget { url, server_name: ldapfqdn, starttls };
+        password_bind(settings, &mut ldap, target, &args.s_username, &args.s_password).await?;
+    }
+    Ok(ldap)
+}
+
+/// Function to get why the DC certificate was rejected from the TLS error of rustls or native-tls
+/// (OpenSSL, SChannel or Security framework).
+fn certificate_rejection(error: &str) -> Option<&'static str> {
+    let error = error.to_lowercase();
+    let reason = if error.contains("expired") {
+        "expired"
+    } else if error.contains("notvalidyet") || error.contains("not yet valid") {
+        "not valid yet"
+    } else if error.contains("revoked") {
+        "revoked"
+    } else if ["unknownissuer", "unable to get local issuer", "self-signed", "self signed", "not trusted", "untrusted"]
+        .iter()
+        .any(|issuer| error.contains(issuer))
+    {
+        "unknown issuer, trust the enterprise root CA with --ca-cert or disable the validation with --insecure"
+    } else if error.contains("notvalidforname") || error.contains("hostname mismatch") {
+        "name mismatch, use --no-hostname-verify"
+    } else {
+        return None
+    };
+    Some(reason)
+}
+
+/// Function to explain a failed connection, never continued in cleartext when StartTLS is refused.
+fn connection_error(settings: &LdapSettings, url: &str, starttls: bool, err: ldap3::LdapError) -> Box<dyn Error> {
+    if matches!(err, ldap3::LdapError::Timeout { .. }) {
+        timeout_error(settings, err, &format!("Connection to {url}"))
+    } else if let Some(reason) = certificate_rejection(&format!("{err} {:?}", err)) {
+        format!("The certificate of {url} was rejected: {reason} ({err})").into()
+    } else if starttls && url.starts_with("ldap://") {
+        starttls_error(url, err).into()
+    } else {
+        err.into()
+    }
+}
+
+/// Function to explain a failed StartTLS upgrade: the extended operation refused by the DC (no certificate
+/// on the DC, or LDAPS only), or the TLS handshake failed once accepted.
+fn starttls_error(url: &str, err: ldap3::LdapError) -> String {
+    match &err {
+        ldap3::LdapError::LdapResult { result } => {
+            format!("StartTLS not supported by {url}, the DC refused the upgrade ({result}), try --ldaps")
+        }
+        // Before the StartTLS request
+        ldap3::LdapError::Io { source } if source.kind() != io::ErrorKind::InvalidData => {
+            format!("Can't connect to {url}: {err}")
+        }
+        _ => format!("StartTLS accepted by {url} but the TLS handshake failed: {err}"),
+    }
+}
+
+/// Function to request all AD values of the domain of the options, on its DC (`-f`, `-i`) then the fallback DCs.
+/// The configuration and schema naming contexts are requested too with `forest_wide`, the categories in the
+/// `collection_order` of the `--deadline`. A failed bind or a collection without any object is an error.
+pub async fn ldap_search<S: Storage<LdapSearchEntry>>(
+    options: &Options,
+    settings: &LdapSettings,
+    forest_wide: bool,
+    collection_order: Option<&[&CollectionCategory]>,
     storage: &mut S,
 ) -> Result<usize, Box<dyn Error>> {
-    // Construct LDAP args
-    let ldap_args = ldap_constructor(
-        ldaps, ip, port, domain, ldapfqdn, username, password, kerberos,
+    let (ldaps, starttls, port, gc, domain) = (options.ldaps, options.starttls, options.port, options.gc, options.domain.as_str());
+    let (ip, ldapfqdn) = (options.ip.as_deref(), options.ldapfqdn.as_str());
+    let (username, password) = (options.username.as_deref(), options.bind_secret());
+    let (anonymous, all_attributes, custom_attributes) = (options.anonymous, options.all_attributes, options.config.custom_attributes());
+    let throttle = &Throttle::from(options);
+    let retry = &RetryPolicy::from(options);
+    let ldapfilter = &types_filter(&methods_filter(&options.object_filter(), options.collection_method), &options.excluded_types);
+    let ldapfilter = ldapfilter.as_str();
+    // Construct LDAP args, no prompt with a client certificate
+    let certificate = settings.has_client_certificate();
+    // Global Catalog: the whole forest on 3268 or 3269
+    let search_port = if gc { Some(gc_port(ldaps)) } else { port };
+    let mut ldap_args = ldap_constructor(
+        ldaps, ip, search_port, domain, options.bind_dn.as_deref().or(username), password.clone(), options.kerberos || certificate || anonymous,
     )?;
+    // Anonymous: empty name and password for the main bind, the reconnections and the Global Catalog
+    if anonymous {
+        ldap_args.s_username.clear();
+        ldap_args.s_password = BindSecret::default();
+    }
+    if certificate && !ldap_args.s_url.starts_with("ldaps") && !starttls {
+        return Err("The client certificate is only sent over TLS, please use --ldaps or --starttls".into())
+    }
+
+    // Domain controllers: the first one, then the fallback DCs when it is unavailable
+    let mut targets = vec![(ldap_args.s_url.to_owned(), ldapfqdn.to_owned())];
+    targets.extend(options.fallback_dcs.iter().map(|dc| (prepare_ldap_url(ldaps, Some(dc), search_port, domain), dc.to_owned())));
+    let check_domain = (targets.len() > 1).then_some(domain);
+    let mut target = 0;
 
     // LDAP connection
-    let consettings = LdapConnSettings::new()
-        .set_conn_timeout(std::time::Duration::from_secs(10))
-        .set_no_tls_verify(true);
-    let (conn, mut ldap) = LdapConnAsync::with_settings(consettings, &ldap_args.s_url).await?;
-    ldap3::drive!(conn);
+    let mut ldap = loop {
+        let (url, fqdn) = &targets[target];
+        match connect_domain_controller(settings, url, fqdn, starttls, check_domain).await {
+            Ok(ldap) => break ldap,
+            Err(err) if target + 1 < targets.len() => {
+                let (next_url, next_fqdn) = &targets[target + 1];
+                warn!(
+                    "Domain controller {} unavailable ({err}), trying {}",
+                    domain_controller_name(url, fqdn).bold().yellow(),
+                    domain_controller_name(next_url, next_fqdn).bold()
+                );
+                target += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    };
+    let (mut url, mut ldapfqdn) = targets[target].to_owned();
+    let security = check_channel_binding(&mut ldap, url.starts_with("ldaps") || starttls).await;
+    if security.tls && !security.channel_binding {
+        warn!("No channel binding token for the DC certificate, the bind fails if the DC enforces channel binding");
+    }
 
-    if !kerberos {
-        debug!("Trying to connect with simple_bind() function (username:password)");
-        let res = ldap
-            .simple_bind(&ldap_args.s_username, &ldap_args.s_password)
-            .await?
-            .success();
-        match res {
-            Ok(_res) => {
+    // Kerberos: false once bound with the password, the reconnections bind with it then
+    #[cfg_attr(feature = "nogssapi", allow(unused_mut))]
+    let mut kerberos = options.kerberos;
+    let mechanism;
+    if certificate {
+        debug!("Trying to connect with sasl_external_bind() function (client certificate)");
+        match certificate_bind(settings, &mut ldap).await {
+            Ok(identity) => {
+                mechanism = AuthMechanism::ClientCertificate;
                 info!(
-                    "Connected to {} Active Directory!",
-                    domain.to_uppercase().bold().green()
+                    "Connected to {} Active Directory as {}!",
+                    domain.to_uppercase().bold().green(),
+                    identity.bold()
                 );
                 info!("Starting data collection...");
             }
             Err(err) => {
-                error!(
-                    "Failed to authenticate to {} Active Directory. Reason: {err}\n",
-                    domain.to_uppercase().bold().red()
+                return Err(format!("Failed to authenticate to {} Active Directory. Reason: {err}", domain.to_uppercase()).into())
+            }
+        }
+    } else if !kerberos {
+        if anonymous {
+            debug!("Trying to connect with simple_bind() function (anonymous)");
+        } else {
+            debug!("Trying to connect with the password (username:password)");
+        }
+        let target = DcTarget { url: &url, server_name: &ldapfqdn, starttls };
+        match password_bind(settings, &mut ldap, target, &ldap_args.s_username, &ldap_args.s_password).await {
+            Ok(bound) => {
+                settings.set_bind_credentials(&ldap_args.s_username, &ldap_args.s_password);
+                mechanism = if anonymous { AuthMechanism::Anonymous } else { bound };
+                info!(
+                    "Connected to {} Active Directory{}!",
+                    domain.to_uppercase().bold().green(),
+                    if anonymous { " anonymously" } else { "" }
                 );
-                process::exit(0x0100);
+                info!("Starting data collection...");
+            }
+            Err(err) => {
+                return Err(format!("Failed to authenticate to {} Active Directory. Reason: {err}", domain.to_uppercase()).into())
             }
         }
     } else {
         debug!("Trying to connect with sasl_gssapi_bind() function (kerberos session)");
         if !&ldapfqdn.contains("not set") {
             #[cfg(not(feature = "nogssapi"))]
-            gssapi_connection(&mut ldap, &ldapfqdn, &domain).await?;
-            #[cfg(feature = "nogssapi")]
             {
-                error!("Kerberos auth and GSSAPI not compatible with current os!");
-                process::exit(0x0100);
+                // -u/-p for the password bind when the Kerberos authentication fails
+                let fallback = !options.kerberos_only && username.is_some() && password.is_some();
+                let target = DcTarget { url: &url, server_name: &ldapfqdn, starttls };
+                mechanism = kerberos_bind(settings, &mut ldap, target, domain, &ldap_args, fallback, security).await?;
+                kerberos = mechanism == AuthMechanism::Kerberos;
             }
+            #[cfg(feature = "nogssapi")]
+            return Err("Kerberos auth and GSSAPI not compatible with current os!".into());
         } else {
-            error!(
-                "Need Domain Controller FQDN to bind GSSAPI connection. Please use '{}'\n",
-                "-f DC01.DOMAIN.LAB".bold()
-            );
-            process::exit(0x0100);
+            return Err("Need Domain Controller FQDN to bind GSSAPI connection. Please use '-f DC01.DOMAIN.LAB'".into())
         }
     }
 
+    settings.record_auth_mechanism(mechanism);
+    settings.record_domain_controller(domain_controller_name(&url, &ldapfqdn));
+
     // // Prepare LDAP result vector
     let mut total = 0; // for progress bar
+    // Pages received, lowered page size after a sizeLimitExceeded or adminLimitExceeded result
+    let mut pages = 0;
+    let mut page_size = options.page_size;
 
-    // Request all namingContexts for current DC
+    // rootDSE: vendor and configuration NC state
+    let root_dse = match get_root_dse(&mut ldap).await {
+        Ok(root_dse) => root_dse,
+        Err(err) => {
+            debug!("Unable to read the rootDSE: {err}");
+            HashMap::new()
+        }
+    };
+
+    // Samba AD answers like a Windows DC with a few differences
+    let samba = options.samba || root_dse.get("vendorName")
+        .and_then(|v| v.first())
+        .is_some_and(|vendor| is_samba_vendor(vendor));
+    if samba {
+        info!("Samba AD compatibility mode: {}", "enabled".bold().yellow());
+    }
+
+    // DC and levels of the collection, recorded in the output meta
+    let server = RootDse::from_attributes(&root_dse);
+    if let (Some(domain_level), Some(forest_level)) = (server.domainfunctionality, server.forestfunctionality) {
+        info!(
+            "Domain functional level {}, forest functional level {}",
+            functional_level_name(domain_level).bold(),
+            functional_level_name(forest_level).bold()
+        );
+    }
+    // LDAP_SERVER_SD_FLAGS_OID, nTSecurityDescriptor is only returned with it
+    if !server.supportedcontrols.is_empty() && !server.supports_control(SD_FLAGS_OID) {
+        warn!("The DC doesn't support the SD flags control, the ACLs may be missing");
+    }
+    let sd_flags = settings.sd_flags;
+    if sd_flags & SACL_SECURITY_INFORMATION != 0 {
+        warn!("SACL requested with --sd-flags {sd_flags:#x}: without SeSecurityPrivilege the DC returns no nTSecurityDescriptor");
+    }
+
+    // Request all namingContexts for current DC, with the default and configuration ones of the rootDSE
     let res = match get_all_naming_contexts(&mut ldap).await {
         Ok(res) => {
             trace!("naming_contexts: {:?}", &res);
-            res
-        }
-        Err(err) => {
-            error!("No namingContexts found! Reason: {err}\n");
-            process::exit(0x0100);
+            server.search_bases(&res)
         }
+        Err(err) => return Err(format!("No namingContexts found! Reason: {err}").into()),
     };
+    let default_nc = server.defaultnamingcontext.to_owned();
+    // --search-base: the subtrees of the domain partition instead of the whole domain
+    let scoped = !options.search_base.is_empty() && !gc;
+    let search_bases: Vec<String> = dedup_search_bases(&options.search_base).into_iter()
+        .filter(|base| {
+            let under = default_nc.is_empty() || is_under(base, &default_nc);
+            if !under {
+                warn!("{} is not in the domain partition {default_nc}, not requested", base.bold().yellow());
+            }
+            under
+        })
+        .collect();
+    if scoped && search_bases.is_empty() {
+        return Err(format!("No --search-base in the domain partition {default_nc}").into())
+    }
+    let scoped_bases = server.scoped_search_bases(&res, &search_bases);
+    // Another domain of a --forest collection: the configuration, the schema and the forest DNS zones are already
+    // collected from the DC of -d, its rootDSE isn't the one of the output meta
+    if forest_wide {
+        record_root_dse(server);
+    }
+
+    // Attributes read by the parsers, or "*" with --all-attributes, the security descriptors with the ACL method
+    // Custom attributes are added for the operational ones not returned by "*"
+    let mut attributes: Vec<&str> = search_attributes(all_attributes, options.collection_method.contains(CollectionMethod::ACL));
+    for attribute in &custom_attributes {
+        if samba && SAMBA_UNSUPPORTED_ATTRIBUTES.iter().any(|a| a.eq_ignore_ascii_case(attribute)) {
+            warn!("{} not supported by Samba AD, not requested", attribute.bold().yellow());
+            continue;
+        }
+        if !attributes.iter().any(|a| a.eq_ignore_ascii_case(attribute)) {
+            attributes.push(attribute);
+        }
+    }
+    // --dns: the records of the DNS nodes
+    if options.dns && !all_attributes {
+        for attribute in DNS_NODE_ATTRIBUTES {
+            if !attributes.iter().any(|a| a.eq_ignore_ascii_case(attribute)) {
+                attributes.push(attribute);
+            }
+        }
+    }
+    record_requested(&attributes);
+
+    // Configuration NC state, PKI objects are counted during the search
+    let mut config_nc = ConfigNcStatus::from_root_dse(&root_dse);
+    if config_nc.dc.is_empty() {
+        config_nc.dc = ldapfqdn.to_owned();
+    }
+    let mut config_nc_dns: HashSet<String> = HashSet::new();
+    // A query given up after the retries, its objects are partial
+    let mut connection_lost = false;
+    // Continuation references to the naming contexts of the child domains
+    let mut referrals = ReferralQueue::new(&res);
 
     // namingContexts: DC=domain,DC=local
     // namingContexts: CN=Configuration,DC=domain,DC=local (needed for AD CS datas)
+    // Global Catalog: one query from the root for all the domains of the forest
+    let mut naming_contexts = match (gc, scoped) {
+        (true, _) => vec![String::new()],
+        (false, true) => {
+            info!("Collection limited to {}", search_bases.join(" ; ").bold().green());
+            scoped_bases
+        }
+        (false, false) if forest_wide => res.to_owned(),
+        (false, false) => res.iter().filter(|nc| is_under(nc, &default_nc)).cloned().collect(),
+    };
+    if gc {
+        info!("Global Catalog collection of the forest from {}", url.bold().green());
+    }
+    // The deleted objects of the domain, only returned with the Show Deleted Objects control
+    if options.deleted_objects && !gc && !default_nc.is_empty() {
+        let deleted = deleted_objects_dn(&default_nc);
+        info!("Requesting the deleted objects of {}", deleted.bold().green());
+        naming_contexts.push(deleted);
+    }
+    // --adcs-only: the PKI container of the configuration partition, the domain object is prepended to the plan
+    let adcs_only = options.adcs_only && !gc && !config_nc.naming_context.is_empty();
+    if adcs_only {
+        naming_contexts = vec![config_nc.public_key_services()];
+        info!("Collection limited to {}", naming_contexts[0].bold().green());
+    }
+    // --dns: the forest DNS zones of a child domain, not under its domain partition
+    if options.dns && !gc && !adcs_only {
+        for partition in res.iter().filter(|nc| is_dns_partition(nc)) {
+            if !naming_contexts.iter().any(|nc| nc.eq_ignore_ascii_case(partition)) {
+                info!("Requesting the DNS zones of {}", partition.bold().green());
+                naming_contexts.push(partition.to_owned());
+            }
+        }
+    }
+    // Custom filter: checked by the DC before any object is requested
+    if ldapfilter != DEFAULT_LDAP_FILTER && !default_nc.is_empty() {
+        debug!("Checking the LDAP filter {ldapfilter} on {default_nc}");
+        check_ldap_filter(&mut ldap, &default_nc, ldapfilter).await?;
+    }
+    // --cache-state: only the objects changed since the USN recorded on this DC
+    let mut delta = match gc {
+        true => DeltaSearch::default(),
+        false => DeltaSearch::new(&root_dse, ldapfilter, &naming_contexts),
+    };
+    // Entries of the Global Catalog, completed with the attributes not replicated to the GC before they are stored
+    let mut gc_entries: Vec<SearchEntry> = Vec::new();
+    // One query per naming context, or per category and naming context with --deadline
+    let mut plan = match collection_order {
+        Some(order) => QueryPlan::new(&naming_contexts, order, ldapfilter, deadline_cutoff()),
+        None => QueryPlan::single(&naming_contexts, ldapfilter),
+    };
+    // Domain object of a --search-base or --adcs-only collection, for the domain SID and properties
+    if (scoped && !search_bases.iter().any(|base| base.eq_ignore_ascii_case(&default_nc))) || (adcs_only && !default_nc.is_empty()) {
+        let category = if collection_order.is_some() { "domain" } else { "" };
+        plan.prepend(category, &default_nc, "(objectClass=domain)");
+    }
+    // Throttling: time added to the collection, estimated before the first query
+    let mut throttled = std::time::Duration::ZERO;
+    let mut queries = 0;
+    if !throttle.delay.is_zero() {
+        let (between_queries, per_10000_objects) = throttle.estimate(plan.query_count(), page_size);
+        info!(
+            "Throttling {}ms ±{}% between LDAP pages and queries: about {} added for {} queries, plus {} every 10000 objects",
+            throttle.delay.as_millis(),
+            throttle.jitter,
+            format_duration(between_queries).bold(),
+            plan.query_count(),
+            format_duration(per_10000_objects).bold()
+        );
+    }
     if res.iter().any(|s| s.contains("Configuration")) {
-        for cn in &res {
-            // Set control LDAP_SERVER_SD_FLAGS_OID to get nTSecurityDescriptor
-            // https://ldapwiki.com/wiki/LDAP_SERVER_SD_FLAGS_OID
-            let ctrls = RawControl {
-                ctype: String::from("1.2.840.113556.1.4.801"),
-                crit: true,
-                val: Some(vec![48, 3, 2, 1, 5]),
-            };
-            ldap.with_controls(ctrls.to_owned());
-
+        while let Some((category, cn, filter)) = plan.next_query() {
+            let cn = &cn;
+            // Interrupted: no new query
+            if is_interrupted() {
+                warn!("Interrupted, {} not requested", cn.bold().yellow());
+                delta.finish_query(cn, false);
+                continue;
+            }
+            // Throttling: wait before the next query
+            if queries > 0 {
+                throttled += throttle.wait().await;
+            }
+            queries += 1;
             // Prepare filter
             // let mut _s_filter: &str = "";
             // if cn.contains("Configuration") {
@@ -130,79 +1291,483 @@ pub async fn ldap_search<S: Storage<LdapSearchEntry>>(
             // }
             //let _s_filter = "(objectClass=*)";
             //let _s_filter = "(objectGuid=*)";
-            info!("Ldap filter : {}", ldapfilter.bold().green());
-            let _s_filter = ldapfilter;
-
-            // Every 999 max value in ldap response (err 4 ldap)
-            let adapters: Vec<Box<dyn Adapter<_, _>>> = vec![
-                Box::new(EntriesOnly::new()),
-                Box::new(PagedResults::new(999)),
-            ];
-
-            // Streaming search with adaptaters and filters
-            let mut search = ldap
-                .streaming_search_with(
-                    adapters, // Adapter which fetches Search results with a Paged Results control.
-                    cn,
-                    Scope::Subtree,
-                    _s_filter,
-                    vec!["*", "nTSecurityDescriptor"],
-                    // Without the presence of this control, the server returns an SD only when the SD attribute name is explicitly mentioned in the requested attribute list.
-                    // https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-adts/932a7a8d-8c93-4448-8093-c79b7d9ba499
-                )
-                .await?;
+            let full_filter = filter;
+            let mut filter = delta.filter(cn, &full_filter);
+            if category.is_empty() {
+                info!("Ldap filter : {}", filter.bold().green());
+            } else {
+                info!("Requesting {} on {}", category.bold().green(), cn);
+                debug!("Ldap filter : {filter}");
+            }
 
             // Wait and get next values
             let pb = ProgressBar::new(1);
             let mut count = 0;
-            while let Some(entry) = search.next().await? {
-                let entry = SearchEntry::construct(entry);
-                //trace!("{:?}", &entry);
-                total += 1;
-                // Manage progress bar
-                count += 1;
-                progress_bar(
-                    pb.to_owned(),
-                    "LDAP objects retrieved".to_string(),
-                    count,
-                    "#".to_string(),
-                );
+            let is_config_nc = cn.eq_ignore_ascii_case(&config_nc.naming_context)
+                || (adcs_only && cn.eq_ignore_ascii_case(&config_nc.public_key_services()));
+            // Entries already stored, skipped when the query is requested again after a reconnection
+            let mut received: HashSet<u64> = HashSet::new();
+            let mut attempts = 0;
+            let mut first_error: Option<String> = None;
+            let resumable = retry.retries > 0 || targets.len() > 1 || page_size > MIN_PAGE_SIZE;
+            // Result of the last page, once all the pages are received
+            let mut finished = None;
+            let mut query_pages = 0;
+            // Entries with a part of the values of an attribute, completed once the pages are received
+            let mut ranged: Vec<SearchEntry> = Vec::new();
+            let completed = loop {
+                // One page at a time, the next one requested with the cookie of the previous one
+                let source = LdapPages { settings, ldap: &mut ldap, base: cn, filter: &filter, attributes: &attributes, show_deleted: is_under_deleted_objects(cn) };
+                let mut search = PagedSearch::new(source, page_size);
+                let err = loop {
+                    let page = match search.next_page().await {
+                        Ok(Some(page)) => page,
+                        Ok(None) => break None,
+                        Err(err) => break Some(err),
+                    };
+                    query_pages += 1;
+                    for entry in page.entries {
+                        if resumable && !received.insert(dn_hash(&entry.dn)) {
+                            continue;
+                        }
+                        if is_config_nc {
+                            config_nc.observe(&entry);
+                            config_nc_dns.insert(entry.dn.to_uppercase());
+                        }
+                        //trace!("{:?}", &entry);
+                        total += 1;
+                        // Manage progress bar
+                        count += 1;
+                        progress_bar(
+                            pb.to_owned(),
+                            "LDAP objects retrieved".to_string(),
+                            count,
+                            "#".to_string(),
+                        );
 
-                storage.add(entry.into())?;
-            }
+                        if has_ranged_attributes(&entry) {
+                            ranged.push(entry);
+                        } else if gc {
+                            gc_entries.push(entry);
+                        } else {
+                            storage.add(entry.into())?;
+                        }
+                    }
+                    if search.is_finished() {
+                        // DC busy or unavailable: retried like a lost connection
+                        if is_transient_result(&page.result) {
+                            break Some(ldap3::LdapError::LdapResult { result: page.result });
+                        }
+                        finished = Some(page.result);
+                        break None;
+                    }
+                    // Interrupted or deadline reached: no new page requested
+                    if is_interrupted() || plan.is_expired() {
+                        break None;
+                    }
+                    // Throttling: wait before the next page request
+                    throttled += throttle.wait().await;
+                };
+                let Some(err) = err else {
+                    // Pages refused by the DC: the query is requested again with smaller pages
+                    let refused = finished.as_ref().is_some_and(is_limit_exceeded);
+                    if let (true, Some(lower)) = (refused, lower_page_size(page_size)) {
+                        warn!(
+                            "{} refused pages of {page_size} entries on {} after {count} entries, requesting it again with pages of {lower}",
+                            domain_controller_name(&url, &ldapfqdn).bold().yellow(),
+                            cn.bold().yellow()
+                        );
+                        page_size = lower;
+                        finished = None;
+                        continue;
+                    }
+                    break true
+                };
+                if !is_transient_error(&err) {
+                    pb.finish_and_clear();
+                    return Err(err.into())
+                }
+                // No page before the --search-timeout: handled like a lost connection
+                let lost = match &err {
+                    ldap3::LdapError::Timeout { .. } => {
+                        format!("LDAP search timed out after {}s (--search-timeout)", settings.timeouts.search.as_secs())
+                    }
+                    ldap3::LdapError::LdapResult { result } => format!("Domain controller busy (rc={})", result.rc),
+                    _ => "LDAP connection lost".to_string(),
+                };
+                // Reported when the retries are exhausted
+                let original = first_error.get_or_insert_with(|| err.to_string()).to_owned();
+                // Retries exhausted on this DC: the query is requested again on the next DC of the list
+                if attempts >= retry.retries && target + 1 < targets.len() && !is_interrupted() {
+                    target += 1;
+                    attempts = 0;
+                    (url, ldapfqdn) = targets[target].to_owned();
+                    // The USNs of the next DC are not the ones of the state file
+                    delta.dc_changed();
+                    filter = full_filter.to_owned();
+                    warn!(
+                        "{lost} on {} after {count} entries ({err}), switching to the domain controller {}",
+                        cn.bold().yellow(),
+                        domain_controller_name(&url, &ldapfqdn).bold()
+                    );
+                // Retries exhausted: the entries already stored are kept
+                } else if attempts >= retry.retries || is_interrupted() {
+                    add_collection_warning(&format!("{lost} on {cn} after {count} entries: {original}"));
+                    break false;
+                } else {
+                    attempts += 1;
+                    let wait = retry.backoff(attempts);
+                    warn!(
+                        "{lost} on {} after {count} entries ({err}), reconnecting in {}s ({attempts}/{})",
+                        cn.bold().yellow(),
+                        wait.as_secs(),
+                        retry.retries
+                    );
+                    tokio::time::sleep(wait).await;
+                }
+                // A failed reconnection is one more attempt
+                match reconnect(settings, &url, &ldap_args, &ldapfqdn, starttls, kerberos, check_domain).await {
+                    Ok(reconnected) => ldap = reconnected,
+                    Err(err) => warn!("Reconnection to {} failed: {err}", domain_controller_name(&url, &ldapfqdn)),
+                }
+            };
             pb.finish_and_clear();
+            pages += query_pages;
+            // More values than the MaxValRange of the DC, like the members of Domain Users: the ranges left
+            for mut entry in ranged {
+                let mut reader = LdapRangeReader { settings, ldap: &mut ldap, retry };
+                if let Err(err) = complete_ranges(&mut reader, &mut entry).await {
+                    add_collection_warning(&format!("Values of {} not all collected: {err}", entry.dn));
+                }
+                if gc {
+                    gc_entries.push(entry);
+                } else {
+                    storage.add(entry.into())?;
+                }
+            }
+            emit(Event::QueryFinished { naming_context: cn.to_string(), entries: count });
+
+            if !completed {
+                warn!("Partial data collected for NamingContext {}", &cn.bold().yellow());
+                plan.finish(category, false);
+                delta.finish_query(cn, false);
+                connection_lost = true;
+                continue;
+            }
+            // Interrupted or deadline reached before the last page
+            let Some(res) = finished else {
+                warn!("Partial data collected for NamingContext {}", &cn.bold().yellow());
+                plan.finish(category, false);
+                delta.finish_query(cn, false);
+                continue;
+            };
+            plan.finish(category, true);
+            referrals.push(&res.refs, 1);
+            match res.success() {
+                Ok(_res) => {
+                    info!("All data collected for NamingContext {} ({query_pages} pages)", &cn.bold());
+                    delta.finish_query(cn, true);
+                }
+                Err(err) => {
+                    error!("No data collected on {}! Reason: {err}", &cn.bold().red());
+                    delta.finish_query(cn, false);
+                }
+            }
+        }
+        // // If no result exit program
+        // if rs.is_empty() {
+        //     process::exit(0x0100);
+        // }
+    }
+    // Searches done: no idle connection left to a firewall while the entries are parsed
+    close_connection(settings, ldap).await;
+
+    // Child domains: the referred naming contexts on a DC of their domain
+    if options.follow_referrals && !is_interrupted() && !plan.is_expired() {
+        let chase = ReferralChase {
+            settings,
+            ldaps,
+            starttls,
+            port,
+            kerberos,
+            page_size,
+            args: &ldap_args,
+            ldapfilter,
+            attributes: &attributes,
+            throttle,
+        };
+        let (entries, chase_pages, chase_throttled) = chase.follow(&mut referrals, storage).await?;
+        total += entries;
+        pages += chase_pages;
+        throttled += chase_throttled;
+    } else {
+        let ignored: Vec<&str> = referrals.pending().map(|referral| referral.base.as_str()).collect();
+        if !ignored.is_empty() && !gc {
+            info!(
+                "Referrals to {} not followed, use {} to collect the child domains",
+                ignored.join(", ").bold().yellow(),
+                "--follow-referrals".bold()
+            );
+        }
+    }
+
+    // Categories not collected before the deadline, the PKI objects can't be checked then
+    let skipped = plan.skipped();
+    record_skipped(&skipped);
+    delta.finish(skipped.is_empty() && !connection_lost && !is_interrupted());
+
+    // Incomplete configuration NC (RODC, stale replica): PKI objects from a writable forest root DC
+    let cert_services = options.collection_method.contains(CollectionMethod::CERT_SERVICES);
+    if !gc && forest_wide && !is_interrupted() && skipped.is_empty() && !connection_lost && cert_services {
+        let mut fallback = LdapConfigNcFallback {
+            settings,
+            ldaps,
+            starttls,
+            port,
+            kerberos,
+            domain,
+            page_size,
+            attributes: &attributes,
+            args: &ldap_args,
+            credentials: options.credentials_for("forest-root"),
+            own_args: None,
+        };
+        let retry = retry_config_nc(&config_nc, &config_nc_dns, &mut fallback).await;
+        for warning in &retry.warnings {
+            add_collection_warning(warning);
+        }
+        if !retry.entries.is_empty() {
+            emit(Event::QueryFinished { naming_context: config_nc.public_key_services(), entries: retry.entries.len() as u64 });
+        }
+        for entry in retry.entries {
+            total += 1;
+            storage.add(entry.into())?;
+        }
+    }
+
+    // Global Catalog: attributes not replicated to the GC from a DC of each domain
+    if gc && !gc_entries.is_empty() {
+        if !is_interrupted() {
+            let supplement = GcSupplement {
+                settings,
+                ldaps,
+                starttls,
+                ip,
+                port,
+                domain,
+                ldapfqdn: &targets[0].1,
+                kerberos,
+                page_size,
+                args: &ldap_args,
+                custom_attributes: &custom_attributes,
+            };
+            supplement.complete(&mut gc_entries, &root_dse).await;
+        }
+        for entry in gc_entries {
+            storage.add(entry.into())?;
+        }
+    }
+
+    if total == 0 {
+        return Err("No LDAP objects found!".into())
+    }
+
+    storage.flush()?;
+    info!("{} LDAP objects retrieved in {} pages of {page_size} entries", total.to_string().bold(), pages.to_string().bold());
+    if !throttled.is_zero() {
+        info!("Throttling added {} to the collection", format_duration(throttled).bold());
+    }
+
+    // Return the vector with the result
+    Ok(total)
+}
+
+/// Requests of the attributes not replicated to the Global Catalog, on a DC of each domain of the forest.
+struct GcSupplement<'a> {
+    settings: &'a LdapSettings,
+    ldaps: bool,
+    starttls: bool,
+    ip: Option<&'a str>,
+    port: Option<u16>,
+    domain: &'a str,
+    ldapfqdn: &'a str,
+    kerberos: bool,
+    page_size: i32,
+    args: &'a LdapArgs,
+    custom_attributes: &'a [String],
+}
+
+impl GcSupplement<'_> {
+    /// Function to complete the Global Catalog entries, a domain not reachable keeps its GC attributes only.
+    async fn complete(&self, entries: &mut [SearchEntry], root_dse: &HashMap<String, Vec<String>>) {
+        let index: HashMap<String, usize> = entries.iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.dn.to_uppercase(), i))
+            .collect();
+        let naming_contexts = supplement_naming_contexts(entries, root_dse, self.domain);
+        let all: Vec<String> = naming_contexts.iter().map(|(nc, _)| nc.to_owned()).collect();
+        for (nc, nc_domain) in &naming_contexts {
+            if is_interrupted() {
+                break
+            }
+            let mut attributes: Vec<&str> = GC_SUPPLEMENT_ATTRIBUTES.to_vec();
+            attributes.extend(self.custom_attributes.iter().map(String::as_str));
+            // The GC doesn't return the security descriptor of every object
+            let missing_sd = entries.iter()
+                .filter(|entry| naming_context_of(&entry.dn, &all) == Some(nc.as_str()))
+                .any(|entry| !entry.bin_attrs.contains_key("nTSecurityDescriptor"));
+            if missing_sd {
+                attributes.push("nTSecurityDescriptor");
+            }
+            match self.search(nc, nc_domain, &attributes).await {
+                Ok(supplement) => {
+                    let mut merged = 0;
+                    for entry in supplement {
+                        if let Some(&i) = index.get(&entry.dn.to_uppercase()) {
+                            merge_entry(&mut entries[i], entry);
+                            merged += 1;
+                        }
+                    }
+                    info!("{merged} objects of {} completed with the attributes not replicated to the Global Catalog", nc.bold());
+                }
+                Err(err) => add_collection_warning(&format!("Attributes not replicated to the Global Catalog not collected on {nc}: {err}")),
+            }
+        }
+    }
+
+    /// Function to request the attributes of a naming context on a DC of its domain: the DC of `-d` for its domain,
+    /// the configuration and the schema, the DC found by DNS for the other domains.
+    async fn search(&self, nc: &str, nc_domain: &str, attributes: &[&str]) -> Result<Vec<SearchEntry>, Box<dyn Error>> {
+        let (url, ldapfqdn) = if nc_domain.eq_ignore_ascii_case(self.domain) {
+            (prepare_ldap_url(self.ldaps, self.ip, self.port, self.domain), self.ldapfqdn)
+        } else {
+            (prepare_ldap_url(self.ldaps, None, self.port, nc_domain), nc_domain)
+        };
+        debug!("Requesting the attributes not replicated to the Global Catalog of {nc} on {url}");
+        let mut ldap = bind_domain_controller(self.settings, &url, self.args, ldapfqdn, self.starttls, self.kerberos, Some(nc_domain)).await?;
+        let source = LdapPages { settings: self.settings, ldap: &mut ldap, base: nc, filter: "(objectClass=*)", attributes, show_deleted: false };
+        let entries = PagedSearch::new(source, self.page_size).collect().await.map_err(|err| search_timeout_error(self.settings, err, nc))?;
+        close_connection(self.settings, ldap).await;
+        Ok(entries)
+    }
+}
+
+/// Requests of the naming contexts referred by the DCs, on a DC of each child domain with the same bind.
+struct ReferralChase<'a> {
+    settings: &'a LdapSettings,
+    ldaps: bool,
+    starttls: bool,
+    port: Option<u16>,
+    kerberos: bool,
+    page_size: i32,
+    args: &'a LdapArgs,
+    ldapfilter: &'a str,
+    attributes: &'a [&'a str],
+    throttle: &'a Throttle,
+}
 
-            let res = search.finish().await.success();
-            match res {
-                Ok(_res) => info!("All data collected for NamingContext {}", &cn.bold()),
-                Err(err) => {
-                    error!("No data collected on {}! Reason: {err}", &cn.bold().red());
+impl ReferralChase<'_> {
+    /// Function to follow the referrals, and the ones returned by the referred DCs, until none is left.
+    /// Returns the entries stored, the pages received and the time added by the throttling.
+    async fn follow<S: Storage<LdapSearchEntry>>(
+        &self,
+        referrals: &mut ReferralQueue,
+        storage: &mut S,
+    ) -> Result<(usize, u64, std::time::Duration), Box<dyn Error>> {
+        let (mut total, mut pages, mut throttled) = (0, 0, std::time::Duration::ZERO);
+        while let Some((referral, depth)) = referrals.next_referral() {
+            if is_interrupted() {
+                add_collection_warning(&format!("Referral to {} not followed, interrupted", referral.base));
+                continue
+            }
+            throttled += self.throttle.wait().await;
+            info!("Following the referral to {} ({})", referral.base.bold().green(), referral.host);
+            match self.search(&referral, storage, &mut throttled).await {
+                Ok((count, query_pages, refs)) => {
+                    info!("All data collected for NamingContext {} ({query_pages} pages)", referral.base.bold());
+                    emit(Event::QueryFinished { naming_context: referral.base.to_owned(), entries: count as u64 });
+                    total += count;
+                    pages += query_pages;
+                    referrals.push(&refs, depth + 1);
                 }
+                Err(err) => add_collection_warning(&format!("Referral to {} not followed: {err}", referral.url)),
             }
         }
-        // // If no result exit program
-        // if rs.is_empty() {
-        //     process::exit(0x0100);
-        // }
-
-        ldap.unbind().await?;
+        Ok((total, pages, throttled))
     }
 
-    // drop ldap before final flush,
-    // otherwise it will warn about an i/o error
-    // "LDAP connection error: I/O error: Connection reset by peer (os error 54)"
-    drop(ldap);
-    if total == 0 {
-        error!("No LDAP objects found! Exiting...");
-        // std::fs::remove_file(cache_path)?; // TODO: return error so we can cleanup cache
-        process::exit(0x0100);
+    /// Function to request a referred naming context, its own referrals are returned with the entries stored.
+    async fn search<S: Storage<LdapSearchEntry>>(
+        &self,
+        referral: &Referral,
+        storage: &mut S,
+        throttled: &mut std::time::Duration,
+    ) -> Result<(usize, u64, Vec<String>), Box<dyn Error>> {
+        // The port of the referral only when no port is given, a custom one is kept for every DC
+        let url = prepare_ldap_url(self.ldaps, None, self.port.or(referral.port), &referral.host);
+        let domain = referral.domain();
+        let mut ldap = bind_domain_controller(self.settings, &url, self.args, &referral.host, self.starttls, self.kerberos, Some(&domain)).await?;
+        self.settings.record_domain_controller(domain_controller_name(&url, &referral.host));
+        let base = referral.base.as_str();
+        let source = LdapPages { settings: self.settings, ldap: &mut ldap, base, filter: self.ldapfilter, attributes: self.attributes, show_deleted: false };
+        let mut search = PagedSearch::new(source, self.page_size);
+        let pb = ProgressBar::new(1);
+        let (mut count, mut refs) = (0, Vec::new());
+        while let Some(page) = search.next_page().await.map_err(|err| search_timeout_error(self.settings, err, base))? {
+            for entry in page.entries {
+                storage.add(entry.into())?;
+                count += 1;
+                progress_bar(pb.to_owned(), "LDAP objects retrieved".to_string(), count, "#".to_string());
+            }
+            if search.is_finished() {
+                refs = page.result.refs.to_owned();
+                page.result.success()?;
+            } else {
+                // Throttling: wait before the next page request
+                *throttled += self.throttle.wait().await;
+            }
+        }
+        pb.finish_and_clear();
+        let pages = search.pages();
+        close_connection(self.settings, ldap).await;
+        Ok((count as usize, pages, refs))
     }
+}
 
-    storage.flush()?;
-
+/// Pages of a query on a DC.
+struct LdapPages<'a> {
+    settings: &'a LdapSettings,
+    ldap: &'a mut ldap3::Ldap,
+    base: &'a str,
+    filter: &'a str,
+    attributes: &'a [&'a str],
+    /// Show Deleted Objects control, for the container of the deleted objects.
+    show_deleted: bool,
+}
 
-    // Return the vector with the result
-    Ok(total)
+impl PageSource for LdapPages<'_> {
+    async fn page(&mut self, size: i32, cookie: &[u8]) -> Result<Page, ldap3::LdapError> {
+        // Set control LDAP_SERVER_SD_FLAGS_OID to get nTSecurityDescriptor with the --sd-flags parts
+        // Without the presence of this control, the server returns an SD only when the SD attribute name is explicitly mentioned in the requested attribute list.
+        // https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-adts/932a7a8d-8c93-4448-8093-c79b7d9ba499
+        let mut controls = vec![page_control(size, cookie)];
+        if self.attributes.iter().any(|attribute| attribute.eq_ignore_ascii_case("nTSecurityDescriptor")) {
+            controls.push(sd_flags_control(self.settings));
+        }
+        if self.show_deleted {
+            controls.push(show_deleted_control());
+        }
+        self.ldap.with_controls(controls);
+        let ldap3::SearchResult(entries, result) = self.ldap
+            .with_timeout(self.settings.timeouts.search)
+            .search(self.base, Scope::Subtree, self.filter, self.attributes.to_vec())
+            .await?;
+        Ok(Page {
+            entries: entries.into_iter().map(SearchEntry::construct).collect(),
+            cookie: page_cookie(&result.ctrls),
+            result,
+        })
+    }
 }
 
 /// Structure containing the LDAP connection arguments.
@@ -211,7 +1776,7 @@ struct LdapArgs {
     _s_dc: Vec<String>,
     _s_email: String,
     s_username: String,
-    s_password: String,
+    s_password: BindSecret,
 }
 
 /// Function to prepare LDAP arguments.
@@ -220,9 +1785,8 @@ fn ldap_constructor(
     ip: Option<&str>,
     port: Option<u16>,
     domain: &str,
-    ldapfqdn: &str,
     username: Option<&str>,
-    password: Option<&str>,
+    password: Option<BindSecret>,
     kerberos: bool,
 ) -> Result<LdapArgs, Box<dyn Error>> {
     // Prepare ldap url
@@ -253,26 +1817,16 @@ fn ldap_constructor(
     }
 
     // Format username and email
-    let mut s_email: String = "".to_owned();
-    if !_s_username.contains("@") {
-        s_email.push_str(&_s_username.to_string());
-        s_email.push_str("@");
-        s_email.push_str(domain);
-        _s_username = s_email.to_string();
-    } else {
-        s_email = _s_username.to_string().to_lowercase();
-    }
+    let s_email = bind_identity(&_s_username, domain);
 
     // Password prompt
-    let mut _s_password: String = String::new();
-    if !_s_username.contains("not set") && !kerberos {
-        _s_password = match password {
-            Some(p) => p.to_owned(),
-            None => rpassword::prompt_password("Password: ").unwrap_or("not set".to_string()),
-        };
-    } else {
-        _s_password = password.unwrap_or("not set").to_owned();
-    }
+    let _s_password = match password {
+        Some(secret) => secret,
+        None if !_s_username.contains("not set") && !kerberos => {
+            BindSecret::Password(prompt_password("Password: ").unwrap_or_else(|_| Secret::from("not set")))
+        }
+        None => BindSecret::Password(Secret::from("not set")),
+    };
 
     // Print infos if verbose mod is set
     debug!("IP: {}", match ip {
@@ -285,24 +1839,40 @@ fn ldap_constructor(
         },
         None => "not set".to_owned()
     });
-    debug!("FQDN: {}", ldapfqdn);
     debug!("Url: {}", s_url);
     debug!("Domain: {}", domain);
     debug!("Username: {}", _s_username);
-    debug!("Email: {}", s_email.to_lowercase());
-    debug!("Password: {}", _s_password);
+    debug!("Bind identity: {}", s_email);
+    debug!("Password: {}", match &_s_password {
+        BindSecret::Password(password) if password.is_empty() => "",
+        BindSecret::Password(_) => REDACTED,
+        BindSecret::NtHash(_) => "NT hash <redacted>",
+    });
     debug!("DC: {:?}", s_dc);
     debug!("Kerberos: {:?}", kerberos);
 
     Ok(LdapArgs {
         s_url: s_url.to_string(),
         _s_dc: s_dc,
-        _s_email: s_email.to_string().to_lowercase(),
-        s_username: s_email.to_string().to_lowercase(),
-        s_password: _s_password.to_string(),
+        _s_email: s_email.to_lowercase(),
+        s_username: s_email,
+        s_password: _s_password,
     })
 }
 
+/// Function to build the identity of the simple bind from `-u`: the UPN and `DOMAIN\\sam` as given, even with a
+/// UPN suffix other than the domain, and the implicit UPN `sam@domain` for a plain sAMAccountName.
+/// The DN of `--bind-dn` is kept as given.
+pub fn bind_identity(username: &str, domain: &str) -> String {
+    if username.contains('=') {
+        username.to_owned()
+    } else if username.contains('@') || username.contains('\\') {
+        username.to_lowercase()
+    } else {
+        format!("{username}@{domain}").to_lowercase()
+    }
+}
+
 /// Function to prepare LDAP url.
 fn prepare_ldap_url(
     ldaps: bool,
@@ -316,10 +1886,8 @@ fn prepare_ldap_url(
         "ldap"
     };
 
-    let target = match ip {
-        Some(ip) => ip,
-        None => domain,
-    };
+    // An IPv6 address between brackets
+    let target = url_host(ip.unwrap_or(domain));
 
     match port {
         Some(port) => {
@@ -355,25 +1923,122 @@ pub fn prepare_ldap_dc(domain: &str) -> Vec<String> {
 /// Function to make GSSAPI ldap connection.
 #[cfg(not(feature = "nogssapi"))]
 async fn gssapi_connection(
+    settings: &LdapSettings,
     ldap: &mut ldap3::Ldap,
     ldapfqdn: &str,
+) -> Result<(), ldap3::LdapError> {
+    // The ticket comes from KRB5CCNAME, the principal is shown when the cache is a file
+    match crate::utils::krb5::ccache_path() {
+        Some(path) => match std::fs::read(&path).map_err(Into::into).and_then(|data| crate::utils::krb5::ccache_principal(&data)) {
+            Ok(principal) => info!("Using the Kerberos ticket of {} from {path}", principal.bold()),
+            Err(err) => warn!("Can't read the Kerberos credential cache {path}: {err}"),
+        },
+        None if std::env::var_os("KRB5CCNAME").is_none() => warn!("KRB5CCNAME is not set, using the default Kerberos credential cache"),
+        None => debug!("Using the Kerberos credential cache {:?}", std::env::var_os("KRB5CCNAME").unwrap_or_default()),
+    }
+    debug!("GSSAPI bind with the service principal ldap/{ldapfqdn}");
+    // A missing or expired ticket fails before the bind request
+    ldap.with_timeout(settings.timeouts.connect).sasl_gssapi_bind(ldapfqdn).await.and_then(|res| res.success())?;
+    Ok(())
+}
+
+/// Function to bind with the Kerberos ticket, or with the password of `-u/-p` when the Kerberos authentication
+/// fails for a reason the password bind doesn't have (clock skew, no service principal, no ticket) and `fallback`
+/// is set. Returns the authentication of the bind, the error of the Kerberos bind when no bind succeeded.
+#[cfg(not(feature = "nogssapi"))]
+async fn kerberos_bind(
+    settings: &LdapSettings,
+    ldap: &mut ldap3::Ldap,
+    target: DcTarget<'_>,
     domain: &str,
-) -> Result<(), Box<dyn Error>> {
-    let res = ldap.sasl_gssapi_bind(ldapfqdn).await?.success();
-    match res {
-        Ok(_res) => {
+    args: &LdapArgs,
+    fallback: bool,
+    security: ChannelSecurity,
+) -> Result<AuthMechanism, Box<dyn Error>> {
+    let err = match gssapi_connection(settings, ldap, target.server_name).await {
+        Ok(()) => {
             info!("Connected to {} Active Directory!", domain.to_uppercase().bold().green());
             info!("Starting data collection...");
+            return Ok(AuthMechanism::Kerberos)
         }
-        Err(err) => {
-            error!("Failed to authenticate to {} Active Directory. Reason: {err}\n", domain.to_uppercase().bold().red());
-            process::exit(0x0100);
+        Err(err) => err,
+    };
+    let reason = err.to_string();
+    let recoverable = crate::utils::krb5::fallback_reason(&reason);
+    // KRB_AP_ERR_SKEW: the offset from the currentTime of the rootDSE, readable without bind
+    if recoverable == Some("clock skew") {
+        match clock_offset(ldap).await {
+            Some(offset) => warn!("Clock skew: {}", describe_clock_offset(offset).bold().yellow()),
+            None => warn!("Clock skew with the DC, its currentTime can't be read"),
         }
     }
-    Ok(())
+    match recoverable {
+        Some(recoverable) if fallback => {
+            warn!(
+                "Kerberos bind failed ({recoverable}: {reason}), falling back to a password bind as {}",
+                args.s_username.bold()
+            );
+            match password_bind(settings, ldap, target, &args.s_username, &args.s_password).await {
+                Ok(bound) => {
+                    settings.set_bind_credentials(&args.s_username, &args.s_password);
+                    info!("Connected to {} Active Directory!", domain.to_uppercase().bold().green());
+                    info!("Starting data collection...");
+                    Ok(AuthMechanism::KerberosFallback { ntlm: bound == AuthMechanism::Ntlm, reason: recoverable })
+                }
+                Err(err) => Err(format!(
+                    "Failed to authenticate to {} Active Directory with the password. Reason: {err}",
+                    domain.to_uppercase()
+                ).into()),
+            }
+        }
+        _ => {
+            let mut message = format!(
+                "Failed to authenticate to {} Active Directory. Reason: {}",
+                domain.to_uppercase(),
+                timeout_error(settings, err, "LDAP bind")
+            );
+            if let Some(hint) = bind_error_hint(&reason, security, AuthMechanism::Kerberos) {
+                message.push_str(&format!(". {hint}"));
+            } else if let Some(hint) = crate::utils::krb5::bind_error_hint(&reason) {
+                message.push_str(&format!(". Kerberos: {hint}"));
+            }
+            if recoverable.is_some() {
+                message.push_str(". No password bind with --kerberos-only or without -u/-p");
+            }
+            Err(message.into())
+        }
+    }
+}
+
+/// Function to measure the offset of the DC clock from the local one in seconds, from the currentTime of the rootDSE.
+#[cfg(not(feature = "nogssapi"))]
+async fn clock_offset(ldap: &mut ldap3::Ldap) -> Option<i64> {
+    let root_dse = get_root_dse(ldap).await.ok()?;
+    let current_time = crate::utils::date::string_to_epoch(root_dse.get("currentTime")?.first()?).ok()?;
+    Some(current_time - chrono::Utc::now().timestamp())
+}
+
+/// Function to describe the offset of the DC clock, Kerberos accepts 5 minutes by default.
+#[cfg_attr(feature = "nogssapi", allow(dead_code))]
+fn describe_clock_offset(offset: i64) -> String {
+    let skew = format_duration(std::time::Duration::from_secs(offset.unsigned_abs()));
+    match offset {
+        0 => "the local clock is in sync with the DC".to_string(),
+        offset if offset > 0 => format!("the local clock is {skew} behind the DC, Kerberos accepts 5 minutes"),
+        _ => format!("the local clock is {skew} ahead of the DC, Kerberos accepts 5 minutes"),
+    }
 }
 
 /// (Not needed yet) Get all namingContext for DC
+/// Function to check a custom filter with the DC before the first query, on the domain object only: a filter
+/// refused by the DC, like an unknown matching rule, stops the collection with the error of the DC.
+async fn check_ldap_filter(ldap: &mut ldap3::Ldap, base: &str, filter: &str) -> Result<(), Box<dyn Error>> {
+    match ldap.search(base, Scope::Base, filter, vec!["1.1"]).await?.success() {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("LDAP filter {filter} refused by the DC: {err}").into()),
+    }
+}
+
 pub async fn get_all_naming_contexts(
     ldap: &mut ldap3::Ldap
 ) -> Result<Vec<String>, Box<dyn Error>> {
@@ -425,6 +2090,230 @@ pub async fn get_all_naming_contexts(
     Ok(Vec::new())
 }
 
+/// Get the rootDSE attributes: vendor, configuration NC and replication state.
+pub async fn get_root_dse(
+    ldap: &mut ldap3::Ldap
+) -> Result<HashMap<String, Vec<String>>, Box<dyn Error>> {
+    let (rs, _res) = ldap.search(
+        "",
+        Scope::Base,
+        "(objectClass=*)",
+        vec![
+            "vendorName",
+            "vendorVersion",
+            "namingContexts",
+            "configurationNamingContext",
+            "rootDomainNamingContext",
+            "dnsHostName",
+            "isSynchronized",
+            "supportedCapabilities",
+            "currentTime",
+            "defaultNamingContext",
+            "domainFunctionality",
+            "forestFunctionality",
+            "supportedControl",
+            "ldapServiceName",
+            "dsServiceName",
+            "highestCommittedUSN",
+        ],
+    ).await?.success()?;
+
+    let mut root_dse = HashMap::new();
+    for result in rs {
+        root_dse.extend(SearchEntry::construct(result).attrs);
+    }
+    for name in ["vendorName", "vendorVersion", "dnsHostName", "isSynchronized"] {
+        if let Some(value) = root_dse.get(name).and_then(|v: &Vec<String>| v.first()) {
+            debug!("{name} found: {}", value.bold().green());
+        }
+    }
+    Ok(root_dse)
+}
+
+/// Domain controller of the DNS SRV records.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredDc {
+    /// Target of the SRV record, the name of the TLS certificate and the Kerberos SPN.
+    pub fqdn: String,
+    /// IPv6 address of the selected DC when it has no IPv4 address (AAAA record only).
+    pub ipv6: Option<std::net::Ipv6Addr>,
+}
+
+impl DiscoveredDc {
+    /// Address to connect to: the IPv6 address of an IPv6-only DC, its name otherwise.
+    pub fn address(&self) -> String {
+        self.ipv6.map_or_else(|| self.fqdn.to_owned(), |ipv6| ipv6.to_string())
+    }
+}
+
+/// Function to find the domain controllers of a domain in the DNS SRV records, `_gc._tcp` for the Global Catalog
+/// and `_ldap._tcp.dc._msdcs` otherwise, on the `--name-server` or the system resolver.
+pub async fn discover_domain_controllers(domain: &str, gc: bool, dns_tcp: bool, name_server: &str) -> Vec<DiscoveredDc> {
+    use trust_dns_resolver::config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts};
+
+    let record = match gc {
+        true => format!("_gc._tcp.{domain}."),
+        false => format!("_ldap._tcp.dc._msdcs.{domain}."),
+    };
+    let resolver = match name_server.parse::<std::net::IpAddr>() {
+        Ok(address) => {
+            let mut config = ResolverConfig::new();
+            config.add_name_server(NameServerConfig {
+                socket_addr: std::net::SocketAddr::new(address, 53),
+                protocol: if dns_tcp { Protocol::Tcp } else { Protocol::Udp },
+                tls_dns_name: None,
+                trust_negative_responses: false,
+                bind_addr: None,
+            });
+            trust_dns_resolver::TokioAsyncResolver::tokio(config, ResolverOpts::default())
+        }
+        Err(_) => match trust_dns_resolver::TokioAsyncResolver::tokio_from_system_conf() {
+            Ok(resolver) => resolver,
+            Err(err) => {
+                debug!("No DNS resolver: {err}");
+                return Vec::new()
+            }
+        },
+    };
+    match resolver.srv_lookup(record.as_str()).await {
+        Ok(records) => {
+            let domain_controllers = sort_srv_records(
+                records.iter().map(|srv| (srv.priority(), srv.weight(), srv.target().to_utf8())).collect()
+            );
+            let count = domain_controllers.len();
+            let mut discovered: Vec<DiscoveredDc> = domain_controllers.into_iter()
+                .map(|fqdn| DiscoveredDc { fqdn, ipv6: None })
+                .collect();
+            if let Some(first) = discovered.first_mut() {
+                // The other DCs are resolved by the system when the first one fails
+                first.ipv6 = match resolver.lookup_ip(format!("{}.", first.fqdn)).await {
+                    Ok(addresses) => ipv6_only_address(&addresses.iter().collect::<Vec<std::net::IpAddr>>()),
+                    Err(err) => {
+                        debug!("No address found for {}: {err}", first.fqdn);
+                        None
+                    }
+                };
+                info!(
+                    "Domain controller {}{} selected from the DNS SRV records ({count} found)",
+                    first.fqdn.bold().green(),
+                    first.ipv6.map(|ipv6| format!(" (IPv6 only: {ipv6})")).unwrap_or_default()
+                );
+            }
+            discovered
+        }
+        Err(err) => {
+            debug!("No DC found in {record}: {err}");
+            Vec::new()
+        }
+    }
+}
+
+/// Function to get the IPv6 address of a host without IPv4 address (AAAA records only).
+fn ipv6_only_address(addresses: &[std::net::IpAddr]) -> Option<std::net::Ipv6Addr> {
+    if addresses.iter().any(|address| address.is_ipv4()) {
+        return None
+    }
+    addresses.iter().find_map(|address| match address {
+        std::net::IpAddr::V6(ipv6) => Some(*ipv6),
+        std::net::IpAddr::V4(_) => None,
+    })
+}
+
+/// Function to order the SRV targets: the lowest priority first, then the highest weight.
+fn sort_srv_records(mut records: Vec<(u16, u16, String)>) -> Vec<String> {
+    records.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+    let mut targets: Vec<String> = Vec::new();
+    for (_priority, _weight, target) in records {
+        let target = target.trim_end_matches('.').to_owned();
+        if !target.is_empty() && !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    targets
+}
+
+/// Configuration NC requests on the writable DCs of the forest root domain,
+/// with the forest-root credentials or the same ones.
+struct LdapConfigNcFallback<'a> {
+    settings: &'a LdapSettings,
+    ldaps: bool,
+    starttls: bool,
+    port: Option<u16>,
+    kerberos: bool,
+    domain: &'a str,
+    page_size: i32,
+    /// Same attributes as the main search.
+    attributes: &'a [&'a str],
+    args: &'a LdapArgs,
+    credentials: Option<&'a Credentials>,
+    /// Built on the first bind with the forest-root credentials, the password is prompted once.
+    own_args: Option<LdapArgs>,
+}
+
+impl ConfigNcFallback for LdapConfigNcFallback<'_> {
+    async fn writable_dcs(&mut self, forest_root: &str) -> Vec<String> {
+        // RODCs don't register the dc._msdcs SRV records
+        let resolver = match trust_dns_resolver::TokioAsyncResolver::tokio_from_system_conf() {
+            Ok(resolver) => resolver,
+            Err(err) => {
+                debug!("No DNS resolver: {err}");
+                return Vec::new()
+            }
+        };
+        match resolver.srv_lookup(format!("_ldap._tcp.dc._msdcs.{forest_root}.")).await {
+            Ok(records) => records.iter()
+                .map(|srv| srv.target().to_utf8().trim_end_matches('.').to_owned())
+                .collect(),
+            Err(err) => {
+                debug!("No DC found for {forest_root}: {err}");
+                Vec::new()
+            }
+        }
+    }
+
+    async fn search(&mut self, dc: &str, base: &str) -> Result<Vec<SearchEntry>, Box<dyn Error>> {
+        let s_url = prepare_ldap_url(self.ldaps, Some(dc), self.port, dc);
+        let mut ldap = ldap_connect(self.settings, &s_url, dc, self.starttls).await?;
+
+        if self.settings.has_client_certificate() && self.credentials.is_none() {
+            certificate_bind(self.settings, &mut ldap).await?;
+        } else if self.kerberos {
+            #[cfg(not(feature = "nogssapi"))]
+            ldap.with_timeout(self.settings.timeouts.connect).sasl_gssapi_bind(dc).await
+                .map_err(|err| timeout_error(self.settings, err, "LDAP bind"))?
+                .success()?;
+            #[cfg(feature = "nogssapi")]
+            return Err("Kerberos auth and GSSAPI not compatible with current os!".into());
+        } else {
+            if let (Some(set), None) = (self.credentials, &self.own_args) {
+                self.own_args = Some(ldap_constructor(
+                    self.ldaps,
+                    Some(dc),
+                    self.port,
+                    self.domain,
+                    Some(&set.username),
+                    set.password.as_deref().map(|password| BindSecret::Password(Secret::from(password))),
+                    false,
+                )?);
+            }
+            let args = self.own_args.as_ref().unwrap_or(self.args);
+            let target = DcTarget { url: &s_url, server_name: dc, starttls: self.starttls };
+            password_bind(self.settings, &mut ldap, target, &args.s_username, &args.s_password).await?;
+        }
+
+        // Same LDAP_SERVER_SD_FLAGS_OID control as the main search
+        let source = LdapPages { settings: self.settings, ldap: &mut ldap, base, filter: "(objectClass=*)", attributes: self.attributes, show_deleted: false };
+        let entries = PagedSearch::new(source, self.page_size).collect().await.map_err(|err| search_timeout_error(self.settings, err, base))?;
+        close_connection(self.settings, ldap).await;
+        Ok(entries)
+    }
+}
+
+/// Check if the rootDSE vendorName is a Samba AD DC ("Samba Team (https://www.samba.org)").
+pub fn is_samba_vendor(vendor: &str) -> bool {
+    vendor.to_lowercase().contains("samba")
+}
+
 // New type to implement Serialize and Deserialize for SearchEntry
 #[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
 pub struct LdapSearchEntry {
@@ -455,3 +2344,557 @@ impl From<LdapSearchEntry> for SearchEntry {
         }
     }
 }
+
+/// Global Catalog connection used by `--resolve-foreign`.
+pub(crate) struct LdapGlobalCatalog {
+    settings: LdapSettings,
+    ldap: ldap3::Ldap,
+    retry: RetryPolicy,
+}
+
+/// Search of the whole forest on the Global Catalog.
+struct GcSearch<'a> {
+    settings: &'a LdapSettings,
+    ldap: &'a mut ldap3::Ldap,
+    filter: &'a str,
+}
+
+impl Retryable for GcSearch<'_> {
+    type Output = Vec<SearchEntry>;
+
+    fn describe(&self) -> String {
+        format!("Global Catalog search {}", self.filter)
+    }
+
+    async fn attempt(&mut self) -> Result<Self::Output, ldap3::LdapError> {
+        // Empty base: the whole forest
+        let (entries, _res) = self.ldap
+            .with_timeout(self.settings.timeouts.search)
+            .search("", Scope::Subtree, self.filter, GC_ATTRIBUTES.to_vec())
+            .await?
+            .success()?;
+        Ok(entries.into_iter().map(SearchEntry::construct).collect())
+    }
+}
+
+impl GlobalCatalog for LdapGlobalCatalog {
+    async fn search(&mut self, filter: &str) -> Result<Vec<SearchEntry>, Box<dyn Error>> {
+        self.retry.run(&mut GcSearch { settings: &self.settings, ldap: &mut self.ldap, filter }).await
+            .map_err(|err| search_timeout_error(&self.settings, err, "the Global Catalog"))
+    }
+}
+
+/// Function to connect to the Global Catalog of the target DC (port 3268, 3269 with LDAPS), with the credentials
+/// of the main bind when no `gc` set is given.
+pub(crate) async fn connect_global_catalog(options: &Options, bind: &BindState) -> Result<LdapGlobalCatalog, Box<dyn Error>> {
+    let settings = &LdapSettings::from_options(options)?;
+    let port = if options.ldaps { 3269 } else { 3268 };
+    let s_url = prepare_ldap_url(options.ldaps, options.ip.as_deref(), Some(port), &options.domain);
+    let mut ldap = ldap_connect(settings, &s_url, &options.ldapfqdn, options.starttls).await?;
+
+    if settings.has_client_certificate() && options.credentials_for("gc").is_none() {
+        certificate_bind(settings, &mut ldap).await?;
+    } else if options.kerberos && bind.credentials.is_none() {
+        // Kerberos, unless the main bind fell back to the password
+        #[cfg(not(feature = "nogssapi"))]
+        ldap.with_timeout(settings.timeouts.connect).sasl_gssapi_bind(&options.ldapfqdn).await
+            .map_err(|err| timeout_error(settings, err, "LDAP bind"))?
+            .success()?;
+        #[cfg(feature = "nogssapi")]
+        return Err("Kerberos auth and GSSAPI not compatible with current os!".into());
+    } else {
+        // Own gc set, or the primary credentials of the main bind (not bound yet when resumed from the cache)
+        let (username, password) = match (options.credentials_for("gc"), &bind.credentials) {
+            (None, Some((username, secret))) => (username.to_owned(), secret.to_owned()),
+            (None, None) if options.anonymous => (String::new(), BindSecret::default()),
+            (set, _) => {
+                let mut args = ldap_constructor(
+                    options.ldaps,
+                    options.ip.as_deref(),
+                    Some(port),
+                    &options.domain,
+                    set.map_or(options.bind_dn.as_deref().or(options.username.as_deref()), |set| Some(set.username.as_str())),
+                    set.map_or_else(
+                        || options.bind_secret(),
+                        |set| set.password.as_deref().map(|password| BindSecret::Password(Secret::from(password))),
+                    ),
+                    false,
+                )?;
+                (std::mem::take(&mut args.s_username), std::mem::take(&mut args.s_password))
+            }
+        };
+        let target = DcTarget { url: &s_url, server_name: &options.ldapfqdn, starttls: options.starttls };
+        let bind = password_bind(settings, &mut ldap, target, &username, &password).await;
+        drop(password);
+        bind?;
+    }
+    debug!("Connected to the Global Catalog {s_url}");
+    Ok(LdapGlobalCatalog { settings: settings.to_owned(), ldap, retry: RetryPolicy::from(options) })
+}
+
+/// Ranges of the attributes of the entries of a search, on its connection.
+struct LdapRangeReader<'a> {
+    settings: &'a LdapSettings,
+    ldap: &'a mut ldap3::Ldap,
+    retry: &'a RetryPolicy,
+}
+
+/// Read of one range of an attribute of an object.
+struct RangeRead<'a> {
+    settings: &'a LdapSettings,
+    ldap: &'a mut ldap3::Ldap,
+    dn: &'a str,
+    attribute: &'a str,
+}
+
+impl Retryable for RangeRead<'_> {
+    type Output = Option<SearchEntry>;
+
+    fn describe(&self) -> String {
+        format!("LDAP read of {} on {}", self.attribute, self.dn)
+    }
+
+    async fn attempt(&mut self) -> Result<Self::Output, ldap3::LdapError> {
+        let result = self.ldap
+            .with_timeout(self.settings.timeouts.search)
+            .search(self.dn, Scope::Base, "(objectClass=*)", vec![self.attribute])
+            .await?;
+        // noSuchObject: the object is gone
+        if result.1.rc == 32 {
+            return Ok(None)
+        }
+        let (entries, _res) = result.success()?;
+        Ok(entries.into_iter().next().map(SearchEntry::construct))
+    }
+}
+
+impl RangeReader for LdapRangeReader<'_> {
+    async fn read_range(&mut self, dn: &str, attribute: &str) -> Result<Option<SearchEntry>, Box<dyn Error>> {
+        self.retry.run(&mut RangeRead { settings: self.settings, ldap: self.ldap, dn, attribute }).await
+            .map_err(|err| search_timeout_error(self.settings, err, dn))
+    }
+}
+
+/// Targeted reads of the `verify` subcommand.
+pub(crate) struct LdapObjectReader {
+    settings: LdapSettings,
+    ldap: ldap3::Ldap,
+    retry: RetryPolicy,
+}
+
+/// Read of one object by its DN.
+struct ObjectRead<'a> {
+    settings: &'a LdapSettings,
+    ldap: &'a mut ldap3::Ldap,
+    base: &'a str,
+}
+
+impl Retryable for ObjectRead<'_> {
+    type Output = Option<SearchEntry>;
+
+    fn describe(&self) -> String {
+        format!("LDAP read of {}", self.base)
+    }
+
+    async fn attempt(&mut self) -> Result<Self::Output, ldap3::LdapError> {
+        // Same LDAP_SERVER_SD_FLAGS_OID control as the main search, the edges come from the DACL
+        self.ldap.with_controls(sd_flags_control(self.settings));
+        let result = self.ldap
+            .with_timeout(self.settings.timeouts.search)
+            .search(self.base, Scope::Base, "(objectClass=*)", vec!["*", "nTSecurityDescriptor"])
+            .await?;
+        // noSuchObject: the object is gone
+        if result.1.rc == 32 {
+            return Ok(None)
+        }
+        let (entries, _res) = result.success()?;
+        Ok(entries.into_iter().next().map(SearchEntry::construct))
+    }
+}
+
+impl ObjectReader for LdapObjectReader {
+    async fn read(&mut self, base: &str) -> Result<Option<SearchEntry>, Box<dyn Error>> {
+        self.retry.run(&mut ObjectRead { settings: &self.settings, ldap: &mut self.ldap, base }).await
+            .map_err(|err| search_timeout_error(&self.settings, err, base))
+    }
+}
+
+/// Function to connect to the target DC for the targeted reads of the `verify` subcommand.
+pub(crate) async fn connect_object_reader(options: &Options, settings: &LdapSettings) -> Result<LdapObjectReader, Box<dyn Error>> {
+    let args = ldap_constructor(
+        options.ldaps,
+        options.ip.as_deref(),
+        options.port,
+        &options.domain,
+        options.bind_dn.as_deref().or(options.username.as_deref()),
+        options.bind_secret(),
+        options.kerberos || settings.has_client_certificate(),
+    )?;
+    let mut ldap = ldap_connect(settings, &args.s_url, &options.ldapfqdn, options.starttls).await?;
+
+    if settings.has_client_certificate() {
+        certificate_bind(settings, &mut ldap).await?;
+    } else if options.kerberos {
+        #[cfg(not(feature = "nogssapi"))]
+        ldap.with_timeout(settings.timeouts.connect).sasl_gssapi_bind(&options.ldapfqdn).await
+            .map_err(|err| timeout_error(settings, err, "LDAP bind"))?
+            .success()?;
+        #[cfg(feature = "nogssapi")]
+        return Err("Kerberos auth and GSSAPI not compatible with current os!".into());
+    } else {
+        let target = DcTarget { url: &args.s_url, server_name: &options.ldapfqdn, starttls: options.starttls };
+        password_bind(settings, &mut ldap, target, &args.s_username, &args.s_password).await?;
+    }
+    info!("Connected to {} for the verification", args.s_url.bold().green());
+    Ok(LdapObjectReader { settings: settings.to_owned(), ldap, retry: RetryPolicy::from(options) })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::ldap::*;
+    use crate::utils::ntlm::nt_hash;
+
+    #[test]
+    pub fn test_sort_srv_records() {
+        let records = vec![
+            (10, 100, "dc03.contoso.local.".to_string()),
+            (0, 50, "dc02.contoso.local.".to_string()),
+            (0, 100, "dc01.contoso.local.".to_string()),
+            (0, 100, "dc01.contoso.local.".to_string()),
+        ];
+        assert_eq!(sort_srv_records(records), vec!["dc01.contoso.local", "dc02.contoso.local", "dc03.contoso.local"]);
+    }
+
+    #[test]
+    pub fn test_ipv6_domain_controller() {
+        let ipv4: std::net::IpAddr = "192.168.56.10".parse().unwrap();
+        let ipv6: std::net::IpAddr = "2001:db8::10".parse().unwrap();
+        assert_eq!(ipv6_only_address(&[ipv6]), Some("2001:db8::10".parse().unwrap()));
+        assert_eq!(ipv6_only_address(&[ipv4, ipv6]), None);
+        assert_eq!(ipv6_only_address(&[]), None);
+
+        // The address in the URL, the name for the certificate and the SPN
+        let dc = DiscoveredDc { fqdn: "dc01.contoso.local".to_string(), ipv6: Some("2001:db8::10".parse().unwrap()) };
+        assert_eq!(dc.address(), "2001:db8::10");
+        assert_eq!(prepare_ldap_url(true, Some(&dc.address()), None, "contoso.local"), "ldaps://[2001:db8::10]");
+        assert_eq!(prepare_ldap_url(false, Some("2001:db8::10"), Some(3268), "contoso.local"), "ldap://[2001:db8::10]:3268");
+        assert_eq!(prepare_ldap_url(false, None, Some(389), "contoso.local"), "ldap://contoso.local:389");
+    }
+
+    #[test]
+    pub fn test_is_samba_vendor() {
+        assert!(is_samba_vendor("Samba Team (https://www.samba.org)"));
+        assert!(is_samba_vendor("Samba Team (http://samba.org)"));
+        assert!(!is_samba_vendor(""));
+        assert!(!is_samba_vendor("Microsoft Corporation"));
+    }
+
+    #[test]
+    pub fn test_is_connection_error() {
+        let reset = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "Connection reset by peer");
+        assert!(is_connection_error(&ldap3::LdapError::from(reset)));
+        assert!(is_connection_error(&ldap3::LdapError::EndOfStream));
+        // Returned by the DC: not retried
+        let busy = ldap3::LdapResult { rc: 51, matched: String::new(), text: String::new(), refs: Vec::new(), ctrls: Vec::new() };
+        assert!(!is_connection_error(&ldap3::LdapError::LdapResult { result: busy.to_owned() }));
+        assert!(!is_connection_error(&ldap3::LdapError::FilterParsing));
+        // Retried: busy, unavailable and the lost connections, not the refused bind or rights
+        assert!(is_transient_error(&ldap3::LdapError::LdapResult { result: busy.to_owned() }));
+        assert!(is_transient_error(&ldap3::LdapError::LdapResult { result: ldap3::LdapResult { rc: 52, ..busy.to_owned() } }));
+        assert!(is_transient_error(&ldap3::LdapError::EndOfStream));
+        assert!(!is_transient_error(&ldap3::LdapError::LdapResult { result: ldap3::LdapResult { rc: 49, ..busy.to_owned() } }));
+        assert!(!is_transient_error(&ldap3::LdapError::LdapResult { result: ldap3::LdapResult { rc: 50, ..busy } }));
+
+        // The DNs of the entries received before the connection is lost
+        assert_eq!(dn_hash("CN=alice,CN=Users,DC=CONTOSO,DC=LOCAL"), dn_hash("CN=alice,CN=Users,DC=CONTOSO,DC=LOCAL"));
+        assert_ne!(dn_hash("CN=alice,CN=Users,DC=CONTOSO,DC=LOCAL"), dn_hash("CN=bob,CN=Users,DC=CONTOSO,DC=LOCAL"));
+        assert_eq!(RetryPolicy::default().retries, 3);
+    }
+
+    #[test]
+    pub fn test_certificate_rejection() {
+        // rustls
+        assert_eq!(certificate_rejection("I/O error: invalid peer certificate: Expired"), Some("expired"));
+        assert_eq!(certificate_rejection("invalid peer certificate: NotValidYet"), Some("not valid yet"));
+        assert!(certificate_rejection("invalid peer certificate: UnknownIssuer").unwrap().starts_with("unknown issuer"));
+        // OpenSSL and SChannel
+        assert_eq!(certificate_rejection("certificate verify failed:../ssl/statem/statem_clnt.c:1889: (certificate has expired)"), Some("expired"));
+        assert!(certificate_rejection("certificate verify failed: (unable to get local issuer certificate)").unwrap().contains("--ca-cert"));
+        assert!(certificate_rejection("The certificate chain was issued by an authority that is not trusted.").unwrap().contains("--insecure"));
+        assert_eq!(certificate_rejection("Connection refused (os error 111)"), None);
+    }
+
+    #[test]
+    pub fn test_bind_error_hint() {
+        let settings = LdapSettings::default();
+        let plain = ChannelSecurity::default();
+        let tls = ChannelSecurity { tls: true, channel_binding: true };
+        let no_token = ChannelSecurity { tls: true, channel_binding: false };
+        let channel_binding = "rc=49 (invalidCredentials), text: \"80090346: LdapErr: DSID-0C090569, comment: AcceptSecurityContext error, data 80090346, v4563\"";
+        let signing = "rc=8 (strongerAuthRequired), text: \"00002028: LdapErr: DSID-0C090259, comment: The server requires binds to turn on integrity checking if SSL\\TLS are not already active on the connection, data 0, v4563\"";
+
+        let (simple, ntlm, kerberos) = (AuthMechanism::SimpleBind, AuthMechanism::Ntlm, AuthMechanism::Kerberos);
+        assert!(bind_error_hint(channel_binding, tls, simple).unwrap().contains("without --no-channel-binding"));
+        assert!(bind_error_hint(channel_binding, no_token, kerberos).unwrap().contains("no token could be computed"));
+        assert!(bind_error_hint(channel_binding, tls, kerberos).unwrap().contains("refused the token"));
+        assert!(bind_error_hint(channel_binding, tls, ntlm).unwrap().contains("refused the token"));
+        assert!(bind_error_hint(signing, plain, simple).unwrap().contains("--starttls"));
+        assert_eq!(bind_error_hint(signing, tls, simple), None);
+        assert_eq!(bind_error_hint("rc=49 (invalidCredentials)", plain, simple), None);
+
+        let err = ldap3::LdapResult { rc: 8, matched: String::new(), text: "00002028: LdapErr".to_string(), refs: Vec::new(), ctrls: Vec::new() };
+        assert!(bind_error(&settings, ldap3::LdapError::LdapResult { result: err }, plain, simple).to_string().contains("enforces LDAP signing"));
+    }
+
+    #[test]
+    pub fn test_describe_clock_offset() {
+        assert_eq!(describe_clock_offset(432), "the local clock is 7m12s behind the DC, Kerberos accepts 5 minutes");
+        assert_eq!(describe_clock_offset(-5400), "the local clock is 1h30m ahead of the DC, Kerberos accepts 5 minutes");
+        assert_eq!(describe_clock_offset(0), "the local clock is in sync with the DC");
+    }
+
+    #[test]
+    pub fn test_bind_identity() {
+        assert_eq!(bind_identity("Robert.Baratheon", "sevenkingdoms.local"), "robert.baratheon@sevenkingdoms.local");
+        // UPN suffix other than the domain
+        assert_eq!(bind_identity("robert@kingslanding.com", "sevenkingdoms.local"), "robert@kingslanding.com");
+        assert_eq!(bind_identity("SEVENKINGDOMS\\robert.baratheon", "sevenkingdoms.local"), "sevenkingdoms\\robert.baratheon");
+
+        // --bind-dn as given
+        assert_eq!(bind_identity("CN=svc_ldap,OU=Service,DC=sevenkingdoms,DC=local", "sevenkingdoms.local"), "CN=svc_ldap,OU=Service,DC=sevenkingdoms,DC=local");
+
+        let args = ldap_constructor(
+            false, None, None, "sevenkingdoms.local", Some("CN=svc_ldap,OU=Service,DC=sevenkingdoms,DC=local"),
+            Some(BindSecret::Password(Secret::from("Password1"))), false,
+        ).unwrap();
+        assert_eq!(args.s_username, "CN=svc_ldap,OU=Service,DC=sevenkingdoms,DC=local");
+    }
+
+    #[test]
+    pub fn test_throttle() {
+        let throttle = Throttle { delay: std::time::Duration::from_millis(2000), jitter: 30 };
+        for _ in 0..100 {
+            let delay = throttle.next_delay();
+            assert!(delay >= std::time::Duration::from_millis(1400) && delay <= std::time::Duration::from_millis(2600));
+        }
+        let exact = Throttle { delay: std::time::Duration::from_millis(500), jitter: 0 };
+        assert_eq!(exact.next_delay(), std::time::Duration::from_millis(500));
+        // 4 queries: 3 waits, 10000 objects in pages of 999: 10 waits
+        assert_eq!(throttle.estimate(4, 999), (std::time::Duration::from_secs(6), std::time::Duration::from_secs(20)));
+        assert_eq!(Throttle::default().estimate(4, 999), (std::time::Duration::ZERO, std::time::Duration::ZERO));
+    }
+
+    #[test]
+    pub fn test_sd_flags() {
+        let settings = LdapSettings::default();
+        assert_eq!(parse_sd_flags("0x7"), Ok(DEFAULT_SD_FLAGS));
+        assert_eq!(parse_sd_flags("15"), Ok(0xF));
+        assert!(parse_sd_flags("0").is_err());
+        assert!(parse_sd_flags("0x10").is_err());
+        assert!(parse_sd_flags("dacl").is_err());
+
+        // Owner, group and DACL by default, SEQUENCE { INTEGER 7 }
+        let control = sd_flags_control(&settings);
+        assert_eq!(control.ctype, SD_FLAGS_OID);
+        assert_eq!(control.val, Some(vec![0x30, 0x03, 0x02, 0x01, 0x07]));
+    }
+
+    #[test]
+    pub fn test_lower_page_size() {
+        let result = |rc| ldap3::LdapResult { rc, matched: String::new(), text: String::new(), refs: Vec::new(), ctrls: Vec::new() };
+        assert!(is_limit_exceeded(&result(4)));
+        assert!(is_limit_exceeded(&result(11)));
+        assert!(!is_limit_exceeded(&result(0)));
+        assert!(!is_limit_exceeded(&result(51)));
+
+        // MaxPageSize of 500: 999 then 499
+        assert_eq!(lower_page_size(999), Some(499));
+        assert_eq!(lower_page_size(80), Some(MIN_PAGE_SIZE));
+        assert_eq!(lower_page_size(MIN_PAGE_SIZE), None);
+        assert_eq!(lower_page_size(10), None);
+    }
+
+    #[test]
+    pub fn test_starttls_error() {
+        let settings = LdapSettings::default();
+        let url = "ldap://dc01.contoso.local";
+        let unavailable = ldap3::LdapResult { rc: 52, matched: String::new(), text: String::new(), refs: Vec::new(), ctrls: Vec::new() };
+        let err = connection_error(&settings, url, true, ldap3::LdapError::LdapResult { result: unavailable });
+        assert!(err.to_string().starts_with("StartTLS not supported by ldap://dc01.contoso.local"));
+
+        let corrupt = std::io::Error::new(std::io::ErrorKind::InvalidData, "received corrupt message");
+        let err = connection_error(&settings, url, true, ldap3::LdapError::from(corrupt));
+        assert!(err.to_string().starts_with("StartTLS accepted by ldap://dc01.contoso.local but the TLS handshake failed"));
+
+        let refused = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "Connection refused");
+        let err = connection_error(&settings, url, true, ldap3::LdapError::from(refused));
+        assert!(err.to_string().starts_with("Can't connect to"));
+
+        // The certificate rejections are the same as LDAPS
+        let expired = std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid peer certificate: Expired");
+        let err = connection_error(&settings, url, true, ldap3::LdapError::from(expired));
+        assert!(err.to_string().starts_with("The certificate of ldap://dc01.contoso.local was rejected: expired"));
+    }
+
+    #[tokio::test]
+    pub async fn test_timeout_error() {
+        let settings = LdapSettings::default();
+        // DC accepting the connection without answering
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ldap://{}", listener.local_addr().unwrap());
+        let (conn, mut ldap) = LdapConnAsync::new(&url).await.unwrap();
+        ldap3::drive!(conn);
+        let err = ldap.with_timeout(std::time::Duration::from_millis(100)).simple_bind("", "").await.unwrap_err();
+        assert!(is_connection_error(&err));
+        assert_eq!(timeout_error(&settings, err, "LDAP bind").to_string(), "LDAP bind timed out after 30s (--connect-timeout)");
+
+        let err = ldap.with_timeout(std::time::Duration::from_millis(100))
+            .search("DC=contoso,DC=local", Scope::Base, "(objectClass=*)", vec!["*"]).await.unwrap_err();
+        assert_eq!(
+            search_timeout_error(&settings, err, "DC=contoso,DC=local").to_string(),
+            "LDAP search of DC=contoso,DC=local timed out after 300s (--search-timeout)"
+        );
+        // The other errors are kept
+        let refused = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "Connection refused");
+        assert_eq!(timeout_error(&settings, ldap3::LdapError::from(refused), "LDAP bind").to_string(), "I/O error: Connection refused");
+    }
+
+    /// DC answering with the result codes in order, then the entries.
+    struct FlakyDc {
+        failures: Vec<u32>,
+        attempts: u32,
+    }
+
+    impl Retryable for FlakyDc {
+        type Output = u32;
+
+        fn describe(&self) -> String {
+            "LDAP read of DC=contoso,DC=local".to_string()
+        }
+
+        async fn attempt(&mut self) -> Result<u32, ldap3::LdapError> {
+            self.attempts += 1;
+            if self.failures.is_empty() {
+                return Ok(self.attempts)
+            }
+            let rc = self.failures.remove(0);
+            Err(ldap3::LdapError::LdapResult {
+                result: ldap3::LdapResult { rc, matched: String::new(), text: format!("attempt {}", self.attempts), refs: Vec::new(), ctrls: Vec::new() },
+            })
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_retry_backoff() {
+        let retry = RetryPolicy { retries: 3, delay: std::time::Duration::from_millis(5), max_delay: std::time::Duration::from_millis(12) };
+        assert_eq!(
+            (1..=4).map(|attempt| retry.backoff(attempt).as_millis()).collect::<Vec<u128>>(),
+            [5, 10, 12, 12]
+        );
+        let default = RetryPolicy::default();
+        assert_eq!(default.backoff(1).as_secs(), 5);
+        assert_eq!(default.backoff(40).as_secs(), 60);
+
+        // Busy twice, then the answer
+        let mut dc = FlakyDc { failures: vec![51, 52], attempts: 0 };
+        assert_eq!(retry.run(&mut dc).await.unwrap(), 3);
+        // Still busy after the retries: the first error
+        let mut dc = FlakyDc { failures: vec![51; 5], attempts: 0 };
+        let err = retry.run(&mut dc).await.unwrap_err();
+        assert!(err.to_string().contains("attempt 1"));
+        assert_eq!(dc.attempts, 4);
+        // Fatal: not retried
+        let mut dc = FlakyDc { failures: vec![51, 50], attempts: 0 };
+        let err = retry.run(&mut dc).await.unwrap_err();
+        assert!(err.to_string().contains("attempt 2"));
+        let mut dc = FlakyDc { failures: vec![49], attempts: 0 };
+        assert!(retry.run(&mut dc).await.is_err());
+        assert_eq!(dc.attempts, 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_ntlm_connect_starttls_refused() {
+        // DC without certificate: the StartTLS request is refused (protocolError), never continued in cleartext
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ldap://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 64];
+            tokio::io::AsyncReadExt::read(&mut socket, &mut request).await.unwrap();
+            let response = [0x30, 0x0c, 0x02, 0x01, 0x01, 0x78, 0x07, 0x0a, 0x01, 0x02, 0x04, 0x00, 0x04, 0x00];
+            tokio::io::AsyncWriteExt::write_all(&mut socket, &response).await.unwrap();
+        });
+        let account = NtlmAccount { user: "alice", domain: "CONTOSO", nt_hash: nt_hash("secret") };
+        let target = DcTarget { url: &url, server_name: "not set", starttls: true };
+        let err = ntlm_connect(&LdapSettings::default(), target, &account).await.unwrap_err();
+        assert!(err.to_string().starts_with(&format!("StartTLS not supported by {url}")));
+    }
+
+    #[tokio::test]
+    pub async fn test_password_bind_nt_hash() {
+        // The NT hash is only bound with NTLM, never sent in a simple bind
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ldap://{}", listener.local_addr().unwrap());
+        let (conn, mut ldap) = LdapConnAsync::new(&url).await.unwrap();
+        ldap3::drive!(conn);
+        let target = DcTarget { url: &url, server_name: "not set", starttls: false };
+        let secret = BindSecret::NtHash(Secret::from("a4f49c406510bdcab6824ee7c30fd852"));
+        let err = password_bind(&LdapSettings::default(), &mut ldap, target, "CN=alice,CN=Users,DC=contoso,DC=local", &secret).await.unwrap_err();
+        assert!(err.to_string().starts_with("--nt-hash needs the account name of -u"));
+
+        let options = Options { nt_hash: Some(Secret::from("a4f49c406510bdcab6824ee7c30fd85")), ..Default::default() };
+        assert!(LdapSettings::from_options(&options).err().unwrap().to_string().starts_with("invalid --nt-hash: an NT hash is 32"));
+    }
+
+    #[tokio::test]
+    pub async fn test_close_reset_connection() {
+        let settings = LdapSettings::default();
+        // Middlebox resetting the idle connection: the unbind fails, nothing panics
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ldap://{}", listener.local_addr().unwrap());
+        let (conn, ldap) = LdapConnAsync::new(&url).await.unwrap();
+        ldap3::drive!(conn);
+        let (socket, _) = listener.accept().await.unwrap();
+        socket.set_linger(Some(std::time::Duration::ZERO)).unwrap();
+        drop(socket);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        close_connection(&settings, ldap).await;
+
+        // DC never answering
+        let (conn, ldap) = LdapConnAsync::new(&url).await.unwrap();
+        ldap3::drive!(conn);
+        close_connection(&settings, ldap).await;
+    }
+
+    #[test]
+    pub fn test_load_tls_validation() {
+        let pem = std::env::temp_dir().join("rusthound-ce-test-ca.pem");
+        std::fs::write(&pem, "-----BEGIN CERTIFICATE-----\nMAMCAQA=\n-----END CERTIFICATE-----\n").unwrap();
+        let validation = load_tls_validation(Some(pem.to_str().unwrap()), false, true).unwrap();
+        assert_eq!(validation.ca_certificates, vec![vec![0x30, 0x03, 0x02, 0x01, 0x00]]);
+        assert!(validation.no_hostname_verify && !validation.insecure);
+
+        std::fs::write(&pem, "not a certificate").unwrap();
+        assert!(load_tls_validation(Some(pem.to_str().unwrap()), false, false).is_err());
+        assert!(load_tls_validation(Some("missing.pem"), false, false).is_err());
+        std::fs::remove_file(&pem).unwrap();
+        assert!(load_tls_validation(None, false, false).unwrap().ca_certificates.is_empty());
+    }
+
+    #[test]
+    pub fn test_load_client_certificate() {
+        let pfx = format!("{}/tests/fixtures/alice.pfx", env!("CARGO_MANIFEST_DIR"));
+        assert!(load_client_certificate("missing.pfx", "").is_err());
+        #[cfg(feature = "nogssapi")]
+        {
+            let err = load_client_certificate(&pfx, "wrong").map(|_| ()).unwrap_err();
+            assert!(err.to_string().contains("wrong --pfx-pass?"));
+            let settings = LdapSettings { client_certificate: Some(load_client_certificate(&pfx, "Password123").unwrap()), ..Default::default() };
+            assert!(settings.has_client_certificate());
+            assert!(!LdapSettings::default().has_client_certificate());
+        }
+        #[cfg(not(feature = "nogssapi"))]
+        assert!(load_client_certificate(&pfx, "Password123").unwrap_err().to_string().contains("nogssapi"));
+    }
+}
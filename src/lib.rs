@@ -20,83 +20,273 @@
 //! Active Directory data collector for BloodHound Community Edition.
 //! g0h4n <https://twitter.com/g0h4n_0>
 //! 
-//! Usage: rusthound-ce [OPTIONS] --domain <domain>
+//! Usage: rusthound-ce [OPTIONS] --domain <domain> [COMMAND]
+//! 
+//! Commands:
+//!   verify  Re-check the findings of a previous collection against the live AD, read-only
+//!   help    Print this message or the help of the given subcommand(s)
 //! 
 //! Options:
 //!   -v...          Set the level of verbosity
-//!   -h, --help     Print help
 //!   -V, --version  Print version
+//!   -h, --help     Print help
 //! 
 //! REQUIRED VALUES:
 //!   -d, --domain <domain>  Domain name like: DOMAIN.LOCAL
 //! 
 //! OPTIONAL VALUES:
-//!   -u, --ldapusername <ldapusername>  LDAP username, like: user@domain.local
-//!   -p, --ldappassword <ldappassword>  LDAP password
-//!   -f, --ldapfqdn <ldapfqdn>          Domain Controller FQDN like: DC01.DOMAIN.LOCAL or just DC01
-//!   -i, --ldapip <ldapip>              Domain Controller IP address like: 192.168.1.10
+//!   -u, --ldapusername <ldapusername>  LDAP username, like: user@domain.local, DOMAIN\user or user
+//!   -p, --ldappassword <ldappassword>  LDAP password, prompted if omitted (or set RUSTHOUND_PASSWORD)
+//!       --password-file <FILE>
+//!           Read the LDAP password from the first line of a file instead of -p
+//!       --bind-dn <DN>
+//!           DN of the simple bind used as given, like: CN=svc_ldap,OU=Service,DC=domain,DC=local
+//!       --pfx <FILE>
+//!           PKCS#12 client certificate to authenticate over LDAPS or StartTLS instead of -u/-p
+//!       --pfx-pass <PASSWORD>
+//!           Password of the PKCS#12 file, empty by default
+//!       --module-credentials <module=username[:password]>
+//!           Credentials of a module binding on its own instead of -u/-p, password prompted if omitted [modules: gc, forest-root]
+//!   -f, --ldapfqdn <ldapfqdn>
+//!           Domain Controller FQDN like: DC01.DOMAIN.LOCAL, DC01 or DC01:636, an address like [2001:db8::10]:636, several DCs like DC01,DC02 are used in order when one fails
+//!       --dc <FQDN>
+//!           Fallback Domain Controller used when the previous ones fail, can be repeated
+//!   -i, --ldapip <ldapip>              Domain Controller IP address like: 192.168.1.10 or 2001:db8::10
 //!   -P, --ldapport <ldapport>          LDAP port [default: 389]
 //!   -n, --name-server <name-server>    Alternative IP address name server to use for DNS queries
 //!   -o, --output <output>              Output directory where you would like to save JSON files [default: ./]
+//!       --well-known-overrides <well-known-overrides>
+//!           JSON file to extend or adjust the well-known SIDs mapping (renamed or localized builtin groups)
+//!       --config <FILE>
+//!           TOML configuration file (custom_properties copying extra LDAP attributes in the output)
+//!       --profile <profile>
+//!           Collection profile setting several options at once, explicit flags keep their value [possible values: default, stealth, full, adcs-only, dconly, audit]
+//!       --throttle <ms>
+//!           Wait this many milliseconds between two LDAP pages and between two LDAP queries
+//!       --jitter <pct>
+//!           Randomize each --throttle wait by up to this percentage, more or less
+//!       --page-size <N>
+//!           Entries per LDAP page, lowered automatically when the DC answers sizeLimitExceeded or adminLimitExceeded [default: 1000]
+//!       --sd-flags <flags>
+//!           Parts of nTSecurityDescriptor requested with the LDAP_SERVER_SD_FLAGS control: 1 owner, 2 group, 4 DACL, 8 SACL (needs SeSecurityPrivilege) [default: 0x7]
+//!       --retries <N>
+//!           Retry up to N times when the connection to the DC drops or the DC is busy during a search, the entries already received are kept [default: 3]
+//!       --retry-delay <seconds>
+//!           Wait this many seconds before the first retry, twice as long before each next one [default: 5]
+//!       --retry-max-delay <seconds>
+//!           Never wait more than this many seconds before a retry [default: 60]
+//!       --connect-timeout <seconds>
+//!           Give up the connection, the TLS handshake or the bind to a DC after this many seconds [default: 30]
+//!       --search-timeout <seconds>
+//!           Give up a search when a page is not received after this many seconds, then reconnect like a lost connection [default: 300]
+//!       --deadline <duration>
+//!           Time box of the run like 30m or 1h30m: the most valuable objects are requested first, partial output once the deadline approaches
+//!       --collection-priority <categories>
+//!           Comma separated collection order with --deadline [default: domain,trusts,groups,users,adcs,computers,containers,gpos,others]
+//!       --explain-edges <principal-or-object>
+//!           Explain which ACE made or suppressed each edge of an object or principal (SID, GUID, name or DN) in <domain>_explain_edges.txt
+//!       --ace-filter <filter>
+//!           Comma separated ACE output filter: high-value (keep only the control, ADCS, delegation and DCSync edges), EdgeName to keep, -EdgeName to drop, no-default-large-groups
+//!       --progress-json [<TARGET>]
+//!           Emit NDJSON progress events (phase_started, phase_finished, query_finished, warning, file_written, finished) to stdout [-], a file or named pipe, or a unix socket [unix:<path>]. Logs are written to stderr
+//!       --bh-url <bh-url>
+//!           BloodHound CE URL to upload the JSON files like: https://bloodhound.domain.local
+//!       --bh-token-id <bh-token-id>
+//!           BloodHound CE API token ID used for the upload
+//!       --bh-token-key <bh-token-key>
+//!           BloodHound CE API token key used for the upload
 //! 
 //! OPTIONAL FLAGS:
-//!   -c, --collectionmethod [<COLLECTIONMETHOD>]
-//!           Which information to collect. Supported: All (LDAP,SMB,HTTP requests), DCOnly (no computer connections, only LDAP requests). (default: All) [possible values: All, DCOnly]
+//!   -c, --collection-methods [<METHODS>]
+//!           SharpHound collection methods, comma separated like DCOnly or ACL,ObjectProps,Container, -ACL removes ACL from All. Only the LDAP ones and CARegistry (CA host registry over SMB, with the password of the bind) are collected: Group, Trusts, ACL, Container, ObjectProps, SPNTargets, CertServices, CARegistry (default: All) [aliases: --collectionmethod]
 //!       --ldap-filter <ldap-filter>
-//!           Use custom ldap-filter default is : (objectClass=*)
+//!           Custom LDAP filter AND-ed with every query, default is : (objectClass=*). The objects left out are missing from the graph with all their edges
+//!       --user-filter <FILTER>
+//!           LDAP filter of the users only, like (!(userAccountControl:1.2.840.113556.1.4.803:=2)) for the enabled ones. The users left out are missing from the graph with all their edges
+//!       --computer-filter <FILTER>
+//!           LDAP filter of the computers only. The computers left out are missing from the graph with all their edges
+//!       --group-filter <FILTER>
+//!           LDAP filter of the groups only. The groups left out are missing from the graph with all their edges, their members are not resolved
+//!       --search-base <DN>
+//!           Collect only the objects under this DN of the domain partition, like OU=Workstations,DC=corp,DC=local, can be repeated. The configuration partition is still collected
+//!       --skip <types>
+//!           Comma separated object types not collected nor written, like users,gpos: users, groups, computers, ous, domains, gpos, fsps, containers, trusts, ntauthstores, aiacas, rootcas, enterprisecas, certtemplates, issuancepolicies
+//!       --only <types>
+//!           Comma separated object types collected and written, like computers,enterprisecas, the references to the other objects are not resolved
+//!       --empty-skipped
+//!           Write an empty file for each object type left out by --skip or --only instead of no file
+//!       --adcs-only
+//!           Refresh the AD CS objects only: the Public Key Services container of the configuration partition and the domain object are requested, the type of the principals of their ACEs and the computer of each CA dNSHostName are requested on the Global Catalog one at a time
+//!       --deleted-objects
+//!           Collect the deleted objects of CN=Deleted Objects (AD Recycle Bin) with IsDeleted, contained by their last known parent. Listing them needs an account allowed to, like a domain admin
+//!       --dns
+//!           Collect the A and AAAA records of the AD-integrated DNS zones (DomainDnsZones, ForestDnsZones), written in <domain>_dns.json, and name the computers without dNSHostName from them
+//!       --all-attributes
+//!           Request all the attributes (*) instead of the ones read by the parsers, slower on big domains
 //!       --ldaps
 //!           Force LDAPS using for request like: ldaps://DOMAIN.LOCAL/
+//!       --starttls
+//!           Upgrade the LDAP connection with StartTLS before the bind, when the port 636 is blocked
+//!       --ca-cert <PEM>
+//!           PEM file of the enterprise root CA trusted for the DC certificate, in addition to the system store
+//!       --insecure
+//!           Don't validate the DC certificate with LDAPS or StartTLS (not recommended)
+//!       --no-hostname-verify
+//!           Don't check the DC name in its certificate, for DCs with a certificate for the NetBIOS name only
+//!       --proxy <URL>
+//!           Tunnel the LDAP connections through a SOCKS5 proxy like socks5://[user:password@]127.0.0.1:1080
+//!       --proxy-dns
+//!           Resolve the DC name with the SOCKS5 proxy instead of locally, like socks5h://
+//!       --samba
+//!           Samba AD compatibility mode, auto-detected from the rootDSE vendorName
+//!       --gc
+//!           Collect all the domains of the forest from the Global Catalog (3268, 3269 with --ldaps), the attributes not replicated to the GC from each domain
+//!       --follow-referrals
+//!           Follow the LDAP referrals to the child domains with the same credentials, 3 referrals deep
+//!       --forest
+//!           Collect the other domains of the forest found in the trusts, each from a DC of its domain with the same credentials
+//!       --follow-trusts [<DEPTH>]
+//!           Collect the domains of all the enabled trusts (forest and external ones too), each from a DC of its domain with the same credentials, up to DEPTH trusts away. All the domains are in the same output [default: 3]
 //!   -k, --kerberos
 //!           Use Kerberos authentication. Grabs credentials from ccache file (KRB5CCNAME) based on target parameters for Linux.
+//!       --krb5-conf <FILE>
+//!           krb5.conf used for the Kerberos authentication instead of KRB5_CONFIG or /etc/krb5.conf
+//!       --ccache <FILE>
+//!           Kerberos credential cache used instead of KRB5CCNAME, without it -u/-p request a TGT with kinit
+//!       --realm <REALM>
+//!           Kerberos realm of the -u account when it is not the domain collected (cross-realm trust), like FABRIKAM.LOCAL
+//!       --kerberos-only
+//!           No password bind with -u/-p when the Kerberos authentication fails (clock skew, no SPN, no ticket)
+//!       --anonymous
+//!           Anonymous simple bind, only the objects readable without authentication are collected
 //!       --dns-tcp
 //!           Use TCP instead of UDP for DNS queries
 //!   -z, --zip
-//!           Compress the JSON files into a zip archive
+//!           Compress the JSON files into one <timestamp>_<domain>.zip archive like SharpHound
+//!       --ndjson
+//!           Also write all objects in one NDJSON file (one object per line)
+//!       --reports
+//!           Also write the analysis reports as JSON in the output directory (tier zero ACL exposure, roastable users)
+//!       --include-disabled
+//!           Keep the disabled users and computers in the delegation edges and the analysis reports
 //!       --cache
 //!           Cache LDAP search results to disk (reduce memory usage on large domains)
 //!       --cache-buffer <cache_buffer>
 //!           Buffer size to use when caching [default: 1000]
+//!       --parse-threads <N>
+//!           Threads parsing the LDAP entries, 1 to parse them one at a time [default: all the cores]
 //!       --resume
 //!           Resume the collection from the last saved state
+//!       --dump <FILE>
+//!           Also write the raw LDAP entries to a JSON lines file, parsed again offline with --from-dump
+//!       --from-dump <FILE>
+//!           Parse the LDAP entries of a --dump file instead of requesting the DC
+//!       --cache-state <FILE>
+//!           Incremental collection: only the objects changed since the USNs of this file are collected, the USNs of the DC are recorded in it at the end of the run. A full collection when the file doesn't exist or was recorded on another DC
+//!       --count-only
+//!           Request and parse the objects, print their count by type then exit: no module is run and no file is written
+//!       --print-config
+//!           Print the effective collection options (profile and flags) then exit
+//!       --json
+//!           With --version, print the build metadata (version, commit, date, features) as JSON
+//!       --check-update
+//!           Compare the version with the latest GitHub release then exit, nothing is downloaded
 //! 
 //! OPTIONAL MODULES:
 //!       --fqdn-resolver  Use fqdn-resolver module to get computers IP address
+//!       --honeypot       Use honeypot module to score users and computers on deception indicators (honeypotscore property)
+//!       --honeypot-config <honeypot-config>
+//!           JSON file to tune the honeypot indicators weights, enables the honeypot module
+//!       --acl-baseline   Compare ACEs with the schema default security descriptors (isdefault and acedeviation properties)
+//!       --resolve-foreign
+//!           Resolve the foreign principals (ACEs and members not collected) with the Global Catalog, cached in .rusthound-cache
+//!       --coverage-report
+//!           Compare the requested attributes and the edges produced with SharpHound CE in <domain>_coverage.txt
+//!       --provenance
+//!           Record the source (DC, module, time) of each property group of each object in <domain>_provenance.json
 //! ```
 //! 
-//! Or build your own using the ldap_search() function:
-//! ```ignore
-//! # use rusthound::ldap::ldap_search;
-//! # let ldaps = true;
-//! # let ip = Some("127.0.0.1");
-//! # let port = Some(676);
-//! # let domain = "DOMAIN.COM";
-//! # let ldapfqdn = "ad1.domain.com";
-//! # let username = Some("user");
-//! # let password = Some("pwd");
-//! # let kerberos= false;
-//! let result = ldap_search(
-//!     &ldaps,
-//!     &Some(ip),
-//!     &Some(port),
-//!     &domain,
-//!     &ldapfqdn,
-//!     &username,
-//!     &password,
-//!     kerberos,
-//! );
+//! Or embed the collection in your own tool with the [`Collector`] API:
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let collector = rusthound_ce::Collector::builder("contoso.local")
+//!     .ldapfqdn("dc01.contoso.local")
+//!     .credentials("user", "Password123")
+//!     .build();
+//! let results = collector.collect().await?;
+//! println!("{} users collected", results.users.len());
+//! collector.write(results)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The progress of the collection is reported to the [`ProgressObserver`] set with [`set_progress_observer`], the
+//! phases and the count of each object type as they are parsed, see `examples/progress.rs`.
+//!
+//! Or build your own using the ldap_search() function, with the settings of the LDAP connections loaded from the options:
+//! ```no_run
+//! use rusthound_ce::Collector;
+//! use rusthound_ce::ldap::{ldap_search, LdapSearchEntry, LdapSettings};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let collector = Collector::builder("domain.com")
+//!     .ldapfqdn("ad1.domain.com")
+//!     .credentials("user", "pwd")
+//!     .ldaps(true)
+//!     .build();
+//! let options = collector.options();
+//! let settings = LdapSettings::from_options(options)?;
+//! let mut entries: Vec<LdapSearchEntry> = Vec::new();
+//! let total = ldap_search(options, &settings, true, None, &mut entries).await?;
+//! println!("{total} LDAP entries collected");
+//! # Ok(())
+//! # }
 //! ```
 //! 
 pub mod args;
+pub mod attributes;
 pub mod banner;
+pub mod buildinfo;
 pub mod ldap;
 pub mod utils;
 
 pub mod enums;
+pub mod error;
 pub mod json;
 pub mod objects;
+pub mod events;
+pub mod collector;
+pub mod config;
+pub mod credentials;
+pub mod deadline;
+pub mod deleted;
+pub mod dns;
+pub mod filters;
+pub mod forest;
+pub mod incremental;
+pub mod methods;
+pub mod objecttypes;
+pub mod profile;
+pub mod confignc;
+pub mod coverage;
+pub mod provenance;
+pub mod proxy;
+pub mod resolver;
+pub mod globalcatalog;
+pub mod paging;
+pub mod ranged;
+pub mod referral;
+pub mod registry;
+pub mod rootdse;
+pub mod sicily;
+pub mod smb;
+pub mod interrupt;
+pub mod verify;
 pub (crate) mod storage;
 
 pub (crate) mod api;
+#[cfg(test)]
+pub (crate) mod test_support;
 
 extern crate bitflags;
 extern crate chrono;
@@ -109,5 +299,9 @@ pub use ldap::ldap_search;
 pub use ldap3::SearchEntry;
 
 pub use json::maker::make_result;
-pub use api::prepare_results_from_source;
-pub use storage::{Storage, EntrySource, DiskStorage, DiskStorageReader};
\ No newline at end of file
+pub use api::{object_counts, prepare_results_from_source, ADResults, DomainMappings};
+pub use buildinfo::{build_info, BuildInfo};
+pub use collector::{Collector, CollectorBuilder, CollectionResult};
+pub use error::RustHoundError;
+pub use events::{clear_progress_observer, set_progress_observer, Event, ProgressObserver};
+pub use storage::{Storage, EntrySource, DiskStorage, DiskStorageReader, DumpReader, DumpWriter};
\ No newline at end of file
@@ -2,10 +2,11 @@ pub mod banner;
 pub mod modules;
 
 use env_logger::Builder;
-use log::{error, info, trace};
+use log::{error, info, trace, warn};
 
 use rusthound_ce::{
-    args, ldap, objects,
+    args, objects, events, interrupt, deadline, buildinfo,
+    Collector,
     DiskStorage, DiskStorageReader,
     utils,
 };
@@ -18,8 +19,11 @@ use args::auto_args;
 #[cfg(not(feature = "noargs"))]
 use args::{extract_args, Options};
 
-use banner::{print_banner, print_end_banner};
-use ldap::ldap_search;
+use buildinfo::{check_update, print_update_status, GithubReleases};
+use banner::{print_banner, print_end_banner, print_object_counts, print_warnings_summary};
+use events::{emit, init_progress_events, Event, EventLogger};
+use deadline::is_deadline_reached;
+use interrupt::{install_signal_handlers, interrupted_phase, is_interrupted, EXIT_PARTIAL_OUTPUT};
 use modules::run_modules;
 
 const CACHE_DIR: &str = ".rusthound-cache";
@@ -28,33 +32,91 @@ const CACHE_FILE: &str = "ldap.bin";
 /// Main of RustHound
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    // Banner
-    print_banner();
-
     // Get args
     #[cfg(not(feature = "noargs"))]
     let common_args: Options = extract_args();
     #[cfg(feature = "noargs")]
     let common_args = auto_args();
 
+    // Keep stdout clean when progress events are written on it
+    let events_on_stdout = matches!(common_args.progress_json.as_deref(), Some("-") | Some("stdout"));
+
+    // Banner
+    if !events_on_stdout {
+        print_banner();
+    }
+
     // Build logger
-    Builder::new()
+    let mut builder = Builder::new();
+    builder
         .filter(Some("rusthound"), common_args.verbose)
-        .filter_level(log::LevelFilter::Error)
-        .init();
+        .filter_level(log::LevelFilter::Error);
+    match &common_args.progress_json {
+        Some(target) => {
+            init_progress_events(target)?;
+            EventLogger::init(builder)?;
+        }
+        None => builder.init(),
+    }
 
     // Get verbose level
     info!("Verbosity level: {:?}", common_args.verbose);
-    info!("Collection method: {:?}", common_args.collection_method);
+    info!("Collection methods: {}", common_args.collection_method);
+
+    // Opt-in update check, the only request outside of the target domain
+    match check_update(common_args.check_update, &GithubReleases) {
+        Ok(Some(status)) => {
+            print_update_status(&status);
+            return Ok(())
+        }
+        Ok(None) => {}
+        Err(err) => {
+            error!("Failed to check the latest release: {err}");
+            std::process::exit(1);
+        }
+    }
+
+    // Ctrl-c / SIGTERM: stop the LDAP search and write partial output
+    install_signal_handlers();
+
+    let collector = Collector::from_options(common_args.clone());
+
+    // Read-only verification of a previous collection
+    if let Some(verify) = &common_args.verify {
+        collector.verify(&verify.findings, &verify.previous).await?;
+        return Ok(())
+    }
+
+    // Scoping: the count of the objects of each type, no module and no file
+    if common_args.count_only {
+        let mut entries = Vec::new();
+        let total = collector.search(&mut entries).await?;
+        let counts = collector.count(entries, Some(total))?;
+        emit(Event::Finished);
+        if !events_on_stdout {
+            print_object_counts(&common_args.domain, &counts);
+        }
+        if is_interrupted() {
+            warn!("Interrupted, the counts are partial");
+            std::process::exit(EXIT_PARTIAL_OUTPUT);
+        }
+        return Ok(())
+    }
 
     let mut results = match common_args.resume {
+        // Offline: the entries of a previous --dump
+        false if common_args.from_dump.is_some() => {
+            let dump = common_args.from_dump.as_deref().unwrap_or_default();
+            info!("Parsing the LDAP entries of {}", dump.bold());
+            collector.process_dump(dump).await?
+        }
         true => {
             let ldap_cache_path = std::path::PathBuf::from(CACHE_DIR)
                 .join(&common_args.domain)
                 .join(CACHE_FILE);
             info!("Resuming from cache: {}", format!("{}",ldap_cache_path.display()).bold());
             let cache = DiskStorageReader::from_path(ldap_cache_path)?;
-            rusthound_ce::prepare_results_from_source(cache, &common_args, None).await?
+            collector.process(cache, None).await?
         }
         false => {
             if common_args.cache {
@@ -73,64 +135,54 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     ldap_cache_path,
                     common_args.cache_buffer_size,
                 )?;
-
-                let total_cached = ldap_search(
-                    common_args.ldaps,
-                    common_args.ip.as_deref(),
-                    common_args.port,
-                    &common_args.domain,
-                    &common_args.ldapfqdn,
-                    common_args.username.as_deref(),
-                    common_args.password.as_deref(),
-                    common_args.kerberos,
-                    &common_args.ldap_filter,
-                    &mut cache_writer,
-                )
-                .await?;
-
-                rusthound_ce::prepare_results_from_source(
-                    cache_writer.into_reader()?,
-                    &common_args,
-                    Some(total_cached),
-                )
-                .await?
+                let total_cached = collector.search(&mut cache_writer).await?;
+                collector.process(cache_writer.into_reader()?, Some(total_cached)).await?
             } else {
                 // store ldap results in memory
-                let mut ldap_results = Vec::new();
-                let total = rusthound_ce::ldap::ldap_search(
-                    common_args.ldaps,
-                    common_args.ip.as_deref(),
-                    common_args.port,
-                    &common_args.domain,
-                    &common_args.ldapfqdn,
-                    common_args.username.as_deref(),
-                    common_args.password.as_deref(),
-                    common_args.kerberos,
-                    &common_args.ldap_filter,
-                    &mut ldap_results,
-                )
-                .await?;
-                rusthound_ce::prepare_results_from_source(ldap_results, &common_args, Some(total))
-                    .await?
+                collector.collect().await?
             }
         }
     };
 
-    // Running modules
-    run_modules(
-        &common_args,
-        &mut results.mappings.fqdn_ip,
-        &mut results.computers,
-    )
-    .await?;
+    // Interrupted: mark the output as partial and skip the network modules
+    if is_interrupted() {
+        results.partial = interrupted_phase();
+    } else if is_deadline_reached() {
+        warn!("Deadline reached, network modules skipped");
+    } else {
+        // Running modules
+        emit(Event::phase_started("modules"));
+        run_modules(
+            &common_args,
+            &mut results.mappings.fqdn_ip,
+            &mut results.computers,
+        )
+        .await?;
+        emit(Event::phase_finished("modules"));
+    }
+
+    // Optional analysis (honeypot scores)
+    collector.analyze(&mut results)?;
 
     // Add all in json files
-    match rusthound_ce::make_result(&common_args, results) {
-        Ok(_) => trace!("Making json/zip files finished!"),
-        Err(err) => error!("Error. Reason: {err}"),
+    let warnings = results.warnings.clone();
+    let partial = results.partial.is_some();
+    // No output written: an error, not a partial run
+    if let Err(err) = collector.write(results) {
+        error!("Failed to write the output files: {err}");
+        std::process::exit(1);
     }
+    trace!("Making json/zip files finished!");
+
+    emit(Event::Finished);
 
     // End banner
-    print_end_banner();
+    if !events_on_stdout {
+        print_warnings_summary(&warnings);
+        print_end_banner();
+    }
+    if is_interrupted() || partial {
+        std::process::exit(EXIT_PARTIAL_OUTPUT);
+    }
     Ok(())
 }
@@ -0,0 +1,258 @@
+//! SharpHound collection methods
+//!
+//! `-c/--collection-methods` takes a comma separated list of SharpHound methods like `DCOnly`, `ACL,ObjectProps`
+//! or `All,-ACL`, a method prefixed by `-` is removed. RustHound-CE only collects the LDAP methods and `CARegistry`,
+//! the bits of the ones selected are the `methods` of the output meta, read by BloodHound CE.
+//!
+//! The methods turned off are not requested at all:
+//!
+//! - `ACL`: no `nTSecurityDescriptor` requested, the `Aces` are empty
+//! - `Group`, `Trusts`, `Container` (containers, OUs), `CertServices` (configuration NC PKI objects): their objects
+//!   are left out of the LDAP filter, and no fallback on a writable DC for the PKI objects
+//! - `ObjectProps`: only the identity properties of the objects (name, domain, DN...) are written
+//! - `SPNTargets`: the `SPNTargets` of the users are empty
+//! - `CARegistry`: the registry of the enterprise CA hosts is not read (see [`crate::registry`])
+
+use bitflags::bitflags;
+use serde_json::Value;
+
+bitflags! {
+    /// SharpHound collection methods, with the bits of SharpHoundCommon.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct CollectionMethod: u32 {
+        const GROUP = 1;
+        const LOCAL_ADMIN = 1 << 1;
+        const GPO_LOCAL_GROUP = 1 << 2;
+        const SESSION = 1 << 3;
+        const LOGGED_ON = 1 << 4;
+        const TRUSTS = 1 << 5;
+        const ACL = 1 << 6;
+        const CONTAINER = 1 << 7;
+        const RDP = 1 << 8;
+        const OBJECT_PROPS = 1 << 9;
+        const SESSION_LOOP = 1 << 10;
+        const LOGGED_ON_LOOP = 1 << 11;
+        const DCOM = 1 << 12;
+        const SPN_TARGETS = 1 << 13;
+        const PS_REMOTE = 1 << 14;
+        const USER_RIGHTS = 1 << 15;
+        const CA_REGISTRY = 1 << 16;
+        const DC_REGISTRY = 1 << 17;
+        const CERT_SERVICES = 1 << 18;
+        const WEB_CLIENT_SERVICE = 1 << 19;
+        const LDAP_SERVICES = 1 << 20;
+        const SMB_INFO = 1 << 21;
+        const NTLM_REGISTRY = 1 << 22;
+
+        const LOCAL_GROUP = Self::DCOM.bits() | Self::RDP.bits() | Self::LOCAL_ADMIN.bits() | Self::PS_REMOTE.bits();
+        const COMPUTER_ONLY = Self::LOCAL_GROUP.bits() | Self::SESSION.bits() | Self::USER_RIGHTS.bits()
+            | Self::CA_REGISTRY.bits() | Self::DC_REGISTRY.bits() | Self::WEB_CLIENT_SERVICE.bits()
+            | Self::SMB_INFO.bits() | Self::NTLM_REGISTRY.bits();
+        const DC_ONLY = Self::ACL.bits() | Self::CONTAINER.bits() | Self::GROUP.bits() | Self::OBJECT_PROPS.bits()
+            | Self::TRUSTS.bits() | Self::GPO_LOCAL_GROUP.bits() | Self::CERT_SERVICES.bits() | Self::LDAP_SERVICES.bits();
+        const DEFAULT = Self::GROUP.bits() | Self::SESSION.bits() | Self::TRUSTS.bits() | Self::ACL.bits()
+            | Self::OBJECT_PROPS.bits() | Self::LOCAL_GROUP.bits() | Self::SPN_TARGETS.bits() | Self::CONTAINER.bits()
+            | Self::CERT_SERVICES.bits() | Self::LDAP_SERVICES.bits() | Self::SMB_INFO.bits();
+        const ALL = Self::DEFAULT.bits() | Self::LOGGED_ON.bits() | Self::GPO_LOCAL_GROUP.bits()
+            | Self::USER_RIGHTS.bits() | Self::CA_REGISTRY.bits() | Self::DC_REGISTRY.bits()
+            | Self::WEB_CLIENT_SERVICE.bits() | Self::NTLM_REGISTRY.bits();
+
+        /// Methods collected by RustHound-CE, from LDAP.
+        const LDAP = Self::GROUP.bits() | Self::TRUSTS.bits() | Self::ACL.bits() | Self::CONTAINER.bits()
+            | Self::OBJECT_PROPS.bits() | Self::SPN_TARGETS.bits() | Self::CERT_SERVICES.bits();
+    }
+}
+
+/// SharpHound names of the methods, case insensitive on the command line.
+const METHOD_NAMES: &[(&str, CollectionMethod)] = &[
+    ("All", CollectionMethod::ALL),
+    ("Default", CollectionMethod::DEFAULT),
+    ("DCOnly", CollectionMethod::DC_ONLY),
+    ("ComputerOnly", CollectionMethod::COMPUTER_ONLY),
+    ("LocalGroup", CollectionMethod::LOCAL_GROUP),
+    ("Group", CollectionMethod::GROUP),
+    ("LocalAdmin", CollectionMethod::LOCAL_ADMIN),
+    ("GPOLocalGroup", CollectionMethod::GPO_LOCAL_GROUP),
+    ("Session", CollectionMethod::SESSION),
+    ("LoggedOn", CollectionMethod::LOGGED_ON),
+    ("Trusts", CollectionMethod::TRUSTS),
+    ("ACL", CollectionMethod::ACL),
+    ("Container", CollectionMethod::CONTAINER),
+    ("RDP", CollectionMethod::RDP),
+    ("ObjectProps", CollectionMethod::OBJECT_PROPS),
+    ("SessionLoop", CollectionMethod::SESSION_LOOP),
+    ("LoggedOnLoop", CollectionMethod::LOGGED_ON_LOOP),
+    ("DCOM", CollectionMethod::DCOM),
+    ("SPNTargets", CollectionMethod::SPN_TARGETS),
+    ("PSRemote", CollectionMethod::PS_REMOTE),
+    ("UserRights", CollectionMethod::USER_RIGHTS),
+    ("CARegistry", CollectionMethod::CA_REGISTRY),
+    ("DCRegistry", CollectionMethod::DC_REGISTRY),
+    ("CertServices", CollectionMethod::CERT_SERVICES),
+    ("WebClientService", CollectionMethod::WEB_CLIENT_SERVICE),
+    ("LdapServices", CollectionMethod::LDAP_SERVICES),
+    ("SmbInfo", CollectionMethod::SMB_INFO),
+    ("NTLMRegistry", CollectionMethod::NTLM_REGISTRY),
+];
+
+/// Objects of the methods selecting LDAP objects, left out of the filter when the method is off.
+const METHOD_FILTERS: &[(CollectionMethod, &str)] = &[
+    (CollectionMethod::GROUP, "(objectClass=group)"),
+    (CollectionMethod::TRUSTS, "(objectClass=trustedDomain)"),
+    (
+        CollectionMethod::CONTAINER,
+        "(&(objectClass=container)(!(objectClass=groupPolicyContainer)))(objectClass=organizationalUnit)(objectClass=builtinDomain)",
+    ),
+    (
+        CollectionMethod::CERT_SERVICES,
+        "(objectClass=pKIEnrollmentService)(objectClass=pKICertificateTemplate)(objectClass=certificationAuthority)(objectClass=msPKI-Enterprise-Oid)",
+    ),
+];
+
+/// Properties written without `ObjectProps`, the identity of the object.
+pub const IDENTITY_PROPERTIES: &[&str] = &[
+    "name", "domain", "domainsid", "distinguishedname", "samaccountname", "isaclprotected", "isdeleted", "highvalue",
+];
+
+impl Default for CollectionMethod {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl std::fmt::Display for CollectionMethod {
+    /// SharpHound name of a group of methods, or the names of the methods.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((name, _)) = METHOD_NAMES.iter().find(|(_, methods)| methods == self) {
+            return f.write_str(name)
+        }
+        let names: Vec<&str> = METHOD_NAMES.iter()
+            .filter(|(_, method)| method.bits().is_power_of_two() && self.contains(*method))
+            .map(|(name, _)| *name)
+            .collect();
+        f.write_str(&names.join(","))
+    }
+}
+
+impl CollectionMethod {
+    /// Methods selected and collected by RustHound-CE, the `methods` of the output meta.
+    pub fn collected(self) -> Self {
+        self & (Self::LDAP | Self::CA_REGISTRY)
+    }
+}
+
+/// Function to parse a comma separated list of methods, `-` before a method removes it.
+pub fn parse_collection_methods(value: &str) -> Result<CollectionMethod, String> {
+    let mut methods = CollectionMethod::empty();
+    for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        let (removed, name) = match name.strip_prefix('-') {
+            Some(name) => (true, name),
+            None => (false, name),
+        };
+        let Some((_, method)) = METHOD_NAMES.iter().find(|(known, _)| known.eq_ignore_ascii_case(name)) else {
+            return Err(format!(
+                "unknown collection method '{name}', expected one of: {}",
+                METHOD_NAMES.iter().map(|(known, _)| *known).collect::<Vec<&str>>().join(", ")
+            ))
+        };
+        // A list starting with a removal removes from All
+        if removed && methods.is_empty() {
+            methods = CollectionMethod::ALL;
+        }
+        methods.set(*method, !removed);
+    }
+    if (methods & CollectionMethod::LDAP).is_empty() {
+        return Err(format!("no LDAP collection method in '{value}'"))
+    }
+    Ok(methods)
+}
+
+/// Function to get the LDAP filter of the collection: the `--ldap-filter` without the objects of the methods turned off.
+pub fn methods_filter(ldapfilter: &str, methods: CollectionMethod) -> String {
+    let excluded: String = METHOD_FILTERS.iter()
+        .filter(|(method, _)| !methods.contains(*method))
+        .map(|(_, filter)| *filter)
+        .collect();
+    match (excluded.is_empty(), ldapfilter) {
+        (true, _) => ldapfilter.to_owned(),
+        (false, "" | "(objectClass=*)") => format!("(!(|{excluded}))"),
+        (false, custom) => format!("(&{custom}(!(|{excluded})))"),
+    }
+}
+
+/// Function to empty the fields of an output object collected by the methods turned off.
+pub fn strip_object(json: &mut Value, methods: CollectionMethod) {
+    if !methods.contains(CollectionMethod::OBJECT_PROPS) {
+        if let Some(properties) = json.get_mut("Properties").and_then(Value::as_object_mut) {
+            properties.retain(|name, _| IDENTITY_PROPERTIES.contains(&name.as_str()));
+        }
+    }
+    if !methods.contains(CollectionMethod::ACL) {
+        if let Some(aces) = json.get_mut("Aces") {
+            *aces = Value::Array(Vec::new());
+        }
+    }
+    if !methods.contains(CollectionMethod::SPN_TARGETS) {
+        if let Some(targets) = json.get_mut("SPNTargets") {
+            *targets = Value::Array(Vec::new());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serde_json::json;
+
+    use crate::methods::*;
+
+    #[test]
+    pub fn test_parse_collection_methods() {
+        assert_eq!(parse_collection_methods("All").unwrap(), CollectionMethod::ALL);
+        assert_eq!(parse_collection_methods("dconly").unwrap(), CollectionMethod::DC_ONLY);
+        let methods = parse_collection_methods("ACL, ObjectProps,Container").unwrap();
+        assert_eq!(methods.bits(), (1 << 6) | (1 << 9) | (1 << 7));
+        assert_eq!(methods.to_string(), "ACL,Container,ObjectProps");
+        assert_eq!(CollectionMethod::DC_ONLY.to_string(), "DCOnly");
+        // Removed from All, or from the methods listed before
+        let methods = parse_collection_methods("-ACL").unwrap();
+        assert!(!methods.contains(CollectionMethod::ACL) && methods.contains(CollectionMethod::CERT_SERVICES));
+        assert_eq!(parse_collection_methods("DCOnly,-CertServices").unwrap(), CollectionMethod::DC_ONLY - CollectionMethod::CERT_SERVICES);
+        assert!(parse_collection_methods("Sessions").unwrap_err().contains("unknown collection method 'Sessions'"));
+        // Nothing RustHound-CE can collect
+        assert!(parse_collection_methods("Session,LoggedOn").is_err());
+
+        // Meta of BloodHound CE: the LDAP methods and CARegistry
+        assert_eq!(CollectionMethod::ALL.collected().bits(), 0x522e1);
+        assert!(parse_collection_methods("CARegistry").is_err());
+        assert_eq!(CollectionMethod::DC_ONLY.collected(), CollectionMethod::LDAP - CollectionMethod::SPN_TARGETS);
+    }
+
+    #[test]
+    pub fn test_methods_filter() {
+        assert_eq!(methods_filter("(objectClass=*)", CollectionMethod::ALL), "(objectClass=*)");
+        let filter = methods_filter("(objectClass=*)", CollectionMethod::ALL - CollectionMethod::CERT_SERVICES - CollectionMethod::TRUSTS);
+        assert_eq!(filter, "(!(|(objectClass=trustedDomain)(objectClass=pKIEnrollmentService)(objectClass=pKICertificateTemplate)(objectClass=certificationAuthority)(objectClass=msPKI-Enterprise-Oid)))");
+        assert_eq!(
+            methods_filter("(sAMAccountName=alice)", CollectionMethod::ALL - CollectionMethod::GROUP),
+            "(&(sAMAccountName=alice)(!(|(objectClass=group))))"
+        );
+    }
+
+    #[test]
+    pub fn test_strip_object() {
+        let user = json!({
+            "Properties": {"name": "ALICE@CONTOSO.LOCAL", "domain": "CONTOSO.LOCAL", "description": "Helpdesk", "lastlogon": 0},
+            "Aces": [{"RightName": "GenericAll"}],
+            "SPNTargets": [{"ComputerSID": "S-1-5-21-1-1104", "Port": 1433, "Service": "SQLAdmin"}],
+        });
+        let mut stripped = user.to_owned();
+        strip_object(&mut stripped, CollectionMethod::ALL);
+        assert_eq!(stripped, user);
+
+        strip_object(&mut stripped, CollectionMethod::DC_ONLY - CollectionMethod::OBJECT_PROPS - CollectionMethod::ACL);
+        assert_eq!(stripped["Properties"], json!({"name": "ALICE@CONTOSO.LOCAL", "domain": "CONTOSO.LOCAL"}));
+        assert_eq!(stripped["Aces"], json!([]));
+        assert_eq!(stripped["SPNTargets"], json!([]));
+    }
+}
@@ -23,8 +23,10 @@ pub async fn resolving_all_fqdn(
    {
       for i in 0..vec_computer.len()
       {
-         if (*vec_computer[i].properties().name() == value.0.to_owned()) 
-         && (*vec_computer[i].properties().enabled()) {
+         let properties = vec_computer[i].properties();
+         // The computer name or one of its additional host names
+         if (*properties.name() == value.0 || properties.additionalhostnames().contains(&value.0))
+         && (*properties.enabled()) {
             debug!("Trying to resolve FQDN: {}",value.0.to_string());
             // Resolve FQDN to IP address
             let address = resolver(value.0.to_string(),dns_tcp,name_server).await;
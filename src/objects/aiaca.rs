@@ -1,16 +1,15 @@
 use serde_json::value::Value;
 use serde::{Deserialize, Serialize};
-use x509_parser::oid_registry::asn1_rs::oid;
-use x509_parser::prelude::*;
+use base64::Engine;
 use ldap3::SearchEntry;
 use log::{debug, error, trace};
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::RustHoundError;
 
 use crate::objects::common::{LdapObject, AceTemplate, SPNTarget, Link, Member};
-use crate::enums::{decode_guid_le, parse_ntsecuritydescriptor};
+use crate::enums::{decode_guid_le, dedup_aces, parse_ntsecuritydescriptor};
 use crate::utils::date::string_to_epoch;
-use crate::utils::crypto::calculate_sha1;
+use crate::utils::crypto::{basic_constraints, calculate_sha1, certificate_parse_failure, current_certificate};
 
 /// AIACA structure
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
@@ -45,7 +44,7 @@ impl AIACA {
         dn_sid: &mut HashMap<String, String>,
         sid_type: &mut HashMap<String, String>,
         domain_sid: &str
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), RustHoundError> {
         let result_dn: String = result.dn.to_uppercase();
         let result_attrs: HashMap<String, Vec<String>> = result.attrs;
         let result_bin: HashMap<String, Vec<Vec<u8>>> = result.bin_attrs;
@@ -85,12 +84,14 @@ impl AIACA {
                         self.properties.whencreated = epoch;
                     }
                 }
-                "IsDeleted" => {
+                "isDeleted" => {
                     self.is_deleted = true;
                 }
                 "crossCertificatePair" => {
-                    self.properties.hascrosscertificatepair = true;
-                    // self.properties.crosscertificatepair = value[0].to_owned();
+                    self.properties.crosscertificatepair = value.iter()
+                        .map(|pair| base64::engine::general_purpose::STANDARD.encode(pair.as_bytes()))
+                        .collect();
+                    self.properties.hascrosscertificatepair = !value.is_empty();
                 }
                 _ => {}
             }
@@ -114,50 +115,36 @@ impl AIACA {
                         &result_bin,
                         domain,
                     );
-                    self.aces = relations_ace;
+                    self.aces = dedup_aces(relations_ace);
+                }
+                "crossCertificatePair" => {
+                    // DER CertificatePair (forward and reverse cross certificates), base64 like SharpHound
+                    self.properties.crosscertificatepair = value.iter()
+                        .map(|pair| base64::engine::general_purpose::STANDARD.encode(pair))
+                        .collect();
+                    self.properties.hascrosscertificatepair = !value.is_empty();
                 }
                 "cACertificate" => {
-                    //info!("{:?}:{:?}", key,value[0].to_owned());
-                    let certsha1: String = calculate_sha1(&value[0]);
+                    // A renewed CA publishes one certificate per key, the chain lists them all
+                    self.properties.certchain = value.iter().map(|cert| calculate_sha1(cert)).collect();
+                    let current = current_certificate(value);
+                    let certsha1: String = calculate_sha1(&value[current.as_ref().map_or(0, |(index, _)| *index)]);
                     self.properties.certthumbprint = certsha1.to_owned();
                     self.properties.certname = certsha1.to_owned();
-                    self.properties.certchain = vec![certsha1.to_owned()];
 
                     // Parsing certificate.
-                    let res = X509Certificate::from_der(&value[0]);
-                    match res {
-                        Ok((_rem, cert)) => {
-                            // println!("Basic Constraints Extensions:");
-                            for ext in cert.extensions() {
-                                // println!("{:?} : {:?}",&ext.oid, ext);
-                                if &ext.oid == &oid!(2.5.29.19) {
-                                    // <https://docs.rs/x509-parser/latest/x509_parser/extensions/struct.BasicConstraints.html>
-                                    if let ParsedExtension::BasicConstraints(basic_constraints) = &ext.parsed_extension() {
-                                        let _ca = &basic_constraints.ca;
-                                        let _path_len_constraint = &basic_constraints.path_len_constraint;
-                                        // println!("ca: {:?}", _ca);
-                                        // println!("path_len_constraint: {:?}", _path_len_constraint);
-                                        match _path_len_constraint {
-                                            Some(_path_len_constraint) => {
-                                                if _path_len_constraint > &0 {
-                                                    self.properties.hasbasicconstraints = true;
-                                                    self.properties.basicconstraintpathlength = _path_len_constraint.to_owned();
-
-                                                } else {
-                                                    self.properties.hasbasicconstraints = false;
-                                                    self.properties.basicconstraintpathlength = 0;
-                                                }
-                                            },
-                                            None => {
-                                                self.properties.hasbasicconstraints = false;
-                                                self.properties.basicconstraintpathlength = 0;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                    match current {
+                        Some((_index, cert)) => {
+                            // <https://docs.rs/x509-parser/latest/x509_parser/extensions/struct.BasicConstraints.html>
+                            let (ca, path_length) = basic_constraints(&cert);
+                            self.properties.hasbasicconstraints = ca;
+                            self.properties.basicconstraintpathlength = path_length;
                         },
-                        _ => error!("CA x509 certificate parsing failed: {:?}", res),
+                        None => {
+                            let failure = certificate_parse_failure(value);
+                            error!("{failure} for {}", self.properties.distinguishedname);
+                            self.properties.certparsefailure = Some(failure);
+                        }
                     }
                 }
                 _ => {}
@@ -272,6 +259,8 @@ pub struct AIACAProperties {
    certchain: Vec<String>,
    hasbasicconstraints: bool,
    basicconstraintpathlength: u32,
+   /// Error of the CA certificate when none of its certificates could be decoded, none once parsed.
+   certparsefailure: Option<String>,
 }
 
 impl Default for AIACAProperties {
@@ -291,6 +280,60 @@ impl Default for AIACAProperties {
             certchain: Vec::new(),
             hasbasicconstraints: false,
             basicconstraintpathlength: 0,
+            certparsefailure: None,
        }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use base64::Engine;
+    use ldap3::SearchEntry;
+
+    use crate::objects::aiaca::AIACA;
+    use crate::objects::common::LdapObject;
+    use crate::utils::crypto::calculate_sha1;
+    use crate::utils::crypto::tests::{CERTIFICATE, ISSUING_CA};
+
+    #[test]
+    pub fn test_aiaca_parse() {
+        let certificate = base64::engine::general_purpose::STANDARD.decode(ISSUING_CA).unwrap();
+        let cross_certificate = base64::engine::general_purpose::STANDARD.decode(CERTIFICATE).unwrap();
+        // CertificatePair ::= SEQUENCE { forward [0] Certificate OPTIONAL, reverse [1] Certificate OPTIONAL }
+        let forward = [&[0xA0, 0x82, (cross_certificate.len() >> 8) as u8, cross_certificate.len() as u8][..], &cross_certificate].concat();
+        let pair = [&[0x30, 0x82, (forward.len() >> 8) as u8, forward.len() as u8][..], &forward].concat();
+        let entry = SearchEntry {
+            dn: "CN=contoso-DC01-CA,CN=AIA,CN=Public Key Services,CN=Services,CN=Configuration,DC=CONTOSO,DC=LOCAL".to_string(),
+            attrs: HashMap::from([
+                ("name".to_string(), vec!["contoso-DC01-CA".to_string()]),
+                ("whenCreated".to_string(), vec!["20240101120000.0Z".to_string()]),
+            ]),
+            bin_attrs: HashMap::from([
+                ("objectGUID".to_string(), vec![vec![0x0C, 0x6E, 0x6B, 0x5A, 0x8B, 0x6A, 0x3E, 0x4E, 0x9B, 0x2D, 0x1C, 0x2D, 0x3E, 0x4F, 0x5A, 0x6B]]),
+                ("cACertificate".to_string(), vec![certificate.to_owned()]),
+                ("crossCertificatePair".to_string(), vec![pair.to_owned()]),
+            ]),
+        };
+        let mut dn_sid = HashMap::new();
+        let mut sid_type = HashMap::new();
+        let mut aiaca = AIACA::new();
+        aiaca.parse(entry, "contoso.local", &mut dn_sid, &mut sid_type, "S-1-5-21-1-2-3").unwrap();
+
+        assert_eq!(aiaca.get_object_identifier(), "5A6B6E0C-6A8B-4E3E-9B2D-1C2D3E4F5A6B");
+        assert_eq!(sid_type.get(aiaca.get_object_identifier()).map(String::as_str), Some("AIACA"));
+        assert_eq!(aiaca.properties.name, "CONTOSO-DC01-CA@CONTOSO.LOCAL");
+        assert_eq!(aiaca.properties.certthumbprint, calculate_sha1(&certificate));
+        assert_eq!(aiaca.properties.certname, aiaca.properties.certthumbprint);
+        assert_eq!(aiaca.properties.certchain, vec![calculate_sha1(&certificate)]);
+        assert!(aiaca.properties.hasbasicconstraints);
+        assert!(aiaca.properties.hascrosscertificatepair);
+        assert_eq!(aiaca.properties.crosscertificatepair, vec![base64::engine::general_purpose::STANDARD.encode(&pair)]);
+        // No nTSecurityDescriptor read
+        assert!(aiaca.get_aces().is_empty());
+        let json = aiaca.to_json();
+        assert_eq!(json["DomainSID"], "S-1-5-21-1-2-3");
+        assert_eq!(json["Properties"]["distinguishedname"], "CN=CONTOSO-DC01-CA,CN=AIA,CN=PUBLIC KEY SERVICES,CN=SERVICES,CN=CONFIGURATION,DC=CONTOSO,DC=LOCAL");
+    }
+}
@@ -3,10 +3,10 @@ use serde::{Deserialize, Serialize};
 use ldap3::SearchEntry;
 use log::{debug, trace};
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::RustHoundError;
 
 use crate::objects::common::{LdapObject, AceTemplate, SPNTarget, Link, Member};
-use crate::enums::{decode_guid_le, get_pki_cert_name_flags, get_pki_enrollment_flags, parse_ntsecuritydescriptor};
+use crate::enums::{decode_guid_le, get_pki_cert_name_flags, get_pki_enrollment_flags, dedup_aces, parse_ntsecuritydescriptor};
 use crate::json::checker::common::get_name_from_full_distinguishedname;
 use crate::utils::date::{filetime_to_span, span_to_string, string_to_epoch};
 
@@ -49,7 +49,7 @@ impl CertTemplate {
         dn_sid: &mut HashMap<String, String>,
         sid_type: &mut HashMap<String, String>,
         domain_sid: &str
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), RustHoundError> {
         let result_dn: String = result.dn.to_uppercase();
         let result_attrs: HashMap<String, Vec<String>> = result.attrs;
         let result_bin: HashMap<String, Vec<Vec<u8>>> = result.bin_attrs;
@@ -88,15 +88,22 @@ impl CertTemplate {
                 "msPKI-Certificate-Name-Flag" => {
                     if !value.is_empty() {
                         self.properties.certificatenameflag = get_pki_cert_name_flags(value[0].parse::<i64>().unwrap_or(0) as u64);
-                        self.properties.enrolleesuppliessubject = self.properties.certificatenameflag.contains("ENROLLEE_SUPPLIES_SUBJECT");
-                        self.properties.subjectaltrequireupn = self.properties.certificatenameflag.contains("SUBJECT_ALT_REQUIRE_UPN");
+                        // Exact names, ENROLLEE_SUPPLIES_SUBJECT_ALT_NAME is not ENROLLEE_SUPPLIES_SUBJECT (ESC1)
+                        let name_flag = |flag| has_flag(&self.properties.certificatenameflag, flag);
+                        self.properties.enrolleesuppliessubject = name_flag("ENROLLEE_SUPPLIES_SUBJECT");
+                        self.properties.subjectaltrequireupn = name_flag("SUBJECT_ALT_REQUIRE_UPN");
+                        self.properties.subjectaltrequiredns = name_flag("SUBJECT_ALT_REQUIRE_DNS");
+                        self.properties.subjectaltrequiredomaindns = name_flag("SUBJECT_ALT_REQUIRE_DOMAIN_DNS");
+                        self.properties.subjectaltrequireemail = name_flag("SUBJECT_ALT_REQUIRE_EMAIL");
+                        self.properties.subjectaltrequirespn = name_flag("SUBJECT_ALT_REQUIRE_SPN");
+                        self.properties.subjectrequireemail = name_flag("SUBJECT_REQUIRE_EMAIL");
                     }
                 }
                 "msPKI-Enrollment-Flag" => {
                     if !value.is_empty() {
                         self.properties.enrollmentflag = get_pki_enrollment_flags(value[0].parse::<i64>().unwrap_or(0) as u64);
-                        self.properties.requiresmanagerapproval = self.properties.enrollmentflag.contains("PEND_ALL_REQUESTS");
-                        self.properties.nosecurityextension = self.properties.enrollmentflag.contains("NO_SECURITY_EXTENSION");
+                        self.properties.requiresmanagerapproval = has_flag(&self.properties.enrollmentflag, "PEND_ALL_REQUESTS");
+                        self.properties.nosecurityextension = has_flag(&self.properties.enrollmentflag, "NO_SECURITY_EXTENSION");
                     }
                 }
                 "msPKI-Private-Key-Flag" => {
@@ -111,7 +118,7 @@ impl CertTemplate {
                 }
                 "msPKI-RA-Application-Policies" => {
                     if !value.is_empty() {
-                        self.properties.applicationpolicies = value.to_owned();
+                        self.properties.applicationpolicies = parse_ra_application_policies(value);
                     }
                 }
                 "msPKI-Certificate-Application-Policy" => {
@@ -143,7 +150,7 @@ impl CertTemplate {
                         self.properties.whencreated = epoch;
                     }
                 }
-                "IsDeleted" => {
+                "isDeleted" => {
                     self.is_deleted = true;
                 }
                 _ => {}
@@ -168,7 +175,7 @@ impl CertTemplate {
                         &result_bin,
                         domain,
                     );
-                    self.aces = relations_ace;
+                    self.aces = dedup_aces(relations_ace);
                 }
                 "pKIExpirationPeriod" => {
                     self.properties.validityperiod = span_to_string(filetime_to_span(value[0].to_owned())?);
@@ -189,6 +196,7 @@ impl CertTemplate {
 
         // Check if authentication is enabled or not for this template.
         self.properties.authenticationenabled = Self::authentication_is_enabled(self);
+        self.properties.schannelauthenticationenabled = Self::schannel_authentication_is_enabled(self);
 
         // Push DN and SID in HashMap
         if self.object_identifier != "SID" {
@@ -233,6 +241,41 @@ impl CertTemplate {
             .any(|eku| authentication_oids.contains(&eku.as_str()))
             || self.properties.effectiveekus.is_empty()
     }
+
+    /// Function to check if the certificates authenticate over Schannel (LDAPS client certificate).
+    fn schannel_authentication_is_enabled(&mut self) -> bool {
+        let schannel_oids = [
+            "1.3.6.1.5.5.7.3.2", // ClientAuthentication
+            "2.5.29.37.0", // AnyPurpose
+        ];
+        self.properties.effectiveekus.iter()
+            .any(|eku| schannel_oids.contains(&eku.as_str()))
+            || self.properties.effectiveekus.is_empty()
+    }
+}
+
+/// Function to check a flag name in the names of `get_pki_cert_name_flags` or `get_pki_enrollment_flags`.
+fn has_flag(flags: &str, flag: &str) -> bool {
+    flags.split(", ").any(|name| name == flag)
+}
+
+/// Function to get the application policies required in the enrollment agent signatures (ESC3).
+/// Schema version 4 templates store them with the key algorithm as `name`type`value` triples:
+/// `msPKI-RA-Application-Policies`PZPWSTR`1.3.6.1.4.1.311.20.2.1`msPKI-Asymmetric-Algorithm`PZPWSTR`RSA`...
+fn parse_ra_application_policies(values: &[String]) -> Vec<String> {
+    values.iter()
+        .flat_map(|value| {
+            if !value.contains('`') {
+                return vec![value.to_owned()]
+            }
+            value.split('`')
+                .collect::<Vec<&str>>()
+                .chunks(3)
+                .filter(|triple| triple.len() == 3 && triple[0] == "msPKI-RA-Application-Policies")
+                .map(|triple| triple[2].to_owned())
+                .collect()
+        })
+        .collect()
 }
 
 impl LdapObject for CertTemplate {
@@ -328,6 +371,11 @@ pub struct CertTemplateProperties {
    certificatenameflag: String,
    enrolleesuppliessubject: bool,
    subjectaltrequireupn: bool,
+   subjectaltrequiredns: bool,
+   subjectaltrequiredomaindns: bool,
+   subjectaltrequireemail: bool,
+   subjectaltrequirespn: bool,
+   subjectrequireemail: bool,
    ekus: Vec<String>,
    certificateapplicationpolicy: Vec<String>,
    authorizedsignatures: i64,
@@ -335,6 +383,7 @@ pub struct CertTemplateProperties {
    issuancepolicies: Vec<String>,
    effectiveekus: Vec<String>,
    authenticationenabled: bool,
+   schannelauthenticationenabled: bool,
 }
 
 impl Default for CertTemplateProperties {
@@ -358,6 +407,11 @@ impl Default for CertTemplateProperties {
             certificatenameflag: String::from(""),
             enrolleesuppliessubject: false,
             subjectaltrequireupn: true,
+            subjectaltrequiredns: false,
+            subjectaltrequiredomaindns: false,
+            subjectaltrequireemail: false,
+            subjectaltrequirespn: false,
+            subjectrequireemail: false,
             ekus: Vec::new(),
             certificateapplicationpolicy: Vec::new(),
             authorizedsignatures: 0,
@@ -365,6 +419,7 @@ impl Default for CertTemplateProperties {
             issuancepolicies: Vec::new(),
             effectiveekus: Vec::new(),
             authenticationenabled: false,
+            schannelauthenticationenabled: false,
        }
     }
  }
@@ -374,4 +429,107 @@ impl CertTemplateProperties {
     pub fn name(&self) -> &String {
         &self.name
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use ldap3::SearchEntry;
+
+    use crate::objects::certtemplate::CertTemplate;
+    use crate::objects::common::LdapObject;
+
+    fn template(name: &str, attrs: &[(&str, &[&str])]) -> CertTemplate {
+        let mut entry_attrs: HashMap<String, Vec<String>> = HashMap::from([
+            ("name".to_string(), vec![name.to_string()]),
+            ("displayName".to_string(), vec![name.to_string()]),
+        ]);
+        for (key, values) in attrs {
+            entry_attrs.insert(key.to_string(), values.iter().map(|value| value.to_string()).collect());
+        }
+        let entry = SearchEntry {
+            dn: format!("CN={name},CN=Certificate Templates,CN=Public Key Services,CN=Services,CN=Configuration,DC=CONTOSO,DC=LOCAL"),
+            attrs: entry_attrs,
+            bin_attrs: HashMap::from([
+                ("objectGUID".to_string(), vec![vec![0x3E, 0x4D, 0x5C, 0x6B, 0x7A, 0x89, 0x98, 0x47, 0xA6, 0xB5, 0xC4, 0xD3, 0xE2, 0xF1, 0x00, 0x1F]]),
+            ]),
+        };
+        let mut template = CertTemplate::new();
+        template.parse(entry, "contoso.local", &mut HashMap::new(), &mut HashMap::new(), "S-1-5-21-1-2-3").unwrap();
+        template
+    }
+
+    #[test]
+    pub fn test_certtemplate_esc1() {
+        // Enrollee supplies subject, client authentication, no approval, no signature
+        let json = template("ESC1", &[
+            ("msPKI-Certificate-Name-Flag", &["1"]),
+            ("msPKI-Enrollment-Flag", &["0"]),
+            ("msPKI-RA-Signature", &["0"]),
+            ("msPKI-Template-Schema-Version", &["2"]),
+            ("pKIExtendedKeyUsage", &["1.3.6.1.5.5.7.3.2"]),
+            ("msPKI-Certificate-Application-Policy", &["1.3.6.1.5.5.7.3.2"]),
+        ]).to_json();
+        let properties = &json["Properties"];
+        assert_eq!(properties["name"], "ESC1@CONTOSO.LOCAL");
+        assert_eq!(properties["enrolleesuppliessubject"], true);
+        assert_eq!(properties["requiresmanagerapproval"], false);
+        assert_eq!(properties["authorizedsignatures"], 0);
+        assert_eq!(properties["effectiveekus"], serde_json::json!(["1.3.6.1.5.5.7.3.2"]));
+        assert_eq!(properties["authenticationenabled"], true);
+        assert_eq!(properties["schannelauthenticationenabled"], true);
+
+        // Alternative name only: not ESC1, manager approval required
+        let json = template("Machine", &[
+            ("msPKI-Certificate-Name-Flag", &["65536"]),
+            ("msPKI-Enrollment-Flag", &["2"]),
+            ("pKIExtendedKeyUsage", &["1.3.6.1.5.5.7.3.2"]),
+        ]).to_json();
+        assert_eq!(json["Properties"]["enrolleesuppliessubject"], false);
+        assert_eq!(json["Properties"]["certificatenameflag"], "ENROLLEE_SUPPLIES_SUBJECT_ALT_NAME");
+        assert_eq!(json["Properties"]["requiresmanagerapproval"], true);
+
+        // Default User template: names from the directory (-1509949440 = 0xA6000000)
+        let json = template("User", &[("msPKI-Certificate-Name-Flag", &["-1509949440"])]).to_json();
+        assert_eq!(json["Properties"]["subjectaltrequireupn"], true);
+        assert_eq!(json["Properties"]["subjectaltrequireemail"], true);
+        assert_eq!(json["Properties"]["subjectrequireemail"], true);
+        assert_eq!(json["Properties"]["subjectaltrequiredns"], false);
+        assert_eq!(json["Properties"]["enrolleesuppliessubject"], false);
+    }
+
+    #[test]
+    pub fn test_certtemplate_esc2_esc3() {
+        // ESC2: Any Purpose, or no EKU at all
+        let json = template("AnyPurpose", &[
+            ("msPKI-Template-Schema-Version", &["1"]),
+            ("pKIExtendedKeyUsage", &["2.5.29.37.0"]),
+        ]).to_json();
+        assert_eq!(json["Properties"]["ekus"], serde_json::json!(["2.5.29.37.0"]));
+        assert_eq!(json["Properties"]["authenticationenabled"], true);
+        let json = template("SubCA", &[("msPKI-Template-Schema-Version", &["1"])]).to_json();
+        assert_eq!(json["Properties"]["effectiveekus"], serde_json::json!([]));
+        assert_eq!(json["Properties"]["authenticationenabled"], true);
+
+        // ESC3: Certificate Request Agent, the templates it signs require one agent signature
+        let json = template("EnrollmentAgent", &[
+            ("msPKI-Template-Schema-Version", &["2"]),
+            ("msPKI-Certificate-Application-Policy", &["1.3.6.1.4.1.311.20.2.1"]),
+        ]).to_json();
+        assert_eq!(json["Properties"]["effectiveekus"], serde_json::json!(["1.3.6.1.4.1.311.20.2.1"]));
+        assert_eq!(json["Properties"]["authenticationenabled"], false);
+        let json = template("SignedUser", &[
+            ("msPKI-Template-Schema-Version", &["4"]),
+            ("msPKI-RA-Signature", &["1"]),
+            ("msPKI-RA-Application-Policies", &["msPKI-RA-Application-Policies`PZPWSTR`1.3.6.1.4.1.311.20.2.1`msPKI-Asymmetric-Algorithm`PZPWSTR`RSA`msPKI-Key-Usage`DWORD`16777215`"]),
+            ("msPKI-Certificate-Application-Policy", &["1.3.6.1.5.5.7.3.2", "1.3.6.1.4.1.311.20.2.2"]),
+        ]).to_json();
+        assert_eq!(json["Properties"]["authorizedsignatures"], 1);
+        assert_eq!(json["Properties"]["applicationpolicies"], serde_json::json!(["1.3.6.1.4.1.311.20.2.1"]));
+        let json = template("SignedUserV2", &[
+            ("msPKI-RA-Signature", &["1"]),
+            ("msPKI-RA-Application-Policies", &["1.3.6.1.4.1.311.20.2.1"]),
+        ]).to_json();
+        assert_eq!(json["Properties"]["applicationpolicies"], serde_json::json!(["1.3.6.1.4.1.311.20.2.1"]));
+    }
+}
@@ -1,9 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use ldap3::SearchEntry;
-use log::trace;
+use log::{trace, warn};
 use serde_json::{json,value::Value};
 use serde::{Deserialize, Serialize};
+use crate::buildinfo::BuildInfo;
+use crate::rootdse::RootDse;
 
 
 /// LdapObject structure
@@ -36,6 +38,10 @@ pub trait LdapObject {
    fn set_links(&mut self, links: Vec<Link>);
    fn set_contained_by(&mut self, contained_by: Option<Member>);
    fn set_child_objects(&mut self, child_objects: Vec<Member>);
+   /// Number of ACEs not matching the AD default security descriptors, only on the main AD objects.
+   fn set_ace_deviation(&mut self, _count: u32) {}
+   /// Extra properties from the `--config` custom_properties, only on the main AD objects.
+   fn set_custom_properties(&mut self, _properties: serde_json::Map<String, serde_json::Value>) {}
 }
 
 /// LocalGroup structure
@@ -332,10 +338,18 @@ pub struct AceTemplate {
    principal_type: String,
    #[serde(rename = "RightName")]
    right_name: String,
-   #[serde(rename = "IsInherited")]
+   /// INHERITED_ACE flag of the ACE, false for the ACEs written without it.
+   #[serde(rename = "IsInherited", default)]
    is_inherited: bool,
    #[serde(rename = "InheritanceHash")]
    inheritance_hash: String,
+   #[serde(rename = "isdefault", default, skip_serializing_if = "Option::is_none")]
+   is_default: Option<bool>,
+   /// ACE type and object type GUID of the ACE making the edge, to find the duplicate ACEs. Not written.
+   #[serde(skip)]
+   ace_type: Option<u8>,
+   #[serde(skip)]
+   ace_object_type: Option<u128>,
 }
 
 impl AceTemplate {
@@ -347,7 +361,22 @@ impl AceTemplate {
       is_inherited: bool,
       inheritance_hash: String,
    ) -> Self { 
-      Self { principal_sid, principal_type , right_name, is_inherited, inheritance_hash} 
+      Self {
+         principal_sid,
+         principal_type,
+         right_name,
+         is_inherited,
+         inheritance_hash,
+         is_default: None,
+         ace_type: None,
+         ace_object_type: None,
+      }
+   }
+
+   /// Set the ACE this edge comes from.
+   pub fn set_ace_source(&mut self, ace_type: u8, object_type: Option<u128>) {
+      self.ace_type = Some(ace_type);
+      self.ace_object_type = object_type;
    }
 
    // Immutable access.
@@ -366,6 +395,15 @@ impl AceTemplate {
    pub fn inheritance_hash(&self) -> &String {
       &self.inheritance_hash
    }
+   pub fn is_default(&self) -> &Option<bool> {
+      &self.is_default
+   }
+   pub fn ace_type(&self) -> &Option<u8> {
+      &self.ace_type
+   }
+   pub fn ace_object_type(&self) -> &Option<u128> {
+      &self.ace_object_type
+   }
 
    // Mutable access.
    pub fn principal_sid_mut(&mut self) -> &mut String {
@@ -383,6 +421,9 @@ impl AceTemplate {
    pub fn inheritance_hash_mut(&mut self) -> &mut String {
       &mut self.inheritance_hash
    }
+   pub fn is_default_mut(&mut self) -> &mut Option<bool> {
+      &mut self.is_default
+   }
 }
 
 /// Link structure
@@ -415,7 +456,9 @@ impl Link {
    }
 }
 
-/// GPOChange structure
+/// GPOChange structure, `GPOChanges` of the OUs and domains.
+/// AffectedComputers are the computers of the OU subtree or of the domain. The local groups need the
+/// GPO files on SYSVOL, not collected: they are always written as empty arrays.
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct GPOChange {
    #[serde(rename = "LocalAdmins")]
@@ -557,7 +600,25 @@ pub struct Meta {
    mtype: String,
    count: i32,
    version: i8,
-   collectorversion: String
+   collectorversion: String,
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   build: Option<BuildInfo>,
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   partial: Option<bool>,
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   phase: Option<String>,
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   acefilter: Option<String>,
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   warnings: Option<Vec<String>>,
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   skipped: Option<Vec<String>>,
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   domaincontrollers: Option<Vec<String>>,
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   authmechanism: Option<String>,
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   rootdse: Option<RootDse>,
 }
 
 impl Meta {
@@ -574,9 +635,59 @@ impl Meta {
          mtype,
          count,
          version,
-         collectorversion
+         collectorversion,
+         build: None,
+         partial: None,
+         phase: None,
+         acefilter: None,
+         warnings: None,
+         skipped: None,
+         domaincontrollers: None,
+         authmechanism: None,
+         rootdse: None,
       } 
    }
+
+   /// Record the build that produced the output.
+   pub fn set_build(&mut self, build: BuildInfo) {
+      self.build = Some(build);
+   }
+
+   /// Mark the output as partial, with the phase reached when the run was interrupted.
+   pub fn set_partial(&mut self, phase: &str) {
+      self.partial = Some(true);
+      self.phase = Some(phase.to_owned());
+   }
+
+   /// Record the `--ace-filter` applied to the ACEs.
+   pub fn set_ace_filter(&mut self, filter: &str) {
+      self.acefilter = Some(filter.to_owned());
+   }
+
+   /// Record the warnings about the collection completeness (incomplete configuration NC...).
+   pub fn set_warnings(&mut self, warnings: &[String]) {
+      self.warnings = Some(warnings.to_vec());
+   }
+
+   /// Record the collection categories skipped or cut by `--deadline`.
+   pub fn set_skipped(&mut self, skipped: &[String]) {
+      self.skipped = Some(skipped.to_vec());
+   }
+
+   /// Record the domain controllers used for the collection, several when `--dc` fallbacks were needed.
+   pub fn set_domain_controllers(&mut self, domain_controllers: &[String]) {
+      self.domaincontrollers = Some(domain_controllers.to_vec());
+   }
+
+   /// Record the authentication of the LDAP bind, the password bind when the Kerberos one failed.
+   pub fn set_auth_mechanism(&mut self, auth_mechanism: &str) {
+      self.authmechanism = Some(auth_mechanism.to_owned());
+   }
+
+   /// Record the DC, the naming contexts and the functional levels of the rootDSE.
+   pub fn set_root_dse(&mut self, root_dse: &RootDse) {
+      self.rootdse = Some(root_dse.to_owned());
+   }
    
    // Imutable access.
    pub fn methods(&self) -> &i32 {
@@ -591,6 +702,33 @@ impl Meta {
    pub fn version(&self) -> &i8 {
       &self.version
    }
+   pub fn build(&self) -> &Option<BuildInfo> {
+      &self.build
+   }
+   pub fn partial(&self) -> &Option<bool> {
+      &self.partial
+   }
+   pub fn phase(&self) -> &Option<String> {
+      &self.phase
+   }
+   pub fn acefilter(&self) -> &Option<String> {
+      &self.acefilter
+   }
+   pub fn warnings(&self) -> &Option<Vec<String>> {
+      &self.warnings
+   }
+   pub fn skipped(&self) -> &Option<Vec<String>> {
+      &self.skipped
+   }
+   pub fn domaincontrollers(&self) -> &Option<Vec<String>> {
+      &self.domaincontrollers
+   }
+   pub fn authmechanism(&self) -> &Option<String> {
+      &self.authmechanism
+   }
+   pub fn rootdse(&self) -> &Option<RootDse> {
+      &self.rootdse
+   }
 
    // Mutable access.
    pub fn methods_mut(&mut self) -> &mut i32 {
@@ -607,6 +745,168 @@ impl Meta {
    }
 }
 
+/// HostResolver structure
+/// Index used to find a computer SID from a hostname, a FQDN or a SPN host part.
+#[derive(Debug, Clone, Default)]
+pub struct HostResolver {
+   fqdn_sid: HashMap<String, String>,
+   shortname_sid: HashMap<String, String>,
+   domains: HashSet<String>,
+}
+
+impl HostResolver {
+   // New HostResolver.
+   pub fn new() -> Self {
+      Self {
+         ..Default::default()
+      }
+   }
+
+   /// Register every host names known for one computer.
+   pub fn add_computer(
+      &mut self,
+      sid: &str,
+      dnshostname: Option<&str>,
+      samaccountname: Option<&str>,
+      additional_dnshostnames: &[String],
+      serviceprincipalnames: &[String],
+   ) {
+      if let Some(dnshostname) = dnshostname {
+         self.insert(dnshostname, sid);
+         if let Some((_, domain)) = dnshostname.split_once('.') {
+            self.add_domain(domain);
+         }
+      }
+      if let Some(samaccountname) = samaccountname {
+         self.insert(samaccountname.trim_end_matches('$'), sid);
+      }
+      for hostname in additional_dnshostnames {
+         self.insert(hostname, sid);
+      }
+      for spn in serviceprincipalnames {
+         if let Some(host) = spn_host_part(spn) {
+            self.insert(host, sid);
+         }
+      }
+   }
+
+   /// Register one host name (short name or FQDN) for a computer SID.
+   pub fn insert(&mut self, host: &str, sid: &str) {
+      let host = host.trim().trim_end_matches('.').to_uppercase();
+      if host.is_empty() || sid.is_empty() {
+         return
+      }
+      if host.contains('.') {
+         let short = host.split('.').next().unwrap_or_default().to_string();
+         Self::insert_into(&mut self.fqdn_sid, host, sid);
+         Self::insert_into(&mut self.shortname_sid, short, sid);
+      } else {
+         Self::insert_into(&mut self.shortname_sid, host, sid);
+      }
+   }
+
+   /// Register a domain of the collected computers, its FQDNs can be resolved from their short name.
+   pub fn add_domain(&mut self, domain: &str) {
+      let domain = domain.trim().trim_end_matches('.').to_uppercase();
+      if !domain.is_empty() {
+         self.domains.insert(domain);
+      }
+   }
+
+   /// Add the host names registered in another resolver, like the ones of the computers parsed on another thread.
+   pub fn merge(&mut self, other: HostResolver) {
+      for (host, sid) in other.fqdn_sid {
+         Self::insert_into(&mut self.fqdn_sid, host, &sid);
+      }
+      for (host, sid) in other.shortname_sid {
+         Self::insert_into(&mut self.shortname_sid, host, &sid);
+      }
+      self.domains.extend(other.domains);
+   }
+
+   /// Duplicate registrations keep the lowest SID so the result doesn't depend on the LDAP order.
+   fn insert_into(map: &mut HashMap<String, String>, host: String, sid: &str) {
+      match map.get(&host) {
+         Some(current) if current == sid => {}
+         Some(current) => {
+            warn!("Host {host} is registered by {current} and {sid}");
+            if sid < current.as_str() {
+               map.insert(host, sid.to_string());
+            }
+         }
+         None => {
+            map.insert(host, sid.to_string());
+         }
+      }
+   }
+
+   /// Find the computer SID for a hostname, a FQDN or a SPN ("service/host:port").
+   /// The FQDN is checked first, then the short name when the host has no domain or a collected one:
+   /// WEB01.PARTNER.COM is not the WEB01 computer of this domain.
+   pub fn resolve(&self, host: &str) -> Option<&String> {
+      let host = spn_host_part(host).unwrap_or(host).trim().trim_end_matches('.').to_uppercase();
+      if let Some(sid) = self.fqdn_sid.get(&host) {
+         return Some(sid)
+      }
+      match host.split_once('.') {
+         None => self.shortname_sid.get(&host),
+         Some((short, domain)) if self.domains.contains(domain) => self.shortname_sid.get(short),
+         Some(_) => None,
+      }
+   }
+
+   pub fn is_empty(&self) -> bool {
+      self.fqdn_sid.is_empty() && self.shortname_sid.is_empty()
+   }
+}
+
+/// Get the host part from a SPN value "service/host:port/name" or None if it is not a SPN.
+pub fn spn_host_part(spn: &str) -> Option<&str> {
+   let host = spn.split('/').nth(1)?;
+   host.split(':').next()
+}
+
+/// Clean one raw msDS-AdditionalDnsHostName value, None if nothing is left.
+/// The NetBIOS entries are `$`-suffixed and can be padded or interleaved (UTF-16) with null bytes,
+/// a plain UTF-8 conversion keeps the nulls inside the name.
+pub fn clean_additional_hostname(raw: &[u8]) -> Option<String> {
+   let bytes: Vec<u8> = raw.iter().copied().filter(|byte| *byte != 0).collect();
+   let host = String::from_utf8_lossy(&bytes);
+   let host = host.trim().trim_end_matches('$').trim_end_matches('.');
+   let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_');
+   if host.is_empty() || !host.chars().all(valid) {
+      return None
+   }
+   Some(host.to_uppercase())
+}
+
+/// Function to get the other host names of a computer: its additional DNS host names and the hosts of its SPNs,
+/// without its own name, sorted.
+pub fn additional_hostnames(
+   name: &str,
+   samaccountname: &str,
+   additional_dnshostnames: &[String],
+   serviceprincipalnames: &[String],
+) -> Vec<String> {
+   let name = name.to_uppercase();
+   let own = [
+      name.to_owned(),
+      name.split('.').next().unwrap_or_default().to_string(),
+      samaccountname.trim_end_matches('$').to_uppercase(),
+   ];
+   let spn_hosts = serviceprincipalnames.iter().filter_map(|spn| spn_host_part(spn));
+   let mut hosts: Vec<String> = additional_dnshostnames.iter().map(String::as_str)
+      .chain(spn_hosts)
+      .filter_map(|host| clean_additional_hostname(host.as_bytes()))
+      // DRS replication SPNs have the DSA GUID as host
+      .filter(|host| !(host.len() == 36 && host.matches('-').count() == 4))
+      .filter(|host| !own.contains(host))
+      .collect();
+   hosts.sort();
+   hosts.dedup();
+   hosts
+}
+
 
 /// Function to parse and replace value for unknown object.
 pub fn parse_unknown(result: SearchEntry, _domain: &str) -> serde_json::value::Value  {
@@ -630,4 +930,120 @@ pub fn parse_unknown(result: SearchEntry, _domain: &str) -> serde_json::value::V
    // }
 
    unknown_json
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+
+   use crate::objects::common::{additional_hostnames, clean_additional_hostname, GPOChange, HostResolver, LdapObject, Member};
+   use crate::objects::{domain::Domain, ou::Ou};
+
+   #[test]
+   pub fn test_host_resolver() {
+      let mut resolver = HostResolver::new();
+      resolver.add_computer(
+         "S-1-5-21-1-2-3-1000",
+         Some("srv01.contoso.local"),
+         Some("SRV01$"),
+         &["web.contoso.local".to_string()],
+         &["MSSQLSvc/sql01.contoso.local:1433".to_string(), "HOST/SRV01".to_string()],
+      );
+
+      let sid = Some(&"S-1-5-21-1-2-3-1000".to_string());
+      assert_eq!(resolver.resolve("SRV01.CONTOSO.LOCAL"), sid);
+      assert_eq!(resolver.resolve("srv01"), sid);
+      assert_eq!(resolver.resolve("srv01.contoso.local."), sid);
+      assert_eq!(resolver.resolve("WEB.contoso.local"), sid);
+      assert_eq!(resolver.resolve("cifs/sql01.contoso.local"), sid);
+      assert_eq!(resolver.resolve("sql01"), sid);
+      assert_eq!(resolver.resolve("unknown.contoso.local"), None);
+   }
+
+   #[test]
+   pub fn test_gpo_changes_schema() {
+      // Same casing and order as SharpHound CE, the arrays are always written
+      let empty = r#"{"LocalAdmins":[],"RemoteDesktopUsers":[],"DcomUsers":[],"PSRemoteUsers":[],"AffectedComputers":[]}"#;
+      assert_eq!(Ou::new().to_json()["GPOChanges"].to_string(), empty);
+      assert_eq!(Domain::new().to_json()["GPOChanges"].to_string(), empty);
+
+      let mut computer = Member::new();
+      *computer.object_identifier_mut() = "S-1-5-21-1-2-3-1000".to_string();
+      *computer.object_type_mut() = "Computer".to_string();
+      let mut gpo_changes = GPOChange::new();
+      gpo_changes.affected_computers_mut().push(computer.to_owned());
+      gpo_changes.local_admins_mut().push(computer);
+      let mut ou = Ou::new();
+      *ou.gpo_changes_mut() = gpo_changes;
+      assert_eq!(
+         ou.to_json()["GPOChanges"].to_string(),
+         concat!(
+            r#"{"LocalAdmins":[{"ObjectIdentifier":"S-1-5-21-1-2-3-1000","ObjectType":"Computer"}],"#,
+            r#""RemoteDesktopUsers":[],"DcomUsers":[],"PSRemoteUsers":[],"#,
+            r#""AffectedComputers":[{"ObjectIdentifier":"S-1-5-21-1-2-3-1000","ObjectType":"Computer"}]}"#,
+         )
+      );
+   }
+
+   #[test]
+   pub fn test_host_resolver_duplicates() {
+      let mut resolver = HostResolver::new();
+      resolver.insert("srv02.contoso.local", "S-1-5-21-1-2-3-2001");
+      resolver.insert("SRV02.contoso.local", "S-1-5-21-1-2-3-2000");
+      resolver.insert("srv02.contoso.local", "S-1-5-21-1-2-3-2002");
+      assert_eq!(resolver.resolve("srv02.contoso.local"), Some(&"S-1-5-21-1-2-3-2000".to_string()));
+      assert_eq!(resolver.resolve("srv02"), Some(&"S-1-5-21-1-2-3-2000".to_string()));
+   }
+
+   #[test]
+   pub fn test_host_resolver_foreign_fqdn() {
+      let mut resolver = HostResolver::new();
+      resolver.add_computer("S-1-5-21-1-2-3-1000", Some("web01.contoso.local"), Some("WEB01$"), &[], &[]);
+      resolver.add_computer("S-1-5-21-1-2-3-1001", None, Some("SRV01$"), &[], &[]);
+      resolver.add_domain("corp.contoso.local");
+
+      // Same short name in a trusted domain
+      assert_eq!(resolver.resolve("WEB01.PARTNER.COM"), None);
+      assert_eq!(resolver.resolve("cifs/web01.partner.com"), None);
+      assert_eq!(resolver.resolve("web01.contoso.local"), Some(&"S-1-5-21-1-2-3-1000".to_string()));
+      // Short name only known, FQDN of a collected domain
+      assert_eq!(resolver.resolve("srv01.contoso.local"), Some(&"S-1-5-21-1-2-3-1001".to_string()));
+      assert_eq!(resolver.resolve("srv01.corp.contoso.local"), Some(&"S-1-5-21-1-2-3-1001".to_string()));
+      assert_eq!(resolver.resolve("srv01.partner.com"), None);
+   }
+
+   #[test]
+   pub fn test_clean_additional_hostname() {
+      assert_eq!(clean_additional_hostname(b"sqlag.contoso.local"), Some("SQLAG.CONTOSO.LOCAL".to_string()));
+      // NetBIOS entry padded with nulls
+      assert_eq!(clean_additional_hostname(b"SQLCLUSTER$\0\0\0"), Some("SQLCLUSTER".to_string()));
+      // UTF-16LE NetBIOS entry
+      let utf16: Vec<u8> = "SQLAG$".encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+      assert_eq!(clean_additional_hostname(&utf16), Some("SQLAG".to_string()));
+      assert_eq!(clean_additional_hostname(b"sql\0ag.contoso.local.\0"), Some("SQLAG.CONTOSO.LOCAL".to_string()));
+      assert_eq!(clean_additional_hostname(b"\0\0"), None);
+      assert_eq!(clean_additional_hostname(b"$"), None);
+      assert_eq!(clean_additional_hostname(&[0xff, 0xfe, b'A']), None);
+      assert_eq!(clean_additional_hostname(b"bad name"), None);
+   }
+
+   #[test]
+   pub fn test_additional_hostnames() {
+      let hosts = additional_hostnames(
+         "NODE01.CONTOSO.LOCAL",
+         "NODE01$",
+         &["sqlag.contoso.local".to_string(), "SQLAG$\0".to_string(), "node01.contoso.local".to_string()],
+         &[
+            "MSSQLSvc/sqlag.contoso.local:1433".to_string(),
+            "HOST/NODE01".to_string(),
+            "MSServerClusterMgmtAPI/CLUSTER01.contoso.local".to_string(),
+            "E3514235-4B06-11D1-AB04-00C04FC2DCD2/5b7a3c9e-2f04-4d5b-9d4a-8a6f0e3c1b2d/contoso.local".to_string(),
+         ],
+      );
+      assert_eq!(hosts, vec!["CLUSTER01.CONTOSO.LOCAL", "SQLAG", "SQLAG.CONTOSO.LOCAL"]);
+
+      // Aliases resolve to the computer, so delegation targets and CA hosts match them
+      let mut resolver = HostResolver::new();
+      resolver.add_computer("S-1-5-21-1-2-3-1100", Some("NODE01.CONTOSO.LOCAL"), Some("NODE01$"), &hosts, &[]);
+      assert_eq!(resolver.resolve("cifs/sqlag.contoso.local"), Some(&"S-1-5-21-1-2-3-1100".to_string()));
+      assert_eq!(resolver.resolve("CLUSTER01"), Some(&"S-1-5-21-1-2-3-1100".to_string()));
+   }
+}
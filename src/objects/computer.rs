@@ -4,16 +4,17 @@ use colored::Colorize;
 use ldap3::SearchEntry;
 use log::{info, debug, trace};
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::RustHoundError;
 
 use crate::enums::{OBJECT_SID_RE1, SID_PART1_RE1};
-use crate::objects::common::{LdapObject, Session, AceTemplate, Member, SPNTarget, LocalGroup, Link, DCRegistryData};
+use crate::objects::common::{LdapObject, Session, AceTemplate, Member, SPNTarget, LocalGroup, Link, DCRegistryData, HostResolver, additional_hostnames, clean_additional_hostname};
 use crate::utils::date::{convert_timestamp,string_to_epoch};
 use crate::utils::crypto::convert_encryption_types;
-use crate::enums::acl::parse_ntsecuritydescriptor;
+use crate::enums::acl::{dedup_aces, parse_ntsecuritydescriptor};
 use crate::enums::secdesc::LdapSid;
 use crate::enums::sid::sid_maker;
 use crate::enums::uacflags::get_flag;
+use crate::json::checker::honeypot::HoneypotFacts;
 
 use super::common::UserRight;
 
@@ -84,11 +85,20 @@ impl Computer {
     pub fn allowed_to_act(&self) -> &Vec<Member> {
         &self.allowed_to_act
     }
+    pub fn unconstrained_delegation(&self) -> &bool {
+        &self.unconstrained_delegation
+    }
 
     // Mutable access.
+    pub fn properties_mut(&mut self) -> &mut ComputerProperties {
+        &mut self.properties
+    }
     pub fn allowed_to_act_mut(&mut self) -> &mut Vec<Member> {
         &mut self.allowed_to_act
     }
+    pub fn unconstrained_delegation_mut(&mut self) -> &mut bool {
+        &mut self.unconstrained_delegation
+    }
 
     /// Function to parse and replace value for computer object.
     /// <https://bloodhound.readthedocs.io/en/latest/further-reading/json.html#computers>
@@ -98,10 +108,10 @@ impl Computer {
         domain: &str,
         dn_sid: &mut HashMap<String, String>,
         sid_type: &mut HashMap<String, String>,
-        fqdn_sid: &mut HashMap<String, String>,
+        host_resolver: &mut HostResolver,
         fqdn_ip: &mut HashMap<String, String>,
         domain_sid: &str
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), RustHoundError> {
         let result_dn: String = result.dn.to_uppercase();
         let result_attrs: HashMap<String, Vec<String>> = result.attrs;
         let result_bin: HashMap<String, Vec<Vec<u8>>> = result.bin_attrs;
@@ -129,6 +139,7 @@ impl Computer {
 
         let mut sid: String = "".to_owned();
         let mut group_id: String = "".to_owned();
+        let mut additional_dnshostnames: Vec<String> = Vec::new();
         // With a check
         for (key, value) in &result_attrs {
             match key.as_str() {
@@ -142,6 +153,7 @@ impl Computer {
                 }
                 "dNSHostName" => {
                     self.properties.name = value[0].to_uppercase();
+                    self.properties.dnshostname = Some(value[0].to_uppercase());
                 }
                 "description" => {
                     self.properties.description = Some(value[0].to_owned());
@@ -203,7 +215,7 @@ impl Computer {
                 }
                 "userAccountControl" => {
                     //userAccountControl
-                    let uac = &value[0].parse::<u32>().unwrap();
+                    let uac = &value[0].parse::<u32>().unwrap_or(0);
                     let uac_flags = get_flag(*uac);
                     //trace!("UAC : {:?}",uac_flags);
                     for flag in uac_flags {
@@ -293,12 +305,15 @@ impl Computer {
                 "primaryGroupID" => {
                     group_id = value[0].to_owned();
                 }
-                "IsDeleted" => {
+                "isDeleted" => {
                     self.is_deleted = true;
                 }
                 "msDS-SupportedEncryptionTypes" => {
                     self.properties.supportedencryptiontypes = convert_encryption_types(value[0].parse::<i32>().unwrap_or(0));
                  }
+                "msDS-AdditionalDnsHostName" => {
+                    additional_dnshostnames.extend(value.to_owned());
+                }
                 _ => {}
             }
         }
@@ -325,7 +340,11 @@ impl Computer {
                         &result_bin,
                         domain,
                     );
-                    self.aces = relations_ace;
+                    self.aces = dedup_aces(relations_ace);
+                }
+                "msDS-AdditionalDnsHostName" => {
+                    // Values with invalid UTF-8 are binary
+                    additional_dnshostnames.extend(value.iter().filter_map(|raw| clean_additional_hostname(raw)));
                 }
                 "msDS-AllowedToActOnBehalfOfOtherIdentity" => {
                     // RBCD (Resource-based constrained)
@@ -377,15 +396,31 @@ impl Computer {
             "Computer".to_string(),
         );
 
-        fqdn_sid.insert(
-            self.properties.name.to_string(),
-            self.object_identifier.to_string(),
+        // Other host names: cluster names, availability group listeners...
+        self.properties.additionalhostnames = additional_hostnames(
+            &self.properties.name,
+            &self.properties.samaccountname,
+            &additional_dnshostnames,
+            &self.properties.serviceprincipalnames,
+        );
+
+        // Push all host names in HostResolver
+        host_resolver.add_computer(
+            &self.object_identifier,
+            Some(&self.properties.name),
+            Some(&self.properties.samaccountname),
+            &self.properties.additionalhostnames,
+            &[],
         );
 
         fqdn_ip.insert(
             self.properties.name.to_string(),
             String::from(""),
         );
+        // Aliases FQDN resolved with the fqdn-resolver module too
+        for hostname in self.properties.additionalhostnames.iter().filter(|hostname| hostname.contains('.')) {
+            fqdn_ip.entry(hostname.to_owned()).or_default();
+        }
 
         // Trace and return Computer struct
         // trace!("JSON OUTPUT: {:?}",serde_json::to_string(&self).unwrap());
@@ -462,6 +497,12 @@ impl LdapObject for Computer {
     fn set_child_objects(&mut self, _child_objects: Vec<Member>) {
         // Not used by current object.
     }
+    fn set_ace_deviation(&mut self, count: u32) {
+        self.properties.acedeviation = Some(count);
+    }
+    fn set_custom_properties(&mut self, properties: serde_json::Map<String, serde_json::Value>) {
+        self.properties.custom.extend(properties);
+    }
 }
 
 // Computer properties structure
@@ -486,15 +527,28 @@ pub struct ComputerProperties {
     passwordnotreqd: bool,
     pwdneverexpires: bool,
     serviceprincipalnames: Vec<String>,
+    additionalhostnames: Vec<String>,
     operatingsystem: String,
     sidhistory: Vec<String>,
     supportedencryptiontypes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    honeypotscore: Option<u32>,
+    #[serde(skip_serializing)]
+    is_dc: bool,
+    /// dNSHostName, or the host name found in the DNS zones with `--dns`.
     #[serde(skip_serializing)]
-    is_dc: bool
+    dnshostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acedeviation: Option<u32>,
+    #[serde(flatten)]
+    custom: serde_json::Map<String, serde_json::Value>,
 }
 
 impl ComputerProperties {  
     // Immutable access.
+    pub fn domain(&self) -> &String {
+        &self.domain
+    }
     pub fn name(&self) -> &String {
         &self.name
     }
@@ -504,7 +558,43 @@ impl ComputerProperties {
     pub fn enabled(&self) -> &bool {
         &self.enabled
     }
+    pub fn additionalhostnames(&self) -> &Vec<String> {
+        &self.additionalhostnames
+    }
     pub fn get_is_dc(&self) -> &bool {
         &self.is_dc
     }
+    pub fn honeypotscore(&self) -> &Option<u32> {
+        &self.honeypotscore
+    }
+    pub fn samaccountname(&self) -> &String {
+        &self.samaccountname
+    }
+    pub fn dnshostname(&self) -> &Option<String> {
+        &self.dnshostname
+    }
+
+    /// Values used by the honeypot analysis.
+    pub fn honeypot_facts(&self) -> HoneypotFacts {
+        HoneypotFacts {
+            whencreated: self.whencreated,
+            lastlogon: self.lastlogon,
+            lastlogontimestamp: self.lastlogontimestamp,
+            pwdlastset: self.pwdlastset,
+            description: self.description.to_owned(),
+            is_computer: true,
+            ..Default::default()
+        }
+    }
+
+    // Mutable access.
+    pub fn honeypotscore_mut(&mut self) -> &mut Option<u32> {
+        &mut self.honeypotscore
+    }
+
+    /// Name the computer after a host name found without its dNSHostName.
+    pub fn set_dnshostname(&mut self, dnshostname: &str) {
+        self.name = dnshostname.to_uppercase();
+        self.dnshostname = Some(self.name.to_owned());
+    }
 }
\ No newline at end of file
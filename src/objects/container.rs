@@ -3,10 +3,10 @@ use serde::{Deserialize, Serialize};
 use ldap3::SearchEntry;
 use log::{debug, trace};
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::RustHoundError;
 
 use crate::objects::common::{LdapObject, AceTemplate, SPNTarget, Link, Member};
-use crate::enums::acl::parse_ntsecuritydescriptor;
+use crate::enums::acl::{dedup_aces, parse_ntsecuritydescriptor};
 use crate::enums::sid::decode_guid_le;
 use crate::utils::date::string_to_epoch;
 
@@ -44,7 +44,7 @@ impl Container {
         dn_sid: &mut HashMap<String, String>,
         sid_type: &mut HashMap<String, String>,
         domain_sid: &str
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), RustHoundError> {
         let result_dn: String = result.dn.to_uppercase();
         let result_attrs: HashMap<String, Vec<String>> = result.attrs;
         let result_bin: HashMap<String, Vec<Vec<u8>>> = result.bin_attrs;
@@ -103,9 +103,9 @@ impl Container {
                         &result_bin,
                         domain,
                     );
-                    self.aces = relations_ace;
+                    self.aces = dedup_aces(relations_ace);
                 }
-                "IsDeleted" => {
+                "isDeleted" => {
                     self.is_deleted = true;
                 }
                 _ => {}
@@ -140,6 +140,10 @@ pub struct ContainerProperties {
    highvalue: bool,
    description: Option<String>,
    whencreated: i64,
+   #[serde(skip_serializing_if = "Option::is_none")]
+   acedeviation: Option<u32>,
+   #[serde(flatten)]
+   custom: serde_json::Map<String, serde_json::Value>,
 }
 
 impl LdapObject for Container {
@@ -211,4 +215,10 @@ impl LdapObject for Container {
     fn set_child_objects(&mut self, child_objects: Vec<Member>) {
         self.child_objects = child_objects
     }
+    fn set_ace_deviation(&mut self, count: u32) {
+        self.properties.acedeviation = Some(count);
+    }
+    fn set_custom_properties(&mut self, properties: serde_json::Map<String, serde_json::Value>) {
+        self.properties.custom.extend(properties);
+    }
 }
\ No newline at end of file
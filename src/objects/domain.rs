@@ -2,15 +2,15 @@ use serde_json::value::Value;
 use serde::{Deserialize, Serialize};
 use colored::Colorize;
 use ldap3::SearchEntry;
-use log::{info, debug, trace};
+use log::{info, debug, trace, warn};
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::RustHoundError;
 
 use crate::enums::regex::OBJECT_SID_RE1;
 use crate::objects::common::{LdapObject, GPOChange, Link, AceTemplate, SPNTarget, Member};
 use crate::objects::trust::Trust;
 use crate::utils::date::{span_to_string, string_to_epoch};
-use crate::enums::acl::parse_ntsecuritydescriptor;
+use crate::enums::acl::{dedup_aces, parse_ntsecuritydescriptor};
 use crate::enums::forestlevel::get_forest_level;
 use crate::enums::gplink::parse_gplink;
 use crate::enums::secdesc::LdapSid;
@@ -47,6 +47,11 @@ impl Domain {
         Self { ..Default::default() } 
     }
 
+    // Immutable access.
+    pub fn properties(&self) -> &DomainProperties {
+        &self.properties
+    }
+
     // Mutable access.
     pub fn properties_mut(&mut self) -> &mut DomainProperties {
         &mut self.properties
@@ -69,7 +74,7 @@ impl Domain {
         domain_name: &str,
         dn_sid: &mut HashMap<String, String>,
         sid_type: &mut HashMap<String, String>,
-    ) -> Result<String, Box<dyn Error>> {
+    ) -> Result<String, RustHoundError> {
         let result_dn: String = result.dn.to_uppercase();
         let result_attrs: HashMap<String, Vec<String>> = result.attrs;
         let result_bin: HashMap<String, Vec<Vec<u8>>> = result.bin_attrs;
@@ -133,7 +138,7 @@ impl Domain {
                         info!("MachineAccountQuota: {}", machine_account_quota.to_string().yellow().bold());
                     }
                 }
-                "IsDeleted" => {
+                "isDeleted" => {
                     self.is_deleted = true;
                 }
                 "msDS-ExpirePasswordsOnSmartCardOnlyAccounts" => {
@@ -167,6 +172,12 @@ impl Domain {
             }
         }
 
+        // Samba AD doesn't always return msDS-Behavior-Version
+        if self.properties.functionallevel.is_empty() {
+            warn!("msDS-Behavior-Version not found for {}, functional level unknown", self.properties.name.bold().yellow());
+            self.properties.functionallevel = get_forest_level(String::new());
+        }
+
         // For all, bins attributes
         for (key, value) in &result_bin {
             match key.as_str() {
@@ -193,7 +204,7 @@ impl Domain {
                         &result_bin,
                         domain_name,
                     );
-                    self.aces = relations_ace;
+                    self.aces = dedup_aces(relations_ace);
                 }
                 _ => {}
             }
@@ -285,6 +296,12 @@ impl LdapObject for Domain {
     fn set_child_objects(&mut self, child_objects: Vec<Member>) {
         self.child_objects = child_objects
     }
+    fn set_ace_deviation(&mut self, count: u32) {
+        self.properties.acedeviation = Some(count);
+    }
+    fn set_custom_properties(&mut self, properties: serde_json::Map<String, serde_json::Value>) {
+        self.properties.custom.extend(properties);
+    }
 }
 
 // Domain properties structure
@@ -309,10 +326,19 @@ pub struct DomainProperties {
     lockoutduration: String,
     lockoutobservationwindow: i64,
     functionallevel: String,
-    collected: bool
+    collected: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acedeviation: Option<u32>,
+    #[serde(flatten)]
+    custom: serde_json::Map<String, serde_json::Value>,
 }
 
 impl DomainProperties {
+    // Immutable access.
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
     // Mutable access.
     pub fn domain_mut(&mut self) -> &mut String {
        &mut self.domain
@@ -1,20 +1,19 @@
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
 use serde_json::value::Value;
-use x509_parser::oid_registry::asn1_rs::oid;
-use x509_parser::prelude::*;
 use ldap3::SearchEntry;
 use log::{debug, error, info, trace};
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::RustHoundError;
 
 use crate::enums::{
     MaskFlags, SecurityDescriptor, AceFormat, Acl,
-    decode_guid_le, parse_ntsecuritydescriptor, sid_maker, parse_ca_security
+    decode_guid_le, dedup_aces, parse_ntsecuritydescriptor, sid_maker, parse_ca_security, is_admin_sid, get_ca_flags, is_user_specifies_san_enabled,
+    parse_enrollment_agent_rights,
 };
 use crate::json::checker::common::get_name_from_full_distinguishedname;
 use crate::objects::common::{LdapObject, AceTemplate, SPNTarget, Link, Member};
-use crate::utils::crypto::calculate_sha1;
+use crate::utils::crypto::{basic_constraints, calculate_sha1, calculate_sha256, certificate_key, certificate_parse_failure, current_certificate, extended_key_usage};
 use crate::utils::date::string_to_epoch;
 
 /// EnterpriseCA structure
@@ -46,15 +45,38 @@ impl EnterpriseCA {
         Self { ..Default::default() } 
     }
 
+    /// Builder of an EnterpriseCA from other sources than LDAP, like test fixtures.
+    pub fn builder(object_identifier: &str) -> EnterpriseCABuilder {
+        EnterpriseCABuilder::new(object_identifier)
+    }
+
     // Immutable access.
     pub fn enabled_cert_templates(&self) -> &Vec<Member> {
         &self.enabled_cert_templates
     }
+    pub fn hosting_computer(&self) -> &String {
+        &self.hosting_computer
+    }
+    pub fn caname(&self) -> &String {
+        &self.properties.caname
+    }
+    pub fn dnshostname(&self) -> &String {
+        &self.properties.dnshostname
+    }
+    pub fn dnshostname_sid(&self) -> &Option<String> {
+        &self.properties.dnshostnamesid
+    }
 
     // Mutable access.
     pub fn enabled_cert_templates_mut(&mut self) -> &mut Vec<Member> {
         &mut self.enabled_cert_templates
     }
+    pub fn unresolved_published_templates_mut(&mut self) -> &mut Vec<String> {
+        &mut self.properties.unresolvedpublishedtemplates
+    }
+    pub fn dnshostname_sid_mut(&mut self) -> &mut Option<String> {
+        &mut self.properties.dnshostnamesid
+    }
 
     /// Function to parse and replace value in json template for Enterprise CA object.
     pub fn parse(
@@ -64,7 +86,7 @@ impl EnterpriseCA {
         dn_sid: &mut HashMap<String, String>,
         sid_type: &mut HashMap<String, String>,
         domain_sid: &str,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), RustHoundError> {
         let result_dn: String = result.dn.to_uppercase();
         let result_attrs: HashMap<String, Vec<String>> = result.attrs;
         let result_bin: HashMap<String, Vec<Vec<u8>>> = result.bin_attrs;
@@ -124,7 +146,10 @@ impl EnterpriseCA {
                         self.properties.whencreated = epoch;
                     }
                 }
-                "IsDeleted" => {
+                "flags" => {
+                    self.properties.flags = get_ca_flags(value[0].parse::<i64>().unwrap_or(0) as u64);
+                }
+                "isDeleted" => {
                     self.is_deleted = true;
                 }
                 _ => {}
@@ -150,7 +175,7 @@ impl EnterpriseCA {
                         domain,
                     );
                     // Aces
-                    self.aces = relations_ace;
+                    self.aces = dedup_aces(relations_ace);
                     // HostingComputer
                     self.hosting_computer = Self::get_hosting_computer(&value[0], domain);
                     // CASecurity
@@ -176,53 +201,49 @@ impl EnterpriseCA {
                     }
                 }
                 "cACertificate" => {
-                    //info!("{:?}:{:?}", key,value[0].to_owned());
-                    let certsha1: String = calculate_sha1(&value[0]);
+                    // A renewed CA publishes one certificate per key, the chain lists them all
+                    self.properties.certchain = value.iter().map(|cert| calculate_sha1(cert)).collect();
+                    let current = current_certificate(value);
+                    let der = &value[current.as_ref().map_or(0, |(index, _)| *index)];
+                    let certsha1: String = calculate_sha1(der);
                     self.properties.certthumbprint = certsha1.to_owned();
+                    self.properties.certthumbprintsha256 = calculate_sha256(der);
                     self.properties.certname = certsha1.to_owned();
-                    self.properties.certchain = vec![certsha1.to_owned()];
 
                     // Parsing certificate.
-                    let res = X509Certificate::from_der(&value[0]);
-                    match res {
-                        Ok((_rem, cert)) => {
-                            // println!("Basic Constraints Extensions:");
-                            for ext in cert.extensions() {
-                                // println!("{:?} : {:?}",&ext.oid, ext);
-                                if &ext.oid == &oid!(2.5.29.19) {
-                                    // <https://docs.rs/x509-parser/latest/x509_parser/extensions/struct.BasicConstraints.html>
-                                    if let ParsedExtension::BasicConstraints(basic_constraints) = &ext.parsed_extension() {
-                                        let _ca = &basic_constraints.ca;
-                                        let _path_len_constraint = &basic_constraints.path_len_constraint;
-                                        // println!("ca: {:?}", _ca);
-                                        // println!("path_len_constraint: {:?}", _path_len_constraint);
-                                        match _path_len_constraint {
-                                            Some(_path_len_constraint) => {
-                                                if _path_len_constraint > &0 {
-                                                    self.properties.hasbasicconstraints = true;
-                                                    self.properties.basicconstraintpathlength = _path_len_constraint.to_owned();
-
-                                                } else {
-                                                    self.properties.hasbasicconstraints = false;
-                                                    self.properties.basicconstraintpathlength = 0;
-                                                }
-                                            }
-                                            None => {
-                                                self.properties.hasbasicconstraints = false;
-                                                self.properties.basicconstraintpathlength = 0;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                    match current {
+                        Some((_index, cert)) => {
+                            // <https://docs.rs/x509-parser/latest/x509_parser/extensions/struct.BasicConstraints.html>
+                            let (ca, path_length) = basic_constraints(&cert);
+                            self.properties.hasbasicconstraints = ca;
+                            self.properties.basicconstraintpathlength = path_length;
+                            self.properties.certificatekeyusage = extended_key_usage(&cert);
+                            self.properties.notbefore = cert.validity().not_before.timestamp();
+                            self.properties.notafter = cert.validity().not_after.timestamp();
+                            let key = certificate_key(&cert);
+                            self.properties.publickeyalgorithm = Some(key.algorithm);
+                            self.properties.publickeysize = key.size;
+                            self.properties.publickeycurve = key.curve;
                         },
-                        _ => error!("CA x509 certificate parsing failed: {:?}", res),
+                        None => {
+                            let failure = certificate_parse_failure(value);
+                            error!("{failure} for {}", self.properties.distinguishedname);
+                            self.properties.certparsefailure = Some(failure);
+                        }
                     }
                 }
                 _ => {}
             }
         }
 
+        // EnrollmentAgentRights, EditFlags and RoleSeparationEnabled are only in the CA host registry, never reported as empty when not read
+        self.ca_registry_data.enrollment_agent_restrictions = EnrollmentAgentRestrictions::not_collected(&self.properties.dnshostname);
+        self.properties.enrollmentagentrestrictionscollected = false;
+        self.ca_registry_data.is_user_specifies_san_enabled = IsUserSpecifiesSanEnabled::not_collected(&self.properties.dnshostname);
+        self.properties.isuserspecifiessanenabledcollected = false;
+        self.ca_registry_data.role_separation_enabled = RoleSeparationEnabled::not_collected(&self.properties.dnshostname);
+        self.properties.roleseparationenabledcollected = false;
+
         // Push DN and SID in HashMap
         if self.object_identifier != "SID" {
             dn_sid.insert(
@@ -241,20 +262,92 @@ impl EnterpriseCA {
         Ok(())
     }
 
+    /// Replace the HostingComputer SID and the CASecurity local groups built from the previous one.
+    pub fn set_hosting_computer(&mut self, hosting_computer: &str) {
+        let previous = format!("{}-", self.hosting_computer);
+        for ace in self.ca_registry_data.ca_security.data.iter_mut() {
+            if ace.principal_type() == "LocalGroup" && ace.principal_sid().starts_with(&previous) {
+                let rid = ace.principal_sid()[previous.len()..].to_string();
+                *ace.principal_sid_mut() = format!("{hosting_computer}-{rid}");
+            }
+        }
+        self.hosting_computer = hosting_computer.to_string();
+    }
+
+    /// Set EnrollmentAgentRestrictions from the EnrollmentAgentRights security descriptor read in the CA registry.
+    pub fn set_enrollment_agent_rights(&mut self, nt: &[u8], domain: &str) {
+        let restrictions = &mut self.ca_registry_data.enrollment_agent_restrictions;
+        match parse_enrollment_agent_rights(nt, domain) {
+            Ok(parsed) => {
+                restrictions.restrictions = parsed;
+                restrictions.collected = true;
+                restrictions.failure_reason = None;
+            }
+            Err(err) => {
+                restrictions.restrictions = Vec::new();
+                restrictions.collected = false;
+                restrictions.failure_reason = Some(format!("Failed to parse EnrollmentAgentRights: {err}"));
+            }
+        }
+        self.properties.enrollmentagentrestrictionscollected = restrictions.collected;
+    }
+
+    /// Set no EnrollmentAgentRestrictions, EnrollmentAgentRights is not set in the CA registry.
+    pub fn clear_enrollment_agent_rights(&mut self) {
+        self.ca_registry_data.enrollment_agent_restrictions = EnrollmentAgentRestrictions::default();
+        self.properties.enrollmentagentrestrictionscollected = true;
+    }
+
+    /// Enrollment agent restrictions, their template names are replaced by the template object identifiers.
+    pub fn enrollment_agent_restrictions_mut(&mut self) -> &mut Vec<EnrollmentAgentRestriction> {
+        &mut self.ca_registry_data.enrollment_agent_restrictions.restrictions
+    }
+
+    /// Set IsUserSpecifiesSanEnabled (ESC6) from the EditFlags value read in the CA registry.
+    pub fn set_edit_flags(&mut self, edit_flags: u32) {
+        self.ca_registry_data.is_user_specifies_san_enabled = IsUserSpecifiesSanEnabled {
+            value: is_user_specifies_san_enabled(edit_flags),
+            collected: true,
+            failure_reason: None,
+        };
+        self.properties.isuserspecifiessanenabledcollected = true;
+    }
+
+    /// Set RoleSeparationEnabled from the value read in the CA registry.
+    pub fn set_role_separation_enabled(&mut self, role_separation_enabled: bool) {
+        self.ca_registry_data.role_separation_enabled = RoleSeparationEnabled {
+            value: role_separation_enabled,
+            collected: true,
+            failure_reason: None,
+        };
+        self.properties.roleseparationenabledcollected = true;
+    }
+
+    /// Set the reason of the CA registry values not read, the values already read are kept.
+    pub fn set_registry_failure(&mut self, reason: &str) {
+        let registry = &mut self.ca_registry_data;
+        if !registry.enrollment_agent_restrictions.collected {
+            registry.enrollment_agent_restrictions.failure_reason = Some(reason.to_string());
+        }
+        if !registry.is_user_specifies_san_enabled.collected {
+            registry.is_user_specifies_san_enabled.failure_reason = Some(reason.to_string());
+        }
+        if !registry.role_separation_enabled.collected {
+            registry.role_separation_enabled.failure_reason = Some(reason.to_string());
+        }
+    }
+
     /// Function to get HostingComputer from ACL if ACE get ManageCertificates and is not Group.
     fn get_hosting_computer(
         nt: &[u8],
         domain: &str,
     ) -> String {
         let mut hosting_computer = String::from("Not found");
-        let blacklist_sid = [
-            // <https://learn.microsoft.com/fr-fr/windows-server/identity/ad-ds/manage/understand-security-identifiers>
-            "-544", // Administrators
-            "-519", // Enterprise Administrators
-            "-512", // Domain Admins
-        ];
-        let secdesc: SecurityDescriptor = SecurityDescriptor::parse(nt).unwrap().1;
-        if secdesc.offset_dacl as usize != 0 
+        let secdesc: SecurityDescriptor = match SecurityDescriptor::parse(nt) {
+            Ok(res) => res.1,
+            Err(_) => return hosting_computer,
+        };
+        if secdesc.offset_dacl as usize != 0 && (secdesc.offset_dacl as usize) < nt.len()
         {
             let res = Acl::parse(&nt[secdesc.offset_dacl as usize..]);
             match res {
@@ -269,7 +362,7 @@ impl EnterpriseCA {
                                 None => continue,
                             };
                             if (MaskFlags::MANAGE_CERTIFICATES.bits() | mask) == mask
-                            && !blacklist_sid.iter().any(|blacklisted| sid.ends_with(blacklisted)) 
+                            && !is_admin_sid(&sid)
                             {
                                 // println!("SID MANAGE_CERTIFICATES: {:?}",&sid);
                                 hosting_computer = sid;
@@ -285,6 +378,152 @@ impl EnterpriseCA {
     }
 }
 
+/// Builder for [`EnterpriseCA`], the values are kept as given (the LDAP parsing uppercases the domain and the name).
+///
+/// Every value not set keeps the default of [`EnterpriseCA::new`]: `isaclprotected`, `IsDeleted`, `ContainedBy`,
+/// `flags` and `unresolvedpublishedtemplates`. The CA registry data is not collected, like an LDAP collection,
+/// except CASecurity and the EditFlags given to the builder.
+#[derive(Clone, Debug)]
+pub struct EnterpriseCABuilder {
+    ca: EnterpriseCA,
+}
+
+impl EnterpriseCABuilder {
+    /// New builder with the object identifier (objectGUID) of the CA.
+    pub fn new(object_identifier: &str) -> Self {
+        let mut ca = EnterpriseCA::new();
+        ca.object_identifier = object_identifier.to_string();
+        ca.ca_registry_data.ca_security.collected = false;
+        Self { ca }
+    }
+
+    /// Domain name and domain SID.
+    pub fn domain(mut self, domain: &str, domain_sid: &str) -> Self {
+        self.ca.properties.domain = domain.to_string();
+        self.ca.properties.domainsid = domain_sid.to_string();
+        self
+    }
+    /// CA name, the node name is `CANAME@DOMAIN` unless set with [`EnterpriseCABuilder::name`].
+    pub fn caname(mut self, caname: &str) -> Self {
+        self.ca.properties.caname = caname.to_string();
+        self
+    }
+    /// Node name.
+    pub fn name(mut self, name: &str) -> Self {
+        self.ca.properties.name = name.to_string();
+        self
+    }
+    /// Distinguished name of the pKIEnrollmentService object.
+    pub fn distinguishedname(mut self, distinguishedname: &str) -> Self {
+        self.ca.properties.distinguishedname = distinguishedname.to_string();
+        self
+    }
+    /// DNS host name of the CA server.
+    pub fn dnshostname(mut self, dnshostname: &str) -> Self {
+        self.ca.properties.dnshostname = dnshostname.to_string();
+        self
+    }
+    /// SID of the computer hosting the CA.
+    pub fn hosting_computer(mut self, hosting_computer: &str) -> Self {
+        self.ca.hosting_computer = hosting_computer.to_string();
+        self
+    }
+    /// Description.
+    pub fn description(mut self, description: &str) -> Self {
+        self.ca.properties.description = Some(description.to_string());
+        self
+    }
+    /// Creation date, epoch seconds.
+    pub fn whencreated(mut self, whencreated: i64) -> Self {
+        self.ca.properties.whencreated = whencreated;
+        self
+    }
+    /// SHA1 thumbprint of the current CA certificate, also the certificate name and the chain unless set with [`EnterpriseCABuilder::certchain`].
+    pub fn certthumbprint(mut self, certthumbprint: &str) -> Self {
+        self.ca.properties.certthumbprint = certthumbprint.to_string();
+        self.ca.properties.certname = certthumbprint.to_string();
+        self
+    }
+    /// SHA256 thumbprint of the current CA certificate.
+    pub fn certthumbprintsha256(mut self, certthumbprintsha256: &str) -> Self {
+        self.ca.properties.certthumbprintsha256 = certthumbprintsha256.to_string();
+        self
+    }
+    /// SHA1 thumbprints of all the CA certificates.
+    pub fn certchain(mut self, certchain: &[&str]) -> Self {
+        self.ca.properties.certchain = certchain.iter().map(|cert| cert.to_string()).collect();
+        self
+    }
+    /// Basic constraints of the CA certificate.
+    pub fn basic_constraints(mut self, hasbasicconstraints: bool, basicconstraintpathlength: u32) -> Self {
+        self.ca.properties.hasbasicconstraints = hasbasicconstraints;
+        self.ca.properties.basicconstraintpathlength = basicconstraintpathlength;
+        self
+    }
+    /// Extended Key Usage OIDs of the CA certificate.
+    pub fn certificatekeyusage(mut self, certificatekeyusage: &[&str]) -> Self {
+        self.ca.properties.certificatekeyusage = certificatekeyusage.iter().map(|oid| oid.to_string()).collect();
+        self
+    }
+    /// Validity of the CA certificate, epoch seconds.
+    pub fn validity(mut self, notbefore: i64, notafter: i64) -> Self {
+        self.ca.properties.notbefore = notbefore;
+        self.ca.properties.notafter = notafter;
+        self
+    }
+    /// Subject public key of the CA certificate.
+    pub fn public_key(mut self, algorithm: &str, size: Option<u32>, curve: Option<&str>) -> Self {
+        self.ca.properties.publickeyalgorithm = Some(algorithm.to_string());
+        self.ca.properties.publickeysize = size;
+        self.ca.properties.publickeycurve = curve.map(str::to_string);
+        self
+    }
+    /// Object identifiers of the certificate templates published by the CA.
+    pub fn enabled_cert_templates(mut self, templates: &[&str]) -> Self {
+        self.ca.enabled_cert_templates = templates.iter().map(|template| {
+            let mut member = Member::new();
+            *member.object_identifier_mut() = template.to_string();
+            *member.object_type_mut() = String::from("CertTemplate");
+            member
+        }).collect();
+        self
+    }
+    /// ACEs of the pKIEnrollmentService object.
+    pub fn aces(mut self, aces: Vec<AceTemplate>) -> Self {
+        self.ca.aces = aces;
+        self
+    }
+    /// CASecurity ACEs of the CA.
+    pub fn ca_security(mut self, aces: Vec<AceTemplate>) -> Self {
+        self.ca.ca_registry_data.ca_security = CASecurity { data: aces, collected: true, failure_reason: None };
+        self.ca.properties.casecuritycollected = true;
+        self
+    }
+    /// EditFlags read in the CA registry, sets IsUserSpecifiesSanEnabled.
+    pub fn edit_flags(mut self, edit_flags: u32) -> Self {
+        self.ca.set_edit_flags(edit_flags);
+        self
+    }
+
+    /// Build the EnterpriseCA.
+    pub fn build(mut self) -> EnterpriseCA {
+        let properties = &mut self.ca.properties;
+        if properties.name.is_empty() && !properties.caname.is_empty() {
+            properties.name = format!("{}@{}", properties.caname, properties.domain).to_uppercase();
+        }
+        if properties.certchain.is_empty() && !properties.certthumbprint.is_empty() {
+            properties.certchain = vec![properties.certthumbprint.to_owned()];
+        }
+        let registry = &mut self.ca.ca_registry_data;
+        registry.enrollment_agent_restrictions = EnrollmentAgentRestrictions::not_collected(&properties.dnshostname);
+        registry.role_separation_enabled = RoleSeparationEnabled::not_collected(&properties.dnshostname);
+        if !properties.isuserspecifiessanenabledcollected {
+            registry.is_user_specifies_san_enabled = IsUserSpecifiesSanEnabled::not_collected(&properties.dnshostname);
+        }
+        self.ca
+    }
+}
+
 impl LdapObject for EnterpriseCA {
     // To JSON
     fn to_json(&self) -> Value {
@@ -370,11 +609,26 @@ pub struct EnterpriseCAProperties {
     flags: String,
     caname: String,
     dnshostname: String,
+    /// SID of the computer named by the dNSHostName of the CA, none when it is not a collected computer.
+    dnshostnamesid: Option<String>,
     certthumbprint: String,
+    /// SHA256 thumbprint of the current CA certificate, `certthumbprint` stays the SHA1 one used by BloodHound.
+    certthumbprintsha256: String,
     certname: String,
     certchain: Vec<String>,
     hasbasicconstraints: bool,
     basicconstraintpathlength: u32,
+    /// Error of the CA certificate when none of its certificates could be decoded, none once parsed.
+    certparsefailure: Option<String>,
+    /// Extended Key Usage OIDs of the CA certificate, empty when not constrained.
+    certificatekeyusage: Vec<String>,
+    /// Validity of the CA certificate, epoch seconds.
+    notbefore: i64,
+    notafter: i64,
+    /// Subject public key of the CA certificate: algorithm OID, size in bits and named curve of an EC key.
+    publickeyalgorithm: Option<String>,
+    publickeysize: Option<u32>,
+    publickeycurve: Option<String>,
     unresolvedpublishedtemplates: Vec<String>,
     casecuritycollected: bool,
     enrollmentagentrestrictionscollected: bool,
@@ -395,11 +649,20 @@ impl Default for EnterpriseCAProperties {
             flags: String::from(""),
             caname: String::from(""),
             dnshostname: String::from(""),
+            dnshostnamesid: None,
             certthumbprint: String::from(""),
+            certthumbprintsha256: String::from(""),
             certname: String::from(""),
             certchain: Vec::new(),
             hasbasicconstraints: false,
             basicconstraintpathlength: 0,
+            certparsefailure: None,
+            certificatekeyusage: Vec::new(),
+            notbefore: -1,
+            notafter: -1,
+            publickeyalgorithm: None,
+            publickeysize: None,
+            publickeycurve: None,
             unresolvedpublishedtemplates: Vec::new(),
             casecuritycollected: false,
             enrollmentagentrestrictionscollected: false,
@@ -459,13 +722,63 @@ impl Default for CASecurity {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct EnrollmentAgentRestrictions {
     #[serde(rename = "Restrictions")]
-    restrictions: Vec<String>, // data to validate
+    restrictions: Vec<EnrollmentAgentRestriction>,
     #[serde(rename = "Collected")]
     collected: bool,
     #[serde(rename = "FailureReason")]
     failure_reason: Option<String>,
 }
 
+impl EnrollmentAgentRestrictions {
+    /// EnrollmentAgentRights not read in the CA registry.
+    pub fn not_collected(dnshostname: &str) -> Self {
+        Self {
+            restrictions: Vec::new(),
+            collected: false,
+            failure_reason: Some(registry_failure_reason(dnshostname)),
+        }
+    }
+}
+
+// EnrollmentAgentRestriction structure, one callback ACE of EnrollmentAgentRights
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EnrollmentAgentRestriction {
+    #[serde(rename = "AccessType")]
+    access_type: String,
+    #[serde(rename = "Agent")]
+    agent: Member,
+    #[serde(rename = "Targets")]
+    targets: Vec<Member>,
+    #[serde(rename = "Template")]
+    template: Option<Member>,
+    #[serde(rename = "AllTemplates")]
+    all_templates: bool,
+}
+
+impl EnrollmentAgentRestriction {
+    /// New restriction, for all templates without a template name.
+    pub fn new(access_type: &str, agent: String, targets: Vec<String>, template: Option<String>) -> Self {
+        let member = |object_identifier: String, object_type: &str| {
+            let mut member = Member::new();
+            *member.object_identifier_mut() = object_identifier;
+            *member.object_type_mut() = object_type.to_string();
+            member
+        };
+        Self {
+            access_type: access_type.to_string(),
+            agent: member(agent, "Base"),
+            targets: targets.into_iter().map(|target| member(target, "Base")).collect(),
+            all_templates: template.is_none(),
+            template: template.map(|name| member(name, "CertTemplate")),
+        }
+    }
+
+    // Mutable access.
+    pub fn template_mut(&mut self) -> &mut Option<Member> {
+        &mut self.template
+    }
+}
+
 impl Default for EnrollmentAgentRestrictions {
     fn default() -> EnrollmentAgentRestrictions {
         EnrollmentAgentRestrictions {
@@ -487,6 +800,17 @@ pub struct IsUserSpecifiesSanEnabled {
     failure_reason: Option<String>,
 }
 
+impl IsUserSpecifiesSanEnabled {
+    /// EditFlags not read in the CA registry.
+    pub fn not_collected(dnshostname: &str) -> Self {
+        Self {
+            value: false,
+            collected: false,
+            failure_reason: Some(registry_failure_reason(dnshostname)),
+        }
+    }
+}
+
 impl Default for IsUserSpecifiesSanEnabled {
     fn default() -> IsUserSpecifiesSanEnabled {
         IsUserSpecifiesSanEnabled {
@@ -508,6 +832,26 @@ pub struct RoleSeparationEnabled {
     failure_reason: Option<String>,
 }
 
+impl RoleSeparationEnabled {
+    /// RoleSeparationEnabled not read in the CA registry.
+    pub fn not_collected(dnshostname: &str) -> Self {
+        Self {
+            value: false,
+            collected: false,
+            failure_reason: Some(registry_failure_reason(dnshostname)),
+        }
+    }
+}
+
+/// Reason of a CA registry value not collected.
+fn registry_failure_reason(dnshostname: &str) -> String {
+    if dnshostname.is_empty() {
+        String::from("No dNSHostName to read the CA registry")
+    } else {
+        format!("CA registry of {dnshostname} not read")
+    }
+}
+
 impl Default for RoleSeparationEnabled {
     fn default() -> RoleSeparationEnabled {
         RoleSeparationEnabled {
@@ -516,4 +860,212 @@ impl Default for RoleSeparationEnabled {
             failure_reason: None,
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use base64::Engine;
+    use ldap3::SearchEntry;
+
+    use crate::enums::sddl::sddl_to_security_descriptor;
+    use crate::objects::enterpriseca::EnterpriseCA;
+    use crate::objects::common::LdapObject;
+    use crate::utils::crypto::{calculate_sha1, calculate_sha256};
+    use crate::utils::crypto::tests::{CERTIFICATE, EKU_CA, ISSUING_CA, RSA_CA};
+
+    #[test]
+    pub fn test_renewed_ca_certificates() {
+        // Renewed CA: the previous certificate then the current one
+        let certificates: Vec<Vec<u8>> = [CERTIFICATE, ISSUING_CA].iter()
+            .map(|cert| base64::engine::general_purpose::STANDARD.decode(cert).unwrap())
+            .collect();
+        let entry = SearchEntry {
+            dn: "CN=contoso-DC01-CA,CN=Enrollment Services,CN=Public Key Services,CN=Services,CN=Configuration,DC=CONTOSO,DC=LOCAL".to_string(),
+            attrs: HashMap::from([("name".to_string(), vec!["contoso-DC01-CA".to_string()])]),
+            bin_attrs: HashMap::from([("cACertificate".to_string(), certificates.to_owned())]),
+        };
+        let mut ca = EnterpriseCA::new();
+        ca.parse(entry, "contoso.local", &mut HashMap::new(), &mut HashMap::new(), "S-1-5-21-1-2-3").unwrap();
+        assert_eq!(ca.properties.certchain.len(), 2);
+        assert_eq!(ca.properties.certchain, vec![calculate_sha1(&certificates[0]), calculate_sha1(&certificates[1])]);
+        // The most recent certificate is the current one
+        assert_eq!(ca.properties.certthumbprint, calculate_sha1(&certificates[1]));
+        assert_eq!(ca.properties.certthumbprintsha256, calculate_sha256(&certificates[1]));
+        assert_eq!(ca.properties.certname, ca.properties.certthumbprint);
+        assert!(ca.properties.hasbasicconstraints);
+        assert!(ca.properties.certificatekeyusage.is_empty());
+        // No dNSHostName: the registry can't be read
+        let role_separation = &ca.ca_registry_data.role_separation_enabled;
+        assert!(!role_separation.collected);
+        assert_eq!(role_separation.failure_reason.as_deref(), Some("No dNSHostName to read the CA registry"));
+    }
+
+    #[test]
+    pub fn test_user_specifies_san_from_edit_flags() {
+        let entry = SearchEntry {
+            dn: "CN=contoso-DC01-CA,CN=Enrollment Services,CN=Public Key Services,CN=Services,CN=Configuration,DC=CONTOSO,DC=LOCAL".to_string(),
+            attrs: HashMap::from([("dNSHostName".to_string(), vec!["dc01.contoso.local".to_string()])]),
+            bin_attrs: HashMap::new(),
+        };
+        let mut ca = EnterpriseCA::new();
+        ca.parse(entry, "contoso.local", &mut HashMap::new(), &mut HashMap::new(), "S-1-5-21-1-2-3").unwrap();
+        let json = serde_json::to_value(&ca).unwrap();
+        assert_eq!(json["Properties"]["isuserspecifiessanenabledcollected"], false);
+        assert_eq!(json["CARegistryData"]["IsUserSpecifiesSanEnabled"]["Collected"], false);
+        assert!(json["CARegistryData"]["IsUserSpecifiesSanEnabled"]["FailureReason"].as_str().unwrap().contains("dc01.contoso.local"));
+
+        // EditFlags with EDITF_ATTRIBUTESUBJECTALTNAME2
+        ca.set_edit_flags(0x0015014E);
+        let json = serde_json::to_value(&ca).unwrap();
+        assert_eq!(json["Properties"]["isuserspecifiessanenabledcollected"], true);
+        assert_eq!(
+            json["CARegistryData"]["IsUserSpecifiesSanEnabled"],
+            serde_json::json!({"Value": true, "Collected": true, "FailureReason": null})
+        );
+        ca.set_edit_flags(0x0011014E);
+        assert_eq!(serde_json::to_value(&ca).unwrap()["CARegistryData"]["IsUserSpecifiesSanEnabled"]["Value"], false);
+    }
+
+    #[test]
+    pub fn test_enrollment_agent_rights_failure() {
+        let mut ca = EnterpriseCA::new();
+        assert_eq!(serde_json::to_value(&ca).unwrap()["CARegistryData"]["EnrollmentAgentRestrictions"]["Restrictions"], serde_json::json!([]));
+        // Security descriptor without DACL: no restriction
+        ca.set_enrollment_agent_rights(&[1, 0, 0, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], "contoso.local");
+        assert!(ca.properties.enrollmentagentrestrictionscollected);
+        ca.set_enrollment_agent_rights(&[1, 0, 4, 0x80], "contoso.local");
+        let json = serde_json::to_value(&ca).unwrap();
+        assert_eq!(json["Properties"]["enrollmentagentrestrictionscollected"], false);
+        assert_eq!(json["CARegistryData"]["EnrollmentAgentRestrictions"]["Collected"], false);
+        assert!(json["CARegistryData"]["EnrollmentAgentRestrictions"]["FailureReason"].as_str().unwrap().starts_with("Failed to parse EnrollmentAgentRights"));
+    }
+
+    #[test]
+    pub fn test_ca_certificate_key_usage() {
+        let entry = SearchEntry {
+            dn: "CN=contoso-DC01-CA,CN=Enrollment Services,CN=Public Key Services,CN=Services,CN=Configuration,DC=CONTOSO,DC=LOCAL".to_string(),
+            attrs: HashMap::new(),
+            bin_attrs: HashMap::from([("cACertificate".to_string(), vec![base64::engine::general_purpose::STANDARD.decode(EKU_CA).unwrap()])]),
+        };
+        let mut ca = EnterpriseCA::new();
+        ca.parse(entry, "contoso.local", &mut HashMap::new(), &mut HashMap::new(), "S-1-5-21-1-2-3").unwrap();
+        let json = serde_json::to_value(&ca).unwrap();
+        assert_eq!(json["Properties"]["certificatekeyusage"], serde_json::json!(["1.3.6.1.5.5.7.3.2", "1.3.6.1.4.1.311.20.2.2"]));
+    }
+
+    #[test]
+    pub fn test_ca_certificate_validity() {
+        let entry = SearchEntry {
+            dn: "CN=contoso-DC01-CA,CN=Enrollment Services,CN=Public Key Services,CN=Services,CN=Configuration,DC=CONTOSO,DC=LOCAL".to_string(),
+            attrs: HashMap::new(),
+            bin_attrs: HashMap::from([("cACertificate".to_string(), vec![base64::engine::general_purpose::STANDARD.decode(ISSUING_CA).unwrap()])]),
+        };
+        let mut ca = EnterpriseCA::new();
+        ca.parse(entry, "contoso.local", &mut HashMap::new(), &mut HashMap::new(), "S-1-5-21-1-2-3").unwrap();
+        let json = serde_json::to_value(&ca).unwrap();
+        // 2026-10-16 12:18:49 UTC to 2036-10-13 12:18:49 UTC
+        assert_eq!(json["Properties"]["notbefore"], 1792153129);
+        assert_eq!(json["Properties"]["notafter"], 2107513129);
+        assert_eq!(EnterpriseCA::new().properties.notbefore, -1);
+    }
+
+    #[test]
+    pub fn test_ca_certificate_public_key() {
+        for (certificate, algorithm, size, curve) in [
+            (RSA_CA, "1.2.840.113549.1.1.1", 2048, serde_json::Value::Null),
+            (CERTIFICATE, "1.2.840.10045.2.1", 256, serde_json::json!("P-256")),
+        ] {
+            let entry = SearchEntry {
+                dn: "CN=contoso-DC01-CA,CN=Enrollment Services,CN=Public Key Services,CN=Services,CN=Configuration,DC=CONTOSO,DC=LOCAL".to_string(),
+                attrs: HashMap::new(),
+                bin_attrs: HashMap::from([("cACertificate".to_string(), vec![base64::engine::general_purpose::STANDARD.decode(certificate).unwrap()])]),
+            };
+            let mut ca = EnterpriseCA::new();
+            ca.parse(entry, "contoso.local", &mut HashMap::new(), &mut HashMap::new(), "S-1-5-21-1-2-3").unwrap();
+            let json = serde_json::to_value(&ca).unwrap();
+            assert_eq!(json["Properties"]["publickeyalgorithm"], algorithm);
+            assert_eq!(json["Properties"]["publickeysize"], size);
+            assert_eq!(json["Properties"]["publickeycurve"], curve);
+        }
+        // No certificate
+        let json = serde_json::to_value(EnterpriseCA::new()).unwrap();
+        assert!(json["Properties"]["publickeyalgorithm"].is_null() && json["Properties"]["publickeysize"].is_null());
+    }
+
+    #[test]
+    pub fn test_ca_certificate_parse_failure() {
+        // Truncated DER: thumbprints of the stored bytes, the parse failure in the output
+        let mut der = base64::engine::general_purpose::STANDARD.decode(ISSUING_CA).unwrap();
+        der.truncate(40);
+        let entry = SearchEntry {
+            dn: "CN=contoso-DC01-CA,CN=Enrollment Services,CN=Public Key Services,CN=Services,CN=Configuration,DC=CONTOSO,DC=LOCAL".to_string(),
+            attrs: HashMap::new(),
+            bin_attrs: HashMap::from([("cACertificate".to_string(), vec![der.to_owned()])]),
+        };
+        let mut ca = EnterpriseCA::new();
+        ca.parse(entry, "contoso.local", &mut HashMap::new(), &mut HashMap::new(), "S-1-5-21-1-2-3").unwrap();
+        let json = serde_json::to_value(&ca).unwrap();
+        assert_eq!(json["Properties"]["certthumbprint"], calculate_sha1(&der));
+        assert!(json["Properties"]["certparsefailure"].as_str().unwrap().starts_with("Failed to parse the CA certificate: "));
+        assert_eq!(json["Properties"]["notbefore"], -1);
+
+        // Parsed certificate: no failure
+        let entry = SearchEntry {
+            dn: "CN=contoso-DC01-CA,CN=Enrollment Services,CN=Public Key Services,CN=Services,CN=Configuration,DC=CONTOSO,DC=LOCAL".to_string(),
+            attrs: HashMap::new(),
+            bin_attrs: HashMap::from([("cACertificate".to_string(), vec![b"garbage".to_vec(), base64::engine::general_purpose::STANDARD.decode(ISSUING_CA).unwrap()])]),
+        };
+        let mut ca = EnterpriseCA::new();
+        ca.parse(entry, "contoso.local", &mut HashMap::new(), &mut HashMap::new(), "S-1-5-21-1-2-3").unwrap();
+        assert!(serde_json::to_value(&ca).unwrap()["Properties"]["certparsefailure"].is_null());
+    }
+
+    #[test]
+    pub fn test_enterpriseca_builder() {
+        let ca = EnterpriseCA::builder("5A6B6E0C-6A8B-4E3E-9B2D-1C2D3E4F5A6B")
+            .domain("CONTOSO.LOCAL", "S-1-5-21-1-2-3")
+            .caname("contoso-DC01-CA")
+            .dnshostname("dc01.contoso.local")
+            .hosting_computer("S-1-5-21-1-2-3-1000")
+            .certthumbprint("A1B2C3")
+            .basic_constraints(true, 0)
+            .validity(1792153129, 2107513129)
+            .public_key("1.2.840.113549.1.1.1", Some(4096), None)
+            .enabled_cert_templates(&["7E1A7D64-0000-0000-0000-000000000001"])
+            .edit_flags(0x00040000)
+            .build();
+        assert_eq!(ca.get_object_identifier(), "5A6B6E0C-6A8B-4E3E-9B2D-1C2D3E4F5A6B");
+        assert_eq!(ca.dnshostname(), "dc01.contoso.local");
+        let json = ca.to_json();
+        assert_eq!(json["Properties"]["name"], "CONTOSO-DC01-CA@CONTOSO.LOCAL");
+        assert_eq!(json["Properties"]["certname"], "A1B2C3");
+        assert_eq!(json["Properties"]["certchain"], serde_json::json!(["A1B2C3"]));
+        assert_eq!(json["HostingComputer"], "S-1-5-21-1-2-3-1000");
+        assert_eq!(json["Properties"]["publickeysize"], 4096);
+        assert_eq!(json["EnabledCertTemplates"][0]["ObjectType"], "CertTemplate");
+        assert_eq!(json["CARegistryData"]["IsUserSpecifiesSanEnabled"]["Value"], true);
+        // Not set: defaulted
+        assert_eq!(json["Properties"]["isaclprotected"], false);
+        assert_eq!(json["CARegistryData"]["CASecurity"]["Collected"], false);
+        assert_eq!(json["CARegistryData"]["RoleSeparationEnabled"]["Collected"], false);
+        assert!(json["CARegistryData"]["RoleSeparationEnabled"]["FailureReason"].as_str().unwrap().contains("dc01.contoso.local"));
+    }
+
+    #[test]
+    pub fn test_hosting_computer_admin_rids() {
+        let domain_sid = "S-1-5-21-1004336348-1177238915-682003330";
+        let hosting_computer = |rid: &str| {
+            // ManageCertificates
+            let sddl = format!("O:BAD:(A;;0x2;;;{domain_sid}-{rid})");
+            let nt = sddl_to_security_descriptor(&sddl, domain_sid, false).unwrap();
+            EnterpriseCA::get_hosting_computer(&nt, "contoso.local")
+        };
+        // Domain Admins, not a computer
+        assert_eq!(hosting_computer("512"), "Not found");
+        // Same suffix, other RIDs
+        assert_eq!(hosting_computer("11512"), format!("{domain_sid}-11512"));
+        assert_eq!(hosting_computer("2512"), format!("{domain_sid}-2512"));
+        assert_eq!(hosting_computer("1105"), format!("{domain_sid}-1105"));
+    }
+}
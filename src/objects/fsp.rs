@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use ldap3::SearchEntry;
 use log::{debug, trace};
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::RustHoundError;
 
 use crate::enums::regex::OBJECT_SID_RE1;
 use crate::objects::common::{LdapObject, AceTemplate, SPNTarget, Link, Member};
@@ -34,6 +34,11 @@ impl Fsp {
         Self { ..Default::default() } 
     }
 
+    // Mutable access.
+    pub fn properties_mut(&mut self) -> &mut FspProperties {
+        &mut self.properties
+    }
+
     /// Function to parse and replace value in json template for ForeignSecurityPrincipal object.
     pub fn parse(
         &mut self,
@@ -41,7 +46,7 @@ impl Fsp {
         domain: &str,
         dn_sid: &mut HashMap<String, String>,
         sid_type: &mut HashMap<String, String>,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), RustHoundError> {
         let result_dn: String = result.dn.to_uppercase();
         let result_attrs: HashMap<String, Vec<String>> = result.attrs;
         let result_bin: HashMap<String, Vec<Vec<u8>>> = result.bin_attrs;
@@ -101,15 +106,28 @@ impl Fsp {
                         self.properties.domainsid = domain_sid[0].to_owned().to_string();
                     }
                 }
-                "IsDeleted" => {
+                "isDeleted" => {
                     self.is_deleted = true;
                 }
                 _ => {}
             }
         }
 
+        // For all, bins attributs
+        for (key, value) in &result_bin {
+            if key.as_str() == "objectSid" {
+                // objectSid raw to string, the SID of the principal in its own domain
+                let sid = sid_maker(LdapSid::parse(&value[0]).unwrap().1, domain);
+                self.object_identifier = sid.to_owned();
+
+                for domain_sid in OBJECT_SID_RE1.captures_iter(&sid) {
+                    self.properties.domainsid = domain_sid[0].to_owned().to_string();
+                }
+            }
+        }
+
         // Push DN and SID in HashMap
-        if self.object_identifier != "SID" {
+        if !self.object_identifier.is_empty() && self.object_identifier != "SID" {
             dn_sid.insert(
                 self.properties.distinguishedname.to_string(),
                 self.object_identifier.to_string()
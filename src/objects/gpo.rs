@@ -3,11 +3,11 @@ use serde::{Deserialize, Serialize};
 use ldap3::SearchEntry;
 use log::{debug, trace};
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::RustHoundError;
 
 use crate::objects::common::{LdapObject, AceTemplate, Link, SPNTarget, Member};
 use crate::enums::decode_guid_le;
-use crate::enums::acl::parse_ntsecuritydescriptor;
+use crate::enums::acl::{dedup_aces, parse_ntsecuritydescriptor};
 use crate::utils::date::string_to_epoch;
 
 /// Gpo structure
@@ -44,7 +44,7 @@ impl Gpo {
         dn_sid: &mut HashMap<String, String>,
         sid_type: &mut HashMap<String, String>,
         domain_sid: &str
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), RustHoundError> {
         let result_dn: String = result.dn.to_uppercase();
         let result_attrs: HashMap<String, Vec<String>> = result.attrs;
         let result_bin: HashMap<String, Vec<Vec<u8>>> = result.bin_attrs;
@@ -86,7 +86,7 @@ impl Gpo {
                 "gPCFileSysPath" => {
                     self.properties.gpcpath = value[0].to_owned();
                 }
-                "IsDeleted" => {
+                "isDeleted" => {
                     self.is_deleted = true;
                 }
                 _ => {}
@@ -110,7 +110,7 @@ impl Gpo {
                         &result_bin,
                         domain,
                     );
-                    self.aces = relations_ace;
+                    self.aces = dedup_aces(relations_ace);
                 }
                 _ => {}
             }
@@ -202,6 +202,12 @@ impl LdapObject for Gpo {
     fn set_child_objects(&mut self, _child_objects: Vec<Member>) {
         // Not used by current object.
     }
+    fn set_ace_deviation(&mut self, count: u32) {
+        self.properties.acedeviation = Some(count);
+    }
+    fn set_custom_properties(&mut self, properties: serde_json::Map<String, serde_json::Value>) {
+        self.properties.custom.extend(properties);
+    }
 }
 
 // Gpo properties structure
@@ -215,5 +221,9 @@ pub struct GpoProperties {
    highvalue: bool,
    description: Option<String>,
    whencreated: i64,
-   gpcpath: String
+   gpcpath: String,
+   #[serde(skip_serializing_if = "Option::is_none")]
+   acedeviation: Option<u32>,
+   #[serde(flatten)]
+   custom: serde_json::Map<String, serde_json::Value>,
 }
\ No newline at end of file
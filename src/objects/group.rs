@@ -3,13 +3,13 @@ use serde_json::value::Value;
 use ldap3::SearchEntry;
 use log::{debug, trace};
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::RustHoundError;
 
 use crate::enums::regex::OBJECT_SID_RE1;
 use crate::objects::common::{LdapObject, AceTemplate, SPNTarget, Link, Member};
-use crate::enums::acl::parse_ntsecuritydescriptor;
+use crate::enums::acl::{dedup_aces, parse_ntsecuritydescriptor};
 use crate::enums::secdesc::LdapSid;
-use crate::enums::sid::{objectsid_to_vec8, sid_maker};
+use crate::enums::sid::{objectsid_to_vec8, sid_maker, is_highvalue_sid};
 use crate::utils::date::string_to_epoch;
 
 /// Group structure
@@ -62,7 +62,7 @@ impl Group {
         dn_sid: &mut HashMap<String, String>,
         sid_type: &mut HashMap<String, String>,
         domain_sid: &str,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), RustHoundError> {
         let result_dn: String = result.dn.to_uppercase();
         let result_attrs: HashMap<String, Vec<String>> = result.attrs;
         let result_bin: HashMap<String, Vec<Vec<u8>>> = result.bin_attrs;
@@ -133,22 +133,7 @@ impl Group {
                     }*/
 
                     // highvalue
-                    if sid.ends_with("-512") 
-                        || sid.ends_with("-516") 
-                        || sid.ends_with("-519") 
-                        || sid.ends_with("-520") 
-                    {
-                        self.properties.highvalue = true;
-                    } else if sid.ends_with("S-1-5-32-544") 
-                        || sid.ends_with("S-1-5-32-548") 
-                        || sid.ends_with("S-1-5-32-549")
-                        || sid.ends_with("S-1-5-32-550") 
-                        || sid.ends_with("S-1-5-32-551")
-                    {
-                        self.properties.highvalue = true;
-                    } else {
-                        self.properties.highvalue = false;
-                    }
+                    self.properties.highvalue = is_highvalue_sid(&sid);
                 }
                 "whenCreated" => {
                     let epoch = string_to_epoch(&value[0])?;
@@ -156,7 +141,7 @@ impl Group {
                         self.properties.whencreated = epoch;
                     }
                 }
-                "IsDeleted" => {
+                "isDeleted" => {
                     self.is_deleted = true;
                 }
                 _ => {}
@@ -176,24 +161,7 @@ impl Group {
                     }
     
                     // highvalue
-                    if sid.ends_with("-512") 
-                        || sid.ends_with("-516") 
-                        || sid.ends_with("-519") 
-                        || sid.ends_with("-520") 
-                    {
-                        self.properties.highvalue = true;
-                    }
-                    else if sid.ends_with("S-1-5-32-544") 
-                        || sid.ends_with("S-1-5-32-548") 
-                        || sid.ends_with("S-1-5-32-549")
-                        || sid.ends_with("S-1-5-32-550") 
-                        || sid.ends_with("S-1-5-32-551") 
-                    {
-                        self.properties.highvalue = true;
-                    }
-                    else {
-                        self.properties.highvalue = false;
-                    }
+                    self.properties.highvalue = is_highvalue_sid(&sid);
                 }
                 "nTSecurityDescriptor" => {
                     // nTSecurityDescriptor raw to string
@@ -205,7 +173,7 @@ impl Group {
                         &result_bin,
                         domain,
                     );
-                    self.aces = relations_ace;
+                    self.aces = dedup_aces(relations_ace);
                 }
                 _ => {}
             }
@@ -297,6 +265,12 @@ impl LdapObject for Group {
     fn set_child_objects(&mut self, _child_objects: Vec<Member>) {
         // Not used by current object.
     }
+    fn set_ace_deviation(&mut self, count: u32) {
+        self.properties.acedeviation = Some(count);
+    }
+    fn set_custom_properties(&mut self, properties: serde_json::Map<String, serde_json::Value>) {
+        self.properties.custom.extend(properties);
+    }
 }
 
 // Group properties structure
@@ -312,6 +286,10 @@ pub struct GroupProperties {
     description: Option<String>,
     whencreated: i64,
     admincount: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acedeviation: Option<u32>,
+    #[serde(flatten)]
+    custom: serde_json::Map<String, serde_json::Value>,
 }
 
 impl GroupProperties {
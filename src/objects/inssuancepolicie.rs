@@ -3,9 +3,9 @@ use serde::{Deserialize, Serialize};
 use ldap3::SearchEntry;
 use log::{debug, trace};
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::RustHoundError;
 
-use crate::enums::{decode_guid_le, parse_ntsecuritydescriptor};
+use crate::enums::{decode_guid_le, dedup_aces, parse_ntsecuritydescriptor};
 use crate::utils::date::string_to_epoch;
 use crate::objects::common::{LdapObject, AceTemplate, SPNTarget, Link, Member};
 
@@ -44,7 +44,7 @@ impl IssuancePolicie {
         dn_sid: &mut HashMap<String, String>,
         sid_type: &mut HashMap<String, String>,
         domain_sid: &str
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), RustHoundError> {
         let result_dn: String = result.dn.to_uppercase();
         let result_attrs: HashMap<String, Vec<String>> = result.attrs;
         let result_bin: HashMap<String, Vec<Vec<u8>>> = result.bin_attrs;
@@ -78,7 +78,7 @@ impl IssuancePolicie {
                         self.properties.whencreated = epoch;
                     }
                 }
-                "IsDeleted" => {
+                "isDeleted" => {
                     self.is_deleted = true;
                 }
                 "displayName" => {
@@ -110,7 +110,7 @@ impl IssuancePolicie {
                          &result_bin,
                          domain,
                     );
-                    self.aces = relations_ace;
+                    self.aces = dedup_aces(relations_ace);
                 }
                 _ => {}
             }
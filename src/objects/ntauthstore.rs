@@ -3,10 +3,10 @@ use serde::{Deserialize, Serialize};
 use ldap3::SearchEntry;
 use log::{debug, trace};
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::RustHoundError;
 
 use crate::objects::common::{LdapObject, AceTemplate, SPNTarget, Link, Member};
-use crate::enums::{decode_guid_le, parse_ntsecuritydescriptor};
+use crate::enums::{decode_guid_le, dedup_aces, parse_ntsecuritydescriptor};
 use crate::utils::date::string_to_epoch;
 use crate::utils::crypto::calculate_sha1;
 
@@ -35,6 +35,27 @@ impl NtAuthStore {
         Self { ..Default::default() } 
     }
 
+    /// Domain of the NTAuthCertificates object.
+    pub fn domain(&self) -> &str {
+        &self.properties.domain
+    }
+
+    /// Domain SID of the NTAuthStoreFor edge, `DOMAIN_SID` when parsed before its domain object.
+    pub fn domain_sid(&self) -> &str {
+        &self.domain_sid
+    }
+
+    /// Function to set the domain SID once the domain object is parsed.
+    pub fn set_domain_sid(&mut self, domain_sid: &str) {
+        self.domain_sid = domain_sid.to_string();
+        self.properties.domainsid = domain_sid.to_string();
+    }
+
+    /// SHA1 thumbprints of the CA certificates trusted for the NT authentication, matched with the EnterpriseCA ones.
+    pub fn certthumbprints(&self) -> &[String] {
+        &self.properties.certthumbprints
+    }
+
     /// Function to parse and replace value in json template for NT Auth Store object.
     pub fn parse(
         &mut self,
@@ -43,7 +64,7 @@ impl NtAuthStore {
         dn_sid: &mut HashMap<String, String>,
         sid_type: &mut HashMap<String, String>,
         domain_sid: &str
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), RustHoundError> {
         let result_dn: String = result.dn.to_uppercase();
         let result_attrs: HashMap<String, Vec<String>> = result.attrs;
         let result_bin: HashMap<String, Vec<Vec<u8>>> = result.bin_attrs;
@@ -82,7 +103,7 @@ impl NtAuthStore {
                         self.properties.whencreated = epoch;
                     }
                 }
-                "IsDeleted" => {
+                "isDeleted" => {
                     self.is_deleted = true;
                 }
                 _ => {}
@@ -106,11 +127,19 @@ impl NtAuthStore {
                         &result_bin,
                         domain,
                     );
-                    self.aces = relations_ace;
+                    self.aces = dedup_aces(relations_ace);
                 }
                 "cACertificate" => {
-                    //info!("{:?}:{:?}", key,value[0].to_owned());
-                    self.properties.certthumbprints = vec![calculate_sha1(&value[0])];
+                    // One value per CA trusted for the NT authentication, a single null byte once the store is emptied
+                    self.properties.certthumbprints = value.iter()
+                        .filter(|cert| cert.len() > 1)
+                        .map(|cert| calculate_sha1(cert))
+                        .fold(Vec::new(), |mut thumbprints, thumbprint| {
+                            if !thumbprints.contains(&thumbprint) {
+                                thumbprints.push(thumbprint);
+                            }
+                            thumbprints
+                        });
                 }
                 _ => {}
             }
@@ -218,4 +247,58 @@ pub struct NtAuthStoreProperties {
    certthumbprints: Vec<String>,
    description: Option<String>,
    whencreated: i64,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use base64::Engine;
+    use ldap3::SearchEntry;
+
+    use crate::objects::ntauthstore::NtAuthStore;
+    use crate::objects::common::LdapObject;
+    use crate::utils::crypto::calculate_sha1;
+    use crate::utils::crypto::tests::{CERTIFICATE, ISSUING_CA};
+
+    fn nt_auth_store_entry(certificates: Vec<Vec<u8>>) -> SearchEntry {
+        SearchEntry {
+            dn: "CN=NTAuthCertificates,CN=Public Key Services,CN=Services,CN=Configuration,DC=CONTOSO,DC=LOCAL".to_string(),
+            attrs: HashMap::from([
+                ("name".to_string(), vec!["NTAuthCertificates".to_string()]),
+                ("whenCreated".to_string(), vec!["20240101120000.0Z".to_string()]),
+            ]),
+            bin_attrs: HashMap::from([
+                ("objectGUID".to_string(), vec![vec![0x1D, 0x2C, 0x3B, 0x4A, 0x59, 0x68, 0x77, 0x46, 0x95, 0xA4, 0xB3, 0xC2, 0xD1, 0xE0, 0xF1, 0x02]]),
+                ("cACertificate".to_string(), certificates),
+            ]),
+        }
+    }
+
+    #[test]
+    pub fn test_ntauthstore_parse() {
+        let certificates: Vec<Vec<u8>> = [CERTIFICATE, ISSUING_CA, CERTIFICATE].iter()
+            .map(|cert| base64::engine::general_purpose::STANDARD.decode(cert).unwrap())
+            .collect();
+        let mut dn_sid = HashMap::new();
+        let mut sid_type = HashMap::new();
+        let mut nt_auth_store = NtAuthStore::new();
+        nt_auth_store.parse(nt_auth_store_entry(certificates.to_owned()), "contoso.local", &mut dn_sid, &mut sid_type, "S-1-5-21-1-2-3").unwrap();
+        assert_eq!(sid_type.get("4A3B2C1D-6859-4677-95A4-B3C2D1E0F102").map(String::as_str), Some("NtAuthStore"));
+
+        // Every stored certificate, once
+        assert_eq!(nt_auth_store.certthumbprints(), [calculate_sha1(&certificates[0]), calculate_sha1(&certificates[1])]);
+        let json = nt_auth_store.to_json();
+        assert_eq!(json["ObjectIdentifier"], "4A3B2C1D-6859-4677-95A4-B3C2D1E0F102");
+        assert_eq!(json["DomainSID"], "S-1-5-21-1-2-3");
+        assert_eq!(json["Properties"]["domainsid"], "S-1-5-21-1-2-3");
+        assert_eq!(json["Properties"]["name"], "NTAUTHCERTIFICATES@CONTOSO.LOCAL");
+        assert_eq!(json["Properties"]["certthumbprints"].as_array().unwrap().len(), 2);
+
+        // Emptied store: a single null byte
+        let mut nt_auth_store = NtAuthStore::new();
+        nt_auth_store.parse(nt_auth_store_entry(vec![vec![0]]), "contoso.local", &mut dn_sid, &mut sid_type, "DOMAIN_SID").unwrap();
+        assert!(nt_auth_store.certthumbprints().is_empty());
+        nt_auth_store.set_domain_sid("S-1-5-21-1-2-3");
+        assert_eq!(nt_auth_store.to_json()["DomainSID"], "S-1-5-21-1-2-3");
+    }
+}
@@ -3,10 +3,10 @@ use serde::{Deserialize, Serialize};
 use ldap3::SearchEntry;
 use log::{debug, trace};
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::RustHoundError;
 
 use crate::objects::common::{LdapObject, AceTemplate, GPOChange, Link, SPNTarget, Member};
-use crate::enums::acl::parse_ntsecuritydescriptor;
+use crate::enums::acl::{dedup_aces, parse_ntsecuritydescriptor};
 use crate::enums::gplink::parse_gplink;
 use crate::enums::sid::decode_guid_le;
 use crate::utils::date::string_to_epoch;
@@ -52,6 +52,9 @@ impl Ou {
     pub fn child_objects_mut(&mut self) -> &mut Vec<Member> {
         &mut self.child_objects
     }
+    pub fn object_identifier_mut(&mut self) -> &mut String {
+        &mut self.object_identifier
+    }
 
     /// Function to parse and replace value for OU object.
     /// <https://bloodhound.readthedocs.io/en/latest/further-reading/json.html#ous>
@@ -62,7 +65,7 @@ impl Ou {
         dn_sid: &mut HashMap<String, String>,
         sid_type: &mut HashMap<String, String>,
         domain_sid: &str
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), RustHoundError> {
         let result_dn: String = result.dn.to_uppercase();
         let result_attrs: HashMap<String, Vec<String>> = result.attrs;
         let result_bin: HashMap<String, Vec<Vec<u8>>> = result.bin_attrs;
@@ -104,10 +107,10 @@ impl Ou {
                  "gPLink" => {
                      self.links = parse_gplink(value[0].to_string())?;
                  }
-                 "gPOtions" => {
+                 "gPOptions" => {
                      self.properties.blocksinheritance = value[0].parse::<i64>().unwrap_or(0) == 1;
                  }
-                 "IsDeleted" => {
+                 "isDeleted" => {
                      self.is_deleted = true;
                  }
                  _ => {}
@@ -132,7 +135,7 @@ impl Ou {
                           &result_bin,
                           domain,
                      );
-                     self.aces = relations_ace;
+                     self.aces = dedup_aces(relations_ace);
                  }
                  _ => {}
              }
@@ -223,6 +226,12 @@ impl LdapObject for Ou {
     fn set_child_objects(&mut self, child_objects: Vec<Member>) {
         self.child_objects = child_objects
     }
+    fn set_ace_deviation(&mut self, count: u32) {
+        self.properties.acedeviation = Some(count);
+    }
+    fn set_custom_properties(&mut self, properties: serde_json::Map<String, serde_json::Value>) {
+        self.properties.custom.extend(properties);
+    }
 }
 
 // Ou properties structure
@@ -236,7 +245,11 @@ pub struct OuProperties {
     highvalue: bool,
     description: Option<String>,
     whencreated: i64,
-    blocksinheritance: bool
+    blocksinheritance: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acedeviation: Option<u32>,
+    #[serde(flatten)]
+    custom: serde_json::Map<String, serde_json::Value>,
 }
 
 impl OuProperties {
@@ -1,16 +1,14 @@
 use serde_json::value::Value;
 use serde::{Deserialize, Serialize};
-use x509_parser::oid_registry::asn1_rs::oid;
-use x509_parser::prelude::*;
 use ldap3::SearchEntry;
 use log::{debug, error, trace};
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::RustHoundError;
 
 use crate::objects::common::{LdapObject, AceTemplate, SPNTarget, Link, Member};
-use crate::enums::{decode_guid_le, parse_ntsecuritydescriptor};
+use crate::enums::{decode_guid_le, dedup_aces, parse_ntsecuritydescriptor};
 use crate::utils::date::string_to_epoch;
-use crate::utils::crypto::calculate_sha1;
+use crate::utils::crypto::{basic_constraints, calculate_sha1, certificate_parse_failure, current_certificate};
 
 
 /// RootCA structure
@@ -38,6 +36,22 @@ impl RootCA {
         Self { ..Default::default() } 
     }
 
+    /// Domain of the RootCA, in uppercase.
+    pub fn domain(&self) -> &str {
+        &self.properties.domain
+    }
+
+    /// Domain SID of the RootCAFor edge, `DOMAIN_SID` when parsed before its domain object.
+    pub fn domain_sid(&self) -> &str {
+        &self.domain_sid
+    }
+
+    /// Function to set the domain SID once the domain object is parsed.
+    pub fn set_domain_sid(&mut self, domain_sid: &str) {
+        self.domain_sid = domain_sid.to_string();
+        self.properties.domainsid = domain_sid.to_string();
+    }
+
     /// Function to parse and replace value in json template for ROOT CA object.
     pub fn parse(
         &mut self,
@@ -46,7 +60,7 @@ impl RootCA {
         dn_sid: &mut HashMap<String, String>,
         sid_type: &mut HashMap<String, String>,
         domain_sid: &str
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), RustHoundError> {
         let result_dn: String = result.dn.to_uppercase();
         let result_attrs: HashMap<String, Vec<String>> = result.attrs;
         let result_bin: HashMap<String, Vec<Vec<u8>>> = result.bin_attrs;
@@ -85,7 +99,7 @@ impl RootCA {
                         self.properties.whencreated = epoch;
                     }
                 }
-                "IsDeleted" => {
+                "isDeleted" => {
                     self.is_deleted = true;
                 }
                 _ => {}
@@ -109,50 +123,29 @@ impl RootCA {
                         &result_bin,
                         domain,
                     );
-                    self.aces = relations_ace;
+                    self.aces = dedup_aces(relations_ace);
                 }
                 "cACertificate" => {
-                    //info!("{:?}:{:?}", key,value[0].to_owned());
-                    let certsha1: String = calculate_sha1(&value[0]);
-                    self.properties.certthumbprint = certsha1.to_string();
-                    self.properties.certname = certsha1.to_string();
-                    self.properties.certchain = vec![certsha1.to_string()];
+                    // A renewed CA publishes one certificate per key, the chain lists them all
+                    self.properties.certchain = value.iter().map(|cert| calculate_sha1(cert)).collect();
+                    let current = current_certificate(value);
+                    let certsha1: String = calculate_sha1(&value[current.as_ref().map_or(0, |(index, _)| *index)]);
+                    self.properties.certthumbprint = certsha1.to_owned();
+                    self.properties.certname = certsha1.to_owned();
 
                     // Parsing certificate.
-                    let res = X509Certificate::from_der(&value[0]);
-                    match res {
-                        Ok((_rem, cert)) => {
-                            // println!("Basic Constraints Extensions:");
-                            for ext in cert.extensions() {
-                                // println!("{:?} : {:?}",&ext.oid, ext);
-                                if &ext.oid == &oid!(2.5.29.19) {
-                                    // <https://docs.rs/x509-parser/latest/x509_parser/extensions/struct.BasicConstraints.html>
-                                    if let ParsedExtension::BasicConstraints(basic_constraints) = &ext.parsed_extension() {
-                                        let _ca = &basic_constraints.ca;
-                                        let _path_len_constraint = &basic_constraints.path_len_constraint;
-                                        // println!("ca: {:?}", _ca);
-                                        // println!("path_len_constraint: {:?}", _path_len_constraint);
-                                        match _path_len_constraint {
-                                            Some(_path_len_constraint) => {
-                                                if _path_len_constraint > &0 {
-                                                    self.properties.hasbasicconstraints = true;
-                                                    self.properties.basicconstraintpathlength = _path_len_constraint.to_owned();
-
-                                                } else {
-                                                    self.properties.hasbasicconstraints = false;
-                                                    self.properties.basicconstraintpathlength = 0_u32;
-                                                }
-                                            },
-                                            None => {
-                                                self.properties.hasbasicconstraints = false;
-                                                self.properties.basicconstraintpathlength = 0_u32;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                    match current {
+                        Some((_index, cert)) => {
+                            // <https://docs.rs/x509-parser/latest/x509_parser/extensions/struct.BasicConstraints.html>
+                            let (ca, path_length) = basic_constraints(&cert);
+                            self.properties.hasbasicconstraints = ca;
+                            self.properties.basicconstraintpathlength = path_length;
                         },
-                        _ => error!("CA x509 certificate parsing failed: {:?}", res),
+                        None => {
+                            let failure = certificate_parse_failure(value);
+                            error!("{failure} for {}", self.properties.distinguishedname);
+                            self.properties.certparsefailure = Some(failure);
+                        }
                     }
                 }
                 _ => {}
@@ -265,6 +258,8 @@ pub struct RootCAProperties {
    certchain: Vec<String>,
    hasbasicconstraints: bool,
    basicconstraintpathlength: u32,
+   /// Error of the CA certificate when none of its certificates could be decoded, none once parsed.
+   certparsefailure: Option<String>,
 }
 
 impl Default for RootCAProperties {
@@ -282,6 +277,89 @@ impl Default for RootCAProperties {
             certchain: Vec::new(),
             hasbasicconstraints: false,
             basicconstraintpathlength: 0,
+            certparsefailure: None,
        }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use base64::Engine;
+    use ldap3::SearchEntry;
+
+    use crate::objects::rootca::RootCA;
+    use crate::objects::common::LdapObject;
+    use crate::utils::crypto::calculate_sha1;
+    use crate::utils::crypto::tests::{CERTIFICATE, ISSUING_CA};
+
+    fn root_ca_entry(certificates: Vec<Vec<u8>>) -> SearchEntry {
+        SearchEntry {
+            dn: "CN=contoso-DC01-CA,CN=Certification Authorities,CN=Public Key Services,CN=Services,CN=Configuration,DC=CONTOSO,DC=LOCAL".to_string(),
+            attrs: HashMap::from([
+                ("name".to_string(), vec!["contoso-DC01-CA".to_string()]),
+                ("whenCreated".to_string(), vec!["20240101120000.0Z".to_string()]),
+            ]),
+            bin_attrs: HashMap::from([
+                ("objectGUID".to_string(), vec![vec![0x0C, 0x6E, 0x6B, 0x5A, 0x8B, 0x6A, 0x3E, 0x4E, 0x9B, 0x2D, 0x1C, 0x2D, 0x3E, 0x4F, 0x5A, 0x6B]]),
+                ("cACertificate".to_string(), certificates),
+            ]),
+        }
+    }
+
+    #[test]
+    pub fn test_rootca_serialization() {
+        let certificates: Vec<Vec<u8>> = [CERTIFICATE, ISSUING_CA].iter()
+            .map(|cert| base64::engine::general_purpose::STANDARD.decode(cert).unwrap())
+            .collect();
+        let mut dn_sid = HashMap::new();
+        let mut sid_type = HashMap::new();
+        let mut root_ca = RootCA::new();
+        root_ca.parse(root_ca_entry(certificates.to_owned()), "contoso.local", &mut dn_sid, &mut sid_type, "S-1-5-21-1-2-3").unwrap();
+        assert_eq!(sid_type.get("5A6B6E0C-6A8B-4E3E-9B2D-1C2D3E4F5A6B").map(String::as_str), Some("RootCA"));
+
+        let json = root_ca.to_json();
+        assert_eq!(json["ObjectIdentifier"], "5A6B6E0C-6A8B-4E3E-9B2D-1C2D3E4F5A6B");
+        // DomainSID of the RootCAFor edge
+        assert_eq!(json["DomainSID"], "S-1-5-21-1-2-3");
+        assert_eq!(json["Properties"]["domainsid"], "S-1-5-21-1-2-3");
+        assert_eq!(json["Properties"]["domain"], "CONTOSO.LOCAL");
+        assert_eq!(json["Properties"]["name"], "CONTOSO-DC01-CA@CONTOSO.LOCAL");
+        assert_eq!(json["Properties"]["certthumbprint"], calculate_sha1(&certificates[1]));
+        assert_eq!(json["Properties"]["certname"], json["Properties"]["certthumbprint"]);
+        assert_eq!(json["Properties"]["certchain"], serde_json::json!([calculate_sha1(&certificates[0]), calculate_sha1(&certificates[1])]));
+        assert_eq!(json["Properties"]["hasbasicconstraints"], true);
+        assert!(json["Properties"]["certparsefailure"].is_null());
+        assert_eq!(json["Aces"], serde_json::json!([]));
+        assert_eq!(json["IsACLProtected"], false);
+        assert_eq!(json["IsDeleted"], false);
+        assert!(json["ContainedBy"].is_null());
+
+        // Same JSON once read back
+        let read: RootCA = serde_json::from_value(json.to_owned()).unwrap();
+        assert_eq!(read.to_json(), json);
+    }
+
+    #[test]
+    pub fn test_rootca_malformed_certificate() {
+        let mut root_ca = RootCA::new();
+        root_ca.parse(root_ca_entry(vec![vec![0x30, 0x82, 0x01]]), "contoso.local", &mut HashMap::new(), &mut HashMap::new(), "S-1-5-21-1-2-3").unwrap();
+        let json = root_ca.to_json();
+        assert_eq!(json["Properties"]["certthumbprint"], calculate_sha1(&[0x30, 0x82, 0x01]));
+        assert!(json["Properties"]["certparsefailure"].as_str().unwrap().starts_with("Failed to parse the CA certificate"));
+        assert_eq!(json["Properties"]["hasbasicconstraints"], false);
+    }
+
+    #[test]
+    pub fn test_rootca_parsed_before_domain() {
+        let certificate = base64::engine::general_purpose::STANDARD.decode(ISSUING_CA).unwrap();
+        let mut root_ca = RootCA::new();
+        root_ca.parse(root_ca_entry(vec![certificate]), "contoso.local", &mut HashMap::new(), &mut HashMap::new(), "DOMAIN_SID").unwrap();
+        assert_eq!(root_ca.domain(), "CONTOSO.LOCAL");
+        assert_eq!(root_ca.domain_sid(), "DOMAIN_SID");
+        root_ca.set_domain_sid("S-1-5-21-1-2-3");
+        assert_eq!(root_ca.to_json()["DomainSID"], "S-1-5-21-1-2-3");
+        assert_eq!(root_ca.to_json()["Properties"]["domainsid"], "S-1-5-21-1-2-3");
+    }
+}
@@ -1,7 +1,7 @@
 use ldap3::SearchEntry;
 use log::{debug, trace};
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::RustHoundError;
 use serde::{Deserialize, Serialize};
 
 use crate::enums::secdesc::LdapSid;
@@ -25,6 +25,9 @@ pub struct Trust {
    trust_direction: String,
    #[serde(rename = "TrustType")]
    trust_type: String,
+   /// Domain holding the trust, several domains with --forest.
+   #[serde(skip)]
+   domain: String,
 }
 
 impl Trust {
@@ -42,6 +45,9 @@ impl Trust {
    pub fn target_domain_name(&self) -> &String {
       &self.target_domain_name
    }
+   pub fn domain(&self) -> &String {
+      &self.domain
+   }
 
    // Mutable access.
    pub fn is_transitive_mut(&mut self) -> &mut bool {
@@ -59,13 +65,14 @@ impl Trust {
       &mut self,
       result: SearchEntry,
       domain: &str
-   ) -> Result<(), Box<dyn Error>> {
+   ) -> Result<(), RustHoundError> {
       let result_dn: String = result.dn.to_uppercase();
       let result_attrs: HashMap<String, Vec<String>> = result.attrs;
       let result_bin: HashMap<String, Vec<Vec<u8>>> = result.bin_attrs;
 
       // Debug for current object
       debug!("Parse TrustDomain: {result_dn}");
+      self.domain = domain.to_uppercase();
 
       // Trace all result attributes
       for (key, value) in &result_attrs {
@@ -4,17 +4,18 @@ use ldap3::SearchEntry;
 use log::{debug, error, trace};
 use std::collections::HashMap;
 use x509_parser::prelude::*;
-use std::error::Error;
+use crate::error::RustHoundError;
 
 use crate::enums::regex::{OBJECT_SID_RE1, SID_PART1_RE1};
 use crate::objects::common::{LdapObject, AceTemplate, SPNTarget, Link, Member};
 use crate::utils::date::{convert_timestamp, string_to_epoch};
 use crate::utils::crypto::convert_encryption_types;
-use crate::enums::acl::{parse_ntsecuritydescriptor, parse_gmsa};
+use crate::enums::acl::{dedup_aces, parse_ntsecuritydescriptor, parse_gmsa};
 use crate::enums::secdesc::LdapSid;
 use crate::enums::sid::sid_maker;
 use crate::enums::spntasks::check_spn;
 use crate::enums::uacflags::get_flag;
+use crate::json::checker::honeypot::HoneypotFacts;
 
 /// User structure
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
@@ -58,6 +59,9 @@ impl User {
     pub fn aces(&self) -> &Vec<AceTemplate> {
         &self.aces
     }
+    pub fn unconstrained_delegation(&self) -> &bool {
+        &self.unconstrained_delegation
+    }
 
     // Mutable access.
     pub fn properties_mut(&mut self) -> &mut UserProperties {
@@ -69,6 +73,9 @@ impl User {
     pub fn object_identifier_mut(&mut self) -> &mut String {
         &mut self.object_identifier
     }
+    pub fn unconstrained_delegation_mut(&mut self) -> &mut bool {
+        &mut self.unconstrained_delegation
+    }
 
     /// Function to parse and replace value for user object.
     /// <https://bloodhound.readthedocs.io/en/latest/further-reading/json.html#users>
@@ -79,7 +86,7 @@ impl User {
         dn_sid: &mut HashMap<String, String>,
         sid_type: &mut HashMap<String, String>,
         domain_sid: &str
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), RustHoundError> {
         let result_dn: String = result.dn.to_uppercase();
         let result_attrs: HashMap<String, Vec<String>> = result.attrs;
         let result_bin: HashMap<String, Vec<Vec<u8>>> = result.bin_attrs;
@@ -130,9 +137,6 @@ impl User {
                 "unicodepwd" => {
                     self.properties.unicodepassword = value[0].to_owned();
                 }
-                "sfupassword" => {
-                    //self.properties.sfupassword = value[0].to_owned();
-                }
                 "displayName" => {
                     self.properties.displayname = value[0].to_owned();
                 }
@@ -207,6 +211,9 @@ impl User {
                     // *properties.allowedtodelegate = vec_members2.to_owned();
                     self.allowed_to_delegate = vec_members2;
                 }
+                "logonCount" => {
+                    self.properties.logoncount = value[0].parse::<i64>().unwrap_or(0);
+                }
                 "lastLogon" => {
                     let lastlogon = &value[0].parse::<i64>().unwrap_or(0);
                     if lastlogon.is_positive() {
@@ -259,7 +266,7 @@ impl User {
                 "primaryGroupID" => {
                     group_id = value[0].to_owned();
                 }
-                "IsDeleted" => {
+                "isDeleted" => {
                     // OID to use: 1.2.840.113556.1.4.417
                     // https://ldapwiki.com/wiki/IsDeleted
                     //trace!("isDeleted: {:?}",&value[0]);
@@ -267,6 +274,14 @@ impl User {
                 }
                 "msDS-SupportedEncryptionTypes" => {
                     self.properties.supportedencryptiontypes = convert_encryption_types(value[0].parse::<i32>().unwrap_or(0));
+                }
+                "objectClass" => {
+                    self.properties.gmsa = value.iter().any(|class| class == "msDS-GroupManagedServiceAccount");
+                    self.properties.dmsa = value.iter().any(|class| class == "msDS-DelegatedManagedServiceAccount");
+                }
+                "msDS-ManagedPasswordInterval" => {
+                    // Days between two password changes of a gMSA or a dMSA
+                    self.properties.managedpasswordinterval = value[0].parse::<i64>().ok();
                 }
                  _ => {}
             }
@@ -274,6 +289,7 @@ impl User {
 
         // For all, bins attributs
         let mut sid: String = "".to_owned();
+        let mut gmsa_aces: Vec<AceTemplate> = Vec::new();
         for (key, value) in &result_bin {
             match key.as_str() {
                 "objectSid" => {
@@ -294,7 +310,7 @@ impl User {
                         &result_bin,
                         domain,
                     );
-                    self.aces_mut().extend(relations_ace);
+                    self.aces_mut().extend(dedup_aces(relations_ace));
                 }
                 "sIDHistory" => {
                     // not tested! #tocheck
@@ -308,19 +324,8 @@ impl User {
                     self.properties.sidhistory = list_sid_history;
                 }
                 "msDS-GroupMSAMembership" => {
-                    // nTSecurityDescriptor raw to string
-                    let mut relations_ace = parse_ntsecuritydescriptor(
-                        self,
-                        &value[0],
-                        "User",
-                        &result_attrs,
-                        &result_bin,
-                        domain,
-                    );
-                    // Now add the new ACE wich who can read GMSA password
-                    // trace!("User ACES before GMSA: {:?}", self.aces());
-                    parse_gmsa(&mut relations_ace, self);
-                    // trace!("User ACES after GMSA: {:?}", self.aces());
+                    // Principals who can read the gMSA or dMSA password, after the ACEs of the nTSecurityDescriptor
+                    gmsa_aces = parse_gmsa(&value[0], domain);
                 }
                 "userCertificate" => {
                     // <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-adls/d66d1662-0b4f-44ab-a4c8-e788f3ae39cf>
@@ -335,6 +340,8 @@ impl User {
             }
         }
 
+        self.aces.extend(dedup_aces(gmsa_aces));
+
         // primaryGroupID if group_id is set
         #[allow(irrefutable_let_patterns)]
         if let id = group_id {
@@ -432,6 +439,12 @@ impl LdapObject for User {
     fn set_child_objects(&mut self, _child_objects: Vec<Member>) {
         // Not used by current object.
     }
+    fn set_ace_deviation(&mut self, count: u32) {
+        self.properties.acedeviation = Some(count);
+    }
+    fn set_custom_properties(&mut self, properties: serde_json::Map<String, serde_json::Value>) {
+        self.properties.custom.extend(properties);
+    }
 }
 
 /// User properties structure
@@ -471,11 +484,26 @@ pub struct UserProperties {
     admincount: bool,
     supportedencryptiontypes: Vec<String>,
     sidhistory: Vec<String>,
-    allowedtodelegate: Vec<String>
+    allowedtodelegate: Vec<String>,
+    gmsa: bool,
+    dmsa: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    managedpasswordinterval: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    honeypotscore: Option<u32>,
+    #[serde(skip_serializing)]
+    logoncount: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acedeviation: Option<u32>,
+    #[serde(flatten)]
+    custom: serde_json::Map<String, serde_json::Value>,
 }
 
 impl UserProperties {
     // Immutable access.
+    pub fn domain(&self) -> &String {
+        &self.domain
+    }
     pub fn name(&self) -> &String {
         &self.name
     }
@@ -485,6 +513,33 @@ impl UserProperties {
     pub fn isaclprotected(&self) -> &bool {
         &self.isaclprotected
     }
+    pub fn enabled(&self) -> &bool {
+        &self.enabled
+    }
+    pub fn honeypotscore(&self) -> &Option<u32> {
+        &self.honeypotscore
+    }
+    pub fn gmsa(&self) -> &bool {
+        &self.gmsa
+    }
+    pub fn dmsa(&self) -> &bool {
+        &self.dmsa
+    }
+
+    /// Values used by the honeypot analysis.
+    pub fn honeypot_facts(&self) -> HoneypotFacts {
+        HoneypotFacts {
+            whencreated: self.whencreated,
+            lastlogon: self.lastlogon,
+            lastlogontimestamp: self.lastlogontimestamp,
+            pwdlastset: self.pwdlastset,
+            logoncount: self.logoncount,
+            admincount: self.admincount,
+            hasspn: self.hasspn,
+            description: self.description.to_owned(),
+            ..Default::default()
+        }
+    }
 
     // Mutable access.
     pub fn name_mut(&mut self) -> &mut String {
@@ -496,4 +551,7 @@ impl UserProperties {
     pub fn isaclprotected_mut(&mut self) -> &mut bool {
         &mut self.isaclprotected
     }
+    pub fn honeypotscore_mut(&mut self) -> &mut Option<u32> {
+        &mut self.honeypotscore
+    }
 }
\ No newline at end of file
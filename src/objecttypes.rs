@@ -0,0 +1,167 @@
+//! Object types of the collection, `--skip` and `--only`
+//!
+//! `--skip users,gpos` collects every object type but the listed ones, `--only computers,enterprisecas` only the
+//! listed ones. The names are the ones of the output files, plus `fsps` and `trusts`.
+//!
+//! The objects of a type left out are not requested, unless their LDAP class is shared with a type collected: the
+//! `ntauthstores`, `aiacas` and `rootcas` are all `certificationAuthority` objects. The domain object is always
+//! requested for the SID of the domain. The entries of the types left out are not parsed, nor written: no file, or
+//! an empty one with `--empty-skipped`.
+//!
+//! The references to the objects not collected stay unresolved: a group member is written with its DN, an ACE
+//! principal or a `ContainedBy` with its SID and the default type.
+//!
+//! `--adcs-only` refreshes the AD CS objects only: the `CN=Public Key Services` container of the configuration
+//! partition (enrollment services, certification authorities, AIA, NTAuthCertificates, certificate templates and
+//! OIDs) and the domain object are requested, the CA, template and issuance policy files are written. The type of
+//! each principal of their ACEs is requested on the Global Catalog, one query per SID, like `--resolve-foreign`,
+//! and the computer of the `dNSHostName` of each enterprise CA for its `HostingComputer`. A host the Global Catalog
+//! doesn't know keeps the principal with `ManageCertificates`.
+
+
+use crate::enums::ldaptype::Type;
+
+/// Types of objects, in the order of the output files, with the LDAP filter of their objects.
+pub const OBJECT_TYPES: &[(&str, &str)] = &[
+    ("users", "(|(&(objectClass=user)(objectClass=person)(!(objectClass=computer)))(objectClass=msDS-GroupManagedServiceAccount)(objectClass=msDS-DelegatedManagedServiceAccount))"),
+    ("groups", "(objectClass=group)"),
+    ("computers", "(&(objectClass=computer)(!(objectClass=msDS-GroupManagedServiceAccount))(!(objectClass=msDS-DelegatedManagedServiceAccount)))"),
+    ("ous", "(objectClass=organizationalUnit)"),
+    ("domains", "(objectClass=domain)"),
+    ("gpos", "(objectClass=groupPolicyContainer)"),
+    ("fsps", "(objectClass=foreignSecurityPrincipal)"),
+    ("containers", "(&(objectClass=container)(!(objectClass=groupPolicyContainer)))"),
+    ("trusts", "(objectClass=trustedDomain)"),
+    ("ntauthstores", "(objectClass=certificationAuthority)"),
+    ("aiacas", "(objectClass=certificationAuthority)"),
+    ("rootcas", "(objectClass=certificationAuthority)"),
+    ("enterprisecas", "(objectClass=pKIEnrollmentService)"),
+    ("certtemplates", "(objectClass=pKICertificateTemplate)"),
+    ("issuancepolicies", "(objectClass=msPKI-Enterprise-Oid)"),
+];
+
+/// Types written by `--adcs-only`.
+pub const ADCS_TYPES: &[&str] = &["ntauthstores", "aiacas", "rootcas", "enterprisecas", "certtemplates", "issuancepolicies"];
+
+/// Function to parse a comma separated list of object types, in lower case.
+pub fn parse_object_types(value: &str) -> Result<Vec<String>, String> {
+    let mut types: Vec<String> = Vec::new();
+    for name in value.split(',').map(|name| name.trim().to_lowercase()).filter(|name| !name.is_empty()) {
+        if !OBJECT_TYPES.iter().any(|(known, _)| *known == name) {
+            return Err(format!(
+                "unknown object type '{name}', expected one of: {}",
+                OBJECT_TYPES.iter().map(|(known, _)| *known).collect::<Vec<&str>>().join(", ")
+            ))
+        }
+        if !types.contains(&name) {
+            types.push(name);
+        }
+    }
+    if types.is_empty() {
+        return Err(format!("no object type in '{value}'"))
+    }
+    Ok(types)
+}
+
+/// Function to get the types left out of the collection from the `--skip` or the `--only` list.
+pub fn excluded_types(skip: &[String], only: &[String]) -> Vec<String> {
+    OBJECT_TYPES.iter()
+        .map(|(name, _)| name.to_string())
+        .filter(|name| skip.contains(name) || (!only.is_empty() && !only.contains(name)))
+        .collect()
+}
+
+/// Function to get the types left out by `--adcs-only`.
+pub fn adcs_excluded_types() -> Vec<String> {
+    let only: Vec<String> = ADCS_TYPES.iter().map(|name| name.to_string()).collect();
+    excluded_types(&[], &only)
+}
+
+/// Function to get the output file of the objects of a type of entry, none for the entries which are not objects.
+pub fn type_name(atype: &Type) -> Option<&'static str> {
+    match atype {
+        Type::User => Some("users"),
+        Type::Group => Some("groups"),
+        Type::Computer => Some("computers"),
+        Type::Ou => Some("ous"),
+        Type::Domain => Some("domains"),
+        Type::Gpo => Some("gpos"),
+        Type::ForeignSecurityPrincipal => Some("fsps"),
+        Type::Container => Some("containers"),
+        Type::Trust => Some("trusts"),
+        Type::NtAutStore => Some("ntauthstores"),
+        Type::AIACA => Some("aiacas"),
+        Type::RootCA => Some("rootcas"),
+        Type::EnterpriseCA => Some("enterprisecas"),
+        Type::CertTemplate => Some("certtemplates"),
+        Type::IssuancePolicie => Some("issuancepolicies"),
+        Type::ClassSchema | Type::Unknown => None,
+    }
+}
+
+/// Function to check if the entries of a type are not parsed, the domain object is always parsed.
+pub fn is_parsing_skipped(atype: &Type, excluded: &[String]) -> bool {
+    type_name(atype).is_some_and(|name| name != "domains" && excluded.iter().any(|e| e == name))
+}
+
+/// Function to get the LDAP filter of the collection: the `--ldap-filter` without the objects of the types left out.
+pub fn types_filter(ldapfilter: &str, excluded: &[String]) -> String {
+    let mut filters: Vec<&str> = Vec::new();
+    for (name, filter) in OBJECT_TYPES {
+        // The domain object is always requested, a class shared with a type collected too
+        let shared = OBJECT_TYPES.iter().any(|(other, same)| same == filter && !excluded.iter().any(|e| e == other));
+        if *name == "domains" || shared || filters.contains(filter) {
+            continue
+        }
+        filters.push(filter);
+    }
+    let excluded = filters.concat();
+    match (excluded.is_empty(), ldapfilter) {
+        (true, _) => ldapfilter.to_owned(),
+        (false, "" | "(objectClass=*)") => format!("(!(|{excluded}))"),
+        (false, custom) => format!("(&{custom}(!(|{excluded})))"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::objecttypes::*;
+
+    #[test]
+    pub fn test_object_types() {
+        assert_eq!(parse_object_types("Computers, enterprisecas,computers").unwrap(), ["computers", "enterprisecas"]);
+        assert!(parse_object_types("users,sessions").unwrap_err().contains("unknown object type 'sessions'"));
+        assert!(parse_object_types(" , ").is_err());
+
+        let skip = vec!["users".to_string(), "gpos".to_string()];
+        assert_eq!(excluded_types(&skip, &[]), skip);
+        let excluded = excluded_types(&[], &["computers".to_string(), "aiacas".to_string()]);
+        assert_eq!(excluded.len(), OBJECT_TYPES.len() - 2);
+        assert!(!excluded.contains(&"computers".to_string()) && excluded.contains(&"domains".to_string()));
+        assert!(excluded_types(&[], &[]).is_empty());
+
+        assert_eq!(types_filter("(objectClass=*)", &[]), "(objectClass=*)");
+        assert_eq!(
+            types_filter("(objectClass=*)", &skip),
+            format!("(!(|{}(objectClass=groupPolicyContainer)))", OBJECT_TYPES[0].1)
+        );
+        // The domain object and the certificationAuthority of the aiacas are still requested
+        let filter = types_filter("(cn=*)", &excluded);
+        assert!(filter.starts_with("(&(cn=*)(!(|") && !filter.contains("(&(objectClass=computer)"));
+        assert!(!filter.contains("(objectClass=domain)") && !filter.contains("certificationAuthority"));
+        assert!(filter.contains("(objectClass=pKIEnrollmentService)"));
+        assert!(crate::filters::parse_ldap_filter(&filter).is_ok());
+
+        // --adcs-only: the PKI objects only, the domain object is still requested
+        let excluded = adcs_excluded_types();
+        assert_eq!(excluded, ["users", "groups", "computers", "ous", "domains", "gpos", "fsps", "containers", "trusts"]);
+        let filter = types_filter("(objectClass=*)", &excluded);
+        assert!(filter.contains("(objectClass=trustedDomain)") && !filter.contains("(objectClass=domain)"));
+        assert!(!filter.contains("pKI") && !filter.contains("certificationAuthority") && !filter.contains("msPKI"));
+        assert!(is_parsing_skipped(&Type::User, &excluded) && is_parsing_skipped(&Type::Trust, &excluded));
+        assert!(!is_parsing_skipped(&Type::Domain, &excluded) && !is_parsing_skipped(&Type::RootCA, &excluded));
+        assert!(!is_parsing_skipped(&Type::ClassSchema, &excluded) && !is_parsing_skipped(&Type::User, &[]));
+        assert!(OBJECT_TYPES.iter().all(|(name, _)| ADCS_TYPES.contains(name) != excluded.iter().any(|e| e == name)));
+    }
+}
@@ -0,0 +1,164 @@
+//! Simple paged results (RFC 2696)
+//!
+//! Every query is requested one page at a time with the paged results control: the entries of a page are stored
+//! before the next page is requested with the cookie of the previous one, until the DC returns an empty cookie. Only
+//! one page is held in memory, the storage (`--cache`) caps the rest of the collection before the parsing.
+use ldap3::controls::{Control, PagedResults, RawControl};
+use ldap3::{LdapError, LdapResult, SearchEntry};
+
+/// Entries per page when `--page-size` is not given.
+pub const DEFAULT_PAGE_SIZE: i32 = 1000;
+
+/// OID of the paged results control.
+pub const PAGED_RESULTS_OID: &str = "1.2.840.113556.1.4.319";
+
+/// Page returned by the DC.
+#[derive(Debug)]
+pub struct Page {
+    pub entries: Vec<SearchEntry>,
+    /// Cookie of the next page, empty for the last one.
+    pub cookie: Vec<u8>,
+    /// Result of the page request, with the referrals of the query on the last page.
+    pub result: LdapResult,
+}
+
+/// Requests of the pages of a query.
+pub(crate) trait PageSource {
+    /// Request the page of `size` entries following the one of the cookie, the first page with an empty cookie.
+    async fn page(&mut self, size: i32, cookie: &[u8]) -> Result<Page, LdapError>;
+}
+
+/// Query requested one page at a time, following the cookies until the last page.
+pub(crate) struct PagedSearch<S> {
+    source: S,
+    page_size: i32,
+    cookie: Vec<u8>,
+    pages: u64,
+    finished: bool,
+    /// Referrals of the pages received.
+    refs: Vec<String>,
+}
+
+impl<S: PageSource> PagedSearch<S> {
+    pub fn new(source: S, page_size: i32) -> Self {
+        Self { source, page_size, cookie: Vec::new(), pages: 0, finished: false, refs: Vec::new() }
+    }
+
+    /// Function to request the next page, none once the last page is received.
+    /// The last page is the one with an empty cookie, or the one refused by the DC, it holds the referrals of every page.
+    pub async fn next_page(&mut self) -> Result<Option<Page>, LdapError> {
+        if self.finished {
+            return Ok(None)
+        }
+        let mut page = self.source.page(self.page_size, &self.cookie).await?;
+        self.pages += 1;
+        self.refs.append(&mut page.result.refs);
+        self.finished = page.cookie.is_empty() || page.result.rc != 0;
+        if self.finished {
+            page.result.refs = std::mem::take(&mut self.refs);
+        }
+        self.cookie = page.cookie.to_owned();
+        Ok(Some(page))
+    }
+
+    /// Function to request every page, for the queries whose entries are all needed at once.
+    pub async fn collect(mut self) -> Result<Vec<SearchEntry>, LdapError> {
+        let mut entries = Vec::new();
+        while let Some(page) = self.next_page().await? {
+            entries.extend(page.entries);
+            if self.finished {
+                page.result.success()?;
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Check the last page is received.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Pages received.
+    pub fn pages(&self) -> u64 {
+        self.pages
+    }
+}
+
+/// Function to get the cookie of the paged results control returned by the DC, empty without the control.
+pub fn page_cookie(controls: &[Control]) -> Vec<u8> {
+    controls.iter()
+        .find(|Control(_, raw)| raw.ctype == PAGED_RESULTS_OID)
+        .map(|Control(_, raw)| raw.parse::<PagedResults>().cookie)
+        .unwrap_or_default()
+}
+
+/// Function to get the paged results control requesting the page following the cookie.
+pub fn page_control(size: i32, cookie: &[u8]) -> RawControl {
+    PagedResults { size, cookie: cookie.to_vec() }.into()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+
+    use crate::paging::*;
+
+    /// DC returning the pages in order, the last one smaller.
+    struct FakePages {
+        pages: Vec<(Vec<&'static str>, &'static [u8])>,
+        requests: Vec<(i32, Vec<u8>)>,
+    }
+
+    impl PageSource for &mut FakePages {
+        async fn page(&mut self, size: i32, cookie: &[u8]) -> Result<Page, LdapError> {
+            self.requests.push((size, cookie.to_vec()));
+            let (names, next) = self.pages.remove(0);
+            Ok(Page {
+                entries: names.iter()
+                    .map(|name| SearchEntry { dn: format!("CN={name},CN=Users,DC=contoso,DC=local"), attrs: HashMap::new(), bin_attrs: HashMap::new() })
+                    .collect(),
+                cookie: next.to_vec(),
+                result: LdapResult {
+                    rc: 0,
+                    matched: String::new(),
+                    text: String::new(),
+                    refs: vec![format!("ldap://child{}.contoso.local/DC=child{0},DC=contoso,DC=local", self.requests.len())],
+                    ctrls: Vec::new(),
+                },
+            })
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_paged_search() {
+        let mut dc = FakePages {
+            pages: vec![
+                (vec!["alice", "bob", "carol"], b"page2"),
+                (vec!["dave", "erin", "frank"], b"page3"),
+                (vec!["grace"], b""),
+            ],
+            requests: Vec::new(),
+        };
+        let mut search = PagedSearch::new(&mut dc, 3);
+        let (mut sizes, mut refs) = (Vec::new(), Vec::new());
+        while let Some(page) = search.next_page().await.unwrap() {
+            sizes.push(page.entries.len());
+            refs = page.result.refs;
+        }
+        assert_eq!(sizes, [3, 3, 1]);
+        // Referrals of the three pages on the last one
+        assert_eq!(refs.len(), 3);
+        assert_eq!(search.pages(), 3);
+        assert!(search.is_finished());
+        // No request once the cookie is empty
+        assert!(search.next_page().await.unwrap().is_none());
+        assert_eq!(dc.requests, [(3, Vec::new()), (3, b"page2".to_vec()), (3, b"page3".to_vec())]);
+
+        // Cookie of the control returned by the DC
+        let control = Control(None, page_control(1000, b"next"));
+        assert_eq!(control.1.ctype, PAGED_RESULTS_OID);
+        assert_eq!(page_cookie(&[control]), b"next");
+        assert!(page_cookie(&[]).is_empty());
+    }
+}
@@ -0,0 +1,283 @@
+//! Collection profiles
+//!
+//! `--profile` sets a group of options for a common scenario in one switch, every option given
+//! explicitly on the command line keeps its value. The presets are plain lists of
+//! `(argument, value)` pairs using the CLI argument names, `--print-config` shows the effective options.
+//!
+//! | profile     | scenario                                                                 |
+//! |-------------|--------------------------------------------------------------------------|
+//! | `default`   | CLI defaults                                                             |
+//! | `stealth`   | LDAP only over LDAPS, slow paged requests, no DNS resolution             |
+//! | `full`      | everything: computer modules, honeypot scores, ACL baseline, NDJSON copy |
+//! | `adcs-only` | LDAP only, ADCS objects written, the domain and containers requested     |
+//! | `dconly`    | LDAP only                                                                |
+//! | `audit`     | LDAP only, ACL baseline and honeypot reports, no network-noisy module    |
+use std::error::Error;
+
+use crate::args::Options;
+use crate::deadline::{collection_order, format_duration, parse_duration, parse_priority};
+use crate::filters::parse_ldap_filter;
+use crate::methods::parse_collection_methods;
+use crate::objecttypes::{excluded_types, parse_object_types, OBJECT_TYPES};
+
+/// LDAP filter of the `adcs-only` profile: the domain, the containers and the ADCS objects.
+pub const ADCS_LDAP_FILTER: &str = "(|(objectClass=domain)(objectClass=container)(objectClass=pKIEnrollmentService)(objectClass=pKICertificateTemplate)(objectClass=certificationAuthority)(objectClass=msPKI-Enterprise-Oid))";
+
+/// One collection profile.
+#[derive(Debug)]
+pub struct Profile {
+    /// Name used with `--profile`.
+    pub name: &'static str,
+    /// One line description.
+    pub description: &'static str,
+    /// CLI argument names and values set by the profile.
+    pub settings: &'static [(&'static str, &'static str)],
+}
+
+/// All collection profiles.
+pub const PROFILES: &[Profile] = &[
+    Profile {
+        name: "default",
+        description: "CLI defaults",
+        settings: &[],
+    },
+    Profile {
+        name: "stealth",
+        description: "LDAP only over LDAPS, 2 seconds ±30% between LDAP pages and queries, no DNS resolution",
+        settings: &[
+            ("collectionmethod", "DCOnly"),
+            ("ldaps", "true"),
+            ("throttle", "2000"),
+            ("jitter", "30"),
+            ("fqdn-resolver", "false"),
+            ("zip", "true"),
+        ],
+    },
+    Profile {
+        name: "full",
+        description: "All collection methods and modules, honeypot scores, ACL baseline and NDJSON copy",
+        settings: &[
+            ("collectionmethod", "All"),
+            ("fqdn-resolver", "true"),
+            ("honeypot", "true"),
+            ("acl-baseline", "true"),
+            ("ndjson", "true"),
+            ("zip", "true"),
+        ],
+    },
+    Profile {
+        name: "adcs-only",
+        description: "LDAP only, ADCS objects (CAs, templates, OIDs) written, containers and the domain requested",
+        settings: &[
+            ("collectionmethod", "DCOnly"),
+            ("ldap-filter", ADCS_LDAP_FILTER),
+            ("only", "ntauthstores,aiacas,rootcas,enterprisecas,certtemplates,issuancepolicies"),
+            ("zip", "true"),
+        ],
+    },
+    Profile {
+        name: "dconly",
+        description: "LDAP only, no connection to the computers",
+        settings: &[
+            ("collectionmethod", "DCOnly"),
+            ("zip", "true"),
+        ],
+    },
+    Profile {
+        name: "audit",
+        description: "LDAP only, ACL baseline and honeypot reports with the ADCS objects, no network-noisy module",
+        settings: &[
+            ("collectionmethod", "DCOnly"),
+            ("fqdn-resolver", "false"),
+            ("acl-baseline", "true"),
+            ("honeypot", "true"),
+            ("zip", "true"),
+        ],
+    },
+];
+
+/// Function to get a profile by name.
+pub fn get_profile(name: &str) -> Option<&'static Profile> {
+    PROFILES.iter().find(|profile| profile.name.eq_ignore_ascii_case(name))
+}
+
+impl Profile {
+    /// Apply the profile settings, except the `explicit` CLI arguments.
+    pub fn apply(&self, options: &mut Options, explicit: &[&str]) -> Result<(), Box<dyn Error>> {
+        for (argument, value) in self.settings {
+            if !explicit.contains(argument) {
+                set_option(options, argument, value)?;
+            }
+        }
+        options.profile = Some(self.name.to_string());
+        Ok(())
+    }
+}
+
+/// Function to set one option from its CLI argument name.
+fn set_option(options: &mut Options, argument: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    let flag = || value.parse::<bool>().map_err(|_| format!("invalid value '{value}' for {argument}"));
+    match argument {
+        "collectionmethod" => options.collection_method = parse_collection_methods(value)?,
+        "ldap-filter" => options.ldap_filter = parse_ldap_filter(value)?,
+        "ldaps" => options.ldaps = flag()?,
+        "throttle" => options.throttle = value.parse()?,
+        "jitter" => options.jitter = value.parse::<u8>()?.min(100),
+        "deadline" => options.deadline = Some(parse_duration(value)?),
+        "collection-priority" => {
+            collection_order(&parse_priority(value))?;
+            options.collection_priority = parse_priority(value);
+        }
+        "fqdn-resolver" => options.fqdn_resolver = flag()?,
+        "honeypot" => options.honeypot = flag()?,
+        "acl-baseline" => options.acl_baseline = flag()?,
+        "ace-filter" => options.ace_filter = Some(value.to_string()),
+        // Unless --skip or --adcs-only already left out some types
+        "only" if options.excluded_types.is_empty() => {
+            options.excluded_types = excluded_types(&[], &parse_object_types(value)?);
+        }
+        "only" => {}
+        "zip" => options.zip = flag()?,
+        "ndjson" => options.ndjson = flag()?,
+        _ => return Err(format!("unknown profile setting '{argument}'").into()),
+    }
+    Ok(())
+}
+
+/// Function to show the effective collection options, one `argument = value` per line.
+pub fn effective_config(options: &Options) -> String {
+    [
+        ("profile", options.profile.as_deref().unwrap_or("default").to_string()),
+        ("collectionmethod", options.collection_method.to_string()),
+        ("ldap-filter", options.ldap_filter.to_owned()),
+        ("user-filter", options.user_filter.as_deref().unwrap_or("none").to_string()),
+        ("computer-filter", options.computer_filter.as_deref().unwrap_or("none").to_string()),
+        ("group-filter", options.group_filter.as_deref().unwrap_or("none").to_string()),
+        ("search-base", match options.search_base.is_empty() {
+            true => "domain".to_string(),
+            false => options.search_base.join("; "),
+        }),
+        ("ldaps", options.ldaps.to_string()),
+        ("throttle", options.throttle.to_string()),
+        ("jitter", options.jitter.to_string()),
+        ("deadline", options.deadline.map(format_duration).unwrap_or("none".to_string())),
+        ("collection-priority", collection_order(&options.collection_priority)
+            .map(|order| order.iter().map(|category| category.name).collect::<Vec<&str>>().join(","))
+            .unwrap_or_else(|err| err.to_string())),
+        ("fqdn-resolver", options.fqdn_resolver.to_string()),
+        ("honeypot", options.honeypot.to_string()),
+        ("acl-baseline", options.acl_baseline.to_string()),
+        ("ace-filter", options.ace_filter.as_deref().unwrap_or("none").to_string()),
+        ("only", match options.excluded_types.is_empty() {
+            true => "all".to_string(),
+            false => OBJECT_TYPES.iter()
+                .map(|(name, _)| *name)
+                .filter(|name| !options.excluded_types.iter().any(|excluded| excluded == name))
+                .collect::<Vec<&str>>()
+                .join(","),
+        }),
+        ("zip", options.zip.to_string()),
+        ("ndjson", options.ndjson.to_string()),
+        ("output", options.path.to_owned()),
+    ]
+    .iter()
+    .map(|(argument, value)| format!("{argument} = {value}\n"))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::args::Options;
+    use crate::profile::*;
+
+    fn effective(name: &str, explicit: &[&str]) -> String {
+        let mut options = Options::default();
+        get_profile(name).unwrap().apply(&mut options, explicit).unwrap();
+        effective_config(&options)
+    }
+
+    fn expected(profile: &str, settings: &[(&str, &str)]) -> String {
+        let mut lines = vec![
+            ("profile", profile.to_string()),
+            ("collectionmethod", "All".to_string()),
+            ("ldap-filter", "(objectClass=*)".to_string()),
+            ("user-filter", "none".to_string()),
+            ("computer-filter", "none".to_string()),
+            ("group-filter", "none".to_string()),
+            ("search-base", "domain".to_string()),
+            ("ldaps", "false".to_string()),
+            ("throttle", "0".to_string()),
+            ("jitter", "0".to_string()),
+            ("deadline", "none".to_string()),
+            ("collection-priority", "domain,trusts,groups,users,adcs,computers,containers,gpos,others".to_string()),
+            ("fqdn-resolver", "false".to_string()),
+            ("honeypot", "false".to_string()),
+            ("acl-baseline", "false".to_string()),
+            ("ace-filter", "none".to_string()),
+            ("only", "all".to_string()),
+            ("zip", "false".to_string()),
+            ("ndjson", "false".to_string()),
+            ("output", "./".to_string()),
+        ];
+        for (argument, value) in settings {
+            lines.iter_mut().find(|(a, _)| a == argument).unwrap().1 = value.to_string();
+        }
+        lines.iter().map(|(a, v)| format!("{a} = {v}\n")).collect()
+    }
+
+    #[test]
+    pub fn test_default_profile() {
+        assert_eq!(effective("default", &[]), expected("default", &[]));
+        assert_eq!(effective_config(&Options::default()), expected("default", &[]));
+    }
+
+    #[test]
+    pub fn test_stealth_profile() {
+        assert_eq!(effective("stealth", &[]), expected("stealth", &[
+            ("collectionmethod", "DCOnly"), ("ldaps", "true"), ("throttle", "2000"), ("jitter", "30"), ("zip", "true"),
+        ]));
+    }
+
+    #[test]
+    pub fn test_full_profile() {
+        assert_eq!(effective("full", &[]), expected("full", &[
+            ("fqdn-resolver", "true"), ("honeypot", "true"), ("acl-baseline", "true"), ("ndjson", "true"), ("zip", "true"),
+        ]));
+    }
+
+    #[test]
+    pub fn test_adcs_only_profile() {
+        assert_eq!(effective("adcs-only", &[]), expected("adcs-only", &[
+            ("collectionmethod", "DCOnly"), ("ldap-filter", ADCS_LDAP_FILTER),
+            ("only", "ntauthstores,aiacas,rootcas,enterprisecas,certtemplates,issuancepolicies"), ("zip", "true"),
+        ]));
+        // --profile adcs-only --skip certtemplates: the explicit types are kept
+        let mut options = Options { excluded_types: vec!["certtemplates".to_string()], ..Options::default() };
+        get_profile("adcs-only").unwrap().apply(&mut options, &["skip"]).unwrap();
+        assert_eq!(options.excluded_types, ["certtemplates"]);
+    }
+
+    #[test]
+    pub fn test_dconly_profile() {
+        assert_eq!(effective("dconly", &[]), expected("dconly", &[("collectionmethod", "DCOnly"), ("zip", "true")]));
+    }
+
+    #[test]
+    pub fn test_audit_profile() {
+        // Reports without any network-noisy module
+        assert_eq!(effective("audit", &[]), expected("audit", &[
+            ("collectionmethod", "DCOnly"), ("acl-baseline", "true"), ("honeypot", "true"), ("zip", "true"),
+        ]));
+    }
+
+    #[test]
+    pub fn test_explicit_flags_override_profile() {
+        // --profile stealth --collectionmethod All: the explicit flag keeps the CLI value
+        assert_eq!(effective("stealth", &["collectionmethod", "zip"]), expected("stealth", &[
+            ("ldaps", "true"), ("throttle", "2000"), ("jitter", "30"),
+        ]));
+        assert!(get_profile("AUDIT").is_some());
+        assert!(get_profile("noisy").is_none());
+    }
+}
@@ -0,0 +1,379 @@
+//! Property provenance of the output objects
+//!
+//! With `--provenance` the source of each property group of each output object (the DC, the module and
+//! the collection time) is written in `<domain>_provenance.json`, keyed by ObjectIdentifier. The
+//! BloodHound CE json files are not changed. The sources are interned, an object only holds indexes:
+//!
+//! ```json
+//! {
+//!   "sources": [
+//!     {"dc": "DC01.CONTOSO.LOCAL", "module": "ldap", "timestamp": "2026-10-16T09:30:00Z"},
+//!     {"dc": "DC02.CONTOSO.LOCAL", "module": "ldap", "timestamp": "2026-10-17T08:00:00Z"}
+//!   ],
+//!   "objects": {
+//!     "S-1-5-21-1004336348-1177238915-682003330-1104": 0,
+//!     "S-1-5-21-1004336348-1177238915-682003330-1105": {"attributes": 0, "logon": 1}
+//!   }
+//! }
+//! ```
+//!
+//! An object with one source for all its groups only holds the source index. [`merge_datasets`] combines
+//! two collections of the same objects group by group, the newest source wins.
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use chrono::DateTime;
+use log::info;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Property groups with their properties, the other properties are in `attributes`.
+pub const PROPERTY_GROUPS: &[(&str, &[&str])] = &[
+    ("logon", &["lastlogon", "lastlogontimestamp", "logoncount"]),
+    ("password", &["pwdlastset", "pwdneverexpires", "passwordnotreqd", "dontreqpreauth"]),
+    ("account", &["enabled", "useraccountcontrol", "admincount", "sensitive", "unconstraineddelegation", "trustedtoauth"]),
+    ("hosts", &["serviceprincipalnames", "additionalhostnames"]),
+];
+
+/// Object keys out of `Properties` with their group, the other keys are in `relations`.
+const OBJECT_GROUPS: &[(&str, &str)] = &[
+    ("Aces", "acl"),
+    ("IsACLProtected", "acl"),
+    ("Members", "members"),
+];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static PROVENANCE: Lazy<Mutex<(Provenance, Option<Source>)>> = Lazy::new(|| Mutex::new((Provenance::default(), None)));
+
+/// Where and when values were collected.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Source {
+    /// DC hostname or address.
+    pub dc: String,
+    /// `ldap`, or `checker` for the objects added by the checker.
+    pub module: String,
+    /// RFC 3339 collection time.
+    pub timestamp: String,
+}
+
+impl Source {
+    /// Same source with another module.
+    pub fn with_module(&self, module: &str) -> Self {
+        Self { module: module.to_string(), ..self.to_owned() }
+    }
+
+    /// Sources are ordered by timestamp, the invalid ones first.
+    fn is_newer_than(&self, other: &Source) -> bool {
+        match (DateTime::parse_from_rfc3339(&self.timestamp), DateTime::parse_from_rfc3339(&other.timestamp)) {
+            (Ok(this), Ok(other)) => this > other,
+            (this, other) => this.is_ok() && other.is_err(),
+        }
+    }
+}
+
+/// Sources of one object, one index for all the groups or one index per group.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ObjectProvenance {
+    Single(u32),
+    Groups(BTreeMap<String, u32>),
+}
+
+/// Interned sources and the sources of each object.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Provenance {
+    pub sources: Vec<Source>,
+    pub objects: BTreeMap<String, ObjectProvenance>,
+    #[serde(skip)]
+    index: HashMap<Source, u32>,
+}
+
+impl Provenance {
+    /// Load a `<domain>_provenance.json` file.
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        let mut provenance: Provenance = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        provenance.index = provenance.sources.iter().enumerate().map(|(i, source)| (source.to_owned(), i as u32)).collect();
+        Ok(provenance)
+    }
+
+    /// Index of a source, added once.
+    pub fn intern(&mut self, source: &Source) -> u32 {
+        if let Some(index) = self.index.get(source) {
+            return *index
+        }
+        let index = self.sources.len() as u32;
+        self.sources.push(source.to_owned());
+        self.index.insert(source.to_owned(), index);
+        index
+    }
+
+    /// Record one source for all the groups of an object.
+    pub fn record(&mut self, object_identifier: &str, source: &Source) {
+        let index = self.intern(source);
+        self.objects.insert(object_identifier.to_owned(), ObjectProvenance::Single(index));
+    }
+
+    /// Record the source of one group of an object, the other groups keep their source.
+    pub fn record_group(&mut self, object_identifier: &str, group: &str, source: &Source) {
+        let index = self.intern(source);
+        let mut groups = match self.objects.remove(object_identifier) {
+            Some(ObjectProvenance::Groups(groups)) => groups,
+            Some(ObjectProvenance::Single(single)) => all_groups().map(|g| (g.to_string(), single)).collect(),
+            None => BTreeMap::new(),
+        };
+        groups.insert(group.to_owned(), index);
+        // Back to one index when all the groups have the same source
+        let single = groups.values().all(|i| *i == index) && all_groups().all(|g| groups.contains_key(g));
+        let object = if single { ObjectProvenance::Single(index) } else { ObjectProvenance::Groups(groups) };
+        self.objects.insert(object_identifier.to_owned(), object);
+    }
+
+    /// Source of one group of an object.
+    pub fn source(&self, object_identifier: &str, group: &str) -> Option<&Source> {
+        let index = match self.objects.get(object_identifier)? {
+            ObjectProvenance::Single(index) => *index,
+            ObjectProvenance::Groups(groups) => *groups.get(group)?,
+        };
+        self.sources.get(index as usize)
+    }
+}
+
+/// All the groups, `attributes` and `relations` included.
+fn all_groups() -> impl Iterator<Item = &'static str> {
+    PROPERTY_GROUPS.iter().map(|(group, _)| *group)
+        .chain(["attributes"])
+        .chain(OBJECT_GROUPS.iter().map(|(_, group)| *group))
+        .chain(["relations"])
+}
+
+/// Function to get the group of a property.
+pub fn property_group(property: &str) -> &'static str {
+    PROPERTY_GROUPS.iter()
+        .find(|(_, properties)| properties.contains(&property))
+        .map(|(group, _)| *group)
+        .unwrap_or("attributes")
+}
+
+/// Function to get the group of an object key out of `Properties`.
+fn key_group(key: &str) -> &'static str {
+    OBJECT_GROUPS.iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, group)| *group)
+        .unwrap_or("relations")
+}
+
+/// Function to start the provenance bookkeeping with the source of this collection.
+pub fn enable_provenance(source: Source) {
+    *PROVENANCE.lock().unwrap() = (Provenance::default(), Some(source));
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Function to check if the provenance bookkeeping is enabled.
+pub fn is_provenance_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Function to stop the provenance bookkeeping and get the records.
+pub fn take_provenance() -> Provenance {
+    ENABLED.store(false, Ordering::SeqCst);
+    std::mem::take(&mut PROVENANCE.lock().unwrap().0)
+}
+
+/// Function to record the source of one output object.
+pub fn record_object(object: &Value) {
+    if !is_provenance_enabled() {
+        return
+    }
+    let (provenance, source) = &mut *PROVENANCE.lock().unwrap();
+    if let Some(source) = source {
+        record_object_into(provenance, source, object);
+    }
+}
+
+/// Function to record one output object, the objects without DN are added by the checker.
+pub fn record_object_into(provenance: &mut Provenance, source: &Source, object: &Value) {
+    let Some(object_identifier) = object["ObjectIdentifier"].as_str() else {
+        return
+    };
+    match object["Properties"]["distinguishedname"].as_str() {
+        Some(dn) if !dn.is_empty() => provenance.record(object_identifier, source),
+        _ => provenance.record(object_identifier, &source.with_module("checker")),
+    }
+}
+
+/// Function to write the recorded provenance in `{path}/{domain}_provenance.json`.
+pub fn write_provenance(path: &str, domain: &str) -> Result<Option<String>, Box<dyn Error>> {
+    if !is_provenance_enabled() {
+        return Ok(None)
+    }
+    let provenance = take_provenance();
+    std::fs::create_dir_all(path)?;
+    let file = format!("{}/{}_provenance.json", path.trim_end_matches('/'), domain.to_lowercase());
+    std::fs::write(&file, serde_json::to_string(&provenance)?)?;
+    info!("Provenance of {} objects from {} sources written to {file}", provenance.objects.len(), provenance.sources.len());
+    Ok(Some(file))
+}
+
+/// Objects of one collection by ObjectIdentifier, with their provenance when recorded.
+#[derive(Debug, Clone, Default)]
+pub struct Dataset {
+    pub objects: BTreeMap<String, Value>,
+    pub provenance: Option<Provenance>,
+}
+
+/// Function to merge two collections. For the objects in both, each group comes from the newest source,
+/// from `other` when a source is not known.
+pub fn merge_datasets(base: Dataset, other: Dataset) -> Dataset {
+    let mut merged = Provenance::default();
+    let mut objects = base.objects;
+    let source_of = |dataset: &Option<Provenance>, id: &str, group: &str| {
+        dataset.as_ref().and_then(|provenance| provenance.source(id, group)).cloned()
+    };
+    // Provenance of the base objects only, the sources of the others are picked group by group
+    for id in objects.keys().filter(|id| !other.objects.contains_key(*id)) {
+        for group in all_groups() {
+            if let Some(source) = source_of(&base.provenance, id, group) {
+                merged.record_group(id, group, &source);
+            }
+        }
+    }
+    for (id, object) in other.objects {
+        let Some(current) = objects.get_mut(&id) else {
+            for group in all_groups() {
+                if let Some(source) = source_of(&other.provenance, &id, group) {
+                    merged.record_group(&id, group, &source);
+                }
+            }
+            objects.insert(id, object);
+            continue
+        };
+        for group in all_groups() {
+            let (theirs, ours) = (source_of(&other.provenance, &id, group), source_of(&base.provenance, &id, group));
+            let newer = match (&theirs, &ours) {
+                (Some(theirs), Some(ours)) => !ours.is_newer_than(theirs),
+                _ => true,
+            };
+            if newer {
+                copy_group(current, &object, group);
+            }
+            if let Some(source) = if newer { theirs } else { ours } {
+                merged.record_group(&id, group, &source);
+            }
+        }
+    }
+    let provenance = (base.provenance.is_some() || other.provenance.is_some()).then_some(merged);
+    Dataset { objects, provenance }
+}
+
+/// Function to copy the values of one group from an object to another.
+fn copy_group(target: &mut Value, source: &Value, group: &str) {
+    if let (Some(target), Some(source)) = (target.as_object_mut(), source.as_object()) {
+        for (key, value) in source.iter().filter(|(key, _)| *key != "Properties" && *key != "ObjectIdentifier") {
+            if key_group(key) == group {
+                target.insert(key.to_owned(), value.to_owned());
+            }
+        }
+    }
+    if let (Some(target), Some(source)) = (target["Properties"].as_object_mut(), source["Properties"].as_object()) {
+        for (property, value) in source {
+            if property_group(property) == group {
+                target.insert(property.to_owned(), value.to_owned());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::BTreeMap;
+    use serde_json::json;
+
+    use crate::provenance::*;
+
+    const ALICE: &str = "S-1-5-21-1004336348-1177238915-682003330-1104";
+
+    fn source(dc: &str, timestamp: &str) -> Source {
+        Source { dc: dc.to_string(), module: "ldap".to_string(), timestamp: timestamp.to_string() }
+    }
+
+    fn alice(lastlogontimestamp: i64, description: &str) -> serde_json::Value {
+        json!({
+            "ObjectIdentifier": ALICE,
+            "Properties": {
+                "name": "ALICE@CONTOSO.LOCAL",
+                "distinguishedname": "CN=ALICE,CN=USERS,DC=CONTOSO,DC=LOCAL",
+                "lastlogontimestamp": lastlogontimestamp,
+                "description": description,
+            },
+            "Aces": [],
+        })
+    }
+
+    #[test]
+    pub fn test_provenance_interning() {
+        let dc01 = source("DC01.CONTOSO.LOCAL", "2026-10-16T09:30:00Z");
+        let mut provenance = Provenance::default();
+        for rid in 1000..1100 {
+            let object = json!({
+                "ObjectIdentifier": format!("S-1-5-21-1-2-3-{rid}"),
+                "Properties": {"distinguishedname": format!("CN=USER{rid},DC=CONTOSO,DC=LOCAL")},
+            });
+            record_object_into(&mut provenance, &dc01, &object);
+        }
+        // Default group added by the checker
+        record_object_into(&mut provenance, &dc01, &json!({"ObjectIdentifier": "CONTOSO.LOCAL-S-1-5-32-544", "Properties": {}}));
+        assert_eq!(provenance.sources, vec![dc01.to_owned(), dc01.with_module("checker")]);
+        assert_eq!(provenance.objects.len(), 101);
+        assert_eq!(provenance.source("S-1-5-21-1-2-3-1000", "logon"), Some(&dc01));
+
+        // One group from another source, back to one index once they all match
+        let dc02 = source("DC02.CONTOSO.LOCAL", "2026-10-17T08:00:00Z");
+        provenance.record_group("S-1-5-21-1-2-3-1000", "logon", &dc02);
+        assert_eq!(provenance.source("S-1-5-21-1-2-3-1000", "logon"), Some(&dc02));
+        assert_eq!(provenance.source("S-1-5-21-1-2-3-1000", "acl"), Some(&dc01));
+        let json = serde_json::to_value(&provenance).unwrap();
+        assert_eq!(json["objects"]["S-1-5-21-1-2-3-1001"], json!(0));
+        assert_eq!(json["objects"]["S-1-5-21-1-2-3-1000"]["logon"], json!(2));
+        provenance.record_group("S-1-5-21-1-2-3-1000", "logon", &dc01);
+        assert_eq!(provenance.objects["S-1-5-21-1-2-3-1000"], ObjectProvenance::Single(0));
+    }
+
+    #[test]
+    pub fn test_merge_conflicting_lastlogontimestamp() {
+        let dc01 = source("DC01.CONTOSO.LOCAL", "2026-10-16T09:30:00Z");
+        let dc02 = source("DC02.CONTOSO.LOCAL", "2026-10-17T08:00:00Z");
+        let dc01_later = source("DC01.CONTOSO.LOCAL", "2026-10-18T10:00:00Z");
+
+        // First run: logon values from DC01, description updated on a later DC01 run
+        let mut first = Provenance::default();
+        first.record(ALICE, &dc01);
+        first.record_group(ALICE, "attributes", &dc01_later);
+        let mut second = Provenance::default();
+        second.record(ALICE, &dc02);
+
+        let merged = merge_datasets(
+            Dataset { objects: BTreeMap::from([(ALICE.to_string(), alice(133700000, "new description"))]), provenance: Some(first) },
+            Dataset { objects: BTreeMap::from([(ALICE.to_string(), alice(133800000, "old description"))]), provenance: Some(second) },
+        );
+        let object = &merged.objects[ALICE];
+        let provenance = merged.provenance.unwrap();
+        // DC02 saw the newest logon, DC01 the newest description
+        assert_eq!(object["Properties"]["lastlogontimestamp"], json!(133800000));
+        assert_eq!(provenance.source(ALICE, "logon"), Some(&dc02));
+        assert_eq!(object["Properties"]["description"], json!("new description"));
+        assert_eq!(provenance.source(ALICE, "attributes"), Some(&dc01_later));
+        assert_eq!(provenance.sources.len(), 2);
+
+        // Without provenance the second dataset wins
+        let merged = merge_datasets(
+            Dataset { objects: BTreeMap::from([(ALICE.to_string(), alice(133800000, "a"))]), provenance: None },
+            Dataset { objects: BTreeMap::from([(ALICE.to_string(), alice(133700000, "b"))]), provenance: None },
+        );
+        assert_eq!(merged.objects[ALICE]["Properties"]["lastlogontimestamp"], json!(133700000));
+        assert!(merged.provenance.is_none());
+    }
+}
@@ -0,0 +1,163 @@
+//! Ranged retrieval of the multi-valued attributes
+//!
+//! The DC returns at most `MaxValRange` values of a multi-valued attribute in one search (1500 by default, 5000 on
+//! recent DCs). The members of a large group like Domain Users come in a ranged key, `member;range=0-1499`, and
+//! the values left are requested on the object, one range at a time:
+//!
+//! ```text
+//! member;range=0-1499       first values, with the search
+//! member;range=1500-2999    requested with member;range=1500-*
+//! member;range=3000-*       requested with member;range=3000-*, last range
+//! ```
+//!
+//! The values of every range are concatenated under the attribute name before the entry is stored, for `member`
+//! as for `msDS-RevealedUsers` or any other attribute requested. The values of an entry not completed (connection
+//! lost) are still parsed.
+use std::error::Error;
+
+use ldap3::SearchEntry;
+use log::{debug, warn};
+
+/// Function to parse a ranged attribute key like `member;range=0-1499`: the attribute name, the first value and the
+/// last one, none for the last range (`member;range=1500-*`).
+pub fn parse_range(key: &str) -> Option<(&str, usize, Option<usize>)> {
+    let (attribute, range) = key.split_once(";range=")?;
+    let (start, end) = range.split_once('-')?;
+    let end = match end {
+        "*" => None,
+        end => Some(end.parse::<usize>().ok()?),
+    };
+    Some((attribute, start.parse().ok()?, end))
+}
+
+/// Function to check if an entry holds only a part of the values of an attribute.
+pub fn has_ranged_attributes(entry: &SearchEntry) -> bool {
+    entry.attrs.keys().any(|key| parse_range(key).is_some())
+}
+
+/// Function to move the values of the ranged keys under their attribute name.
+/// Returns the attributes with values left and the index of the next value, by name.
+pub fn merge_ranges(entry: &mut SearchEntry) -> Vec<(String, usize)> {
+    let keys: Vec<String> = entry.attrs.keys().filter(|key| parse_range(key).is_some()).cloned().collect();
+    let mut pending = Vec::new();
+    for key in keys {
+        let Some((attribute, _, end)) = parse_range(&key) else { continue };
+        let attribute = attribute.to_owned();
+        let values = entry.attrs.remove(&key).unwrap_or_default();
+        entry.attrs.entry(attribute.to_owned()).or_default().extend(values);
+        if let Some(end) = end {
+            pending.push((attribute, end + 1));
+        }
+    }
+    pending.sort();
+    pending
+}
+
+/// Requests of the ranges of an attribute.
+pub(crate) trait RangeReader {
+    /// Read the object of the DN with one ranged attribute like `member;range=1500-*`, `None` when it is gone.
+    async fn read_range(&mut self, dn: &str, attribute: &str) -> Result<Option<SearchEntry>, Box<dyn Error>>;
+}
+
+/// Function to request the values left of the ranged attributes of an entry, until the last range of each one.
+/// Returns the number of requests.
+pub(crate) async fn complete_ranges<R: RangeReader>(reader: &mut R, entry: &mut SearchEntry) -> Result<usize, Box<dyn Error>> {
+    let mut pending = merge_ranges(entry);
+    let mut requests = 0;
+    while let Some((attribute, next)) = pending.pop() {
+        let range = format!("{attribute};range={next}-*");
+        debug!("Requesting {range} on {}", entry.dn);
+        requests += 1;
+        let Some(mut part) = reader.read_range(&entry.dn, &range).await? else {
+            warn!("{} deleted while its {attribute} values were requested", entry.dn);
+            break
+        };
+        // The range requested only: the DC moves forward or the values are complete
+        part.attrs.retain(|key, _| parse_range(key).is_some_and(|(name, start, _)| name.eq_ignore_ascii_case(&attribute) && start == next));
+        if part.attrs.is_empty() {
+            warn!("No {attribute} value from {next} returned for {}, the values left are not collected", entry.dn);
+            continue
+        }
+        // Under the name of the first range, whatever the case returned by the DC
+        for (key, values) in part.attrs {
+            entry.attrs.insert(format!("{attribute}{}", &key[attribute.len()..]), values);
+        }
+        pending.extend(merge_ranges(entry));
+    }
+    Ok(requests)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use std::error::Error;
+
+    use ldap3::SearchEntry;
+
+    use crate::ranged::*;
+
+    fn members(range: std::ops::Range<usize>) -> Vec<String> {
+        range.map(|i| format!("CN=U{i},CN=Users,DC=contoso,DC=local")).collect()
+    }
+
+    /// Group of 4000 members and 1600 revealed users, 1500 values per range.
+    struct FakeRanges {
+        requests: Vec<String>,
+    }
+
+    impl RangeReader for FakeRanges {
+        async fn read_range(&mut self, dn: &str, attribute: &str) -> Result<Option<SearchEntry>, Box<dyn Error>> {
+            self.requests.push(attribute.to_owned());
+            let (name, start, _) = parse_range(attribute).unwrap();
+            let total = if name == "member" { 4000 } else { 1600 };
+            let end = (start + 1500).min(total);
+            let key = match end == total {
+                true => format!("{name};range={start}-*"),
+                false => format!("{name};range={start}-{}", end - 1),
+            };
+            Ok(Some(SearchEntry { dn: dn.to_owned(), attrs: HashMap::from([(key, members(start..end))]), bin_attrs: HashMap::new() }))
+        }
+    }
+
+    #[test]
+    pub fn test_parse_range() {
+        assert_eq!(parse_range("member;range=0-1499"), Some(("member", 0, Some(1499))));
+        assert_eq!(parse_range("msDS-RevealedUsers;range=1500-*"), Some(("msDS-RevealedUsers", 1500, None)));
+        assert_eq!(parse_range("member"), None);
+        assert_eq!(parse_range("member;range=0-x"), None);
+
+        // Values of the last range under the attribute name, nothing left
+        let mut entry = SearchEntry {
+            dn: "CN=Small,DC=contoso,DC=local".to_string(),
+            attrs: HashMap::from([
+                ("member;range=0-*".to_string(), members(0..3)),
+                ("name".to_string(), vec!["Small".to_string()]),
+            ]),
+            bin_attrs: HashMap::new(),
+        };
+        assert!(has_ranged_attributes(&entry));
+        assert!(merge_ranges(&mut entry).is_empty());
+        assert_eq!(entry.attrs["member"], members(0..3));
+        assert!(!has_ranged_attributes(&entry));
+    }
+
+    #[tokio::test]
+    async fn test_complete_ranges() {
+        let mut entry = SearchEntry {
+            dn: "CN=Domain Users,CN=Users,DC=contoso,DC=local".to_string(),
+            attrs: HashMap::from([
+                ("member;range=0-1499".to_string(), members(0..1500)),
+                ("msDS-RevealedUsers;range=0-1499".to_string(), members(0..1500)),
+                ("name".to_string(), vec!["Domain Users".to_string()]),
+            ]),
+            bin_attrs: HashMap::new(),
+        };
+        let mut reader = FakeRanges { requests: Vec::new() };
+        assert_eq!(complete_ranges(&mut reader, &mut entry).await.unwrap(), 3);
+        assert_eq!(reader.requests, ["msDS-RevealedUsers;range=1500-*", "member;range=1500-*", "member;range=3000-*"]);
+        assert_eq!(entry.attrs["member"], members(0..4000));
+        assert_eq!(entry.attrs["msDS-RevealedUsers"], members(0..1600));
+        assert_eq!(entry.attrs.len(), 3);
+    }
+}
@@ -0,0 +1,204 @@
+//! LDAP referrals to the child domains
+//!
+//! A subtree search on the naming context of a parent domain returns the naming contexts of its child domains as
+//! continuation references like `ldap://child.contoso.local/DC=child,DC=contoso,DC=local`. With `--follow-referrals`
+//! each referred naming context is requested on a DC of its domain with the same bind, its own referrals are then
+//! followed up to [`MAX_REFERRAL_DEPTH`], each naming context once.
+use std::collections::{HashSet, VecDeque};
+
+use crate::confignc::add_collection_warning;
+use crate::utils::format::dn_to_domain;
+
+/// Referrals followed from a referred naming context, the ones of the first DC are at depth 1.
+pub const MAX_REFERRAL_DEPTH: usize = 3;
+
+/// Naming context referred by a continuation reference.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Referral {
+    /// URL returned by the DC.
+    pub url: String,
+    /// Domain name or DC of the referral.
+    pub host: String,
+    /// Port of the URL, none for the default one.
+    pub port: Option<u16>,
+    /// Referred naming context.
+    pub base: String,
+}
+
+impl Referral {
+    /// Parse a continuation reference `ldap://host[:port]/DN[?attributes?scope?filter]`, none without a DN.
+    pub fn parse(url: &str) -> Option<Self> {
+        let (scheme, rest) = url.split_once("://")?;
+        if !scheme.eq_ignore_ascii_case("ldap") && !scheme.eq_ignore_ascii_case("ldaps") {
+            return None
+        }
+        let (address, path) = rest.split_once('/')?;
+        let base = percent_decode(path.split('?').next().unwrap_or_default());
+        if address.is_empty() || base.is_empty() {
+            return None
+        }
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') || host.ends_with(']') => (host, Some(port.parse::<u16>().ok()?)),
+            _ => (address, None),
+        };
+        Some(Self {
+            url: url.to_string(),
+            host: host.trim_start_matches('[').trim_end_matches(']').to_string(),
+            port,
+            base,
+        })
+    }
+
+    /// Domain of the referred naming context.
+    pub fn domain(&self) -> String {
+        dn_to_domain(&self.base)
+    }
+}
+
+/// Function to decode the `%XX` escapes of a DN in an LDAP URL.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Referrals to follow, breadth first, each naming context once.
+#[derive(Debug, Default)]
+pub struct ReferralQueue {
+    pending: VecDeque<(Referral, usize)>,
+    visited: HashSet<String>,
+}
+
+impl ReferralQueue {
+    /// New queue, the naming contexts already collected are never followed.
+    pub fn new(collected: &[String]) -> Self {
+        Self {
+            pending: VecDeque::new(),
+            visited: collected.iter().map(|nc| nc.to_uppercase()).collect(),
+        }
+    }
+
+    /// Add the referrals returned by a search, `depth` 1 for the first DC.
+    pub fn push(&mut self, urls: &[String], depth: usize) {
+        for url in urls {
+            let Some(referral) = Referral::parse(url) else {
+                add_collection_warning(&format!("Invalid LDAP referral {url} not followed"));
+                continue
+            };
+            if self.visited.contains(&referral.base.to_uppercase()) {
+                continue
+            }
+            if depth > MAX_REFERRAL_DEPTH {
+                add_collection_warning(&format!("Referral to {} not followed, more than {MAX_REFERRAL_DEPTH} referrals deep", referral.base));
+                continue
+            }
+            self.visited.insert(referral.base.to_uppercase());
+            self.pending.push_back((referral, depth));
+        }
+    }
+
+    /// Next referral to follow with its depth.
+    pub fn next_referral(&mut self) -> Option<(Referral, usize)> {
+        self.pending.pop_front()
+    }
+
+    /// Referrals not followed yet.
+    pub fn pending(&self) -> impl Iterator<Item = &Referral> {
+        self.pending.iter().map(|(referral, _)| referral)
+    }
+}
+
+/// Function to get the domain of an entry collected with the referrals: the child domain of its DN, the domain of
+/// `-d` for its own objects, the configuration, the schema and the DNS application partitions.
+pub fn referral_entry_domain(dn: &str, domain: &str) -> String {
+    let dn_domain = dn_to_domain(dn);
+    let application_partition = dn.split(',')
+        .map(str::trim)
+        .any(|rdn| rdn.eq_ignore_ascii_case("DC=DomainDnsZones") || rdn.eq_ignore_ascii_case("DC=ForestDnsZones"));
+    if !application_partition && dn_domain.ends_with(&format!(".{}", domain.to_uppercase())) {
+        dn_domain
+    } else {
+        domain.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::referral::*;
+
+    #[test]
+    pub fn test_parse_referral() {
+        let referral = Referral::parse("ldap://child.contoso.local/DC=child,DC=contoso,DC=local").unwrap();
+        assert_eq!(referral.host, "child.contoso.local");
+        assert_eq!(referral.port, None);
+        assert_eq!(referral.base, "DC=child,DC=contoso,DC=local");
+        assert_eq!(referral.domain(), "CHILD.CONTOSO.LOCAL");
+
+        // Port, escaped DN and RFC 4516 extensions
+        let referral = Referral::parse("ldap://dc01.child.contoso.local:3890/OU=Sales%20EMEA,DC=child,DC=contoso,DC=local??sub").unwrap();
+        assert_eq!(referral.host, "dc01.child.contoso.local");
+        assert_eq!(referral.port, Some(3890));
+        assert_eq!(referral.base, "OU=Sales EMEA,DC=child,DC=contoso,DC=local");
+
+        assert_eq!(Referral::parse("ldap://child.contoso.local/"), None);
+        assert_eq!(Referral::parse("https://child.contoso.local/DC=child"), None);
+        assert_eq!(Referral::parse("child.contoso.local"), None);
+    }
+
+    #[test]
+    pub fn test_referral_queue() {
+        let collected = vec![
+            "DC=contoso,DC=local".to_string(),
+            "CN=Configuration,DC=contoso,DC=local".to_string(),
+            "DC=DomainDnsZones,DC=contoso,DC=local".to_string(),
+        ];
+        let mut queue = ReferralQueue::new(&collected);
+        queue.push(&[
+            "ldap://child.contoso.local/DC=child,DC=contoso,DC=local".to_string(),
+            "ldap://DomainDnsZones.contoso.local/DC=DomainDnsZones,DC=contoso,DC=local".to_string(),
+            "ldap://CHILD.contoso.local/DC=CHILD,DC=CONTOSO,DC=LOCAL".to_string(),
+        ], 1);
+        let (referral, depth) = queue.next_referral().unwrap();
+        assert_eq!((referral.base.as_str(), depth), ("DC=child,DC=contoso,DC=local", 1));
+        assert!(queue.next_referral().is_none());
+
+        // Bidirectional trusts: a naming context already followed is never requested again
+        queue.push(&[
+            "ldap://contoso.local/DC=contoso,DC=local".to_string(),
+            "ldap://child.contoso.local/DC=child,DC=contoso,DC=local".to_string(),
+            "ldap://sub.child.contoso.local/DC=sub,DC=child,DC=contoso,DC=local".to_string(),
+        ], 2);
+        assert_eq!(queue.pending().map(|referral| referral.host.as_str()).collect::<Vec<&str>>(), ["sub.child.contoso.local"]);
+        assert_eq!(queue.next_referral().unwrap().1, 2);
+
+        // Too deep
+        queue.push(&["ldap://deep.sub.child.contoso.local/DC=deep,DC=sub,DC=child,DC=contoso,DC=local".to_string()], MAX_REFERRAL_DEPTH + 1);
+        assert!(queue.next_referral().is_none());
+    }
+
+    #[test]
+    pub fn test_referral_entry_domain() {
+        assert_eq!(referral_entry_domain("CN=Alice,CN=Users,DC=child,DC=contoso,DC=local", "CONTOSO.LOCAL"), "CHILD.CONTOSO.LOCAL");
+        assert_eq!(referral_entry_domain("CN=Bob,CN=Users,DC=contoso,DC=local", "CONTOSO.LOCAL"), "CONTOSO.LOCAL");
+        assert_eq!(referral_entry_domain("CN=User,CN=Certificate Templates,CN=Public Key Services,CN=Services,CN=Configuration,DC=contoso,DC=local", "CONTOSO.LOCAL"), "CONTOSO.LOCAL");
+        assert_eq!(referral_entry_domain("DC=dc01,DC=contoso.local,CN=MicrosoftDNS,DC=DomainDnsZones,DC=contoso,DC=local", "CONTOSO.LOCAL"), "CONTOSO.LOCAL");
+    }
+}
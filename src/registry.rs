@@ -0,0 +1,634 @@
+//! CA registry collection with the remote registry protocol (`CARegistry` method)
+//!
+//! Some settings of an enterprise CA are only in the registry of its host, under
+//! `HKLM\SYSTEM\CurrentControlSet\Services\CertSvc\Configuration\<CA name>`. They are read with the remote
+//! registry protocol ([MS-RRP]) on the `winreg` named pipe of the `dNSHostName` of the CA, in an SMB session
+//! authenticated with the password or the `--nt-hash` of the LDAP bind (NTLMv2):
+//!
+//! - `EnrollmentAgentRights`, the security descriptor of the `EnrollmentAgentRestrictions`, none when not set
+//! - `RoleSeparationEnabled`, false when the value is not set
+//! - `EditFlags` of the active policy module (`PolicyModules\Active`, the Microsoft default one when not set), its
+//!   `EDITF_ATTRIBUTESUBJECTALTNAME2` bit is `IsUserSpecifiesSanEnabled` (ESC6)
+//!
+//! The Remote Registry service of the recent Windows versions is started by the first open of the pipe, which
+//! is retried for a few seconds. Without the password of the bind (Kerberos, certificate or anonymous bind), with
+//! the `CARegistry` method turned off or when the host can't be read, the values are written with
+//! `Collected: false` and the reason in `FailureReason`.
+use std::error::Error;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+
+use crate::args::Options;
+use crate::confignc::add_collection_warning;
+use crate::ldap::BindState;
+use crate::methods::CollectionMethod;
+use crate::objects::enterpriseca::EnterpriseCA;
+use crate::proxy::Proxy;
+use crate::smb::{FileId, SmbConnection, SmbError, STATUS_OBJECT_NAME_NOT_FOUND, STATUS_PIPE_NOT_AVAILABLE};
+use crate::utils::ntlm::{ntlm_identity, NtlmAccount};
+
+/// Key of the configuration of the CAs hosted, one subkey per CA name.
+pub const CERTSVC_CONFIGURATION: &str = "SYSTEM\\CurrentControlSet\\Services\\CertSvc\\Configuration";
+/// Policy module of a CA without `PolicyModules\Active`.
+pub const DEFAULT_POLICY_MODULE: &str = "CertificateAuthority_MicrosoftDefault.Policy";
+
+/// winreg interface 338CD001-2244-31F1-AAAA-900038001003 v1.0.
+const WINREG_SYNTAX: [u8; 20] = [
+    0x01, 0xd0, 0x8c, 0x33, 0x44, 0x22, 0xf1, 0x31, 0xaa, 0xaa, 0x90, 0x00, 0x38, 0x00, 0x10, 0x03, 1, 0, 0, 0,
+];
+/// NDR transfer syntax 8A885D04-1CEB-11C9-9FE8-08002B104860 v2.
+const NDR_SYNTAX: [u8; 20] = [
+    0x04, 0x5d, 0x88, 0x8a, 0xeb, 0x1c, 0xc9, 0x11, 0x9f, 0xe8, 0x08, 0x00, 0x2b, 0x10, 0x48, 0x60, 2, 0, 0, 0,
+];
+
+const PTYPE_REQUEST: u8 = 0;
+const PTYPE_RESPONSE: u8 = 2;
+const PTYPE_FAULT: u8 = 3;
+const PTYPE_BIND: u8 = 11;
+const PTYPE_BIND_ACK: u8 = 12;
+const PFC_LAST_FRAG: u8 = 0x02;
+/// Largest fragment sent and received.
+const MAX_FRAGMENT: u16 = 4280;
+
+const OPNUM_OPEN_LOCAL_MACHINE: u16 = 2;
+const OPNUM_BASE_REG_CLOSE_KEY: u16 = 5;
+const OPNUM_BASE_REG_OPEN_KEY: u16 = 15;
+const OPNUM_BASE_REG_QUERY_VALUE: u16 = 17;
+
+const MAXIMUM_ALLOWED: u32 = 0x0200_0000;
+const KEY_READ: u32 = 0x0002_0019;
+const ERROR_SUCCESS: u32 = 0;
+const ERROR_FILE_NOT_FOUND: u32 = 2;
+const ERROR_MORE_DATA: u32 = 234;
+/// First size of the buffer of a value, grown to the size answered by the server.
+const VALUE_BUFFER_SIZE: u32 = 1024;
+
+pub const REG_SZ: u32 = 1;
+pub const REG_EXPAND_SZ: u32 = 2;
+pub const REG_BINARY: u32 = 3;
+pub const REG_DWORD: u32 = 4;
+
+/// Opens of the `winreg` pipe while the Remote Registry service starts.
+const PIPE_RETRIES: u32 = 5;
+const PIPE_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Handle of an open registry key.
+pub type KeyHandle = [u8; 20];
+
+/// Registry value: type and raw data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegistryValue {
+    pub kind: u32,
+    pub data: Vec<u8>,
+}
+
+impl RegistryValue {
+    /// Value of a REG_DWORD.
+    pub fn as_dword(&self) -> Option<u32> {
+        match (self.kind, self.data.get(..4)) {
+            (REG_DWORD, Some(data)) => Some(u32::from_le_bytes([data[0], data[1], data[2], data[3]])),
+            _ => None,
+        }
+    }
+
+    /// Value of a REG_SZ or REG_EXPAND_SZ, without the terminating null.
+    pub fn as_string(&self) -> Option<String> {
+        if self.kind != REG_SZ && self.kind != REG_EXPAND_SZ {
+            return None
+        }
+        let chars: Vec<u16> = self.data.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+        Some(String::from_utf16_lossy(&chars).trim_end_matches('\0').to_string())
+    }
+}
+
+/// Settings of a CA read in the registry of its host.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CaConfiguration {
+    /// EnrollmentAgentRights security descriptor, none when not set.
+    pub enrollment_agent_rights: Option<Vec<u8>>,
+    pub role_separation_enabled: bool,
+    /// Active policy module and its EditFlags, none when not set.
+    pub policy_module: String,
+    pub edit_flags: Option<u32>,
+}
+
+impl CaConfiguration {
+    /// Set the values read on the CA, the ones not set keep the reason.
+    pub fn apply(&self, ca: &mut EnterpriseCA, domain: &str) {
+        match &self.enrollment_agent_rights {
+            Some(nt) => ca.set_enrollment_agent_rights(nt, domain),
+            None => ca.clear_enrollment_agent_rights(),
+        }
+        ca.set_role_separation_enabled(self.role_separation_enabled);
+        match self.edit_flags {
+            Some(edit_flags) => ca.set_edit_flags(edit_flags),
+            None => ca.set_registry_failure(&format!("No EditFlags in the policy module {}", self.policy_module)),
+        }
+    }
+}
+
+/// Remote registry of a host, `HKEY_LOCAL_MACHINE` opened.
+pub struct RemoteRegistry {
+    smb: SmbConnection,
+    pipe: FileId,
+    call_id: u32,
+    hklm: KeyHandle,
+}
+
+impl RemoteRegistry {
+    /// Open the remote registry of a host with NTLM credentials.
+    pub async fn connect(
+        host: &str,
+        account: &NtlmAccount<'_>,
+        proxy: Option<&Proxy>,
+        timeout: Duration,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut smb = SmbConnection::connect(host, proxy, timeout).await?;
+        smb.login(account).await?;
+        smb.tree_connect_ipc(host).await?;
+        let pipe = open_winreg(&mut smb).await?;
+        let mut registry = Self { smb, pipe, call_id: 0, hklm: [0u8; 20] };
+        registry.bind().await?;
+        let mut stub = 0u32.to_le_bytes().to_vec();
+        stub.extend(MAXIMUM_ALLOWED.to_le_bytes());
+        let response = registry.call(OPNUM_OPEN_LOCAL_MACHINE, &stub).await?;
+        let (hklm, code) = parse_key_response(&response)?;
+        check_return("OpenLocalMachine", code)?;
+        registry.hklm = hklm;
+        Ok(registry)
+    }
+
+    /// Open a subkey of `HKEY_LOCAL_MACHINE` for reading, none when it does not exist.
+    pub async fn open_key(&mut self, path: &str) -> Result<Option<KeyHandle>, Box<dyn Error>> {
+        let mut stub = self.hklm.to_vec();
+        push_unicode_string(&mut stub, path);
+        // dwOptions
+        stub.extend(0u32.to_le_bytes());
+        stub.extend(KEY_READ.to_le_bytes());
+        let response = self.call(OPNUM_BASE_REG_OPEN_KEY, &stub).await?;
+        match parse_key_response(&response)? {
+            (_, ERROR_FILE_NOT_FOUND) => Ok(None),
+            (key, code) => check_return("BaseRegOpenKey", code).map(|_| Some(key)),
+        }
+    }
+
+    /// Read a value of an open key, none when it is not set.
+    pub async fn query_value(&mut self, key: &KeyHandle, name: &str) -> Result<Option<RegistryValue>, Box<dyn Error>> {
+        let mut size = VALUE_BUFFER_SIZE;
+        loop {
+            let response = self.call(OPNUM_BASE_REG_QUERY_VALUE, &query_value_stub(key, name, size)).await?;
+            match parse_query_value(&response)? {
+                QueryValue::Value(value) => return Ok(Some(value)),
+                QueryValue::NotFound => return Ok(None),
+                QueryValue::MoreData(needed) if needed > size => size = needed,
+                QueryValue::MoreData(_) => return Err(format!("BaseRegQueryValue {name} failed: {}", win32_error(ERROR_MORE_DATA)).into()),
+            }
+        }
+    }
+
+    /// Close an open key.
+    pub async fn close_key(&mut self, key: &KeyHandle) -> Result<(), Box<dyn Error>> {
+        let response = self.call(OPNUM_BASE_REG_CLOSE_KEY, key).await?;
+        check_return("BaseRegCloseKey", parse_key_response(&response)?.1)
+    }
+
+    /// Close `HKEY_LOCAL_MACHINE`, the pipe and the session, the errors are only logged.
+    pub async fn disconnect(mut self) {
+        let hklm = self.hklm;
+        let pipe = self.pipe;
+        let closed = async {
+            self.close_key(&hklm).await?;
+            self.smb.close(&pipe).await?;
+            self.smb.logoff().await
+        };
+        if let Err(err) = closed.await {
+            debug!("Remote registry not closed cleanly: {err}");
+        }
+    }
+
+    /// Bind the winreg interface on the pipe.
+    async fn bind(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut body = Vec::with_capacity(56);
+        body.extend(MAX_FRAGMENT.to_le_bytes());
+        body.extend(MAX_FRAGMENT.to_le_bytes());
+        // assoc_group_id
+        body.extend(0u32.to_le_bytes());
+        // One context with one transfer syntax
+        body.extend([1u8, 0, 0, 0]);
+        body.extend([0u8, 0, 1, 0]);
+        body.extend(WINREG_SYNTAX);
+        body.extend(NDR_SYNTAX);
+        self.call_id += 1;
+        let (response, _) = self.smb.transceive(&self.pipe, &pdu(PTYPE_BIND, self.call_id, &body)).await?;
+        check_bind_ack(&response)
+    }
+
+    /// Call an operation of the winreg interface, the fragments of the answer are joined.
+    async fn call(&mut self, opnum: u16, stub: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut body = (stub.len() as u32).to_le_bytes().to_vec();
+        // p_cont_id
+        body.extend(0u16.to_le_bytes());
+        body.extend(opnum.to_le_bytes());
+        body.extend(stub);
+        if body.len() + 16 > MAX_FRAGMENT as usize {
+            return Err("remote registry request larger than one fragment".into())
+        }
+        self.call_id += 1;
+        let (mut data, _) = self.smb.transceive(&self.pipe, &pdu(PTYPE_REQUEST, self.call_id, &body)).await?;
+        let mut answer = Vec::new();
+        loop {
+            while data.len() < 16 || data.len() < fragment_length(&data) {
+                let (more, _) = self.smb.read(&self.pipe).await?;
+                if more.is_empty() {
+                    return Err("truncated RPC response".into())
+                }
+                data.extend(more);
+            }
+            let fragment: Vec<u8> = data.drain(..fragment_length(&data)).collect();
+            match fragment[2] {
+                PTYPE_RESPONSE if fragment.len() >= 24 => answer.extend(&fragment[24..]),
+                PTYPE_FAULT if fragment.len() >= 28 => {
+                    let status = u32::from_le_bytes([fragment[24], fragment[25], fragment[26], fragment[27]]);
+                    return Err(format!("RPC fault 0x{status:08X} of the remote registry").into())
+                }
+                ptype => return Err(format!("unexpected RPC packet type {ptype}").into()),
+            }
+            if fragment[3] & PFC_LAST_FRAG != 0 {
+                return Ok(answer)
+            }
+        }
+    }
+}
+
+/// Function to open the `winreg` pipe, again while the Remote Registry service starts.
+async fn open_winreg(smb: &mut SmbConnection) -> Result<FileId, Box<dyn Error>> {
+    let mut attempt = 1;
+    loop {
+        match smb.open_pipe("winreg").await {
+            Err(err) if attempt < PIPE_RETRIES && err.downcast_ref::<SmbError>()
+                .is_some_and(|err| err.status == STATUS_PIPE_NOT_AVAILABLE || err.status == STATUS_OBJECT_NAME_NOT_FOUND) => {
+                debug!("winreg pipe not available yet, the Remote Registry service may be starting");
+                tokio::time::sleep(PIPE_RETRY_INTERVAL).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Function to build a DCE/RPC PDU of one fragment, NDR little endian.
+fn pdu(ptype: u8, call_id: u32, body: &[u8]) -> Vec<u8> {
+    let mut pdu = vec![5, 0, ptype, 0x03, 0x10, 0, 0, 0];
+    pdu.extend(((body.len() + 16) as u16).to_le_bytes());
+    // auth_length
+    pdu.extend(0u16.to_le_bytes());
+    pdu.extend(call_id.to_le_bytes());
+    pdu.extend(body);
+    pdu
+}
+
+/// Function to get the frag_length of the PDU at the start of the data.
+fn fragment_length(data: &[u8]) -> usize {
+    u16::from_le_bytes([data[8], data[9]]) as usize
+}
+
+/// Function to check the bind_ack accepts the winreg context.
+fn check_bind_ack(response: &[u8]) -> Result<(), Box<dyn Error>> {
+    if response.len() < 28 || response[2] != PTYPE_BIND_ACK {
+        return Err("winreg interface bind refused".into())
+    }
+    // Secondary address then the results, aligned on 4 bytes
+    let address_length = u16::from_le_bytes([response[24], response[25]]) as usize;
+    let results = (26 + address_length).div_ceil(4) * 4;
+    let result = response.get(results + 4..results + 6).ok_or("truncated RPC bind_ack")?;
+    match u16::from_le_bytes([result[0], result[1]]) {
+        0 => Ok(()),
+        result => Err(format!("winreg interface bind rejected (result {result})").into()),
+    }
+}
+
+/// Function to add an RRP_UNICODE_STRING with its terminating null, the buffer follows the structure.
+fn push_unicode_string(stub: &mut Vec<u8>, value: &str) {
+    let chars: Vec<u16> = value.encode_utf16().chain([0]).collect();
+    let length = (chars.len() * 2) as u16;
+    stub.extend(length.to_le_bytes());
+    stub.extend(length.to_le_bytes());
+    // Referent id, maximum count, offset and actual count
+    stub.extend(0x0002_0000u32.to_le_bytes());
+    stub.extend((chars.len() as u32).to_le_bytes());
+    stub.extend(0u32.to_le_bytes());
+    stub.extend((chars.len() as u32).to_le_bytes());
+    for char in chars {
+        stub.extend(char.to_le_bytes());
+    }
+    stub.resize(stub.len().div_ceil(4) * 4, 0);
+}
+
+/// Function to build the BaseRegQueryValue request, with an empty buffer of `size` bytes.
+fn query_value_stub(key: &KeyHandle, name: &str, size: u32) -> Vec<u8> {
+    let mut stub = key.to_vec();
+    push_unicode_string(&mut stub, name);
+    // lpType
+    stub.extend(0x0002_0004u32.to_le_bytes());
+    stub.extend(0u32.to_le_bytes());
+    // lpData: maximum count, offset and actual count
+    stub.extend(0x0002_0008u32.to_le_bytes());
+    stub.extend(size.to_le_bytes());
+    stub.extend(0u32.to_le_bytes());
+    stub.extend(0u32.to_le_bytes());
+    // lpcbData, lpcbLen
+    stub.extend(0x0002_000Cu32.to_le_bytes());
+    stub.extend(size.to_le_bytes());
+    stub.extend(0x0002_0010u32.to_le_bytes());
+    stub.extend(0u32.to_le_bytes());
+    stub
+}
+
+/// Answer of BaseRegQueryValue.
+#[derive(Debug, PartialEq)]
+enum QueryValue {
+    Value(RegistryValue),
+    NotFound,
+    /// Size of the value, larger than the buffer.
+    MoreData(u32),
+}
+
+/// Function to parse the BaseRegQueryValue answer.
+fn parse_query_value(response: &[u8]) -> Result<QueryValue, Box<dyn Error>> {
+    let mut reader = NdrReader { data: response, offset: 0 };
+    let kind = reader.unique_u32()?.unwrap_or_default();
+    let data = match reader.u32()? {
+        0 => Vec::new(),
+        _ => {
+            // Maximum count and offset, then the bytes sent
+            reader.u32()?;
+            reader.u32()?;
+            let length = reader.u32()? as usize;
+            let data = reader.bytes(length)?.to_vec();
+            reader.offset = reader.offset.div_ceil(4) * 4;
+            data
+        }
+    };
+    let size = reader.unique_u32()?.unwrap_or_default();
+    let length = reader.unique_u32()?.unwrap_or_default() as usize;
+    match reader.u32()? {
+        ERROR_SUCCESS => Ok(QueryValue::Value(RegistryValue { kind, data: data.into_iter().take(length).collect() })),
+        ERROR_FILE_NOT_FOUND => Ok(QueryValue::NotFound),
+        ERROR_MORE_DATA => Ok(QueryValue::MoreData(size)),
+        code => Err(format!("BaseRegQueryValue failed: {}", win32_error(code)).into()),
+    }
+}
+
+/// Function to parse the key handle and the return code of OpenLocalMachine, BaseRegOpenKey and BaseRegCloseKey.
+fn parse_key_response(response: &[u8]) -> Result<(KeyHandle, u32), Box<dyn Error>> {
+    let mut reader = NdrReader { data: response, offset: 0 };
+    let handle: KeyHandle = reader.bytes(20)?.try_into()?;
+    Ok((handle, reader.u32()?))
+}
+
+/// Function to check the Win32 return code of an operation.
+fn check_return(operation: &str, code: u32) -> Result<(), Box<dyn Error>> {
+    match code {
+        ERROR_SUCCESS => Ok(()),
+        code => Err(format!("{operation} failed: {}", win32_error(code)).into()),
+    }
+}
+
+/// Function to get the name of a Win32 error code of the remote registry.
+fn win32_error(code: u32) -> String {
+    let name = match code {
+        ERROR_FILE_NOT_FOUND => "ERROR_FILE_NOT_FOUND",
+        5 => "ERROR_ACCESS_DENIED",
+        6 => "ERROR_INVALID_HANDLE",
+        87 => "ERROR_INVALID_PARAMETER",
+        ERROR_MORE_DATA => "ERROR_MORE_DATA",
+        _ => "error",
+    };
+    format!("{name} ({code})")
+}
+
+/// Reader of an NDR stub.
+struct NdrReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl NdrReader<'_> {
+    fn bytes(&mut self, length: usize) -> Result<&[u8], Box<dyn Error>> {
+        let bytes = self.data.get(self.offset..self.offset + length).ok_or("truncated remote registry answer")?;
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, Box<dyn Error>> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Unique pointer to a DWORD, none for a null referent.
+    fn unique_u32(&mut self) -> Result<Option<u32>, Box<dyn Error>> {
+        match self.u32()? {
+            0 => Ok(None),
+            _ => self.u32().map(Some),
+        }
+    }
+}
+
+/// Function to read the CertSvc configuration of a CA in the registry of its host.
+pub async fn read_ca_configuration(registry: &mut RemoteRegistry, ca_name: &str) -> Result<CaConfiguration, Box<dyn Error>> {
+    let path = format!("{CERTSVC_CONFIGURATION}\\{ca_name}");
+    let key = registry.open_key(&path).await?
+        .ok_or_else(|| format!("no CertSvc configuration of {ca_name}"))?;
+    let enrollment_agent_rights = registry.query_value(&key, "EnrollmentAgentRights").await;
+    let role_separation = registry.query_value(&key, "RoleSeparationEnabled").await;
+    registry.close_key(&key).await?;
+    let policy_module = read_value(registry, &format!("{path}\\PolicyModules"), "Active").await?
+        .and_then(|value| value.as_string())
+        .unwrap_or_else(|| DEFAULT_POLICY_MODULE.to_string());
+    let edit_flags = read_value(registry, &format!("{path}\\PolicyModules\\{policy_module}"), "EditFlags").await?;
+    Ok(CaConfiguration {
+        enrollment_agent_rights: enrollment_agent_rights?.filter(|value| value.kind == REG_BINARY).map(|value| value.data),
+        role_separation_enabled: role_separation?.and_then(|value| value.as_dword()).is_some_and(|value| value != 0),
+        edit_flags: edit_flags.and_then(|value| value.as_dword()),
+        policy_module,
+    })
+}
+
+/// Function to read a value, none when the key or the value does not exist.
+async fn read_value(registry: &mut RemoteRegistry, path: &str, name: &str) -> Result<Option<RegistryValue>, Box<dyn Error>> {
+    let Some(key) = registry.open_key(path).await? else {
+        return Ok(None)
+    };
+    let value = registry.query_value(&key, name).await;
+    registry.close_key(&key).await?;
+    value
+}
+
+/// Function to read the registry of the enterprise CAs with the password or the NT hash of the LDAP bind,
+/// the values not read keep the reason of the failure.
+pub async fn collect_ca_registry(options: &Options, bind: &BindState, enterprisecas: &mut [EnterpriseCA]) {
+    if !options.collection_method.contains(CollectionMethod::CA_REGISTRY) {
+        for ca in enterprisecas.iter_mut() {
+            ca.set_registry_failure("CARegistry collection method not selected");
+        }
+        return
+    }
+    let credentials = bind.credentials().and_then(|(username, secret)| {
+        let (user, domain) = ntlm_identity(username, &options.domain)?;
+        Some((user, domain, secret.nt_hash().ok()?))
+    });
+    let proxy = options.proxy.as_deref().and_then(|proxy| Proxy::parse(proxy, options.proxy_dns).ok());
+    let timeout = Duration::from_secs(options.connect_timeout);
+    for ca in enterprisecas.iter_mut() {
+        let host = ca.dnshostname().to_owned();
+        if host.is_empty() {
+            continue
+        }
+        let Some((user, domain, nt_hash)) = &credentials else {
+            ca.set_registry_failure(&format!("No password of the LDAP bind for the NTLM authentication to {host}"));
+            continue
+        };
+        let configuration = async {
+            let account = NtlmAccount { user, domain, nt_hash: *nt_hash };
+            let mut registry = RemoteRegistry::connect(&host, &account, proxy.as_ref(), timeout).await?;
+            let configuration = read_ca_configuration(&mut registry, ca.caname()).await;
+            registry.disconnect().await;
+            configuration
+        };
+        match configuration.await {
+            Ok(configuration) => {
+                info!("CA registry of {} read on {host}", ca.caname());
+                configuration.apply(ca, &options.domain);
+            }
+            Err(err) => {
+                warn!("CA registry of {} not read on {host}: {err}", ca.caname());
+                add_collection_warning(&format!("CA registry of {} not read on {host}: {err}", ca.caname()));
+                ca.set_registry_failure(&format!("Failed to read the CA registry on {host}: {err}"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::registry::*;
+
+    #[test]
+    pub fn test_query_value_stub() {
+        let stub = query_value_stub(&[7u8; 20], "Active", 1024);
+        // Handle, RRP_UNICODE_STRING of 7 chars with the null, padded to 4 bytes
+        assert_eq!(&stub[20..24], &[14, 0, 14, 0]);
+        assert_eq!(&stub[28..40], &[7, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0]);
+        assert_eq!(&stub[40..54], crate::utils::ntlm::utf16le("Active\0").as_slice());
+        assert_eq!(&stub[54..56], &[0, 0]);
+        assert_eq!(stub.len(), 56 + 40);
+        assert_eq!(&stub[stub.len() - 8..stub.len() - 4], &0x0002_0010u32.to_le_bytes());
+    }
+
+    #[test]
+    pub fn test_parse_query_value() {
+        let answer = |kind: u32, data: &[u8], code: u32| {
+            let mut response = [0x0002_0000u32, kind, 0x0002_0004, 8, 0, data.len() as u32].iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect::<Vec<u8>>();
+            response.extend(data);
+            response.resize(response.len().div_ceil(4) * 4, 0);
+            for value in [0x0002_0008u32, 8, 0x0002_000C, data.len() as u32, code] {
+                response.extend(value.to_le_bytes());
+            }
+            response
+        };
+        let value = RegistryValue { kind: REG_DWORD, data: vec![1, 0, 0, 0] };
+        assert_eq!(parse_query_value(&answer(REG_DWORD, &[1, 0, 0, 0], 0)).unwrap(), QueryValue::Value(value.clone()));
+        assert_eq!(value.as_dword(), Some(1));
+        assert_eq!(value.as_string(), None);
+
+        let response = answer(REG_SZ, &crate::utils::ntlm::utf16le("Policy\0"), 0);
+        let QueryValue::Value(value) = parse_query_value(&response).unwrap() else { panic!("no value") };
+        assert_eq!(value.as_string().as_deref(), Some("Policy"));
+        assert_eq!(value.as_dword(), None);
+
+        assert_eq!(parse_query_value(&answer(0, &[], ERROR_FILE_NOT_FOUND)).unwrap(), QueryValue::NotFound);
+        assert_eq!(parse_query_value(&answer(REG_BINARY, &[], ERROR_MORE_DATA)).unwrap(), QueryValue::MoreData(8));
+        assert_eq!(parse_query_value(&answer(0, &[], 5)).unwrap_err().to_string(), "BaseRegQueryValue failed: ERROR_ACCESS_DENIED (5)");
+        assert!(parse_query_value(&answer(REG_DWORD, &[1, 0, 0, 0], 0)[..20]).is_err());
+    }
+
+    #[test]
+    pub fn test_bind_ack() {
+        let mut ack = pdu(PTYPE_BIND_ACK, 1, &[]);
+        ack.extend([0xb8, 0x10, 0xb8, 0x10, 0x12, 0x34, 0, 0]);
+        // Secondary address \PIPE\winreg, padded
+        ack.extend(13u16.to_le_bytes());
+        ack.extend(b"\\PIPE\\winreg\0");
+        ack.resize(ack.len().div_ceil(4) * 4, 0);
+        ack.extend([1, 0, 0, 0, 0, 0, 0, 0]);
+        ack.extend(NDR_SYNTAX);
+        assert!(check_bind_ack(&ack).is_ok());
+        let result = ack.len() - 24;
+        ack[result] = 2;
+        assert!(check_bind_ack(&ack).is_err());
+        assert!(check_bind_ack(&pdu(13, 1, &[0; 16])).is_err());
+    }
+
+    #[test]
+    pub fn test_apply_ca_configuration() {
+        let mut ca = EnterpriseCA::builder("S-1-5-21-1-2-3-1000").caname("contoso-CA").dnshostname("ca01.contoso.local").build();
+        let mut configuration = CaConfiguration {
+            enrollment_agent_rights: None,
+            role_separation_enabled: false,
+            policy_module: DEFAULT_POLICY_MODULE.to_string(),
+            edit_flags: None,
+        };
+        configuration.apply(&mut ca, "contoso.local");
+        let json = serde_json::to_value(&ca).unwrap();
+        assert_eq!(json["CARegistryData"]["RoleSeparationEnabled"]["Collected"], true);
+        // No EnrollmentAgentRights: no restriction
+        assert_eq!(
+            json["CARegistryData"]["EnrollmentAgentRestrictions"],
+            serde_json::json!({"Restrictions": [], "Collected": true, "FailureReason": null})
+        );
+        assert_eq!(
+            json["CARegistryData"]["IsUserSpecifiesSanEnabled"]["FailureReason"],
+            "No EditFlags in the policy module CertificateAuthority_MicrosoftDefault.Policy"
+        );
+
+        // Default EditFlags of a CA with EDITF_ATTRIBUTESUBJECTALTNAME2 (ESC6)
+        configuration.edit_flags = Some(0x0015014E);
+        configuration.enrollment_agent_rights = Some(vec![1, 0, 4, 0x80]);
+        configuration.apply(&mut ca, "contoso.local");
+        let json = serde_json::to_value(&ca).unwrap();
+        assert_eq!(
+            json["CARegistryData"]["IsUserSpecifiesSanEnabled"],
+            serde_json::json!({"Value": true, "Collected": true, "FailureReason": null})
+        );
+        assert_eq!(json["Properties"]["isuserspecifiessanenabledcollected"], true);
+        assert_eq!(json["CARegistryData"]["EnrollmentAgentRestrictions"]["Collected"], false);
+    }
+
+    #[tokio::test]
+    pub async fn test_collect_ca_registry_not_read() {
+        let ca = || EnterpriseCA::builder("S-1-5-21-1-2-3-1000").caname("contoso-CA").dnshostname("ca01.contoso.local").build();
+        let reason = |ca: &EnterpriseCA| {
+            serde_json::to_value(ca).unwrap()["CARegistryData"]["RoleSeparationEnabled"]["FailureReason"].clone()
+        };
+        let mut options = Options { collection_method: CollectionMethod::DC_ONLY, ..Default::default() };
+        let mut cas = vec![ca()];
+        collect_ca_registry(&options, &BindState::default(), &mut cas).await;
+        assert_eq!(reason(&cas[0]), "CARegistry collection method not selected");
+
+        // No bind in the tests: no password for NTLM, no connection
+        options.collection_method = CollectionMethod::ALL;
+        let mut cas = vec![ca()];
+        collect_ca_registry(&options, &BindState::default(), &mut cas).await;
+        assert_eq!(reason(&cas[0]), "No password of the LDAP bind for the NTLM authentication to ca01.contoso.local");
+        assert_eq!(serde_json::to_value(&cas[0]).unwrap()["Properties"]["roleseparationenabledcollected"], false);
+
+        cas[0].set_role_separation_enabled(true);
+        cas[0].set_registry_failure("ignored");
+        assert_eq!(serde_json::to_value(&cas[0]).unwrap()["CARegistryData"]["RoleSeparationEnabled"],
+            serde_json::json!({"Value": true, "Collected": true, "FailureReason": null}));
+    }
+}
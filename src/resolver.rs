@@ -0,0 +1,429 @@
+//! On-demand resolution of foreign principals with the Global Catalog
+//!
+//! ACEs and group members can reference principals of another domain of the forest, or not collected
+//! by a scoped run (`--ldap-filter`). Their type is only guessed (`Group` for the ACEs, from the name
+//! length for the foreign security principals) and BloodHound CE shows them as untyped stubs.
+//!
+//! With `--resolve-foreign` the unknown domain SIDs are requested on the Global Catalog before the checker:
+//!
+//! - by batches, one `(|(objectSid=...)(objectSid=...))` filter for up to [`GC_BATCH_SIZE`] SIDs, one SID per
+//!   query with `--adcs-only` which resolves the principals of the AD CS ACEs instead of collecting them
+//! - at most one query every [`GC_QUERY_INTERVAL`] (or `--throttle` if longer)
+//! - every answer, found or not, is cached in memory and in `.rusthound-cache/<domain>/foreign_principals.json`
+//!   so the next runs only request the new SIDs
+//!
+//! The resolved types replace the guessed ones for the ACEs and the group members, the names are set on
+//! the foreign security principals.
+//!
+//! With `--adcs-only` the computers are not collected: the computer of the `dNSHostName` of each enterprise CA
+//! is requested too, for its `HostingComputer`.
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use colored::Colorize;
+use ldap3::SearchEntry;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, Instant};
+
+use crate::api::ADResults;
+use crate::enums::secdesc::LdapSid;
+use crate::enums::sid::sid_maker;
+use crate::objects::common::LdapObject;
+
+/// Maximum number of objectSid values in one Global Catalog filter.
+pub const GC_BATCH_SIZE: usize = 50;
+/// Minimum time between two Global Catalog queries.
+pub const GC_QUERY_INTERVAL: Duration = Duration::from_millis(500);
+/// Cache file name in the `.rusthound-cache/<domain>` directory.
+pub const GC_CACHE_FILE: &str = "foreign_principals.json";
+/// Attributes requested on the Global Catalog.
+pub const GC_ATTRIBUTES: &[&str] = &["objectSid", "objectClass", "sAMAccountName", "name"];
+
+/// Principal found on the Global Catalog.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ForeignPrincipal {
+    /// BloodHound type: User, Group, Computer or Base.
+    pub object_type: String,
+    /// Name like `JDOE@CHILD.CONTOSO.LOCAL`.
+    pub name: String,
+}
+
+/// Global Catalog queries.
+pub(crate) trait GlobalCatalog {
+    /// Request the forest objects matching the filter.
+    async fn search(&mut self, filter: &str) -> Result<Vec<SearchEntry>, Box<dyn Error>>;
+}
+
+/// SID to principal cache, `None` when the Global Catalog doesn't know the SID.
+#[derive(Debug, Default)]
+pub struct PrincipalCache {
+    path: Option<PathBuf>,
+    principals: HashMap<String, Option<ForeignPrincipal>>,
+}
+
+impl PrincipalCache {
+    /// Load the cache file, empty if it doesn't exist yet.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let principals = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|err| {
+                warn!("Ignoring the invalid cache {}: {err}", path.display());
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        PrincipalCache { path: Some(path), principals }
+    }
+
+    /// Write the cache file.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let Some(path) = &self.path else { return Ok(()) };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string(&self.principals)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, sid: &str) -> Option<&Option<ForeignPrincipal>> {
+        self.principals.get(sid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.principals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.principals.is_empty()
+    }
+}
+
+/// Batched and rate limited Global Catalog resolver.
+pub(crate) struct ForeignResolver<G: GlobalCatalog> {
+    catalog: G,
+    cache: PrincipalCache,
+    batch_size: usize,
+    interval: Duration,
+    last_query: Option<Instant>,
+}
+
+impl<G: GlobalCatalog> ForeignResolver<G> {
+    pub fn new(catalog: G, cache: PrincipalCache, batch_size: usize, interval: Duration) -> Self {
+        ForeignResolver { catalog, cache, batch_size: batch_size.max(1), interval, last_query: None }
+    }
+
+    /// Resolve the SIDs, only the ones not cached are requested.
+    pub async fn resolve(&mut self, sids: &BTreeSet<String>) -> HashMap<String, ForeignPrincipal> {
+        let pending: Vec<&String> = sids.iter().filter(|sid| self.cache.get(sid).is_none()).collect();
+        debug!("{} SIDs cached, {} to request on the Global Catalog", sids.len() - pending.len(), pending.len());
+
+        for batch in pending.chunks(self.batch_size) {
+            if let Some(last_query) = self.last_query {
+                sleep(self.interval.saturating_sub(last_query.elapsed())).await;
+            }
+            self.last_query = Some(Instant::now());
+
+            let filter = format!("(|{})", batch.iter().map(|sid| format!("(objectSid={sid})")).collect::<String>());
+            let entries = match self.catalog.search(&filter).await {
+                Ok(entries) => entries,
+                Err(err) => {
+                    // Not cached, requested again on the next run
+                    warn!("Global Catalog query failed for {} SIDs: {err}", batch.len());
+                    continue
+                }
+            };
+            let mut found: HashMap<String, ForeignPrincipal> = entries.iter().filter_map(parse_principal).collect();
+            for sid in batch {
+                let principal = found.remove(*sid);
+                self.cache.principals.insert(sid.to_string(), principal);
+            }
+        }
+
+        sids.iter()
+            .filter_map(|sid| Some((sid.to_owned(), self.cache.get(sid)?.clone()?)))
+            .collect()
+    }
+
+    pub fn into_cache(self) -> PrincipalCache {
+        self.cache
+    }
+}
+
+/// Function to get the SID, type and name of one Global Catalog entry.
+fn parse_principal(entry: &SearchEntry) -> Option<(String, ForeignPrincipal)> {
+    let raw_sid = entry.bin_attrs.get("objectSid")?.first()?;
+    let sid = sid_maker(LdapSid::parse(raw_sid).ok()?.1, "");
+    let classes = entry.attrs.get("objectClass").cloned().unwrap_or_default();
+    let has_class = |class: &str| classes.iter().any(|c| c.eq_ignore_ascii_case(class));
+    // computer inherits from user
+    let object_type = if has_class("computer") {
+        "Computer"
+    } else if has_class("user") {
+        "User"
+    } else if has_class("group") {
+        "Group"
+    } else {
+        "Base"
+    };
+    let name = entry.attrs.get("sAMAccountName").or(entry.attrs.get("name"))?.first()?;
+    let domain = entry.dn.split(',')
+        .filter_map(|part| part.get(..3).filter(|prefix| prefix.eq_ignore_ascii_case("DC=")).map(|_| &part[3..]))
+        .collect::<Vec<&str>>()
+        .join(".");
+    Some((sid, ForeignPrincipal {
+        object_type: object_type.to_owned(),
+        name: format!("{name}@{domain}").to_uppercase(),
+    }))
+}
+
+/// Function to list the domain SIDs referenced but not collected, and the foreign security principals.
+pub fn foreign_sids(results: &ADResults) -> BTreeSet<String> {
+    fn unknown_aces<T: LdapObject>(objects: &[T], results: &ADResults, sids: &mut BTreeSet<String>) {
+        for ace in objects.iter().flat_map(|object| object.get_aces()) {
+            if !results.mappings.sid_type.contains_key(ace.principal_sid()) {
+                sids.insert(ace.principal_sid().to_owned());
+            }
+        }
+    }
+    let mut sids = BTreeSet::new();
+    unknown_aces(&results.users, results, &mut sids);
+    unknown_aces(&results.groups, results, &mut sids);
+    unknown_aces(&results.computers, results, &mut sids);
+    unknown_aces(&results.ous, results, &mut sids);
+    unknown_aces(&results.domains, results, &mut sids);
+    unknown_aces(&results.gpos, results, &mut sids);
+    unknown_aces(&results.containers, results, &mut sids);
+    unknown_aces(&results.ntauthstores, results, &mut sids);
+    unknown_aces(&results.aiacas, results, &mut sids);
+    unknown_aces(&results.rootcas, results, &mut sids);
+    unknown_aces(&results.enterprisecas, results, &mut sids);
+    unknown_aces(&results.certtemplates, results, &mut sids);
+    unknown_aces(&results.issuancepolicies, results, &mut sids);
+    sids.extend(results.fsps.iter().map(|fsp| fsp.get_object_identifier().to_owned()));
+    // Only the domain principals are in the Global Catalog
+    sids.retain(|sid| sid.starts_with("S-1-5-21-"));
+    sids
+}
+
+/// Function to replace the guessed types and names with the resolved ones.
+pub fn apply_foreign_principals(results: &mut ADResults, resolved: &HashMap<String, ForeignPrincipal>) {
+    for (sid, principal) in resolved {
+        results.mappings.sid_type.insert(sid.to_owned(), principal.object_type.to_owned());
+    }
+    for fsp in results.fsps.iter_mut() {
+        if let Some(principal) = resolved.get(fsp.get_object_identifier()) {
+            *fsp.properties_mut().name_mut() = principal.name.to_owned();
+        }
+    }
+}
+
+/// Function to find the computers hosting the enterprise CAs with the Global Catalog, when they are not collected.
+/// A host not found keeps the principal with `ManageCertificates` as `HostingComputer`.
+pub(crate) async fn resolve_ca_hosts<G: GlobalCatalog>(catalog: &mut G, results: &mut ADResults) -> usize {
+    let hosts: BTreeSet<String> = results.enterprisecas.iter()
+        .map(|enterpriseca| enterpriseca.dnshostname().to_owned())
+        .filter(|host| !host.is_empty() && results.mappings.host_resolver.resolve(host).is_none())
+        .collect();
+    let mut resolved = 0;
+    for host in hosts {
+        let filter = format!("(&(objectClass=computer)(dNSHostName={}))", ldap3::ldap_escape(&host));
+        let entries = match catalog.search(&filter).await {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!("Global Catalog query failed for the CA host {host}: {err}");
+                continue
+            }
+        };
+        let Some((sid, _)) = entries.iter().find_map(parse_principal) else {
+            debug!("CA host {host} not found on the Global Catalog");
+            continue
+        };
+        results.mappings.host_resolver.insert(&host, &sid);
+        results.mappings.sid_type.insert(sid, "Computer".to_string());
+        resolved += 1;
+    }
+    resolved
+}
+
+/// Function to resolve the foreign principals of the results with the Global Catalog.
+pub(crate) async fn resolve_foreign_principals<G: GlobalCatalog>(
+    catalog: G,
+    cache_path: PathBuf,
+    interval: Duration,
+    batch_size: usize,
+    results: &mut ADResults,
+) -> Result<usize, Box<dyn Error>> {
+    let sids = foreign_sids(results);
+    if sids.is_empty() {
+        return Ok(0)
+    }
+    info!("Resolving {} foreign principals with the Global Catalog...", sids.len().to_string().bold());
+    let mut resolver = ForeignResolver::new(catalog, PrincipalCache::load(cache_path), batch_size, interval);
+    let resolved = resolver.resolve(&sids).await;
+    resolver.into_cache().save()?;
+    apply_foreign_principals(results, &resolved);
+    info!("{} foreign principals resolved!", resolved.len().to_string().bold());
+    Ok(resolved.len())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::{BTreeSet, HashMap};
+    use std::error::Error;
+    use std::time::Duration;
+    use ldap3::SearchEntry;
+
+    use crate::resolver::*;
+
+    /// Binary objectSid of S-1-5-21-1-2-3-<rid>.
+    fn raw_sid(rid: u32) -> Vec<u8> {
+        let mut sid = vec![1, 5, 0, 0, 0, 0, 0, 5];
+        for authority in [21, 1, 2, 3, rid] {
+            sid.extend(authority.to_le_bytes());
+        }
+        sid
+    }
+
+    /// Global Catalog with the even RIDs, recording the filters.
+    #[derive(Default)]
+    struct FakeCatalog {
+        filters: Vec<String>,
+    }
+
+    impl GlobalCatalog for FakeCatalog {
+        async fn search(&mut self, filter: &str) -> Result<Vec<SearchEntry>, Box<dyn Error>> {
+            self.filters.push(filter.to_owned());
+            // One computer: CA01.CHILD.CONTOSO.LOCAL
+            if filter.contains("(dNSHostName=") {
+                return Ok(filter.contains("(dNSHostName=ca01.child.contoso.local)").then(|| SearchEntry {
+                    dn: "CN=CA01,CN=Computers,DC=child,DC=contoso,DC=local".to_string(),
+                    attrs: HashMap::from([
+                        ("objectClass".to_string(), vec!["top".to_string(), "user".to_string(), "computer".to_string()]),
+                        ("sAMAccountName".to_string(), vec!["CA01$".to_string()]),
+                    ]),
+                    bin_attrs: HashMap::from([("objectSid".to_string(), vec![raw_sid(1100)])]),
+                }).into_iter().collect())
+            }
+            Ok(filter.split("(objectSid=S-1-5-21-1-2-3-")
+                .filter_map(|part| part.split(')').next()?.parse::<u32>().ok())
+                .filter(|rid| rid % 2 == 0)
+                .map(|rid| SearchEntry {
+                    dn: format!("CN=U{rid},CN=Users,DC=child,DC=contoso,DC=local"),
+                    attrs: HashMap::from([
+                        ("objectClass".to_string(), vec!["top".to_string(), "user".to_string()]),
+                        ("sAMAccountName".to_string(), vec![format!("u{rid}")]),
+                    ]),
+                    bin_attrs: HashMap::from([("objectSid".to_string(), vec![raw_sid(rid)])]),
+                })
+                .collect())
+        }
+    }
+
+    fn sids(rids: std::ops::Range<u32>) -> BTreeSet<String> {
+        rids.map(|rid| format!("S-1-5-21-1-2-3-{rid}")).collect()
+    }
+
+    #[tokio::test]
+    async fn test_batched_resolution() {
+        let mut resolver = ForeignResolver::new(FakeCatalog::default(), PrincipalCache::default(), 4, Duration::from_millis(20));
+        let start = std::time::Instant::now();
+        let resolved = resolver.resolve(&sids(1000..1010)).await;
+
+        // 10 SIDs by batches of 4: 3 queries, rate limited
+        assert_eq!(resolver.catalog.filters.len(), 3);
+        assert_eq!(resolver.catalog.filters[2], "(|(objectSid=S-1-5-21-1-2-3-1008)(objectSid=S-1-5-21-1-2-3-1009))");
+        assert!(start.elapsed() >= Duration::from_millis(40));
+
+        assert_eq!(resolved.len(), 5);
+        assert_eq!(resolved["S-1-5-21-1-2-3-1002"], ForeignPrincipal {
+            object_type: "User".to_string(),
+            name: "U1002@CHILD.CONTOSO.LOCAL".to_string(),
+        });
+        // Not found SIDs are cached too
+        assert_eq!(resolver.into_cache().len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_cache_across_runs() {
+        let path = std::env::temp_dir().join("rusthound-ce-resolver").join(GC_CACHE_FILE);
+        std::fs::remove_file(&path).ok();
+
+        // First run: everything requested
+        let mut resolver = ForeignResolver::new(FakeCatalog::default(), PrincipalCache::load(&path), 50, Duration::ZERO);
+        let first = resolver.resolve(&sids(1000..1006)).await;
+        assert_eq!(resolver.catalog.filters.len(), 1);
+        resolver.into_cache().save().unwrap();
+
+        // Second run: cache hits, only the new SIDs are requested
+        let mut resolver = ForeignResolver::new(FakeCatalog::default(), PrincipalCache::load(&path), 50, Duration::ZERO);
+        let second = resolver.resolve(&sids(1000..1006)).await;
+        assert!(resolver.catalog.filters.is_empty());
+        assert_eq!(first, second);
+
+        let third = resolver.resolve(&sids(1000..1008)).await;
+        assert_eq!(resolver.catalog.filters, vec!["(|(objectSid=S-1-5-21-1-2-3-1006)(objectSid=S-1-5-21-1-2-3-1007))"]);
+        assert_eq!(third.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_adcs_principals() {
+        use crate::api::ADResults;
+        use crate::objects::common::AceTemplate;
+        use crate::objects::enterpriseca::EnterpriseCA;
+
+        let ace = |sid: &str, right: &str| AceTemplate::new(sid.to_string(), "Group".to_string(), right.to_string(), false, String::new());
+        let mut results = ADResults::new();
+        results.enterprisecas.push(EnterpriseCA::builder("CA-GUID-1")
+            .aces(vec![
+                ace("S-1-5-21-1-2-3-1002", "ManageCA"),
+                ace("S-1-5-21-1-2-3-1003", "Enroll"),
+                ace("S-1-5-21-1-2-3-519", "GenericAll"),
+                ace("CONTOSO.LOCAL-S-1-5-11", "Enroll"),
+            ])
+            .build());
+        // Collected with the domain object
+        results.mappings.sid_type.insert("S-1-5-21-1-2-3-519".to_string(), "Group".to_string());
+
+        // --adcs-only: one query per unknown domain SID
+        let sids = foreign_sids(&results);
+        assert_eq!(sids, BTreeSet::from(["S-1-5-21-1-2-3-1002".to_string(), "S-1-5-21-1-2-3-1003".to_string()]));
+        let mut resolver = ForeignResolver::new(FakeCatalog::default(), PrincipalCache::default(), 1, Duration::ZERO);
+        let resolved = resolver.resolve(&sids).await;
+        assert_eq!(resolver.catalog.filters, ["(|(objectSid=S-1-5-21-1-2-3-1002))", "(|(objectSid=S-1-5-21-1-2-3-1003))"]);
+
+        apply_foreign_principals(&mut results, &resolved);
+        assert_eq!(results.mappings.sid_type["S-1-5-21-1-2-3-1002"], "User");
+        assert!(!results.mappings.sid_type.contains_key("S-1-5-21-1-2-3-1003"));
+    }
+
+    #[tokio::test]
+    async fn test_ca_hosts() {
+        use crate::api::ADResults;
+        use crate::json::checker::common::resolve_hosting_computer;
+        use crate::objects::enterpriseca::EnterpriseCA;
+
+        let mut results = ADResults::new();
+        for (guid, host) in [("CA-GUID-1", "ca01.child.contoso.local"), ("CA-GUID-2", "ca02.child.contoso.local"), ("CA-GUID-3", "dc01.contoso.local")] {
+            results.enterprisecas.push(EnterpriseCA::builder(guid).dnshostname(host).hosting_computer("S-1-5-21-1-2-3-1104").build());
+        }
+        // Collected computer: not requested
+        results.mappings.host_resolver.insert("dc01.contoso.local", "S-1-5-21-1-2-3-1000");
+
+        let mut catalog = FakeCatalog::default();
+        assert_eq!(resolve_ca_hosts(&mut catalog, &mut results).await, 1);
+        assert_eq!(catalog.filters, [
+            "(&(objectClass=computer)(dNSHostName=ca01.child.contoso.local))",
+            "(&(objectClass=computer)(dNSHostName=ca02.child.contoso.local))",
+        ]);
+        assert_eq!(results.mappings.sid_type["S-1-5-21-1-2-3-1100"], "Computer");
+
+        // The host not found keeps the principal with ManageCertificates
+        resolve_hosting_computer(&mut results.enterprisecas, &results.mappings.host_resolver).unwrap();
+        let hosting: Vec<&str> = results.enterprisecas.iter().map(|enterpriseca| enterpriseca.hosting_computer().as_str()).collect();
+        assert_eq!(hosting, ["S-1-5-21-1-2-3-1100", "S-1-5-21-1-2-3-1104", "S-1-5-21-1-2-3-1000"]);
+    }
+}
@@ -0,0 +1,225 @@
+//! rootDSE of the domain controller
+//!
+//! Read before the collection: its naming contexts are the search bases, even for a domain whose DN doesn't match
+//! the DNS name given with `-d`, and the DC, the functional levels and the supported controls are recorded in the
+//! `rootdse` meta of the output files.
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::format::domain_to_dc;
+
+/// rootDSE of the DC bound for the collection.
+static ROOT_DSE: Lazy<Mutex<Option<RootDse>>> = Lazy::new(|| Mutex::new(None));
+
+/// DC and directory levels the data came from.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct RootDse {
+    pub dnshostname: String,
+    pub defaultnamingcontext: String,
+    pub configurationnamingcontext: String,
+    /// Domain functional level, like 7 for Windows Server 2016.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domainfunctionality: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forestfunctionality: Option<u8>,
+    /// OIDs of the LDAP controls supported by the DC.
+    pub supportedcontrols: Vec<String>,
+}
+
+impl RootDse {
+    /// Values of the rootDSE attributes.
+    pub fn from_attributes(root_dse: &HashMap<String, Vec<String>>) -> Self {
+        let first = |name: &str| root_dse.get(name).and_then(|v| v.first()).cloned().unwrap_or_default();
+        Self {
+            dnshostname: first("dnsHostName"),
+            defaultnamingcontext: first("defaultNamingContext"),
+            configurationnamingcontext: first("configurationNamingContext"),
+            domainfunctionality: first("domainFunctionality").parse().ok(),
+            forestfunctionality: first("forestFunctionality").parse().ok(),
+            supportedcontrols: root_dse.get("supportedControl").cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Search bases: the naming contexts of the DC, with the default and configuration ones when not listed.
+    pub fn search_bases(&self, naming_contexts: &[String]) -> Vec<String> {
+        let mut bases = naming_contexts.to_vec();
+        for base in [&self.defaultnamingcontext, &self.configurationnamingcontext] {
+            if !base.is_empty() && !bases.iter().any(|nc| nc.eq_ignore_ascii_case(base)) {
+                bases.push(base.to_owned());
+            }
+        }
+        bases
+    }
+
+    /// Search bases of a `--search-base` collection: the subtrees instead of the default naming context, the other
+    /// naming contexts (configuration, schema, DNS zones) unchanged.
+    pub fn scoped_search_bases(&self, naming_contexts: &[String], search_bases: &[String]) -> Vec<String> {
+        let mut bases = Vec::new();
+        for naming_context in naming_contexts {
+            match naming_context.eq_ignore_ascii_case(&self.defaultnamingcontext) {
+                true => bases.extend(search_bases.iter().cloned()),
+                false => bases.push(naming_context.to_owned()),
+            }
+        }
+        bases
+    }
+
+    /// Check the control is supported by the DC.
+    pub fn supports_control(&self, oid: &str) -> bool {
+        self.supportedcontrols.iter().any(|control| control == oid)
+    }
+}
+
+/// Function to get the Windows Server version of a functional level.
+pub fn functional_level_name(level: u8) -> &'static str {
+    match level {
+        0 => "2000",
+        1 => "2003 interim",
+        2 => "2003",
+        3 => "2008",
+        4 => "2008 R2",
+        5 => "2012",
+        6 => "2012 R2",
+        7 => "2016",
+        10 => "2025",
+        _ => "unknown",
+    }
+}
+
+/// Function to check a DC holds the domain of `-d`: its default naming context, or the DNS domain of its
+/// `ldapServiceName` (`contoso.local:dc01$@CONTOSO.LOCAL`) when the DN doesn't match the DNS name.
+pub fn is_domain_of(root_dse: &HashMap<String, Vec<String>>, domain: &str) -> bool {
+    let first = |name: &str| root_dse.get(name).and_then(|v| v.first()).cloned().unwrap_or_default();
+    first("defaultNamingContext").eq_ignore_ascii_case(&domain_to_dc(domain))
+        || first("ldapServiceName").split(':').next().is_some_and(|dns| dns.eq_ignore_ascii_case(domain))
+}
+
+/// Function to remove the spaces around the RDNs of a DN, `OU=Workstations, DC=corp` like `OU=Workstations,DC=corp`.
+pub fn normalize_dn(dn: &str) -> String {
+    let mut rdns = vec![String::new()];
+    let mut escaped = false;
+    for c in dn.chars() {
+        match (escaped, c) {
+            (false, ',') => rdns.push(String::new()),
+            _ => rdns.last_mut().unwrap().push(c),
+        }
+        escaped = !escaped && c == '\\';
+    }
+    rdns.iter().map(|rdn| rdn.trim()).filter(|rdn| !rdn.is_empty()).collect::<Vec<&str>>().join(",")
+}
+
+/// Function to check a DN is the base or in its subtree.
+pub fn is_under(dn: &str, base: &str) -> bool {
+    let (dn, base) = (dn.to_lowercase(), base.to_lowercase());
+    dn == base || dn.ends_with(&format!(",{base}"))
+}
+
+/// Function to get the `--search-base` DNs once each, without the ones under another base: its subtree search
+/// already returns their objects.
+pub fn dedup_search_bases(search_bases: &[String]) -> Vec<String> {
+    let normalized: Vec<String> = search_bases.iter().map(|dn| normalize_dn(dn)).filter(|dn| !dn.is_empty()).collect();
+    let mut bases: Vec<String> = Vec::new();
+    for dn in &normalized {
+        let nested = normalized.iter().any(|base| !base.eq_ignore_ascii_case(dn) && is_under(dn, base));
+        if !nested && !bases.iter().any(|base| base.eq_ignore_ascii_case(dn)) {
+            bases.push(dn.to_owned());
+        }
+    }
+    bases
+}
+
+/// Function to record the rootDSE of the DC bound for the collection.
+pub fn record_root_dse(root_dse: RootDse) {
+    *ROOT_DSE.lock().unwrap() = Some(root_dse);
+}
+
+/// Function to get the recorded rootDSE, once for the output meta.
+pub fn take_root_dse() -> Option<RootDse> {
+    ROOT_DSE.lock().unwrap().take()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+
+    use crate::rootdse::*;
+
+    fn attributes(values: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        values.iter()
+            .map(|(name, values)| (name.to_string(), values.iter().map(|v| v.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    pub fn test_root_dse() {
+        // DNS name corp.contoso.com, DN of an old NetBIOS style domain
+        let root_dse = attributes(&[
+            ("dnsHostName", &["dc01.corp.contoso.com"]),
+            ("defaultNamingContext", &["DC=CORP,DC=LOCAL"]),
+            ("configurationNamingContext", &["CN=Configuration,DC=CORP,DC=LOCAL"]),
+            ("namingContexts", &["DC=CORP,DC=LOCAL", "CN=Schema,CN=Configuration,DC=CORP,DC=LOCAL"]),
+            ("domainFunctionality", &["7"]),
+            ("forestFunctionality", &["6"]),
+            ("supportedControl", &["1.2.840.113556.1.4.319", "1.2.840.113556.1.4.801"]),
+            ("ldapServiceName", &["corp.contoso.com:dc01$@CORP.CONTOSO.COM"]),
+        ]);
+        let info = RootDse::from_attributes(&root_dse);
+        assert_eq!(info.dnshostname, "dc01.corp.contoso.com");
+        assert_eq!((info.domainfunctionality, info.forestfunctionality), (Some(7), Some(6)));
+        assert_eq!(functional_level_name(7), "2016");
+        assert!(info.supports_control("1.2.840.113556.1.4.319"));
+        assert!(!info.supports_control("1.2.840.113556.1.4.417"));
+
+        // The configuration NC is searched even when it isn't listed
+        assert_eq!(info.search_bases(&root_dse["namingContexts"]), vec![
+            "DC=CORP,DC=LOCAL",
+            "CN=Schema,CN=Configuration,DC=CORP,DC=LOCAL",
+            "CN=Configuration,DC=CORP,DC=LOCAL",
+        ]);
+
+        assert!(is_domain_of(&root_dse, "corp.contoso.com"));
+        assert!(is_domain_of(&root_dse, "CORP.LOCAL"));
+        assert!(!is_domain_of(&root_dse, "contoso.com"));
+
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["defaultnamingcontext"], "DC=CORP,DC=LOCAL");
+        assert_eq!(json["domainfunctionality"], 7);
+        assert!(RootDse::from_attributes(&HashMap::new()).domainfunctionality.is_none());
+    }
+
+    #[test]
+    pub fn test_search_bases() {
+        let bases: Vec<String> = [
+            "OU=Workstations, DC=corp,DC=local",
+            "ou=workstations,dc=corp,dc=local",
+            "OU=Paris,OU=Workstations,DC=corp,DC=local",
+            "OU=Servers,DC=corp,DC=local",
+            "CN=Smith\\, John,OU=Servers,DC=corp,DC=local",
+            " ",
+        ].iter().map(|dn| dn.to_string()).collect();
+        // Once each, the nested ones searched by their parent
+        let bases = dedup_search_bases(&bases);
+        assert_eq!(bases, vec!["OU=Workstations,DC=corp,DC=local", "OU=Servers,DC=corp,DC=local"]);
+        assert_eq!(normalize_dn("CN=Smith\\, John , OU=Servers"), "CN=Smith\\, John,OU=Servers");
+        assert!(is_under("CN=WS01,OU=Workstations,DC=CORP,DC=LOCAL", "ou=workstations,dc=corp,dc=local"));
+        assert!(!is_under("CN=WS01,OU=OldWorkstations,DC=corp,DC=local", "OU=Workstations,DC=corp,DC=local"));
+
+        // The domain partition replaced by the subtrees, the configuration partition still searched
+        let server = RootDse {
+            defaultnamingcontext: "DC=corp,DC=local".to_string(),
+            configurationnamingcontext: "CN=Configuration,DC=corp,DC=local".to_string(),
+            ..RootDse::default()
+        };
+        let naming_contexts = server.search_bases(&["DC=CORP,DC=LOCAL".to_string(), "DC=DomainDnsZones,DC=corp,DC=local".to_string()]);
+        assert_eq!(server.scoped_search_bases(&naming_contexts, &bases), vec![
+            "OU=Workstations,DC=corp,DC=local",
+            "OU=Servers,DC=corp,DC=local",
+            "DC=DomainDnsZones,DC=corp,DC=local",
+            "CN=Configuration,DC=corp,DC=local",
+        ]);
+    }
+}
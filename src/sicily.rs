@@ -0,0 +1,283 @@
+//! NTLM bind of the LDAP connections (Sicily)
+//!
+//! ldap3 only sends the simple, EXTERNAL and GSSAPI binds. The NTLM bind ([MS-ADTS] 5.1.1.1.3) is sent on a
+//! connection opened by RustHound-CE: the NEGOTIATE_MESSAGE in a `sicilyNegotiate` BindRequest, the challenge in
+//! the matchedDN of the BindResponse, then the AUTHENTICATE_MESSAGE in a `sicilyResponse` BindRequest. Once
+//! bound, the connection is handed to ldap3 through a local socket only it connects to, like the SOCKS5 tunnels.
+//! Signing is not negotiated: the LDAP messages after the bind are protected by TLS, with the channel binding
+//! hash of the DC certificate in the AUTHENTICATE_MESSAGE.
+use std::error::Error;
+use std::time::Duration;
+
+use ldap3::asn1::{parse_tag, StructureTag, TagClass};
+use ldap3::LdapResult;
+use log::{debug, trace};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::utils::ntlm::{authenticate_message, filetime_now, negotiate_message, parse_challenge, NtlmAccount};
+
+/// Time for ldap3 to connect to the local socket of a bound connection.
+const HANDOVER_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest LDAP message read before the handover, the bind and StartTLS responses are small.
+const MAX_MESSAGE: usize = 64 * 1024;
+
+/// BindRequest and BindResponse, [APPLICATION 0] and [APPLICATION 1].
+const BIND_REQUEST: u8 = 0x60;
+const BIND_RESPONSE: u64 = 1;
+/// Sicily authentication choices of the BindRequest, [10] and [11].
+const SICILY_NEGOTIATE: u8 = 0x8A;
+const SICILY_RESPONSE: u8 = 0x8B;
+/// ExtendedRequest and ExtendedResponse, [APPLICATION 23] and [APPLICATION 24].
+const EXTENDED_REQUEST: u8 = 0x77;
+const EXTENDED_RESPONSE: u64 = 24;
+/// StartTLS extended operation.
+const STARTTLS_OID: &str = "1.3.6.1.4.1.1466.20037";
+
+/// Connection to a DC, TCP or TLS.
+pub trait LdapStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> LdapStream for S {}
+
+/// Function to encode a BER element with its definite length.
+fn ber(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut element = vec![tag];
+    match content.len() {
+        length @ 0..=0x7F => element.push(length as u8),
+        length => {
+            let length: Vec<u8> = (length as u32).to_be_bytes().into_iter().skip_while(|byte| *byte == 0).collect();
+            element.push(0x80 | length.len() as u8);
+            element.extend(length);
+        }
+    }
+    element.extend_from_slice(content);
+    element
+}
+
+/// Function to build an LDAPMessage, the message ids of the bind are below 128.
+fn ldap_message(message_id: u8, operation: Vec<u8>) -> Vec<u8> {
+    let mut content = ber(0x02, &[message_id]);
+    content.extend(operation);
+    ber(0x30, &content)
+}
+
+/// Function to build a Sicily BindRequest, version 3 without name.
+fn bind_request(message_id: u8, authentication: u8, token: &[u8]) -> Vec<u8> {
+    let mut content = ber(0x02, &[3]);
+    content.extend(ber(0x04, &[]));
+    content.extend(ber(authentication, token));
+    ldap_message(message_id, ber(BIND_REQUEST, &content))
+}
+
+/// Function to read one LDAPMessage.
+async fn read_message<S: LdapStream>(stream: &mut S) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut message = vec![0u8; 2];
+    stream.read_exact(&mut message).await?;
+    let length = match message[1] {
+        length @ 0..=0x7F => length as usize,
+        octets @ 0x81..=0x84 => {
+            let mut length = vec![0u8; (octets & 0x7F) as usize];
+            stream.read_exact(&mut length).await?;
+            message.extend_from_slice(&length);
+            length.iter().fold(0, |length, byte| length << 8 | *byte as usize)
+        }
+        _ => return Err("invalid length of an LDAP message".into()),
+    };
+    if message[0] != 0x30 || length > MAX_MESSAGE {
+        return Err("invalid LDAP message".into())
+    }
+    let start = message.len();
+    message.resize(start + length, 0);
+    stream.read_exact(&mut message[start..]).await?;
+    Ok(message)
+}
+
+/// Function to get the result of a response and its matchedDN, the NTLM challenge of the negotiate BindResponse.
+fn parse_response(message: &[u8], operation: u64) -> Result<(LdapResult, Vec<u8>), Box<dyn Error>> {
+    let invalid = || format!("invalid LDAP response, expected [APPLICATION {operation}]");
+    let (_, tag) = parse_tag(message).map_err(|_| invalid())?;
+    let mut fields = tag.expect_constructed()
+        .and_then(|parts| parts.into_iter().nth(1))
+        .and_then(|response| response.match_class(TagClass::Application))
+        .and_then(|response| response.match_id(operation))
+        .and_then(StructureTag::expect_constructed)
+        .ok_or_else(invalid)?
+        .into_iter()
+        .map(StructureTag::expect_primitive);
+    let (Some(Some(rc)), Some(Some(matched)), Some(Some(text))) = (fields.next(), fields.next(), fields.next()) else {
+        return Err(invalid().into())
+    };
+    let result = LdapResult {
+        rc: rc.iter().fold(0, |rc, byte| rc << 8 | u32::from(*byte)),
+        matched: String::new(),
+        text: String::from_utf8_lossy(&text).into_owned(),
+        refs: Vec::new(),
+        ctrls: Vec::new(),
+    };
+    Ok((result, matched))
+}
+
+/// Function to upgrade a connection with StartTLS, the TLS handshake is left to the caller.
+pub async fn starttls<S: LdapStream>(stream: &mut S) -> Result<(), Box<dyn Error>> {
+    let request = ldap_message(1, ber(EXTENDED_REQUEST, &ber(0x80, STARTTLS_OID.as_bytes())));
+    stream.write_all(&request).await?;
+    let (result, _) = parse_response(&read_message(stream).await?, EXTENDED_RESPONSE)?;
+    result.success()?;
+    Ok(())
+}
+
+/// Function to bind a connection with NTLM, with the `channel_bindings` hash of its TLS channel.
+/// A refused bind is the [`ldap3::LdapError`] of its result, like the binds of ldap3.
+pub async fn ntlm_bind<S: LdapStream>(
+    stream: &mut S,
+    account: &NtlmAccount<'_>,
+    channel_bindings: Option<&[u8; 16]>,
+) -> Result<(), Box<dyn Error>> {
+    stream.write_all(&bind_request(2, SICILY_NEGOTIATE, &negotiate_message(false))).await?;
+    let (result, challenge) = parse_response(&read_message(stream).await?, BIND_RESPONSE)?;
+    result.success()?;
+    let challenge = parse_challenge(&challenge)?;
+
+    let (authenticate, _) = authenticate_message(
+        &challenge, account, false, channel_bindings, fastrand::u64(..).to_le_bytes(), filetime_now(),
+    );
+    stream.write_all(&bind_request(3, SICILY_RESPONSE, &authenticate)).await?;
+    let (result, _) = parse_response(&read_message(stream).await?, BIND_RESPONSE)?;
+    result.success()?;
+    trace!("NTLM bind of {}\\{}", account.domain, account.user);
+    Ok(())
+}
+
+/// Function to hand a bound connection to ldap3, return the URL of the local socket forwarded to it: a Unix socket
+/// in a directory only readable by the user (`ldapi://`), a listener on 127.0.0.1 on the other systems.
+pub async fn handover(mut stream: Box<dyn LdapStream>) -> Result<String, Box<dyn Error>> {
+    #[cfg(unix)]
+    let (listener, url, directory) = {
+        use std::os::unix::fs::DirBuilderExt;
+        let directory = std::env::temp_dir().join(format!("rusthound-ce-{}-{:016x}", std::process::id(), fastrand::u64(..)));
+        std::fs::DirBuilder::new().mode(0o700).create(&directory)?;
+        let path = directory.join("ldap.sock");
+        let listener = tokio::net::UnixListener::bind(&path)?;
+        (listener, format!("ldapi://{}", percent_encode(&path.display().to_string())), directory)
+    };
+    #[cfg(not(unix))]
+    let (listener, url) = {
+        let listener = tokio::net::TcpListener::bind(std::net::SocketAddr::from(([127, 0, 0, 1], 0))).await?;
+        let url = format!("ldap://{}", listener.local_addr()?);
+        (listener, url)
+    };
+    debug!("Bound LDAP connection handed over on {url}");
+    tokio::spawn(async move {
+        let accepted = tokio::time::timeout(HANDOVER_TIMEOUT, listener.accept()).await;
+        #[cfg(unix)]
+        std::fs::remove_dir_all(&directory).ok();
+        match accepted {
+            Ok(Ok((mut local, _))) => {
+                if let Err(err) = tokio::io::copy_bidirectional(&mut local, &mut stream).await {
+                    trace!("Bound LDAP connection closed: {err}");
+                }
+            }
+            _ => debug!("No LDAP connection on the local socket of the bound connection"),
+        }
+    });
+    Ok(url)
+}
+
+/// Function to percent-encode the path of a Unix socket for an `ldapi://` URL.
+#[cfg(unix)]
+fn percent_encode(path: &str) -> String {
+    path.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+        byte => format!("%{byte:02X}"),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::sicily::*;
+    use crate::utils::ntlm::{nt_hash, NTLMSSP_SIGNATURE};
+
+    /// Function to build a response of the DC.
+    fn response(message_id: u8, operation: u8, rc: u8, matched: &[u8], text: &str) -> Vec<u8> {
+        let mut content = ber(0x0A, &[rc]);
+        content.extend(ber(0x04, matched));
+        content.extend(ber(0x04, text.as_bytes()));
+        ldap_message(message_id, ber(operation, &content))
+    }
+
+    /// CHALLENGE_MESSAGE with an empty target info.
+    fn challenge() -> Vec<u8> {
+        let mut message = NTLMSSP_SIGNATURE.to_vec();
+        message.extend(2u32.to_le_bytes());
+        message.extend([0u8; 8]);
+        message.extend(0xe288_8215u32.to_le_bytes());
+        message.extend([0x11; 8]);
+        message.extend([0u8; 8]);
+        message.extend([4, 0, 4, 0]);
+        message.extend(48u32.to_le_bytes());
+        message.extend([0u8; 4]);
+        message
+    }
+
+    #[test]
+    pub fn test_ber() {
+        assert_eq!(ber(0x04, b"abc"), vec![0x04, 3, b'a', b'b', b'c']);
+        let long = ber(0x04, &[0u8; 300]);
+        assert_eq!(long[..4], [0x04, 0x82, 0x01, 0x2C]);
+        assert_eq!(long.len(), 304);
+        // version 3, empty name, [10] token
+        assert_eq!(bind_request(2, SICILY_NEGOTIATE, b"NT"), vec![0x30, 14, 0x02, 1, 2, 0x60, 9, 0x02, 1, 3, 0x04, 0, 0x8A, 2, b'N', b'T']);
+    }
+
+    #[tokio::test]
+    pub async fn test_ntlm_bind() {
+        let (mut client, mut server) = tokio::io::duplex(MAX_MESSAGE);
+        let dc = tokio::spawn(async move {
+            let negotiate = read_message(&mut server).await.unwrap();
+            assert_eq!(negotiate[12], SICILY_NEGOTIATE);
+            server.write_all(&response(2, 0x61, 0, &challenge(), "")).await.unwrap();
+            let authenticate = read_message(&mut server).await.unwrap();
+            let text = "80090346: LdapErr: DSID-0C090569, comment: AcceptSecurityContext error, data 80090346, v4563";
+            server.write_all(&response(3, 0x61, 49, &[], text)).await.unwrap();
+            authenticate
+        });
+        let account = NtlmAccount { user: "alice", domain: "contoso.local", nt_hash: nt_hash("Password1") };
+        let err = ntlm_bind(&mut client, &account, Some(&[0x42; 16])).await.unwrap_err();
+        assert!(err.to_string().contains("80090346"), "{err}");
+        let authenticate = dc.await.unwrap();
+        let start = authenticate.windows(8).position(|window| window == NTLMSSP_SIGNATURE).unwrap();
+        assert_eq!(authenticate[start - 3..start - 1], [SICILY_RESPONSE, 0x81]);
+        assert!(authenticate.windows(20).any(|window| window[..4] == [0x0a, 0x00, 0x10, 0x00] && window[4..] == [0x42; 16]));
+    }
+
+    #[tokio::test]
+    pub async fn test_starttls_refused() {
+        let (mut client, mut server) = tokio::io::duplex(MAX_MESSAGE);
+        tokio::spawn(async move {
+            let request = read_message(&mut server).await.unwrap();
+            assert!(request.ends_with(STARTTLS_OID.as_bytes()));
+            server.write_all(&response(1, 0x78, 2, &[], "00000057: LdapErr: DSID-0C0911CA, comment: Error initializing SSL/TLS")).await.unwrap();
+        });
+        let err = starttls(&mut client).await.unwrap_err();
+        assert!(err.to_string().contains("rc=2"), "{err}");
+    }
+
+    #[tokio::test]
+    pub async fn test_handover() {
+        let (client, mut server) = tokio::io::duplex(MAX_MESSAGE);
+        let url = handover(Box::new(client)).await.unwrap();
+        #[cfg(unix)]
+        let mut local = {
+            let path = url.strip_prefix("ldapi://").unwrap().replace("%2F", "/");
+            tokio::net::UnixStream::connect(path).await.unwrap()
+        };
+        #[cfg(not(unix))]
+        let mut local = tokio::net::TcpStream::connect(url.strip_prefix("ldap://").unwrap()).await.unwrap();
+        local.write_all(b"ping").await.unwrap();
+        let mut data = [0u8; 4];
+        server.read_exact(&mut data).await.unwrap();
+        assert_eq!(&data, b"ping");
+    }
+}
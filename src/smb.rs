@@ -0,0 +1,544 @@
+//! SMB2 client of the RPC named pipes
+//!
+//! Minimal SMB 2.0.2/2.1 client reaching the named pipes of a host, used by the CA registry collection
+//! (`CARegistry` method): negotiate, NTLMv2 session setup, `IPC$` tree connect, then create, transceive,
+//! read and close on one pipe. The messages after the session setup are signed (HMAC-SHA256), SMB 3 is not
+//! negotiated. The connection goes through `--proxy` like the LDAP connections.
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use log::trace;
+use sha2::Sha256;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::proxy::Proxy;
+use crate::utils::ntlm::{authenticate_message, filetime_now, negotiate_message, parse_challenge, utf16le, NtlmAccount, NTLMSSP_SIGNATURE};
+
+/// SMB over TCP port.
+pub const SMB_PORT: u16 = 445;
+
+const SMB2_NEGOTIATE: u16 = 0x0000;
+const SMB2_SESSION_SETUP: u16 = 0x0001;
+const SMB2_LOGOFF: u16 = 0x0002;
+const SMB2_TREE_CONNECT: u16 = 0x0003;
+const SMB2_CREATE: u16 = 0x0005;
+const SMB2_CLOSE: u16 = 0x0006;
+const SMB2_READ: u16 = 0x0008;
+const SMB2_IOCTL: u16 = 0x000B;
+
+const SMB2_FLAGS_ASYNC_COMMAND: u32 = 0x0000_0002;
+const SMB2_FLAGS_SIGNED: u32 = 0x0000_0008;
+const SMB2_SESSION_FLAG_IS_GUEST: u16 = 0x0001;
+const SMB2_SESSION_FLAG_IS_NULL: u16 = 0x0002;
+const SMB2_NEGOTIATE_SIGNING_ENABLED: u16 = 0x0001;
+const FSCTL_PIPE_TRANSCEIVE: u32 = 0x0011_C017;
+/// Dialects offered, the ones signed with HMAC-SHA256.
+const DIALECTS: [u16; 2] = [0x0202, 0x0210];
+const CREDITS_REQUESTED: u16 = 64;
+/// Largest answer of one transceive or read, one credit.
+const MAX_RESPONSE: u32 = 65_535;
+/// Message id of the oplock break notifications, not an answer.
+const UNSOLICITED_MESSAGE_ID: u64 = u64::MAX;
+
+pub const STATUS_SUCCESS: u32 = 0x0000_0000;
+pub const STATUS_PENDING: u32 = 0x0000_0103;
+pub const STATUS_BUFFER_OVERFLOW: u32 = 0x8000_0005;
+pub const STATUS_MORE_PROCESSING_REQUIRED: u32 = 0xC000_0016;
+pub const STATUS_ACCESS_DENIED: u32 = 0xC000_0022;
+pub const STATUS_OBJECT_NAME_NOT_FOUND: u32 = 0xC000_0034;
+pub const STATUS_LOGON_FAILURE: u32 = 0xC000_006D;
+pub const STATUS_ACCOUNT_RESTRICTION: u32 = 0xC000_006E;
+pub const STATUS_PIPE_NOT_AVAILABLE: u32 = 0xC000_00AC;
+pub const STATUS_BAD_NETWORK_NAME: u32 = 0xC000_00CC;
+
+/// Handle of a file or pipe opened on the share.
+pub type FileId = [u8; 16];
+
+/// SMB2 command answered with an error status.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmbError {
+    pub command: &'static str,
+    pub status: u32,
+}
+
+impl fmt::Display for SmbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SMB2 {} failed: {} (0x{:08X})", self.command, status_name(self.status), self.status)
+    }
+}
+
+impl Error for SmbError {}
+
+/// Function to get the name of an NTSTATUS answered by the SMB server.
+fn status_name(status: u32) -> &'static str {
+    match status {
+        STATUS_ACCESS_DENIED => "STATUS_ACCESS_DENIED",
+        STATUS_OBJECT_NAME_NOT_FOUND => "STATUS_OBJECT_NAME_NOT_FOUND",
+        STATUS_LOGON_FAILURE => "STATUS_LOGON_FAILURE",
+        STATUS_ACCOUNT_RESTRICTION => "STATUS_ACCOUNT_RESTRICTION",
+        STATUS_PIPE_NOT_AVAILABLE => "STATUS_PIPE_NOT_AVAILABLE",
+        STATUS_BAD_NETWORK_NAME => "STATUS_BAD_NETWORK_NAME",
+        0xC000_0072 => "STATUS_ACCOUNT_DISABLED",
+        0xC000_0071 => "STATUS_PASSWORD_EXPIRED",
+        0xC000_0224 => "STATUS_PASSWORD_MUST_CHANGE",
+        0xC000_00BB => "STATUS_NOT_SUPPORTED",
+        _ => "error",
+    }
+}
+
+/// SMB2 answer: status, header flags and the whole message (header included, the offsets start at it).
+struct Response {
+    status: u32,
+    session_id: u64,
+    tree_id: u32,
+    message: Vec<u8>,
+}
+
+impl Response {
+    fn u16(&self, offset: usize) -> Result<u16, Box<dyn Error>> {
+        let bytes = self.message.get(64 + offset..66 + offset).ok_or("truncated SMB2 response")?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, Box<dyn Error>> {
+        let bytes = self.message.get(64 + offset..68 + offset).ok_or("truncated SMB2 response")?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Interim answer of a command still running or unsolicited oplock break, never signed.
+    fn is_interim(&self) -> Result<bool, Box<dyn Error>> {
+        let flags = u32::from_le_bytes(self.message[16..20].try_into()?);
+        let message_id = u64::from_le_bytes(self.message[24..32].try_into()?);
+        Ok(message_id == UNSOLICITED_MESSAGE_ID || (self.status == STATUS_PENDING && flags & SMB2_FLAGS_ASYNC_COMMAND != 0))
+    }
+
+    /// Buffer at an offset from the header start.
+    fn buffer(&self, offset: usize, length: usize) -> Result<&[u8], Box<dyn Error>> {
+        Ok(self.message.get(offset..offset + length).ok_or("SMB2 buffer out of the response")?)
+    }
+}
+
+/// SMB2 connection to one host, with one session and one tree.
+pub struct SmbConnection {
+    stream: TcpStream,
+    timeout: Duration,
+    dialect: u16,
+    message_id: u64,
+    session_id: u64,
+    tree_id: u32,
+    signing_key: Option<[u8; 16]>,
+}
+
+impl SmbConnection {
+    /// Connect to port 445 of a host, through the proxy when set, and negotiate the dialect.
+    pub async fn connect(host: &str, proxy: Option<&Proxy>, timeout: Duration) -> Result<Self, Box<dyn Error>> {
+        let stream = match proxy {
+            Some(proxy) => proxy.connect(host, SMB_PORT).await?,
+            None => tokio::time::timeout(timeout, TcpStream::connect((host, SMB_PORT))).await
+                .map_err(|_| format!("connection to {host}:{SMB_PORT} timed out"))?
+                .map_err(|err| format!("can't connect to {host}:{SMB_PORT}: {err}"))?,
+        };
+        let mut connection = Self { stream, timeout, dialect: 0, message_id: 0, session_id: 0, tree_id: 0, signing_key: None };
+        connection.negotiate().await?;
+        Ok(connection)
+    }
+
+    async fn negotiate(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut body = Vec::with_capacity(40);
+        body.extend(36u16.to_le_bytes());
+        body.extend((DIALECTS.len() as u16).to_le_bytes());
+        body.extend(SMB2_NEGOTIATE_SIGNING_ENABLED.to_le_bytes());
+        body.extend([0u8; 2]);
+        // Capabilities
+        body.extend([0u8; 4]);
+        body.extend(fastrand::u128(..).to_le_bytes());
+        // ClientStartTime
+        body.extend([0u8; 8]);
+        for dialect in DIALECTS {
+            body.extend(dialect.to_le_bytes());
+        }
+        let response = self.request(SMB2_NEGOTIATE, "NEGOTIATE", &body).await?;
+        self.dialect = response.u16(4)?;
+        if !DIALECTS.contains(&self.dialect) {
+            return Err(format!("SMB server selected the unsupported dialect 0x{:04X}", self.dialect).into())
+        }
+        trace!("SMB dialect 0x{:04X} negotiated", self.dialect);
+        Ok(())
+    }
+
+    /// NTLMv2 session setup, the session key signs the next messages.
+    pub async fn login(&mut self, account: &NtlmAccount<'_>) -> Result<(), Box<dyn Error>> {
+        let response = self.session_setup(&spnego_init(&negotiate_message(true))).await?;
+        if response.status != STATUS_MORE_PROCESSING_REQUIRED {
+            return Err(SmbError { command: "SESSION_SETUP", status: response.status }.into())
+        }
+        self.session_id = response.session_id;
+        let token = response.buffer(response.u16(4)? as usize, response.u16(6)? as usize)?;
+        let start = token.windows(8).position(|window| window == NTLMSSP_SIGNATURE)
+            .ok_or("no NTLM challenge in the SESSION_SETUP response")?;
+        let challenge = parse_challenge(&token[start..])?;
+
+        let (authenticate, session_key) = authenticate_message(
+            &challenge, account, true, None, fastrand::u64(..).to_le_bytes(), filetime_now(),
+        );
+        let response = self.session_setup(&spnego_response(&authenticate)).await?;
+        if response.status != STATUS_SUCCESS {
+            return Err(SmbError { command: "SESSION_SETUP", status: response.status }.into())
+        }
+        let flags = response.u16(2)?;
+        if flags & (SMB2_SESSION_FLAG_IS_GUEST | SMB2_SESSION_FLAG_IS_NULL) != 0 {
+            return Err(format!("SMB session of {}\\{} mapped to the guest or anonymous account", account.domain, account.user).into())
+        }
+        self.signing_key = Some(session_key);
+        Ok(())
+    }
+
+    async fn session_setup(&mut self, token: &[u8]) -> Result<Response, Box<dyn Error>> {
+        let mut body = Vec::with_capacity(24 + token.len());
+        body.extend(25u16.to_le_bytes());
+        // Flags, SecurityMode
+        body.extend([0u8, SMB2_NEGOTIATE_SIGNING_ENABLED as u8]);
+        // Capabilities, Channel
+        body.extend([0u8; 8]);
+        body.extend(88u16.to_le_bytes());
+        body.extend((token.len() as u16).to_le_bytes());
+        // PreviousSessionId
+        body.extend([0u8; 8]);
+        body.extend(token);
+        self.exchange(SMB2_SESSION_SETUP, &body).await
+    }
+
+    /// Connect to the `IPC$` share of the host.
+    pub async fn tree_connect_ipc(&mut self, host: &str) -> Result<(), Box<dyn Error>> {
+        let path = utf16le(&format!("\\\\{host}\\IPC$"));
+        let mut body = Vec::with_capacity(8 + path.len());
+        body.extend(9u16.to_le_bytes());
+        body.extend([0u8; 2]);
+        body.extend(72u16.to_le_bytes());
+        body.extend((path.len() as u16).to_le_bytes());
+        body.extend(path);
+        let response = self.request(SMB2_TREE_CONNECT, "TREE_CONNECT", &body).await?;
+        self.tree_id = response.tree_id;
+        Ok(())
+    }
+
+    /// Open a named pipe of the `IPC$` tree, like `winreg`.
+    pub async fn open_pipe(&mut self, name: &str) -> Result<FileId, Box<dyn Error>> {
+        let name = utf16le(name);
+        let mut body = Vec::with_capacity(56 + name.len());
+        body.extend(57u16.to_le_bytes());
+        // SecurityFlags, RequestedOplockLevel
+        body.extend([0u8; 2]);
+        // ImpersonationLevel: Impersonation
+        body.extend(2u32.to_le_bytes());
+        // SmbCreateFlags, Reserved
+        body.extend([0u8; 16]);
+        // DesiredAccess: read, write, attributes and synchronize
+        body.extend(0x0012_019Fu32.to_le_bytes());
+        // FileAttributes
+        body.extend([0u8; 4]);
+        // ShareAccess: read and write
+        body.extend(3u32.to_le_bytes());
+        // CreateDisposition: FILE_OPEN
+        body.extend(1u32.to_le_bytes());
+        // CreateOptions
+        body.extend([0u8; 4]);
+        body.extend(120u16.to_le_bytes());
+        body.extend((name.len() as u16).to_le_bytes());
+        // CreateContextsOffset, CreateContextsLength
+        body.extend([0u8; 8]);
+        body.extend(name);
+        let response = self.request(SMB2_CREATE, "CREATE", &body).await?;
+        let mut file_id = [0u8; 16];
+        file_id.copy_from_slice(response.buffer(64 + 64, 16)?);
+        Ok(file_id)
+    }
+
+    /// Write a message on a pipe and read the answer, `true` when the answer continues in the next reads.
+    pub async fn transceive(&mut self, file_id: &FileId, input: &[u8]) -> Result<(Vec<u8>, bool), Box<dyn Error>> {
+        let mut body = Vec::with_capacity(56 + input.len());
+        body.extend(57u16.to_le_bytes());
+        body.extend([0u8; 2]);
+        body.extend(FSCTL_PIPE_TRANSCEIVE.to_le_bytes());
+        body.extend(file_id);
+        body.extend(120u32.to_le_bytes());
+        body.extend((input.len() as u32).to_le_bytes());
+        // MaxInputResponse, OutputOffset, OutputCount
+        body.extend([0u8; 12]);
+        body.extend(MAX_RESPONSE.to_le_bytes());
+        // Flags: SMB2_0_IOCTL_IS_FSCTL
+        body.extend(1u32.to_le_bytes());
+        body.extend([0u8; 4]);
+        body.extend(input);
+        let response = self.exchange(SMB2_IOCTL, &body).await?;
+        if response.status != STATUS_SUCCESS && response.status != STATUS_BUFFER_OVERFLOW {
+            return Err(SmbError { command: "IOCTL", status: response.status }.into())
+        }
+        let output = response.buffer(response.u32(32)? as usize, response.u32(36)? as usize)?.to_vec();
+        Ok((output, response.status == STATUS_BUFFER_OVERFLOW))
+    }
+
+    /// Read the next bytes of a pipe, `true` when the message continues in the next reads.
+    pub async fn read(&mut self, file_id: &FileId) -> Result<(Vec<u8>, bool), Box<dyn Error>> {
+        let mut body = Vec::with_capacity(49);
+        body.extend(49u16.to_le_bytes());
+        // Padding, Flags
+        body.extend([0x50u8, 0]);
+        body.extend(MAX_RESPONSE.to_le_bytes());
+        // Offset
+        body.extend([0u8; 8]);
+        body.extend(file_id);
+        // MinimumCount, Channel, RemainingBytes, ReadChannelInfoOffset, ReadChannelInfoLength, Buffer
+        body.extend([0u8; 17]);
+        let response = self.exchange(SMB2_READ, &body).await?;
+        if response.status != STATUS_SUCCESS && response.status != STATUS_BUFFER_OVERFLOW {
+            return Err(SmbError { command: "READ", status: response.status }.into())
+        }
+        let offset = *response.message.get(66).ok_or("truncated SMB2 response")? as usize;
+        let data = response.buffer(offset, response.u32(4)? as usize)?.to_vec();
+        Ok((data, response.status == STATUS_BUFFER_OVERFLOW))
+    }
+
+    /// Close a pipe.
+    pub async fn close(&mut self, file_id: &FileId) -> Result<(), Box<dyn Error>> {
+        let mut body = Vec::with_capacity(24);
+        body.extend(24u16.to_le_bytes());
+        body.extend([0u8; 6]);
+        body.extend(file_id);
+        self.request(SMB2_CLOSE, "CLOSE", &body).await?;
+        Ok(())
+    }
+
+    /// Log the session off, the connection is closed when dropped.
+    pub async fn logoff(&mut self) -> Result<(), Box<dyn Error>> {
+        self.request(SMB2_LOGOFF, "LOGOFF", &[4, 0, 0, 0]).await?;
+        Ok(())
+    }
+
+    /// Send a command and check the answer is a success.
+    async fn request(&mut self, command: u16, name: &'static str, body: &[u8]) -> Result<Response, Box<dyn Error>> {
+        let response = self.exchange(command, body).await?;
+        if response.status != STATUS_SUCCESS {
+            return Err(SmbError { command: name, status: response.status }.into())
+        }
+        Ok(response)
+    }
+
+    /// Send a command and wait for its final answer, whatever the status.
+    async fn exchange(&mut self, command: u16, body: &[u8]) -> Result<Response, Box<dyn Error>> {
+        let message = self.message(command, body);
+        let mut packet = (message.len() as u32).to_be_bytes().to_vec();
+        packet.extend(message);
+        tokio::time::timeout(self.timeout, self.send(&packet)).await
+            .map_err(|_| "SMB2 response timed out")?
+    }
+
+    async fn send(&mut self, packet: &[u8]) -> Result<Response, Box<dyn Error>> {
+        self.stream.write_all(packet).await?;
+        loop {
+            let response = self.receive().await?;
+            // Interim answer of a command still running, the final one follows
+            if response.is_interim()? {
+                continue
+            }
+            return Ok(response)
+        }
+    }
+
+    /// Build a message, signed after the session setup.
+    fn message(&mut self, command: u16, body: &[u8]) -> Vec<u8> {
+        let signed = self.signing_key.is_some() && command != SMB2_NEGOTIATE && command != SMB2_SESSION_SETUP;
+        let credit_charge: u16 = if self.dialect == 0x0210 { 1 } else { 0 };
+        let mut message = Vec::with_capacity(64 + body.len());
+        message.extend(b"\xfeSMB");
+        message.extend(64u16.to_le_bytes());
+        message.extend(credit_charge.to_le_bytes());
+        // Status, ChannelSequence
+        message.extend([0u8; 4]);
+        message.extend(command.to_le_bytes());
+        message.extend(CREDITS_REQUESTED.to_le_bytes());
+        message.extend((if signed { SMB2_FLAGS_SIGNED } else { 0 }).to_le_bytes());
+        // NextCommand
+        message.extend([0u8; 4]);
+        message.extend(self.message_id.to_le_bytes());
+        // Reserved (ProcessId)
+        message.extend(0xFEFFu32.to_le_bytes());
+        message.extend(self.tree_id.to_le_bytes());
+        message.extend(self.session_id.to_le_bytes());
+        message.extend([0u8; 16]);
+        message.extend(body);
+        self.message_id += 1;
+        if let (true, Some(key)) = (signed, &self.signing_key) {
+            let signature = sign(key, &message);
+            message[48..64].copy_from_slice(&signature);
+        }
+        message
+    }
+
+    async fn receive(&mut self) -> Result<Response, Box<dyn Error>> {
+        let mut length = [0u8; 4];
+        self.stream.read_exact(&mut length).await?;
+        let length = u32::from_be_bytes([0, length[1], length[2], length[3]]) as usize;
+        let mut message = vec![0u8; length];
+        self.stream.read_exact(&mut message).await?;
+        let response = parse_response(message)?;
+        // The answers are signed once the session is set up, like the requests
+        if let (Some(key), false) = (&self.signing_key, response.is_interim()?) {
+            verify_signature(key, &response.message)?;
+        }
+        Ok(response)
+    }
+}
+
+/// Function to read the header fields of an SMB2 response.
+fn parse_response(message: Vec<u8>) -> Result<Response, Box<dyn Error>> {
+    if message.len() < 64 || &message[..4] != b"\xfeSMB" {
+        return Err("not an SMB2 response, the host may only support SMB1".into())
+    }
+    Ok(Response {
+        status: u32::from_le_bytes(message[8..12].try_into()?),
+        tree_id: u32::from_le_bytes(message[36..40].try_into()?),
+        session_id: u64::from_le_bytes(message[40..48].try_into()?),
+        message,
+    })
+}
+
+/// Function to sign an SMB 2.0.2/2.1 message: HMAC-SHA256 of the session key, signature field zeroed.
+fn sign(key: &[u8; 16], message: &[u8]) -> [u8; 16] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(message);
+    let mut signature = [0u8; 16];
+    signature.copy_from_slice(&mac.finalize().into_bytes()[..16]);
+    signature
+}
+
+/// Function to check the signature of a response of the session, a response altered or unsigned on the way is rejected.
+fn verify_signature(key: &[u8; 16], message: &[u8]) -> Result<(), Box<dyn Error>> {
+    let flags = u32::from_le_bytes(message[16..20].try_into()?);
+    if flags & SMB2_FLAGS_SIGNED == 0 {
+        return Err("unsigned SMB2 response in a signed session, rejected".into())
+    }
+    let mut unsigned = message.to_vec();
+    unsigned[48..64].fill(0);
+    if sign(key, &unsigned) != message[48..64] {
+        return Err("invalid signature of the SMB2 response, rejected".into())
+    }
+    Ok(())
+}
+
+/// SPNEGO OID 1.3.6.1.5.5.2.
+const SPNEGO_OID: &[u8] = &[0x06, 0x06, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x02];
+/// NTLMSSP mechanism OID 1.3.6.1.4.1.311.2.2.10.
+const NTLMSSP_OID: &[u8] = &[0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x02, 0x0a];
+
+/// Function to wrap the NTLM NEGOTIATE_MESSAGE in a SPNEGO NegTokenInit.
+fn spnego_init(token: &[u8]) -> Vec<u8> {
+    let mech_types = der(0xa0, &der(0x30, NTLMSSP_OID));
+    let mech_token = der(0xa2, &der(0x04, token));
+    let init = der(0xa0, &der(0x30, &[mech_types, mech_token].concat()));
+    der(0x60, &[SPNEGO_OID, &init].concat())
+}
+
+/// Function to wrap the NTLM AUTHENTICATE_MESSAGE in a SPNEGO NegTokenResp.
+fn spnego_response(token: &[u8]) -> Vec<u8> {
+    der(0xa1, &der(0x30, &der(0xa2, &der(0x04, token))))
+}
+
+/// Function to encode a DER tag and length before its content.
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    match content.len() {
+        length @ 0..=0x7f => encoded.push(length as u8),
+        length @ 0x80..=0xff => encoded.extend([0x81, length as u8]),
+        length => encoded.extend([0x82, (length >> 8) as u8, length as u8]),
+    }
+    encoded.extend(content);
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::smb::*;
+
+    #[test]
+    pub fn test_spnego_tokens() {
+        let init = spnego_init(&negotiate_message(true));
+        assert_eq!(&init[..2], &[0x60, 0x40]);
+        assert_eq!(&init[2..10], SPNEGO_OID);
+        assert!(init.ends_with(&negotiate_message(true)));
+
+        let token = vec![0x41; 300];
+        let response = spnego_response(&token);
+        assert_eq!(&response[..4], &[0xa1, 0x82, 0x01, 0x38]);
+        assert_eq!(&response[response.len() - 304..response.len() - 300], &[0x04, 0x82, 0x01, 0x2c]);
+        assert_eq!(der(0x04, &[0; 0x80])[..3], [0x04, 0x81, 0x80]);
+    }
+
+    #[test]
+    pub fn test_parse_response() {
+        let mut message = b"\xfeSMB".to_vec();
+        message.extend([0u8; 60]);
+        message[8..12].copy_from_slice(&STATUS_MORE_PROCESSING_REQUIRED.to_le_bytes());
+        message[36..40].copy_from_slice(&5u32.to_le_bytes());
+        message[40..48].copy_from_slice(&0x1122u64.to_le_bytes());
+        message.extend([9, 0, 0, 0]);
+        let response = parse_response(message).unwrap();
+        assert_eq!(response.status, STATUS_MORE_PROCESSING_REQUIRED);
+        assert_eq!(response.tree_id, 5);
+        assert_eq!(response.session_id, 0x1122);
+        assert_eq!(response.u16(0).unwrap(), 9);
+        assert!(response.u32(2).is_err());
+
+        // SMB1 answer of a negotiate
+        assert!(parse_response(b"\xffSMB".iter().copied().chain([0u8; 60]).collect()).is_err());
+        let err = SmbError { command: "TREE_CONNECT", status: STATUS_ACCESS_DENIED };
+        assert_eq!(err.to_string(), "SMB2 TREE_CONNECT failed: STATUS_ACCESS_DENIED (0xC0000022)");
+    }
+
+    #[tokio::test]
+    pub async fn test_verify_signature() {
+        let key = [0x42u8; 16];
+        let mut signed = b"\xfeSMB".to_vec();
+        signed.extend([0u8; 60]);
+        signed[16..20].copy_from_slice(&SMB2_FLAGS_SIGNED.to_le_bytes());
+        signed.extend([9, 0, 0, 0]);
+        let signature = sign(&key, &signed);
+        signed[48..64].copy_from_slice(&signature);
+        assert!(verify_signature(&key, &signed).is_ok());
+        assert!(verify_signature(&[0x24u8; 16], &signed).unwrap_err().to_string().starts_with("invalid signature"));
+
+        let mut altered = signed.clone();
+        altered[64] = 8;
+        let mut unsigned = signed.clone();
+        unsigned[16..20].fill(0);
+        // Interim answer of a command still running, not signed
+        let mut interim = unsigned.clone();
+        interim[8..12].copy_from_slice(&STATUS_PENDING.to_le_bytes());
+        interim[16..20].copy_from_slice(&SMB2_FLAGS_ASYNC_COMMAND.to_le_bytes());
+
+        // Host answering the session
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let host = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            for message in [signed, interim, altered, unsigned] {
+                socket.write_all(&(message.len() as u32).to_be_bytes()).await.unwrap();
+                socket.write_all(&message).await.unwrap();
+            }
+            socket
+        });
+        let stream = TcpStream::connect(address).await.unwrap();
+        let mut smb = SmbConnection {
+            stream, timeout: Duration::from_secs(5), dialect: 0x0210, message_id: 0, session_id: 0, tree_id: 0, signing_key: Some(key),
+        };
+        assert_eq!(smb.receive().await.unwrap().u16(0).unwrap(), 9);
+        assert!(smb.receive().await.unwrap().is_interim().unwrap());
+        assert!(smb.receive().await.err().unwrap().to_string().starts_with("invalid signature"));
+        assert!(smb.receive().await.err().unwrap().to_string().starts_with("unsigned SMB2 response"));
+        drop(host.await.unwrap());
+    }
+}
@@ -0,0 +1,243 @@
+//! LDAP dump files, to parse a collection again without the DC
+//!
+//! `--dump <FILE>` writes the raw LDAP entries of the collection as they are stored, `--from-dump <FILE>` parses
+//! them again offline: same entries, same BloodHound json files, even with a newer RustHound-CE.
+//!
+//! The format is JSON lines, a header line then one line per entry, the attributes sorted by name and the binary
+//! values in base64:
+//!
+//! ```text
+//! {"format":"rusthound-ce-dump","version":1,"domain":"CONTOSO.LOCAL"}
+//! {"dn":"CN=Alice,CN=Users,DC=contoso,DC=local","attrs":{"sAMAccountName":["alice"]},"bin_attrs":{"objectSid":["AQUAAAAAAAUVAAAA..."]}}
+//! ```
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::Path;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::ldap::LdapSearchEntry;
+use crate::storage::{EntrySource, Storage};
+
+/// Format name of the header line.
+pub const DUMP_FORMAT: &str = "rusthound-ce-dump";
+
+/// Version of the format, a dump of a newer version is refused.
+pub const DUMP_FORMAT_VERSION: u32 = 1;
+
+/// First line of a dump.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DumpHeader {
+    pub format: String,
+    pub version: u32,
+    /// Domain of `-d` when the entries were collected.
+    pub domain: String,
+}
+
+/// Entry line of a dump.
+#[derive(Debug, Deserialize, Serialize)]
+struct DumpEntry {
+    dn: String,
+    #[serde(default)]
+    attrs: BTreeMap<String, Vec<String>>,
+    /// Binary values in base64.
+    #[serde(default)]
+    bin_attrs: BTreeMap<String, Vec<String>>,
+}
+
+impl From<&LdapSearchEntry> for DumpEntry {
+    fn from(entry: &LdapSearchEntry) -> Self {
+        DumpEntry {
+            dn: entry.dn.to_owned(),
+            attrs: entry.attrs.iter().map(|(name, values)| (name.to_owned(), values.to_owned())).collect(),
+            bin_attrs: entry.bin_attrs.iter()
+                .map(|(name, values)| (name.to_owned(), values.iter().map(|v| STANDARD.encode(v)).collect()))
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<DumpEntry> for LdapSearchEntry {
+    type Error = Box<dyn Error>;
+
+    fn try_from(entry: DumpEntry) -> Result<Self, Self::Error> {
+        let mut bin_attrs = HashMap::new();
+        for (name, values) in entry.bin_attrs {
+            let values = values.iter()
+                .map(|v| STANDARD.decode(v).map_err(|err| format!("invalid base64 value of {name} in {}: {err}", entry.dn)))
+                .collect::<Result<Vec<Vec<u8>>, _>>()?;
+            bin_attrs.insert(name, values);
+        }
+        Ok(LdapSearchEntry { dn: entry.dn, attrs: entry.attrs.into_iter().collect(), bin_attrs })
+    }
+}
+
+/// Storage writing each entry to the dump before the storage of the collection.
+pub struct DumpWriter<'a, S> {
+    writer: BufWriter<File>,
+    storage: &'a mut S,
+}
+
+impl<'a, S: Storage<LdapSearchEntry>> DumpWriter<'a, S> {
+    /// Create the dump of the domain, truncated if it already exists.
+    pub fn create(path: impl AsRef<Path>, domain: &str, storage: &'a mut S) -> Result<Self, Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let header = DumpHeader { format: DUMP_FORMAT.to_string(), version: DUMP_FORMAT_VERSION, domain: domain.to_uppercase() };
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+        Ok(Self { writer, storage })
+    }
+}
+
+impl<S: Storage<LdapSearchEntry>> Storage<LdapSearchEntry> for DumpWriter<'_, S> {
+    fn buffer_mut(&mut self) -> &mut Vec<LdapSearchEntry> {
+        self.storage.buffer_mut()
+    }
+
+    fn add(&mut self, item: LdapSearchEntry) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(&mut self.writer, &DumpEntry::from(&item))?;
+        self.writer.write_all(b"\n")?;
+        self.storage.add(item)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        self.storage.flush()
+    }
+}
+
+/// Entries of a dump, read one line at a time.
+#[derive(Debug)]
+pub struct DumpReader {
+    header: DumpHeader,
+    lines: Lines<BufReader<File>>,
+    line: usize,
+}
+
+impl DumpReader {
+    /// Open a dump and check its header.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let first = lines.next().transpose()?.unwrap_or_default();
+        let header: DumpHeader = serde_json::from_str(&first)
+            .ok()
+            .filter(|header: &DumpHeader| header.format == DUMP_FORMAT)
+            .ok_or(format!("{} is not a RustHound-CE dump", path.display()))?;
+        if header.version > DUMP_FORMAT_VERSION {
+            return Err(format!(
+                "{} is a dump of version {}, this RustHound-CE reads up to version {DUMP_FORMAT_VERSION}",
+                path.display(), header.version
+            ).into())
+        }
+        Ok(Self { header, lines, line: 1 })
+    }
+
+    /// Domain the entries were collected for.
+    pub fn domain(&self) -> &str {
+        &self.header.domain
+    }
+}
+
+impl Iterator for DumpReader {
+    type Item = Result<LdapSearchEntry, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err.into())),
+            };
+            self.line += 1;
+            if line.trim().is_empty() {
+                continue
+            }
+            return Some(
+                serde_json::from_str::<DumpEntry>(&line)
+                    .map_err(|err| format!("invalid dump entry on line {}: {err}", self.line).into())
+                    .and_then(LdapSearchEntry::try_from)
+            )
+        }
+    }
+}
+
+// For reading from a dump
+impl EntrySource for DumpReader {
+    type Iter = Self;
+
+    fn into_entry_iter(self) -> Self::Iter {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+
+    use crate::enums::sddl::{sddl_to_security_descriptor, sid_to_bytes};
+    use crate::storage::dump::*;
+
+    #[test]
+    pub fn test_dump_round_trip() {
+        // Entries as returned by the DC: text values, SIDs, GUIDs and a security descriptor
+        let domain_sid = "S-1-5-21-1004336348-1177238915-682003330";
+        let alice = LdapSearchEntry {
+            dn: "CN=Alice,CN=Users,DC=contoso,DC=local".to_string(),
+            attrs: HashMap::from([
+                ("sAMAccountName".to_string(), vec!["alice".to_string()]),
+                ("objectClass".to_string(), vec!["top".to_string(), "person".to_string(), "user".to_string()]),
+                ("description".to_string(), vec!["Ünïcode, \"quoted\"\nmultiline".to_string()]),
+            ]),
+            bin_attrs: HashMap::from([
+                ("objectSid".to_string(), vec![sid_to_bytes(&format!("{domain_sid}-1104")).unwrap()]),
+                ("objectGUID".to_string(), vec![vec![0x8a, 0x2f, 0, 0xff, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]]),
+                ("nTSecurityDescriptor".to_string(), vec![sddl_to_security_descriptor("O:DAD:(A;;GA;;;DA)", domain_sid, false).unwrap()]),
+            ]),
+        };
+        let empty = LdapSearchEntry { dn: "DC=contoso,DC=local".to_string(), attrs: HashMap::new(), bin_attrs: HashMap::new() };
+
+        let path = std::env::temp_dir().join(format!("rusthound-ce-dump-{}.jsonl", std::process::id()));
+        let mut entries: Vec<LdapSearchEntry> = Vec::new();
+        let mut dump = DumpWriter::create(&path, "contoso.local", &mut entries).unwrap();
+        dump.add(alice.to_owned()).unwrap();
+        dump.add(empty.to_owned()).unwrap();
+        dump.finish().unwrap();
+        // Still stored for the collection
+        assert_eq!(entries.len(), 2);
+
+        let reader = DumpReader::from_path(&path).unwrap();
+        assert_eq!(reader.domain(), "CONTOSO.LOCAL");
+        let read: Vec<LdapSearchEntry> = reader.into_entry_iter().collect::<Result<_, _>>().unwrap();
+        assert_eq!(read.len(), 2);
+        for (read, written) in read.iter().zip([&alice, &empty]) {
+            assert_eq!(read.dn, written.dn);
+            assert_eq!(read.attrs, written.attrs);
+            assert_eq!(read.bin_attrs, written.bin_attrs);
+        }
+
+        // Same entries, same bytes
+        let first = std::fs::read_to_string(&path).unwrap();
+        let mut entries: Vec<LdapSearchEntry> = Vec::new();
+        let mut dump = DumpWriter::create(&path, "contoso.local", &mut entries).unwrap();
+        dump.add(alice).unwrap();
+        dump.add(empty).unwrap();
+        dump.finish().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), first);
+        assert_eq!(first.lines().count(), 3);
+
+        // Not a dump, or a newer one
+        std::fs::write(&path, "{\"dn\":\"DC=contoso,DC=local\"}\n").unwrap();
+        assert!(DumpReader::from_path(&path).is_err());
+        std::fs::write(&path, format!("{{\"format\":\"{DUMP_FORMAT}\",\"version\":{},\"domain\":\"CONTOSO.LOCAL\"}}\n", DUMP_FORMAT_VERSION + 1)).unwrap();
+        assert!(DumpReader::from_path(&path).unwrap_err().to_string().contains("version 2"));
+        // Invalid entry line
+        std::fs::write(&path, format!("{{\"format\":\"{DUMP_FORMAT}\",\"version\":1,\"domain\":\"CONTOSO.LOCAL\"}}\n\n{{\"dn\":\"CN=Bob\",\"bin_attrs\":{{\"objectSid\":[\"not base64!\"]}}}}\n")).unwrap();
+        let error = DumpReader::from_path(&path).unwrap().next().unwrap().unwrap_err();
+        assert!(error.to_string().contains("objectSid"));
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -1,8 +1,10 @@
 pub mod buffer;
+pub mod dump;
 pub mod iter;
 use std::error::Error;
 
 pub use buffer::{BincodeObjectBuffer, Storage};
+pub use dump::{DumpReader, DumpWriter};
 
 use crate::ldap::LdapSearchEntry;
 pub use iter::DiskStorageReader;
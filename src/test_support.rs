@@ -0,0 +1,60 @@
+//! Shared helpers of the unit tests
+//!
+//! LDAP entries are built from `(attribute, value)` pairs, `|` separates the values of one attribute:
+//! `("objectClass", "top|person|organizationalPerson|user")`.
+//! The output round-trip writes the BloodHound json files of the results in a temporary directory and reads them back.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use ldap3::SearchEntry;
+
+use crate::api::{prepare_results_from_source, ADResults};
+use crate::args::Options;
+use crate::enums::sddl::sid_to_bytes;
+use crate::json::maker::make_result;
+use crate::ldap::{BindState, LdapSearchEntry};
+
+/// Function to build one search entry, with its binary attributes.
+pub fn search_entry(dn: &str, attrs: &[(&str, &str)], bin_attrs: HashMap<String, Vec<Vec<u8>>>) -> SearchEntry {
+    SearchEntry {
+        dn: dn.to_string(),
+        attrs: attrs.iter().map(|(k, v)| (k.to_string(), v.split('|').map(str::to_string).collect())).collect(),
+        bin_attrs,
+    }
+}
+
+/// Function to build one LDAP entry without binary attributes.
+pub fn entry(dn: &str, attrs: &[(&str, &str)]) -> LdapSearchEntry {
+    search_entry(dn, attrs, HashMap::new()).into()
+}
+
+/// Function to build one LDAP entry with its objectSid.
+pub fn entry_with_sid(dn: &str, sid: &str, attrs: &[(&str, &str)]) -> LdapSearchEntry {
+    search_entry(dn, attrs, HashMap::from([("objectSid".to_string(), vec![sid_to_bytes(sid).unwrap()])])).into()
+}
+
+/// Function to parse and check the entries as the collection does.
+pub async fn parse_entries(entries: Vec<LdapSearchEntry>, options: &Options) -> ADResults {
+    let total = entries.len();
+    prepare_results_from_source(entries, options, BindState::default(), Some(total)).await.unwrap()
+}
+
+/// Function to write the output files of the results and read them back, sorted by file name.
+/// The files go in a temporary directory of their own, removed once read.
+pub fn write_output(options: &Options, results: ADResults) -> Vec<(String, serde_json::Value)> {
+    static RUN: AtomicUsize = AtomicUsize::new(0);
+    let run = RUN.fetch_add(1, Ordering::SeqCst);
+    let path = std::env::temp_dir().join(format!("rusthound-ce-output-{}-{run}", std::process::id()));
+    std::fs::remove_dir_all(&path).ok();
+    let options = Options { path: path.display().to_string(), ..options.to_owned() };
+    make_result(&options, results).unwrap();
+
+    let mut files = Vec::new();
+    for file in std::fs::read_dir(&path).unwrap() {
+        let file = file.unwrap().path();
+        let json = serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+        files.push((file.file_name().unwrap().to_string_lossy().to_string(), json));
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    std::fs::remove_dir_all(path).ok();
+    files
+}
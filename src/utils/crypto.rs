@@ -1,4 +1,7 @@
 use sha1::{Sha1, Digest};
+use sha2::{Sha256, Sha384, Sha512};
+use x509_parser::prelude::*;
+use x509_parser::public_key::PublicKey;
 
 /// Easy function to get SHA1 hash
 pub fn calculate_sha1(data: &[u8]) -> String {
@@ -7,6 +10,148 @@ pub fn calculate_sha1(data: &[u8]) -> String {
     format!("{:X}", hasher.finalize())
 }
 
+/// Easy function to get SHA256 hash, same uppercase hex as [`calculate_sha1`]
+pub fn calculate_sha256(data: &[u8]) -> String {
+    format!("{:X}", Sha256::digest(data))
+}
+
+/// Function to get the current certificate of a renewed CA: the most recent (notBefore) parsable one, with its index.
+pub fn current_certificate(certificates: &[Vec<u8>]) -> Option<(usize, X509Certificate<'_>)> {
+    certificates.iter().enumerate()
+        .filter_map(|(index, der)| X509Certificate::from_der(der).ok().map(|(_, cert)| (index, cert)))
+        .max_by_key(|(_, cert)| cert.validity().not_before.timestamp())
+}
+
+/// Function to get the reason no certificate of a CA could be parsed, the DER error of the first one.
+pub fn certificate_parse_failure(certificates: &[Vec<u8>]) -> String {
+    match certificates.first().map(|der| X509Certificate::from_der(der)) {
+        Some(Err(err)) => format!("Failed to parse the CA certificate: {err}"),
+        _ => String::from("Failed to parse the CA certificate"),
+    }
+}
+
+/// Function to get the BasicConstraints of a certificate: the CA boolean and the path length constraint, 0 without one.
+pub fn basic_constraints(cert: &X509Certificate) -> (bool, u32) {
+    match cert.basic_constraints() {
+        Ok(Some(extension)) => (extension.value.ca, extension.value.path_len_constraint.unwrap_or(0)),
+        _ => (false, 0),
+    }
+}
+
+/// Function to get the Extended Key Usage OIDs of a certificate, empty without the extension.
+pub fn extended_key_usage(cert: &X509Certificate) -> Vec<String> {
+    let eku = match cert.extended_key_usage() {
+        Ok(Some(extension)) => extension.value,
+        _ => return Vec::new(),
+    };
+    [
+        (eku.any, "2.5.29.37.0"),
+        (eku.server_auth, "1.3.6.1.5.5.7.3.1"),
+        (eku.client_auth, "1.3.6.1.5.5.7.3.2"),
+        (eku.code_signing, "1.3.6.1.5.5.7.3.3"),
+        (eku.email_protection, "1.3.6.1.5.5.7.3.4"),
+        (eku.time_stamping, "1.3.6.1.5.5.7.3.8"),
+        (eku.ocsp_signing, "1.3.6.1.5.5.7.3.9"),
+    ]
+    .iter()
+    .filter(|(present, _)| *present)
+    .map(|(_, oid)| oid.to_string())
+    .chain(eku.other.iter().map(|oid| oid.to_id_string()))
+    .collect()
+}
+
+/// Public key of a certificate.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CertificateKey {
+    /// OID of the subject public key algorithm, like 1.2.840.113549.1.1.1 for RSA.
+    pub algorithm: String,
+    /// Modulus bit length of an RSA key, field size of an EC key, none for the other algorithms.
+    pub size: Option<u32>,
+    /// Named curve of an EC key, like P-256, the OID of a curve not listed.
+    pub curve: Option<String>,
+}
+
+/// Function to get the public key algorithm, size and curve of a certificate.
+pub fn certificate_key(cert: &X509Certificate) -> CertificateKey {
+    let spki = cert.public_key();
+    let algorithm = spki.algorithm.algorithm.to_id_string();
+    let (size, curve) = match (algorithm.as_str(), spki.parsed()) {
+        // rsaEncryption, RSASSA-PSS
+        ("1.2.840.113549.1.1.1" | "1.2.840.113549.1.1.10", Ok(PublicKey::RSA(rsa))) => {
+            // Unsigned big-endian modulus, without the leading zeroes of the DER INTEGER
+            let modulus = &rsa.modulus[rsa.modulus.iter().take_while(|byte| **byte == 0).count()..];
+            let bits = modulus.first().map_or(0, |first| modulus.len() as u32 * 8 - first.leading_zeros());
+            (Some(bits), None)
+        }
+        // id-ecPublicKey with a named curve
+        ("1.2.840.10045.2.1", Ok(PublicKey::EC(point))) => {
+            let oid = spki.algorithm.parameters.as_ref().and_then(|parameters| parameters.as_oid().ok()).map(|oid| oid.to_id_string());
+            match oid.as_deref() {
+                Some("1.2.840.10045.3.1.7") => (Some(256), Some("P-256".to_string())),
+                Some("1.3.132.0.34") => (Some(384), Some("P-384".to_string())),
+                Some("1.3.132.0.35") => (Some(521), Some("P-521".to_string())),
+                _ => (Some(point.key_size() as u32).filter(|size| *size > 0), oid),
+            }
+        }
+        _ => (None, None),
+    };
+    CertificateKey { algorithm, size, curve }
+}
+
+/// Function to get the names of a DER server certificate: the DNS and IP subject alternative names,
+/// or the common name without subject alternative name.
+pub fn certificate_host_names(certificate: &[u8]) -> Vec<String> {
+    let Ok((_, cert)) = X509Certificate::from_der(certificate) else {
+        return Vec::new()
+    };
+    let mut names: Vec<String> = match cert.subject_alternative_name() {
+        Ok(Some(extension)) => extension.value.general_names.iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(dns) => Some(dns.to_string()),
+                GeneralName::IPAddress(&[a, b, c, d]) => Some(std::net::Ipv4Addr::new(a, b, c, d).to_string()),
+                GeneralName::IPAddress(ip) => <[u8; 16]>::try_from(*ip).ok().map(|ip| std::net::Ipv6Addr::from(ip).to_string()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    if names.is_empty() {
+        names.extend(cert.subject().iter_common_name().filter_map(|cn| cn.as_str().ok()).map(str::to_string));
+    }
+    names
+}
+
+/// Function to check if a certificate name matches a host, case insensitive, a wildcard for the first label only.
+/// RFC: <https://www.rfc-editor.org/rfc/rfc6125#section-6.4.3>
+pub fn host_name_matches(name: &str, host: &str) -> bool {
+    let host = host.trim_end_matches('.');
+    match name.strip_prefix("*.") {
+        Some(parent) => host.split_once('.')
+            .is_some_and(|(label, rest)| !label.is_empty() && rest.eq_ignore_ascii_case(parent)),
+        None => name.trim_end_matches('.').eq_ignore_ascii_case(host),
+    }
+}
+
+/// Function to get the tls-server-end-point channel binding token of a DER server certificate.
+/// The certificate is hashed with the hash of its signature algorithm, SHA-256 for MD5 and SHA-1.
+/// RFC: <https://www.rfc-editor.org/rfc/rfc5929#section-4.1>
+pub fn tls_server_end_point(certificate: &[u8]) -> Option<Vec<u8>> {
+    let (_, cert) = X509Certificate::from_der(certificate).ok()?;
+    let hash = match cert.signature_algorithm.algorithm.to_id_string().as_str() {
+        // md5WithRSA, sha1WithRSA, sha256WithRSA, ecdsa-with-SHA1, ecdsa-with-SHA256, RSASSA-PSS
+        "1.2.840.113549.1.1.4" | "1.2.840.113549.1.1.5" | "1.2.840.113549.1.1.11" | "1.2.840.10045.4.1"
+        | "1.2.840.10045.4.3.2" | "1.2.840.113549.1.1.10" => Sha256::digest(certificate).to_vec(),
+        // sha384WithRSA, ecdsa-with-SHA384
+        "1.2.840.113549.1.1.12" | "1.2.840.10045.4.3.3" => Sha384::digest(certificate).to_vec(),
+        // sha512WithRSA, ecdsa-with-SHA512
+        "1.2.840.113549.1.1.13" | "1.2.840.10045.4.3.4" => Sha512::digest(certificate).to_vec(),
+        _ => return None,
+    };
+    let mut token = b"tls-server-end-point:".to_vec();
+    token.extend(hash);
+    Some(token)
+}
+
 /// Return encryption type msDS-SupportedEncryptionTypes to BloodHound-CE string format.
 /// <https://github.com/SpecterOps/SharpHoundCommon/blob/c953260325cbfd335ed2e9726cfe28d4b16357c8/src/CommonLib/Processors/LdapPropertyProcessor.cs#L731>
 pub fn convert_encryption_types(encryption_types: i32) -> Vec<String> {
@@ -44,4 +189,106 @@ pub fn convert_encryption_types(encryption_types: i32) -> Vec<String> {
     }
 
     supported_encryption_types
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+
+    use base64::Engine;
+
+    use crate::utils::crypto::*;
+
+    // contoso-DC01-CA, ecdsa-with-SHA256
+    pub(crate) const CERTIFICATE: &str = "MIIB6TCCAY+gAwIBAgIUKV9BdcM3bgEh8MqsG2TOMvdQO/0wCgYIKoZIzj0EAwIwSjEVMBMGCgmSJomT8ixkARkWBWxvY2FsMRcwFQYKCZImiZPyLGQBGRYHY29udG9zbzEYMBYGA1UEAwwPY29udG9zby1EQzAxLUNBMB4XDTI2MTAxNjExMDgzNFoXDTM2MTAxMzExMDgzNFowSjEVMBMGCgmSJomT8ixkARkWBWxvY2FsMRcwFQYKCZImiZPyLGQBGRYHY29udG9zbzEYMBYGA1UEAwwPY29udG9zby1EQzAxLUNBMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAExHuBnKVa6Xk6mgvRy/+pRPddHHHRDC9bySMRp1gwUSA/frMPd02u359phstii7hhnajDIYhJ8ZJYcqFDGHy156NTMFEwHQYDVR0OBBYEFLba0Q1CzhCaPbgmkfXlMe6GkvZvMB8GA1UdIwQYMBaAFLba0Q1CzhCaPbgmkfXlMe6GkvZvMA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDSAAwRQIgKiglIyXHycvF6wtOKNfy/lLnKoTCbgYp90l+6QbRMmwCIQCaj/mEOEkRXH3HGLQ0K6At28oPSmYOJhxm3h5wrsSQUg==";
+
+    // CA:TRUE without pathlen, CA:TRUE with pathlen:0, CA:TRUE with pathlen:2, CA:FALSE
+    const ROOT_CA: &str = "MIIBejCCAR+gAwIBAgIUTHknH27TU97lfxPU6Z8LMjnUxwQwCgYIKoZIzj0EAwIwEjEQMA4GA1UEAwwHcm9vdC1jYTAeFw0yNjEwMTYxMjE4NDlaFw0zNjEwMTMxMjE4NDlaMBIxEDAOBgNVBAMMB3Jvb3QtY2EwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAAT1uxRDeBenZqGvGouxknxfDXn8xAl/ztViVn8YgERIe60ipQSzGRt+OTFuoRKNrS4W8jo5ThTiFDad0SY/x9qao1MwUTAdBgNVHQ4EFgQUIKhS/wIBuS5Ul1oxBBv2q6DoSu0wHwYDVR0jBBgwFoAUIKhS/wIBuS5Ul1oxBBv2q6DoSu0wDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNJADBGAiEArndn7tt6iYt/kYMsMZvWmgQ2bnSK1E1aiL6hJu21EAACIQCxK5oQqkQunzIcKj8i1IBWbCHOdfoRb6zDyL/nurw32g==";
+    pub(crate) const ISSUING_CA: &str = "MIIBgjCCASigAwIBAgIUboiy5+HL2TR/ZgA8V16ihBdNhfUwCgYIKoZIzj0EAwIwFTETMBEGA1UEAwwKaXNzdWluZy1jYTAeFw0yNjEwMTYxMjE4NDlaFw0zNjEwMTMxMjE4NDlaMBUxEzARBgNVBAMMCmlzc3VpbmctY2EwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAAT1uxRDeBenZqGvGouxknxfDXn8xAl/ztViVn8YgERIe60ipQSzGRt+OTFuoRKNrS4W8jo5ThTiFDad0SY/x9qao1YwVDAdBgNVHQ4EFgQUIKhS/wIBuS5Ul1oxBBv2q6DoSu0wHwYDVR0jBBgwFoAUIKhS/wIBuS5Ul1oxBBv2q6DoSu0wEgYDVR0TAQH/BAgwBgEB/wIBADAKBggqhkjOPQQDAgNIADBFAiAusPpROz0Yl2Vbeq+WHpTR1WLFcVfeAG/rVT8pq0IeJwIhAKmy3M/Wn+FhQSUjuyCdLlsQ6Lfubfo54zhu25WYEqJk";
+    const POLICY_CA: &str = "MIIBfzCCASagAwIBAgIUCgjI1X/uehqxcPK1lCC3mIfvZWMwCgYIKoZIzj0EAwIwFDESMBAGA1UEAwwJcG9saWN5LWNhMB4XDTI2MTAxNjEyMTkxNVoXDTM2MTAxMzEyMTkxNVowFDESMBAGA1UEAwwJcG9saWN5LWNhMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE9bsUQ3gXp2ahrxqLsZJ8Xw15/MQJf87VYlZ/GIBESHutIqUEsxkbfjkxbqESja0uFvI6OU4U4hQ2ndEmP8famqNWMFQwHQYDVR0OBBYEFCCoUv8CAbkuVJdaMQQb9qug6ErtMB8GA1UdIwQYMBaAFCCoUv8CAbkuVJdaMQQb9qug6ErtMBIGA1UdEwEB/wQIMAYBAf8CAQIwCgYIKoZIzj0EAwIDRwAwRAIgEJjxagJhUKpzVfIbCYynbGZEOGQR4WU7TBEefO/K84sCIEWMfYaOO+0QoM/R6xBiwgFi01NPVrNUyjGFxLzycHXc";
+    // CA:TRUE with the clientAuth and smartcardLogon EKUs
+    pub(crate) const EKU_CA: &str = "MIIBmTCCAT6gAwIBAgIUCcsHQUsLXbBxOX0PR7mDFKdTwA0wCgYIKoZIzj0EAwIwETEPMA0GA1UEAwwGZWt1LWNhMB4XDTI2MTAxNjEyMzkzMFoXDTM2MTAxMzEyMzkzMFowETEPMA0GA1UEAwwGZWt1LWNhMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE2tS2e6qP2fd3Bz++tUEn8rDsKbgZtzmw+BxHHnxD+9A5/sAu7SCBRXW+XcILu/u0j4QOm519HEOpGYNMAR7EJaN0MHIwHQYDVR0OBBYEFNDE0kjivZhL+3OGYG5YoYgI9n5WMB8GA1UdIwQYMBaAFNDE0kjivZhL+3OGYG5YoYgI9n5WMA8GA1UdEwEB/wQFMAMBAf8wHwYDVR0lBBgwFgYIKwYBBQUHAwIGCisGAQQBgjcUAgIwCgYIKoZIzj0EAwIDSQAwRgIhAJh6PtTIgWHQm27GeztMWZslE12I/e9etUZCJCwp/yfKAiEAzcPKFNNrFJqlMWYCgdROj6H5icsCaFwhZpMypHzZsI0=";
+    // contoso-RSA-CA, RSA 2048 bits
+    pub(crate) const RSA_CA: &str = "MIIDczCCAlugAwIBAgIUOmI2eG4SQyUZhloUmRQ3mzSPj78wDQYJKoZIhvcNAQELBQAwSTEVMBMGCgmSJomT8ixkARkWBWxvY2FsMRcwFQYKCZImiZPyLGQBGRYHY29udG9zbzEXMBUGA1UEAwwOY29udG9zby1SU0EtQ0EwHhcNMjYxMDE2MTQxMzEzWhcNMzYxMDEzMTQxMzEzWjBJMRUwEwYKCZImiZPyLGQBGRYFbG9jYWwxFzAVBgoJkiaJk/IsZAEZFgdjb250b3NvMRcwFQYDVQQDDA5jb250b3NvLVJTQS1DQTCCASIwDQYJKoZIhvcNAQEBBQADggEPADCCAQoCggEBAKB/Sx+M/W64NIRBxMtSw/QXjwXM99eCn2UgbMoc8Ix5m2dZbKXbNN//nF8hCNwcCR529EiwtoipW3eBVGuVm/+0MdPSPGs3s8cGD0gZg+kCkoeqBGjRMMDx8RzidD4ho2v2xIR08LqKFycKhDQr3oK1qNjdM72PTCvYe5ZwijS17Tz14DdriIklyfLeJjpkhVc9h1uagd1gctkgYmMtQc9fjFMf/tdHZ0N51UAoRkb9mN/LpBVwrPGHToGiurVY6v5o/wsvuwWQQ45minZpzcN1qATr7kK0Uz18py/OH99JLICzGiP21+bigSao9YOBeUl3PdmgXlGX3gJmSvhT6TkCAwEAAaNTMFEwHQYDVR0OBBYEFPEU3BGEUgaCRH2zuDS1gbVoVl3gMB8GA1UdIwQYMBaAFPEU3BGEUgaCRH2zuDS1gbVoVl3gMA8GA1UdEwEB/wQFMAMBAf8wDQYJKoZIhvcNAQELBQADggEBACpmawsTcDClohj1ricmuJyDKD8+Ir99De32iBlvcAd+huXORDN3bp2tG0USUu//Ho14F46KCMvOrrC4S9hBUopwDWfD7+ty6iUdKox/oFHrKalo0GLHsM6eiT+OgJ43G7TYyoFMr7PIiwy2LOWr7VTpGnzHTHmbk6AS7nW3O9vJM4DgODEKVxz0TDTxIT3W61L7LnYHp0iTtYYmqLFR64KqY8Pr8EYpn8Uqe7aOBGdMaBnAuClCvzLg2da6wggoJSmBJ+ZraNK4/MCK0Gbd1yMFq4eu2jZ6Utkkg53Fyxr2X5GvMVeshUCbj31DevclovXr2aLmMbYXs88qBWEx7mY=";
+    const LEAF: &str = "MIIBbzCCARagAwIBAgIUNEFBL2bG8iF+QCjY+K1TtFIMTsswCgYIKoZIzj0EAwIwDzENMAsGA1UEAwwEbGVhZjAeFw0yNjEwMTYxMjE4NDlaFw0zNjEwMTMxMjE4NDlaMA8xDTALBgNVBAMMBGxlYWYwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAAT1uxRDeBenZqGvGouxknxfDXn8xAl/ztViVn8YgERIe60ipQSzGRt+OTFuoRKNrS4W8jo5ThTiFDad0SY/x9qao1AwTjAdBgNVHQ4EFgQUIKhS/wIBuS5Ul1oxBBv2q6DoSu0wHwYDVR0jBBgwFoAUIKhS/wIBuS5Ul1oxBBv2q6DoSu0wDAYDVR0TAQH/BAIwADAKBggqhkjOPQQDAgNHADBEAiADFPg8YQcGjVoiZKWE862v8jUoQRQ8RVLIjln7whzjkQIgGz19fAPFo6aCoGXgPQYAb8T++G8g5jAwyk6CnyQebN4=";
+
+    #[test]
+    pub fn test_calculate_hashes() {
+        // FIPS 180-2 known-answer vectors
+        assert_eq!(calculate_sha1(b"abc"), "A9993E364706816ABA3E25717850C26C9CD0D89D");
+        assert_eq!(calculate_sha256(b"abc"), "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD");
+        assert_eq!(calculate_sha256(b""), "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855");
+    }
+
+    #[test]
+    pub fn test_basic_constraints() {
+        for (certificate, expected) in [(ROOT_CA, (true, 0)), (ISSUING_CA, (true, 0)), (POLICY_CA, (true, 2)), (LEAF, (false, 0)), (CERTIFICATE, (true, 0))] {
+            let der = base64::engine::general_purpose::STANDARD.decode(certificate).unwrap();
+            let (_, cert) = X509Certificate::from_der(&der).unwrap();
+            assert_eq!(basic_constraints(&cert), expected);
+        }
+    }
+
+    #[test]
+    pub fn test_certificate_parse_failure() {
+        let der = base64::engine::general_purpose::STANDARD.decode(ISSUING_CA).unwrap();
+        let malformed = vec![der[..40].to_vec(), b"not a certificate".to_vec()];
+        assert!(current_certificate(&malformed).is_none());
+        let failure = certificate_parse_failure(&malformed);
+        assert!(failure.starts_with("Failed to parse the CA certificate: "), "{failure}");
+        assert_eq!(certificate_parse_failure(&[]), "Failed to parse the CA certificate");
+    }
+
+    #[test]
+    pub fn test_certificate_key() {
+        let der = base64::engine::general_purpose::STANDARD.decode(RSA_CA).unwrap();
+        let (_, cert) = X509Certificate::from_der(&der).unwrap();
+        assert_eq!(certificate_key(&cert), CertificateKey { algorithm: "1.2.840.113549.1.1.1".to_string(), size: Some(2048), curve: None });
+
+        let der = base64::engine::general_purpose::STANDARD.decode(CERTIFICATE).unwrap();
+        let (_, cert) = X509Certificate::from_der(&der).unwrap();
+        assert_eq!(certificate_key(&cert), CertificateKey { algorithm: "1.2.840.10045.2.1".to_string(), size: Some(256), curve: Some("P-256".to_string()) });
+    }
+
+    #[test]
+    pub fn test_extended_key_usage() {
+        // clientAuth and Microsoft smartcardLogon
+        let der = base64::engine::general_purpose::STANDARD.decode(EKU_CA).unwrap();
+        let (_, cert) = X509Certificate::from_der(&der).unwrap();
+        assert_eq!(extended_key_usage(&cert), vec!["1.3.6.1.5.5.7.3.2", "1.3.6.1.4.1.311.20.2.2"]);
+        // No EKU extension
+        let der = base64::engine::general_purpose::STANDARD.decode(CERTIFICATE).unwrap();
+        let (_, cert) = X509Certificate::from_der(&der).unwrap();
+        assert!(extended_key_usage(&cert).is_empty());
+    }
+
+    // CN=DC01, DNS:DC01.contoso.local, DNS:*.dc.contoso.local, IP:10.0.0.10
+    const DC_CERTIFICATE: &str = "MIIBrjCCAVSgAwIBAgIUMif/hEFo7at0NkkDYYzn3kUKhGowCgYIKoZIzj0EAwIwDzENMAsGA1UEAwwEREMwMTAeFw0yNjEwMTYxMjU5MTNaFw0zNjEwMTMxMjU5MTNaMA8xDTALBgNVBAMMBERDMDEwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAAThGKPj94pEAkBqkpTELPScpBf3Heofies2dMgSg6XhSYuXr/5L2KbtjRUtCyBKaRTcZ6MLA8zQDlzLF3dULGebo4GNMIGKMB0GA1UdDgQWBBQc7YuoTbsaGT+Jl9L6N8GH6RUsBzAfBgNVHSMEGDAWgBQc7YuoTbsaGT+Jl9L6N8GH6RUsBzAPBgNVHRMBAf8EBTADAQH/MDcGA1UdEQQwMC6CEkRDMDEuY29udG9zby5sb2NhbIISKi5kYy5jb250b3NvLmxvY2FshwQKAAAKMAoGCCqGSM49BAMCA0gAMEUCIB4+pVOtoAzmrbPO8akZQZ3xzbe+DAEBsxE9KA72YJ4eAiEA35raaq4xgZi8UanJwg1JKWt8K6l5M5nnLrnytDCkcWg=";
+    // CN=dc02.contoso.local without subject alternative name
+    const CN_CERTIFICATE: &str = "MIIBjzCCATWgAwIBAgIUaf/FQ3dxdXHaAm5rbkl2xKYVZC8wCgYIKoZIzj0EAwIwHTEbMBkGA1UEAwwSZGMwMi5jb250b3NvLmxvY2FsMB4XDTI2MTAxNjEyNTkxM1oXDTM2MTAxMzEyNTkxM1owHTEbMBkGA1UEAwwSZGMwMi5jb250b3NvLmxvY2FsMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEWbu7TxoEX89JCaHVFCXZSvv4XCBEADRvDz7Tko4IbX1MRULsjSvucHnd/nAFFGi/sy/afIqI2i/03oh0+JaQFaNTMFEwHQYDVR0OBBYEFKslwOs6IGpuu5HSYDp8k+YRffKxMB8GA1UdIwQYMBaAFKslwOs6IGpuu5HSYDp8k+YRffKxMA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDSAAwRQIhAN2BdhFeWCjWLESEDexGlo1kLcFP7jmrUMF6JN2GYf32AiAy7Fr711o1oOYpRYAhgtMyorK2j/X7jp75qybigdgoHg==";
+
+    #[test]
+    pub fn test_certificate_host_names() {
+        let der = base64::engine::general_purpose::STANDARD.decode(DC_CERTIFICATE).unwrap();
+        assert_eq!(certificate_host_names(&der), vec!["DC01.contoso.local", "*.dc.contoso.local", "10.0.0.10"]);
+        let der = base64::engine::general_purpose::STANDARD.decode(CN_CERTIFICATE).unwrap();
+        assert_eq!(certificate_host_names(&der), vec!["dc02.contoso.local"]);
+        assert!(certificate_host_names(b"not a certificate").is_empty());
+
+        assert!(host_name_matches("DC01.contoso.local", "dc01.CONTOSO.local"));
+        assert!(host_name_matches("*.dc.contoso.local", "dc03.dc.contoso.local"));
+        assert!(!host_name_matches("*.dc.contoso.local", "a.dc03.dc.contoso.local"));
+        assert!(!host_name_matches("*.dc.contoso.local", "dc.contoso.local"));
+        assert!(host_name_matches("10.0.0.10", "10.0.0.10"));
+        assert!(!host_name_matches("DC01.contoso.local", "DC01"));
+    }
+
+    #[test]
+    pub fn test_tls_server_end_point() {
+        let certificate = base64::engine::general_purpose::STANDARD.decode(CERTIFICATE).unwrap();
+        let token = tls_server_end_point(&certificate).unwrap();
+        let prefix = b"tls-server-end-point:";
+        assert_eq!(&token[..prefix.len()], prefix);
+        assert_eq!(&token[prefix.len()..], Sha256::digest(&certificate).as_slice());
+        assert_eq!(tls_server_end_point(b"not a certificate"), None);
+    }
+}
@@ -1,6 +1,6 @@
-use chrono::{NaiveDateTime, Local};
+use chrono::{Datelike, NaiveDateTime, Local};
 use std::convert::TryInto;
-use std::error::Error;
+use crate::error::RustHoundError;
 //use log::trace;
 // special thanks to: https://github.com/NH-RED-TEAM/RustHound/pull/30/commits/e4b5dbc0f147dd0f8efe64d515e0a18b69937aeb
 
@@ -12,14 +12,41 @@ pub fn convert_timestamp(timestamp: i64) -> i64
     epoch
 }
 
-/// Function to change string to epoch format.
-pub fn string_to_epoch(date: &str) -> Result<i64, Box<dyn Error>> {
+/// Epoch of the "never" sentinels: the `0x7FFFFFFFFFFFFFFF` FILETIME of `accountExpires` and the generalized
+/// times of the year 9999.
+pub const NEVER: i64 = -1;
+
+/// Function to change a FILETIME (100-nanosecond intervals since 1601) to epoch format.
+/// 0 stays 0 (not set, or "must change" for `pwdLastSet`), the never sentinel is [`NEVER`].
+pub fn filetime_to_epoch(filetime: i64) -> Result<i64, RustHoundError> {
+    match filetime {
+        0 => Ok(0),
+        i64::MAX => Ok(NEVER),
+        filetime if filetime < 0 => Err(RustHoundError::InvalidValue(format!("negative FILETIME {filetime}"))),
+        filetime => Ok(convert_timestamp(filetime)),
+    }
+}
+
+/// Function to change string to epoch format: a generalized time like `whenCreated` (`yyyyMMddHHmmss.0Z`), or an
+/// integer FILETIME like `accountExpires` or `pwdLastSet`. The never sentinels of both are [`NEVER`].
+pub fn string_to_epoch(date: &str) -> Result<i64, RustHoundError> {
+    let date = date.trim();
+    // FILETIME: any integer but the 14 digits of a generalized time without its fraction
+    if date.len() != 14 {
+        if let Ok(filetime) = date.parse::<i64>() {
+            return filetime_to_epoch(filetime)
+        }
+    }
     // Extract the portion before the dot
     // yyyyMMddHHmmss.0z to epoch format
-    let str_representation = date.split('.').next().ok_or("Invalid date format")?;
+    let str_representation = date.split(['.', 'Z', 'z']).next().unwrap_or(date);
     
     // Parse the date and convert to epoch
-    let naive_date = NaiveDateTime::parse_from_str(str_representation, "%Y%m%d%H%M%S")?;
+    let naive_date = NaiveDateTime::parse_from_str(str_representation, "%Y%m%d%H%M%S")
+        .map_err(|source| RustHoundError::DateParse { value: date.to_owned(), source })?;
+    if naive_date.year() == 9999 {
+        return Ok(NEVER)
+    }
     Ok(naive_date.and_utc().timestamp())
 }
 
@@ -43,10 +70,11 @@ pub fn return_current_fulldate() -> String
 }
 
 /// Function to convert pKIExpirationPeriod Vec<u8> format to i64 Windows format (nanoseconds).
-pub fn filetime_to_span(filetime: Vec<u8>) -> Result<i64, Box<dyn Error>> {
+pub fn filetime_to_span(filetime: Vec<u8>) -> Result<i64, RustHoundError> {
     if filetime.len() >= 8 {
         // Convert the first 8 bytes into an i64 using native endianness
-        let span = i64::from_ne_bytes(filetime[0..8].try_into()?);
+        let span = i64::from_ne_bytes(filetime[0..8].try_into()
+            .map_err(|_| RustHoundError::InvalidValue(format!("{} bytes for a FILETIME span", filetime.len())))?);
         return Ok(span);
     }
     Ok(0)
@@ -97,4 +125,29 @@ pub fn span_to_string(span: i64) -> String {
     } else {
         "less than a minute".to_string()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::utils::date::*;
+
+    #[test]
+    pub fn test_string_to_epoch() {
+        // Generalized time, with and without its fraction
+        assert_eq!(string_to_epoch("20240101120000.0Z").unwrap(), 1704110400);
+        assert_eq!(string_to_epoch("20240101120000Z").unwrap(), 1704110400);
+        assert_eq!(string_to_epoch("20240101120000").unwrap(), 1704110400);
+        assert_eq!(string_to_epoch("99991231235959.0Z").unwrap(), NEVER);
+        assert!(matches!(string_to_epoch("2024-01-01").unwrap_err(), RustHoundError::DateParse { .. }));
+
+        // FILETIME: 2024-01-01 12:00:00 UTC, not set and never
+        assert_eq!(string_to_epoch("133485840000000000").unwrap(), 1704110400);
+        assert_eq!(string_to_epoch("0").unwrap(), 0);
+        assert_eq!(string_to_epoch("9223372036854775807").unwrap(), NEVER);
+        assert!(matches!(string_to_epoch("-1").unwrap_err(), RustHoundError::InvalidValue(_)));
+
+        assert_eq!(filetime_to_epoch(i64::MAX).unwrap(), NEVER);
+        assert_eq!(filetime_to_epoch(133485840000000000).unwrap(), 1704110400);
+    }
+}
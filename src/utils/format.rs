@@ -12,4 +12,79 @@ pub fn domain_to_dc(domain: &str) -> String {
         }
     }
     dc
-}
\ No newline at end of file
+}
+
+/// Fonction to parse CN=USER,DC=DOMAIN,DC=LOCAL to DOMAIN.LOCAL
+pub fn dn_to_domain(dn: &str) -> String {
+    dn.split(',')
+        .map(str::trim)
+        .filter(|rdn| rdn.get(..3).is_some_and(|prefix| prefix.eq_ignore_ascii_case("DC=")))
+        .map(|rdn| &rdn[3..])
+        .collect::<Vec<&str>>()
+        .join(".")
+        .to_uppercase()
+}
+
+/// Fonction to split `host`, `host:port`, `192.168.1.10:636`, `[2001:db8::10]:636` or `2001:db8::10`
+/// in the host (without brackets) and the port
+pub fn parse_host_port(value: &str) -> Result<(String, Option<u16>), String> {
+    let value = value.trim();
+    let parse_port = |port: &str| port.parse::<u16>().map(Some).map_err(|_| format!("invalid port '{port}' in {value}"));
+    let (host, port) = if let Some(rest) = value.strip_prefix('[') {
+        let (host, after) = rest.split_once(']').ok_or_else(|| format!("missing ']' in {value}"))?;
+        if host.parse::<std::net::Ipv6Addr>().is_err() {
+            return Err(format!("{host} is not an IPv6 address"))
+        }
+        match after {
+            "" => (host, None),
+            _ => (host, parse_port(after.strip_prefix(':').ok_or_else(|| format!("invalid address {value}"))?)?),
+        }
+    } else if value.parse::<std::net::Ipv6Addr>().is_ok() {
+        // IPv6 without brackets: no port
+        (value, None)
+    } else {
+        match value.split_once(':') {
+            Some((host, port)) => (host, parse_port(port)?),
+            None => (value, None),
+        }
+    };
+    if host.is_empty() {
+        return Err(format!("missing host in {value}"))
+    }
+    Ok((host.to_owned(), port))
+}
+
+/// Fonction to write a host in an URL, an IPv6 address between brackets
+pub fn url_host(host: &str) -> String {
+    match host.parse::<std::net::Ipv6Addr>() {
+        Ok(_) => format!("[{host}]"),
+        Err(_) => host.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::utils::format::*;
+
+    #[test]
+    pub fn test_parse_host_port() {
+        assert_eq!(parse_host_port("dc01.contoso.local"), Ok(("dc01.contoso.local".to_string(), None)));
+        assert_eq!(parse_host_port("DC01:3268"), Ok(("DC01".to_string(), Some(3268))));
+        assert_eq!(parse_host_port("192.168.1.10"), Ok(("192.168.1.10".to_string(), None)));
+        assert_eq!(parse_host_port("192.168.1.10:636"), Ok(("192.168.1.10".to_string(), Some(636))));
+        assert_eq!(parse_host_port("[2001:db8::10]:636"), Ok(("2001:db8::10".to_string(), Some(636))));
+        assert_eq!(parse_host_port("[2001:db8::10]"), Ok(("2001:db8::10".to_string(), None)));
+        assert_eq!(parse_host_port("2001:db8::10"), Ok(("2001:db8::10".to_string(), None)));
+
+        assert!(parse_host_port("dc01:ldaps").is_err());
+        assert!(parse_host_port("[2001:db8::10").is_err());
+        assert!(parse_host_port("[2001:db8::10]636").is_err());
+        assert!(parse_host_port("[dc01]:636").is_err());
+        assert!(parse_host_port(":636").is_err());
+
+        assert_eq!(url_host("2001:db8::10"), "[2001:db8::10]");
+        assert_eq!(url_host("192.168.1.10"), "192.168.1.10");
+        assert_eq!(url_host("dc01.contoso.local"), "dc01.contoso.local");
+    }
+}
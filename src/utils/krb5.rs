@@ -0,0 +1,296 @@
+//! Kerberos credential cache and configuration used by the GSSAPI bind
+//!
+//! With `-k` the GSSAPI library takes the ticket from `KRB5CCNAME` (like the impacket getTGT output) and the
+//! realms from `KRB5_CONFIG`, no password is needed. The default principal of a `FILE:` cache is read here
+//! to show which account binds and to fail early on a missing or invalid cache.
+//!
+//! With `-k -u -p` and no cache a TGT is requested with the MIT or Heimdal `kinit` in a private cache first.
+//!
+//! The client realm is the one of `--realm` when the account is in another realm than the domain collected
+//! (cross-realm trust), the service principal is always `ldap/<DC FQDN>`: its realm is found by the Kerberos library
+//! from the `[domain_realm]` of the krb5.conf or the referrals of the KDC.
+use std::error::Error;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Function to get the path of the `FILE:` credential cache set in KRB5CCNAME.
+/// The other cache types (KEYRING:, KCM:, DIR:, MEMORY:) are only read by the GSSAPI library.
+pub fn ccache_path() -> Option<String> {
+    let name = std::env::var("KRB5CCNAME").ok()?;
+    match name.split_once(':') {
+        Some(("FILE", path)) => Some(path.to_string()),
+        Some((kind, _)) if kind.len() > 1 => None,
+        // No type or a Windows drive letter: a file
+        _ => Some(name),
+    }
+}
+
+/// Function to get the default principal (`user@REALM`) of a MIT credential cache, version 3 or 4.
+/// MIT: <https://web.mit.edu/kerberos/krb5-latest/doc/formats/ccache_file_format.html>
+pub fn ccache_principal(data: &[u8]) -> Result<String, Box<dyn Error>> {
+    let mut offset = 0;
+    let mut read = |len: usize| -> Result<&[u8], Box<dyn Error>> {
+        let bytes = data.get(offset..offset + len).ok_or("truncated credential cache")?;
+        offset += len;
+        Ok(bytes)
+    };
+    let u16_be = |bytes: &[u8]| u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+    let u32_be = |bytes: &[u8]| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+
+    match read(2)? {
+        [0x05, 0x04] => {
+            // Header tags (KDC time offset)
+            let length = u16_be(read(2)?);
+            read(length)?;
+        }
+        [0x05, 0x03] => {}
+        version => return Err(format!("unsupported credential cache version {version:02X?}").into()),
+    }
+    let _name_type = read(4)?;
+    let components = u32_be(read(4)?);
+    let length = u32_be(read(4)?);
+    let realm = String::from_utf8_lossy(read(length)?).to_string();
+    let mut names = Vec::new();
+    for _ in 0..components {
+        let length = u32_be(read(4)?);
+        names.push(String::from_utf8_lossy(read(length)?).to_string());
+    }
+    Ok(format!("{}@{realm}", names.join("/")))
+}
+
+/// Function to use a custom krb5.conf for the GSSAPI bind, set before any LDAP connection.
+pub fn use_krb5_config(path: &str) -> Result<(), Box<dyn Error>> {
+    if !std::path::Path::new(path).is_file() {
+        return Err(format!("krb5.conf file not found: {path}").into())
+    }
+    std::env::set_var("KRB5_CONFIG", path);
+    Ok(())
+}
+
+/// Function to use a credential cache file for the GSSAPI bind instead of KRB5CCNAME, set before any LDAP connection.
+/// Return the default principal of the cache.
+pub fn use_ccache(path: &str) -> Result<String, Box<dyn Error>> {
+    let data = std::fs::read(path).map_err(|err| format!("can't read the Kerberos credential cache {path}: {err}"))?;
+    let principal = ccache_principal(&data).map_err(|err| format!("invalid Kerberos credential cache {path}: {err}"))?;
+    std::env::set_var("KRB5CCNAME", format!("FILE:{path}"));
+    Ok(principal)
+}
+
+/// Principals of the GSSAPI bind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KerberosPrincipals {
+    /// `user@REALM` of `-u`, none with the ticket of a credential cache.
+    pub client: Option<String>,
+    /// `ldap/<DC FQDN>`.
+    pub service: String,
+    /// Realm of the DC in the `--krb5-conf`, none when found by the Kerberos library.
+    pub service_realm: Option<String>,
+}
+
+/// Function to get the Kerberos principal of a LDAP username: `user@REALM` with the realm of `--realm`, else the
+/// suffix of `user@domain.local`, else the domain collected. A UPN suffix is not always a realm, `--realm` wins.
+pub fn user_principal(username: &str, domain: &str, realm: Option<&str>) -> String {
+    let (user, suffix) = match username.split_once('@') {
+        Some((user, suffix)) => (user, suffix),
+        None => (username, domain),
+    };
+    format!("{user}@{}", realm.unwrap_or(suffix).to_uppercase())
+}
+
+/// Function to get the service principal of the GSSAPI bind, from the DC FQDN whatever the realm of the client.
+pub fn service_principal(ldapfqdn: &str) -> String {
+    format!("ldap/{}", ldapfqdn.trim_end_matches('.').to_lowercase())
+}
+
+/// Function to get the realm of a host from the `[domain_realm]` section of a krb5.conf: the host itself, then
+/// `.domain` and `domain` of its parent domains, the closest first. None when the Kerberos library finds it (DNS,
+/// referrals).
+pub fn host_realm(krb5_conf: &str, host: &str) -> Option<String> {
+    let mut section = String::new();
+    let mut mappings: Vec<(String, String)> = Vec::new();
+    for line in krb5_conf.lines() {
+        let line = line.trim();
+        if line.starts_with('#') || line.starts_with(';') || line.is_empty() {
+            continue
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            section = name.trim().to_lowercase();
+            continue
+        }
+        if section != "domain_realm" {
+            continue
+        }
+        if let Some((name, realm)) = line.split_once('=') {
+            mappings.push((name.trim().to_lowercase(), realm.trim().to_string()));
+        }
+    }
+    let host = host.trim_end_matches('.').to_lowercase();
+    let realm_of = |name: &str| mappings.iter().find(|(mapped, _)| mapped == name).map(|(_, realm)| realm.to_owned());
+    realm_of(&host).or_else(|| {
+        let mut parent = host.as_str();
+        while let Some((_, rest)) = parent.split_once('.') {
+            if let Some(realm) = realm_of(&format!(".{rest}")).or_else(|| realm_of(rest)) {
+                return Some(realm)
+            }
+            parent = rest;
+        }
+        None
+    })
+}
+
+/// Function to request a TGT with the password of the principal (AS exchange by `kinit`) in a private
+/// credential cache, then used by the GSSAPI bind. Return the path of the cache.
+pub fn request_tgt(principal: &str, password: &str) -> Result<String, Box<dyn Error>> {
+    let path = std::env::temp_dir().join(format!("rusthound-ce-{}.ccache", std::process::id()));
+    let path = path.to_string_lossy().to_string();
+    // MIT reads the password on stdin, Heimdal with --password-file
+    let heimdal = Command::new("kinit").arg("--version").output()
+        .map_err(|err| format!("can't run kinit to request a TGT for {principal} ({err}), install the Kerberos client or set KRB5CCNAME"))?;
+    let heimdal = String::from_utf8_lossy(&heimdal.stdout).contains("Heimdal");
+    let mut command = Command::new("kinit");
+    command.arg("-c").arg(format!("FILE:{path}"));
+    if heimdal {
+        command.arg("--password-file=STDIN");
+    }
+    let mut kinit = command.arg(principal)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = kinit.stdin.take() {
+        writeln!(stdin, "{password}")?;
+    }
+    let output = kinit.wait_with_output()?;
+    if !output.status.success() {
+        let reason = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("the KDC refused the TGT request for {principal}: {}", reason.trim()).into())
+    }
+    std::env::set_var("KRB5CCNAME", format!("FILE:{path}"));
+    Ok(path)
+}
+
+/// Function to check the Kerberos credentials before the GSSAPI bind: a `FILE:` cache of KRB5CCNAME must exist.
+/// The other cache types and the default cache are checked by the GSSAPI library.
+pub fn check_credentials() -> Result<(), Box<dyn Error>> {
+    match ccache_path() {
+        Some(path) if !std::path::Path::new(&path).is_file() => Err(format!(
+            "no Kerberos credentials, the credential cache {path} of KRB5CCNAME doesn't exist: request a TGT (getTGT.py, kinit), use --ccache or give -u/-p"
+        ).into()),
+        _ => Ok(()),
+    }
+}
+
+/// Function to get why the Kerberos authentication failed when the password bind would work: clock skew with the
+/// DC (KRB_AP_ERR_SKEW), no service principal for the DC name or no ticket. None for the other errors.
+pub fn fallback_reason(error: &str) -> Option<&'static str> {
+    let error = error.to_lowercase();
+    if error.contains("clock skew") || error.contains("krb_ap_err_skew") {
+        Some("clock skew")
+    } else if error.contains("server not found in kerberos database") || error.contains("kdc_err_s_principal_unknown") {
+        Some("no service principal")
+    } else if error.contains("no kerberos credentials") || error.contains("no credentials") || error.contains("credentials cache") {
+        Some("no Kerberos ticket")
+    } else {
+        None
+    }
+}
+
+/// Function to explain a failed GSSAPI bind from the GSSAPI (MIT, Heimdal) or SSPI error.
+pub fn bind_error_hint(error: &str) -> Option<&'static str> {
+    let error = error.to_lowercase();
+    if error.contains("no kerberos credentials") || error.contains("no credentials") || error.contains("credentials cache") {
+        Some("no Kerberos ticket: set KRB5CCNAME, use --ccache or give -u/-p to request a TGT")
+    } else if error.contains("expired") {
+        Some("the Kerberos ticket expired, request a new TGT")
+    } else if error.contains("server not found in kerberos database") {
+        Some("no service principal ldap/<DC>, use -f with the DC FQDN")
+    } else if error.contains("clock skew") {
+        Some("the clock is not in sync with the DC")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::utils::krb5::*;
+
+    fn counted(value: &str) -> Vec<u8> {
+        let mut bytes = (value.len() as u32).to_be_bytes().to_vec();
+        bytes.extend(value.as_bytes());
+        bytes
+    }
+
+    #[test]
+    pub fn test_ccache_principal() {
+        // Version 4 with the KDC time offset header, like impacket getTGT
+        let mut ccache = vec![0x05, 0x04, 0x00, 0x0c, 0x00, 0x01, 0x00, 0x08, 0, 0, 0, 0, 0, 0, 0, 0];
+        ccache.extend(1u32.to_be_bytes());
+        ccache.extend(1u32.to_be_bytes());
+        ccache.extend(counted("CONTOSO.LOCAL"));
+        ccache.extend(counted("alice"));
+        assert_eq!(ccache_principal(&ccache).unwrap(), "alice@CONTOSO.LOCAL");
+
+        // Version 3 without header, two components
+        let mut ccache = vec![0x05, 0x03];
+        ccache.extend(2u32.to_be_bytes());
+        ccache.extend(2u32.to_be_bytes());
+        ccache.extend(counted("CONTOSO.LOCAL"));
+        ccache.extend(counted("HTTP"));
+        ccache.extend(counted("web01.contoso.local"));
+        assert_eq!(ccache_principal(&ccache).unwrap(), "HTTP/web01.contoso.local@CONTOSO.LOCAL");
+
+        assert!(ccache_principal(&ccache[..10]).is_err());
+        assert!(ccache_principal(&[0x05, 0x02, 0, 0]).is_err());
+    }
+
+    #[test]
+    pub fn test_use_ccache() {
+        let path = std::env::temp_dir().join("rusthound-ce-test.ccache");
+        let mut ccache = vec![0x05, 0x03];
+        ccache.extend(1u32.to_be_bytes());
+        ccache.extend(1u32.to_be_bytes());
+        ccache.extend(counted("CONTOSO.LOCAL"));
+        ccache.extend(counted("alice"));
+        std::fs::write(&path, &ccache).unwrap();
+        assert_eq!(use_ccache(path.to_str().unwrap()).unwrap(), "alice@CONTOSO.LOCAL");
+        assert_eq!(ccache_path().as_deref(), path.to_str());
+        assert!(check_credentials().is_ok());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(check_credentials().unwrap_err().to_string().starts_with("no Kerberos credentials"));
+        assert!(use_ccache(path.to_str().unwrap()).is_err());
+        std::env::remove_var("KRB5CCNAME");
+    }
+
+    #[test]
+    pub fn test_bind_error_hint() {
+        assert!(bind_error_hint("No Kerberos credentials available (default cache: FILE:/tmp/krb5cc_0)").unwrap().contains("--ccache"));
+        assert!(bind_error_hint("Ticket expired").unwrap().contains("expired"));
+        assert!(bind_error_hint("Server not found in Kerberos database").unwrap().contains("-f"));
+        assert!(bind_error_hint("Clock skew too great").is_some());
+        assert_eq!(bind_error_hint("Invalid credentials"), None);
+    }
+
+    #[test]
+    pub fn test_fallback_reason() {
+        assert_eq!(fallback_reason("Clock skew too great while getting initial credentials"), Some("clock skew"));
+        assert_eq!(fallback_reason("Server not found in Kerberos database"), Some("no service principal"));
+        assert_eq!(fallback_reason("no Kerberos credentials, the credential cache /tmp/x of KRB5CCNAME doesn't exist"), Some("no Kerberos ticket"));
+        // The password would fail the same way
+        assert_eq!(fallback_reason("Preauthentication failed"), None);
+        assert_eq!(fallback_reason("Ticket expired"), None);
+    }
+
+    #[test]
+    pub fn test_user_principal() {
+        assert_eq!(user_principal("alice@contoso.local", "contoso.local", None), "alice@CONTOSO.LOCAL");
+        assert_eq!(user_principal("alice", "child.contoso.local", None), "alice@CHILD.CONTOSO.LOCAL");
+        assert_eq!(user_principal("alice@FABRIKAM.LOCAL", "contoso.local", None), "alice@FABRIKAM.LOCAL");
+        // Cross-realm: the realm of --realm, even with an alternative UPN suffix
+        assert_eq!(user_principal("alice", "contoso.local", Some("fabrikam.local")), "alice@FABRIKAM.LOCAL");
+        assert_eq!(user_principal("alice@fabrikam.com", "contoso.local", Some("FABRIKAM.LOCAL")), "alice@FABRIKAM.LOCAL");
+        assert_eq!(service_principal("DC01.contoso.local."), "ldap/dc01.contoso.local");
+    }
+}
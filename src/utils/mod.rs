@@ -3,5 +3,7 @@
 pub mod crypto;
 pub mod date;
 pub mod format;
+pub mod krb5;
+pub mod ntlm;
 #[cfg(feature = "noargs")]
 pub mod exec;
\ No newline at end of file
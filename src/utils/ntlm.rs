@@ -0,0 +1,368 @@
+//! NTLMv2 authentication of the SMB sessions and the LDAP binds
+//!
+//! The CA registry is read over SMB with the password of the LDAP bind ([MS-NLMP]). Only NTLMv2 is answered,
+//! without key exchange: the exported session key is the session base key, which signs the SMB messages.
+//! The LDAP binds don't negotiate signing, over TLS they carry the channel binding hash of the DC certificate.
+use hmac::{Hmac, Mac};
+use md4::{Digest, Md4};
+use md5::Md5;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Signature of the NTLMSSP messages.
+pub const NTLMSSP_SIGNATURE: &[u8; 8] = b"NTLMSSP\0";
+
+const NEGOTIATE_UNICODE: u32 = 0x0000_0001;
+const REQUEST_TARGET: u32 = 0x0000_0004;
+const NEGOTIATE_SIGN: u32 = 0x0000_0010;
+const NEGOTIATE_NTLM: u32 = 0x0000_0200;
+const NEGOTIATE_ALWAYS_SIGN: u32 = 0x0000_8000;
+const NEGOTIATE_EXTENDED_SESSIONSECURITY: u32 = 0x0008_0000;
+const NEGOTIATE_TARGET_INFO: u32 = 0x0080_0000;
+const NEGOTIATE_128: u32 = 0x2000_0000;
+const NEGOTIATE_56: u32 = 0x8000_0000;
+/// Flags of the NEGOTIATE_MESSAGE, no key exchange and no version.
+const NEGOTIATE_FLAGS: u32 = NEGOTIATE_UNICODE | REQUEST_TARGET | NEGOTIATE_SIGN | NEGOTIATE_NTLM
+    | NEGOTIATE_ALWAYS_SIGN | NEGOTIATE_EXTENDED_SESSIONSECURITY | NEGOTIATE_TARGET_INFO | NEGOTIATE_128 | NEGOTIATE_56;
+/// Signing flags, left out of the LDAP binds: the DC would expect signed LDAP messages after the bind.
+const SIGNING_FLAGS: u32 = NEGOTIATE_SIGN | NEGOTIATE_ALWAYS_SIGN;
+
+/// AV_PAIR ids of the target info.
+const MSV_AV_EOL: u16 = 0;
+const MSV_AV_TIMESTAMP: u16 = 7;
+const MSV_AV_CHANNEL_BINDINGS: u16 = 0x000A;
+
+/// Seconds between 1601-01-01 (FILETIME) and 1970-01-01.
+const FILETIME_UNIX_OFFSET: u64 = 11_644_473_600;
+
+/// CHALLENGE_MESSAGE of the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Challenge {
+    pub flags: u32,
+    pub server_challenge: [u8; 8],
+    pub target_info: Vec<u8>,
+}
+
+/// Account answering a challenge, with the NT hash of its password.
+#[derive(Clone, Copy)]
+pub struct NtlmAccount<'a> {
+    pub user: &'a str,
+    pub domain: &'a str,
+    pub nt_hash: [u8; 16],
+}
+
+/// Function to get the NTLM user and domain of the bind username: `user@domain`, `DOMAIN\user` or `user`.
+pub fn ntlm_identity(username: &str, domain: &str) -> Option<(String, String)> {
+    // Bind DN
+    if username.is_empty() || username.contains('=') {
+        return None
+    }
+    Some(match (username.split_once('\\'), username.rsplit_once('@')) {
+        (Some((domain, user)), _) => (user.to_string(), domain.to_string()),
+        (None, Some((user, domain))) => (user.to_string(), domain.to_string()),
+        (None, None) => (username.to_string(), domain.to_string()),
+    })
+}
+
+/// Function to get the flags negotiated, without signing for the LDAP binds.
+fn negotiate_flags(signing: bool) -> u32 {
+    if signing { NEGOTIATE_FLAGS } else { NEGOTIATE_FLAGS & !SIGNING_FLAGS }
+}
+
+/// Function to build the NEGOTIATE_MESSAGE, without domain and workstation.
+pub fn negotiate_message(signing: bool) -> Vec<u8> {
+    let mut message = NTLMSSP_SIGNATURE.to_vec();
+    message.extend_from_slice(&1u32.to_le_bytes());
+    message.extend_from_slice(&negotiate_flags(signing).to_le_bytes());
+    // DomainNameFields and WorkstationFields, empty
+    message.extend_from_slice(&[0u8; 16]);
+    message
+}
+
+/// Function to parse the CHALLENGE_MESSAGE, it can be followed by other bytes of the security buffer.
+pub fn parse_challenge(message: &[u8]) -> Result<Challenge, Box<dyn Error>> {
+    if message.len() < 48 || &message[..8] != NTLMSSP_SIGNATURE || read_u32(message, 8) != 2 {
+        return Err("invalid NTLM CHALLENGE_MESSAGE".into())
+    }
+    let length = u16::from_le_bytes([message[40], message[41]]) as usize;
+    let offset = read_u32(message, 44) as usize;
+    let target_info = message.get(offset..offset + length)
+        .ok_or("NTLM target info out of the CHALLENGE_MESSAGE")?
+        .to_vec();
+    let mut server_challenge = [0u8; 8];
+    server_challenge.copy_from_slice(&message[24..32]);
+    Ok(Challenge { flags: read_u32(message, 20), server_challenge, target_info })
+}
+
+/// Function to compute the NT hash of a password, the MD4 of its UTF-16LE encoding.
+pub fn nt_hash(password: &str) -> [u8; 16] {
+    Md4::digest(utf16le(password)).into()
+}
+
+/// Function to parse an NT hash of 32 hexadecimal characters, `LMHASH:NTHASH` of impacket is accepted too.
+/// The value is never part of the error.
+pub fn parse_nt_hash(value: &str) -> Result<[u8; 16], String> {
+    let hex = value.rsplit_once(':').map_or(value, |(_, nt_hash)| nt_hash);
+    if hex.len() != 32 {
+        return Err(format!(
+            "an NT hash is 32 hexadecimal characters like 31d6cfe0d16ae931b73c59d7e0c089c0 (or LMHASH:NTHASH), not {}",
+            hex.len()
+        ))
+    }
+    let mut hash = [0u8; 16];
+    for (byte, digits) in hash.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = std::str::from_utf8(digits).ok()
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            .ok_or("an NT hash is only made of hexadecimal characters (0-9, a-f)")?;
+    }
+    Ok(hash)
+}
+
+/// Function to compute NTOWFv2, the NTLMv2 key of an account.
+pub fn nt_owf_v2(user: &str, domain: &str, nt_hash: &[u8; 16]) -> [u8; 16] {
+    hmac_md5(nt_hash, &[&utf16le(&format!("{}{domain}", user.to_uppercase()))])
+}
+
+/// Function to build the AUTHENTICATE_MESSAGE answering a challenge, with the session base key.
+///
+/// The timestamp of the server target info is used when there is one, `timestamp` otherwise. The
+/// `channel_bindings` hash of a TLS channel is added to the target info of the response.
+pub fn authenticate_message(
+    challenge: &Challenge,
+    account: &NtlmAccount,
+    signing: bool,
+    channel_bindings: Option<&[u8; 16]>,
+    client_challenge: [u8; 8],
+    timestamp: u64,
+) -> (Vec<u8>, [u8; 16]) {
+    let NtlmAccount { user, domain, nt_hash } = *account;
+    let key = nt_owf_v2(user, domain, &nt_hash);
+    let server_timestamp = target_info_timestamp(&challenge.target_info);
+    let mut temp = vec![1u8, 1, 0, 0, 0, 0, 0, 0];
+    temp.extend_from_slice(&server_timestamp.unwrap_or(timestamp).to_le_bytes());
+    temp.extend_from_slice(&client_challenge);
+    temp.extend_from_slice(&[0u8; 4]);
+    match channel_bindings {
+        Some(channel_bindings) => temp.extend(with_channel_bindings(&challenge.target_info, channel_bindings)),
+        None => temp.extend_from_slice(&challenge.target_info),
+    }
+    temp.extend_from_slice(&[0u8; 4]);
+    let nt_proof = hmac_md5(&key, &[&challenge.server_challenge, &temp]);
+    let session_key = hmac_md5(&key, &[&nt_proof]);
+
+    let mut nt_response = nt_proof.to_vec();
+    nt_response.extend_from_slice(&temp);
+    // LMv2 left empty (Z(24)) when the server sent a timestamp
+    let lm_response = match server_timestamp {
+        Some(_) => vec![0u8; 24],
+        None => {
+            let mut response = hmac_md5(&key, &[&challenge.server_challenge, &client_challenge]).to_vec();
+            response.extend_from_slice(&client_challenge);
+            response
+        }
+    };
+    let fields = [lm_response, nt_response, utf16le(domain), utf16le(user), Vec::new(), Vec::new()];
+
+    let mut message = NTLMSSP_SIGNATURE.to_vec();
+    message.extend_from_slice(&3u32.to_le_bytes());
+    let mut offset = 64u32;
+    for field in &fields {
+        message.extend_from_slice(&(field.len() as u16).to_le_bytes());
+        message.extend_from_slice(&(field.len() as u16).to_le_bytes());
+        message.extend_from_slice(&offset.to_le_bytes());
+        offset += field.len() as u32;
+    }
+    message.extend_from_slice(&(challenge.flags & negotiate_flags(signing)).to_le_bytes());
+    for field in &fields {
+        message.extend_from_slice(field);
+    }
+    (message, session_key)
+}
+
+/// Function to get the current time as a FILETIME, for the NTLMv2 response.
+pub fn filetime_now() -> u64 {
+    let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    (elapsed.as_secs() + FILETIME_UNIX_OFFSET) * 10_000_000 + u64::from(elapsed.subsec_nanos() / 100)
+}
+
+/// Function to hash the tls-server-end-point token of a TLS channel as the gss_channel_bindings_struct
+/// of RFC 2744, without addresses: the MsvAvChannelBindings of the response.
+pub fn channel_bindings_hash(token: &[u8]) -> [u8; 16] {
+    let mut bindings = Md5::new();
+    bindings.update([0u8; 16]);
+    bindings.update((token.len() as u32).to_le_bytes());
+    bindings.update(token);
+    bindings.finalize().into()
+}
+
+/// Function to add the MsvAvChannelBindings to the target info of the server, before its MsvAvEOL.
+fn with_channel_bindings(target_info: &[u8], channel_bindings: &[u8; 16]) -> Vec<u8> {
+    let mut pairs = Vec::with_capacity(target_info.len() + 20);
+    let mut offset = 0;
+    while offset + 4 <= target_info.len() {
+        let id = u16::from_le_bytes([target_info[offset], target_info[offset + 1]]);
+        let length = u16::from_le_bytes([target_info[offset + 2], target_info[offset + 3]]) as usize;
+        let Some(pair) = target_info.get(offset..offset + 4 + length).filter(|_| id != MSV_AV_EOL) else {
+            break
+        };
+        pairs.extend_from_slice(pair);
+        offset += 4 + length;
+    }
+    pairs.extend_from_slice(&MSV_AV_CHANNEL_BINDINGS.to_le_bytes());
+    pairs.extend_from_slice(&16u16.to_le_bytes());
+    pairs.extend_from_slice(channel_bindings);
+    pairs.extend_from_slice(&[0u8; 4]);
+    pairs
+}
+
+/// Function to get the MsvAvTimestamp of a target info.
+fn target_info_timestamp(target_info: &[u8]) -> Option<u64> {
+    let mut offset = 0;
+    while offset + 4 <= target_info.len() {
+        let id = u16::from_le_bytes([target_info[offset], target_info[offset + 1]]);
+        let length = u16::from_le_bytes([target_info[offset + 2], target_info[offset + 3]]) as usize;
+        let value = target_info.get(offset + 4..offset + 4 + length)?;
+        match id {
+            MSV_AV_EOL => return None,
+            MSV_AV_TIMESTAMP if length == 8 => return Some(u64::from_le_bytes(value.try_into().ok()?)),
+            _ => offset += 4 + length,
+        }
+    }
+    None
+}
+
+/// Function to compute HMAC-MD5 over the concatenated parts.
+fn hmac_md5(key: &[u8], parts: &[&[u8]]) -> [u8; 16] {
+    let mut mac = <Hmac<Md5> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+/// Function to encode a string in UTF-16LE.
+pub fn utf16le(value: &str) -> Vec<u8> {
+    value.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::utils::ntlm::*;
+
+    fn hex(data: &[u8]) -> String {
+        data.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    /// Target info of the MS-NLMP 4.2.4 example, "Domain" and "Server".
+    fn example_challenge() -> Challenge {
+        let mut target_info = vec![0x02, 0x00, 0x0c, 0x00];
+        target_info.extend(utf16le("Domain"));
+        target_info.extend([0x01, 0x00, 0x0c, 0x00]);
+        target_info.extend(utf16le("Server"));
+        target_info.extend([0x00, 0x00, 0x00, 0x00]);
+        Challenge {
+            flags: 0xe28a8233,
+            server_challenge: [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef],
+            target_info,
+        }
+    }
+
+    #[test]
+    pub fn test_ntlmv2_response() {
+        // MS-NLMP 4.2.4 NTLMv2 authentication
+        assert_eq!(hex(&nt_hash("Password")), "a4f49c406510bdcab6824ee7c30fd852");
+        assert_eq!(hex(&nt_owf_v2("User", "Domain", &nt_hash("Password"))), "0c868a403bfd7a93a3001ef22ef02e3f");
+        let challenge = example_challenge();
+        let account = NtlmAccount { user: "User", domain: "Domain", nt_hash: nt_hash("Password") };
+        let (message, session_key) = authenticate_message(&challenge, &account, true, None, [0xaa; 8], 0);
+        assert_eq!(hex(&session_key), "8de40ccadbc14a82f15cb0ad0de95ca3");
+
+        assert_eq!(&message[..8], NTLMSSP_SIGNATURE);
+        let field = |index: usize| {
+            let length = u16::from_le_bytes([message[12 + index * 8], message[13 + index * 8]]) as usize;
+            let offset = read_u32(&message, 16 + index * 8) as usize;
+            &message[offset..offset + length]
+        };
+        // NTProofStr then the blob with the target info
+        assert_eq!(hex(&field(1)[..16]), "68cd0ab851e51c96aabc927bebef6a1c");
+        assert_eq!(hex(&field(0)[..16]), "86c35097ac9cec102554764a57cccc19");
+        assert_eq!(field(2), utf16le("Domain").as_slice());
+        assert_eq!(field(3), utf16le("User").as_slice());
+    }
+
+    #[test]
+    pub fn test_channel_bindings() {
+        let token = b"tls-server-end-point:0123456789abcdef0123456789abcdef";
+        let mut bindings = vec![0u8; 16];
+        bindings.extend((token.len() as u32).to_le_bytes());
+        bindings.extend(token);
+        let hash = channel_bindings_hash(token);
+        assert_eq!(hash, <[u8; 16]>::from(Md5::digest(&bindings)));
+
+        // MsvAvChannelBindings before MsvAvEOL, no signing negotiated for LDAP
+        let challenge = example_challenge();
+        let account = NtlmAccount { user: "User", domain: "Domain", nt_hash: nt_hash("Password") };
+        let (message, _) = authenticate_message(&challenge, &account, false, Some(&hash), [0xaa; 8], 0);
+        let length = u16::from_le_bytes([message[20], message[21]]) as usize;
+        let offset = read_u32(&message, 24) as usize;
+        let nt_response = &message[offset..offset + length];
+        let mut expected = challenge.target_info[..challenge.target_info.len() - 4].to_vec();
+        expected.extend([0x0a, 0x00, 0x10, 0x00]);
+        expected.extend(hash);
+        expected.extend([0u8; 8]);
+        assert!(nt_response.ends_with(&expected));
+        assert_eq!(read_u32(&message, 60) & SIGNING_FLAGS, 0);
+        assert_eq!(read_u32(&negotiate_message(false), 12) & SIGNING_FLAGS, 0);
+        assert_eq!(read_u32(&negotiate_message(true), 12) & SIGNING_FLAGS, SIGNING_FLAGS);
+    }
+
+    #[test]
+    pub fn test_parse_nt_hash() {
+        let expected = nt_hash("Password");
+        assert_eq!(parse_nt_hash("a4f49c406510bdcab6824ee7c30fd852"), Ok(expected));
+        assert_eq!(parse_nt_hash("A4F49C406510BDCAB6824EE7C30FD852"), Ok(expected));
+        assert_eq!(parse_nt_hash("aad3b435b51404eeaad3b435b51404ee:a4f49c406510bdcab6824ee7c30fd852"), Ok(expected));
+        assert_eq!(parse_nt_hash(":a4f49c406510bdcab6824ee7c30fd852"), Ok(expected));
+        assert!(parse_nt_hash("a4f49c406510bdcab6824ee7c30fd8").unwrap_err().contains("32 hexadecimal characters"));
+        assert!(parse_nt_hash("Password").unwrap_err().ends_with("not 8"));
+        assert!(parse_nt_hash("g4f49c406510bdcab6824ee7c30fd852").unwrap_err().contains("only made of hexadecimal"));
+        assert!(parse_nt_hash("é4f49c406510bdcab6824ee7c30fd85").is_err());
+    }
+
+    #[test]
+    pub fn test_ntlm_identity() {
+        assert_eq!(ntlm_identity("alice@contoso.local", "fabrikam.local"), Some(("alice".to_string(), "contoso.local".to_string())));
+        assert_eq!(ntlm_identity("CONTOSO\\alice", "contoso.local"), Some(("alice".to_string(), "CONTOSO".to_string())));
+        assert_eq!(ntlm_identity("alice", "contoso.local"), Some(("alice".to_string(), "contoso.local".to_string())));
+        assert_eq!(ntlm_identity("CN=alice,CN=Users,DC=contoso,DC=local", "contoso.local"), None);
+        assert_eq!(ntlm_identity("", "contoso.local"), None);
+    }
+
+    #[test]
+    pub fn test_parse_challenge() {
+        let expected = example_challenge();
+        let mut message = NTLMSSP_SIGNATURE.to_vec();
+        message.extend(2u32.to_le_bytes());
+        message.extend([0u8; 8]);
+        message.extend(expected.flags.to_le_bytes());
+        message.extend(expected.server_challenge);
+        message.extend([0u8; 8]);
+        message.extend((expected.target_info.len() as u16).to_le_bytes());
+        message.extend((expected.target_info.len() as u16).to_le_bytes());
+        message.extend(48u32.to_le_bytes());
+        message.extend(&expected.target_info);
+        // mechListMIC of the SPNEGO token after the message
+        message.extend([0xa3, 0x00]);
+        assert_eq!(parse_challenge(&message).unwrap(), expected);
+        assert!(target_info_timestamp(&expected.target_info).is_none());
+
+        assert!(parse_challenge(&message[..40]).is_err());
+        message[8] = 3;
+        assert!(parse_challenge(&message).is_err());
+    }
+}
@@ -0,0 +1,494 @@
+//! Read-only verification of previous findings
+//!
+//! `rusthound-ce -d <domain> ... verify --findings <FILE> --previous <DIR>` re-checks a list of findings
+//! of a previous collection against the live AD, like "is this GenericAll gone now?". Only the objects
+//! of the findings are read, by `<GUID=...>` or `<SID=...>` base searches, and parsed with the same
+//! object parsers as a full collection.
+//!
+//! The findings file has one finding per line, `#` starts a comment:
+//!
+//! ```text
+//! # <object identifier> <Edge> [<principal SID>]
+//! 0A1B2C3D-4E5F-4A6B-8C7D-9E0F1A2B3C4D  GenericAll  S-1-5-21-1004336348-1177238915-682003330-1105
+//! S-1-5-21-1004336348-1177238915-682003330-1106  AllowedToAct
+//! # <object identifier> <property>[=<value>], without value the previous output value is expected
+//! S-1-5-21-1004336348-1177238915-682003330-1104  dontreqpreauth=true
+//! S-1-5-21-1004336348-1177238915-682003330-1104  serviceprincipalnames
+//! ```
+//!
+//! Edges are the ACE rights (`GenericAll`, `WriteDacl`...) and the `AllowedToAct` and `HasSIDHistory`
+//! relations. Each finding is reported `present` (still true), `changed` (the object exists but the
+//! finding is not true anymore) or `gone` (the object doesn't exist anymore), on the console and in
+//! `<domain>_verify.json` with the previous and current values.
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use colored::Colorize;
+use ldap3::SearchEntry;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::api::{parse_result_type_from_source, ADResults};
+use crate::args::Options;
+use crate::json::maker::identifiers::{canonicalize_identifier, canonicalize_identifiers};
+use crate::ldap::LdapSearchEntry;
+use crate::objects::common::LdapObject;
+use crate::utils::format::domain_to_dc;
+
+/// Edges read from the relations of the object instead of its ACEs.
+const RELATION_EDGES: &[&str] = &["AllowedToAct", "HasSIDHistory"];
+
+/// Files and findings of the `verify` subcommand.
+#[derive(Debug, Clone)]
+pub struct VerifyOptions {
+    /// Findings file.
+    pub findings: String,
+    /// Directory with the json files of the previous collection.
+    pub previous: String,
+}
+
+/// One finding to verify.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub object_identifier: String,
+    pub kind: FindingKind,
+}
+
+/// What is checked on the object.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FindingKind {
+    /// Edge from one principal, or from any principal.
+    Edge { name: String, principal: Option<String> },
+    /// Property with the expected value, or the previous one.
+    Property { name: String, value: Option<Value> },
+}
+
+impl fmt::Display for FindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FindingKind::Edge { name, principal: Some(principal) } => write!(f, "{name} from {principal}"),
+            FindingKind::Edge { name, principal: None } => write!(f, "{name}"),
+            FindingKind::Property { name, value: Some(value) } => write!(f, "{name}={value}"),
+            FindingKind::Property { name, value: None } => write!(f, "{name}"),
+        }
+    }
+}
+
+/// Verification result of one finding.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerificationStatus {
+    /// The finding is still true.
+    Present,
+    /// The object exists but the finding is not true anymore.
+    Changed,
+    /// The object doesn't exist anymore.
+    Gone,
+}
+
+/// One verified finding.
+#[derive(Debug, Clone, Serialize)]
+pub struct Verification {
+    pub object_identifier: String,
+    /// Object name from the live AD or the previous output.
+    pub name: String,
+    pub finding: String,
+    pub status: VerificationStatus,
+    /// Value in the previous output, `None` if the object was not in it.
+    pub previous: Option<String>,
+    /// Current value, `None` if the object is gone.
+    pub current: Option<String>,
+}
+
+/// Targeted LDAP reads.
+pub(crate) trait ObjectReader {
+    /// Read one object from its base like `<GUID=...>`, `None` when it doesn't exist.
+    async fn read(&mut self, base: &str) -> Result<Option<SearchEntry>, Box<dyn Error>>;
+}
+
+/// Function to parse the findings file content.
+pub fn parse_findings(content: &str) -> Result<Vec<Finding>, Box<dyn Error>> {
+    let mut findings = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (object, check, principal) = match fields.as_slice() {
+            [object, check] => (object, check, None),
+            [object, check, principal] => (object, check, Some(principal)),
+            _ => return Err(format!("line {}: expected <object> <Edge> [<principal>] or <object> <property>[=<value>]", number + 1).into()),
+        };
+        // Edges are CamelCase, properties lowercase
+        let kind = if check.starts_with(|c: char| c.is_ascii_uppercase()) {
+            FindingKind::Edge { name: check.to_string(), principal: principal.map(|p| canonicalize_identifier(p)) }
+        } else if principal.is_some() {
+            return Err(format!("line {}: a property finding has no principal", number + 1).into())
+        } else {
+            match check.split_once('=') {
+                Some((name, value)) => FindingKind::Property {
+                    name: name.to_string(),
+                    // true, 0, "text"... or a plain string
+                    value: Some(serde_json::from_str(value).unwrap_or(Value::String(value.to_string()))),
+                },
+                None => FindingKind::Property { name: check.to_string(), value: None },
+            }
+        };
+        findings.push(Finding { object_identifier: canonicalize_identifier(object), kind });
+    }
+    Ok(findings)
+}
+
+/// Function to load the objects of a previous collection, by ObjectIdentifier.
+pub fn load_previous(path: &str) -> Result<HashMap<String, Value>, Box<dyn Error>> {
+    let mut objects = HashMap::new();
+    for file in std::fs::read_dir(path)? {
+        let file = file?.path();
+        if file.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue
+        }
+        let json: Value = serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+        // Reports in the same directory have no data list
+        for object in json["data"].as_array().into_iter().flatten() {
+            if let Some(identifier) = object["ObjectIdentifier"].as_str() {
+                objects.insert(canonicalize_identifier(identifier), object.to_owned());
+            }
+        }
+    }
+    Ok(objects)
+}
+
+/// Function to get the base of an object from its identifier, without the domain prefix of the well-known SIDs.
+pub fn object_base(identifier: &str) -> String {
+    match identifier.find("S-1-") {
+        Some(start) => format!("<SID={}>", &identifier[start..]),
+        None => format!("<GUID={identifier}>"),
+    }
+}
+
+/// Function to read, parse and check all findings. The domain head is read first for the domain SID.
+pub(crate) async fn verify_findings<R: ObjectReader>(
+    mut reader: R,
+    options: &Options,
+    previous: &HashMap<String, Value>,
+    findings: &[Finding],
+) -> Result<Vec<Verification>, Box<dyn Error>> {
+    let mut entries: Vec<LdapSearchEntry> = Vec::new();
+    // The domain head from the rootDSE, its DN may not match the DNS name
+    let head = match reader.read("").await {
+        Ok(Some(root_dse)) => root_dse.attrs.get("defaultNamingContext").and_then(|v| v.first()).cloned(),
+        _ => None,
+    };
+    if let Some(entry) = reader.read(&head.unwrap_or_else(|| domain_to_dc(&options.domain))).await? {
+        entries.push(entry.into());
+    }
+    let mut identifiers: Vec<&str> = Vec::new();
+    for finding in findings {
+        if !identifiers.contains(&finding.object_identifier.as_str()) {
+            identifiers.push(&finding.object_identifier);
+        }
+    }
+    for identifier in identifiers {
+        if let Some(entry) = reader.read(&object_base(identifier)).await? {
+            entries.push(entry.into());
+        }
+    }
+    let total = entries.len();
+    let results = parse_result_type_from_source(options, entries, Some(total))?;
+    let current = objects_by_identifier(&results);
+
+    let verifications = findings.iter()
+        .map(|finding| {
+            let before = previous.get(&finding.object_identifier);
+            let now = current.get(&finding.object_identifier);
+            let name = now.or(before)
+                .and_then(|object| object["Properties"]["name"].as_str())
+                .unwrap_or(&finding.object_identifier)
+                .to_string();
+            let previous_value = before.map(|object| evaluate(object, &finding.kind, None).1);
+            // Without expected value, the previous one
+            let expected = before.map(|object| property_value(object, &finding.kind));
+            let (status, current) = match now {
+                None => (VerificationStatus::Gone, None),
+                Some(object) => match evaluate(object, &finding.kind, expected.as_ref()) {
+                    (true, value) => (VerificationStatus::Present, Some(value)),
+                    (false, value) => (VerificationStatus::Changed, Some(value)),
+                },
+            };
+            Verification {
+                object_identifier: finding.object_identifier.to_owned(),
+                name,
+                finding: finding.kind.to_string(),
+                status,
+                previous: previous_value,
+                current,
+            }
+        })
+        .collect();
+    Ok(verifications)
+}
+
+/// Function to get all the parsed objects as output json.
+fn objects_by_identifier(results: &ADResults) -> HashMap<String, Value> {
+    fn add<T: LdapObject>(objects: &mut HashMap<String, Value>, values: &[T]) {
+        for value in values {
+            let mut json = value.to_json();
+            canonicalize_identifiers(&mut json);
+            if let Some(identifier) = json["ObjectIdentifier"].as_str() {
+                objects.insert(identifier.to_owned(), json.to_owned());
+            }
+        }
+    }
+    let mut objects = HashMap::new();
+    add(&mut objects, &results.users);
+    add(&mut objects, &results.groups);
+    add(&mut objects, &results.computers);
+    add(&mut objects, &results.ous);
+    add(&mut objects, &results.domains);
+    add(&mut objects, &results.gpos);
+    add(&mut objects, &results.containers);
+    add(&mut objects, &results.ntauthstores);
+    add(&mut objects, &results.aiacas);
+    add(&mut objects, &results.rootcas);
+    add(&mut objects, &results.enterprisecas);
+    add(&mut objects, &results.certtemplates);
+    add(&mut objects, &results.issuancepolicies);
+    objects
+}
+
+/// Function to get a property value, `Null` when not set or not a property finding.
+fn property_value(object: &Value, kind: &FindingKind) -> Value {
+    match kind {
+        FindingKind::Property { name, .. } => object["Properties"][name].to_owned(),
+        FindingKind::Edge { .. } => Value::Null,
+    }
+}
+
+/// Function to check a finding on one object json, return if it holds and the current value.
+fn evaluate(object: &Value, kind: &FindingKind, expected: Option<&Value>) -> (bool, String) {
+    match kind {
+        FindingKind::Edge { name, principal } if RELATION_EDGES.contains(&name.as_str()) => {
+            let related: Vec<&str> = object[name].as_array().into_iter().flatten()
+                .filter_map(|member| member["ObjectIdentifier"].as_str())
+                .collect();
+            let holds = match principal {
+                Some(principal) => related.contains(&principal.as_str()),
+                None => !related.is_empty(),
+            };
+            (holds, join_or_none(related))
+        }
+        FindingKind::Edge { name, principal } => {
+            let aces = object["Aces"].as_array().into_iter().flatten();
+            match principal {
+                // Rights still held by the principal
+                Some(principal) => {
+                    let rights: Vec<&str> = aces
+                        .filter(|ace| ace["PrincipalSID"].as_str() == Some(principal.as_str()))
+                        .filter_map(|ace| ace["RightName"].as_str())
+                        .collect();
+                    (rights.contains(&name.as_str()), join_or_none(rights))
+                }
+                // Principals still holding the right
+                None => {
+                    let principals: Vec<&str> = aces
+                        .filter(|ace| ace["RightName"].as_str() == Some(name.as_str()))
+                        .filter_map(|ace| ace["PrincipalSID"].as_str())
+                        .collect();
+                    (!principals.is_empty(), join_or_none(principals))
+                }
+            }
+        }
+        FindingKind::Property { name, value } => {
+            let current = &object["Properties"][name];
+            let expected = value.as_ref().or(expected).cloned().unwrap_or(json!(true));
+            let text = match current {
+                Value::Null => "not set".to_string(),
+                value => value.to_string(),
+            };
+            (*current == expected, text)
+        }
+    }
+}
+
+fn join_or_none(values: Vec<&str>) -> String {
+    match values.is_empty() {
+        true => "none".to_string(),
+        false => values.join(", "),
+    }
+}
+
+/// Function to show the verifications as a table.
+pub fn print_verifications(verifications: &[Verification]) {
+    let width = |column: fn(&Verification) -> &str, header: &str| {
+        verifications.iter().map(|v| column(v).len()).chain([header.len()]).max().unwrap_or_default()
+    };
+    let (name, finding) = (width(|v| &v.name, "OBJECT"), width(|v| &v.finding, "FINDING"));
+    info!("{:<8} {:<name$} {:<finding$} CURRENT", "STATUS", "OBJECT", "FINDING");
+    for verification in verifications {
+        let status = match verification.status {
+            VerificationStatus::Present => "present".red(),
+            VerificationStatus::Changed => "changed".green(),
+            VerificationStatus::Gone => "gone".green(),
+        };
+        info!(
+            "{:<8} {:<name$} {:<finding$} {}",
+            status,
+            verification.name,
+            verification.finding,
+            verification.current.as_deref().unwrap_or("-")
+        );
+    }
+    let count = |status| verifications.iter().filter(|v| v.status == status).count();
+    let present = count(VerificationStatus::Present);
+    if present > 0 {
+        warn!("{} findings still present", present.to_string().bold().red());
+    }
+    info!(
+        "{} present, {} changed, {} gone",
+        present,
+        count(VerificationStatus::Changed),
+        count(VerificationStatus::Gone)
+    );
+}
+
+/// Function to write the verifications in `{path}/{domain}_verify.json`.
+pub fn write_verifications(verifications: &[Verification], path: &str, domain: &str) -> Result<String, Box<dyn Error>> {
+    std::fs::create_dir_all(path)?;
+    let file = format!("{}/{}_verify.json", path.trim_end_matches('/'), domain.to_lowercase());
+    let report = json!({
+        "domain": domain.to_uppercase(),
+        "verified_at": chrono::Utc::now().to_rfc3339(),
+        "findings": verifications,
+    });
+    std::fs::write(&file, serde_json::to_string_pretty(&report)?)?;
+    info!("Verification report written to {file}");
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use std::error::Error;
+    use ldap3::SearchEntry;
+    use serde_json::json;
+
+    use crate::args::Options;
+    use crate::enums::sddl::{sddl_to_security_descriptor, sid_to_bytes};
+    use crate::test_support::search_entry;
+    use crate::verify::*;
+
+    const DOMAIN_SID: &str = "S-1-5-21-1004336348-1177238915-682003330";
+
+    /// Objects of the live AD by base.
+    struct FakeReader(HashMap<String, SearchEntry>);
+
+    impl ObjectReader for FakeReader {
+        async fn read(&mut self, base: &str) -> Result<Option<SearchEntry>, Box<dyn Error>> {
+            Ok(self.0.get(base).cloned())
+        }
+    }
+
+    fn entry(dn: &str, attrs: &[(&str, &str)], sid: &str, sddl: &str) -> SearchEntry {
+        search_entry(dn, attrs, HashMap::from([
+            ("objectSid".to_string(), vec![sid_to_bytes(sid).unwrap()]),
+            ("nTSecurityDescriptor".to_string(), vec![sddl_to_security_descriptor(sddl, DOMAIN_SID, false).unwrap()]),
+        ]))
+    }
+
+    #[test]
+    pub fn test_parse_findings() {
+        let findings = parse_findings(&format!("
+            # tickets
+            {DOMAIN_SID}-1104 GenericAll {DOMAIN_SID}-1105
+            {DOMAIN_SID}-1104 dontreqpreauth=true   # AS-REP roasting
+            0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d description=old text
+            0A1B2C3D-4E5F-4A6B-8C7D-9E0F1A2B3C4D AllowedToAct
+        ")).unwrap_err();
+        // "old text" has a space
+        assert!(findings.to_string().contains("line 5"));
+
+        let findings = parse_findings(&format!("
+            {DOMAIN_SID}-1104 GenericAll {DOMAIN_SID}-1105
+            {DOMAIN_SID}-1104 dontreqpreauth=true   # AS-REP roasting
+            0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d description=old
+            0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d AllowedToAct
+        ")).unwrap();
+        assert_eq!(findings.len(), 4);
+        assert_eq!(findings[0].kind, FindingKind::Edge { name: "GenericAll".to_string(), principal: Some(format!("{DOMAIN_SID}-1105")) });
+        assert_eq!(findings[1].kind, FindingKind::Property { name: "dontreqpreauth".to_string(), value: Some(json!(true)) });
+        assert_eq!(findings[2].kind, FindingKind::Property { name: "description".to_string(), value: Some(json!("old")) });
+        assert_eq!(findings[3].object_identifier, "0A1B2C3D-4E5F-4A6B-8C7D-9E0F1A2B3C4D");
+        assert!(parse_findings(&format!("{DOMAIN_SID}-1104 enabled {DOMAIN_SID}-1105")).is_err());
+
+        assert_eq!(object_base("CONTOSO.LOCAL-S-1-5-32-544"), "<SID=S-1-5-32-544>");
+        assert_eq!(object_base(&findings[3].object_identifier), "<GUID=0A1B2C3D-4E5F-4A6B-8C7D-9E0F1A2B3C4D>");
+    }
+
+    #[tokio::test]
+    async fn test_verify_findings() {
+        let options = Options { domain: "contoso.local".to_string(), ..Default::default() };
+        let alice = format!("{DOMAIN_SID}-1104");
+        let reader = FakeReader(HashMap::from([
+            (
+                "DC=CONTOSO,DC=LOCAL".to_string(),
+                entry("DC=CONTOSO,DC=LOCAL", &[("objectClass", "top|domain|domainDNS"), ("name", "contoso")], DOMAIN_SID, "O:BAD:(A;;RPLCLORC;;;AU)"),
+            ),
+            (
+                format!("<SID={alice}>"),
+                entry(
+                    "CN=alice,CN=Users,DC=CONTOSO,DC=LOCAL",
+                    &[("objectClass", "top|person|organizationalPerson|user"), ("sAMAccountName", "alice"), ("userAccountControl", "66048")],
+                    &alice,
+                    &format!("O:DAD:(A;;WD;;;{DOMAIN_SID}-1105)(A;;RPLCLORC;;;AU)"),
+                ),
+            ),
+        ]));
+        // Collected when the findings were made
+        let previous = HashMap::from([(
+            alice.to_owned(),
+            json!({
+                "ObjectIdentifier": alice,
+                "Properties": {"name": "ALICE@CONTOSO.LOCAL", "enabled": true, "dontreqpreauth": true},
+                "Aces": [
+                    {"PrincipalSID": format!("{DOMAIN_SID}-1105"), "RightName": "WriteDacl"},
+                    {"PrincipalSID": format!("{DOMAIN_SID}-1106"), "RightName": "GenericAll"},
+                ],
+            }),
+        )]);
+        let findings = parse_findings(&format!("
+            {alice} WriteDacl {DOMAIN_SID}-1105
+            {alice} GenericAll {DOMAIN_SID}-1106
+            {alice} dontreqpreauth
+            {alice} enabled
+            {DOMAIN_SID}-1107 GenericWrite {DOMAIN_SID}-1105
+        ")).unwrap();
+
+        let verifications = verify_findings(reader, &options, &previous, &findings).await.unwrap();
+        let status: Vec<VerificationStatus> = verifications.iter().map(|v| v.status).collect();
+        assert_eq!(status, vec![
+            VerificationStatus::Present,
+            VerificationStatus::Changed,
+            VerificationStatus::Changed,
+            VerificationStatus::Present,
+            VerificationStatus::Gone,
+        ]);
+        assert_eq!(verifications[0].name, "ALICE@CONTOSO.LOCAL");
+        assert_eq!(verifications[1].previous.as_deref(), Some("GenericAll"));
+        assert_eq!(verifications[1].current.as_deref(), Some("none"));
+        assert_eq!(verifications[2].previous.as_deref(), Some("true"));
+        assert_eq!(verifications[2].current.as_deref(), Some("false"));
+        assert_eq!(verifications[4].previous, None);
+        assert_eq!(verifications[4].current, None);
+
+        let path = std::env::temp_dir().join(format!("rusthound-verify-{}", std::process::id()));
+        let file = write_verifications(&verifications, path.to_str().unwrap(), &options.domain).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+        assert_eq!(report["domain"], "CONTOSO.LOCAL");
+        assert_eq!(report["findings"][4]["status"], "gone");
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,66 @@
+//! Fixtures of the integration tests
+//!
+//! See `tests/golden.rs` for the format of `tests/fixtures/<name>.json`.
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ldap3::SearchEntry;
+use serde::Deserialize;
+
+use rusthound_ce::enums::sddl::{guid_to_bytes, sddl_to_security_descriptor, sid_to_bytes};
+use rusthound_ce::ldap::LdapSearchEntry;
+
+#[derive(Deserialize)]
+struct Fixture {
+    domain: String,
+    domain_sid: String,
+    entries: Vec<FixtureEntry>,
+}
+
+#[derive(Deserialize)]
+struct FixtureEntry {
+    dn: String,
+    #[serde(default)]
+    attrs: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    bin_attrs: HashMap<String, Vec<String>>,
+}
+
+/// Decode one prefixed binary value.
+fn decode_binary(value: &str, domain_sid: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (kind, data) = value.split_once(':').ok_or(format!("missing prefix in binary value '{value}'"))?;
+    Ok(match kind {
+        "sid" => sid_to_bytes(data).ok_or(format!("invalid SID '{data}'"))?,
+        "guid" => guid_to_bytes(data).ok_or(format!("invalid GUID '{data}'"))?,
+        "sddl" => sddl_to_security_descriptor(data, domain_sid, false)?,
+        "base64" => STANDARD.decode(data)?,
+        "hex" => (0..data.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&data[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()?,
+        _ => return Err(format!("unknown binary prefix '{kind}'").into()),
+    })
+}
+
+/// Load the fixture entries as they would be returned by the LDAP search.
+pub fn load_fixture(path: &Path) -> Result<(String, Vec<LdapSearchEntry>), Box<dyn Error>> {
+    let fixture: Fixture = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let mut entries = Vec::new();
+    for entry in fixture.entries {
+        let mut bin_attrs = HashMap::new();
+        for (name, values) in entry.bin_attrs {
+            let values = values.iter()
+                .map(|v| decode_binary(v, &fixture.domain_sid))
+                .collect::<Result<Vec<Vec<u8>>, _>>()?;
+            bin_attrs.insert(name, values);
+        }
+        entries.push(SearchEntry { dn: entry.dn, attrs: entry.attrs, bin_attrs }.into());
+    }
+    Ok((fixture.domain, entries))
+}
+
+pub fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures")
+}
@@ -0,0 +1,515 @@
+//! Golden output tests
+//!
+//! Each `tests/fixtures/<name>.json` file holds the LDAP entries of a synthetic domain, injected
+//! at the `SearchEntry` boundary. The full pipeline (parsing, checker, output) runs on them and
+//! every BloodHound json file must match `tests/golden/<name>/<type>.json` byte for byte.
+//!
+//! To add a fixture entry when fixing a parsing bug, append it to the `entries` list then run
+//! `UPDATE_GOLDEN=1 cargo test --test golden` and review the golden files diff.
+//!
+//! `tests/fixtures/forest/` holds the domains of one forest, collected together with `--forest` and
+//! matched against `tests/golden/forest/`.
+//!
+//! Binary attributes use a prefix to stay readable:
+//! `sid:S-1-5-...`, `guid:xxxxxxxx-...`, `sddl:O:DAD:(A;;...)` (self-relative security descriptor),
+//! `base64:...` and `hex:...`.
+mod common;
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use common::{fixtures_dir, load_fixture};
+
+use rusthound_ce::args::RUSTHOUND_VERSION;
+use rusthound_ce::json::checker::tierzero::tier_zero_exposure;
+use rusthound_ce::json::maker::sink::JsonFileSink;
+use rusthound_ce::ldap::LdapSearchEntry;
+use rusthound_ce::methods::{CollectionMethod, IDENTITY_PROPERTIES};
+use rusthound_ce::objecttypes::ADCS_TYPES;
+use rusthound_ce::rootdse::is_under;
+use rusthound_ce::{build_info, Collector, CollectorBuilder, DumpReader, DumpWriter, EntrySource, Storage};
+
+/// Run the whole pipeline on one fixture and return the output files.
+async fn run_pipeline(fixture: &Path) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    run_pipeline_with(fixture, |builder| builder).await
+}
+
+/// Run the whole pipeline on one fixture with more options, like `--ace-filter`.
+async fn run_pipeline_with(
+    fixture: &Path,
+    configure: impl FnOnce(CollectorBuilder) -> CollectorBuilder,
+) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let (domain, entries) = load_fixture(fixture)?;
+    let name = fixture.file_stem().unwrap().to_string_lossy().to_string();
+    let total = entries.len();
+    run_source(&domain, &name, entries, Some(total), configure).await
+}
+
+/// Run the whole pipeline on the entries of a source, from memory or from a dump.
+async fn run_source<S: EntrySource>(
+    domain: &str,
+    name: &str,
+    source: S,
+    total: Option<usize>,
+    configure: impl FnOnce(CollectorBuilder) -> CollectorBuilder,
+) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    static RUN: AtomicUsize = AtomicUsize::new(0);
+    let run = RUN.fetch_add(1, Ordering::SeqCst);
+    let output = std::env::temp_dir().join(format!("rusthound-ce-golden-{name}-{}-{run}", std::process::id()));
+    std::fs::remove_dir_all(&output).ok();
+
+    let collector = configure(Collector::builder(domain).output(&output.display().to_string())).build();
+    let results = collector.process(source, total).await?;
+    // Fixed file names instead of the current datetime
+    let sink = JsonFileSink::new(&output.display().to_string(), "golden", name);
+    collector.write_to(results, vec![Box::new(sink)])?;
+
+    let mut files = Vec::new();
+    for file in std::fs::read_dir(&output)? {
+        let path = file?.path();
+        let object_type = path.file_stem().unwrap().to_string_lossy()
+            .trim_start_matches(&format!("golden_{name}_"))
+            .to_string();
+        // The collector version changes on each release, the build on each commit
+        let content = std::fs::read_to_string(&path)?
+            .replace(&format!("RustHound-CE v{RUSTHOUND_VERSION}"), "RustHound-CE vGOLDEN")
+            .replace(&serde_json::to_string(&build_info())?, "\"GOLDEN\"");
+        files.push((object_type, content));
+    }
+    files.sort();
+    std::fs::remove_dir_all(&output).ok();
+    Ok(files)
+}
+
+fn golden_dir(fixture: &Path) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(fixture.file_stem().unwrap())
+}
+
+#[tokio::test]
+async fn test_golden_output() {
+    let update = std::env::var("UPDATE_GOLDEN").is_ok_and(|v| v == "1");
+    let mut fixtures: Vec<PathBuf> = std::fs::read_dir(fixtures_dir()).unwrap()
+        .map(|f| f.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .collect();
+    fixtures.sort();
+    assert!(!fixtures.is_empty());
+
+    for fixture in fixtures {
+        let files = run_pipeline(&fixture).await.unwrap();
+        let golden = golden_dir(&fixture);
+
+        assert_golden(&golden, &files, update, &fixture);
+    }
+}
+
+/// Check the output files match the golden ones, or write them with `UPDATE_GOLDEN=1`.
+fn assert_golden(golden: &Path, files: &[(String, String)], update: bool, fixture: &Path) {
+    if update {
+        std::fs::remove_dir_all(golden).ok();
+        std::fs::create_dir_all(golden).unwrap();
+        for (object_type, content) in files {
+            std::fs::write(golden.join(format!("{object_type}.json")), content).unwrap();
+        }
+        return
+    }
+
+    let mut expected: Vec<String> = std::fs::read_dir(golden)
+        .unwrap_or_else(|_| panic!("no golden files for {}, run with UPDATE_GOLDEN=1", fixture.display()))
+        .map(|f| f.unwrap().path().file_stem().unwrap().to_string_lossy().to_string())
+        .collect();
+    expected.sort();
+    let produced: Vec<String> = files.iter().map(|(t, _)| t.to_owned()).collect();
+    assert_eq!(produced, expected, "object types for {}", fixture.display());
+
+    for (object_type, content) in files {
+        let golden_file = golden.join(format!("{object_type}.json"));
+        let expected = std::fs::read_to_string(&golden_file).unwrap();
+        assert!(
+            content == &expected,
+            "{} differs from the pipeline output, run with UPDATE_GOLDEN=1 if the change is intended\n{content}",
+            golden_file.display()
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_forest_output() {
+    // Entries of the root domain then of its child, as stored by a --forest collection
+    let update = std::env::var("UPDATE_GOLDEN").is_ok_and(|v| v == "1");
+    let forest = fixtures_dir().join("forest");
+    let mut entries = Vec::new();
+    for name in ["contoso", "child"] {
+        entries.extend(load_fixture(&forest.join(format!("{name}.json"))).unwrap().1);
+    }
+    let total = entries.len();
+    let files = run_source("contoso.local", "forest", entries, Some(total), |builder| builder.forest(true)).await.unwrap();
+    assert_golden(&golden_dir(&forest), &files, update, &forest);
+
+    let data = |object_type: &str| -> Vec<serde_json::Value> {
+        let content = &files.iter().find(|(t, _)| t == object_type).unwrap().1;
+        serde_json::from_str::<serde_json::Value>(content).unwrap()["data"].as_array().unwrap().to_owned()
+    };
+    let find = |object_type: &str, name: &str| -> serde_json::Value {
+        data(object_type).into_iter().find(|object| object["Properties"]["name"] == name).unwrap()
+    };
+    let child_sid = "S-1-5-21-3000000001-3000000002-3000000003";
+
+    // One node per domain, the trusts on the domain holding them, a node for the domain of the other forest
+    let domains: Vec<String> = data("domains").iter().map(|domain| domain["Properties"]["name"].as_str().unwrap().to_owned()).collect();
+    assert_eq!(domains, ["CONTOSO.LOCAL", "CHILD.CONTOSO.LOCAL", "FABRIKAM.LOCAL"]);
+    let trusts = |domain: &str| -> Vec<String> {
+        find("domains", domain)["Trusts"].as_array().unwrap().iter()
+            .map(|trust| trust["TargetDomainName"].as_str().unwrap().to_owned())
+            .collect()
+    };
+    assert_eq!(trusts("CONTOSO.LOCAL"), ["CHILD.CONTOSO.LOCAL", "FABRIKAM.LOCAL"]);
+    assert_eq!(trusts("CHILD.CONTOSO.LOCAL"), ["CONTOSO.LOCAL"]);
+
+    // Objects of the child domain with its own SID
+    let bob = find("users", "BOB@CHILD.CONTOSO.LOCAL");
+    assert_eq!(bob["ObjectIdentifier"], format!("{child_sid}-1105"));
+    assert_eq!(bob["Properties"]["domainsid"], child_sid);
+    // Members from the child domain: by DN, and by the foreign security principal of its group
+    let members = |group: &str| -> Vec<(String, String)> {
+        find("groups", group)["Members"].as_array().unwrap().iter()
+            .map(|member| (member["ObjectIdentifier"].as_str().unwrap().to_owned(), member["ObjectType"].as_str().unwrap().to_owned()))
+            .collect()
+    };
+    assert_eq!(members("FOREST OPERATORS@CONTOSO.LOCAL"), [(format!("{child_sid}-1105"), "User".to_string())]);
+    assert!(members("FILE SHARE ADMINS@CONTOSO.LOCAL").contains(&(format!("{child_sid}-1105"), "User".to_string())));
+    // Foreign security principal read after the object of its domain: the type of the object kept
+    assert!(members("BACKUP TEAM@CHILD.CONTOSO.LOCAL").contains(&("S-1-5-21-1004336348-1177238915-682003330-500".to_string(), "User".to_string())));
+    let ace = find("groups", "FILE SHARE ADMINS@CONTOSO.LOCAL")["Aces"].as_array().unwrap().iter()
+        .find(|ace| ace["PrincipalSID"] == format!("{child_sid}-1105"))
+        .cloned()
+        .unwrap();
+    assert_eq!(ace["PrincipalType"], "User");
+    // Default users of each domain
+    let users: Vec<String> = data("users").iter().map(|user| user["Properties"]["name"].as_str().unwrap().to_owned()).collect();
+    assert!(users.contains(&"NT AUTHORITY@CONTOSO.LOCAL".to_string()) && users.contains(&"NT AUTHORITY@CHILD.CONTOSO.LOCAL".to_string()));
+}
+
+#[tokio::test]
+async fn test_golden_output_is_stable() {
+    // Same input, same bytes: no HashMap ordering leaks in the output
+    let fixture = fixtures_dir().join("contoso.json");
+    let first = run_pipeline(&fixture).await.unwrap();
+    for _ in 0..5 {
+        assert_eq!(run_pipeline(&fixture).await.unwrap(), first);
+    }
+}
+
+#[tokio::test]
+async fn test_parallel_parsing_is_sequential_output() {
+    // Entries parsed on several threads, the mappings merged afterwards: same bytes as one at a time
+    let mut fixtures: Vec<PathBuf> = std::fs::read_dir(fixtures_dir()).unwrap()
+        .map(|f| f.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .collect();
+    fixtures.sort();
+    for fixture in fixtures {
+        let sequential = run_pipeline_with(&fixture, |builder| builder.parse_threads(1)).await.unwrap();
+        for threads in [2, 8] {
+            let parallel = run_pipeline_with(&fixture, |builder| builder.parse_threads(threads)).await.unwrap();
+            assert_eq!(parallel, sequential, "{} parsed on {threads} threads", fixture.display());
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_dump_is_same_output() {
+    // Entries written to a dump as they are collected, then parsed offline: same bytes as the live collection
+    let mut fixtures: Vec<PathBuf> = std::fs::read_dir(fixtures_dir()).unwrap()
+        .map(|f| f.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .collect();
+    fixtures.sort();
+    for fixture in fixtures {
+        let (domain, entries) = load_fixture(&fixture).unwrap();
+        let name = fixture.file_stem().unwrap().to_string_lossy().to_string();
+        let dump = std::env::temp_dir().join(format!("rusthound-ce-golden-{name}-{}.jsonl", std::process::id()));
+        let mut stored: Vec<LdapSearchEntry> = Vec::new();
+        let mut writer = DumpWriter::create(&dump, &domain, &mut stored).unwrap();
+        for entry in entries {
+            writer.add(entry).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let offline = run_source(&domain, &name, DumpReader::from_path(&dump).unwrap(), None, |builder| builder).await.unwrap();
+        assert_eq!(offline, run_pipeline(&fixture).await.unwrap(), "{} parsed from a dump", fixture.display());
+
+        // A dump of another domain is refused
+        let collector = Collector::builder("fabrikam.local").build();
+        assert!(collector.process_dump(&dump.display().to_string()).await.is_err());
+        std::fs::remove_file(&dump).ok();
+    }
+}
+
+/// Right names of all output ACEs and the `acefilter` meta of each output file.
+fn output_aces(files: &[(String, String)]) -> (Vec<String>, Vec<Option<String>>) {
+    let mut rights = Vec::new();
+    let mut filters = Vec::new();
+    for (_, content) in files {
+        let json: serde_json::Value = serde_json::from_str(content).unwrap();
+        for object in json["data"].as_array().unwrap() {
+            for ace in object["Aces"].as_array().into_iter().flatten() {
+                rights.push(ace["RightName"].as_str().unwrap().to_owned());
+            }
+        }
+        filters.push(json["meta"]["acefilter"].as_str().map(|f| f.to_owned()));
+    }
+    (rights, filters)
+}
+
+#[tokio::test]
+async fn test_ace_filter() {
+    let fixture = fixtures_dir().join("contoso.json");
+    let (all, filters) = output_aces(&run_pipeline(&fixture).await.unwrap());
+    assert!(filters.iter().all(|filter| filter.is_none()));
+    let count = |right: &str| all.iter().filter(|r| *r == right).count();
+    assert!(count("Owns") > 0 && count("GenericAll") > 0);
+
+    for (spec, expected) in [
+        ("-Owns", all.len() - count("Owns")),
+        ("GenericAll", count("GenericAll")),
+        ("GenericAll,Owns,-Owns", count("GenericAll")),
+        ("high-value", all.len()),
+    ] {
+        let (rights, filters) = output_aces(&run_pipeline_with(&fixture, |builder| builder.ace_filter(spec)).await.unwrap());
+        assert_eq!(rights.len(), expected, "ACEs kept by {spec}");
+        assert!(filters.iter().all(|filter| filter.as_deref() == Some(spec)), "meta for {spec}");
+    }
+}
+
+#[tokio::test]
+async fn test_collection_methods() {
+    let methods = CollectionMethod::DC_ONLY - CollectionMethod::ACL - CollectionMethod::OBJECT_PROPS - CollectionMethod::CONTAINER
+        - CollectionMethod::CERT_SERVICES;
+    // Entries returned by the DC for the filter of these methods: no container, OU or PKI object
+    let (domain, mut entries) = load_fixture(&fixtures_dir().join("contoso.json")).unwrap();
+    let excluded = ["container", "organizationalUnit", "pKIEnrollmentService", "pKICertificateTemplate", "certificationAuthority", "msPKI-Enterprise-Oid"];
+    entries.retain(|entry| {
+        let classes = entry.attrs.get("objectClass").cloned().unwrap_or_default();
+        classes.iter().any(|class| class == "groupPolicyContainer") || !classes.iter().any(|class| excluded.contains(&class.as_str()))
+    });
+    let total = entries.len();
+    let files = run_source(&domain, "contoso", entries, Some(total), |builder| builder.collection_method(methods)).await.unwrap();
+
+    let types: Vec<&str> = files.iter().map(|(object_type, _)| object_type.as_str()).collect();
+    assert_eq!(types, ["computers", "domains", "gpos", "groups", "users"]);
+    for (object_type, content) in &files {
+        let json: serde_json::Value = serde_json::from_str(content).unwrap();
+        assert_eq!(json["meta"]["methods"], (CollectionMethod::GROUP | CollectionMethod::TRUSTS).bits(), "{object_type}");
+        for object in json["data"].as_array().unwrap() {
+            assert_eq!(object["Aces"], serde_json::json!([]), "{object_type}");
+            let properties = object["Properties"].as_object().unwrap();
+            assert!(properties.keys().all(|name| IDENTITY_PROPERTIES.contains(&name.as_str())), "{object_type}: {properties:?}");
+            assert!(properties.contains_key("name") && properties.contains_key("domain"));
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_meta() {
+    // The meta block read by BloodHound CE on ingestion, for each file
+    let files = run_pipeline(&fixtures_dir().join("contoso.json")).await.unwrap();
+    assert_eq!(RUSTHOUND_VERSION, env!("CARGO_PKG_VERSION"));
+    for (object_type, content) in &files {
+        let json: serde_json::Value = serde_json::from_str(content).unwrap();
+        let meta = &json["meta"];
+        assert_eq!(meta["type"], object_type.as_str());
+        assert_eq!(meta["count"], json["data"].as_array().unwrap().len(), "{object_type}");
+        assert_eq!(meta["methods"], CollectionMethod::ALL.collected().bits(), "{object_type}");
+        assert_eq!(meta["version"], 6, "{object_type}");
+        // Replaced from "RustHound-CE v{RUSTHOUND_VERSION}" by run_source
+        assert_eq!(meta["collectorversion"], "RustHound-CE vGOLDEN", "{object_type}");
+    }
+}
+
+#[tokio::test]
+async fn test_object_types() {
+    // --only: the objects of the other types parsed from the entries are not written
+    let fixture = fixtures_dir().join("contoso.json");
+    let files = run_pipeline_with(&fixture, |builder| builder.only(&["computers", "enterprisecas"])).await.unwrap();
+    let types: Vec<&str> = files.iter().map(|(object_type, _)| object_type.as_str()).collect();
+    assert_eq!(types, ["computers", "enterprisecas"]);
+
+    // --skip users: the DC returned no user, the group members are not resolved
+    let (domain, mut entries) = load_fixture(&fixture).unwrap();
+    entries.retain(|entry| {
+        let classes = entry.attrs.get("objectClass").cloned().unwrap_or_default();
+        !classes.iter().any(|class| class == "person") || classes.iter().any(|class| class == "computer")
+    });
+    let total = entries.len();
+    let files = run_source(&domain, "contoso", entries, Some(total), |builder| builder.skip(&["users"]).empty_skipped(true)).await.unwrap();
+    let file = |name: &str| -> serde_json::Value {
+        let (_, content) = files.iter().find(|(object_type, _)| object_type == name).unwrap();
+        serde_json::from_str(content).unwrap()
+    };
+    assert_eq!(file("users")["data"], serde_json::json!([]));
+    assert_eq!(file("users")["meta"]["count"], 0);
+    let members: Vec<String> = file("groups")["data"].as_array().unwrap().iter()
+        .filter(|group| group["Properties"]["name"] == "DOMAIN ADMINS@CONTOSO.LOCAL")
+        .flat_map(|group| group["Members"].as_array().unwrap().iter().map(|member| member["ObjectIdentifier"].as_str().unwrap().to_string()))
+        .collect();
+    assert_eq!(members, ["CN=ADMINISTRATOR,CN=USERS,DC=CONTOSO,DC=LOCAL"]);
+    // Skipped types without object in the fixture and types which are not files
+    assert!(files.iter().all(|(object_type, _)| object_type != "fsps" && object_type != "trusts"));
+}
+
+#[tokio::test]
+async fn test_adcs_types() {
+    // The AD CS types only, the other entries are not parsed
+    let files = run_pipeline_with(&fixtures_dir().join("contoso.json"), |builder| builder.only(ADCS_TYPES)).await.unwrap();
+    let types: Vec<&str> = files.iter().map(|(object_type, _)| object_type.as_str()).collect();
+    assert_eq!(types, ["aiacas", "certtemplates", "enterprisecas", "issuancepolicies", "ntauthstores", "rootcas"]);
+    for (object_type, content) in &files {
+        let json: serde_json::Value = serde_json::from_str(content).unwrap();
+        assert!(!json["data"].as_array().unwrap().is_empty(), "{object_type}");
+    }
+    // Computer of the dNSHostName not collected: the principal with ManageCertificates
+    let (_, content) = files.iter().find(|(object_type, _)| object_type == "enterprisecas").unwrap();
+    let json: serde_json::Value = serde_json::from_str(content).unwrap();
+    assert!(json["data"][0]["HostingComputer"].as_str().unwrap().starts_with("S-1-5-21-"));
+    assert!(json["data"][0]["Properties"]["dnshostnamesid"].is_null());
+}
+
+#[test]
+fn test_object_counts() {
+    // --count-only: parsed objects only, without the ones added by the checker nor the Deleted Objects container
+    let (domain, entries) = load_fixture(&fixtures_dir().join("contoso.json")).unwrap();
+    let total = entries.len();
+    let counts = Collector::builder(&domain).build().count(entries, Some(total)).unwrap();
+    assert_eq!(counts, [
+        ("users", 5),
+        ("groups", 2),
+        ("computers", 2),
+        ("ous", 2),
+        ("domains", 1),
+        ("gpos", 1),
+        ("fsps", 1),
+        ("containers", 1),
+        ("trusts", 1),
+        ("ntauthstores", 1),
+        ("aiacas", 1),
+        ("rootcas", 1),
+        ("enterprisecas", 1),
+        ("certtemplates", 1),
+        ("issuancepolicies", 1),
+    ]);
+    assert_eq!(counts.iter().map(|(_, count)| count).sum::<usize>(), total - 1);
+}
+
+#[tokio::test]
+async fn test_gmsa() {
+    let files = run_pipeline(&fixtures_dir().join("contoso.json")).await.unwrap();
+    let content = &files.iter().find(|(object_type, _)| object_type == "users").unwrap().1;
+    let users: serde_json::Value = serde_json::from_str(content).unwrap();
+    let user = |name: &str| users["data"].as_array().unwrap().iter()
+        .find(|user| user["Properties"]["name"] == name)
+        .cloned()
+        .unwrap();
+    let readers = |user: &serde_json::Value| -> Vec<String> {
+        user["Aces"].as_array().unwrap().iter()
+            .filter(|ace| ace["RightName"] == "ReadGMSAPassword")
+            .map(|ace| ace["PrincipalSID"].as_str().unwrap().to_owned())
+            .collect()
+    };
+
+    // Every principal of msDS-GroupMSAMembership once, not its owner (BUILTIN\Administrators)
+    let gmsa = user("SVC_WEB$@CONTOSO.LOCAL");
+    assert_eq!(gmsa["Properties"]["gmsa"], true);
+    assert_eq!(gmsa["Properties"]["managedpasswordinterval"], 30);
+    assert_eq!(readers(&gmsa), ["S-1-5-21-1004336348-1177238915-682003330-1105", "S-1-5-21-1004336348-1177238915-682003330-1106"]);
+
+    // dMSA: a user, not a computer
+    let dmsa = user("SVC_APP$@CONTOSO.LOCAL");
+    assert_eq!((dmsa["Properties"]["gmsa"].as_bool(), dmsa["Properties"]["dmsa"].as_bool()), (Some(false), Some(true)));
+    assert_eq!(readers(&dmsa), ["S-1-5-21-1004336348-1177238915-682003330-1104"]);
+    assert!(readers(&user("SVC_SQL@CONTOSO.LOCAL")).is_empty());
+}
+
+#[tokio::test]
+async fn test_search_base() {
+    // Entries returned for --search-base: the domain object, the subtrees and the configuration partition
+    let (domain, entries) = load_fixture(&fixtures_dir().join("contoso.json")).unwrap();
+    let scoped = |bases: &[&str]| -> Vec<LdapSearchEntry> {
+        entries.iter()
+            .filter(|entry| {
+                entry.dn.eq_ignore_ascii_case("DC=CONTOSO,DC=LOCAL")
+                    || is_under(&entry.dn, "CN=Configuration,DC=CONTOSO,DC=LOCAL")
+                    || bases.iter().any(|base| is_under(&entry.dn, base))
+            })
+            .cloned()
+            .collect()
+    };
+    let output = |files: &[(String, String)], object_type: &str| -> serde_json::Value {
+        let content = &files.iter().find(|(t, _)| t == object_type).unwrap().1;
+        serde_json::from_str::<serde_json::Value>(content).unwrap()["data"].to_owned()
+    };
+
+    // Helpdesk has a member outside of the OU: left unresolved, no crash
+    let mut workstations = scoped(&["OU=Workstations,DC=CONTOSO,DC=LOCAL"]);
+    workstations.iter_mut()
+        .find(|entry| entry.dn.starts_with("CN=Helpdesk,"))
+        .unwrap()
+        .attrs.get_mut("member").unwrap()
+        .push("CN=Administrator,CN=Users,DC=CONTOSO,DC=LOCAL".to_string());
+    let total = workstations.len();
+    let files = run_source(&domain, "contoso", workstations, Some(total), |builder| {
+        builder.search_base("OU=Workstations,DC=CONTOSO,DC=LOCAL")
+    }).await.unwrap();
+    let names = |data: serde_json::Value| -> Vec<String> {
+        data.as_array().unwrap().iter().map(|object| object["Properties"]["name"].as_str().unwrap_or_default().to_owned()).collect()
+    };
+    assert!(names(output(&files, "users")).contains(&"SVC_SQL@CONTOSO.LOCAL".to_string()));
+    assert!(!names(output(&files, "users")).contains(&"ADMINISTRATOR@CONTOSO.LOCAL".to_string()));
+    assert_eq!(names(output(&files, "computers")), ["WS01.CONTOSO.LOCAL"]);
+    assert_eq!(output(&files, "domains").as_array().unwrap().len(), 1);
+    assert!(files.iter().any(|(object_type, _)| object_type == "certtemplates"));
+    let helpdesk = output(&files, "groups").as_array().unwrap().iter()
+        .find(|group| group["Properties"]["name"] == "HELPDESK@CONTOSO.LOCAL")
+        .cloned()
+        .unwrap();
+    assert_eq!(helpdesk["Members"].as_array().unwrap().len(), 3);
+
+    // No user in the subtree
+    let controllers = scoped(&["OU=Domain Controllers,DC=CONTOSO,DC=LOCAL"]);
+    let total = controllers.len();
+    let files = run_source(&domain, "contoso", controllers, Some(total), |builder| {
+        builder.search_base("OU=Domain Controllers,DC=CONTOSO,DC=LOCAL")
+    }).await.unwrap();
+    assert_eq!(names(output(&files, "computers")), ["DC01.CONTOSO.LOCAL"]);
+}
+
+#[tokio::test]
+async fn test_tier_zero_exposure() {
+    let (domain, entries) = load_fixture(&fixtures_dir().join("tierzero.json")).unwrap();
+    let collector = Collector::builder(&domain).build();
+    let total = entries.len();
+    let results = collector.process(entries, Some(total)).await.unwrap();
+
+    let exposures = tier_zero_exposure(&results, false);
+    assert_eq!(exposures.len(), 1);
+    let exposure = &exposures[0];
+    assert_eq!(exposure.domain, "TIERZERO.LOCAL");
+    // alice is tier zero through Domain Admins, her GenericAll on the domain doesn't count
+    // and dave's GenericAll on eve doesn't target a tier zero object
+    assert_eq!(exposure.exposed_principals, 5);
+    let ranking: Vec<(&str, usize)> = exposure.offenders.iter()
+        .map(|offender| (offender.name.as_str(), offender.reachable.len()))
+        .collect();
+    assert_eq!(ranking, vec![
+        ("CAROL@TIERZERO.LOCAL", 4),
+        ("BOB@TIERZERO.LOCAL", 2),
+        ("HELPDESK@TIERZERO.LOCAL", 2),
+        ("DAVE@TIERZERO.LOCAL", 1),
+        ("SVC_BACKUP@TIERZERO.LOCAL", 1),
+    ]);
+    let carol = &exposure.offenders[0];
+    assert_eq!(carol.reachable, vec!["ADMINISTRATOR@TIERZERO.LOCAL", "DOMAIN ADMINS@TIERZERO.LOCAL", "ESC1@TIERZERO.LOCAL", "TIERZERO.LOCAL"]);
+    assert_eq!(carol.rights, vec!["AddMember", "GenericAll", "WriteDacl"]);
+    assert_eq!(carol.via, vec!["HELPDESK@TIERZERO.LOCAL"]);
+}
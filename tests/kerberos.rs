@@ -0,0 +1,63 @@
+//! Kerberos bind integration tests
+//!
+//! They need a live domain and run only with the `kerberos-tests` feature:
+//!
+//! ```sh
+//! export RUSTHOUND_TEST_DOMAIN=contoso.local RUSTHOUND_TEST_DC=dc01.contoso.local
+//! # With a ticket (getTGT.py, kinit)
+//! export RUSTHOUND_TEST_CCACHE=/tmp/alice.ccache
+//! # Or a TGT requested with the password
+//! export RUSTHOUND_TEST_USER=alice RUSTHOUND_TEST_PASSWORD='Password123'
+//! cargo test --features kerberos-tests --test kerberos -- --test-threads=1
+//! ```
+#![cfg(all(feature = "kerberos-tests", not(feature = "nogssapi")))]
+
+use rusthound_ce::ldap::LdapSearchEntry;
+use rusthound_ce::Collector;
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn domain() -> (String, String) {
+    let domain = env("RUSTHOUND_TEST_DOMAIN").expect("RUSTHOUND_TEST_DOMAIN is not set");
+    let dc = env("RUSTHOUND_TEST_DC").expect("RUSTHOUND_TEST_DC is not set");
+    (domain, dc)
+}
+
+async fn collect(collector: Collector) -> usize {
+    let mut entries: Vec<LdapSearchEntry> = Vec::new();
+    let total = collector.search(&mut entries).await.expect("Kerberos collection failed");
+    assert_eq!(total, entries.len());
+    total
+}
+
+#[tokio::test]
+async fn test_kerberos_bind_with_ccache() {
+    let Some(ccache) = env("RUSTHOUND_TEST_CCACHE") else {
+        eprintln!("RUSTHOUND_TEST_CCACHE is not set, skipped");
+        return
+    };
+    let (domain, dc) = domain();
+    let collector = Collector::builder(&domain)
+        .ldapfqdn(&dc)
+        .kerberos(true)
+        .ccache(&ccache)
+        .build();
+    assert!(collect(collector).await > 0);
+}
+
+#[tokio::test]
+async fn test_kerberos_bind_with_password() {
+    let (Some(username), Some(password)) = (env("RUSTHOUND_TEST_USER"), env("RUSTHOUND_TEST_PASSWORD")) else {
+        eprintln!("RUSTHOUND_TEST_USER or RUSTHOUND_TEST_PASSWORD is not set, skipped");
+        return
+    };
+    let (domain, dc) = domain();
+    let collector = Collector::builder(&domain)
+        .ldapfqdn(&dc)
+        .credentials(&username, &password)
+        .kerberos(true)
+        .build();
+    assert!(collect(collector).await > 0);
+}
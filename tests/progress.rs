@@ -0,0 +1,66 @@
+//! Progress observer of the library API
+//!
+//! The observer is global, this test runs in its own binary so no other collection reports to it.
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use common::{fixtures_dir, load_fixture};
+use rusthound_ce::ldap::LdapSearchEntry;
+use rusthound_ce::{clear_progress_observer, set_progress_observer, Collector, Event, ProgressObserver};
+
+/// Calls of the observer, in order.
+#[derive(Default)]
+struct Recorder {
+    calls: Mutex<Vec<String>>,
+}
+
+impl ProgressObserver for Recorder {
+    fn on_phase(&self, phase: &str) {
+        self.calls.lock().unwrap().push(format!("phase {phase}"));
+    }
+
+    fn on_object_parsed(&self, kind: &str, count: usize) {
+        self.calls.lock().unwrap().push(format!("{kind} {count}"));
+    }
+
+    fn on_event(&self, event: &Event) {
+        if let Event::PhaseFinished { phase } = event {
+            self.calls.lock().unwrap().push(format!("finished {phase}"));
+        }
+    }
+}
+
+#[test]
+fn test_progress_observer() {
+    let recorder = Arc::new(Recorder::default());
+    set_progress_observer(recorder.clone());
+
+    let (domain, entries) = load_fixture(&fixtures_dir().join("contoso.json")).unwrap();
+    let total = entries.len();
+    let counts = Collector::builder(&domain).build().count(entries.to_owned(), Some(total)).unwrap();
+    recorder.calls.lock().unwrap().clear();
+    Collector::builder(&domain).build().parse(entries, Some(total)).unwrap();
+
+    let calls = recorder.calls.lock().unwrap().to_owned();
+    // Running count of each type, between the start and the end of the parsing
+    assert_eq!(calls.first().map(String::as_str), Some("phase parsing"));
+    assert_eq!(calls.last().map(String::as_str), Some("finished parsing"));
+    let mut running: HashMap<&str, usize> = HashMap::new();
+    for call in &calls[1..calls.len() - 1] {
+        let (kind, count) = call.split_once(' ').unwrap();
+        let previous = running.entry(kind).or_default();
+        *previous += 1;
+        assert_eq!(count.parse::<usize>().unwrap(), *previous, "{call}");
+    }
+    for (kind, count) in counts.iter().filter(|(_, count)| *count > 0) {
+        assert_eq!(running.get(kind), Some(count), "{kind}");
+    }
+    assert_eq!(running.values().sum::<usize>(), counts.iter().map(|(_, count)| count).sum::<usize>());
+
+    // Nothing reported once removed
+    clear_progress_observer();
+    Collector::builder(&domain).build().parse(Vec::<LdapSearchEntry>::new(), None).unwrap();
+    assert_eq!(recorder.calls.lock().unwrap().len(), calls.len());
+}
@@ -0,0 +1,64 @@
+//! Cross-realm Kerberos principals
+//!
+//! An account of FABRIKAM.LOCAL collects the trusting CONTOSO.LOCAL with `--realm`, the realms are the ones of
+//! `tests/fixtures/krb5-cross-realm.conf`. No KDC is needed: only the principals of the GSSAPI bind are checked.
+use std::path::PathBuf;
+
+use rusthound_ce::Collector;
+
+fn krb5_conf() -> String {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join("krb5-cross-realm.conf").display().to_string()
+}
+
+#[test]
+fn test_cross_realm_principals() {
+    let collector = Collector::builder("contoso.local")
+        .ldapfqdn("DC01.contoso.local")
+        .credentials("alice", "Password123")
+        .kerberos(true)
+        .realm("fabrikam.local")
+        .krb5_conf(&krb5_conf())
+        .build();
+    let principals = collector.kerberos_principals();
+    // The client in its own realm, the service in the realm of the DC
+    assert_eq!(principals.client.as_deref(), Some("alice@FABRIKAM.LOCAL"));
+    assert_eq!(principals.service, "ldap/dc01.contoso.local");
+    assert_eq!(principals.service_realm.as_deref(), Some("CONTOSO.LOCAL"));
+    // The objects are still the ones of the domain collected
+    assert_eq!(collector.options().domain, "contoso.local");
+
+    // An alternative UPN suffix is not a realm
+    let collector = Collector::builder("contoso.local")
+        .ldapfqdn("dc01.contoso.local")
+        .credentials("alice@fabrikam.com", "Password123")
+        .kerberos(true)
+        .realm("FABRIKAM.LOCAL")
+        .build();
+    assert_eq!(collector.kerberos_principals().client.as_deref(), Some("alice@FABRIKAM.LOCAL"));
+    assert_eq!(collector.kerberos_principals().service_realm, None);
+}
+
+#[test]
+fn test_same_realm_principals() {
+    // Without --realm the realm is the domain collected
+    let collector = Collector::builder("child.contoso.local")
+        .ldapfqdn("dc03.child.contoso.local")
+        .credentials("bob", "Password123")
+        .kerberos(true)
+        .krb5_conf(&krb5_conf())
+        .build();
+    let principals = collector.kerberos_principals();
+    assert_eq!(principals.client.as_deref(), Some("bob@CHILD.CONTOSO.LOCAL"));
+    assert_eq!(principals.service_realm.as_deref(), Some("CHILD.CONTOSO.LOCAL"));
+
+    // Host mapping before the domain one
+    let collector = Collector::builder("child.contoso.local")
+        .ldapfqdn("dc02.child.contoso.local")
+        .kerberos(true)
+        .krb5_conf(&krb5_conf())
+        .build();
+    let principals = collector.kerberos_principals();
+    assert_eq!(principals.client, None);
+    assert_eq!(principals.service, "ldap/dc02.child.contoso.local");
+    assert_eq!(principals.service_realm.as_deref(), Some("CONTOSO.LOCAL"));
+}